@@ -0,0 +1,154 @@
+//! Bridges a CDC-ECM or CDC-NCM device to a Linux TAP interface.
+//!
+//! Runs the host side of a USB Ethernet gadget in userspace, in place of the `cdc_ether` and
+//! `cdc_ncm` kernel drivers. Needs `CAP_NET_ADMIN` to create the TAP interface, which is then
+//! configured with the usual tools, e.g. `ip link set usb-tap0 up`.
+//!
+//! Usage: `cargo run --example cdc_tap -- /dev/bus/usb/001/004 [usb-tap0]`
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::{env, process};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use usbfs::{CdcEcm, CdcNcm, Error, IoctlBackend, Result, UsbDevice};
+
+const IFF_TAP: i16 = 0x0002;
+const IFF_NO_PI: i16 = 0x1000;
+const IFNAMSIZ: usize = 16;
+
+// interval between checks of the TAP interface and the device, in milliseconds
+const POLL_INTERVAL: u32 = 10;
+
+#[repr(C)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    flags: i16,
+    pad: [u8; 22],
+}
+
+nix::ioctl_write_ptr_bad!(
+    tun_set_iff,
+    nix::request_code_write!(b'T', 202, std::mem::size_of::<i32>()),
+    IfReq
+);
+
+/// The network function of the device.
+enum Link {
+    Ecm(CdcEcm<IoctlBackend>),
+    Ncm(CdcNcm<IoctlBackend>),
+}
+
+impl Link {
+    fn open(path: &str) -> Result<Self> {
+        let backend = IoctlBackend::new(UsbDevice::open(path)?);
+        match CdcNcm::open(backend) {
+            Ok(ncm) => return Ok(Self::Ncm(ncm.with_timeout(POLL_INTERVAL))),
+            Err(Error::NotFound(_)) => (),
+            Err(err) => return Err(err),
+        }
+
+        let backend = IoctlBackend::new(UsbDevice::open(path)?);
+        Ok(Self::Ecm(
+            CdcEcm::open(backend)?.with_timeout(POLL_INTERVAL),
+        ))
+    }
+
+    fn mac_address(&self) -> [u8; 6] {
+        match self {
+            Self::Ecm(ecm) => ecm.mac_address(),
+            Self::Ncm(ncm) => ncm.mac_address(),
+        }
+    }
+
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        match self {
+            Self::Ecm(ecm) => ecm.send(frame),
+            Self::Ncm(ncm) => ncm.send(frame),
+        }
+    }
+
+    fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let res = match self {
+            Self::Ecm(ecm) => ecm.recv(),
+            Self::Ncm(ncm) => ncm.recv(),
+        };
+        match res {
+            Ok(frame) => Ok(Some(frame)),
+            Err(err) if err.errno() == Some(Errno::ETIMEDOUT as i32) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn open_tap(name: &str) -> Result<File> {
+    if name.len() >= IFNAMSIZ {
+        return Err(Error::InvalidArgument(format!("interface name: {name}")));
+    }
+
+    let tun = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/net/tun")?;
+
+    let mut req = IfReq {
+        name: [0u8; IFNAMSIZ],
+        flags: IFF_TAP | IFF_NO_PI,
+        pad: [0u8; 22],
+    };
+    req.name[..name.len()].copy_from_slice(name.as_bytes());
+    // SAFETY: the request is a valid `ifreq`, and the descriptor is open
+    unsafe { tun_set_iff(tun.as_raw_fd(), &req) }?;
+
+    Ok(tun)
+}
+
+fn run(path: &str, name: &str) -> Result<()> {
+    let mut link = Link::open(path)?;
+    let mut tap = open_tap(name)?;
+
+    let mac = link.mac_address();
+    let kind = match link {
+        Link::Ecm(_) => "ECM",
+        Link::Ncm(_) => "NCM",
+    };
+    println!(
+        "bridging {kind} device {path} to {name}, device MAC address: {}",
+        mac.map(|b| format!("{b:02x}")).join(":")
+    );
+
+    let mut buf = vec![0u8; 2048];
+    loop {
+        // frames from the TAP interface to the device
+        let mut fds = [PollFd::new(&tap, PollFlags::POLLIN)];
+        match poll(&mut fds, POLL_INTERVAL as i32) {
+            Ok(n) if n > 0 => {
+                let len = tap.read(&mut buf)?;
+                link.send(&buf[..len])?;
+            }
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(err) => return Err(err.into()),
+        }
+
+        // frames from the device to the TAP interface
+        if let Some(frame) = link.recv()? {
+            tap.write_all(&frame)?;
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(path) = args.first() else {
+        eprintln!("Usage: cdc_tap <device node> [interface name]");
+        process::exit(2);
+    };
+    let name = args.get(1).map_or("usb-tap0", |n| n.as_str());
+
+    if let Err(err) = run(path, name) {
+        eprintln!("cdc_tap: {err}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,183 @@
+//! Kernel ABI verification for the `ioctl` FFI records and request codes.
+//!
+//! Layouts are checked against `linux/usbdevice_fs.h` for LP64 (`x86_64`, `aarch64`) and ILP32
+//! (`arm`, `x86`) targets. 32-bit processes running on a 64-bit kernel go through
+//! `compat_ioctl`, which expects the ILP32 sizes, so the same records are correct there.
+//!
+//! Expected values are given as `(LP64, ILP32)` pairs, and selected with [by_width].
+
+/// Selects the expected value for the target pointer width from an `(LP64, ILP32)` pair.
+pub(crate) const fn by_width(val: (usize, usize)) -> usize {
+    if cfg!(target_pointer_width = "64") {
+        val.0
+    } else {
+        val.1
+    }
+}
+
+/// Asserts, at compile time, the size, alignment and field offsets of an FFI record.
+///
+/// Each value is an `(LP64, ILP32)` pair. Invoked next to the record, so private fields are
+/// visible to `offset_of!`.
+macro_rules! assert_layout {
+    ($ty:ty, size: $size:expr, align: $align:expr, { $($field:ident: $off:expr),* $(,)? }) => {
+        #[cfg(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "x86"
+        ))]
+        const _: () = {
+            use $crate::abi::by_width;
+
+            assert!(core::mem::size_of::<$ty>() == by_width($size));
+            assert!(core::mem::align_of::<$ty>() == by_width($align));
+            $(assert!(core::mem::offset_of!($ty, $field) == by_width($off));)*
+        };
+    };
+}
+
+pub(crate) use assert_layout;
+
+macro_rules! assert_request_codes {
+    ($($code:ident: $exp:expr),* $(,)?) => {
+        #[cfg(any(
+            target_arch = "x86_64",
+            target_arch = "aarch64",
+            target_arch = "arm",
+            target_arch = "x86"
+        ))]
+        const _: () = {
+            $(assert!(crate::ioctl::$code as usize == by_width($exp));)*
+        };
+
+        #[cfg(test)]
+        const REQUEST_CODES: &[(&str, nix::sys::ioctl::ioctl_num_type, (usize, usize))] = &[
+            $((stringify!($code), crate::ioctl::$code, $exp),)*
+        ];
+    };
+}
+
+assert_request_codes! {
+    USBDEVFS_CONTROL: (0xc018_5500, 0xc010_5500),
+    USBDEVFS_BULK: (0xc018_5502, 0xc010_5502),
+    USBDEVFS_RESETEP: (0x8004_5503, 0x8004_5503),
+    USBDEVFS_SETINTERFACE: (0x8008_5504, 0x8008_5504),
+    USBDEVFS_SETCONFIGURATION: (0x8004_5505, 0x8004_5505),
+    USBDEVFS_GETDRIVER: (0x4104_5508, 0x4104_5508),
+    USBDEVFS_SUBMITURB: (0x8038_550a, 0x802c_550a),
+    USBDEVFS_DISCARDURB: (0x550b, 0x550b),
+    USBDEVFS_REAPURB: (0x4008_550c, 0x4004_550c),
+    USBDEVFS_REAPURBNDELAY: (0x4008_550d, 0x4004_550d),
+    USBDEVFS_CLAIMINTERFACE: (0x8004_550f, 0x8004_550f),
+    USBDEVFS_RELEASEINTERFACE: (0x8004_5510, 0x8004_5510),
+    USBDEVFS_CONNECTINFO: (0x4008_5511, 0x4008_5511),
+    USBDEVFS_IOCTL: (0xc010_5512, 0xc00c_5512),
+    USBDEVFS_RESET: (0x5514, 0x5514),
+    USBDEVFS_CLEAR_HALT: (0x8004_5515, 0x8004_5515),
+    USBDEVFS_DISCONNECT: (0x5516, 0x5516),
+    USBDEVFS_CONNECT: (0x5517, 0x5517),
+    USBDEVFS_GET_CAPABILITIES: (0x8004_551a, 0x8004_551a),
+    USBDEVFS_DISCONNECT_CLAIM: (0x8108_551b, 0x8108_551b),
+    USBDEVFS_ALLOC_STREAMS: (0x8008_551c, 0x8008_551c),
+    USBDEVFS_FREE_STREAMS: (0x8008_551d, 0x8008_551d),
+    USBDEVFS_DROP_PRIVILEGES: (0x4004_551e, 0x4004_551e),
+    USBDEVFS_GET_SPEED: (0x551f, 0x551f),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{align_of, size_of};
+
+    use super::*;
+    use crate::types::*;
+    use crate::{UsbfsConnectInfo, UsbfsDisconnectClaim, UsbfsSetInterface};
+
+    #[test]
+    fn test_request_codes() {
+        for (name, code, exp) in REQUEST_CODES.iter() {
+            assert_eq!(*code as usize, by_width(*exp), "{name}: {code:#x}");
+        }
+    }
+
+    #[test]
+    fn test_layouts() {
+        let layouts = [
+            (
+                "UrbFfi",
+                size_of::<UrbFfi>(),
+                align_of::<UrbFfi>(),
+                (56, 44),
+                (8, 4),
+            ),
+            (
+                "UsbfsCtrlTransferFfi",
+                size_of::<UsbfsCtrlTransferFfi>(),
+                align_of::<UsbfsCtrlTransferFfi>(),
+                (24, 16),
+                (8, 4),
+            ),
+            (
+                "UsbfsBulkTransferFfi",
+                size_of::<UsbfsBulkTransferFfi>(),
+                align_of::<UsbfsBulkTransferFfi>(),
+                (24, 16),
+                (8, 4),
+            ),
+            (
+                "UsbfsIoctlFfi",
+                size_of::<UsbfsIoctlFfi>(),
+                align_of::<UsbfsIoctlFfi>(),
+                (16, 12),
+                (8, 4),
+            ),
+            (
+                "UsbfsStreamsFfi",
+                size_of::<UsbfsStreamsFfi>(),
+                align_of::<UsbfsStreamsFfi>(),
+                (8, 8),
+                (4, 4),
+            ),
+            (
+                "UsbfsGetDriver",
+                size_of::<UsbfsGetDriver>(),
+                align_of::<UsbfsGetDriver>(),
+                (260, 260),
+                (4, 4),
+            ),
+            (
+                "UsbfsDisconnectClaim",
+                size_of::<UsbfsDisconnectClaim>(),
+                align_of::<UsbfsDisconnectClaim>(),
+                (264, 264),
+                (4, 4),
+            ),
+            (
+                "UsbfsConnectInfo",
+                size_of::<UsbfsConnectInfo>(),
+                align_of::<UsbfsConnectInfo>(),
+                (8, 8),
+                (4, 4),
+            ),
+            (
+                "UsbfsSetInterface",
+                size_of::<UsbfsSetInterface>(),
+                align_of::<UsbfsSetInterface>(),
+                (8, 8),
+                (4, 4),
+            ),
+            (
+                "UsbfsIsoPacketDesc",
+                size_of::<UsbfsIsoPacketDesc>(),
+                align_of::<UsbfsIsoPacketDesc>(),
+                (12, 12),
+                (4, 4),
+            ),
+        ];
+
+        for (name, size, align, exp_size, exp_align) in layouts {
+            assert_eq!(size, by_width(exp_size), "{name} size");
+            assert_eq!(align, by_width(exp_align), "{name} align");
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Backend abstraction over USBFS operations.
+//!
+//! [UsbBackend] covers every operation exposed by the `usbfs_*` functions, so applications can
+//! be written against the trait, and run on real hardware through an [IoctlBackend], or
+//! deterministically in tests through a [MockDevice]. Sessions on any backend can be recorded
+//! with a [Recorder], and served back by a [Replay].
+
+use std::time::Duration;
+
+use crate::{
+    Result, Urb, UrbId, UsbDevice, UsbfsBulkTransfer, UsbfsConnectInfo, UsbfsCtrlTransfer,
+    UsbfsDisconnectClaim, UsbfsGetDriver, UsbfsIoctl, UsbfsSetInterface, UsbfsSpeed, UsbfsStreams,
+};
+
+mod mock;
+mod session;
+
+pub use mock::{MockControl, MockDevice, MockResponse};
+pub use session::{
+    Recorder, Replay, Session, SessionOp, SessionRecord, SESSION_MAGIC, SESSION_VERSION,
+};
+
+pub(crate) use session::unzigzag;
+
+/// Operations of an opened USBFS device.
+///
+/// Each method mirrors the `usbfs_*` function of the same name, without the file descriptor
+/// argument. URBs are owned by the backend until they are reaped.
+pub trait UsbBackend {
+    /// Gets the raw descriptors, as read from the device node.
+    fn descriptors(&self) -> Result<Vec<u8>>;
+
+    /// Performs a synchronous Control transfer, see [usbfs_control](crate::usbfs_control).
+    fn control(&self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize>;
+
+    /// Performs a synchronous Bulk or Interrupt transfer, see [usbfs_bulk](crate::usbfs_bulk).
+    fn bulk(&self, bulk: &mut UsbfsBulkTransfer) -> Result<usize>;
+
+    /// Selects an alternate setting, see [usbfs_set_interface](crate::usbfs_set_interface).
+    fn set_interface(&self, set_interface: &UsbfsSetInterface) -> Result<()>;
+
+    /// Selects the active configuration, see
+    /// [usbfs_set_configuration](crate::usbfs_set_configuration).
+    fn set_configuration(&self, config: u32) -> Result<()>;
+
+    /// Gets the kernel driver bound to an interface, see
+    /// [usbfs_get_driver](crate::usbfs_get_driver).
+    fn get_driver(&self, get_driver: &mut UsbfsGetDriver) -> Result<()>;
+
+    /// Submits an asynchronous [Urb], see [UsbDevice::submit_urb].
+    fn submit_urb(&self, urb: Urb<'_>) -> Result<UrbId>;
+
+    /// Cancels a submitted URB, see [UsbDevice::discard_urb].
+    fn discard_urb(&self, id: UrbId) -> Result<()>;
+
+    /// Waits for any URB to complete, see [UsbDevice::reap_urb].
+    fn reap_urb(&self, timeout: Option<Duration>) -> Result<(UrbId, Urb<'static>)>;
+
+    /// Claims an interface, see [usbfs_claim_interface](crate::usbfs_claim_interface).
+    fn claim_interface(&self, iface: u32) -> Result<()>;
+
+    /// Releases an interface, see [usbfs_release_interface](crate::usbfs_release_interface).
+    fn release_interface(&self, iface: u32) -> Result<()>;
+
+    /// Gets the connection information, see [usbfs_connect_info](crate::usbfs_connect_info).
+    fn connect_info(&self) -> Result<UsbfsConnectInfo>;
+
+    /// Performs an interface `ioctl`, see [usbfs_ioctl](crate::usbfs_ioctl).
+    fn ioctl(&self, ioctl: &mut UsbfsIoctl) -> Result<()>;
+
+    /// Resets the device, see [usbfs_reset](crate::usbfs_reset).
+    fn reset(&self) -> Result<()>;
+
+    /// Clears an endpoint halt, see [usbfs_clear_halt](crate::usbfs_clear_halt).
+    fn clear_halt(&self, ep: u32) -> Result<()>;
+
+    /// Resets an endpoint data toggle, see [usbfs_reset_ep](crate::usbfs_reset_ep).
+    fn reset_ep(&self, ep: u32) -> Result<()>;
+
+    /// Disconnects the kernel driver, see [usbfs_disconnect](crate::usbfs_disconnect).
+    fn disconnect(&self) -> Result<()>;
+
+    /// Reconnects the kernel driver, see [usbfs_connect](crate::usbfs_connect).
+    fn connect(&self) -> Result<()>;
+
+    /// Gets the capabilities bitmask, see
+    /// [usbfs_get_capabilities](crate::usbfs_get_capabilities).
+    fn get_capabilities(&self) -> Result<u32>;
+
+    /// Claims an interface, disconnecting its driver, see
+    /// [usbfs_disconnect_claim](crate::usbfs_disconnect_claim).
+    fn disconnect_claim(&self, claim: &UsbfsDisconnectClaim) -> Result<()>;
+
+    /// Allocates Bulk streams, see [usbfs_alloc_streams](crate::usbfs_alloc_streams).
+    fn alloc_streams(&self, streams: &UsbfsStreams) -> Result<()>;
+
+    /// Frees Bulk streams, see [usbfs_free_streams](crate::usbfs_free_streams).
+    fn free_streams(&self, streams: &UsbfsStreams) -> Result<()>;
+
+    /// Drops privileges, see [usbfs_drop_privileges](crate::usbfs_drop_privileges).
+    fn drop_privileges(&self, privileges: u64) -> Result<()>;
+
+    /// Gets the device speed, see [usbfs_get_speed](crate::usbfs_get_speed).
+    fn get_speed(&self) -> Result<UsbfsSpeed>;
+}
+
+/// [UsbBackend] issuing `ioctl` calls on an opened [UsbDevice].
+///
+/// Calls go through the device disconnect detection, and URBs through its URB queue.
+#[derive(Debug)]
+pub struct IoctlBackend {
+    device: UsbDevice,
+}
+
+impl IoctlBackend {
+    /// Creates a new [IoctlBackend].
+    pub const fn new(device: UsbDevice) -> Self {
+        Self { device }
+    }
+
+    /// Gets a reference to the [UsbDevice].
+    pub const fn device(&self) -> &UsbDevice {
+        &self.device
+    }
+
+    /// Gets a mutable reference to the [UsbDevice].
+    pub fn device_mut(&mut self) -> &mut UsbDevice {
+        &mut self.device
+    }
+
+    /// Converts the [IoctlBackend] into its [UsbDevice].
+    pub fn into_device(self) -> UsbDevice {
+        self.device
+    }
+}
+
+impl From<UsbDevice> for IoctlBackend {
+    fn from(val: UsbDevice) -> Self {
+        Self::new(val)
+    }
+}
+
+impl UsbBackend for IoctlBackend {
+    fn descriptors(&self) -> Result<Vec<u8>> {
+        Ok(self.device.descriptors().into())
+    }
+
+    fn control(&self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
+        self.device.io(|fd| crate::usbfs_control(fd, ctrl))
+    }
+
+    fn bulk(&self, bulk: &mut UsbfsBulkTransfer) -> Result<usize> {
+        self.device.io(|fd| crate::usbfs_bulk(fd, bulk))
+    }
+
+    fn set_interface(&self, set_interface: &UsbfsSetInterface) -> Result<()> {
+        let mut set_interface = *set_interface;
+        self.device
+            .io(|fd| crate::usbfs_set_interface(fd, &mut set_interface))
+    }
+
+    fn set_configuration(&self, config: u32) -> Result<()> {
+        let mut config = config;
+        self.device
+            .io(|fd| crate::usbfs_set_configuration(fd, &mut config))
+    }
+
+    fn get_driver(&self, get_driver: &mut UsbfsGetDriver) -> Result<()> {
+        self.device.io(|fd| crate::usbfs_get_driver(fd, get_driver))
+    }
+
+    fn submit_urb(&self, urb: Urb<'_>) -> Result<UrbId> {
+        self.device.submit_urb(urb)
+    }
+
+    fn discard_urb(&self, id: UrbId) -> Result<()> {
+        self.device.discard_urb(id)
+    }
+
+    fn reap_urb(&self, timeout: Option<Duration>) -> Result<(UrbId, Urb<'static>)> {
+        self.device.reap_urb(timeout)
+    }
+
+    fn claim_interface(&self, iface: u32) -> Result<()> {
+        let mut iface = iface;
+        self.device
+            .io(|fd| crate::usbfs_claim_interface(fd, &mut iface))
+    }
+
+    fn release_interface(&self, iface: u32) -> Result<()> {
+        let mut iface = iface;
+        self.device
+            .io(|fd| crate::usbfs_release_interface(fd, &mut iface))
+    }
+
+    fn connect_info(&self) -> Result<UsbfsConnectInfo> {
+        let mut info = UsbfsConnectInfo::new();
+        self.device
+            .io(|fd| crate::usbfs_connect_info(fd, &mut info))?;
+        Ok(info)
+    }
+
+    fn ioctl(&self, ioctl: &mut UsbfsIoctl) -> Result<()> {
+        self.device.io(|fd| crate::usbfs_ioctl(fd, ioctl))
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.device.io(crate::usbfs_reset)
+    }
+
+    fn clear_halt(&self, ep: u32) -> Result<()> {
+        let mut ep = ep;
+        self.device.io(|fd| crate::usbfs_clear_halt(fd, &mut ep))
+    }
+
+    fn reset_ep(&self, ep: u32) -> Result<()> {
+        let mut ep = ep;
+        self.device.io(|fd| crate::usbfs_reset_ep(fd, &mut ep))
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        self.device.io(crate::usbfs_disconnect)
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.device.io(crate::usbfs_connect)
+    }
+
+    fn get_capabilities(&self) -> Result<u32> {
+        self.device.capabilities()
+    }
+
+    fn disconnect_claim(&self, claim: &UsbfsDisconnectClaim) -> Result<()> {
+        let mut claim = *claim;
+        self.device
+            .io(|fd| crate::usbfs_disconnect_claim(fd, &mut claim))
+    }
+
+    fn alloc_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let mut streams = streams.clone();
+        self.device
+            .io(|fd| crate::usbfs_alloc_streams(fd, &mut streams))
+    }
+
+    fn free_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let mut streams = streams.clone();
+        self.device
+            .io(|fd| crate::usbfs_free_streams(fd, &mut streams))
+    }
+
+    fn drop_privileges(&self, privileges: u64) -> Result<()> {
+        self.device
+            .io(|fd| crate::usbfs_drop_privileges(fd, privileges))
+    }
+
+    fn get_speed(&self) -> Result<UsbfsSpeed> {
+        self.device.speed()
+    }
+}
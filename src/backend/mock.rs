@@ -0,0 +1,950 @@
+//! In-memory [UsbBackend] for deterministic tests.
+//!
+//! A [MockDevice] is scripted with descriptors, expected control requests and their responses,
+//! canned endpoint data, stalls and disconnects. Standard `GET_DESCRIPTOR` and
+//! `GET_CONFIGURATION` requests are answered from the descriptors, unless an expectation is
+//! queued for them.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use nix::errno::Errno;
+
+use super::UsbBackend;
+use crate::descriptor::{
+    read_u16, DESCRIPTOR_TYPE_CONFIG, DESCRIPTOR_TYPE_DEVICE, ENDPOINT_DIR_IN,
+};
+use crate::{
+    Error, Result, Urb, UrbId, UsbfsBulkTransfer, UsbfsConnectInfo, UsbfsCtrlTransfer,
+    UsbfsDisconnectClaim, UsbfsDisconnectClaimFlag, UsbfsGetDriver, UsbfsIoctl, UsbfsSetInterface,
+    UsbfsSpeed, UsbfsStreams, URB_TYPE_CONTROL, URB_TYPE_ISO, USBFS_DRIVER_NAME,
+    USBFS_IOCTL_CONNECT, USBFS_IOCTL_DISCONNECT,
+};
+
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+const REQUEST_GET_CONFIGURATION: u8 = 0x08;
+const SETUP_LEN: usize = 8;
+
+/// Scripted response of a [MockDevice] to a transfer.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MockResponse {
+    /// Returns the data for `IN` transfers, accepts `OUT` transfers.
+    Data(Vec<u8>),
+    /// Completes without data for `IN` transfers, accepts `OUT` transfers.
+    Ack,
+    /// Stalls the transfer, halting Bulk and Interrupt endpoints until cleared.
+    Stall,
+    /// Fails the transfer with the provided error number.
+    Errno(i32),
+    /// Disconnects the device.
+    Disconnect,
+}
+
+impl MockResponse {
+    /// Creates a new [MockResponse].
+    pub const fn new() -> Self {
+        Self::Ack
+    }
+}
+
+impl Default for MockResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for MockResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Data(data) => write!(f, r#"{{"data": {data:?}}}"#),
+            Self::Ack => write!(f, r#""ack""#),
+            Self::Stall => write!(f, r#""stall""#),
+            Self::Errno(errno) => write!(f, r#"{{"errno": "{}"}}"#, Errno::from_i32(*errno)),
+            Self::Disconnect => write!(f, r#""disconnect""#),
+        }
+    }
+}
+
+/// Expected Control request of a [MockDevice], and its scripted response.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MockControl {
+    request_type: u8,
+    request: u8,
+    value: u16,
+    index: u16,
+    data: Option<Vec<u8>>,
+    response: MockResponse,
+}
+
+impl MockControl {
+    /// Creates a new [MockControl].
+    pub const fn new() -> Self {
+        Self {
+            request_type: 0,
+            request: 0,
+            value: 0,
+            index: 0,
+            data: None,
+            response: MockResponse::new(),
+        }
+    }
+
+    /// Creates a new [MockControl] expecting the provided setup fields.
+    pub const fn create(request_type: u8, request: u8, value: u16, index: u16) -> Self {
+        Self {
+            request_type,
+            request,
+            value,
+            index,
+            data: None,
+            response: MockResponse::new(),
+        }
+    }
+
+    /// Gets the expected request type.
+    pub const fn request_type(&self) -> u8 {
+        self.request_type
+    }
+
+    /// Gets the expected request.
+    pub const fn request(&self) -> u8 {
+        self.request
+    }
+
+    /// Gets the expected value.
+    pub const fn value(&self) -> u16 {
+        self.value
+    }
+
+    /// Gets the expected index.
+    pub const fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Gets the expected `OUT` data, if any.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    /// Sets the expected `OUT` data.
+    ///
+    /// Without expected data, any `OUT` data is accepted.
+    pub fn set_data<D: IntoIterator<Item = u8>>(&mut self, data: D) {
+        self.data = Some(data.into_iter().collect());
+    }
+
+    /// Builder function that sets the expected `OUT` data.
+    pub fn with_data<D: IntoIterator<Item = u8>>(mut self, data: D) -> Self {
+        self.set_data(data);
+        self
+    }
+
+    /// Gets the scripted response.
+    pub const fn response(&self) -> &MockResponse {
+        &self.response
+    }
+
+    /// Sets the scripted response.
+    pub fn set_response(&mut self, response: MockResponse) {
+        self.response = response;
+    }
+
+    /// Builder function that sets the scripted response.
+    pub fn with_response(mut self, response: MockResponse) -> Self {
+        self.set_response(response);
+        self
+    }
+
+    /// Gets whether the Control transfer matches the expectation.
+    pub fn matches(&self, ctrl: &UsbfsCtrlTransfer) -> bool {
+        self.request_type == ctrl.request_type()
+            && self.request == ctrl.request()
+            && self.value == ctrl.value()
+            && self.index == ctrl.index()
+            && (ctrl.request_type() & ENDPOINT_DIR_IN != 0
+                || self.data.as_ref().map_or(true, |d| d == ctrl.data()))
+    }
+}
+
+impl fmt::Display for MockControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""request_type": {}, "#, self.request_type)?;
+        write!(f, r#""request": {}, "#, self.request)?;
+        write!(f, r#""value": {}, "#, self.value)?;
+        write!(f, r#""index": {}, "#, self.index)?;
+        match self.data.as_ref() {
+            Some(data) => write!(f, r#""data": {data:?}, "#)?,
+            None => write!(f, r#""data": null, "#)?,
+        }
+        write!(f, r#""response": {}"#, self.response)?;
+        write!(f, "}}")
+    }
+}
+
+struct MockUrb {
+    id: UrbId,
+    urb: Urb<'static>,
+    discarded: bool,
+}
+
+impl fmt::Debug for MockUrb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockUrb")
+            .field("id", &self.id)
+            .field("urb", &format_args!("{}", self.urb))
+            .field("discarded", &self.discarded)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    descriptors: Vec<u8>,
+    speed: UsbfsSpeed,
+    capabilities: u32,
+    connect_info: UsbfsConnectInfo,
+    configuration: u8,
+    drivers: BTreeMap<u32, String>,
+    claimed: BTreeSet<u32>,
+    alt_settings: BTreeMap<u32, u32>,
+    controls: VecDeque<MockControl>,
+    responses: BTreeMap<u8, VecDeque<MockResponse>>,
+    written: BTreeMap<u8, Vec<Vec<u8>>>,
+    halted: BTreeSet<u8>,
+    urbs: VecDeque<MockUrb>,
+    next_id: u64,
+    privileges: Option<u32>,
+    disconnected: bool,
+    unexpected: Vec<String>,
+}
+
+impl MockState {
+    fn check(&self) -> Result<()> {
+        if self.disconnected {
+            Err(Error::Disconnected)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_privileged(&self) -> Result<()> {
+        self.check()?;
+        match self.privileges {
+            Some(_) => Err(Errno::EACCES.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn control(&mut self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
+        self.check()?;
+
+        let response = match self.controls.front() {
+            Some(exp) if exp.matches(ctrl) => self.controls.pop_front().map(|c| c.response),
+            _ => self.standard_response(ctrl),
+        };
+
+        let Some(response) = response else {
+            self.unexpected.push(format!(
+                "control request_type: 0x{:02x}, request: 0x{:02x}, value: 0x{:04x}, index: 0x{:04x}",
+                ctrl.request_type(),
+                ctrl.request(),
+                ctrl.value(),
+                ctrl.index()
+            ));
+            // devices stall requests they do not support
+            return Err(Errno::EPIPE.into());
+        };
+
+        let data_in = ctrl.request_type() & ENDPOINT_DIR_IN != 0;
+        let mut data = ctrl.data().to_vec();
+        let res = self.respond(None, data_in, &mut data, response);
+        ctrl.set_data(data);
+
+        res
+    }
+
+    fn standard_response(&self, ctrl: &UsbfsCtrlTransfer) -> Option<MockResponse> {
+        match (ctrl.request_type(), ctrl.request()) {
+            (ENDPOINT_DIR_IN, REQUEST_GET_CONFIGURATION) => {
+                Some(MockResponse::Data(vec![self.configuration]))
+            }
+            (ENDPOINT_DIR_IN, REQUEST_GET_DESCRIPTOR) => {
+                let [index, desc_type] = ctrl.value().to_le_bytes();
+                self.descriptor(desc_type, index).map(MockResponse::Data)
+            }
+            _ => None,
+        }
+    }
+
+    fn descriptor(&self, desc_type: u8, index: u8) -> Option<Vec<u8>> {
+        let buf = self.descriptors.as_slice();
+        let dev_len = *buf.first()? as usize;
+
+        match desc_type {
+            DESCRIPTOR_TYPE_DEVICE => buf.get(..dev_len).map(|d| d.to_vec()),
+            DESCRIPTOR_TYPE_CONFIG => {
+                let mut off = dev_len;
+                for _ in 0..index {
+                    off += read_u16(buf.get(off..off + 4)?, 2) as usize;
+                }
+                let len = read_u16(buf.get(off..off + 4)?, 2) as usize;
+                buf.get(off..off + len).map(|d| d.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    /// Applies a response to a transfer, returning the transferred length.
+    fn respond(
+        &mut self,
+        ep: Option<u8>,
+        data_in: bool,
+        data: &mut [u8],
+        response: MockResponse,
+    ) -> Result<usize> {
+        match response {
+            MockResponse::Data(res) if data_in => {
+                let len = res.len().min(data.len());
+                data[..len].copy_from_slice(&res[..len]);
+                Ok(len)
+            }
+            MockResponse::Ack if data_in => Ok(0),
+            MockResponse::Data(_) | MockResponse::Ack => {
+                if let Some(ep) = ep {
+                    self.written.entry(ep).or_default().push(data.to_vec());
+                }
+                Ok(data.len())
+            }
+            MockResponse::Stall => {
+                if let Some(ep) = ep {
+                    self.halted.insert(ep);
+                }
+                Err(Errno::EPIPE.into())
+            }
+            MockResponse::Errno(errno) => Err(Error::Errno(errno)),
+            MockResponse::Disconnect => {
+                self.disconnected = true;
+                Err(Error::Disconnected)
+            }
+        }
+    }
+
+    /// Gets the next response of an endpoint, if the transfer can complete.
+    fn endpoint_response(&mut self, ep: u8) -> Option<MockResponse> {
+        if self.halted.contains(&ep) {
+            return Some(MockResponse::Stall);
+        }
+
+        let res = self.responses.get_mut(&ep).and_then(|r| r.pop_front());
+        if ep & ENDPOINT_DIR_IN != 0 {
+            res
+        } else {
+            Some(res.unwrap_or(MockResponse::Ack))
+        }
+    }
+
+    fn bulk(&mut self, bulk: &mut UsbfsBulkTransfer) -> Result<usize> {
+        self.check()?;
+
+        let ep = bulk.ep() as u8;
+        // nothing to read before the timeout expires
+        let response = self
+            .endpoint_response(ep)
+            .ok_or(Error::from(Errno::ETIMEDOUT))?;
+
+        self.respond(
+            Some(ep),
+            ep & ENDPOINT_DIR_IN != 0,
+            bulk.data_mut(),
+            response,
+        )
+    }
+
+    fn reap_urb(&mut self) -> Result<(UrbId, Urb<'static>)> {
+        if self.urbs.is_empty() {
+            self.check()?;
+            return Err(Error::NotFound("pending URB".into()));
+        }
+
+        for pos in 0..self.urbs.len() {
+            let (urb_type, ep, discarded) = {
+                let u = &self.urbs[pos];
+                (u.urb.urb_type(), u.urb.endpoint(), u.discarded)
+            };
+
+            let status = if self.disconnected {
+                Some(Err(Error::Disconnected))
+            } else if discarded {
+                Some(Err(Errno::ENOENT.into()))
+            } else if urb_type == URB_TYPE_CONTROL {
+                Some(self.control_urb(pos))
+            } else if urb_type == URB_TYPE_ISO && ep & ENDPOINT_DIR_IN != 0 {
+                self.iso_urb(pos)
+            } else {
+                self.endpoint_response(ep).map(|res| {
+                    let mut buf = self.urbs[pos].urb.buffer().to_vec();
+                    let res = self.respond(Some(ep), ep & ENDPOINT_DIR_IN != 0, &mut buf, res);
+                    self.urbs[pos].urb.set_buffer(buf);
+                    res
+                })
+            };
+
+            if let Some(status) = status {
+                let MockUrb { id, mut urb, .. } = self.urbs.remove(pos).unwrap_or_else(|| {
+                    unreachable!("URB position is in range");
+                });
+                match status {
+                    Ok(len) => urb.set_actual_length(len),
+                    Err(err) => urb.set_status(-err.errno().unwrap_or(Errno::EIO as i32)),
+                }
+                return Ok((id, urb));
+            }
+        }
+
+        Err(Errno::ETIMEDOUT.into())
+    }
+
+    /// Fills the packets of an Isochronous `IN` URB, one queued response per packet.
+    ///
+    /// Packets without a queued response complete empty, as on an idle device. As with the
+    /// kernel, the URB completes with `-EXDEV` when any packet failed.
+    fn iso_urb(&mut self, pos: usize) -> Option<Result<usize>> {
+        let ep = self.urbs[pos].urb.endpoint();
+        if self.responses.get(&ep).map_or(true, |r| r.is_empty()) {
+            return None;
+        }
+
+        let mut buf = self.urbs[pos].urb.buffer().to_vec();
+        let mut packets = self.urbs[pos].urb.iso_frame_desc().to_vec();
+        let mut off = 0;
+        let mut total = 0;
+        let mut lost = false;
+
+        for packet in packets.iter_mut() {
+            let end = (off + packet.length() as usize).min(buf.len());
+            let res = self.responses.get_mut(&ep).and_then(|r| r.pop_front());
+            match res.map(|res| self.respond(None, true, &mut buf[off..end], res)) {
+                Some(Ok(len)) => {
+                    packet.set_actual_length(len as u32);
+                    total += len;
+                }
+                Some(Err(err)) => {
+                    packet.set_actual_length(0);
+                    packet
+                        .set_status(err.errno().unwrap_or(Errno::EIO as i32).wrapping_neg() as u32);
+                    lost = true;
+                }
+                None => packet.set_actual_length(0),
+            }
+            off = end;
+        }
+
+        let urb = &mut self.urbs[pos].urb;
+        urb.set_buffer(buf);
+        urb.set_iso_frame_desc(packets);
+        if lost {
+            urb.set_status(-(Errno::EXDEV as i32));
+        }
+        Some(Ok(total))
+    }
+
+    fn control_urb(&mut self, pos: usize) -> Result<usize> {
+        let mut buf = self.urbs[pos].urb.buffer().to_vec();
+        if buf.len() < SETUP_LEN {
+            return Err(Errno::EINVAL.into());
+        }
+
+        let len = (read_u16(&buf, 6) as usize).min(buf.len() - SETUP_LEN);
+        let mut ctrl = UsbfsCtrlTransfer::new()
+            .with_request_type(buf[0])
+            .with_request(buf[1])
+            .with_value(read_u16(&buf, 2))
+            .with_index(read_u16(&buf, 4))
+            .with_data(buf[SETUP_LEN..SETUP_LEN + len].iter().copied());
+
+        let res = self.control(&mut ctrl);
+        buf[SETUP_LEN..SETUP_LEN + len].copy_from_slice(ctrl.data());
+        self.urbs[pos].urb.set_buffer(buf);
+
+        res
+    }
+}
+
+/// In-memory [UsbBackend] for tests without hardware.
+///
+/// Control requests are matched in order against the queued [MockControl] expectations.
+/// Unexpected requests are stalled, and reported by [verify](Self::verify). `IN` endpoints
+/// return their queued [MockResponse] in order, and time out when none is left. `OUT` endpoints
+/// accept all data, unless a response is queued, and record every write.
+///
+/// URBs complete when reaped, in submission order, skipping `IN` URBs without queued data.
+/// Isochronous `IN` URBs take one queued response per packet.
+#[derive(Debug, Default)]
+pub struct MockDevice {
+    state: Mutex<MockState>,
+}
+
+impl MockDevice {
+    /// Creates a new [MockDevice].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Builder function that sets the raw descriptors.
+    ///
+    /// The descriptors use the device node layout: the device descriptor, followed by every
+    /// configuration descriptor.
+    pub fn with_descriptors<D: IntoIterator<Item = u8>>(self, descriptors: D) -> Self {
+        self.state().descriptors = descriptors.into_iter().collect();
+        self
+    }
+
+    /// Builder function that sets the device speed.
+    pub fn with_speed(self, speed: UsbfsSpeed) -> Self {
+        self.state().speed = speed;
+        self
+    }
+
+    /// Builder function that sets the capabilities bitmask.
+    pub fn with_capabilities(self, capabilities: u32) -> Self {
+        self.state().capabilities = capabilities;
+        self
+    }
+
+    /// Builder function that sets the connection information.
+    pub fn with_connect_info(self, connect_info: UsbfsConnectInfo) -> Self {
+        self.state().connect_info = connect_info;
+        self
+    }
+
+    /// Builder function that sets the active configuration value.
+    pub fn with_configuration(self, configuration: u8) -> Self {
+        self.state().configuration = configuration;
+        self
+    }
+
+    /// Builder function that binds a kernel driver to an interface.
+    pub fn with_driver(self, iface: u32, driver: &str) -> Self {
+        self.state().drivers.insert(iface, driver.into());
+        self
+    }
+
+    /// Queues an expected Control request.
+    pub fn expect_control(&self, ctrl: MockControl) {
+        self.state().controls.push_back(ctrl);
+    }
+
+    /// Builder function that queues an expected Control request.
+    pub fn with_control(self, ctrl: MockControl) -> Self {
+        self.expect_control(ctrl);
+        self
+    }
+
+    /// Queues a response for the next transfer on an endpoint.
+    pub fn push_response(&self, ep: u8, response: MockResponse) {
+        self.state()
+            .responses
+            .entry(ep)
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Queues canned data for the next read on an `IN` endpoint.
+    pub fn push_data<D: IntoIterator<Item = u8>>(&self, ep: u8, data: D) {
+        self.push_response(ep, MockResponse::Data(data.into_iter().collect()));
+    }
+
+    /// Builder function that queues canned data for an `IN` endpoint.
+    pub fn with_data<D: IntoIterator<Item = u8>>(self, ep: u8, data: D) -> Self {
+        self.push_data(ep, data);
+        self
+    }
+
+    /// Halts the endpoint, until the halt is cleared.
+    pub fn stall(&self, ep: u8) {
+        self.state().halted.insert(ep);
+    }
+
+    /// Gets whether the endpoint is halted.
+    pub fn is_halted(&self, ep: u8) -> bool {
+        self.state().halted.contains(&ep)
+    }
+
+    /// Simulates unplugging the device.
+    ///
+    /// Pending URBs complete with a disconnected status, and every other operation returns an
+    /// [Error::Disconnected] error.
+    pub fn unplug(&self) {
+        self.state().disconnected = true;
+    }
+
+    /// Gets whether the device was unplugged.
+    pub fn is_disconnected(&self) -> bool {
+        self.state().disconnected
+    }
+
+    /// Takes the data written to an `OUT` endpoint, one entry per transfer.
+    pub fn take_written(&self, ep: u8) -> Vec<Vec<u8>> {
+        self.state().written.remove(&ep).unwrap_or_default()
+    }
+
+    /// Gets the active configuration value.
+    pub fn configuration(&self) -> u8 {
+        self.state().configuration
+    }
+
+    /// Gets the claimed interfaces.
+    pub fn claimed_interfaces(&self) -> Vec<u32> {
+        self.state().claimed.iter().copied().collect()
+    }
+
+    /// Gets the selected alternate setting of an interface.
+    pub fn alt_setting(&self, iface: u32) -> u32 {
+        self.state().alt_settings.get(&iface).copied().unwrap_or(0)
+    }
+
+    /// Gets the number of submitted URBs that were not reaped yet.
+    pub fn pending_urbs(&self) -> usize {
+        self.state().urbs.len()
+    }
+
+    /// Verifies every expected Control request was performed, and no unexpected request was.
+    pub fn verify(&self) -> Result<()> {
+        let state = self.state();
+        let mut errs = state.unexpected.clone();
+        errs.extend(
+            state
+                .controls
+                .iter()
+                .map(|c| format!("missing control {c}")),
+        );
+
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidMessage(errs.join("; ")))
+        }
+    }
+}
+
+impl UsbBackend for MockDevice {
+    fn descriptors(&self) -> Result<Vec<u8>> {
+        let state = self.state();
+        state.check()?;
+        Ok(state.descriptors.clone())
+    }
+
+    fn control(&self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
+        self.state().control(ctrl)
+    }
+
+    fn bulk(&self, bulk: &mut UsbfsBulkTransfer) -> Result<usize> {
+        self.state().bulk(bulk)
+    }
+
+    fn set_interface(&self, set_interface: &UsbfsSetInterface) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        if !state.claimed.contains(&set_interface.interface()) {
+            return Err(Errno::EINVAL.into());
+        }
+        state
+            .alt_settings
+            .insert(set_interface.interface(), set_interface.altsetting());
+        Ok(())
+    }
+
+    fn set_configuration(&self, config: u32) -> Result<()> {
+        let mut state = self.state();
+        state.check_privileged()?;
+        if !state.claimed.is_empty() {
+            return Err(Errno::EBUSY.into());
+        }
+        state.configuration = config as u8;
+        state.alt_settings.clear();
+        Ok(())
+    }
+
+    fn get_driver(&self, get_driver: &mut UsbfsGetDriver) -> Result<()> {
+        let state = self.state();
+        state.check()?;
+        let driver = state
+            .drivers
+            .get(&get_driver.interface())
+            .ok_or(Error::from(Errno::ENODATA))?;
+        get_driver.set_driver(driver);
+        Ok(())
+    }
+
+    fn submit_urb(&self, urb: Urb<'_>) -> Result<UrbId> {
+        let mut state = self.state();
+        state.check()?;
+
+        let id = UrbId::create(state.next_id);
+        state.next_id += 1;
+
+        state.urbs.push_back(MockUrb {
+            id,
+            urb: urb.into_owned(),
+            discarded: false,
+        });
+
+        Ok(id)
+    }
+
+    fn discard_urb(&self, id: UrbId) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        let urb = state
+            .urbs
+            .iter_mut()
+            .find(|u| u.id == id)
+            .ok_or(Error::NotFound(format!("pending URB {id}")))?;
+        urb.discarded = true;
+        Ok(())
+    }
+
+    /// Completes the first URB that can complete.
+    ///
+    /// **NOTE** the mock never blocks, an `ETIMEDOUT` error is returned when no URB can
+    /// complete, regardless of the `timeout`.
+    fn reap_urb(&self, _timeout: Option<Duration>) -> Result<(UrbId, Urb<'static>)> {
+        self.state().reap_urb()
+    }
+
+    fn claim_interface(&self, iface: u32) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        if state
+            .privileges
+            .is_some_and(|p| iface >= 32 || p & (1 << iface) == 0)
+        {
+            return Err(Errno::EACCES.into());
+        }
+        match state.drivers.get(&iface) {
+            Some(driver) if driver != USBFS_DRIVER_NAME => Err(Errno::EBUSY.into()),
+            _ => {
+                state.drivers.insert(iface, USBFS_DRIVER_NAME.into());
+                state.claimed.insert(iface);
+                Ok(())
+            }
+        }
+    }
+
+    fn release_interface(&self, iface: u32) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        if !state.claimed.remove(&iface) {
+            return Err(Errno::EINVAL.into());
+        }
+        state.drivers.remove(&iface);
+        state.alt_settings.remove(&iface);
+        Ok(())
+    }
+
+    fn connect_info(&self) -> Result<UsbfsConnectInfo> {
+        let state = self.state();
+        state.check()?;
+        Ok(state.connect_info)
+    }
+
+    fn ioctl(&self, ioctl: &mut UsbfsIoctl) -> Result<()> {
+        let mut state = self.state();
+        state.check_privileged()?;
+        let iface = ioctl.ifno() as u32;
+
+        match ioctl.ioctl_code() {
+            USBFS_IOCTL_DISCONNECT => match state.drivers.get(&iface) {
+                Some(driver) if driver != USBFS_DRIVER_NAME => {
+                    state.drivers.remove(&iface);
+                    Ok(())
+                }
+                _ => Err(Errno::ENODATA.into()),
+            },
+            // the mock does not track which driver would bind
+            USBFS_IOCTL_CONNECT => Ok(()),
+            _ => Err(Errno::ENOTTY.into()),
+        }
+    }
+
+    fn reset(&self) -> Result<()> {
+        let mut state = self.state();
+        state.check_privileged()?;
+        state.halted.clear();
+        state.alt_settings.clear();
+        Ok(())
+    }
+
+    fn clear_halt(&self, ep: u32) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        state.halted.remove(&(ep as u8));
+        Ok(())
+    }
+
+    fn reset_ep(&self, _ep: u32) -> Result<()> {
+        self.state().check()
+    }
+
+    /// The kernel only handles `USBDEVFS_DISCONNECT` through [usbfs_ioctl](crate::usbfs_ioctl).
+    fn disconnect(&self) -> Result<()> {
+        self.state().check()?;
+        Err(Errno::ENOTTY.into())
+    }
+
+    /// The kernel only handles `USBDEVFS_CONNECT` through [usbfs_ioctl](crate::usbfs_ioctl).
+    fn connect(&self) -> Result<()> {
+        self.state().check()?;
+        Err(Errno::ENOTTY.into())
+    }
+
+    fn get_capabilities(&self) -> Result<u32> {
+        let state = self.state();
+        state.check()?;
+        Ok(state.capabilities)
+    }
+
+    fn disconnect_claim(&self, claim: &UsbfsDisconnectClaim) -> Result<()> {
+        let iface = claim.interface();
+        {
+            let mut state = self.state();
+            state.check()?;
+
+            let detach = match (state.drivers.get(&iface), claim.flags()) {
+                (Some(driver), _) if driver == USBFS_DRIVER_NAME => false,
+                (Some(_), UsbfsDisconnectClaimFlag::None) => true,
+                (Some(driver), UsbfsDisconnectClaimFlag::IfDriver) => driver == claim.driver(),
+                (Some(driver), UsbfsDisconnectClaimFlag::ExceptDriver) => driver != claim.driver(),
+                (None, _) => false,
+            };
+            if detach {
+                state.drivers.remove(&iface);
+            }
+        }
+
+        self.claim_interface(iface)
+    }
+
+    fn alloc_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let state = self.state();
+        state.check()?;
+        if streams.num_streams() < 2 || streams.eps().is_empty() {
+            return Err(Errno::EINVAL.into());
+        }
+        Ok(())
+    }
+
+    fn free_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let state = self.state();
+        state.check()?;
+        if streams.eps().is_empty() {
+            return Err(Errno::EINVAL.into());
+        }
+        Ok(())
+    }
+
+    fn drop_privileges(&self, privileges: u64) -> Result<()> {
+        let mut state = self.state();
+        state.check()?;
+        let privileges = privileges as u32;
+        state.privileges = Some(state.privileges.map_or(privileges, |p| p & privileges));
+        Ok(())
+    }
+
+    fn get_speed(&self) -> Result<UsbfsSpeed> {
+        let state = self.state();
+        state.check()?;
+        Ok(state.speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{URB_TYPE_BULK, URB_TYPE_INTERRUPT};
+
+    #[test]
+    fn test_mock_device() -> Result<()> {
+        let dev = MockDevice::new()
+            .with_driver(1, "cdc_acm")
+            .with_control(
+                MockControl::create(0x21, 0x20, 0, 0)
+                    .with_data([0x80, 0x25, 0, 0, 0, 0, 8])
+                    .with_response(MockResponse::Ack),
+            )
+            .with_data(0x81, [1, 2, 3]);
+
+        // expected control request
+        let mut ctrl = UsbfsCtrlTransfer::new()
+            .with_request_type(0x21)
+            .with_request(0x20)
+            .with_data([0x80, 0x25, 0, 0, 0, 0, 8]);
+        assert_eq!(dev.control(&mut ctrl), Ok(7));
+
+        // unexpected control request
+        let mut ctrl = UsbfsCtrlTransfer::new()
+            .with_request_type(0x21)
+            .with_request(0x22);
+        assert_eq!(dev.control(&mut ctrl), Err(Errno::EPIPE.into()));
+        assert!(dev.verify().is_err());
+
+        // canned data, then timeout
+        let mut bulk = UsbfsBulkTransfer::create(0x81, 0, [0u8; 8]);
+        assert_eq!(dev.bulk(&mut bulk), Ok(3));
+        assert_eq!(&bulk.data()[..3], [1, 2, 3].as_ref());
+        assert_eq!(dev.bulk(&mut bulk), Err(Errno::ETIMEDOUT.into()));
+
+        // stall until the halt is cleared
+        dev.stall(0x02);
+        let mut bulk = UsbfsBulkTransfer::create(0x02, 0, [4u8; 2]);
+        assert_eq!(dev.bulk(&mut bulk), Err(Errno::EPIPE.into()));
+        dev.clear_halt(0x02)?;
+        assert_eq!(dev.bulk(&mut bulk), Ok(2));
+        assert_eq!(dev.take_written(0x02), [vec![4u8; 2]]);
+
+        // claims
+        assert_eq!(dev.claim_interface(1), Err(Errno::EBUSY.into()));
+        dev.disconnect_claim(&UsbfsDisconnectClaim::create(
+            1,
+            UsbfsDisconnectClaimFlag::IfDriver,
+            "cdc_acm",
+        ))?;
+        assert_eq!(dev.claimed_interfaces(), [1]);
+        assert_eq!(dev.set_configuration(1), Err(Errno::EBUSY.into()));
+
+        // URBs complete in order, skipping reads without data
+        let read = dev.submit_urb(
+            Urb::new()
+                .with_urb_type(URB_TYPE_INTERRUPT)
+                .with_endpoint(0x83)
+                .with_buffer([0u8; 4]),
+        )?;
+        let write = dev.submit_urb(
+            Urb::new()
+                .with_urb_type(URB_TYPE_BULK)
+                .with_endpoint(0x02)
+                .with_buffer([5u8; 3]),
+        )?;
+        let (id, urb) = dev.reap_urb(None)?;
+        assert_eq!((id, urb.actual_length()), (write, 3));
+        assert_eq!(dev.reap_urb(None).err(), Some(Errno::ETIMEDOUT.into()));
+
+        // unplugging completes pending URBs, then fails every operation
+        dev.unplug();
+        let (id, urb) = dev.reap_urb(None)?;
+        assert_eq!((id, urb.status()), (read, -(Errno::ENODEV as i32)));
+        assert_eq!(dev.reap_urb(None).err(), Some(Error::Disconnected));
+        assert_eq!(dev.get_speed(), Err(Error::Disconnected));
+
+        Ok(())
+    }
+}
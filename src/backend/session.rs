@@ -0,0 +1,1041 @@
+//! Record and replay of USB sessions.
+//!
+//! A [Recorder] wraps a [UsbBackend], and logs every operation with its timestamp, arguments,
+//! data and result. A [Replay] serves a recorded [Session] back, and flags requests diverging
+//! from the recording.
+//!
+//! Sessions use a compact binary format: the [SESSION_MAGIC] bytes and the [SESSION_VERSION],
+//! followed by the records. Integers are LEB128 varints, signed integers are zigzag encoded,
+//! and byte strings are prefixed with their length.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::UsbBackend;
+use crate::descriptor::ENDPOINT_DIR_IN;
+use crate::{
+    Error, Result, Urb, UrbId, UsbfsBulkTransfer, UsbfsConnectInfo, UsbfsCtrlTransfer,
+    UsbfsDisconnectClaim, UsbfsGetDriver, UsbfsIoctl, UsbfsSetInterface, UsbfsSpeed, UsbfsStreams,
+    URB_TYPE_CONTROL,
+};
+
+/// Magic bytes starting a session file.
+pub const SESSION_MAGIC: &[u8; 8] = b"USBFSREC";
+/// Version of the session format.
+pub const SESSION_VERSION: u64 = 1;
+
+// status of records failing with errors not originating from a system call
+const STATUS_DISCONNECTED: i32 = -1;
+const STATUS_NOT_FOUND: i32 = -2;
+const STATUS_OTHER: i32 = -3;
+
+/// Represents the operation of a [SessionRecord].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SessionOp {
+    Descriptors = 0,
+    Control = 1,
+    Bulk = 2,
+    SetInterface = 3,
+    SetConfiguration = 4,
+    GetDriver = 5,
+    SubmitUrb = 6,
+    DiscardUrb = 7,
+    ReapUrb = 8,
+    ClaimInterface = 9,
+    ReleaseInterface = 10,
+    ConnectInfo = 11,
+    Ioctl = 12,
+    Reset = 13,
+    ClearHalt = 14,
+    ResetEp = 15,
+    Disconnect = 16,
+    Connect = 17,
+    GetCapabilities = 18,
+    DisconnectClaim = 19,
+    AllocStreams = 20,
+    FreeStreams = 21,
+    DropPrivileges = 22,
+    GetSpeed = 23,
+}
+
+impl SessionOp {
+    /// Creates a new [SessionOp] from its inner representation.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Self::Descriptors),
+            1 => Some(Self::Control),
+            2 => Some(Self::Bulk),
+            3 => Some(Self::SetInterface),
+            4 => Some(Self::SetConfiguration),
+            5 => Some(Self::GetDriver),
+            6 => Some(Self::SubmitUrb),
+            7 => Some(Self::DiscardUrb),
+            8 => Some(Self::ReapUrb),
+            9 => Some(Self::ClaimInterface),
+            10 => Some(Self::ReleaseInterface),
+            11 => Some(Self::ConnectInfo),
+            12 => Some(Self::Ioctl),
+            13 => Some(Self::Reset),
+            14 => Some(Self::ClearHalt),
+            15 => Some(Self::ResetEp),
+            16 => Some(Self::Disconnect),
+            17 => Some(Self::Connect),
+            18 => Some(Self::GetCapabilities),
+            19 => Some(Self::DisconnectClaim),
+            20 => Some(Self::AllocStreams),
+            21 => Some(Self::FreeStreams),
+            22 => Some(Self::DropPrivileges),
+            23 => Some(Self::GetSpeed),
+            _ => None,
+        }
+    }
+
+    /// Gets the inner representation of the [SessionOp].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&SessionOp> for &'static str {
+    fn from(val: &SessionOp) -> Self {
+        match val {
+            SessionOp::Descriptors => "descriptors",
+            SessionOp::Control => "control",
+            SessionOp::Bulk => "bulk",
+            SessionOp::SetInterface => "set_interface",
+            SessionOp::SetConfiguration => "set_configuration",
+            SessionOp::GetDriver => "get_driver",
+            SessionOp::SubmitUrb => "submit_urb",
+            SessionOp::DiscardUrb => "discard_urb",
+            SessionOp::ReapUrb => "reap_urb",
+            SessionOp::ClaimInterface => "claim_interface",
+            SessionOp::ReleaseInterface => "release_interface",
+            SessionOp::ConnectInfo => "connect_info",
+            SessionOp::Ioctl => "ioctl",
+            SessionOp::Reset => "reset",
+            SessionOp::ClearHalt => "clear_halt",
+            SessionOp::ResetEp => "reset_ep",
+            SessionOp::Disconnect => "disconnect",
+            SessionOp::Connect => "connect",
+            SessionOp::GetCapabilities => "get_capabilities",
+            SessionOp::DisconnectClaim => "disconnect_claim",
+            SessionOp::AllocStreams => "alloc_streams",
+            SessionOp::FreeStreams => "free_streams",
+            SessionOp::DropPrivileges => "drop_privileges",
+            SessionOp::GetSpeed => "get_speed",
+        }
+    }
+}
+
+impl fmt::Display for SessionOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents one recorded operation of a [Session].
+///
+/// The request is made of the operation, its integer arguments, and its outgoing data. The
+/// result is made of a status, integer values, and incoming data. The status is zero on
+/// success, and the error number on failure.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionRecord {
+    timestamp: u64,
+    op: SessionOp,
+    args: Vec<u64>,
+    data: Vec<u8>,
+    status: i32,
+    values: Vec<u64>,
+    response: Vec<u8>,
+}
+
+impl SessionRecord {
+    fn create(timestamp: u64, op: SessionOp, args: Vec<u64>, data: Vec<u8>) -> Self {
+        Self {
+            timestamp,
+            op,
+            args,
+            data,
+            status: 0,
+            values: Vec::new(),
+            response: Vec::new(),
+        }
+    }
+
+    /// Gets the time of the operation, since the start of the session.
+    pub const fn timestamp(&self) -> Duration {
+        Duration::from_micros(self.timestamp)
+    }
+
+    /// Gets the [SessionOp].
+    pub const fn op(&self) -> SessionOp {
+        self.op
+    }
+
+    /// Gets the integer arguments of the request.
+    pub fn args(&self) -> &[u64] {
+        self.args.as_ref()
+    }
+
+    /// Gets the outgoing data of the request.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Gets the result status.
+    ///
+    /// Zero on success, positive error numbers for failed system calls, and negative values for
+    /// other errors.
+    pub const fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Gets the integer values of the result.
+    pub fn values(&self) -> &[u64] {
+        self.values.as_ref()
+    }
+
+    /// Gets the incoming data of the result.
+    pub fn response(&self) -> &[u8] {
+        self.response.as_ref()
+    }
+
+    fn set_result(&mut self, res: &Result<Outcome>) {
+        match res {
+            Ok(out) => {
+                self.status = 0;
+                self.values = out.values.clone();
+                self.response = out.response.clone();
+            }
+            Err(Error::Disconnected) => self.status = STATUS_DISCONNECTED,
+            Err(Error::Errno(errno)) => self.status = *errno,
+            Err(Error::NotFound(err)) => {
+                self.status = STATUS_NOT_FOUND;
+                self.response = err.as_bytes().into();
+            }
+            Err(err) => {
+                self.status = STATUS_OTHER;
+                self.response = err.to_string().into_bytes();
+            }
+        }
+    }
+
+    fn result(&self) -> Result<Outcome> {
+        let msg = || String::from_utf8_lossy(&self.response).into_owned();
+        match self.status {
+            0 => Ok(Outcome {
+                values: self.values.clone(),
+                response: self.response.clone(),
+            }),
+            STATUS_DISCONNECTED => Err(Error::Disconnected),
+            STATUS_NOT_FOUND => Err(Error::NotFound(msg())),
+            errno if errno > 0 => Err(Error::Errno(errno)),
+            _ => Err(Error::Io(msg())),
+        }
+    }
+
+    fn matches(&self, op: SessionOp, args: &[u64], data: &[u8]) -> bool {
+        self.op == op && self.args == args && self.data == data
+    }
+
+    /// Writes the [SessionRecord] in the session format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[self.op.inner()])?;
+        write_varint(writer, self.timestamp)?;
+        write_varints(writer, &self.args)?;
+        write_bytes(writer, &self.data)?;
+        write_varint(writer, zigzag(self.status as i64))?;
+        write_varints(writer, &self.values)?;
+        write_bytes(writer, &self.response)?;
+        Ok(())
+    }
+
+    /// Reads a [SessionRecord] in the session format.
+    ///
+    /// Returns `None` at the end of the session.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<Self>> {
+        let mut op = [0u8];
+        if reader.read(&mut op)? == 0 {
+            return Ok(None);
+        }
+
+        let op = SessionOp::create(op[0]).ok_or(Error::InvalidMessage(format!(
+            "unknown session operation: {}",
+            op[0]
+        )))?;
+
+        Ok(Some(Self {
+            op,
+            timestamp: read_varint(reader)?,
+            args: read_varints(reader)?,
+            data: read_bytes(reader)?,
+            status: unzigzag(read_varint(reader)?) as i32,
+            values: read_varints(reader)?,
+            response: read_bytes(reader)?,
+        }))
+    }
+}
+
+impl fmt::Display for SessionRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""timestamp": {}, "#, self.timestamp)?;
+        write!(f, r#""op": {}, "#, self.op)?;
+        write!(f, r#""args": {:?}, "#, self.args)?;
+        write!(f, r#""data": {:?}, "#, self.data)?;
+        write!(f, r#""status": {}, "#, self.status)?;
+        write!(f, r#""values": {:?}, "#, self.values)?;
+        write!(f, r#""response": {:?}"#, self.response)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a recorded USB session.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Session {
+    records: Vec<SessionRecord>,
+}
+
+impl Session {
+    /// Creates a new [Session].
+    pub const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+
+    /// Gets the list of [SessionRecord]s.
+    pub fn records(&self) -> &[SessionRecord] {
+        self.records.as_ref()
+    }
+
+    /// Reads a [Session] in the session format.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; SESSION_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != SESSION_MAGIC {
+            return Err(Error::InvalidMessage("not a USB session".into()));
+        }
+
+        let version = read_varint(&mut reader)?;
+        if version != SESSION_VERSION {
+            return Err(Error::InvalidMessage(format!(
+                "unsupported session version: {version}"
+            )));
+        }
+
+        let mut records = Vec::new();
+        while let Some(record) = SessionRecord::read_from(&mut reader)? {
+            records.push(record);
+        }
+
+        Ok(Self { records })
+    }
+
+    /// Writes the [Session] in the session format.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        write_header(&mut writer)?;
+        for record in self.records.iter() {
+            record.write_to(&mut writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Opens a session file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the [Session] to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Result values of an operation, shared by recording and replay.
+#[derive(Debug, Default)]
+struct Outcome {
+    values: Vec<u64>,
+    response: Vec<u8>,
+}
+
+impl Outcome {
+    fn create<V: IntoIterator<Item = u64>>(values: V, response: &[u8]) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+            response: response.into(),
+        }
+    }
+
+    fn value(&self, idx: usize) -> u64 {
+        self.values.get(idx).copied().unwrap_or(0)
+    }
+}
+
+/// Runs session operations, either recording them, or replaying them.
+trait SessionIo {
+    fn run<F>(&self, op: SessionOp, args: Vec<u64>, data: Vec<u8>, exec: F) -> Result<Outcome>
+    where
+        F: FnOnce(&dyn UsbBackend) -> Result<Outcome>;
+
+    /// Keeps a submitted URB that was not passed to a backend.
+    fn submitted(&self, _id: UrbId, _urb: Urb<'static>) {}
+
+    /// Takes back a submitted URB that was not passed to a backend.
+    fn take_submitted(&self, _id: UrbId) -> Option<Urb<'static>> {
+        None
+    }
+}
+
+/// [UsbBackend] wrapping another backend, and recording every operation.
+pub struct Recorder<B: UsbBackend, W: Write> {
+    backend: B,
+    start: Instant,
+    writer: Mutex<(W, Option<Error>)>,
+}
+
+impl<B: UsbBackend, W: Write> Recorder<B, W> {
+    /// Creates a new [Recorder], writing the session header.
+    pub fn new(backend: B, mut writer: W) -> Result<Self> {
+        write_header(&mut writer)?;
+        Ok(Self {
+            backend,
+            start: Instant::now(),
+            writer: Mutex::new((writer, None)),
+        })
+    }
+
+    /// Gets a reference to the recorded backend.
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Flushes the session, and returns the backend and the writer.
+    ///
+    /// Returns the first error encountered while writing records.
+    pub fn finish(self) -> Result<(B, W)> {
+        let (mut writer, err) = self.writer.into_inner().unwrap_or_else(|e| e.into_inner());
+        if let Some(err) = err {
+            return Err(err);
+        }
+        writer.flush()?;
+        Ok((self.backend, writer))
+    }
+}
+
+impl<B: UsbBackend> Recorder<B, BufWriter<File>> {
+    /// Creates a new [Recorder], writing the session to a file.
+    pub fn create<P: AsRef<Path>>(backend: B, path: P) -> Result<Self> {
+        Self::new(backend, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<B: UsbBackend, W: Write> SessionIo for Recorder<B, W> {
+    fn run<F>(&self, op: SessionOp, args: Vec<u64>, data: Vec<u8>, exec: F) -> Result<Outcome>
+    where
+        F: FnOnce(&dyn UsbBackend) -> Result<Outcome>,
+    {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        let res = exec(&self.backend);
+
+        let mut record = SessionRecord::create(timestamp, op, args, data);
+        record.set_result(&res);
+
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if writer.1.is_none() {
+            if let Err(err) = record.write_to(&mut writer.0) {
+                writer.1 = Some(err);
+            }
+        }
+
+        res
+    }
+}
+
+impl<B: UsbBackend + fmt::Debug, W: Write> fmt::Debug for Recorder<B, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("backend", &self.backend)
+            .field("start", &self.start)
+            .finish()
+    }
+}
+
+struct ReplayState {
+    records: VecDeque<SessionRecord>,
+    position: usize,
+    urbs: BTreeMap<UrbId, Urb<'static>>,
+    divergences: Vec<String>,
+}
+
+/// [UsbBackend] serving a recorded [Session] back.
+///
+/// Requests are matched in order against the recorded requests, including their arguments and
+/// outgoing data. Divergent requests fail with an [Error::InvalidMessage] error, and are
+/// reported by [verify](Self::verify).
+///
+/// **NOTE** the recorded timing is not reproduced, and the reap timeout is not compared.
+pub struct Replay {
+    state: Mutex<ReplayState>,
+}
+
+impl Replay {
+    /// Creates a new [Replay].
+    pub fn new(session: Session) -> Self {
+        Self {
+            state: Mutex::new(ReplayState {
+                records: session.records.into(),
+                position: 0,
+                urbs: BTreeMap::new(),
+                divergences: Vec::new(),
+            }),
+        }
+    }
+
+    /// Opens a session file for replay.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Session::open(path).map(Self::new)
+    }
+
+    fn state(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Gets the number of records not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.state().records.len()
+    }
+
+    /// Gets the list of divergent requests.
+    pub fn divergences(&self) -> Vec<String> {
+        self.state().divergences.clone()
+    }
+
+    /// Verifies every record was replayed, without divergence.
+    pub fn verify(&self) -> Result<()> {
+        let state = self.state();
+        let mut errs = state.divergences.clone();
+        if !state.records.is_empty() {
+            errs.push(format!("{} records not replayed", state.records.len()));
+        }
+
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidMessage(errs.join("; ")))
+        }
+    }
+}
+
+impl SessionIo for Replay {
+    fn run<F>(&self, op: SessionOp, args: Vec<u64>, data: Vec<u8>, _exec: F) -> Result<Outcome>
+    where
+        F: FnOnce(&dyn UsbBackend) -> Result<Outcome>,
+    {
+        let mut state = self.state();
+        let position = state.position;
+        let request = SessionRecord::create(0, op, args, data);
+
+        let divergence = match state.records.pop_front() {
+            Some(record) if record.matches(op, &request.args, &request.data) => {
+                state.position += 1;
+                return record.result();
+            }
+            Some(record) => {
+                state.position += 1;
+                format!("record {position}: expected {record}, got {request}")
+            }
+            None => format!("record {position}: end of session, got {request}"),
+        };
+
+        state.divergences.push(divergence.clone());
+        Err(Error::InvalidMessage(divergence))
+    }
+
+    fn submitted(&self, id: UrbId, urb: Urb<'static>) {
+        self.state().urbs.insert(id, urb);
+    }
+
+    fn take_submitted(&self, id: UrbId) -> Option<Urb<'static>> {
+        self.state().urbs.remove(&id)
+    }
+}
+
+impl fmt::Debug for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("Replay")
+            .field("position", &state.position)
+            .field("remaining", &state.records.len())
+            .field("divergences", &state.divergences)
+            .finish()
+    }
+}
+
+fn is_data_in(urb_type: u8, ep: u8) -> bool {
+    urb_type == URB_TYPE_CONTROL || ep & ENDPOINT_DIR_IN != 0
+}
+
+fn reap_outcome(id: UrbId, urb: &Urb<'_>) -> Outcome {
+    let mut values = vec![
+        id.inner(),
+        zigzag(urb.status() as i64),
+        urb.actual_length() as u64,
+        zigzag(urb.start_frame() as i64),
+        zigzag(urb.error_count() as i64),
+        urb.iso_frame_desc().len() as u64,
+    ];
+    for desc in urb.iso_frame_desc() {
+        values.extend([desc.actual_length() as u64, desc.status() as u64]);
+    }
+
+    let response = if is_data_in(urb.urb_type(), urb.endpoint()) {
+        urb.buffer()
+    } else {
+        &[]
+    };
+
+    Outcome::create(values, response)
+}
+
+fn apply_reap(urb: &mut Urb<'_>, out: &Outcome) {
+    // setting the buffer resets the actual length
+    if !out.response.is_empty() {
+        urb.set_buffer(out.response.iter().copied());
+    }
+    urb.set_status(unzigzag(out.value(1)) as i32);
+    urb.set_actual_length(out.value(2) as usize);
+    urb.set_start_frame(unzigzag(out.value(3)) as i32);
+    urb.set_error_count(unzigzag(out.value(4)) as i32);
+
+    let iso = urb
+        .iso_frame_desc()
+        .iter()
+        .enumerate()
+        .map(|(i, desc)| {
+            desc.with_actual_length(out.value(6 + i * 2) as u32)
+                .with_status(out.value(7 + i * 2) as u32)
+        })
+        .collect::<Vec<_>>();
+    urb.set_iso_frame_desc(iso);
+}
+
+impl<S: SessionIo> UsbBackend for S {
+    fn descriptors(&self) -> Result<Vec<u8>> {
+        let out = self.run(SessionOp::Descriptors, vec![], vec![], |b| {
+            Ok(Outcome::create([], &b.descriptors()?))
+        })?;
+        Ok(out.response)
+    }
+
+    fn control(&self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
+        let data_in = ctrl.request_type() & ENDPOINT_DIR_IN != 0;
+        let args = vec![
+            ctrl.request_type() as u64,
+            ctrl.request() as u64,
+            ctrl.value() as u64,
+            ctrl.index() as u64,
+            ctrl.length() as u64,
+        ];
+        let data = if data_in { vec![] } else { ctrl.data().into() };
+
+        let out = self.run(SessionOp::Control, args, data, |b| {
+            let len = b.control(ctrl)?;
+            let res = if data_in { &ctrl.data()[..len] } else { &[] };
+            Ok(Outcome::create([len as u64], res))
+        })?;
+
+        if data_in {
+            let mut data = ctrl.data().to_vec();
+            let len = out.response.len().min(data.len());
+            data[..len].copy_from_slice(&out.response[..len]);
+            ctrl.set_data(data);
+        }
+
+        Ok(out.value(0) as usize)
+    }
+
+    fn bulk(&self, bulk: &mut UsbfsBulkTransfer) -> Result<usize> {
+        let data_in = bulk.ep() as u8 & ENDPOINT_DIR_IN != 0;
+        let args = vec![bulk.ep() as u64, bulk.length() as u64];
+        let data = if data_in { vec![] } else { bulk.data().into() };
+
+        let out = self.run(SessionOp::Bulk, args, data, |b| {
+            let len = b.bulk(bulk)?;
+            let res = if data_in { &bulk.data()[..len] } else { &[] };
+            Ok(Outcome::create([len as u64], res))
+        })?;
+
+        let buf = bulk.data_mut();
+        let len = out.response.len().min(buf.len());
+        buf[..len].copy_from_slice(&out.response[..len]);
+
+        Ok(out.value(0) as usize)
+    }
+
+    fn set_interface(&self, set_interface: &UsbfsSetInterface) -> Result<()> {
+        let args = vec![
+            set_interface.interface() as u64,
+            set_interface.altsetting() as u64,
+        ];
+        self.run(SessionOp::SetInterface, args, vec![], |b| {
+            b.set_interface(set_interface).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn set_configuration(&self, config: u32) -> Result<()> {
+        self.run(
+            SessionOp::SetConfiguration,
+            vec![config as u64],
+            vec![],
+            |b| b.set_configuration(config).map(|_| Outcome::default()),
+        )
+        .map(|_| ())
+    }
+
+    fn get_driver(&self, get_driver: &mut UsbfsGetDriver) -> Result<()> {
+        let args = vec![get_driver.interface() as u64];
+        let out = self.run(SessionOp::GetDriver, args, vec![], |b| {
+            b.get_driver(get_driver)?;
+            Ok(Outcome::create([], get_driver.driver().as_bytes()))
+        })?;
+
+        get_driver.set_driver(&String::from_utf8_lossy(&out.response));
+        Ok(())
+    }
+
+    fn submit_urb(&self, urb: Urb<'_>) -> Result<UrbId> {
+        let mut args = vec![
+            urb.urb_type() as u64,
+            urb.endpoint() as u64,
+            urb.flags() as u64,
+            urb.info().stream_id() as u64,
+            urb.buffer_length() as u64,
+            urb.iso_frame_desc().len() as u64,
+        ];
+        args.extend(urb.iso_frame_desc().iter().map(|d| d.length() as u64));
+        // Control URBs start with their setup packet
+        let data = match urb.urb_type() {
+            URB_TYPE_CONTROL => urb.buffer().into(),
+            _ if urb.endpoint() & ENDPOINT_DIR_IN != 0 => vec![],
+            _ => urb.buffer().into(),
+        };
+
+        let mut slot = Some(urb);
+        let out = self.run(SessionOp::SubmitUrb, args, data, |b| {
+            let urb = slot
+                .take()
+                .ok_or(Error::InvalidArgument("URB already submitted".into()))?;
+            Ok(Outcome::create([b.submit_urb(urb)?.inner()], &[]))
+        })?;
+
+        let id = UrbId::create(out.value(0));
+        if let Some(urb) = slot {
+            self.submitted(id, urb.into_owned());
+        }
+
+        Ok(id)
+    }
+
+    fn discard_urb(&self, id: UrbId) -> Result<()> {
+        self.run(SessionOp::DiscardUrb, vec![id.inner()], vec![], |b| {
+            b.discard_urb(id).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn reap_urb(&self, timeout: Option<Duration>) -> Result<(UrbId, Urb<'static>)> {
+        let mut reaped = None;
+        let out = self.run(SessionOp::ReapUrb, vec![], vec![], |b| {
+            let (id, urb) = b.reap_urb(timeout)?;
+            let out = reap_outcome(id, &urb);
+            reaped = Some(urb);
+            Ok(out)
+        })?;
+
+        let id = UrbId::create(out.value(0));
+        let urb = match reaped {
+            Some(urb) => urb,
+            None => {
+                let mut urb = self
+                    .take_submitted(id)
+                    .ok_or(Error::NotFound(format!("submitted URB {id}")))?;
+                apply_reap(&mut urb, &out);
+                urb
+            }
+        };
+
+        Ok((id, urb))
+    }
+
+    fn claim_interface(&self, iface: u32) -> Result<()> {
+        self.run(SessionOp::ClaimInterface, vec![iface as u64], vec![], |b| {
+            b.claim_interface(iface).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn release_interface(&self, iface: u32) -> Result<()> {
+        self.run(
+            SessionOp::ReleaseInterface,
+            vec![iface as u64],
+            vec![],
+            |b| b.release_interface(iface).map(|_| Outcome::default()),
+        )
+        .map(|_| ())
+    }
+
+    fn connect_info(&self) -> Result<UsbfsConnectInfo> {
+        let out = self.run(SessionOp::ConnectInfo, vec![], vec![], |b| {
+            let info = b.connect_info()?;
+            Ok(Outcome::create(
+                [info.devnum() as u64, info.slow() as u64],
+                &[],
+            ))
+        })?;
+
+        Ok(UsbfsConnectInfo::create(
+            out.value(0) as u32,
+            out.value(1) as u8,
+        ))
+    }
+
+    /// **NOTE** the `ioctl` data is opaque, and is neither recorded nor replayed.
+    fn ioctl(&self, ioctl: &mut UsbfsIoctl) -> Result<()> {
+        let args = vec![ioctl.ifno() as u64, ioctl.ioctl_code() as u64];
+        self.run(SessionOp::Ioctl, args, vec![], |b| {
+            b.ioctl(ioctl).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn reset(&self) -> Result<()> {
+        self.run(SessionOp::Reset, vec![], vec![], |b| {
+            b.reset().map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn clear_halt(&self, ep: u32) -> Result<()> {
+        self.run(SessionOp::ClearHalt, vec![ep as u64], vec![], |b| {
+            b.clear_halt(ep).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn reset_ep(&self, ep: u32) -> Result<()> {
+        self.run(SessionOp::ResetEp, vec![ep as u64], vec![], |b| {
+            b.reset_ep(ep).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        self.run(SessionOp::Disconnect, vec![], vec![], |b| {
+            b.disconnect().map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn connect(&self) -> Result<()> {
+        self.run(SessionOp::Connect, vec![], vec![], |b| {
+            b.connect().map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn get_capabilities(&self) -> Result<u32> {
+        let out = self.run(SessionOp::GetCapabilities, vec![], vec![], |b| {
+            Ok(Outcome::create([b.get_capabilities()? as u64], &[]))
+        })?;
+        Ok(out.value(0) as u32)
+    }
+
+    fn disconnect_claim(&self, claim: &UsbfsDisconnectClaim) -> Result<()> {
+        let args = vec![claim.interface() as u64, claim.flags().inner() as u64];
+        let data = claim.driver().as_bytes().into();
+        self.run(SessionOp::DisconnectClaim, args, data, |b| {
+            b.disconnect_claim(claim).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn alloc_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let args = vec![streams.num_streams() as u64];
+        self.run(SessionOp::AllocStreams, args, streams.eps().into(), |b| {
+            b.alloc_streams(streams).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn free_streams(&self, streams: &UsbfsStreams) -> Result<()> {
+        let args = vec![streams.num_streams() as u64];
+        self.run(SessionOp::FreeStreams, args, streams.eps().into(), |b| {
+            b.free_streams(streams).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn drop_privileges(&self, privileges: u64) -> Result<()> {
+        self.run(SessionOp::DropPrivileges, vec![privileges], vec![], |b| {
+            b.drop_privileges(privileges).map(|_| Outcome::default())
+        })
+        .map(|_| ())
+    }
+
+    fn get_speed(&self) -> Result<UsbfsSpeed> {
+        let out = self.run(SessionOp::GetSpeed, vec![], vec![], |b| {
+            Ok(Outcome::create([b.get_speed()?.inner() as u64], &[]))
+        })?;
+        Ok(UsbfsSpeed::create(out.value(0) as u32))
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W) -> Result<()> {
+    writer.write_all(SESSION_MAGIC)?;
+    write_varint(writer, SESSION_VERSION)
+}
+
+const fn zigzag(val: i64) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+pub(crate) const fn unzigzag(val: u64) -> i64 {
+    ((val >> 1) as i64) ^ -((val & 1) as i64)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut val: u64) -> Result<()> {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            writer.write_all(&[byte])?;
+            return Ok(());
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut val = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte).map_err(truncated)?;
+        val |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(Error::InvalidMessage("session varint overflow".into()))
+}
+
+fn write_varints<W: Write>(writer: &mut W, vals: &[u64]) -> Result<()> {
+    write_varint(writer, vals.len() as u64)?;
+    vals.iter().try_for_each(|&v| write_varint(writer, v))
+}
+
+fn read_varints<R: Read>(reader: &mut R) -> Result<Vec<u64>> {
+    let len = read_varint(reader)?;
+    (0..len).map(|_| read_varint(reader)).collect()
+}
+
+fn write_bytes<W: Write>(writer: &mut W, buf: &[u8]) -> Result<()> {
+    write_varint(writer, buf.len() as u64)?;
+    writer.write_all(buf)?;
+    Ok(())
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = read_varint(reader)?;
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(truncated(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(buf)
+}
+
+fn truncated(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::InvalidMessage("truncated session record".into()),
+        _ => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockDevice, MockResponse, URB_TYPE_BULK};
+
+    fn run_session<B: UsbBackend>(dev: &B) -> Result<Vec<u8>> {
+        let mut ctrl = UsbfsCtrlTransfer::new()
+            .with_request_type(0x80)
+            .with_request(0x08)
+            .with_data([0u8]);
+        dev.control(&mut ctrl)?;
+
+        dev.claim_interface(0)?;
+        let id = dev.submit_urb(
+            Urb::new()
+                .with_urb_type(URB_TYPE_BULK)
+                .with_endpoint(0x81)
+                .with_buffer([0u8; 8]),
+        )?;
+        let (reaped, urb) = dev.reap_urb(None)?;
+        assert_eq!(reaped, id);
+
+        let mut bulk = UsbfsBulkTransfer::create(0x81, 1000, [0u8; 4]);
+        assert_eq!(dev.bulk(&mut bulk), Err(Error::Errno(nix::libc::EPIPE)));
+        dev.reset()?;
+
+        Ok([ctrl.data(), &urb.buffer()[..urb.actual_length()]].concat())
+    }
+
+    #[test]
+    fn test_record_replay() -> Result<()> {
+        let mock = MockDevice::new()
+            .with_configuration(1)
+            .with_data(0x81, [1, 2, 3]);
+        mock.push_response(0x81, MockResponse::Stall);
+
+        let recorder = Recorder::new(mock, Vec::new())?;
+        let recorded = run_session(&recorder)?;
+        assert_eq!(recorded, [1, 1, 2, 3]);
+
+        let (_, buf) = recorder.finish()?;
+        let session = Session::read(buf.as_slice())?;
+        assert_eq!(session.records().len(), 6);
+        assert_eq!(session.records()[4].status(), nix::libc::EPIPE);
+
+        let mut written = Vec::new();
+        session.write(&mut written)?;
+        assert_eq!(written, buf);
+
+        // same requests, same results
+        let replay = Replay::new(session.clone());
+        assert_eq!(run_session(&replay)?, recorded);
+        replay.verify()?;
+
+        // divergent requests are flagged
+        let replay = Replay::new(session);
+        assert!(matches!(
+            replay.claim_interface(1),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert_eq!(replay.divergences().len(), 1);
+        assert!(replay.verify().is_err());
+
+        Ok(())
+    }
+}
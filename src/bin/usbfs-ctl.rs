@@ -0,0 +1,585 @@
+//! Issues raw requests to a USB device from the shell.
+//!
+//! A device is selected by its IDs, bus and device numbers, or device node path, and a sequence
+//! of commands is run on it in order. Read data is printed as a hex dump, or as raw bytes.
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::{env, fmt, fs, process};
+
+use usbfs::{
+    DetachPolicy, DeviceFilter, Endpoint, Error, Result, Sysfs, TransferType, UsbDevice,
+    UsbfsCtrlTransfer,
+};
+
+const USAGE: &str = "Usage: usbfs-ctl [options] <command> [<command>...]
+Issue raw requests to a USB device
+
+Device selection:
+  -d, --device <vendor>:<product>  open the first device with the provided IDs
+  -s, --bus-dev <bus>:<devnum>     open the device with the provided bus and device numbers
+  -p, --path <path>                open the provided device node
+
+Options:
+  -t, --timeout <ms>               transfer timeout in milliseconds (default: 1000)
+  -r, --raw                        write read data to stdout as raw bytes, instead of a hex dump
+      --sysfs-root <path>          sysfs mount point (default: /sys)
+      --dev-root <path>            USB device node directory (default: /dev/bus/usb)
+  -h, --help                       print this help
+
+Commands:
+  control <type> <request> <value> <index> <length|data>
+                                   control transfer, IN requests read <length> bytes
+  bulk-read <ep> <length>          read from a bulk endpoint
+  bulk-write <ep> <data>           write to a bulk endpoint
+  intr-read <ep> <length>          read from an interrupt endpoint
+  intr-write <ep> <data>           write to an interrupt endpoint
+  claim <iface>                    claim an interface
+  claim-detach <iface>             claim an interface, detaching its kernel driver
+  release <iface>                  release an interface
+  detach <iface>                   detach the kernel driver of an interface
+  attach <iface>                   attach the kernel driver of an interface
+  set-config <value>               select the active configuration
+  set-alt <iface> <alt>            select the alternate setting of a claimed interface
+  clear-halt <ep>                  clear the halt condition of an endpoint
+  reset                            reset the device
+
+Numbers are decimal, or hexadecimal with a `0x` prefix. Data is a hex string (e.g. `01:02:ff`),
+`@<file>` to read a file, or `-` to read stdin.";
+
+/// Default transfer timeout, in milliseconds.
+const DEFAULT_TIMEOUT: u32 = 1000;
+
+/// Source of the data written by a command.
+#[derive(Clone, Debug, PartialEq)]
+enum Payload {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    Stdin,
+}
+
+impl Payload {
+    fn parse(arg: &str) -> std::result::Result<Self, String> {
+        match arg {
+            "-" => Ok(Self::Stdin),
+            _ => match arg.strip_prefix('@') {
+                Some(path) => Ok(Self::File(path.into())),
+                None => parse_hex(arg).map(Self::Bytes),
+            },
+        }
+    }
+
+    fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Bytes(data) => Ok(data.clone()),
+            Self::File(path) => Ok(fs::read(path)?),
+            Self::Stdin => {
+                let mut data = Vec::new();
+                io::stdin().read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// Represents a command run on the device.
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    ControlIn {
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        length: u16,
+    },
+    ControlOut {
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: Payload,
+    },
+    Read {
+        transfer_type: TransferType,
+        ep: u8,
+        length: usize,
+    },
+    Write {
+        transfer_type: TransferType,
+        ep: u8,
+        data: Payload,
+    },
+    Claim(u32),
+    ClaimDetach(u32),
+    Release(u32),
+    Detach(u32),
+    Attach(u32),
+    SetConfig(u8),
+    SetAlt(u32, u8),
+    ClearHalt(u8),
+    Reset,
+}
+
+impl Command {
+    /// Parses the next command, consuming its arguments.
+    fn parse<I: Iterator<Item = String>>(
+        name: &str,
+        args: &mut I,
+    ) -> std::result::Result<Self, String> {
+        let mut arg = || {
+            args.next()
+                .ok_or(format!("command `{name}` is missing arguments"))
+        };
+
+        match name {
+            "control" => {
+                let request_type = parse_num(&arg()?)?;
+                let request = parse_num(&arg()?)?;
+                let value = parse_num(&arg()?)?;
+                let index = parse_num(&arg()?)?;
+
+                if request_type & usbfs::descriptor::ENDPOINT_DIR_IN != 0 {
+                    Ok(Self::ControlIn {
+                        request_type,
+                        request,
+                        value,
+                        index,
+                        length: parse_num(&arg()?)?,
+                    })
+                } else {
+                    Ok(Self::ControlOut {
+                        request_type,
+                        request,
+                        value,
+                        index,
+                        data: Payload::parse(&arg()?)?,
+                    })
+                }
+            }
+            "bulk-read" | "intr-read" => Ok(Self::Read {
+                transfer_type: transfer_type(name),
+                ep: parse_num(&arg()?)?,
+                length: parse_num::<u32>(&arg()?)? as usize,
+            }),
+            "bulk-write" | "intr-write" => Ok(Self::Write {
+                transfer_type: transfer_type(name),
+                ep: parse_num(&arg()?)?,
+                data: Payload::parse(&arg()?)?,
+            }),
+            "claim" => Ok(Self::Claim(parse_num(&arg()?)?)),
+            "claim-detach" => Ok(Self::ClaimDetach(parse_num(&arg()?)?)),
+            "release" => Ok(Self::Release(parse_num(&arg()?)?)),
+            "detach" => Ok(Self::Detach(parse_num(&arg()?)?)),
+            "attach" => Ok(Self::Attach(parse_num(&arg()?)?)),
+            "set-config" => Ok(Self::SetConfig(parse_num(&arg()?)?)),
+            "set-alt" => Ok(Self::SetAlt(parse_num(&arg()?)?, parse_num(&arg()?)?)),
+            "clear-halt" => Ok(Self::ClearHalt(parse_num(&arg()?)?)),
+            "reset" => Ok(Self::Reset),
+            _ => Err(format!("unknown command: {name}")),
+        }
+    }
+
+    /// Runs the command, writing read data to `out`.
+    fn run(&self, dev: &mut UsbDevice, opts: &Options, out: &mut dyn Write) -> Result<()> {
+        match self {
+            Self::ControlIn {
+                request_type,
+                request,
+                value,
+                index,
+                length,
+            } => {
+                let mut ctrl = UsbfsCtrlTransfer::new()
+                    .with_request_type(*request_type)
+                    .with_request(*request)
+                    .with_value(*value)
+                    .with_index(*index)
+                    .with_timeout(opts.timeout)
+                    .with_data(vec![0u8; *length as usize]);
+                let len = dev.control_transfer(&mut ctrl)?;
+                opts.output(out, &ctrl.data()[..len.min(ctrl.data().len())])
+            }
+            Self::ControlOut {
+                request_type,
+                request,
+                value,
+                index,
+                data,
+            } => {
+                let mut ctrl = UsbfsCtrlTransfer::new()
+                    .with_request_type(*request_type)
+                    .with_request(*request)
+                    .with_value(*value)
+                    .with_index(*index)
+                    .with_timeout(opts.timeout)
+                    .with_data(data.read()?);
+                let len = dev.control_transfer(&mut ctrl)?;
+                eprintln!("control: wrote {len} bytes");
+                Ok(())
+            }
+            Self::Read {
+                transfer_type,
+                ep,
+                length,
+            } => {
+                check_endpoint(dev, *ep, *transfer_type, true)?;
+
+                let mut buf = vec![0u8; *length];
+                let len = dev.read_endpoint(*ep, &mut buf, opts.timeout)?;
+                opts.output(out, &buf[..len])
+            }
+            Self::Write {
+                transfer_type,
+                ep,
+                data,
+            } => {
+                check_endpoint(dev, *ep, *transfer_type, false)?;
+
+                let len = dev.write_endpoint(*ep, &data.read()?, opts.timeout)?;
+                eprintln!("endpoint 0x{ep:02x}: wrote {len} bytes");
+                Ok(())
+            }
+            Self::Claim(iface) => dev.claim_interface(*iface),
+            Self::ClaimDetach(iface) => {
+                dev.claim_interface_detaching(*iface, &DetachPolicy::Always)
+            }
+            Self::Release(iface) => dev.release_interface(*iface),
+            Self::Detach(iface) => dev.detach_kernel_driver(*iface),
+            Self::Attach(iface) => dev.attach_kernel_driver(*iface),
+            Self::SetConfig(config) => dev.set_configuration(*config),
+            Self::SetAlt(iface, alt) => dev.set_alt_setting(*iface, *alt),
+            Self::ClearHalt(ep) => dev.clear_halt(Endpoint::from(*ep)),
+            Self::Reset => {
+                let status = dev.reset_with(&opts.sysfs, std::time::Duration::from_secs(5))?;
+                eprintln!("reset: {}", <&str>::from(status));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ControlIn { .. } | Self::ControlOut { .. } => write!(f, "control"),
+            Self::Read {
+                transfer_type, ep, ..
+            } => write!(f, "{} read 0x{ep:02x}", <&str>::from(transfer_type)),
+            Self::Write {
+                transfer_type, ep, ..
+            } => write!(f, "{} write 0x{ep:02x}", <&str>::from(transfer_type)),
+            Self::Claim(iface) => write!(f, "claim {iface}"),
+            Self::ClaimDetach(iface) => write!(f, "claim-detach {iface}"),
+            Self::Release(iface) => write!(f, "release {iface}"),
+            Self::Detach(iface) => write!(f, "detach {iface}"),
+            Self::Attach(iface) => write!(f, "attach {iface}"),
+            Self::SetConfig(config) => write!(f, "set-config {config}"),
+            Self::SetAlt(iface, alt) => write!(f, "set-alt {iface} {alt}"),
+            Self::ClearHalt(ep) => write!(f, "clear-halt 0x{ep:02x}"),
+            Self::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+/// Selects the device to open.
+#[derive(Clone, Debug, Default, PartialEq)]
+enum Selector {
+    #[default]
+    None,
+    Ids(DeviceFilter),
+    BusDev(u8, u8),
+    Path(PathBuf),
+}
+
+/// Command line options.
+#[derive(Debug)]
+struct Options {
+    selector: Selector,
+    timeout: u32,
+    raw: bool,
+    sysfs: Sysfs,
+    commands: Vec<Command>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Self, String> {
+        let mut opts = Self {
+            selector: Selector::None,
+            timeout: DEFAULT_TIMEOUT,
+            raw: false,
+            sysfs: Sysfs::new(),
+            commands: Vec::new(),
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or(format!("option `{name}` requires an argument"))
+            };
+
+            match arg.as_str() {
+                "-d" | "--device" => {
+                    opts.selector = Selector::Ids(
+                        DeviceFilter::parse_id(&value(&arg)?).map_err(|e| e.to_string())?,
+                    )
+                }
+                "-s" | "--bus-dev" => {
+                    let bus_dev = value(&arg)?;
+                    let (bus, dev) = bus_dev
+                        .split_once(':')
+                        .ok_or(format!("invalid bus/device numbers: {bus_dev}"))?;
+                    opts.selector = Selector::BusDev(parse_num(bus)?, parse_num(dev)?);
+                }
+                "-p" | "--path" => opts.selector = Selector::Path(value(&arg)?.into()),
+                "-t" | "--timeout" => opts.timeout = parse_num(&value(&arg)?)?,
+                "-r" | "--raw" => opts.raw = true,
+                "--sysfs-root" => opts.sysfs.set_root(value(&arg)?),
+                "--dev-root" => opts.sysfs.set_dev_root(value(&arg)?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+                _ => opts.commands.push(Command::parse(&arg, &mut args)?),
+            }
+        }
+
+        if opts.selector == Selector::None {
+            Err("no device selected".into())
+        } else if opts.commands.is_empty() {
+            Err("no command provided".into())
+        } else {
+            Ok(opts)
+        }
+    }
+
+    fn open(&self) -> Result<UsbDevice> {
+        match &self.selector {
+            Selector::None => Err(Error::InvalidArgument("no device selected".into())),
+            Selector::Ids(filter) => filter.open(&self.sysfs),
+            Selector::BusDev(bus, dev) => {
+                let device = self
+                    .sysfs
+                    .device_by_bus_dev(*bus, *dev)?
+                    .ok_or(Error::NotFound(format!("bus {bus} device {dev}")))?;
+                UsbDevice::open(self.sysfs.devnode(&device))
+            }
+            Selector::Path(path) => UsbDevice::open(path),
+        }
+    }
+
+    fn output(&self, out: &mut dyn Write, data: &[u8]) -> Result<()> {
+        if self.raw {
+            out.write_all(data)?;
+        } else {
+            out.write_all(hex_dump(data).as_bytes())?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn transfer_type(command: &str) -> TransferType {
+    if command.starts_with("intr") {
+        TransferType::Interrupt
+    } else {
+        TransferType::Bulk
+    }
+}
+
+/// Checks the endpoint against the active configuration descriptors, when they are available.
+fn check_endpoint(dev: &UsbDevice, ep: u8, transfer_type: TransferType, is_in: bool) -> Result<()> {
+    let endpoint = Endpoint::from(ep);
+    if endpoint.is_in() != is_in {
+        return Err(Error::InvalidArgument(format!(
+            "endpoint 0x{ep:02x} has the wrong direction"
+        )));
+    }
+
+    let descriptor = dev.parsed_descriptors().ok().and_then(|descs| {
+        descs
+            .configs()
+            .iter()
+            .flat_map(|c| c.interfaces())
+            .flat_map(|i| i.endpoints())
+            .find(|e| e.address() == ep)
+            .cloned()
+    });
+
+    match descriptor {
+        Some(desc) if desc.transfer_type() != transfer_type => {
+            Err(Error::InvalidArgument(format!(
+                "endpoint 0x{ep:02x} is {}, not {}",
+                <&str>::from(desc.transfer_type()),
+                <&str>::from(transfer_type)
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parses a decimal, or `0x` prefixed hexadecimal number.
+fn parse_num<T: TryFrom<u64>>(arg: &str) -> std::result::Result<T, String> {
+    let val = match arg.strip_prefix("0x").or(arg.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => arg.parse::<u64>(),
+    };
+
+    val.ok()
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or(format!("invalid number: {arg}"))
+}
+
+/// Parses a hex string, ignoring whitespace and `:` separators.
+fn parse_hex(arg: &str) -> std::result::Result<Vec<u8>, String> {
+    let digits: Vec<u8> = arg
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace() && *b != b':')
+        .collect();
+
+    if digits.len() % 2 != 0 {
+        return Err(format!("odd number of hex digits: {arg}"));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or(format!("invalid hex data: {arg}"))
+        })
+        .collect()
+}
+
+/// Formats data as a hex dump, with offsets and printable characters.
+fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        out += &format!("{:08x}  {:<47}  |{ascii}|\n", i * 16, hex.join(" "));
+    }
+
+    out
+}
+
+fn main() {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("usbfs-ctl: {err}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    let mut dev = match opts.open() {
+        Ok(dev) => dev,
+        Err(err) => {
+            eprintln!("usbfs-ctl: unable to open device: {err}");
+            process::exit(1);
+        }
+    };
+
+    let mut stdout = io::stdout().lock();
+    for command in opts.commands.iter() {
+        if let Err(err) = command.run(&mut dev, &opts, &mut stdout) {
+            eprintln!("usbfs-ctl: {command}: {err}");
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> impl Iterator<Item = String> + '_ {
+        args.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let opts = Options::parse(args(
+            "-d 0483:5740 -t 0x100 claim 1 control 0xc0 6 0x0100 0 18 \
+             control 0x21 0x22 3 0 - bulk-write 0x01 01:02:ff intr-read 0x82 8 reset",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            opts.selector,
+            Selector::Ids(
+                DeviceFilter::new()
+                    .with_vendor_id(0x0483)
+                    .with_product_id(0x5740)
+            )
+        );
+        assert_eq!(opts.timeout, 256);
+        assert_eq!(
+            opts.commands,
+            [
+                Command::Claim(1),
+                Command::ControlIn {
+                    request_type: 0xc0,
+                    request: 6,
+                    value: 0x100,
+                    index: 0,
+                    length: 18
+                },
+                Command::ControlOut {
+                    request_type: 0x21,
+                    request: 0x22,
+                    value: 3,
+                    index: 0,
+                    data: Payload::Stdin
+                },
+                Command::Write {
+                    transfer_type: TransferType::Bulk,
+                    ep: 1,
+                    data: Payload::Bytes(vec![1, 2, 0xff])
+                },
+                Command::Read {
+                    transfer_type: TransferType::Interrupt,
+                    ep: 0x82,
+                    length: 8
+                },
+                Command::Reset,
+            ]
+        );
+
+        assert_eq!(
+            Options::parse(args("-s 1:4 set-alt 2 1")).unwrap().selector,
+            Selector::BusDev(1, 4)
+        );
+        assert!(Options::parse(args("claim 1")).is_err());
+        assert!(Options::parse(args("-p /dev/null")).is_err());
+        assert!(Options::parse(args("-p /dev/null claim")).is_err());
+        assert!(Options::parse(args("-p /dev/null set-config 256")).is_err());
+        assert!(Options::parse(args("-p /dev/null frobnicate")).is_err());
+
+        assert_eq!(
+            Payload::parse("@data.bin"),
+            Ok(Payload::File("data.bin".into()))
+        );
+        assert!(parse_hex("0g").is_err());
+        assert!(parse_hex("012").is_err());
+
+        assert_eq!(
+            hex_dump(b"0123456789abcdef\x00\xff"),
+            "00000000  30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+             00000010  00 ff                                            |..|\n"
+        );
+    }
+}
@@ -0,0 +1,557 @@
+//! Lists USB devices, in the style of `lsusb`.
+//!
+//! Devices are enumerated from `sysfs`, and each device node is opened read-only to read its
+//! descriptors, speed, capabilities and the kernel drivers bound to its interfaces.
+
+use std::{env, fs, process};
+
+use usbfs::descriptor::DescriptorIter;
+use usbfs::{
+    ConfigDescriptor, Descriptors, DeviceDescriptor, DeviceFilter, EndpointDescriptor,
+    InterfaceDescriptor, Result, Sysfs, SysfsDevice, TransferType, UsbDevice, UsbfsCap, UsbfsSpeed,
+};
+
+const USAGE: &str = "Usage: usbfs-ls [options]
+List USB devices
+
+Options:
+  -v, --verbose                 print the descriptors of every device
+  -j, --json                    print devices as JSON
+  -d, --device [vendor]:[product]
+                                only show devices with the provided IDs
+  -s, --bus-dev [bus]:[devnum]  only show devices with the provided bus and device numbers
+      --sysfs-root <path>       sysfs mount point (default: /sys)
+      --dev-root <path>         USB device node directory (default: /dev/bus/usb)
+  -h, --help                    print this help";
+
+/// Command line options.
+#[derive(Debug, Default)]
+struct Options {
+    verbose: bool,
+    json: bool,
+    filter: DeviceFilter,
+    bus_dev: (Option<u8>, Option<u8>),
+    sysfs: Sysfs,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Self, String> {
+        let mut opts = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or(format!("option `{name}` requires an argument"))
+            };
+
+            match arg.as_str() {
+                "-v" | "--verbose" => opts.verbose = true,
+                "-j" | "--json" => opts.json = true,
+                "-d" | "--device" => {
+                    opts.filter =
+                        DeviceFilter::parse_id(&value(&arg)?).map_err(|e| e.to_string())?
+                }
+                "-s" | "--bus-dev" => opts.bus_dev = parse_bus_dev(&value(&arg)?)?,
+                "--sysfs-root" => opts.sysfs.set_root(value(&arg)?),
+                "--dev-root" => opts.sysfs.set_dev_root(value(&arg)?),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+                _ => return Err(format!("unknown option: {arg}")),
+            }
+        }
+
+        Ok(opts)
+    }
+
+    fn is_filtered(&self) -> bool {
+        self.filter != DeviceFilter::new() || self.bus_dev != (None, None)
+    }
+
+    fn matches(&self, device: &SysfsDevice) -> bool {
+        self.filter.matches(device)
+            && self.bus_dev.0.map_or(true, |b| b == device.bus_num())
+            && self.bus_dev.1.map_or(true, |d| d == device.dev_num())
+    }
+}
+
+/// Parses a `[bus]:[devnum]` pair, or a lone device number.
+fn parse_bus_dev(arg: &str) -> std::result::Result<(Option<u8>, Option<u8>), String> {
+    let parse = |s: &str| match s {
+        "" => Ok(None),
+        _ => s
+            .parse::<u8>()
+            .map(Some)
+            .map_err(|_| format!("invalid bus/device number: {arg}")),
+    };
+
+    match arg.split_once(':') {
+        Some((bus, dev)) => Ok((parse(bus)?, parse(dev)?)),
+        None => Ok((None, parse(arg)?)),
+    }
+}
+
+/// Everything known about a listed device.
+#[derive(Debug)]
+struct DeviceInfo {
+    device: SysfsDevice,
+    descriptors: Option<Descriptors>,
+    speed: UsbfsSpeed,
+    capabilities: Option<u32>,
+    // interface number, and bound kernel driver
+    drivers: Vec<(u8, Option<String>)>,
+}
+
+impl DeviceInfo {
+    /// Probes the device node, falling back to `sysfs` for what cannot be queried.
+    fn probe(sysfs: &Sysfs, device: SysfsDevice) -> Self {
+        let opened = UsbDevice::open_read_only(sysfs.devnode(&device)).ok();
+
+        let descriptors = opened
+            .as_ref()
+            .and_then(|d| d.parsed_descriptors().ok())
+            .or_else(|| {
+                // sysfs exposes the same descriptors, for unreadable device nodes
+                fs::read(device.path().join("descriptors"))
+                    .ok()
+                    .and_then(|d| Descriptors::parse(&d).ok())
+            });
+
+        let speed = opened
+            .as_ref()
+            .and_then(|d| d.speed().ok())
+            .filter(|s| *s != UsbfsSpeed::Unknown)
+            .unwrap_or(device.speed());
+
+        let capabilities = opened.as_ref().and_then(|d| d.capabilities().ok());
+
+        let mut drivers: Vec<(u8, Option<String>)> = Vec::new();
+        for iface in device.interfaces() {
+            if drivers.iter().any(|(n, _)| *n == iface.number()) {
+                continue;
+            }
+            let driver = opened
+                .as_ref()
+                .and_then(|d| d.kernel_driver(iface.number().into()).ok())
+                .unwrap_or(iface.driver().map(String::from));
+
+            drivers.push((iface.number(), driver));
+        }
+        drivers.sort_by_key(|(n, _)| *n);
+
+        Self {
+            device,
+            descriptors,
+            speed,
+            capabilities,
+            drivers,
+        }
+    }
+
+    fn driver(&self, iface: u8) -> Option<&str> {
+        self.drivers
+            .iter()
+            .find(|(n, _)| *n == iface)
+            .and_then(|(_, d)| d.as_deref())
+    }
+
+    fn summary(&self) -> String {
+        let dev = &self.device;
+        let mut line = format!(
+            "Bus {:03} Device {:03}: ID {:04x}:{:04x}",
+            dev.bus_num(),
+            dev.dev_num(),
+            dev.vendor_id(),
+            dev.product_id()
+        );
+        for s in [dev.manufacturer(), dev.product()].into_iter().flatten() {
+            line.push(' ');
+            line.push_str(s);
+        }
+        line
+    }
+
+    fn print_verbose(&self) {
+        println!("{}", self.summary());
+
+        let Some(descs) = &self.descriptors else {
+            println!("Couldn't read descriptors");
+            println!();
+            return;
+        };
+
+        let dev = descs.device();
+        println!("Device Descriptor:");
+        field(2, "bLength", &DeviceDescriptor::LEN.to_string(), "");
+        field(2, "bDescriptorType", "1", "");
+        field(2, "bcdUSB", &bcd(dev.usb_version()), "");
+        field(
+            2,
+            "bDeviceClass",
+            &dev.class().to_string(),
+            class_name(dev.class()),
+        );
+        field(2, "bDeviceSubClass", &dev.subclass().to_string(), "");
+        field(2, "bDeviceProtocol", &dev.protocol().to_string(), "");
+        field(
+            2,
+            "bMaxPacketSize0",
+            &dev.max_packet_size0().to_string(),
+            "",
+        );
+        field(
+            2,
+            "idVendor",
+            &format!("0x{:04x}", dev.vendor_id()),
+            self.device.manufacturer().unwrap_or_default(),
+        );
+        field(
+            2,
+            "idProduct",
+            &format!("0x{:04x}", dev.product_id()),
+            self.device.product().unwrap_or_default(),
+        );
+        field(2, "bcdDevice", &bcd(dev.device_version()), "");
+        field(
+            2,
+            "iManufacturer",
+            &dev.manufacturer_index().to_string(),
+            self.device.manufacturer().unwrap_or_default(),
+        );
+        field(
+            2,
+            "iProduct",
+            &dev.product_index().to_string(),
+            self.device.product().unwrap_or_default(),
+        );
+        field(
+            2,
+            "iSerial",
+            &dev.serial_number_index().to_string(),
+            self.device.serial().unwrap_or_default(),
+        );
+        field(
+            2,
+            "bNumConfigurations",
+            &dev.num_configurations().to_string(),
+            "",
+        );
+
+        for config in descs.configs() {
+            self.print_config(config);
+        }
+
+        println!("Speed: {}", <&str>::from(self.speed));
+        match self.capabilities {
+            Some(caps) => println!(
+                "Capabilities: {}",
+                UsbfsCap::from_mask(caps)
+                    .iter()
+                    .map(<&str>::from)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => println!("Capabilities: unknown"),
+        }
+        println!();
+    }
+
+    fn print_config(&self, config: &ConfigDescriptor) {
+        // SuperSpeed devices report power in 8mA units
+        let power_unit = match self.speed {
+            UsbfsSpeed::Super | UsbfsSpeed::SuperPlus => 8,
+            _ => 2,
+        };
+
+        println!("  Configuration Descriptor:");
+        field(4, "bLength", &ConfigDescriptor::LEN.to_string(), "");
+        field(4, "bDescriptorType", "2", "");
+        field(
+            4,
+            "wTotalLength",
+            &format!("0x{:04x}", config.total_length()),
+            "",
+        );
+        field(
+            4,
+            "bNumInterfaces",
+            &config.num_interfaces().to_string(),
+            "",
+        );
+        field(
+            4,
+            "bConfigurationValue",
+            &config.configuration_value().to_string(),
+            "",
+        );
+        field(
+            4,
+            "iConfiguration",
+            &config.configuration_index().to_string(),
+            "",
+        );
+        field(
+            4,
+            "bmAttributes",
+            &format!("0x{:02x}", config.attributes()),
+            "",
+        );
+        if config.self_powered() {
+            println!("      Self Powered");
+        } else {
+            println!("      (Bus Powered)");
+        }
+        if config.remote_wakeup() {
+            println!("      Remote Wakeup");
+        }
+        field(
+            4,
+            "MaxPower",
+            &format!("{}mA", config.max_power() as u32 * power_unit),
+            "",
+        );
+        print_extra(4, config.extra());
+
+        for iface in config.interfaces() {
+            self.print_interface(iface);
+        }
+    }
+
+    fn print_interface(&self, iface: &InterfaceDescriptor) {
+        println!("    Interface Descriptor:");
+        field(6, "bLength", &InterfaceDescriptor::LEN.to_string(), "");
+        field(6, "bDescriptorType", "4", "");
+        field(6, "bInterfaceNumber", &iface.number().to_string(), "");
+        field(
+            6,
+            "bAlternateSetting",
+            &iface.alternate_setting().to_string(),
+            "",
+        );
+        field(6, "bNumEndpoints", &iface.num_endpoints().to_string(), "");
+        field(
+            6,
+            "bInterfaceClass",
+            &iface.class().to_string(),
+            class_name(iface.class()),
+        );
+        field(6, "bInterfaceSubClass", &iface.subclass().to_string(), "");
+        field(6, "bInterfaceProtocol", &iface.protocol().to_string(), "");
+        field(6, "iInterface", &iface.interface_index().to_string(), "");
+        if iface.alternate_setting() == 0 {
+            field(
+                6,
+                "Driver",
+                self.driver(iface.number()).unwrap_or("[none]"),
+                "",
+            );
+        }
+        print_extra(6, iface.extra());
+
+        for ep in iface.endpoints() {
+            print_endpoint(ep);
+        }
+    }
+
+    fn json(&self) -> String {
+        let dev = &self.device;
+        let mut out = String::from("{");
+
+        out += &format!(r#""bus_num": {}, "#, dev.bus_num());
+        out += &format!(r#""dev_num": {}, "#, dev.dev_num());
+        out += &format!(r#""port_path": {}, "#, json_str(Some(dev.port_path())));
+        out += &format!(r#""vendor_id": {}, "#, dev.vendor_id());
+        out += &format!(r#""product_id": {}, "#, dev.product_id());
+        out += &format!(r#""manufacturer": {}, "#, json_str(dev.manufacturer()));
+        out += &format!(r#""product": {}, "#, json_str(dev.product()));
+        out += &format!(r#""serial": {}, "#, json_str(dev.serial()));
+        out += &format!(r#""speed": {}, "#, self.speed);
+        out += r#""capabilities": "#;
+        match self.capabilities {
+            Some(caps) => {
+                let caps: Vec<String> = UsbfsCap::from_mask(caps)
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect();
+                out += &format!("[{}], ", caps.join(", "));
+            }
+            None => out += "null, ",
+        }
+        let drivers: Vec<String> = self
+            .drivers
+            .iter()
+            .map(|(n, d)| {
+                format!(
+                    r#"{{"interface": {n}, "driver": {}}}"#,
+                    json_str(d.as_deref())
+                )
+            })
+            .collect();
+        out += &format!(r#""drivers": [{}], "#, drivers.join(", "));
+        match &self.descriptors {
+            Some(descs) => out += &format!(r#""descriptors": {descs}"#),
+            None => out += r#""descriptors": null"#,
+        }
+        out += "}";
+
+        out
+    }
+}
+
+fn print_endpoint(ep: &EndpointDescriptor) {
+    let dir = if ep.is_in() { "IN" } else { "OUT" };
+
+    println!("      Endpoint Descriptor:");
+    field(8, "bLength", &EndpointDescriptor::LEN.to_string(), "");
+    field(8, "bDescriptorType", "5", "");
+    field(
+        8,
+        "bEndpointAddress",
+        &format!("0x{:02x}", ep.address()),
+        &format!(" EP {} {dir}", ep.number()),
+    );
+    field(8, "bmAttributes", &ep.attributes().to_string(), "");
+    println!(
+        "          Transfer Type            {}",
+        transfer_type_name(ep.transfer_type())
+    );
+    if ep.transfer_type() == TransferType::Isochronous {
+        let synch = ["None", "Asynchronous", "Adaptive", "Synchronous"];
+        let usage = ["Data", "Feedback", "Implicit feedback Data", "(reserved)"];
+        println!(
+            "          Synch Type               {}",
+            synch[((ep.attributes() >> 2) & 0x3) as usize]
+        );
+        println!(
+            "          Usage Type               {}",
+            usage[((ep.attributes() >> 4) & 0x3) as usize]
+        );
+    }
+    field(
+        8,
+        "wMaxPacketSize",
+        &format!("0x{:04x}", ep.max_packet_size()),
+        &format!(" {}x {} bytes", ep.transactions(), ep.packet_size()),
+    );
+    field(8, "bInterval", &ep.interval().to_string(), "");
+    print_extra(8, ep.extra());
+}
+
+/// Prints a descriptor field, aligning values like `lsusb -v`.
+fn field(indent: usize, name: &str, value: &str, desc: &str) {
+    let line = format!("{:indent$}{name:<19}{value:>6} {desc}", "");
+    println!("{}", line.trim_end());
+}
+
+/// Prints class-specific and unknown descriptors as hex dumps.
+fn print_extra(indent: usize, extra: &[u8]) {
+    for desc in DescriptorIter::new(extra) {
+        let hex: Vec<String> = desc.iter().map(|b| format!("{b:02x}")).collect();
+        println!("{:indent$}** UNRECOGNIZED: {}", "", hex.join(" "));
+    }
+}
+
+fn bcd(val: u16) -> String {
+    format!("{:x}.{:02x}", val >> 8, val & 0xff)
+}
+
+fn transfer_type_name(ty: TransferType) -> &'static str {
+    match ty {
+        TransferType::Control => "Control",
+        TransferType::Isochronous => "Isochronous",
+        TransferType::Bulk => "Bulk",
+        TransferType::Interrupt => "Interrupt",
+    }
+}
+
+/// Gets the name of a USB base class.
+fn class_name(class: u8) -> &'static str {
+    match class {
+        0x00 => "[unknown]",
+        0x01 => "Audio",
+        0x02 => "Communications",
+        0x03 => "Human Interface Device",
+        0x05 => "Physical Interface Device",
+        0x06 => "Imaging",
+        0x07 => "Printer",
+        0x08 => "Mass Storage",
+        0x09 => "Hub",
+        0x0a => "CDC Data",
+        0x0b => "Chip/SmartCard",
+        0x0d => "Content Security",
+        0x0e => "Video",
+        0x0f => "Personal Healthcare",
+        0x10 => "Audio/Video",
+        0x11 => "Billboard",
+        0xdc => "Diagnostic",
+        0xe0 => "Wireless",
+        0xef => "Miscellaneous Device",
+        0xfe => "Application Specific Interface",
+        0xff => "Vendor Specific Class",
+        _ => "",
+    }
+}
+
+/// Formats an optional string as a JSON string, or `null`.
+fn json_str(val: Option<&str>) -> String {
+    let Some(val) = val else {
+        return "null".into();
+    };
+
+    let mut out = String::from("\"");
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+fn run(opts: &Options) -> Result<bool> {
+    let devices: Vec<DeviceInfo> = opts
+        .sysfs
+        .devices()?
+        .into_iter()
+        .filter(|d| opts.matches(d))
+        .map(|d| DeviceInfo::probe(&opts.sysfs, d))
+        .collect();
+
+    if opts.json {
+        let devices: Vec<String> = devices.iter().map(DeviceInfo::json).collect();
+        println!("[{}]", devices.join(", "));
+    } else if opts.verbose {
+        devices.iter().for_each(DeviceInfo::print_verbose);
+    } else {
+        devices.iter().for_each(|d| println!("{}", d.summary()));
+    }
+
+    Ok(!devices.is_empty() || !opts.is_filtered())
+}
+
+fn main() {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("usbfs-ls: {err}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    match run(&opts) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("usbfs-ls: {err}");
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! USB class drivers built on a [UsbBackend].
+//!
+//! Each driver locates its interfaces in the active configuration, claims them (detaching any
+//! bound kernel driver), and implements the class requests with Control transfers. Drivers are
+//! generic over the backend, so they run on real devices through an
+//! [IoctlBackend](crate::IoctlBackend), and on a [MockDevice](crate::MockDevice) in tests.
+
+use nix::errno::Errno;
+
+use crate::descriptor::{
+    ConfigDescriptor, Descriptors, EndpointDescriptor, InterfaceDescriptor, DESCRIPTOR_TYPE_STRING,
+    ENDPOINT_DIR_IN,
+};
+use crate::{
+    DetachPolicy, Error, Result, TransferInfo, TransferType, Urb, UsbBackend, UsbfsBulkTransfer,
+    UsbfsCtrlTransfer, UsbfsIsoPacketDesc, URB_ISO_ASAP, URB_TYPE_ISO,
+};
+
+pub mod cdc;
+pub mod cdc_acm;
+pub mod cdc_ecm;
+pub mod cdc_ncm;
+pub mod dfu;
+pub mod hid;
+pub mod msc;
+pub mod printer;
+pub mod serial;
+pub mod uac;
+pub mod usbtmc;
+pub mod uvc;
+
+pub use cdc::{CdcNotification, CdcUnion};
+pub use cdc_acm::{CdcAcm, ControlLineState, LineCoding, Parity, SerialState, StopBits};
+pub use cdc_ecm::{CdcEcm, EthernetFunctional, LinkState, PacketFilter};
+pub use cdc_ncm::{CdcNcm, NcmFunctional, Ntb, NtbFormat, NtbParameters};
+pub use dfu::{
+    Dfu, DfuFile, DfuFunctional, DfuMode, DfuState, DfuStatus, DfuStatusCode, DfuSuffix,
+    DfuseImage, DfuseMemoryLayout,
+};
+pub use hid::{
+    Hid, HidCollection, HidDescriptor, HidItem, HidNode, HidProtocol, HidValue, ReportDescriptor,
+    ReportField, ReportType,
+};
+pub use msc::{
+    BulkOnly, Capacity, CommandBlockWrapper, CommandStatusWrapper, CswStatus, DataPhase,
+    InquiryData, Scsi, SenseData, SenseKey,
+};
+pub use printer::{DeviceId, PortStatus, Printer};
+pub use serial::{
+    BitMode, Ch34x, Cp210x, Ftdi, FtdiChip, ModemStatus, Pl2303, Pl2303Type, SerialPort,
+};
+pub use uac::{AudioControl, AudioFormat, RateMatcher, SampleRates, Uac, UacVersion};
+pub use usbtmc::{Usbtmc, UsbtmcCapabilities, UsbtmcNotification, UsbtmcStatus};
+pub use uvc::{ProbeCommit, Uvc, UvcRequest, VideoControl, VideoFrame, VideoStreaming};
+
+/// Default timeout of class driver transfers, in milliseconds.
+pub const DEFAULT_TIMEOUT: u32 = 1000;
+
+// bmRequestType of class requests to an interface
+pub(crate) const REQUEST_TYPE_CLASS_IN: u8 = 0xa1;
+pub(crate) const REQUEST_TYPE_CLASS_OUT: u8 = 0x21;
+
+// bmRequestType of vendor requests to the device, and to an interface
+pub(crate) const REQUEST_TYPE_VENDOR_IN: u8 = 0xc0;
+pub(crate) const REQUEST_TYPE_VENDOR_OUT: u8 = 0x40;
+pub(crate) const REQUEST_TYPE_VENDOR_IFACE_IN: u8 = 0xc1;
+pub(crate) const REQUEST_TYPE_VENDOR_IFACE_OUT: u8 = 0x41;
+
+const REQUEST_GET_CONFIGURATION: u8 = 0x08;
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+
+// largest Bulk transfer issued by a single read or write
+const MAX_TRANSFER_LEN: usize = 16 * 1024;
+
+/// Language ID of US English, the string descriptor language most devices provide.
+pub const LANG_ID_EN_US: u16 = 0x0409;
+
+/// Gets the active [ConfigDescriptor] of the device.
+///
+/// Falls back to the first configuration if the device does not answer `GET_CONFIGURATION`.
+pub fn active_config<B: UsbBackend>(backend: &B) -> Result<ConfigDescriptor> {
+    let descriptors = Descriptors::parse(&backend.descriptors()?)?;
+
+    let mut ctrl = UsbfsCtrlTransfer::new()
+        .with_request_type(ENDPOINT_DIR_IN)
+        .with_request(REQUEST_GET_CONFIGURATION)
+        .with_timeout(DEFAULT_TIMEOUT)
+        .with_data([0u8]);
+    let active = match backend.control(&mut ctrl) {
+        Ok(1) => descriptors.config(ctrl.data()[0]),
+        _ => None,
+    };
+
+    active
+        .or(descriptors.configs().first())
+        .cloned()
+        .ok_or(Error::NotFound("configuration descriptor".into()))
+}
+
+/// Gets a string descriptor, decoded from UTF-16.
+///
+/// Index `0` is reserved for the list of supported language IDs.
+pub fn string_descriptor<B: UsbBackend>(backend: &B, index: u8, lang_id: u16) -> Result<String> {
+    if index == 0 {
+        return Err(Error::InvalidArgument("string descriptor index 0".into()));
+    }
+
+    let mut ctrl = UsbfsCtrlTransfer::new()
+        .with_request_type(ENDPOINT_DIR_IN)
+        .with_request(REQUEST_GET_DESCRIPTOR)
+        .with_value(((DESCRIPTOR_TYPE_STRING as u16) << 8) | index as u16)
+        .with_index(lang_id)
+        .with_timeout(DEFAULT_TIMEOUT)
+        .with_data([0u8; 255]);
+    let len = backend.control(&mut ctrl)?.min(ctrl.data().len());
+    let buf = &ctrl.data()[..len];
+
+    if len < 2 || buf[0] < 2 || buf[1] != DESCRIPTOR_TYPE_STRING {
+        return Err(Error::InvalidDescriptor(format!(
+            "invalid string descriptor {index}, length: {len}"
+        )));
+    }
+
+    let units: Vec<u16> = buf[2..(buf[0] as usize).min(len)]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// Claims an interface, detaching any bound kernel driver.
+pub fn claim_detaching<B: UsbBackend>(backend: &B, iface: u8) -> Result<()> {
+    backend.disconnect_claim(&DetachPolicy::new().disconnect_claim(iface as u32))
+}
+
+/// Finds the first endpoint of an interface with the provided transfer type and direction.
+pub fn find_endpoint(
+    iface: &InterfaceDescriptor,
+    transfer_type: TransferType,
+    is_in: bool,
+) -> Option<&EndpointDescriptor> {
+    iface
+        .endpoints()
+        .iter()
+        .find(|ep| ep.transfer_type() == transfer_type && ep.is_in() == is_in)
+}
+
+/// Performs a class or vendor Control request, and returns the transferred length.
+///
+/// The direction is taken from `request_type`, IN requests read up to `data.len()` bytes.
+pub(crate) fn class_request<B: UsbBackend>(
+    backend: &B,
+    request_type: u8,
+    request: u8,
+    value: u16,
+    index: u16,
+    data: &mut [u8],
+    timeout: u32,
+) -> Result<usize> {
+    let mut ctrl = UsbfsCtrlTransfer::new()
+        .with_request_type(request_type)
+        .with_request(request)
+        .with_value(value)
+        .with_index(index)
+        .with_timeout(timeout)
+        .with_data(data.iter().copied());
+
+    let len = backend.control(&mut ctrl)?.min(data.len());
+    if request_type & ENDPOINT_DIR_IN != 0 {
+        data[..len].copy_from_slice(&ctrl.data()[..len]);
+    }
+
+    Ok(len)
+}
+
+/// Buffered reader of a Bulk IN endpoint.
+///
+/// Transfers request whole packets, as devices may not split a packet across transfers. The
+/// data that does not fit the caller's buffer is kept for the next read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct BulkReader {
+    ep: u8,
+    max_packet_size: usize,
+    rx: Vec<u8>,
+    rx_pos: usize,
+}
+
+impl BulkReader {
+    /// Creates a new [BulkReader] for the endpoint.
+    pub(crate) fn new(ep: &EndpointDescriptor) -> Self {
+        Self {
+            ep: ep.address(),
+            max_packet_size: (ep.max_packet_size() as usize).max(1),
+            rx: Vec::new(),
+            rx_pos: 0,
+        }
+    }
+
+    pub(crate) const fn endpoint(&self) -> u8 {
+        self.ep
+    }
+
+    pub(crate) const fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// Gets whether the receive buffer is empty.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rx_pos >= self.rx.len()
+    }
+
+    /// Reads whole packets from the endpoint, at least `len` bytes if available.
+    pub(crate) fn read_packets<B: UsbBackend>(
+        &self,
+        backend: &B,
+        len: usize,
+        timeout: u32,
+    ) -> Result<Vec<u8>> {
+        let len = len.next_multiple_of(self.max_packet_size);
+        let mut bulk = UsbfsBulkTransfer::create(
+            self.ep as u32,
+            timeout,
+            vec![0u8; len.clamp(self.max_packet_size, MAX_TRANSFER_LEN)],
+        );
+        let len = backend.bulk(&mut bulk)?;
+
+        let mut data = bulk.into_data();
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// Replaces the receive buffer.
+    pub(crate) fn fill(&mut self, data: Vec<u8>) {
+        self.rx = data;
+        self.rx_pos = 0;
+    }
+
+    /// Copies buffered data, and returns the copied length.
+    pub(crate) fn drain(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.rx.len().saturating_sub(self.rx_pos));
+        buf[..len].copy_from_slice(&self.rx[self.rx_pos..self.rx_pos + len]);
+        self.rx_pos += len;
+        len
+    }
+
+    /// Drops the buffered data.
+    pub(crate) fn clear(&mut self) {
+        self.fill(Vec::new());
+    }
+
+    /// Reads buffered data, refilling the buffer with one Bulk transfer when empty.
+    pub(crate) fn read<B: UsbBackend>(
+        &mut self,
+        backend: &B,
+        buf: &mut [u8],
+        timeout: u32,
+    ) -> Result<usize> {
+        if self.is_empty() {
+            let data = self.read_packets(backend, buf.len(), timeout)?;
+            self.fill(data);
+        }
+        Ok(self.drain(buf))
+    }
+}
+
+/// Writes to a Bulk OUT endpoint, up to the largest transfer, and returns the written length.
+pub(crate) fn bulk_write<B: UsbBackend>(
+    backend: &B,
+    ep: u8,
+    buf: &[u8],
+    timeout: u32,
+) -> Result<usize> {
+    let len = buf.len().min(MAX_TRANSFER_LEN);
+    let mut bulk = UsbfsBulkTransfer::create(ep as u32, timeout, buf[..len].iter().copied());
+    backend.bulk(&mut bulk)
+}
+
+/// Builds an Isochronous URB with one packet per length, laid out back to back in `buffer`.
+pub(crate) fn iso_urb(ep: u8, buffer: Vec<u8>, lens: &[usize]) -> Urb<'static> {
+    Urb::new()
+        .with_urb_type(URB_TYPE_ISO)
+        .with_endpoint(ep)
+        .with_flags(URB_ISO_ASAP)
+        .with_buffer(buffer)
+        .with_info(TransferInfo::create_isoc(lens.len() as i32))
+        .with_iso_frame_desc(
+            lens.iter()
+                .map(|len| UsbfsIsoPacketDesc::new().with_length(*len as u32)),
+        )
+}
+
+/// Gets the packets of a completed Isochronous URB, `None` for the packets lost.
+///
+/// Lost packets complete the URB with `-EXDEV`, and should not stop the stream: the URB only
+/// fails when it was cancelled, or the device is gone. Otherwise, the URB should be resubmitted.
+pub(crate) fn iso_packets<'u>(urb: &'u Urb<'_>) -> Result<Vec<Option<&'u [u8]>>> {
+    match Errno::from_i32(-urb.status()) {
+        Errno::ENODEV => return Err(Error::Disconnected),
+        err @ (Errno::ENOENT | Errno::ECONNRESET) => return Err(err.into()),
+        _ => (),
+    }
+
+    // packets start at their requested offsets, whatever length they received
+    let buf = urb.buffer();
+    let mut off = 0;
+    Ok(urb
+        .iso_frame_desc()
+        .iter()
+        .map(|packet| {
+            let start = off.min(buf.len());
+            let end = (off + packet.actual_length() as usize).min(buf.len());
+            off += packet.length() as usize;
+            (packet.status() == 0).then(|| &buf[start..end])
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    fn string_request(index: u8, data: Vec<u8>) -> MockControl {
+        MockControl::create(
+            ENDPOINT_DIR_IN,
+            REQUEST_GET_DESCRIPTOR,
+            ((DESCRIPTOR_TYPE_STRING as u16) << 8) | index as u16,
+            LANG_ID_EN_US,
+        )
+        .with_response(MockResponse::Data(data))
+    }
+
+    #[test]
+    fn test_string_descriptor() -> Result<()> {
+        let dev = MockDevice::new()
+            .with_control(string_request(1, vec![0x06, 0x03, b'o', 0, b'k', 0]))
+            .with_control(string_request(2, vec![0x01, 0x03, b'x', 0]))
+            .with_control(string_request(3, vec![0x00, 0x03]));
+
+        assert_eq!(string_descriptor(&dev, 1, LANG_ID_EN_US)?, "ok");
+        // bLength below the header length
+        assert!(string_descriptor(&dev, 2, LANG_ID_EN_US).is_err());
+        assert!(string_descriptor(&dev, 3, LANG_ID_EN_US).is_err());
+        assert!(string_descriptor(&dev, 0, LANG_ID_EN_US).is_err());
+
+        dev.verify()
+    }
+}
@@ -0,0 +1,196 @@
+//! Communications Device Class (CDC) descriptors and notifications.
+//!
+//! A CDC function pairs a communication interface, carrying the class requests and an optional
+//! notification endpoint, with a data interface. Its class-specific functional descriptors
+//! follow the communication interface descriptor.
+
+use std::fmt;
+
+use super::cdc_acm::SerialState;
+use crate::descriptor::{read_u16, ConfigDescriptor, DescriptorIter, InterfaceDescriptor};
+use crate::{Error, Result};
+
+pub const CDC_CLASS_COMM: u8 = 0x02;
+pub const CDC_CLASS_DATA: u8 = 0x0a;
+
+pub const CDC_SUBCLASS_ACM: u8 = 0x02;
+pub const CDC_SUBCLASS_ECM: u8 = 0x06;
+pub const CDC_SUBCLASS_NCM: u8 = 0x0d;
+
+/// Descriptor type of class-specific interface descriptors.
+pub const DESCRIPTOR_TYPE_CS_INTERFACE: u8 = 0x24;
+
+pub const CDC_FUNC_HEADER: u8 = 0x00;
+pub const CDC_FUNC_CALL_MANAGEMENT: u8 = 0x01;
+pub const CDC_FUNC_ACM: u8 = 0x02;
+pub const CDC_FUNC_UNION: u8 = 0x06;
+pub const CDC_FUNC_ETHERNET: u8 = 0x0f;
+pub const CDC_FUNC_NCM: u8 = 0x1a;
+
+pub const CDC_NOTIFY_NETWORK_CONNECTION: u8 = 0x00;
+pub const CDC_NOTIFY_RESPONSE_AVAILABLE: u8 = 0x01;
+pub const CDC_NOTIFY_SERIAL_STATE: u8 = 0x20;
+pub const CDC_NOTIFY_SPEED_CHANGE: u8 = 0x2a;
+
+/// Length of the notification header, preceding the notification data.
+pub const CDC_NOTIFICATION_HEADER_LEN: usize = 8;
+
+/// Gets an iterator over the CDC functional descriptors in class-specific descriptor bytes.
+pub fn functional_descriptors(extra: &[u8]) -> impl Iterator<Item = &[u8]> {
+    DescriptorIter::new(extra).filter(|d| d.len() >= 3 && d[1] == DESCRIPTOR_TYPE_CS_INTERFACE)
+}
+
+/// Finds the functional descriptor with the provided subtype.
+pub fn functional_descriptor(extra: &[u8], subtype: u8) -> Option<&[u8]> {
+    functional_descriptors(extra).find(|d| d[2] == subtype)
+}
+
+/// Represents the CDC union functional descriptor.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CdcUnion {
+    control: u8,
+    subordinates: Vec<u8>,
+}
+
+impl CdcUnion {
+    /// Creates a new [CdcUnion].
+    pub const fn new() -> Self {
+        Self {
+            control: 0,
+            subordinates: Vec::new(),
+        }
+    }
+
+    /// Parses the [CdcUnion] from the class-specific descriptors of a communication interface.
+    pub fn parse(extra: &[u8]) -> Option<Self> {
+        functional_descriptor(extra, CDC_FUNC_UNION)
+            .filter(|d| d.len() >= 5)
+            .map(|d| Self {
+                control: d[3],
+                subordinates: d[4..].into(),
+            })
+    }
+
+    /// Gets the controlling (communication) interface number.
+    pub const fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// Gets the subordinate interface numbers.
+    pub fn subordinates(&self) -> &[u8] {
+        self.subordinates.as_ref()
+    }
+}
+
+impl fmt::Display for CdcUnion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"control": {}, "subordinates": {:?}}}"#,
+            self.control, self.subordinates
+        )
+    }
+}
+
+/// Finds the communication interface of a CDC function, and the number of its data interface.
+///
+/// The data interface is taken from the union functional descriptor, or from the call
+/// management descriptor for devices omitting the union.
+pub fn find_function(
+    config: &ConfigDescriptor,
+    subclass: u8,
+) -> Result<(&InterfaceDescriptor, u8)> {
+    config
+        .interfaces()
+        .iter()
+        .filter(|i| {
+            i.class() == CDC_CLASS_COMM && i.subclass() == subclass && i.alternate_setting() == 0
+        })
+        .find_map(|comm| {
+            let data = CdcUnion::parse(comm.extra())
+                .and_then(|u| u.subordinates().first().copied())
+                .or_else(|| {
+                    functional_descriptor(comm.extra(), CDC_FUNC_CALL_MANAGEMENT)
+                        .filter(|d| d.len() >= 5)
+                        .map(|d| d[4])
+                })?;
+            Some((comm, data))
+        })
+        .ok_or(Error::NotFound(format!(
+            "CDC communication interface, subclass: 0x{subclass:02x}"
+        )))
+}
+
+/// Represents a notification from the CDC notification endpoint.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CdcNotification {
+    /// The network link went up or down.
+    NetworkConnection(bool),
+    /// An encapsulated response is ready to be read.
+    ResponseAvailable,
+    /// The serial line state changed.
+    SerialState(SerialState),
+    /// The link speed changed, in bits per second.
+    SpeedChange { downstream: u32, upstream: u32 },
+    /// A notification not handled by the crate.
+    Other { code: u8, value: u16, data: Vec<u8> },
+}
+
+impl CdcNotification {
+    /// Parses a [CdcNotification] from the bytes read on the notification endpoint.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < CDC_NOTIFICATION_HEADER_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "CDC notification too short: {}",
+                buf.len()
+            )));
+        }
+
+        let value = read_u16(buf, 2);
+        let len = (read_u16(buf, 6) as usize).min(buf.len() - CDC_NOTIFICATION_HEADER_LEN);
+        let data = &buf[CDC_NOTIFICATION_HEADER_LEN..CDC_NOTIFICATION_HEADER_LEN + len];
+
+        match buf[1] {
+            CDC_NOTIFY_NETWORK_CONNECTION => Ok(Self::NetworkConnection(value != 0)),
+            CDC_NOTIFY_RESPONSE_AVAILABLE => Ok(Self::ResponseAvailable),
+            CDC_NOTIFY_SERIAL_STATE if data.len() >= 2 => {
+                Ok(Self::SerialState(SerialState::create(read_u16(data, 0))))
+            }
+            CDC_NOTIFY_SPEED_CHANGE if data.len() >= 8 => Ok(Self::SpeedChange {
+                downstream: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                upstream: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            }),
+            CDC_NOTIFY_SERIAL_STATE | CDC_NOTIFY_SPEED_CHANGE => Err(Error::InvalidMessage(
+                format!("CDC notification 0x{:02x} data too short: {len}", buf[1]),
+            )),
+            code => Ok(Self::Other {
+                code,
+                value,
+                data: data.into(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for CdcNotification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NetworkConnection(up) => write!(f, r#"{{"network_connection": {up}}}"#),
+            Self::ResponseAvailable => write!(f, r#"{{"response_available": true}}"#),
+            Self::SerialState(state) => write!(f, r#"{{"serial_state": {state}}}"#),
+            Self::SpeedChange {
+                downstream,
+                upstream,
+            } => write!(
+                f,
+                r#"{{"speed_change": {{"downstream": {downstream}, "upstream": {upstream}}}}}"#
+            ),
+            Self::Other { code, value, data } => write!(
+                f,
+                r#"{{"code": {code}, "value": {value}, "data": {data:?}}}"#
+            ),
+        }
+    }
+}
@@ -0,0 +1,727 @@
+//! CDC Abstract Control Model (ACM) serial driver.
+//!
+//! Drives modems and virtual serial ports directly, in place of the `cdc_acm` kernel driver.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::cdc::{self, CdcNotification, CDC_SUBCLASS_ACM};
+use super::{class_request, BulkReader, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer, UsbfsSetInterface};
+
+pub const ACM_SET_LINE_CODING: u8 = 0x20;
+pub const ACM_GET_LINE_CODING: u8 = 0x21;
+pub const ACM_SET_CONTROL_LINE_STATE: u8 = 0x22;
+pub const ACM_SEND_BREAK: u8 = 0x23;
+
+/// Length of the line coding structure.
+pub const LINE_CODING_LEN: usize = 7;
+
+/// Represents the number of stop bits of a [LineCoding].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StopBits {
+    #[default]
+    One = 0,
+    OnePointFive = 1,
+    Two = 2,
+}
+
+impl StopBits {
+    /// Creates a new [StopBits].
+    pub const fn new() -> Self {
+        Self::One
+    }
+
+    /// Creates a new [StopBits] from its `bCharFormat` value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Self::One),
+            1 => Some(Self::OnePointFive),
+            2 => Some(Self::Two),
+            _ => None,
+        }
+    }
+
+    /// Gets the inner representation of the [StopBits].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&StopBits> for &'static str {
+    fn from(val: &StopBits) -> Self {
+        match val {
+            StopBits::One => "1",
+            StopBits::OnePointFive => "1.5",
+            StopBits::Two => "2",
+        }
+    }
+}
+
+impl fmt::Display for StopBits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the parity of a [LineCoding].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Parity {
+    #[default]
+    None = 0,
+    Odd = 1,
+    Even = 2,
+    Mark = 3,
+    Space = 4,
+}
+
+impl Parity {
+    /// Creates a new [Parity].
+    pub const fn new() -> Self {
+        Self::None
+    }
+
+    /// Creates a new [Parity] from its `bParityType` value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Self::None),
+            1 => Some(Self::Odd),
+            2 => Some(Self::Even),
+            3 => Some(Self::Mark),
+            4 => Some(Self::Space),
+            _ => None,
+        }
+    }
+
+    /// Gets the inner representation of the [Parity].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&Parity> for &'static str {
+    fn from(val: &Parity) -> Self {
+        match val {
+            Parity::None => "none",
+            Parity::Odd => "odd",
+            Parity::Even => "even",
+            Parity::Mark => "mark",
+            Parity::Space => "space",
+        }
+    }
+}
+
+impl fmt::Display for Parity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the ACM line coding: baud rate and character framing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineCoding {
+    baud_rate: u32,
+    stop_bits: StopBits,
+    parity: Parity,
+    data_bits: u8,
+}
+
+impl LineCoding {
+    /// Creates a new [LineCoding], for 9600 baud 8N1.
+    pub const fn new() -> Self {
+        Self::create(9600, StopBits::One, Parity::None, 8)
+    }
+
+    /// Creates a new [LineCoding] from the provided parameters.
+    pub const fn create(
+        baud_rate: u32,
+        stop_bits: StopBits,
+        parity: Parity,
+        data_bits: u8,
+    ) -> Self {
+        Self {
+            baud_rate,
+            stop_bits,
+            parity,
+            data_bits,
+        }
+    }
+
+    /// Parses a [LineCoding] from its wire representation.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < LINE_CODING_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "line coding too short: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            baud_rate: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            stop_bits: StopBits::create(buf[4]).ok_or(Error::InvalidMessage(format!(
+                "invalid stop bits: {}",
+                buf[4]
+            )))?,
+            parity: Parity::create(buf[5])
+                .ok_or(Error::InvalidMessage(format!("invalid parity: {}", buf[5])))?,
+            data_bits: buf[6],
+        })
+    }
+
+    /// Gets the wire representation of the [LineCoding].
+    pub fn to_bytes(&self) -> [u8; LINE_CODING_LEN] {
+        let rate = self.baud_rate.to_le_bytes();
+        [
+            rate[0],
+            rate[1],
+            rate[2],
+            rate[3],
+            self.stop_bits.inner(),
+            self.parity.inner(),
+            self.data_bits,
+        ]
+    }
+
+    /// Gets the baud rate.
+    pub const fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    /// Sets the baud rate.
+    pub fn set_baud_rate(&mut self, baud_rate: u32) {
+        self.baud_rate = baud_rate;
+    }
+
+    /// Builder function that sets the baud rate.
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.set_baud_rate(baud_rate);
+        self
+    }
+
+    /// Gets the [StopBits].
+    pub const fn stop_bits(&self) -> StopBits {
+        self.stop_bits
+    }
+
+    /// Sets the [StopBits].
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        self.stop_bits = stop_bits;
+    }
+
+    /// Builder function that sets the [StopBits].
+    pub fn with_stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.set_stop_bits(stop_bits);
+        self
+    }
+
+    /// Gets the [Parity].
+    pub const fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    /// Sets the [Parity].
+    pub fn set_parity(&mut self, parity: Parity) {
+        self.parity = parity;
+    }
+
+    /// Builder function that sets the [Parity].
+    pub fn with_parity(mut self, parity: Parity) -> Self {
+        self.set_parity(parity);
+        self
+    }
+
+    /// Gets the number of data bits: 5, 6, 7, 8 or 16.
+    pub const fn data_bits(&self) -> u8 {
+        self.data_bits
+    }
+
+    /// Sets the number of data bits.
+    pub fn set_data_bits(&mut self, data_bits: u8) {
+        self.data_bits = data_bits;
+    }
+
+    /// Builder function that sets the number of data bits.
+    pub fn with_data_bits(mut self, data_bits: u8) -> Self {
+        self.set_data_bits(data_bits);
+        self
+    }
+}
+
+impl Default for LineCoding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for LineCoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""baud_rate": {}, "#, self.baud_rate)?;
+        write!(f, r#""stop_bits": {}, "#, self.stop_bits)?;
+        write!(f, r#""parity": {}, "#, self.parity)?;
+        write!(f, r#""data_bits": {}"#, self.data_bits)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the modem control lines driven by the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ControlLineState {
+    dtr: bool,
+    rts: bool,
+}
+
+impl ControlLineState {
+    /// Creates a new [ControlLineState], with both lines deasserted.
+    pub const fn new() -> Self {
+        Self {
+            dtr: false,
+            rts: false,
+        }
+    }
+
+    /// Creates a new [ControlLineState] from the provided parameters.
+    pub const fn create(dtr: bool, rts: bool) -> Self {
+        Self { dtr, rts }
+    }
+
+    /// Gets whether Data Terminal Ready is asserted.
+    pub const fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    /// Builder function that sets Data Terminal Ready.
+    pub fn with_dtr(mut self, dtr: bool) -> Self {
+        self.dtr = dtr;
+        self
+    }
+
+    /// Gets whether Request To Send is asserted.
+    pub const fn rts(&self) -> bool {
+        self.rts
+    }
+
+    /// Builder function that sets Request To Send.
+    pub fn with_rts(mut self, rts: bool) -> Self {
+        self.rts = rts;
+        self
+    }
+
+    /// Gets the `wValue` bitmap of `SET_CONTROL_LINE_STATE`.
+    pub const fn bits(&self) -> u16 {
+        (self.dtr as u16) | ((self.rts as u16) << 1)
+    }
+}
+
+impl fmt::Display for ControlLineState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#"{{"dtr": {}, "rts": {}}}"#, self.dtr, self.rts)
+    }
+}
+
+/// Represents the `SERIAL_STATE` notification bitmap.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SerialState(u16);
+
+impl SerialState {
+    const DCD: u16 = 1 << 0;
+    const DSR: u16 = 1 << 1;
+    const BREAK: u16 = 1 << 2;
+    const RING: u16 = 1 << 3;
+    const FRAMING: u16 = 1 << 4;
+    const PARITY: u16 = 1 << 5;
+    const OVERRUN: u16 = 1 << 6;
+
+    /// Creates a new [SerialState].
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [SerialState] from its bitmap.
+    pub const fn create(val: u16) -> Self {
+        Self(val)
+    }
+
+    /// Gets the inner bitmap of the [SerialState].
+    pub const fn inner(&self) -> u16 {
+        self.0
+    }
+
+    /// Gets whether Data Carrier Detect is asserted.
+    pub const fn dcd(&self) -> bool {
+        self.0 & Self::DCD != 0
+    }
+
+    /// Gets whether Data Set Ready is asserted.
+    pub const fn dsr(&self) -> bool {
+        self.0 & Self::DSR != 0
+    }
+
+    /// Gets whether a break was detected.
+    pub const fn break_detected(&self) -> bool {
+        self.0 & Self::BREAK != 0
+    }
+
+    /// Gets whether the ring signal is asserted.
+    pub const fn ring(&self) -> bool {
+        self.0 & Self::RING != 0
+    }
+
+    /// Gets whether a framing error occurred.
+    pub const fn framing_error(&self) -> bool {
+        self.0 & Self::FRAMING != 0
+    }
+
+    /// Gets whether a parity error occurred.
+    pub const fn parity_error(&self) -> bool {
+        self.0 & Self::PARITY != 0
+    }
+
+    /// Gets whether received data was lost.
+    pub const fn overrun(&self) -> bool {
+        self.0 & Self::OVERRUN != 0
+    }
+}
+
+impl fmt::Display for SerialState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""dcd": {}, "#, self.dcd())?;
+        write!(f, r#""dsr": {}, "#, self.dsr())?;
+        write!(f, r#""break": {}, "#, self.break_detected())?;
+        write!(f, r#""ring": {}, "#, self.ring())?;
+        write!(f, r#""framing_error": {}, "#, self.framing_error())?;
+        write!(f, r#""parity_error": {}, "#, self.parity_error())?;
+        write!(f, r#""overrun": {}"#, self.overrun())?;
+        write!(f, "}}")
+    }
+}
+
+/// CDC-ACM serial port over a [UsbBackend].
+///
+/// Implements [Read] and [Write] over the Bulk endpoints of the data interface.
+pub struct CdcAcm<B: UsbBackend> {
+    backend: B,
+    comm_iface: u8,
+    data_iface: u8,
+    notify_ep: Option<u8>,
+    reader: BulkReader,
+    bulk_out: u8,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> CdcAcm<B> {
+    /// Opens the first ACM function of the active configuration.
+    ///
+    /// Claims the communication and data interfaces, detaching the `cdc_acm` kernel driver, and
+    /// selects the data alternate setting with the Bulk endpoints.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let (comm, data_iface) = cdc::find_function(&config, CDC_SUBCLASS_ACM)?;
+        let notify_ep =
+            super::find_endpoint(comm, TransferType::Interrupt, true).map(|e| e.address());
+
+        let data = config
+            .alt_settings(data_iface)
+            .find(|i| i.endpoints().len() >= 2)
+            .ok_or(Error::NotFound(format!("CDC data interface {data_iface}")))?;
+        let bulk_in = super::find_endpoint(data, TransferType::Bulk, true)
+            .ok_or(Error::NotFound("CDC Bulk IN endpoint".into()))?;
+        let bulk_out = super::find_endpoint(data, TransferType::Bulk, false)
+            .ok_or(Error::NotFound("CDC Bulk OUT endpoint".into()))?;
+
+        let acm = Self {
+            comm_iface: comm.number(),
+            data_iface,
+            notify_ep,
+            reader: BulkReader::new(bulk_in),
+            bulk_out: bulk_out.address(),
+            timeout: super::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        super::claim_detaching(&acm.backend, acm.comm_iface)?;
+        if let Err(err) = acm.claim_data_interface(data.alternate_setting()) {
+            acm.backend.release_interface(acm.comm_iface as u32).ok();
+            return Err(err);
+        }
+
+        Ok(acm)
+    }
+
+    /// Claims the data interface, and selects the alternate setting.
+    fn claim_data_interface(&self, alt_setting: u8) -> Result<()> {
+        let shared = self.data_iface == self.comm_iface;
+        if !shared {
+            super::claim_detaching(&self.backend, self.data_iface)?;
+        }
+
+        if alt_setting != 0 {
+            let res = self.backend.set_interface(&UsbfsSetInterface::create(
+                self.data_iface as u32,
+                alt_setting as u32,
+            ));
+            if res.is_err() && !shared {
+                self.backend.release_interface(self.data_iface as u32).ok();
+            }
+            res?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the communication interface number.
+    pub const fn comm_interface(&self) -> u8 {
+        self.comm_iface
+    }
+
+    /// Gets the data interface number.
+    pub const fn data_interface(&self) -> u8 {
+        self.data_iface
+    }
+
+    /// Gets the Interrupt IN notification endpoint, if any.
+    pub const fn notify_endpoint(&self) -> Option<u8> {
+        self.notify_ep
+    }
+
+    /// Gets the Bulk IN endpoint.
+    pub const fn bulk_in(&self) -> u8 {
+        self.reader.endpoint()
+    }
+
+    /// Gets the Bulk OUT endpoint.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the [LineCoding] with `SET_LINE_CODING`.
+    pub fn set_line_coding(&self, coding: &LineCoding) -> Result<()> {
+        self.request_out(ACM_SET_LINE_CODING, 0, &mut coding.to_bytes())
+    }
+
+    /// Gets the [LineCoding] with `GET_LINE_CODING`.
+    pub fn line_coding(&self) -> Result<LineCoding> {
+        let mut buf = [0u8; LINE_CODING_LEN];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            ACM_GET_LINE_CODING,
+            0,
+            self.comm_iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        LineCoding::parse(&buf[..len])
+    }
+
+    /// Sets the modem control lines with `SET_CONTROL_LINE_STATE`.
+    pub fn set_control_line_state(&self, state: ControlLineState) -> Result<()> {
+        self.request_out(ACM_SET_CONTROL_LINE_STATE, state.bits(), &mut [])
+    }
+
+    /// Sends a break with `SEND_BREAK`, for a duration in milliseconds.
+    ///
+    /// A duration of `0xffff` holds the break until another `SEND_BREAK` of `0`.
+    pub fn send_break(&self, duration: u16) -> Result<()> {
+        self.request_out(ACM_SEND_BREAK, duration, &mut [])
+    }
+
+    /// Waits for the next [CdcNotification] on the notification endpoint.
+    pub fn read_notification(&self, timeout: u32) -> Result<CdcNotification> {
+        let ep = self
+            .notify_ep
+            .ok_or(Error::NotFound("CDC notification endpoint".into()))?;
+        let mut int = UsbfsBulkTransfer::create(ep as u32, timeout, [0u8; 64]);
+        let len = self.backend.bulk(&mut int)?;
+        CdcNotification::parse(&int.data()[..len])
+    }
+
+    /// Releases the interfaces, and converts the [CdcAcm] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        if self.data_iface != self.comm_iface {
+            self.backend.release_interface(self.data_iface as u32)?;
+        }
+        self.backend.release_interface(self.comm_iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn request_out(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            request,
+            value,
+            self.comm_iface as u16,
+            data,
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+}
+
+impl<B: UsbBackend> Read for CdcAcm<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.reader.read(&self.backend, buf, self.timeout)?)
+    }
+}
+
+impl<B: UsbBackend> Write for CdcAcm<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(super::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for CdcAcm<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CdcAcm")
+            .field("comm_iface", &self.comm_iface)
+            .field("data_iface", &self.data_iface)
+            .field("notify_ep", &self.notify_ep)
+            .field("bulk_in", &self.reader.endpoint())
+            .field("bulk_out", &self.bulk_out)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // ACM function: communication interface 0 with header, call management, ACM and union
+    // functional descriptors, and data interface 1 with a pair of Bulk endpoints
+    const DESCRIPTORS: [u8; 85] = [
+        0x12, 0x01, 0x00, 0x02, 0x02, 0x00, 0x00, 0x40, 0x25, 0x05, 0xa7, 0xa4, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x43, 0x00, 0x02, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x02, 0x02, 0x01, 0x00, //
+        0x05, 0x24, 0x00, 0x10, 0x01, //
+        0x05, 0x24, 0x01, 0x00, 0x01, //
+        0x04, 0x24, 0x02, 0x02, //
+        0x05, 0x24, 0x06, 0x00, 0x01, //
+        0x07, 0x05, 0x83, 0x03, 0x10, 0x00, 0x10, //
+        0x09, 0x04, 0x01, 0x00, 0x02, 0x0a, 0x00, 0x00, 0x00, //
+        0x07, 0x05, 0x02, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_cdc_acm() -> Result<()> {
+        let coding = LineCoding::new().with_baud_rate(115_200);
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "cdc_acm")
+            .with_driver(1, "cdc_acm")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, ACM_SET_LINE_CODING, 0, 0)
+                    .with_data(coding.to_bytes()),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, ACM_GET_LINE_CODING, 0, 0)
+                    .with_response(MockResponse::Data(coding.to_bytes().into())),
+            )
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                ACM_SET_CONTROL_LINE_STATE,
+                0x3,
+                0,
+            ))
+            .with_data(0x81, *b"AT\r\nOK\r\n")
+            .with_data(0x83, [0xa1, 0x20, 0, 0, 0, 0, 2, 0, 0x03, 0x00]);
+
+        let mut acm = CdcAcm::open(dev)?;
+        assert_eq!(acm.comm_interface(), 0);
+        assert_eq!(acm.data_interface(), 1);
+        assert_eq!(acm.notify_endpoint(), Some(0x83));
+        assert_eq!(acm.backend().claimed_interfaces(), [0, 1]);
+
+        acm.set_line_coding(&coding)?;
+        assert_eq!(acm.line_coding()?, coding);
+        acm.set_control_line_state(ControlLineState::create(true, true))?;
+
+        let state = match acm.read_notification(100)? {
+            CdcNotification::SerialState(state) => state,
+            notification => panic!("unexpected notification: {notification}"),
+        };
+        assert!(state.dcd() && state.dsr() && !state.ring());
+
+        // buffered reads keep the remainder of the packet
+        let mut buf = [0u8; 4];
+        acm.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"AT\r\n");
+        acm.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"OK\r\n");
+        assert_eq!(
+            acm.read(&mut buf).map_err(|e| e.kind()),
+            Err(io::ErrorKind::TimedOut)
+        );
+
+        acm.write_all(b"ATZ\r")?;
+        assert_eq!(acm.backend().take_written(0x02), [b"ATZ\r".to_vec()]);
+
+        let dev = acm.close()?;
+        assert!(dev.claimed_interfaces().is_empty());
+        dev.verify()
+    }
+
+    #[test]
+    fn test_cdc_acm_data_alt_setting() -> Result<()> {
+        // the data interface has no endpoints in its default setting, and a pair of Bulk
+        // endpoints in alternate setting 1
+        let mut descriptors = DESCRIPTORS[..62].to_vec();
+        descriptors[20] += 9;
+        descriptors.extend_from_slice(&[0x09, 0x04, 0x01, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00]);
+        descriptors.extend_from_slice(&[0x09, 0x04, 0x01, 0x01, 0x02, 0x0a, 0x00, 0x00, 0x00]);
+        descriptors.extend_from_slice(&DESCRIPTORS[71..]);
+
+        let dev = MockDevice::new()
+            .with_descriptors(descriptors)
+            .with_configuration(1);
+
+        let acm = CdcAcm::open(dev)?;
+        assert_eq!((acm.bulk_in(), acm.bulk_out()), (0x81, 0x02));
+        assert_eq!(acm.backend().alt_setting(1), 1);
+
+        acm.close()?.verify()
+    }
+}
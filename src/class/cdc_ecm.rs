@@ -0,0 +1,710 @@
+//! CDC Ethernet Control Model (ECM) network driver.
+//!
+//! Each Ethernet frame travels in its own Bulk transfer on the data interface, ended by a short
+//! or zero-length packet. The data interface has no endpoints in its default alternate setting,
+//! selecting the second one starts the network traffic.
+
+use std::fmt;
+
+use super::cdc::{self, CdcNotification, CDC_FUNC_ETHERNET, CDC_SUBCLASS_ECM};
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::descriptor::{read_u16, ConfigDescriptor};
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer, UsbfsSetInterface};
+
+pub const ECM_SET_ETHERNET_MULTICAST_FILTERS: u8 = 0x40;
+pub const ECM_SET_ETHERNET_PACKET_FILTER: u8 = 0x43;
+pub const ECM_GET_ETHERNET_STATISTIC: u8 = 0x44;
+
+/// Length of the Ethernet networking functional descriptor.
+pub const ETHERNET_FUNCTIONAL_LEN: usize = 13;
+
+/// Length of a MAC address.
+pub const MAC_ADDRESS_LEN: usize = 6;
+
+/// Largest Ethernet frame without the FCS, used when the device reports none.
+pub const ETHERNET_MAX_SEGMENT_SIZE: u16 = 1514;
+
+/// Represents the Ethernet networking functional descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EthernetFunctional {
+    mac_address_index: u8,
+    statistics: u32,
+    max_segment_size: u16,
+    multicast_filters: u16,
+    power_filters: u8,
+}
+
+impl EthernetFunctional {
+    /// Creates a new [EthernetFunctional].
+    pub const fn new() -> Self {
+        Self {
+            mac_address_index: 0,
+            statistics: 0,
+            max_segment_size: ETHERNET_MAX_SEGMENT_SIZE,
+            multicast_filters: 0,
+            power_filters: 0,
+        }
+    }
+
+    /// Parses the [EthernetFunctional] from the class-specific descriptors of a communication
+    /// interface.
+    pub fn parse(extra: &[u8]) -> Option<Self> {
+        cdc::functional_descriptor(extra, CDC_FUNC_ETHERNET)
+            .filter(|d| d.len() >= ETHERNET_FUNCTIONAL_LEN)
+            .map(|d| Self {
+                mac_address_index: d[3],
+                statistics: u32::from_le_bytes([d[4], d[5], d[6], d[7]]),
+                max_segment_size: read_u16(d, 8),
+                multicast_filters: read_u16(d, 10),
+                power_filters: d[12],
+            })
+    }
+
+    /// Gets the index of the string descriptor holding the MAC address.
+    pub const fn mac_address_index(&self) -> u8 {
+        self.mac_address_index
+    }
+
+    /// Gets the bitmap of the Ethernet statistics collected by the device.
+    pub const fn statistics(&self) -> u32 {
+        self.statistics
+    }
+
+    /// Gets the largest Ethernet frame the device handles, without the FCS.
+    pub const fn max_segment_size(&self) -> u16 {
+        self.max_segment_size
+    }
+
+    /// Gets the number of multicast filters, bit 15 set for imperfect filtering.
+    pub const fn multicast_filters(&self) -> u16 {
+        self.multicast_filters
+    }
+
+    /// Gets the number of wake-up pattern filters.
+    pub const fn power_filters(&self) -> u8 {
+        self.power_filters
+    }
+}
+
+impl fmt::Display for EthernetFunctional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""mac_address_index": {}, "#, self.mac_address_index)?;
+        write!(f, r#""statistics": {}, "#, self.statistics)?;
+        write!(f, r#""max_segment_size": {}, "#, self.max_segment_size)?;
+        write!(f, r#""multicast_filters": {}, "#, self.multicast_filters)?;
+        write!(f, r#""power_filters": {}"#, self.power_filters)?;
+        write!(f, "}}")
+    }
+}
+
+/// Parses a MAC address from its string descriptor form: 12 hexadecimal digits.
+pub fn parse_mac_address(s: &str) -> Result<[u8; MAC_ADDRESS_LEN]> {
+    let s = s.trim();
+    if s.len() != MAC_ADDRESS_LEN * 2 || !s.is_ascii() {
+        return Err(Error::InvalidMessage(format!("invalid MAC address: {s}")));
+    }
+
+    let mut mac = [0u8; MAC_ADDRESS_LEN];
+    for (i, b) in mac.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+            .map_err(|_| Error::InvalidMessage(format!("invalid MAC address: {s}")))?;
+    }
+
+    Ok(mac)
+}
+
+/// Represents the `SET_ETHERNET_PACKET_FILTER` bitmap.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketFilter(u16);
+
+impl PacketFilter {
+    const PROMISCUOUS: u16 = 1 << 0;
+    const ALL_MULTICAST: u16 = 1 << 1;
+    const DIRECTED: u16 = 1 << 2;
+    const BROADCAST: u16 = 1 << 3;
+    const MULTICAST: u16 = 1 << 4;
+
+    /// Creates a new [PacketFilter], forwarding no frames.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [PacketFilter] from its bitmap.
+    pub const fn create(val: u16) -> Self {
+        Self(val)
+    }
+
+    /// Creates the [PacketFilter] of a regular host: directed, broadcast and multicast frames.
+    pub const fn host() -> Self {
+        Self(Self::DIRECTED | Self::BROADCAST | Self::ALL_MULTICAST)
+    }
+
+    /// Gets the inner bitmap of the [PacketFilter].
+    pub const fn inner(&self) -> u16 {
+        self.0
+    }
+
+    /// Gets whether all frames are forwarded.
+    pub const fn promiscuous(&self) -> bool {
+        self.0 & Self::PROMISCUOUS != 0
+    }
+
+    /// Builder function that sets whether all frames are forwarded.
+    pub fn with_promiscuous(self, val: bool) -> Self {
+        self.with_bit(Self::PROMISCUOUS, val)
+    }
+
+    /// Gets whether all multicast frames are forwarded.
+    pub const fn all_multicast(&self) -> bool {
+        self.0 & Self::ALL_MULTICAST != 0
+    }
+
+    /// Builder function that sets whether all multicast frames are forwarded.
+    pub fn with_all_multicast(self, val: bool) -> Self {
+        self.with_bit(Self::ALL_MULTICAST, val)
+    }
+
+    /// Gets whether frames to the device MAC address are forwarded.
+    pub const fn directed(&self) -> bool {
+        self.0 & Self::DIRECTED != 0
+    }
+
+    /// Builder function that sets whether frames to the device MAC address are forwarded.
+    pub fn with_directed(self, val: bool) -> Self {
+        self.with_bit(Self::DIRECTED, val)
+    }
+
+    /// Gets whether broadcast frames are forwarded.
+    pub const fn broadcast(&self) -> bool {
+        self.0 & Self::BROADCAST != 0
+    }
+
+    /// Builder function that sets whether broadcast frames are forwarded.
+    pub fn with_broadcast(self, val: bool) -> Self {
+        self.with_bit(Self::BROADCAST, val)
+    }
+
+    /// Gets whether multicast frames matching the multicast filters are forwarded.
+    pub const fn multicast(&self) -> bool {
+        self.0 & Self::MULTICAST != 0
+    }
+
+    /// Builder function that sets whether multicast frames matching the filters are forwarded.
+    pub fn with_multicast(self, val: bool) -> Self {
+        self.with_bit(Self::MULTICAST, val)
+    }
+
+    fn with_bit(mut self, bit: u16, val: bool) -> Self {
+        if val {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+        self
+    }
+}
+
+impl fmt::Display for PacketFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""promiscuous": {}, "#, self.promiscuous())?;
+        write!(f, r#""all_multicast": {}, "#, self.all_multicast())?;
+        write!(f, r#""directed": {}, "#, self.directed())?;
+        write!(f, r#""broadcast": {}, "#, self.broadcast())?;
+        write!(f, r#""multicast": {}"#, self.multicast())?;
+        write!(f, "}}")
+    }
+}
+
+/// Interfaces and endpoints of a CDC networking function, shared by ECM and NCM.
+#[derive(Clone, Debug)]
+pub(crate) struct EthernetFunction {
+    pub comm_iface: u8,
+    pub data_iface: u8,
+    pub data_alt: u8,
+    pub notify_ep: Option<u8>,
+    pub bulk_in: u8,
+    pub bulk_out: u8,
+    pub max_packet_size: usize,
+    pub functional: EthernetFunctional,
+}
+
+impl EthernetFunction {
+    /// Finds the networking function with the provided subclass.
+    pub fn find(config: &ConfigDescriptor, subclass: u8) -> Result<(Self, Vec<u8>)> {
+        let (comm, data_iface) = cdc::find_function(config, subclass)?;
+        let functional = EthernetFunctional::parse(comm.extra()).ok_or(Error::NotFound(
+            "CDC Ethernet networking functional descriptor".into(),
+        ))?;
+        let notify_ep =
+            super::find_endpoint(comm, TransferType::Interrupt, true).map(|e| e.address());
+
+        // the alternate setting carrying the traffic, the default one has no endpoints
+        let data = config
+            .alt_settings(data_iface)
+            .find(|i| i.endpoints().len() >= 2)
+            .ok_or(Error::NotFound(format!("CDC data interface {data_iface}")))?;
+        let bulk_in = super::find_endpoint(data, TransferType::Bulk, true)
+            .ok_or(Error::NotFound("CDC Bulk IN endpoint".into()))?;
+        let bulk_out = super::find_endpoint(data, TransferType::Bulk, false)
+            .ok_or(Error::NotFound("CDC Bulk OUT endpoint".into()))?;
+
+        Ok((
+            Self {
+                comm_iface: comm.number(),
+                data_iface,
+                data_alt: data.alternate_setting(),
+                notify_ep,
+                bulk_in: bulk_in.address(),
+                bulk_out: bulk_out.address(),
+                max_packet_size: (bulk_in.max_packet_size() as usize).max(1),
+                functional,
+            },
+            comm.extra().to_vec(),
+        ))
+    }
+
+    /// Claims the communication and data interfaces, detaching the kernel driver.
+    pub fn claim<B: UsbBackend>(&self, backend: &B) -> Result<()> {
+        super::claim_detaching(backend, self.comm_iface)?;
+        if self.data_iface != self.comm_iface {
+            super::claim_detaching(backend, self.data_iface)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the MAC address from its string descriptor.
+    pub fn mac_address<B: UsbBackend>(&self, backend: &B) -> Result<[u8; MAC_ADDRESS_LEN]> {
+        let index = self.functional.mac_address_index();
+        parse_mac_address(&super::string_descriptor(
+            backend,
+            index,
+            super::LANG_ID_EN_US,
+        )?)
+    }
+
+    /// Starts or stops the network traffic, by selecting the data alternate setting.
+    pub fn set_active<B: UsbBackend>(&self, backend: &B, active: bool) -> Result<()> {
+        let alt = if active { self.data_alt } else { 0 };
+        backend.set_interface(&UsbfsSetInterface::create(
+            self.data_iface as u32,
+            alt as u32,
+        ))
+    }
+
+    /// Releases the interfaces.
+    pub fn release<B: UsbBackend>(&self, backend: &B) -> Result<()> {
+        if self.data_iface != self.comm_iface {
+            backend.release_interface(self.data_iface as u32)?;
+        }
+        backend.release_interface(self.comm_iface as u32)
+    }
+
+    /// Sends a class request with data to the communication interface.
+    pub fn request_out<B: UsbBackend>(
+        &self,
+        backend: &B,
+        request: u8,
+        value: u16,
+        data: &mut [u8],
+        timeout: u32,
+    ) -> Result<()> {
+        class_request(
+            backend,
+            REQUEST_TYPE_CLASS_OUT,
+            request,
+            value,
+            self.comm_iface as u16,
+            data,
+            timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Sends a class request reading data from the communication interface.
+    pub fn request_in<B: UsbBackend>(
+        &self,
+        backend: &B,
+        request: u8,
+        value: u16,
+        data: &mut [u8],
+        timeout: u32,
+    ) -> Result<usize> {
+        class_request(
+            backend,
+            REQUEST_TYPE_CLASS_IN,
+            request,
+            value,
+            self.comm_iface as u16,
+            data,
+            timeout,
+        )
+    }
+
+    /// Waits for the next [CdcNotification] on the notification endpoint.
+    pub fn read_notification<B: UsbBackend>(
+        &self,
+        backend: &B,
+        timeout: u32,
+    ) -> Result<CdcNotification> {
+        let ep = self
+            .notify_ep
+            .ok_or(Error::NotFound("CDC notification endpoint".into()))?;
+        let mut int = UsbfsBulkTransfer::create(ep as u32, timeout, [0u8; 64]);
+        let len = backend.bulk(&mut int)?;
+        CdcNotification::parse(&int.data()[..len])
+    }
+
+    /// Sends a Bulk OUT transfer, ended by a zero-length packet if it fills the last packet.
+    pub fn send<B: UsbBackend>(&self, backend: &B, data: &[u8], timeout: u32) -> Result<()> {
+        let mut bulk =
+            UsbfsBulkTransfer::create(self.bulk_out as u32, timeout, data.iter().copied());
+        backend.bulk(&mut bulk)?;
+
+        if data.len() % self.max_packet_size == 0 {
+            let mut zlp = UsbfsBulkTransfer::create(self.bulk_out as u32, timeout, []);
+            backend.bulk(&mut zlp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a Bulk IN transfer of up to `len` bytes.
+    pub fn recv<B: UsbBackend>(&self, backend: &B, len: usize, timeout: u32) -> Result<Vec<u8>> {
+        let mut bulk = UsbfsBulkTransfer::create(
+            self.bulk_in as u32,
+            timeout,
+            vec![0u8; len.next_multiple_of(self.max_packet_size)],
+        );
+        let len = backend.bulk(&mut bulk)?;
+
+        let mut data = bulk.into_data();
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+/// Represents the link state reported by the network notifications.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkState {
+    connected: bool,
+    downstream: u32,
+    upstream: u32,
+}
+
+impl LinkState {
+    /// Creates a new [LinkState], disconnected.
+    pub const fn new() -> Self {
+        Self {
+            connected: false,
+            downstream: 0,
+            upstream: 0,
+        }
+    }
+
+    /// Gets whether the network cable is connected.
+    pub const fn connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Gets the downstream (device to host) bit rate, in bits per second.
+    pub const fn downstream(&self) -> u32 {
+        self.downstream
+    }
+
+    /// Gets the upstream (host to device) bit rate, in bits per second.
+    pub const fn upstream(&self) -> u32 {
+        self.upstream
+    }
+
+    /// Updates the [LinkState] from a `NETWORK_CONNECTION` or `SPEED_CHANGE` notification.
+    pub fn update(&mut self, notification: &CdcNotification) {
+        match notification {
+            CdcNotification::NetworkConnection(connected) => self.connected = *connected,
+            CdcNotification::SpeedChange {
+                downstream,
+                upstream,
+            } => {
+                self.downstream = *downstream;
+                self.upstream = *upstream;
+            }
+            _ => (),
+        }
+    }
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"connected": {}, "downstream": {}, "upstream": {}}}"#,
+            self.connected, self.downstream, self.upstream
+        )
+    }
+}
+
+/// CDC-ECM network interface over a [UsbBackend].
+///
+/// Sends and receives whole Ethernet frames, without the FCS.
+pub struct CdcEcm<B: UsbBackend> {
+    backend: B,
+    function: EthernetFunction,
+    mac_address: [u8; MAC_ADDRESS_LEN],
+    link: LinkState,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> CdcEcm<B> {
+    /// Opens the first ECM function of the active configuration.
+    ///
+    /// Claims the communication and data interfaces, detaching the `cdc_ether` kernel driver,
+    /// reads the MAC address, and starts the network traffic with the host [PacketFilter].
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let (function, _) = EthernetFunction::find(&config, CDC_SUBCLASS_ECM)?;
+
+        function.claim(&backend)?;
+        let mac_address = function.mac_address(&backend)?;
+
+        let ecm = Self {
+            backend,
+            function,
+            mac_address,
+            link: LinkState::new(),
+            timeout: super::DEFAULT_TIMEOUT,
+        };
+
+        // the default setting first, resetting the device filters and statistics
+        ecm.function.set_active(&ecm.backend, false)?;
+        ecm.function.set_active(&ecm.backend, true)?;
+        ecm.set_packet_filter(PacketFilter::host())?;
+
+        Ok(ecm)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the communication interface number.
+    pub const fn comm_interface(&self) -> u8 {
+        self.function.comm_iface
+    }
+
+    /// Gets the data interface number.
+    pub const fn data_interface(&self) -> u8 {
+        self.function.data_iface
+    }
+
+    /// Gets the [EthernetFunctional] descriptor.
+    pub const fn functional(&self) -> &EthernetFunctional {
+        &self.function.functional
+    }
+
+    /// Gets the device MAC address.
+    pub const fn mac_address(&self) -> [u8; MAC_ADDRESS_LEN] {
+        self.mac_address
+    }
+
+    /// Gets the [LinkState], as of the last notification.
+    pub const fn link(&self) -> &LinkState {
+        &self.link
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the [PacketFilter] with `SET_ETHERNET_PACKET_FILTER`.
+    pub fn set_packet_filter(&self, filter: PacketFilter) -> Result<()> {
+        self.function.request_out(
+            &self.backend,
+            ECM_SET_ETHERNET_PACKET_FILTER,
+            filter.inner(),
+            &mut [],
+            self.timeout,
+        )
+    }
+
+    /// Sets the multicast addresses with `SET_ETHERNET_MULTICAST_FILTERS`.
+    pub fn set_multicast_filters(&self, addresses: &[[u8; MAC_ADDRESS_LEN]]) -> Result<()> {
+        let mut data = addresses.concat();
+        self.function.request_out(
+            &self.backend,
+            ECM_SET_ETHERNET_MULTICAST_FILTERS,
+            addresses.len() as u16,
+            &mut data,
+            self.timeout,
+        )
+    }
+
+    /// Gets an Ethernet statistic counter with `GET_ETHERNET_STATISTIC`.
+    pub fn statistic(&self, selector: u16) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        let len = self.function.request_in(
+            &self.backend,
+            ECM_GET_ETHERNET_STATISTIC,
+            selector,
+            &mut buf,
+            self.timeout,
+        )?;
+        if len < buf.len() {
+            return Err(Error::InvalidMessage(format!(
+                "Ethernet statistic too short: {len}"
+            )));
+        }
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Waits for the next [CdcNotification], and updates the [LinkState].
+    pub fn read_notification(&mut self, timeout: u32) -> Result<CdcNotification> {
+        let notification = self.function.read_notification(&self.backend, timeout)?;
+        self.link.update(&notification);
+        Ok(notification)
+    }
+
+    /// Sends an Ethernet frame.
+    pub fn send(&self, frame: &[u8]) -> Result<()> {
+        if frame.is_empty() || frame.len() > self.functional().max_segment_size() as usize {
+            return Err(Error::InvalidArgument(format!(
+                "Ethernet frame length: {}",
+                frame.len()
+            )));
+        }
+        self.function.send(&self.backend, frame, self.timeout)
+    }
+
+    /// Receives an Ethernet frame.
+    pub fn recv(&self) -> Result<Vec<u8>> {
+        let len = self.functional().max_segment_size() as usize;
+        self.function.recv(&self.backend, len, self.timeout)
+    }
+
+    /// Stops the network traffic, releases the interfaces, and converts the [CdcEcm] into its
+    /// [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.function.set_active(&self.backend, false)?;
+        self.function.release(&self.backend)?;
+        Ok(self.backend)
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for CdcEcm<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CdcEcm")
+            .field("function", &self.function)
+            .field("mac_address", &self.mac_address)
+            .field("link", &self.link)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    /// Builds the descriptors of an ECM or NCM function: communication interface 0, with the
+    /// MAC address in string 4, and data interface 1.
+    pub(crate) fn ethernet_descriptors(subclass: u8) -> Vec<u8> {
+        let mut extra = vec![
+            0x05, 0x24, 0x00, 0x10, 0x01, //
+            0x05, 0x24, 0x06, 0x00, 0x01, //
+            0x0d, 0x24, 0x0f, 0x04, 0x00, 0x00, 0x00, 0x00, 0xea, 0x05, 0x00, 0x00, 0x00,
+        ];
+        if subclass == cdc::CDC_SUBCLASS_NCM {
+            extra.extend_from_slice(&[0x06, 0x24, 0x1a, 0x00, 0x01, 0x01]);
+        }
+
+        let mut config = vec![0x09, 0x04, 0x00, 0x00, 0x01, 0x02, subclass, 0x00, 0x00];
+        config.extend_from_slice(&extra);
+        config.extend_from_slice(&[
+            0x07, 0x05, 0x83, 0x03, 0x10, 0x00, 0x09, //
+            0x09, 0x04, 0x01, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, //
+            0x09, 0x04, 0x01, 0x01, 0x02, 0x0a, 0x00, 0x00, 0x00, //
+            0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00, //
+            0x07, 0x05, 0x02, 0x02, 0x40, 0x00, 0x00,
+        ]);
+
+        let total = (config.len() + 9) as u16;
+        let mut buf = vec![
+            0x12, 0x01, 0x00, 0x02, 0x02, 0x00, 0x00, 0x40, 0x25, 0x05, 0xa1, 0xa4, 0x00, 0x01,
+            0x01, 0x02, 0x03, 0x01,
+        ];
+        buf.extend_from_slice(&[0x09, 0x02]);
+        buf.extend_from_slice(&total.to_le_bytes());
+        buf.extend_from_slice(&[0x02, 0x01, 0x00, 0x80, 0x32]);
+        buf.extend_from_slice(&config);
+        buf
+    }
+
+    pub(crate) fn mac_string() -> MockControl {
+        let mut desc = vec![26, 0x03];
+        desc.extend("020000000001".encode_utf16().flat_map(|c| c.to_le_bytes()));
+        MockControl::create(0x80, 0x06, 0x0304, super::super::LANG_ID_EN_US)
+            .with_response(MockResponse::Data(desc))
+    }
+
+    #[test]
+    fn test_cdc_ecm() -> Result<()> {
+        let frame: Vec<u8> = (0..64u8).collect();
+
+        let dev = MockDevice::new()
+            .with_descriptors(ethernet_descriptors(CDC_SUBCLASS_ECM))
+            .with_configuration(1)
+            .with_driver(0, "cdc_ether")
+            .with_control(mac_string())
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                ECM_SET_ETHERNET_PACKET_FILTER,
+                PacketFilter::host().inner(),
+                0,
+            ))
+            .with_data(0x83, [0xa1, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .with_data(
+                0x83,
+                [
+                    0xa1, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0xe1, 0xf5, 0x05, 0x00,
+                    0xe1, 0xf5, 0x05,
+                ],
+            )
+            .with_data(0x81, frame[..60].to_vec());
+
+        let mut ecm = CdcEcm::open(dev)?;
+        assert_eq!(ecm.mac_address(), [0x02, 0, 0, 0, 0, 0x01]);
+        assert_eq!(ecm.functional().max_segment_size(), 1514);
+        assert_eq!(ecm.backend().alt_setting(1), 1);
+
+        ecm.read_notification(100)?;
+        ecm.read_notification(100)?;
+        assert!(ecm.link().connected());
+        assert_eq!(ecm.link().downstream(), 100_000_000);
+
+        assert_eq!(ecm.recv()?, frame[..60]);
+        // a full packet is ended by a zero-length packet
+        ecm.send(&frame)?;
+
+        let dev = ecm.close()?;
+        assert_eq!(dev.take_written(0x02), [frame, vec![]]);
+
+        dev.verify()
+    }
+}
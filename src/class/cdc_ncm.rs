@@ -0,0 +1,815 @@
+//! CDC Network Control Model (NCM) network driver.
+//!
+//! NCM batches Ethernet frames into NCM Transfer Blocks (NTBs): a transfer header (NTH),
+//! followed by datagram pointer tables (NDPs) locating each frame in the block. Blocks use
+//! 16-bit offsets (NTB16), or 32-bit offsets (NTB32) on devices supporting large blocks.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::cdc::{self, CdcNotification, CDC_FUNC_NCM, CDC_SUBCLASS_NCM};
+use super::cdc_ecm::{
+    EthernetFunction, EthernetFunctional, LinkState, PacketFilter, ECM_SET_ETHERNET_PACKET_FILTER,
+    MAC_ADDRESS_LEN,
+};
+use crate::descriptor::read_u16;
+use crate::{Error, Result, UsbBackend};
+
+pub const NCM_GET_NTB_PARAMETERS: u8 = 0x80;
+pub const NCM_GET_NET_ADDRESS: u8 = 0x81;
+pub const NCM_SET_NET_ADDRESS: u8 = 0x82;
+pub const NCM_GET_NTB_FORMAT: u8 = 0x83;
+pub const NCM_SET_NTB_FORMAT: u8 = 0x84;
+pub const NCM_GET_NTB_INPUT_SIZE: u8 = 0x85;
+pub const NCM_SET_NTB_INPUT_SIZE: u8 = 0x86;
+pub const NCM_GET_MAX_DATAGRAM_SIZE: u8 = 0x87;
+pub const NCM_SET_MAX_DATAGRAM_SIZE: u8 = 0x88;
+
+/// Length of the `GET_NTB_PARAMETERS` response.
+pub const NTB_PARAMETERS_LEN: usize = 28;
+
+pub const NTH16_SIGNATURE: [u8; 4] = *b"NCMH";
+pub const NTH32_SIGNATURE: [u8; 4] = *b"ncmh";
+pub const NDP16_SIGNATURE: [u8; 4] = *b"NCM0";
+pub const NDP32_SIGNATURE: [u8; 4] = *b"ncm0";
+
+pub const NTH16_LEN: usize = 12;
+pub const NTH32_LEN: usize = 16;
+
+// largest NTB received, devices may accept far more than the host needs
+const MAX_NTB_IN_SIZE: u32 = 16 * 1024;
+
+/// Represents the NCM functional descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NcmFunctional {
+    version: u16,
+    capabilities: u8,
+}
+
+impl NcmFunctional {
+    /// Creates a new [NcmFunctional].
+    pub const fn new() -> Self {
+        Self {
+            version: 0x0100,
+            capabilities: 0,
+        }
+    }
+
+    /// Parses the [NcmFunctional] from the class-specific descriptors of a communication
+    /// interface.
+    pub fn parse(extra: &[u8]) -> Option<Self> {
+        cdc::functional_descriptor(extra, CDC_FUNC_NCM)
+            .filter(|d| d.len() >= 6)
+            .map(|d| Self {
+                version: read_u16(d, 3),
+                capabilities: d[5],
+            })
+    }
+
+    /// Gets the NCM specification release, in BCD.
+    pub const fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Gets the `bmNetworkCapabilities` bitmap.
+    pub const fn capabilities(&self) -> u8 {
+        self.capabilities
+    }
+
+    /// Gets whether the device accepts `SET_ETHERNET_PACKET_FILTER`.
+    pub const fn packet_filter(&self) -> bool {
+        self.capabilities & 0x01 != 0
+    }
+
+    /// Gets whether the device accepts `GET_NET_ADDRESS` and `SET_NET_ADDRESS`.
+    pub const fn net_address(&self) -> bool {
+        self.capabilities & 0x02 != 0
+    }
+
+    /// Gets whether the device accepts `GET_MAX_DATAGRAM_SIZE` and `SET_MAX_DATAGRAM_SIZE`.
+    pub const fn max_datagram_size(&self) -> bool {
+        self.capabilities & 0x08 != 0
+    }
+
+    /// Gets whether `SET_NTB_INPUT_SIZE` takes the 8-byte structure with a datagram count.
+    pub const fn ntb_input_size_8(&self) -> bool {
+        self.capabilities & 0x20 != 0
+    }
+}
+
+impl fmt::Display for NcmFunctional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"version": {}, "capabilities": {}}}"#,
+            self.version, self.capabilities
+        )
+    }
+}
+
+/// Represents the NTB format: 16-bit or 32-bit offsets.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NtbFormat {
+    #[default]
+    Ntb16 = 0,
+    Ntb32 = 1,
+}
+
+impl NtbFormat {
+    /// Creates a new [NtbFormat].
+    pub const fn new() -> Self {
+        Self::Ntb16
+    }
+
+    const fn header_len(&self) -> usize {
+        match self {
+            Self::Ntb16 => NTH16_LEN,
+            Self::Ntb32 => NTH32_LEN,
+        }
+    }
+
+    // NDP header, and datagram pointer entry lengths
+    const fn ndp_lens(&self) -> (usize, usize) {
+        match self {
+            Self::Ntb16 => (8, 4),
+            Self::Ntb32 => (16, 8),
+        }
+    }
+}
+
+impl From<&NtbFormat> for &'static str {
+    fn from(val: &NtbFormat) -> Self {
+        match val {
+            NtbFormat::Ntb16 => "NTB16",
+            NtbFormat::Ntb32 => "NTB32",
+        }
+    }
+}
+
+impl fmt::Display for NtbFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the `GET_NTB_PARAMETERS` response.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtbParameters {
+    formats: u16,
+    in_max_size: u32,
+    in_divisor: u16,
+    in_remainder: u16,
+    in_alignment: u16,
+    out_max_size: u32,
+    out_divisor: u16,
+    out_remainder: u16,
+    out_alignment: u16,
+    out_max_datagrams: u16,
+}
+
+impl NtbParameters {
+    /// Creates a new [NtbParameters], with NTB16 blocks of 2048 bytes.
+    pub const fn new() -> Self {
+        Self {
+            formats: 0x0001,
+            in_max_size: 2048,
+            in_divisor: 4,
+            in_remainder: 0,
+            in_alignment: 4,
+            out_max_size: 2048,
+            out_divisor: 4,
+            out_remainder: 0,
+            out_alignment: 4,
+            out_max_datagrams: 0,
+        }
+    }
+
+    /// Parses the [NtbParameters] from the `GET_NTB_PARAMETERS` response.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < NTB_PARAMETERS_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "NTB parameters too short: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            formats: read_u16(buf, 2),
+            in_max_size: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            in_divisor: read_u16(buf, 8),
+            in_remainder: read_u16(buf, 10),
+            in_alignment: read_u16(buf, 12),
+            out_max_size: u32::from_le_bytes([buf[16], buf[17], buf[18], buf[19]]),
+            out_divisor: read_u16(buf, 20),
+            out_remainder: read_u16(buf, 22),
+            out_alignment: read_u16(buf, 24),
+            out_max_datagrams: read_u16(buf, 26),
+        })
+    }
+
+    /// Gets whether the device supports the [NtbFormat].
+    pub const fn supports(&self, format: NtbFormat) -> bool {
+        self.formats & (1 << format as u16) != 0
+    }
+
+    /// Gets the largest NTB the device sends.
+    pub const fn in_max_size(&self) -> u32 {
+        self.in_max_size
+    }
+
+    /// Gets the largest NTB the device accepts.
+    pub const fn out_max_size(&self) -> u32 {
+        self.out_max_size
+    }
+
+    /// Gets the datagram alignment divisor of sent NTBs.
+    pub const fn out_divisor(&self) -> u16 {
+        self.out_divisor
+    }
+
+    /// Gets the datagram offset remainder of sent NTBs, modulo the divisor.
+    pub const fn out_remainder(&self) -> u16 {
+        self.out_remainder
+    }
+
+    /// Gets the NDP alignment of sent NTBs.
+    pub const fn out_alignment(&self) -> u16 {
+        self.out_alignment
+    }
+
+    /// Gets the most datagrams per sent NTB, `0` for no limit.
+    pub const fn out_max_datagrams(&self) -> u16 {
+        self.out_max_datagrams
+    }
+}
+
+impl fmt::Display for NtbParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""formats": {}, "#, self.formats)?;
+        write!(f, r#""in_max_size": {}, "#, self.in_max_size)?;
+        write!(f, r#""in_divisor": {}, "#, self.in_divisor)?;
+        write!(f, r#""in_remainder": {}, "#, self.in_remainder)?;
+        write!(f, r#""in_alignment": {}, "#, self.in_alignment)?;
+        write!(f, r#""out_max_size": {}, "#, self.out_max_size)?;
+        write!(f, r#""out_divisor": {}, "#, self.out_divisor)?;
+        write!(f, r#""out_remainder": {}, "#, self.out_remainder)?;
+        write!(f, r#""out_alignment": {}, "#, self.out_alignment)?;
+        write!(f, r#""out_max_datagrams": {}"#, self.out_max_datagrams)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents an NCM Transfer Block: the Ethernet frames of one Bulk transfer.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ntb {
+    format: NtbFormat,
+    sequence: u16,
+    datagrams: Vec<Vec<u8>>,
+}
+
+impl Ntb {
+    /// Creates a new [Ntb].
+    pub const fn new() -> Self {
+        Self {
+            format: NtbFormat::Ntb16,
+            sequence: 0,
+            datagrams: Vec::new(),
+        }
+    }
+
+    /// Creates a new [Ntb] from the provided parameters.
+    pub fn create(format: NtbFormat, sequence: u16, datagrams: &[&[u8]]) -> Self {
+        Self {
+            format,
+            sequence,
+            datagrams: datagrams.iter().map(|d| d.to_vec()).collect(),
+        }
+    }
+
+    /// Parses an [Ntb], following the NDP chain.
+    ///
+    /// NDPs with unknown signatures, e.g. with CRCs, are skipped.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let invalid = |err: String| Error::InvalidMessage(format!("invalid NTB: {err}"));
+        let u32_at = |off: usize| -> Result<usize> {
+            buf.get(off..off + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .ok_or(invalid(format!("offset {off} out of bounds")))
+        };
+        let u16_at = |off: usize| -> Result<usize> {
+            buf.get(off..off + 2)
+                .map(|b| read_u16(b, 0) as usize)
+                .ok_or(invalid(format!("offset {off} out of bounds")))
+        };
+
+        let signature = buf
+            .get(..4)
+            .ok_or(invalid(format!("length: {}", buf.len())))?;
+        let format = if signature == NTH16_SIGNATURE {
+            NtbFormat::Ntb16
+        } else if signature == NTH32_SIGNATURE {
+            NtbFormat::Ntb32
+        } else {
+            return Err(invalid(format!("NTH signature: {signature:02x?}")));
+        };
+
+        let sequence = u16_at(6)? as u16;
+        let (block_len, mut ndp) = match format {
+            NtbFormat::Ntb16 => (u16_at(8)?, u16_at(10)?),
+            NtbFormat::Ntb32 => (u32_at(8)?, u32_at(12)?),
+        };
+        if block_len > buf.len() {
+            return Err(invalid(format!(
+                "block length: {block_len}, received: {}",
+                buf.len()
+            )));
+        }
+        let buf = &buf[..block_len];
+
+        let (ndp_header_len, entry_len) = format.ndp_lens();
+        let mut datagrams = Vec::new();
+        // each NDP is after the NTH, bounding the chain length
+        let mut visited = 0;
+
+        while ndp != 0 {
+            visited += 1;
+            if visited > block_len / ndp_header_len {
+                return Err(invalid("NDP chain loop".into()));
+            }
+
+            let signature = buf
+                .get(ndp..ndp + 4)
+                .ok_or(invalid(format!("NDP index: {ndp}")))?;
+            let known = match format {
+                NtbFormat::Ntb16 => signature == NDP16_SIGNATURE,
+                NtbFormat::Ntb32 => signature == NDP32_SIGNATURE,
+            };
+            let ndp_len = u16_at(ndp + 4)?;
+            let next = match format {
+                NtbFormat::Ntb16 => u16_at(ndp + 6)?,
+                NtbFormat::Ntb32 => u32_at(ndp + 8)?,
+            };
+
+            if known {
+                let end = (ndp + ndp_len).min(buf.len());
+                let mut entry = ndp + ndp_header_len;
+                while entry + entry_len <= end {
+                    let (index, len) = match format {
+                        NtbFormat::Ntb16 => (u16_at(entry)?, u16_at(entry + 2)?),
+                        NtbFormat::Ntb32 => (u32_at(entry)?, u32_at(entry + 4)?),
+                    };
+                    if index == 0 || len == 0 {
+                        break;
+                    }
+
+                    let datagram = buf
+                        .get(index..index + len)
+                        .ok_or(invalid(format!("datagram {index}, length: {len}")))?;
+                    datagrams.push(datagram.to_vec());
+                    entry += entry_len;
+                }
+            }
+
+            ndp = next;
+        }
+
+        Ok(Self {
+            format,
+            sequence,
+            datagrams,
+        })
+    }
+
+    /// Gets the [NtbFormat].
+    pub const fn format(&self) -> NtbFormat {
+        self.format
+    }
+
+    /// Gets the sequence number.
+    pub const fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Gets the datagrams: Ethernet frames without the FCS.
+    pub fn datagrams(&self) -> &[Vec<u8>] {
+        self.datagrams.as_ref()
+    }
+
+    /// Converts the [Ntb] into its datagrams.
+    pub fn into_datagrams(self) -> Vec<Vec<u8>> {
+        self.datagrams
+    }
+
+    /// Gets the wire representation of the [Ntb], aligned as the [NtbParameters] require.
+    ///
+    /// The NDP follows the NTH, and the datagrams follow the NDP.
+    pub fn to_bytes(&self, params: &NtbParameters) -> Result<Vec<u8>> {
+        let header_len = self.format.header_len();
+        let (ndp_header_len, entry_len) = self.format.ndp_lens();
+        let ndp = header_len.next_multiple_of(params.out_alignment.max(4) as usize);
+        let ndp_len = ndp_header_len + (self.datagrams.len() + 1) * entry_len;
+        let divisor = params.out_divisor.max(1) as usize;
+        let remainder = params.out_remainder as usize % divisor;
+
+        let mut buf = vec![0u8; ndp + ndp_len];
+        let mut entries = Vec::with_capacity(self.datagrams.len());
+        for datagram in self.datagrams.iter() {
+            let pad = (divisor + remainder - buf.len() % divisor) % divisor;
+            buf.resize(buf.len() + pad, 0);
+            entries.push((buf.len(), datagram.len()));
+            buf.extend_from_slice(datagram);
+        }
+
+        let block_len = buf.len();
+        if self.format == NtbFormat::Ntb16 && block_len > u16::MAX as usize {
+            return Err(Error::InvalidArgument(format!(
+                "NTB16 block length: {block_len}"
+            )));
+        }
+
+        buf[6..8].copy_from_slice(&self.sequence.to_le_bytes());
+        buf[ndp + 4..ndp + 6].copy_from_slice(&(ndp_len as u16).to_le_bytes());
+        match self.format {
+            NtbFormat::Ntb16 => {
+                buf[..4].copy_from_slice(&NTH16_SIGNATURE);
+                buf[4..6].copy_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+                buf[8..10].copy_from_slice(&(block_len as u16).to_le_bytes());
+                buf[10..12].copy_from_slice(&(ndp as u16).to_le_bytes());
+                buf[ndp..ndp + 4].copy_from_slice(&NDP16_SIGNATURE);
+            }
+            NtbFormat::Ntb32 => {
+                buf[..4].copy_from_slice(&NTH32_SIGNATURE);
+                buf[4..6].copy_from_slice(&(NTH32_LEN as u16).to_le_bytes());
+                buf[8..12].copy_from_slice(&(block_len as u32).to_le_bytes());
+                buf[12..16].copy_from_slice(&(ndp as u32).to_le_bytes());
+                buf[ndp..ndp + 4].copy_from_slice(&NDP32_SIGNATURE);
+            }
+        }
+
+        for (i, (index, len)) in entries.into_iter().enumerate() {
+            let entry = ndp + ndp_header_len + i * entry_len;
+            match self.format {
+                NtbFormat::Ntb16 => {
+                    buf[entry..entry + 2].copy_from_slice(&(index as u16).to_le_bytes());
+                    buf[entry + 2..entry + 4].copy_from_slice(&(len as u16).to_le_bytes());
+                }
+                NtbFormat::Ntb32 => {
+                    buf[entry..entry + 4].copy_from_slice(&(index as u32).to_le_bytes());
+                    buf[entry + 4..entry + 8].copy_from_slice(&(len as u32).to_le_bytes());
+                }
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+impl fmt::Display for Ntb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""format": {}, "#, self.format)?;
+        write!(f, r#""sequence": {}, "#, self.sequence)?;
+        write!(f, r#""datagrams": {}"#, self.datagrams.len())?;
+        write!(f, "}}")
+    }
+}
+
+/// CDC-NCM network interface over a [UsbBackend].
+///
+/// Sends and receives whole Ethernet frames, without the FCS, packed into [Ntb]s.
+pub struct CdcNcm<B: UsbBackend> {
+    backend: B,
+    function: EthernetFunction,
+    ncm: NcmFunctional,
+    params: NtbParameters,
+    format: NtbFormat,
+    in_size: u32,
+    mac_address: [u8; MAC_ADDRESS_LEN],
+    link: LinkState,
+    sequence: u16,
+    rx: VecDeque<Vec<u8>>,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> CdcNcm<B> {
+    /// Opens the first NCM function of the active configuration, with NTB16 blocks.
+    pub fn open(backend: B) -> Result<Self> {
+        Self::open_with_format(backend, NtbFormat::Ntb16)
+    }
+
+    /// Opens the first NCM function of the active configuration, with the provided
+    /// [NtbFormat].
+    ///
+    /// Claims the communication and data interfaces, detaching the `cdc_ncm` kernel driver,
+    /// reads the MAC address and NTB parameters, and starts the network traffic.
+    pub fn open_with_format(backend: B, format: NtbFormat) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let (function, extra) = EthernetFunction::find(&config, CDC_SUBCLASS_NCM)?;
+        let ncm = NcmFunctional::parse(&extra)
+            .ok_or(Error::NotFound("CDC NCM functional descriptor".into()))?;
+
+        function.claim(&backend)?;
+        let mac_address = function.mac_address(&backend)?;
+
+        let mut buf = [0u8; NTB_PARAMETERS_LEN];
+        let len = function.request_in(
+            &backend,
+            NCM_GET_NTB_PARAMETERS,
+            0,
+            &mut buf,
+            super::DEFAULT_TIMEOUT,
+        )?;
+        let params = NtbParameters::parse(&buf[..len])?;
+        if !params.supports(format) {
+            return Err(Error::InvalidArgument(format!(
+                "NTB format not supported: {format}"
+            )));
+        }
+
+        let ncm = Self {
+            backend,
+            function,
+            ncm,
+            params,
+            format,
+            in_size: params.in_max_size().min(MAX_NTB_IN_SIZE),
+            mac_address,
+            link: LinkState::new(),
+            sequence: 0,
+            rx: VecDeque::new(),
+            timeout: super::DEFAULT_TIMEOUT,
+        };
+
+        // the NTB settings only change while the data interface is idle
+        ncm.function.set_active(&ncm.backend, false)?;
+        if ncm.params.supports(NtbFormat::Ntb32) {
+            ncm.request_out(NCM_SET_NTB_FORMAT, format as u16, &mut [])?;
+        }
+        ncm.set_ntb_input_size(ncm.in_size)?;
+        ncm.function.set_active(&ncm.backend, true)?;
+
+        if ncm.ncm.packet_filter() {
+            ncm.set_packet_filter(PacketFilter::host())?;
+        }
+
+        Ok(ncm)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the communication interface number.
+    pub const fn comm_interface(&self) -> u8 {
+        self.function.comm_iface
+    }
+
+    /// Gets the data interface number.
+    pub const fn data_interface(&self) -> u8 {
+        self.function.data_iface
+    }
+
+    /// Gets the [EthernetFunctional] descriptor.
+    pub const fn functional(&self) -> &EthernetFunctional {
+        &self.function.functional
+    }
+
+    /// Gets the [NcmFunctional] descriptor.
+    pub const fn ncm_functional(&self) -> &NcmFunctional {
+        &self.ncm
+    }
+
+    /// Gets the [NtbParameters].
+    pub const fn ntb_parameters(&self) -> &NtbParameters {
+        &self.params
+    }
+
+    /// Gets the [NtbFormat] in use.
+    pub const fn format(&self) -> NtbFormat {
+        self.format
+    }
+
+    /// Gets the device MAC address.
+    pub const fn mac_address(&self) -> [u8; MAC_ADDRESS_LEN] {
+        self.mac_address
+    }
+
+    /// Gets the [LinkState], as of the last notification.
+    pub const fn link(&self) -> &LinkState {
+        &self.link
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Sets the [PacketFilter] with `SET_ETHERNET_PACKET_FILTER`.
+    pub fn set_packet_filter(&self, filter: PacketFilter) -> Result<()> {
+        self.request_out(ECM_SET_ETHERNET_PACKET_FILTER, filter.inner(), &mut [])
+    }
+
+    /// Waits for the next [CdcNotification], and updates the [LinkState].
+    pub fn read_notification(&mut self, timeout: u32) -> Result<CdcNotification> {
+        let notification = self.function.read_notification(&self.backend, timeout)?;
+        self.link.update(&notification);
+        Ok(notification)
+    }
+
+    /// Sends an Ethernet frame, in its own [Ntb].
+    pub fn send(&mut self, frame: &[u8]) -> Result<()> {
+        self.send_frames(&[frame])
+    }
+
+    /// Sends Ethernet frames, packed into as few [Ntb]s as the device accepts.
+    pub fn send_frames(&mut self, frames: &[&[u8]]) -> Result<()> {
+        let max_frame = self.functional().max_segment_size() as usize;
+        if let Some(frame) = frames.iter().find(|f| f.is_empty() || f.len() > max_frame) {
+            return Err(Error::InvalidArgument(format!(
+                "Ethernet frame length: {}",
+                frame.len()
+            )));
+        }
+
+        let max_size = self.params.out_max_size() as usize;
+        let max_datagrams = match self.params.out_max_datagrams() {
+            0 => usize::MAX,
+            n => n as usize,
+        };
+
+        let mut start = 0;
+        while start < frames.len() {
+            // grow the block until it reaches the device limits
+            let mut end = start + 1;
+            let mut buf = self.ntb(&frames[start..end])?;
+            while end < frames.len() && end - start < max_datagrams {
+                let next = self.ntb(&frames[start..=end])?;
+                if next.len() > max_size {
+                    break;
+                }
+                buf = next;
+                end += 1;
+            }
+            if buf.len() > max_size {
+                return Err(Error::InvalidArgument(format!(
+                    "NTB length: {}, device maximum: {max_size}",
+                    buf.len()
+                )));
+            }
+
+            self.function.send(&self.backend, &buf, self.timeout)?;
+            self.sequence = self.sequence.wrapping_add(1);
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    /// Receives an Ethernet frame, reading the next [Ntb] once the previous one is consumed.
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        while self.rx.is_empty() {
+            let buf = self
+                .function
+                .recv(&self.backend, self.in_size as usize, self.timeout)?;
+            self.rx.extend(Ntb::parse(&buf)?.into_datagrams());
+        }
+
+        Ok(self.rx.pop_front().unwrap_or_default())
+    }
+
+    /// Stops the network traffic, releases the interfaces, and converts the [CdcNcm] into its
+    /// [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.function.set_active(&self.backend, false)?;
+        self.function.release(&self.backend)?;
+        Ok(self.backend)
+    }
+
+    fn set_ntb_input_size(&self, size: u32) -> Result<()> {
+        let mut data = size.to_le_bytes().to_vec();
+        if self.ncm.ntb_input_size_8() {
+            // wNtbInMaxDatagrams of 0, no limit, and reserved
+            data.extend_from_slice(&[0u8; 4]);
+        }
+        self.request_out(NCM_SET_NTB_INPUT_SIZE, 0, &mut data)
+    }
+
+    fn ntb(&self, frames: &[&[u8]]) -> Result<Vec<u8>> {
+        Ntb::create(self.format, self.sequence, frames).to_bytes(&self.params)
+    }
+
+    fn request_out(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        self.function
+            .request_out(&self.backend, request, value, data, self.timeout)
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for CdcNcm<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CdcNcm")
+            .field("function", &self.function)
+            .field("ncm", &self.ncm)
+            .field("params", &self.params)
+            .field("format", &self.format)
+            .field("in_size", &self.in_size)
+            .field("mac_address", &self.mac_address)
+            .field("link", &self.link)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cdc_ecm::tests::{ethernet_descriptors, mac_string};
+    use super::super::{REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    fn ntb_parameters() -> Vec<u8> {
+        let mut buf = vec![0u8; NTB_PARAMETERS_LEN];
+        buf[..4].copy_from_slice(&[0x1c, 0x00, 0x03, 0x00]);
+        buf[4..8].copy_from_slice(&8192u32.to_le_bytes());
+        buf[8..14].copy_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x04, 0x00]);
+        buf[16..20].copy_from_slice(&8192u32.to_le_bytes());
+        buf[20..28].copy_from_slice(&[0x04, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00]);
+        buf
+    }
+
+    #[test]
+    fn test_cdc_ncm() -> Result<()> {
+        let frames: Vec<Vec<u8>> = (0..3u8).map(|i| vec![i; 60 + i as usize]).collect();
+        let frames: Vec<&[u8]> = frames.iter().map(|f| f.as_slice()).collect();
+        let params = NtbParameters::parse(&ntb_parameters())?;
+
+        // NTB32 framing round trip, with datagrams at offset 2 modulo 4
+        let ntb = Ntb::create(NtbFormat::Ntb32, 7, &frames);
+        let buf = ntb.to_bytes(&params)?;
+        assert_eq!(&buf[..4], b"ncmh");
+        assert_eq!(Ntb::parse(&buf)?, ntb);
+
+        let rx = Ntb::create(NtbFormat::Ntb16, 0, &frames[..2]).to_bytes(&params)?;
+        let dev = MockDevice::new()
+            .with_descriptors(ethernet_descriptors(CDC_SUBCLASS_NCM))
+            .with_configuration(1)
+            .with_driver(0, "cdc_ncm")
+            .with_control(mac_string())
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, NCM_GET_NTB_PARAMETERS, 0, 0)
+                    .with_response(MockResponse::Data(ntb_parameters())),
+            )
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                NCM_SET_NTB_FORMAT,
+                0,
+                0,
+            ))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, NCM_SET_NTB_INPUT_SIZE, 0, 0)
+                    .with_data(8192u32.to_le_bytes()),
+            )
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                ECM_SET_ETHERNET_PACKET_FILTER,
+                PacketFilter::host().inner(),
+                0,
+            ))
+            .with_data(0x81, rx);
+
+        let mut ncm = CdcNcm::open(dev)?;
+        assert_eq!(ncm.mac_address(), [0x02, 0, 0, 0, 0, 0x01]);
+        assert!(ncm.ntb_parameters().supports(NtbFormat::Ntb32));
+
+        assert_eq!(ncm.recv()?, frames[0]);
+        assert_eq!(ncm.recv()?, frames[1]);
+
+        ncm.send_frames(&frames)?;
+
+        let dev = ncm.close()?;
+        let written = dev.take_written(0x02);
+        assert_eq!(written.len(), 1);
+        let ntb = Ntb::parse(&written[0])?;
+        assert_eq!(ntb.format(), NtbFormat::Ntb16);
+        assert_eq!(ntb.datagrams(), frames);
+
+        dev.verify()
+    }
+}
@@ -0,0 +1,919 @@
+//! Device Firmware Upgrade (DFU) class driver.
+//!
+//! A device exposes a DFU runtime interface next to its application functions. A `DFU_DETACH`
+//! request, followed by a bus reset, makes it re-enumerate in DFU mode, where firmware is
+//! downloaded block by block, then manifested. [Dfu] runs the download and upload state machines
+//! through `GETSTATUS` polling, and [Dfu::enter_dfu_mode] / [Dfu::leave_dfu_mode] follow the
+//! device across re-enumerations on an [IoctlBackend].
+//!
+//! The [dfuse] module adds the STMicroelectronics DfuSe extensions, and the [file] module
+//! validates `.dfu` files.
+
+use std::time::Duration;
+use std::{fmt, thread};
+
+use nix::errno::Errno;
+
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::descriptor::{read_u16, DescriptorIter, InterfaceDescriptor};
+use crate::{Error, IoctlBackend, Result, Sysfs, UsbBackend, UsbfsSetInterface};
+
+pub mod dfuse;
+pub mod file;
+
+pub use dfuse::{DfuseElement, DfuseImage, DfuseMemoryLayout, DfuseSegment, DfuseTarget};
+pub use file::{DfuFile, DfuSuffix};
+
+pub const DFU_CLASS: u8 = 0xfe;
+pub const DFU_SUBCLASS: u8 = 0x01;
+pub const DFU_PROTOCOL_RUNTIME: u8 = 0x01;
+pub const DFU_PROTOCOL_DFU: u8 = 0x02;
+
+/// Descriptor type of the DFU functional descriptor.
+pub const DESCRIPTOR_TYPE_DFU_FUNCTIONAL: u8 = 0x21;
+pub const DFU_FUNCTIONAL_LEN: usize = 9;
+
+pub const DFU_DETACH: u8 = 0x00;
+pub const DFU_DNLOAD: u8 = 0x01;
+pub const DFU_UPLOAD: u8 = 0x02;
+pub const DFU_GETSTATUS: u8 = 0x03;
+pub const DFU_CLRSTATUS: u8 = 0x04;
+pub const DFU_GETSTATE: u8 = 0x05;
+pub const DFU_ABORT: u8 = 0x06;
+
+pub const DFU_STATUS_LEN: usize = 6;
+
+/// `bcdDFUVersion` of DfuSe devices.
+pub const DFU_VERSION_DFUSE: u16 = 0x011a;
+
+const ATTR_CAN_DOWNLOAD: u8 = 0x01;
+const ATTR_CAN_UPLOAD: u8 = 0x02;
+const ATTR_MANIFESTATION_TOLERANT: u8 = 0x04;
+const ATTR_WILL_DETACH: u8 = 0x08;
+
+/// Represents the DFU functional descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuFunctional {
+    attributes: u8,
+    detach_timeout: u16,
+    transfer_size: u16,
+    dfu_version: u16,
+}
+
+impl DfuFunctional {
+    /// Creates a new [DfuFunctional].
+    pub const fn new() -> Self {
+        Self {
+            attributes: 0,
+            detach_timeout: 0,
+            transfer_size: 0,
+            dfu_version: 0,
+        }
+    }
+
+    /// Parses the [DfuFunctional] from the class-specific descriptors of a DFU interface.
+    pub fn parse(extra: &[u8]) -> Result<Self> {
+        let desc = DescriptorIter::new(extra)
+            .find(|d| d[1] == DESCRIPTOR_TYPE_DFU_FUNCTIONAL)
+            .ok_or(Error::NotFound("DFU functional descriptor".into()))?;
+
+        // DFU 1.0 descriptors end at wTransferSize
+        if desc.len() < DFU_FUNCTIONAL_LEN - 2 {
+            return Err(Error::InvalidDescriptor(format!(
+                "DFU functional descriptor too short: {}",
+                desc.len()
+            )));
+        }
+
+        Ok(Self {
+            attributes: desc[2],
+            detach_timeout: read_u16(desc, 3),
+            transfer_size: read_u16(desc, 5),
+            dfu_version: if desc.len() >= DFU_FUNCTIONAL_LEN {
+                read_u16(desc, 7)
+            } else {
+                0x0100
+            },
+        })
+    }
+
+    /// Gets the raw `bmAttributes` field.
+    pub const fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Gets whether the device accepts downloads.
+    pub const fn can_download(&self) -> bool {
+        self.attributes & ATTR_CAN_DOWNLOAD != 0
+    }
+
+    /// Gets whether the device supports uploads.
+    pub const fn can_upload(&self) -> bool {
+        self.attributes & ATTR_CAN_UPLOAD != 0
+    }
+
+    /// Gets whether the device keeps communicating after the manifestation phase.
+    pub const fn manifestation_tolerant(&self) -> bool {
+        self.attributes & ATTR_MANIFESTATION_TOLERANT != 0
+    }
+
+    /// Gets whether the device detaches itself on `DFU_DETACH`, without a bus reset.
+    pub const fn will_detach(&self) -> bool {
+        self.attributes & ATTR_WILL_DETACH != 0
+    }
+
+    /// Gets the time the device waits for a reset after `DFU_DETACH`, in milliseconds.
+    pub const fn detach_timeout(&self) -> u16 {
+        self.detach_timeout
+    }
+
+    /// Gets the maximum number of bytes per `DFU_DNLOAD` or `DFU_UPLOAD` request.
+    pub const fn transfer_size(&self) -> u16 {
+        self.transfer_size
+    }
+
+    /// Gets the DFU specification release, in BCD.
+    pub const fn dfu_version(&self) -> u16 {
+        self.dfu_version
+    }
+
+    /// Gets whether the device implements the DfuSe extensions.
+    pub const fn is_dfuse(&self) -> bool {
+        self.dfu_version == DFU_VERSION_DFUSE
+    }
+}
+
+impl fmt::Display for DfuFunctional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""attributes": {}, "#, self.attributes)?;
+        write!(f, r#""detach_timeout": {}, "#, self.detach_timeout)?;
+        write!(f, r#""transfer_size": {}, "#, self.transfer_size)?;
+        write!(f, r#""dfu_version": {}"#, self.dfu_version)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the DFU device state.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DfuState {
+    #[default]
+    AppIdle = 0,
+    AppDetach = 1,
+    DfuIdle = 2,
+    DownloadSync = 3,
+    DownloadBusy = 4,
+    DownloadIdle = 5,
+    ManifestSync = 6,
+    Manifest = 7,
+    ManifestWaitReset = 8,
+    UploadIdle = 9,
+    Error = 10,
+}
+
+impl DfuState {
+    /// Creates a new [DfuState].
+    pub const fn new() -> Self {
+        Self::AppIdle
+    }
+
+    /// Creates a new [DfuState] from its `bState` value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Self::AppIdle),
+            1 => Some(Self::AppDetach),
+            2 => Some(Self::DfuIdle),
+            3 => Some(Self::DownloadSync),
+            4 => Some(Self::DownloadBusy),
+            5 => Some(Self::DownloadIdle),
+            6 => Some(Self::ManifestSync),
+            7 => Some(Self::Manifest),
+            8 => Some(Self::ManifestWaitReset),
+            9 => Some(Self::UploadIdle),
+            10 => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+impl From<&DfuState> for &'static str {
+    fn from(val: &DfuState) -> Self {
+        match val {
+            DfuState::AppIdle => "appIDLE",
+            DfuState::AppDetach => "appDETACH",
+            DfuState::DfuIdle => "dfuIDLE",
+            DfuState::DownloadSync => "dfuDNLOAD-SYNC",
+            DfuState::DownloadBusy => "dfuDNBUSY",
+            DfuState::DownloadIdle => "dfuDNLOAD-IDLE",
+            DfuState::ManifestSync => "dfuMANIFEST-SYNC",
+            DfuState::Manifest => "dfuMANIFEST",
+            DfuState::ManifestWaitReset => "dfuMANIFEST-WAIT-RESET",
+            DfuState::UploadIdle => "dfuUPLOAD-IDLE",
+            DfuState::Error => "dfuERROR",
+        }
+    }
+}
+
+impl fmt::Display for DfuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the DFU status code, reporting the result of the last request.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DfuStatusCode {
+    #[default]
+    Ok = 0x00,
+    Target = 0x01,
+    File = 0x02,
+    Write = 0x03,
+    Erase = 0x04,
+    CheckErased = 0x05,
+    Prog = 0x06,
+    Verify = 0x07,
+    Address = 0x08,
+    NotDone = 0x09,
+    Firmware = 0x0a,
+    Vendor = 0x0b,
+    UsbReset = 0x0c,
+    PowerOnReset = 0x0d,
+    Unknown = 0x0e,
+    StalledPacket = 0x0f,
+}
+
+impl DfuStatusCode {
+    /// Creates a new [DfuStatusCode].
+    pub const fn new() -> Self {
+        Self::Ok
+    }
+
+    /// Creates a new [DfuStatusCode] from its `bStatus` value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0x00 => Some(Self::Ok),
+            0x01 => Some(Self::Target),
+            0x02 => Some(Self::File),
+            0x03 => Some(Self::Write),
+            0x04 => Some(Self::Erase),
+            0x05 => Some(Self::CheckErased),
+            0x06 => Some(Self::Prog),
+            0x07 => Some(Self::Verify),
+            0x08 => Some(Self::Address),
+            0x09 => Some(Self::NotDone),
+            0x0a => Some(Self::Firmware),
+            0x0b => Some(Self::Vendor),
+            0x0c => Some(Self::UsbReset),
+            0x0d => Some(Self::PowerOnReset),
+            0x0e => Some(Self::Unknown),
+            0x0f => Some(Self::StalledPacket),
+            _ => None,
+        }
+    }
+}
+
+impl From<&DfuStatusCode> for &'static str {
+    fn from(val: &DfuStatusCode) -> Self {
+        match val {
+            DfuStatusCode::Ok => "OK",
+            DfuStatusCode::Target => "errTARGET",
+            DfuStatusCode::File => "errFILE",
+            DfuStatusCode::Write => "errWRITE",
+            DfuStatusCode::Erase => "errERASE",
+            DfuStatusCode::CheckErased => "errCHECK_ERASED",
+            DfuStatusCode::Prog => "errPROG",
+            DfuStatusCode::Verify => "errVERIFY",
+            DfuStatusCode::Address => "errADDRESS",
+            DfuStatusCode::NotDone => "errNOTDONE",
+            DfuStatusCode::Firmware => "errFIRMWARE",
+            DfuStatusCode::Vendor => "errVENDOR",
+            DfuStatusCode::UsbReset => "errUSBR",
+            DfuStatusCode::PowerOnReset => "errPOR",
+            DfuStatusCode::Unknown => "errUNKNOWN",
+            DfuStatusCode::StalledPacket => "errSTALLEDPKT",
+        }
+    }
+}
+
+impl fmt::Display for DfuStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the `DFU_GETSTATUS` response.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuStatus {
+    status: DfuStatusCode,
+    poll_timeout: u32,
+    state: DfuState,
+    string_index: u8,
+}
+
+impl DfuStatus {
+    /// Creates a new [DfuStatus] from the provided parameters.
+    pub const fn create(status: DfuStatusCode, poll_timeout: u32, state: DfuState) -> Self {
+        Self {
+            status,
+            poll_timeout,
+            state,
+            string_index: 0,
+        }
+    }
+
+    /// Parses the [DfuStatus] from the `DFU_GETSTATUS` response.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < DFU_STATUS_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "DFU status too short: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            status: DfuStatusCode::create(buf[0]).ok_or(Error::InvalidMessage(format!(
+                "invalid DFU status: {}",
+                buf[0]
+            )))?,
+            poll_timeout: u32::from_le_bytes([buf[1], buf[2], buf[3], 0]),
+            state: DfuState::create(buf[4]).ok_or(Error::InvalidMessage(format!(
+                "invalid DFU state: {}",
+                buf[4]
+            )))?,
+            string_index: buf[5],
+        })
+    }
+
+    /// Gets the wire representation of the [DfuStatus].
+    pub fn to_bytes(&self) -> [u8; DFU_STATUS_LEN] {
+        let timeout = self.poll_timeout.to_le_bytes();
+        [
+            self.status as u8,
+            timeout[0],
+            timeout[1],
+            timeout[2],
+            self.state as u8,
+            self.string_index,
+        ]
+    }
+
+    /// Gets the [DfuStatusCode].
+    pub const fn status(&self) -> DfuStatusCode {
+        self.status
+    }
+
+    /// Gets the minimum time to wait before the next `DFU_GETSTATUS`, in milliseconds.
+    pub const fn poll_timeout(&self) -> u32 {
+        self.poll_timeout
+    }
+
+    /// Gets the [DfuState] the device enters after the response.
+    pub const fn state(&self) -> DfuState {
+        self.state
+    }
+
+    /// Gets the index of a string descriptor describing the status.
+    pub const fn string_index(&self) -> u8 {
+        self.string_index
+    }
+}
+
+impl fmt::Display for DfuStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""status": {}, "#, self.status)?;
+        write!(f, r#""poll_timeout": {}, "#, self.poll_timeout)?;
+        write!(f, r#""state": {}, "#, self.state)?;
+        write!(f, r#""string_index": {}"#, self.string_index)?;
+        write!(f, "}}")
+    }
+}
+
+impl std::error::Error for DfuStatus {}
+
+/// Represents the operating mode of a DFU interface.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DfuMode {
+    /// The device runs its application, and only accepts `DFU_DETACH`.
+    #[default]
+    Runtime = DFU_PROTOCOL_RUNTIME,
+    /// The device runs its bootloader, and accepts downloads and uploads.
+    Dfu = DFU_PROTOCOL_DFU,
+}
+
+impl DfuMode {
+    /// Creates a new [DfuMode].
+    pub const fn new() -> Self {
+        Self::Runtime
+    }
+}
+
+impl From<&DfuMode> for &'static str {
+    fn from(val: &DfuMode) -> Self {
+        match val {
+            DfuMode::Runtime => "runtime",
+            DfuMode::Dfu => "dfu",
+        }
+    }
+}
+
+impl fmt::Display for DfuMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Gets whether an interface is a DFU interface, in runtime or DFU mode.
+pub fn is_dfu_interface(desc: &InterfaceDescriptor) -> bool {
+    desc.class() == DFU_CLASS
+        && desc.subclass() == DFU_SUBCLASS
+        && matches!(desc.protocol(), DFU_PROTOCOL_RUNTIME | DFU_PROTOCOL_DFU)
+}
+
+/// DFU class driver over a [UsbBackend].
+pub struct Dfu<B: UsbBackend> {
+    backend: B,
+    iface: u8,
+    alt_setting: u8,
+    mode: DfuMode,
+    functional: DfuFunctional,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Dfu<B> {
+    /// Opens the first DFU interface of the active configuration.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let iface = config
+            .interfaces()
+            .iter()
+            .find(|i| is_dfu_interface(i))
+            .map(|i| i.number())
+            .ok_or(Error::NotFound("DFU interface".into()))?;
+
+        Self::open_interface(backend, iface, 0)
+    }
+
+    /// Opens a DFU interface, and selects its alternate setting.
+    ///
+    /// DFU mode devices describe each memory region as an alternate setting.
+    pub fn open_interface(backend: B, iface: u8, alt_setting: u8) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let desc = config
+            .interface(iface, alt_setting)
+            .filter(|i| is_dfu_interface(i))
+            .ok_or(Error::NotFound(format!(
+                "DFU interface {iface}, alternate setting {alt_setting}"
+            )))?;
+
+        // the functional descriptor may follow any alternate setting
+        let functional = config
+            .alt_settings(iface)
+            .find_map(|i| DfuFunctional::parse(i.extra()).ok())
+            .map_or_else(|| DfuFunctional::parse(config.extra()), Ok)?;
+
+        let mode = if desc.protocol() == DFU_PROTOCOL_DFU {
+            DfuMode::Dfu
+        } else {
+            DfuMode::Runtime
+        };
+
+        super::claim_detaching(&backend, iface)?;
+        if alt_setting != 0 {
+            backend.set_interface(&UsbfsSetInterface::create(iface as u32, alt_setting as u32))?;
+        }
+
+        Ok(Self {
+            backend,
+            iface,
+            alt_setting,
+            mode,
+            functional,
+            timeout: super::DEFAULT_TIMEOUT * 5,
+        })
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the selected alternate setting.
+    pub const fn alt_setting(&self) -> u8 {
+        self.alt_setting
+    }
+
+    /// Gets the [DfuMode] of the interface.
+    pub const fn mode(&self) -> DfuMode {
+        self.mode
+    }
+
+    /// Gets the [DfuFunctional] descriptor.
+    pub const fn functional(&self) -> &DfuFunctional {
+        &self.functional
+    }
+
+    /// Gets the Control transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the Control transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the Control transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Selects another alternate setting of the DFU interface.
+    pub fn set_alt_setting(&mut self, alt_setting: u8) -> Result<()> {
+        self.backend.set_interface(&UsbfsSetInterface::create(
+            self.iface as u32,
+            alt_setting as u32,
+        ))?;
+        self.alt_setting = alt_setting;
+        Ok(())
+    }
+
+    /// Sends `DFU_DETACH`, and resets the device unless it detaches itself.
+    ///
+    /// The device re-enumerates in DFU mode, invalidating the backend. See
+    /// [enter_dfu_mode](Dfu::enter_dfu_mode) to follow it on an [IoctlBackend].
+    pub fn detach(&self) -> Result<()> {
+        // wValue is the device's own reset timeout, the request keeps the driver timeout
+        let detach_timeout = self.functional.detach_timeout();
+        self.request(REQUEST_TYPE_CLASS_OUT, DFU_DETACH, detach_timeout, &mut [])?;
+
+        if !self.functional.will_detach() {
+            match self.backend.reset() {
+                Err(err) if err.errno() != Some(Errno::ENODEV as i32) => return Err(err),
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a `DFU_DNLOAD` block, an empty block ends the download.
+    pub fn download_block(&self, block: u16, data: &[u8]) -> Result<()> {
+        self.request(
+            REQUEST_TYPE_CLASS_OUT,
+            DFU_DNLOAD,
+            block,
+            &mut data.to_vec(),
+        )
+        .map(|_| ())
+    }
+
+    /// Reads a `DFU_UPLOAD` block, and returns its length.
+    ///
+    /// A block shorter than the buffer ends the upload.
+    pub fn upload_block(&self, block: u16, buf: &mut [u8]) -> Result<usize> {
+        self.request(REQUEST_TYPE_CLASS_IN, DFU_UPLOAD, block, buf)
+    }
+
+    /// Gets the [DfuStatus] with `DFU_GETSTATUS`.
+    pub fn get_status(&self) -> Result<DfuStatus> {
+        let mut buf = [0u8; DFU_STATUS_LEN];
+        let len = self.request(REQUEST_TYPE_CLASS_IN, DFU_GETSTATUS, 0, &mut buf)?;
+        DfuStatus::parse(&buf[..len])
+    }
+
+    /// Clears the error status with `DFU_CLRSTATUS`, returning to `dfuIDLE`.
+    pub fn clear_status(&self) -> Result<()> {
+        self.request(REQUEST_TYPE_CLASS_OUT, DFU_CLRSTATUS, 0, &mut [])
+            .map(|_| ())
+    }
+
+    /// Gets the [DfuState] with `DFU_GETSTATE`, without a state transition.
+    pub fn get_state(&self) -> Result<DfuState> {
+        let mut buf = [0u8];
+        let len = self.request(REQUEST_TYPE_CLASS_IN, DFU_GETSTATE, 0, &mut buf)?;
+        match len {
+            1 => DfuState::create(buf[0]).ok_or(Error::InvalidMessage(format!(
+                "invalid DFU state: {}",
+                buf[0]
+            ))),
+            _ => Err(Error::InvalidMessage("empty DFU state".into())),
+        }
+    }
+
+    /// Aborts a download or upload with `DFU_ABORT`, returning to `dfuIDLE`.
+    pub fn abort(&self) -> Result<()> {
+        self.request(REQUEST_TYPE_CLASS_OUT, DFU_ABORT, 0, &mut [])
+            .map(|_| ())
+    }
+
+    /// Brings the device to `dfuIDLE`, clearing errors and aborting transfers in progress.
+    pub fn ensure_idle(&self) -> Result<()> {
+        if self.mode != DfuMode::Dfu {
+            return Err(Error::InvalidArgument(
+                "DFU interface in runtime mode".into(),
+            ));
+        }
+
+        match self.get_status()?.state() {
+            DfuState::DfuIdle => return Ok(()),
+            DfuState::Error => self.clear_status()?,
+            _ => self.abort()?,
+        }
+
+        match self.get_status()? {
+            status if status.state() == DfuState::DfuIdle => Ok(()),
+            status => Err(Error::class("DFU", status)),
+        }
+    }
+
+    /// Polls `DFU_GETSTATUS` while the device is busy, waiting the poll timeout between
+    /// requests.
+    ///
+    /// Returns the first status out of the synchronization and busy states, or [Error::Class]
+    /// if the device reports an error.
+    pub fn poll_status(&self) -> Result<DfuStatus> {
+        loop {
+            let status = self.get_status()?;
+            if status.status() != DfuStatusCode::Ok || status.state() == DfuState::Error {
+                return Err(Error::class("DFU", status));
+            }
+
+            match status.state() {
+                DfuState::DownloadSync
+                | DfuState::DownloadBusy
+                | DfuState::ManifestSync
+                | DfuState::Manifest => {
+                    thread::sleep(Duration::from_millis(status.poll_timeout() as u64));
+                }
+                _ => return Ok(status),
+            }
+        }
+    }
+
+    /// Downloads a firmware image, and runs the manifestation phase.
+    ///
+    /// Non manifestation tolerant devices end in `dfuMANIFEST-WAIT-RESET`, see
+    /// [leave_dfu_mode](Dfu::leave_dfu_mode).
+    pub fn download(&self, firmware: &[u8]) -> Result<()> {
+        if !self.functional.can_download() {
+            return Err(Error::InvalidArgument("DFU download not supported".into()));
+        }
+        self.ensure_idle()?;
+
+        let mut block = 0u16;
+        for chunk in firmware.chunks(self.transfer_size()) {
+            self.download_block(block, chunk)?;
+            self.expect_state(DfuState::DownloadIdle)?;
+            block = block.wrapping_add(1);
+        }
+
+        self.download_block(block, &[])?;
+        self.manifest()
+    }
+
+    /// Uploads up to `len` bytes of firmware.
+    pub fn upload(&self, len: usize) -> Result<Vec<u8>> {
+        if !self.functional.can_upload() {
+            return Err(Error::InvalidArgument("DFU upload not supported".into()));
+        }
+        self.ensure_idle()?;
+        self.upload_blocks(0, len)
+    }
+
+    /// Releases the interface, and converts the [Dfu] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn upload_blocks(&self, first_block: u16, len: usize) -> Result<Vec<u8>> {
+        let size = self.transfer_size();
+        let mut data = Vec::with_capacity(len);
+        let mut block = first_block;
+
+        while data.len() < len {
+            let pos = data.len();
+            data.resize(pos + size, 0);
+            let read = self.upload_block(block, &mut data[pos..])?;
+            data.truncate(pos + read);
+
+            // a short block ends the upload, and returns the device to dfuIDLE
+            if read < size {
+                data.truncate(len);
+                return Ok(data);
+            }
+            block = block.wrapping_add(1);
+        }
+
+        data.truncate(len);
+        self.abort()?;
+        Ok(data)
+    }
+
+    fn manifest(&self) -> Result<()> {
+        match self.poll_status() {
+            Ok(status) if status.state() == DfuState::DfuIdle => Ok(()),
+            Ok(status) if status.state() == DfuState::ManifestWaitReset => Ok(()),
+            Ok(status) => Err(Error::class("DFU", status)),
+            // devices that are not manifestation tolerant may stop answering
+            Err(err) if !self.functional.manifestation_tolerant() && err.errno().is_some() => {
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn expect_state(&self, state: DfuState) -> Result<DfuStatus> {
+        match self.poll_status()? {
+            status if status.state() == state => Ok(status),
+            status => Err(Error::class("DFU", status)),
+        }
+    }
+
+    fn transfer_size(&self) -> usize {
+        match self.functional.transfer_size() {
+            0 => 64,
+            size => size as usize,
+        }
+    }
+
+    fn request(&self, request_type: u8, request: u8, value: u16, data: &mut [u8]) -> Result<usize> {
+        class_request(
+            &self.backend,
+            request_type,
+            request,
+            value,
+            self.iface as u16,
+            data,
+            self.timeout,
+        )
+    }
+}
+
+impl Dfu<IoctlBackend> {
+    /// Switches a runtime mode device to DFU mode, and opens its DFU interface.
+    ///
+    /// Sends `DFU_DETACH`, resets the device if needed, then waits up to `timeout` for it to
+    /// re-enumerate at the same port. Devices already in DFU mode are returned unchanged.
+    pub fn enter_dfu_mode(self, sysfs: &Sysfs, timeout: Duration) -> Result<Self> {
+        if self.mode == DfuMode::Dfu {
+            return Ok(self);
+        }
+
+        let (port_path, bus_dev) = self.port_path(sysfs)?;
+        self.detach()?;
+
+        let device = crate::device::reopen_at_port(sysfs, &port_path, bus_dev, timeout)?;
+        Self::open(IoctlBackend::new(device))
+    }
+
+    /// Resets a DFU mode device into its application, and returns the re-enumerated device.
+    ///
+    /// Devices that detach by themselves after manifestation are only waited for.
+    pub fn leave_dfu_mode(self, sysfs: &Sysfs, timeout: Duration) -> Result<IoctlBackend> {
+        let (port_path, bus_dev) = self.port_path(sysfs)?;
+        let will_detach = self.functional.will_detach();
+        let backend = self.backend;
+        // the interface may be gone already
+        backend.release_interface(self.iface as u32).ok();
+
+        if !will_detach {
+            if let Err(err) = backend.reset() {
+                if err.errno() != Some(Errno::ENODEV as i32) {
+                    return Err(err);
+                }
+            }
+        }
+
+        crate::device::reopen_at_port(sysfs, &port_path, bus_dev, timeout).map(IoctlBackend::new)
+    }
+
+    fn port_path(&self, sysfs: &Sysfs) -> Result<(String, (u8, u8))> {
+        let device = self.backend.device();
+        device
+            .sysfs_device(sysfs)?
+            .map(|d| {
+                (
+                    d.port_path().to_owned(),
+                    (device.bus_num(), device.dev_num()),
+                )
+            })
+            .ok_or(Error::NotFound(format!(
+                "port path for bus {} device {}",
+                device.bus_num(),
+                device.dev_num()
+            )))
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Dfu<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dfu")
+            .field("iface", &self.iface)
+            .field("alt_setting", &self.alt_setting)
+            .field("mode", &self.mode)
+            .field("functional", &self.functional)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // DFU mode interface 0, downloads and uploads of 64 bytes, manifestation tolerant
+    pub(crate) fn dfu_descriptors(dfu_version: u16) -> Vec<u8> {
+        let version = dfu_version.to_le_bytes();
+        vec![
+            0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x83, 0x04, 0x11, 0xdf, 0x00, 0x22,
+            0x01, 0x02, 0x03, 0x01, //
+            0x09, 0x02, 0x1b, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+            0x09, 0x04, 0x00, 0x00, 0x00, 0xfe, 0x01, 0x02, 0x04, //
+            0x09, 0x21, 0x07, 0xff, 0x00, 0x40, 0x00, version[0], version[1],
+        ]
+    }
+
+    pub(crate) fn status(state: DfuState) -> MockControl {
+        MockControl::create(REQUEST_TYPE_CLASS_IN, DFU_GETSTATUS, 0, 0).with_response(
+            MockResponse::Data(
+                DfuStatus::create(DfuStatusCode::Ok, 0, state)
+                    .to_bytes()
+                    .into(),
+            ),
+        )
+    }
+
+    pub(crate) fn dnload(block: u16, data: &[u8]) -> MockControl {
+        MockControl::create(REQUEST_TYPE_CLASS_OUT, DFU_DNLOAD, block, 0).with_data(data.to_vec())
+    }
+
+    #[test]
+    fn test_dfu_download() -> Result<()> {
+        let firmware: Vec<u8> = (0..100u8).collect();
+
+        let dev = MockDevice::new()
+            .with_descriptors(dfu_descriptors(0x0110))
+            .with_configuration(1)
+            .with_control(status(DfuState::Error))
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                DFU_CLRSTATUS,
+                0,
+                0,
+            ))
+            .with_control(status(DfuState::DfuIdle))
+            .with_control(dnload(0, &firmware[..64]))
+            .with_control(status(DfuState::DownloadBusy))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(dnload(1, &firmware[64..]))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(dnload(2, &[]))
+            .with_control(status(DfuState::Manifest))
+            .with_control(status(DfuState::DfuIdle))
+            // upload, ended by a short block
+            .with_control(status(DfuState::DfuIdle))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, DFU_UPLOAD, 0, 0)
+                    .with_response(MockResponse::Data(firmware[..64].to_vec())),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, DFU_UPLOAD, 1, 0)
+                    .with_response(MockResponse::Data(firmware[64..].to_vec())),
+            )
+            // the detach timeout of the functional descriptor, whatever the request timeout
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                DFU_DETACH,
+                0x00ff,
+                0,
+            ));
+
+        let dfu = Dfu::open(dev)?.with_timeout(100);
+        assert_eq!(dfu.mode(), DfuMode::Dfu);
+        assert!(dfu.functional().manifestation_tolerant());
+        assert!(!dfu.functional().is_dfuse());
+
+        dfu.download(&firmware)?;
+        assert_eq!(dfu.upload(4096)?, firmware);
+        dfu.detach()?;
+
+        dfu.close()?.verify()
+    }
+}
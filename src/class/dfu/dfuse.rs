@@ -0,0 +1,595 @@
+//! STMicroelectronics DfuSe extensions.
+//!
+//! DfuSe devices reserve `DFU_DNLOAD` block 0 for commands (set address pointer, erase, read
+//! unprotect), and transfer data from block 2 on, at the address pointer. Each alternate setting
+//! describes a memory region in its interface string, e.g.
+//! `@Internal Flash  /0x08000000/04*016Kg,01*064Kg,07*128Kg`.
+//!
+//! DfuSe `.dfu` files carry a [DfuseImage], with elements to download at absolute addresses.
+
+use std::fmt;
+
+use super::{Dfu, DfuState};
+use crate::{Error, Result, UsbBackend};
+
+pub const DFUSE_CMD_GET_COMMANDS: u8 = 0x00;
+pub const DFUSE_CMD_SET_ADDRESS_POINTER: u8 = 0x21;
+pub const DFUSE_CMD_ERASE: u8 = 0x41;
+pub const DFUSE_CMD_READ_UNPROTECT: u8 = 0x92;
+
+pub const DFUSE_PREFIX_SIGNATURE: [u8; 5] = *b"DfuSe";
+pub const DFUSE_TARGET_SIGNATURE: [u8; 6] = *b"Target";
+pub const DFUSE_PREFIX_LEN: usize = 11;
+pub const DFUSE_TARGET_PREFIX_LEN: usize = 274;
+pub const DFUSE_ELEMENT_HEADER_LEN: usize = 8;
+
+// first DFU_DNLOAD / DFU_UPLOAD block carrying data
+const DFUSE_FIRST_DATA_BLOCK: u16 = 2;
+const TARGET_NAME_LEN: usize = 255;
+
+const MEMORY_READABLE: u8 = 0x01;
+const MEMORY_ERASABLE: u8 = 0x02;
+const MEMORY_WRITABLE: u8 = 0x04;
+
+/// Represents a run of equally sized sectors in a [DfuseMemoryLayout].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuseSegment {
+    start: u32,
+    sectors: u32,
+    sector_size: u32,
+    attributes: u8,
+}
+
+impl DfuseSegment {
+    /// Creates a new [DfuseSegment] from the provided parameters.
+    pub const fn create(start: u32, sectors: u32, sector_size: u32, attributes: u8) -> Self {
+        Self {
+            start,
+            sectors,
+            sector_size,
+            attributes,
+        }
+    }
+
+    /// Gets the start address.
+    pub const fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Gets the end address, exclusive.
+    pub const fn end(&self) -> u64 {
+        self.start as u64 + self.sectors as u64 * self.sector_size as u64
+    }
+
+    /// Gets the number of sectors.
+    pub const fn sectors(&self) -> u32 {
+        self.sectors
+    }
+
+    /// Gets the sector size, in bytes.
+    pub const fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    /// Gets the raw memory type attributes.
+    pub const fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Gets whether the sectors can be read.
+    pub const fn readable(&self) -> bool {
+        self.attributes & MEMORY_READABLE != 0
+    }
+
+    /// Gets whether the sectors can be erased.
+    pub const fn erasable(&self) -> bool {
+        self.attributes & MEMORY_ERASABLE != 0
+    }
+
+    /// Gets whether the sectors can be written.
+    pub const fn writable(&self) -> bool {
+        self.attributes & MEMORY_WRITABLE != 0
+    }
+
+    /// Gets whether the address is in the segment.
+    pub const fn contains(&self, address: u32) -> bool {
+        address >= self.start && (address as u64) < self.end()
+    }
+}
+
+impl fmt::Display for DfuseSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""start": {}, "#, self.start)?;
+        write!(f, r#""sectors": {}, "#, self.sectors)?;
+        write!(f, r#""sector_size": {}, "#, self.sector_size)?;
+        write!(f, r#""attributes": {}"#, self.attributes)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the memory layout of a DfuSe alternate setting.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuseMemoryLayout {
+    name: String,
+    segments: Vec<DfuseSegment>,
+}
+
+impl DfuseMemoryLayout {
+    /// Creates a new [DfuseMemoryLayout].
+    pub const fn new() -> Self {
+        Self {
+            name: String::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Parses the [DfuseMemoryLayout] from an interface string.
+    pub fn parse(desc: &str) -> Result<Self> {
+        let invalid = || Error::InvalidDescriptor(format!("invalid DfuSe memory layout: {desc}"));
+
+        let mut fields = desc.strip_prefix('@').ok_or_else(invalid)?.split('/');
+        let name = fields.next().unwrap_or_default().trim().to_string();
+        let mut segments = Vec::new();
+
+        while let Some(address) = fields.next() {
+            let mut start = address
+                .trim()
+                .strip_prefix("0x")
+                .and_then(|a| u32::from_str_radix(a, 16).ok())
+                .ok_or_else(invalid)?;
+
+            for sector in fields.next().ok_or_else(invalid)?.split(',') {
+                let (count, size) = sector.trim().split_once('*').ok_or_else(invalid)?;
+                let count: u32 = count.parse().map_err(|_| invalid())?;
+
+                // size digits, an optional unit, then the memory type letter
+                let digits = size
+                    .find(|c: char| !c.is_ascii_digit())
+                    .ok_or_else(invalid)?;
+                let sector_size: u32 = size[..digits].parse().map_err(|_| invalid())?;
+                let mut rest = size[digits..].chars();
+                let (unit, kind) = match (rest.next(), rest.next()) {
+                    (Some('K'), Some(kind)) => (1024, kind),
+                    (Some('M'), Some(kind)) => (1024 * 1024, kind),
+                    (Some('B' | ' '), Some(kind)) | (Some(kind), None) => (1, kind),
+                    _ => return Err(invalid()),
+                };
+                let sector_size = sector_size.checked_mul(unit).ok_or_else(invalid)?;
+                if !('a'..='g').contains(&kind) {
+                    return Err(invalid());
+                }
+
+                segments.push(DfuseSegment::create(
+                    start,
+                    count,
+                    sector_size,
+                    kind as u8 & 7,
+                ));
+                start = start.wrapping_add(count.wrapping_mul(sector_size));
+            }
+        }
+
+        Ok(Self { name, segments })
+    }
+
+    /// Gets the memory region name.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// Gets the list of [DfuseSegment]s.
+    pub fn segments(&self) -> &[DfuseSegment] {
+        self.segments.as_ref()
+    }
+
+    /// Gets the [DfuseSegment] containing the address.
+    pub fn segment(&self, address: u32) -> Option<&DfuseSegment> {
+        self.segments.iter().find(|s| s.contains(address))
+    }
+
+    /// Gets the start addresses of the erasable sectors overlapping a range.
+    pub fn erase_pages(&self, address: u32, len: usize) -> Result<Vec<u32>> {
+        let end = address as u64 + len as u64;
+        let mut pages = Vec::new();
+        let mut addr = address as u64;
+
+        while addr < end {
+            let segment = u32::try_from(addr)
+                .ok()
+                .and_then(|a| self.segment(a))
+                .ok_or(Error::InvalidArgument(format!(
+                    "address 0x{addr:08x} outside of {}",
+                    self.name
+                )))?;
+            if !segment.writable() {
+                return Err(Error::InvalidArgument(format!(
+                    "address 0x{addr:08x} is not writable"
+                )));
+            }
+
+            let size = segment.sector_size().max(1) as u64;
+            let page = segment.start() as u64 + (addr - segment.start() as u64) / size * size;
+            if segment.erasable() {
+                pages.push(page as u32);
+            }
+            addr = page + size;
+        }
+
+        Ok(pages)
+    }
+}
+
+impl fmt::Display for DfuseMemoryLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#"{{"name": "{}", "segments": ["#, self.name)?;
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{segment}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents a DfuSe image element: data to download at an address.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuseElement {
+    address: u32,
+    data: Vec<u8>,
+}
+
+impl DfuseElement {
+    /// Creates a new [DfuseElement] from the provided parameters.
+    pub fn create(address: u32, data: &[u8]) -> Self {
+        Self {
+            address,
+            data: data.into(),
+        }
+    }
+
+    /// Gets the start address.
+    pub const fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets the element data.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+}
+
+/// Represents a DfuSe image target: the elements of an alternate setting.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuseTarget {
+    alt_setting: u8,
+    name: Option<String>,
+    elements: Vec<DfuseElement>,
+}
+
+impl DfuseTarget {
+    /// Creates a new [DfuseTarget] from the provided parameters.
+    pub fn create(alt_setting: u8, name: Option<&str>, elements: Vec<DfuseElement>) -> Self {
+        Self {
+            alt_setting,
+            name: name.map(String::from),
+            elements,
+        }
+    }
+
+    /// Gets the alternate setting the elements are downloaded to.
+    pub const fn alt_setting(&self) -> u8 {
+        self.alt_setting
+    }
+
+    /// Gets the target name, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Gets the list of [DfuseElement]s.
+    pub fn elements(&self) -> &[DfuseElement] {
+        self.elements.as_ref()
+    }
+}
+
+/// Represents the DfuSe image of a DfuSe `.dfu` file payload.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuseImage {
+    targets: Vec<DfuseTarget>,
+}
+
+impl DfuseImage {
+    /// Creates a new [DfuseImage] from the provided targets.
+    pub fn create(targets: Vec<DfuseTarget>) -> Self {
+        Self { targets }
+    }
+
+    /// Parses the [DfuseImage] from a `.dfu` file payload, without the suffix.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let short = |what: &str| Error::InvalidMessage(format!("DfuSe {what} truncated"));
+        let read_u32 = |off: usize| {
+            buf.get(off..off + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+
+        if buf.len() < DFUSE_PREFIX_LEN || buf[..5] != DFUSE_PREFIX_SIGNATURE {
+            return Err(Error::InvalidMessage("missing DfuSe prefix".into()));
+        }
+
+        let num_targets = buf[10];
+        let mut pos = DFUSE_PREFIX_LEN;
+        let mut targets = Vec::with_capacity(num_targets as usize);
+
+        for _ in 0..num_targets {
+            let prefix = buf
+                .get(pos..pos + DFUSE_TARGET_PREFIX_LEN)
+                .ok_or_else(|| short("target prefix"))?;
+            if prefix[..6] != DFUSE_TARGET_SIGNATURE {
+                return Err(Error::InvalidMessage(
+                    "missing DfuSe target signature".into(),
+                ));
+            }
+
+            let named = read_u32(pos + 7).unwrap_or_default() != 0;
+            let name = &prefix[11..11 + TARGET_NAME_LEN];
+            let name_len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            let num_elements = read_u32(pos + 270).unwrap_or_default();
+            pos += DFUSE_TARGET_PREFIX_LEN;
+
+            let mut elements = Vec::new();
+            for _ in 0..num_elements {
+                let address = read_u32(pos).ok_or_else(|| short("element"))?;
+                let len = read_u32(pos + 4).ok_or_else(|| short("element"))? as usize;
+                pos += DFUSE_ELEMENT_HEADER_LEN;
+                let data = buf.get(pos..pos + len).ok_or_else(|| short("element"))?;
+                elements.push(DfuseElement::create(address, data));
+                pos += len;
+            }
+
+            targets.push(DfuseTarget {
+                alt_setting: prefix[6],
+                name: named.then(|| String::from_utf8_lossy(&name[..name_len]).into_owned()),
+                elements,
+            });
+        }
+
+        Ok(Self { targets })
+    }
+
+    /// Gets the list of [DfuseTarget]s.
+    pub fn targets(&self) -> &[DfuseTarget] {
+        self.targets.as_ref()
+    }
+
+    /// Gets the payload representation of the [DfuseImage].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&DFUSE_PREFIX_SIGNATURE);
+        buf.push(0x01);
+        // image size, filled in below
+        buf.extend_from_slice(&[0u8; 4]);
+        buf.push(self.targets.len() as u8);
+
+        for target in self.targets.iter() {
+            let size: usize = target
+                .elements
+                .iter()
+                .map(|e| DFUSE_ELEMENT_HEADER_LEN + e.data.len())
+                .sum();
+            let mut name = [0u8; TARGET_NAME_LEN];
+            if let Some(n) = target.name.as_deref() {
+                let len = n.len().min(TARGET_NAME_LEN - 1);
+                name[..len].copy_from_slice(&n.as_bytes()[..len]);
+            }
+
+            buf.extend_from_slice(&DFUSE_TARGET_SIGNATURE);
+            buf.push(target.alt_setting);
+            buf.extend_from_slice(&(target.name.is_some() as u32).to_le_bytes());
+            buf.extend_from_slice(&name);
+            buf.extend_from_slice(&(size as u32).to_le_bytes());
+            buf.extend_from_slice(&(target.elements.len() as u32).to_le_bytes());
+
+            for element in target.elements.iter() {
+                buf.extend_from_slice(&element.address.to_le_bytes());
+                buf.extend_from_slice(&(element.data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&element.data);
+            }
+        }
+
+        let len = (buf.len() as u32).to_le_bytes();
+        buf[6..10].copy_from_slice(&len);
+        buf
+    }
+}
+
+impl<B: UsbBackend> Dfu<B> {
+    /// Gets the [DfuseMemoryLayout] of the selected alternate setting, from its interface string.
+    pub fn dfuse_memory_layout(&self) -> Result<DfuseMemoryLayout> {
+        let config = crate::class::active_config(self.backend())?;
+        let index = config
+            .interface(self.interface(), self.alt_setting())
+            .map(|i| i.interface_index())
+            .filter(|&i| i != 0)
+            .ok_or(Error::NotFound("DfuSe memory layout string".into()))?;
+
+        DfuseMemoryLayout::parse(&crate::class::string_descriptor(
+            self.backend(),
+            index,
+            crate::class::LANG_ID_EN_US,
+        )?)
+    }
+
+    /// Runs a DfuSe command in `DFU_DNLOAD` block 0, and waits for its completion.
+    pub fn dfuse_command(&self, command: &[u8]) -> Result<()> {
+        self.download_block(0, command)?;
+        self.expect_state(DfuState::DownloadIdle).map(|_| ())
+    }
+
+    /// Sets the address pointer of the following data blocks.
+    pub fn dfuse_set_address(&self, address: u32) -> Result<()> {
+        self.dfuse_command(&dfuse_address_command(
+            DFUSE_CMD_SET_ADDRESS_POINTER,
+            address,
+        ))
+    }
+
+    /// Erases the sector containing the address.
+    pub fn dfuse_erase_page(&self, address: u32) -> Result<()> {
+        self.dfuse_command(&dfuse_address_command(DFUSE_CMD_ERASE, address))
+    }
+
+    /// Erases the whole memory of the selected alternate setting.
+    pub fn dfuse_mass_erase(&self) -> Result<()> {
+        self.dfuse_command(&[DFUSE_CMD_ERASE])
+    }
+
+    /// Removes the read protection, which mass erases the memory and resets the device.
+    pub fn dfuse_read_unprotect(&self) -> Result<()> {
+        self.download_block(0, &[DFUSE_CMD_READ_UNPROTECT])?;
+        // the device resets once the command runs
+        self.get_status().map(|_| ())
+    }
+
+    /// Erases the sectors covered by the data, and downloads it at the address.
+    pub fn dfuse_download(
+        &self,
+        layout: &DfuseMemoryLayout,
+        address: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        if !self.functional().is_dfuse() {
+            return Err(Error::InvalidArgument("not a DfuSe device".into()));
+        }
+        self.ensure_idle()?;
+
+        for page in layout.erase_pages(address, data.len())? {
+            self.dfuse_erase_page(page)?;
+        }
+        self.dfuse_set_address(address)?;
+
+        let mut block = DFUSE_FIRST_DATA_BLOCK;
+        for chunk in data.chunks(self.transfer_size()) {
+            self.download_block(block, chunk)?;
+            self.expect_state(DfuState::DownloadIdle)?;
+            block = block.wrapping_add(1);
+        }
+
+        self.abort()
+    }
+
+    /// Downloads every element of a [DfuseImage], selecting the alternate setting of each target.
+    pub fn dfuse_download_image(&mut self, image: &DfuseImage) -> Result<()> {
+        for target in image.targets() {
+            if target.alt_setting() != self.alt_setting() {
+                self.set_alt_setting(target.alt_setting())?;
+            }
+
+            let layout = self.dfuse_memory_layout()?;
+            for element in target.elements() {
+                self.dfuse_download(&layout, element.address(), element.data())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads `len` bytes of memory from the address.
+    pub fn dfuse_upload(&self, address: u32, len: usize) -> Result<Vec<u8>> {
+        self.ensure_idle()?;
+        self.dfuse_set_address(address)?;
+        // uploads start from dfuIDLE
+        self.abort()?;
+        self.upload_blocks(DFUSE_FIRST_DATA_BLOCK, len)
+    }
+
+    /// Leaves DFU mode, optionally jumping to the address.
+    ///
+    /// The device re-enumerates into its application, see [leave_dfu_mode](Dfu::leave_dfu_mode).
+    pub fn dfuse_leave(&self, address: Option<u32>) -> Result<()> {
+        self.ensure_idle()?;
+        if let Some(address) = address {
+            self.dfuse_set_address(address)?;
+        }
+
+        self.download_block(0, &[])?;
+        // the device may reset before answering
+        self.get_status().ok();
+        Ok(())
+    }
+}
+
+fn dfuse_address_command(command: u8, address: u32) -> [u8; 5] {
+    let addr = address.to_le_bytes();
+    [command, addr[0], addr[1], addr[2], addr[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::dfu::tests::{dfu_descriptors, dnload, status};
+    use crate::class::dfu::{DfuFile, DfuSuffix, DFU_ABORT, DFU_VERSION_DFUSE};
+    use crate::class::REQUEST_TYPE_CLASS_OUT;
+    use crate::{MockControl, MockDevice};
+
+    #[test]
+    fn test_dfuse() -> Result<()> {
+        let layout = DfuseMemoryLayout::parse("@Internal Flash  /0x08000000/04*016Kg,01*064Kg")?;
+        assert_eq!(layout.name(), "Internal Flash");
+        assert!(DfuseMemoryLayout::parse("@Flash/0x08000000/01*8192Mg").is_err());
+        assert_eq!(
+            layout.segments(),
+            [
+                DfuseSegment::create(0x0800_0000, 4, 0x4000, 7),
+                DfuseSegment::create(0x0801_0000, 1, 0x10000, 7),
+            ]
+        );
+        assert_eq!(
+            layout.erase_pages(0x0800_3ff0, 0x20)?,
+            [0x0800_0000, 0x0800_4000]
+        );
+
+        let image = DfuseImage::create(vec![DfuseTarget::create(
+            0,
+            Some("ST..."),
+            vec![DfuseElement::create(0x0800_3ff0, &[0xa5; 0x20])],
+        )]);
+        let file = DfuFile::create(
+            &image.to_bytes(),
+            DfuSuffix::create(0x0483, 0xdf11, 0x2200, DFU_VERSION_DFUSE),
+        );
+        let file = DfuFile::parse(&file.to_bytes())?;
+        assert!(file.is_dfuse());
+        assert_eq!(DfuseImage::parse(file.payload())?, image);
+
+        let element = &image.targets()[0].elements()[0];
+        let dev = MockDevice::new()
+            .with_descriptors(dfu_descriptors(DFU_VERSION_DFUSE))
+            .with_configuration(1)
+            .with_control(status(DfuState::DfuIdle))
+            .with_control(dnload(0, &[DFUSE_CMD_ERASE, 0x00, 0x00, 0x00, 0x08]))
+            .with_control(status(DfuState::DownloadBusy))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(dnload(0, &[DFUSE_CMD_ERASE, 0x00, 0x40, 0x00, 0x08]))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(dnload(
+                0,
+                &[DFUSE_CMD_SET_ADDRESS_POINTER, 0xf0, 0x3f, 0x00, 0x08],
+            ))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(dnload(2, element.data()))
+            .with_control(status(DfuState::DownloadBusy))
+            .with_control(status(DfuState::DownloadIdle))
+            .with_control(MockControl::create(REQUEST_TYPE_CLASS_OUT, DFU_ABORT, 0, 0));
+
+        let dfu = Dfu::open(dev)?;
+        dfu.dfuse_download(&layout, element.address(), element.data())?;
+
+        dfu.close()?.verify()
+    }
+}
@@ -0,0 +1,267 @@
+//! `.dfu` file suffix parsing and CRC validation.
+//!
+//! DFU files end with a 16-byte suffix, holding the target device IDs, the DFU version of the
+//! payload format, and a CRC-32 of the whole file but the CRC field itself.
+
+use std::fmt;
+
+use crate::descriptor::read_u16;
+use crate::{Error, Result};
+
+pub const DFU_SUFFIX_LEN: usize = 16;
+pub const DFU_SUFFIX_SIGNATURE: [u8; 3] = *b"UFD";
+
+/// ID matching any vendor, product, or device release.
+pub const DFU_SUFFIX_ANY_ID: u16 = 0xffff;
+
+/// Computes the CRC used by DFU file suffixes: reflected CRC-32, without the final inversion.
+pub fn dfu_crc32(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0xffff_ffff, |crc, &b| crc32_update(crc, b))
+}
+
+/// Represents the DFU file suffix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuSuffix {
+    device_version: u16,
+    product_id: u16,
+    vendor_id: u16,
+    dfu_version: u16,
+    crc: u32,
+}
+
+impl DfuSuffix {
+    /// Creates a new [DfuSuffix], matching any device.
+    pub const fn new() -> Self {
+        Self {
+            device_version: DFU_SUFFIX_ANY_ID,
+            product_id: DFU_SUFFIX_ANY_ID,
+            vendor_id: DFU_SUFFIX_ANY_ID,
+            dfu_version: 0x0100,
+            crc: 0,
+        }
+    }
+
+    /// Creates a new [DfuSuffix] from the provided parameters.
+    pub const fn create(
+        vendor_id: u16,
+        product_id: u16,
+        device_version: u16,
+        dfu_version: u16,
+    ) -> Self {
+        Self {
+            device_version,
+            product_id,
+            vendor_id,
+            dfu_version,
+            crc: 0,
+        }
+    }
+
+    /// Parses the [DfuSuffix] at the end of a DFU file, and validates its CRC.
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        if file.len() < DFU_SUFFIX_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "DFU file too short: {}",
+                file.len()
+            )));
+        }
+
+        let suffix = &file[file.len() - DFU_SUFFIX_LEN..];
+        if suffix[8..11] != DFU_SUFFIX_SIGNATURE {
+            return Err(Error::InvalidMessage("missing DFU suffix signature".into()));
+        }
+
+        let len = suffix[11] as usize;
+        if !(DFU_SUFFIX_LEN..=file.len()).contains(&len) {
+            return Err(Error::InvalidMessage(format!(
+                "invalid DFU suffix length: {len}"
+            )));
+        }
+
+        let crc = u32::from_le_bytes([suffix[12], suffix[13], suffix[14], suffix[15]]);
+        let exp_crc = dfu_crc32(&file[..file.len() - 4]);
+        if crc != exp_crc {
+            return Err(Error::InvalidMessage(format!(
+                "DFU file CRC mismatch, have: 0x{crc:08x}, expected: 0x{exp_crc:08x}"
+            )));
+        }
+
+        Ok(Self {
+            device_version: read_u16(suffix, 0),
+            product_id: read_u16(suffix, 2),
+            vendor_id: read_u16(suffix, 4),
+            dfu_version: read_u16(suffix, 6),
+            crc,
+        })
+    }
+
+    /// Gets the wire representation of the [DfuSuffix], with the CRC of the provided payload.
+    pub fn to_bytes(&self, payload: &[u8]) -> [u8; DFU_SUFFIX_LEN] {
+        let mut buf = [0u8; DFU_SUFFIX_LEN];
+        buf[..2].copy_from_slice(&self.device_version.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.product_id.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.vendor_id.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.dfu_version.to_le_bytes());
+        buf[8..11].copy_from_slice(&DFU_SUFFIX_SIGNATURE);
+        buf[11] = DFU_SUFFIX_LEN as u8;
+
+        let crc = payload
+            .iter()
+            .chain(&buf[..12])
+            .fold(0xffff_ffff, |crc, &b| crc32_update(crc, b));
+        buf[12..].copy_from_slice(&crc.to_le_bytes());
+
+        buf
+    }
+
+    /// Gets the device release number, in BCD.
+    pub const fn device_version(&self) -> u16 {
+        self.device_version
+    }
+
+    /// Gets the product ID.
+    pub const fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Gets the vendor ID.
+    pub const fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// Gets the DFU version of the payload format, `0x011a` for DfuSe images.
+    pub const fn dfu_version(&self) -> u16 {
+        self.dfu_version
+    }
+
+    /// Gets the file CRC.
+    pub const fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Gets whether the file targets the device, `0xffff` IDs matching any device.
+    pub const fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        (self.vendor_id == DFU_SUFFIX_ANY_ID || self.vendor_id == vendor_id)
+            && (self.product_id == DFU_SUFFIX_ANY_ID || self.product_id == product_id)
+    }
+}
+
+impl Default for DfuSuffix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for DfuSuffix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""device_version": {}, "#, self.device_version)?;
+        write!(f, r#""product_id": {}, "#, self.product_id)?;
+        write!(f, r#""vendor_id": {}, "#, self.vendor_id)?;
+        write!(f, r#""dfu_version": {}, "#, self.dfu_version)?;
+        write!(f, r#""crc": {}"#, self.crc)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a DFU file: the firmware payload, and its validated [DfuSuffix].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DfuFile {
+    payload: Vec<u8>,
+    suffix: DfuSuffix,
+}
+
+impl DfuFile {
+    /// Creates a new [DfuFile] from the provided parameters.
+    pub fn create(payload: &[u8], suffix: DfuSuffix) -> Self {
+        Self {
+            payload: payload.into(),
+            suffix,
+        }
+    }
+
+    /// Parses a [DfuFile], validating its suffix and CRC.
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        let suffix = DfuSuffix::parse(file)?;
+        let len = file[file.len() - DFU_SUFFIX_LEN + 11] as usize;
+
+        Ok(Self {
+            payload: file[..file.len() - len].into(),
+            suffix,
+        })
+    }
+
+    /// Gets the firmware payload, without the suffix.
+    pub fn payload(&self) -> &[u8] {
+        self.payload.as_ref()
+    }
+
+    /// Gets the [DfuSuffix].
+    pub const fn suffix(&self) -> &DfuSuffix {
+        &self.suffix
+    }
+
+    /// Gets whether the payload is a DfuSe image.
+    pub const fn is_dfuse(&self) -> bool {
+        self.suffix.dfu_version() == super::DFU_VERSION_DFUSE
+    }
+
+    /// Gets the file representation of the [DfuFile], computing the CRC.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.payload.clone();
+        buf.extend_from_slice(&self.suffix.to_bytes(&self.payload));
+        buf
+    }
+}
+
+impl fmt::Display for DfuFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"payload_len": {}, "suffix": {}}}"#,
+            self.payload.len(),
+            self.suffix
+        )
+    }
+}
+
+fn crc32_update(crc: u32, b: u8) -> u32 {
+    (0..8).fold(crc ^ b as u32, |crc, _| {
+        (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dfu_file() -> Result<()> {
+        // standard CRC-32 check value, before the final inversion
+        assert_eq!(!dfu_crc32(b"123456789"), 0xcbf4_3926);
+
+        let file = DfuFile::create(
+            &[0xde, 0xad, 0xbe, 0xef],
+            DfuSuffix::create(0x0483, 0xdf11, 0x2200, 0x0100),
+        );
+        let mut buf = file.to_bytes();
+        assert_eq!(buf.len(), 4 + DFU_SUFFIX_LEN);
+
+        let parsed = DfuFile::parse(&buf)?;
+        assert_eq!(parsed.payload(), file.payload());
+        assert!(parsed.suffix().matches(0x0483, 0xdf11));
+        assert!(!parsed.suffix().matches(0x0483, 0x5740));
+        assert!(!parsed.is_dfuse());
+
+        buf[0] ^= 1;
+        assert!(matches!(
+            DfuFile::parse(&buf),
+            Err(Error::InvalidMessage(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1338 @@
+//! Human Interface Device (HID) class driver.
+//!
+//! Parses report descriptors into a tree of collections and report fields, and decodes reports
+//! into usage values. Only the HID interface is claimed, so other interfaces of composite
+//! devices stay available, which `hidraw` does not allow.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::descriptor::{read_u16, DescriptorIter};
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer};
+
+pub const HID_CLASS: u8 = 0x03;
+
+pub const DESCRIPTOR_TYPE_HID: u8 = 0x21;
+pub const DESCRIPTOR_TYPE_REPORT: u8 = 0x22;
+
+pub const HID_GET_REPORT: u8 = 0x01;
+pub const HID_GET_IDLE: u8 = 0x02;
+pub const HID_GET_PROTOCOL: u8 = 0x03;
+pub const HID_SET_REPORT: u8 = 0x09;
+pub const HID_SET_IDLE: u8 = 0x0a;
+pub const HID_SET_PROTOCOL: u8 = 0x0b;
+
+// standard GET_DESCRIPTOR, addressed to an interface
+const REQUEST_TYPE_STANDARD_INTERFACE_IN: u8 = 0x81;
+const REQUEST_GET_DESCRIPTOR: u8 = 0x06;
+
+// main item tags
+const MAIN_INPUT: u8 = 0x8;
+const MAIN_OUTPUT: u8 = 0x9;
+const MAIN_COLLECTION: u8 = 0xa;
+const MAIN_FEATURE: u8 = 0xb;
+const MAIN_END_COLLECTION: u8 = 0xc;
+
+// global item tags
+const GLOBAL_USAGE_PAGE: u8 = 0x0;
+const GLOBAL_LOGICAL_MIN: u8 = 0x1;
+const GLOBAL_LOGICAL_MAX: u8 = 0x2;
+const GLOBAL_PHYSICAL_MIN: u8 = 0x3;
+const GLOBAL_PHYSICAL_MAX: u8 = 0x4;
+const GLOBAL_REPORT_SIZE: u8 = 0x7;
+const GLOBAL_REPORT_ID: u8 = 0x8;
+const GLOBAL_REPORT_COUNT: u8 = 0x9;
+const GLOBAL_PUSH: u8 = 0xa;
+const GLOBAL_POP: u8 = 0xb;
+
+// local item tags
+const LOCAL_USAGE: u8 = 0x0;
+const LOCAL_USAGE_MIN: u8 = 0x1;
+const LOCAL_USAGE_MAX: u8 = 0x2;
+
+const LONG_ITEM_PREFIX: u8 = 0xfe;
+
+// largest report, in bits, as limited by the Linux HID core
+const HID_MAX_REPORT_BITS: u32 = 16384 * 8;
+
+/// Represents the type of a HID report.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReportType {
+    #[default]
+    Input = 1,
+    Output = 2,
+    Feature = 3,
+}
+
+impl ReportType {
+    /// Creates a new [ReportType].
+    pub const fn new() -> Self {
+        Self::Input
+    }
+
+    /// Gets the inner representation of the [ReportType].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&ReportType> for &'static str {
+    fn from(val: &ReportType) -> Self {
+        match val {
+            ReportType::Input => "input",
+            ReportType::Output => "output",
+            ReportType::Feature => "feature",
+        }
+    }
+}
+
+impl fmt::Display for ReportType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the protocol selected with `SET_PROTOCOL`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HidProtocol {
+    /// Fixed boot keyboard and mouse reports.
+    Boot = 0,
+    /// Reports described by the report descriptor.
+    #[default]
+    Report = 1,
+}
+
+impl HidProtocol {
+    /// Creates a new [HidProtocol].
+    pub const fn new() -> Self {
+        Self::Report
+    }
+
+    /// Creates a new [HidProtocol] from its `wValue`.
+    pub const fn create(val: u8) -> Self {
+        match val {
+            0 => Self::Boot,
+            _ => Self::Report,
+        }
+    }
+
+    /// Gets the inner representation of the [HidProtocol].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&HidProtocol> for &'static str {
+    fn from(val: &HidProtocol) -> Self {
+        match val {
+            HidProtocol::Boot => "boot",
+            HidProtocol::Report => "report",
+        }
+    }
+}
+
+impl fmt::Display for HidProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the HID class descriptor, following the interface descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidDescriptor {
+    hid_version: u16,
+    country_code: u8,
+    report_len: u16,
+}
+
+impl HidDescriptor {
+    /// Creates a new [HidDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            hid_version: 0,
+            country_code: 0,
+            report_len: 0,
+        }
+    }
+
+    /// Finds and parses the [HidDescriptor] in the class-specific descriptors of an interface.
+    pub fn parse(extra: &[u8]) -> Result<Self> {
+        let desc = DescriptorIter::new(extra)
+            .find(|d| d[1] == DESCRIPTOR_TYPE_HID && d.len() >= 6)
+            .ok_or(Error::InvalidDescriptor("missing HID descriptor".into()))?;
+
+        // class descriptor list: bDescriptorType, wDescriptorLength
+        let report_len = desc[6..]
+            .chunks_exact(3)
+            .take(desc[5] as usize)
+            .find(|d| d[0] == DESCRIPTOR_TYPE_REPORT)
+            .map(|d| read_u16(d, 1))
+            .ok_or(Error::InvalidDescriptor(
+                "HID descriptor without report descriptor".into(),
+            ))?;
+
+        Ok(Self {
+            hid_version: read_u16(desc, 2),
+            country_code: desc[4],
+            report_len,
+        })
+    }
+
+    /// Gets the HID specification version, in BCD.
+    pub const fn hid_version(&self) -> u16 {
+        self.hid_version
+    }
+
+    /// Gets the localized hardware country code.
+    pub const fn country_code(&self) -> u8 {
+        self.country_code
+    }
+
+    /// Gets the length of the report descriptor.
+    pub const fn report_len(&self) -> u16 {
+        self.report_len
+    }
+}
+
+impl fmt::Display for HidDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"hid_version": {}, "country_code": {}, "report_len": {}}}"#,
+            self.hid_version, self.country_code, self.report_len
+        )
+    }
+}
+
+/// Represents the type of a report descriptor item.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HidItemType {
+    #[default]
+    Main = 0,
+    Global = 1,
+    Local = 2,
+    Reserved = 3,
+}
+
+impl HidItemType {
+    /// Creates a new [HidItemType] from the bits of an item prefix.
+    pub const fn create(val: u8) -> Self {
+        match val & 0x3 {
+            0 => Self::Main,
+            1 => Self::Global,
+            2 => Self::Local,
+            _ => Self::Reserved,
+        }
+    }
+}
+
+impl From<&HidItemType> for &'static str {
+    fn from(val: &HidItemType) -> Self {
+        match val {
+            HidItemType::Main => "main",
+            HidItemType::Global => "global",
+            HidItemType::Local => "local",
+            HidItemType::Reserved => "reserved",
+        }
+    }
+}
+
+impl fmt::Display for HidItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a short item of a report descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidItem {
+    item_type: HidItemType,
+    tag: u8,
+    size: u8,
+    data: u32,
+}
+
+impl HidItem {
+    /// Parses the short items of a report descriptor, skipping long items.
+    pub fn parse_all(mut buf: &[u8]) -> Result<Vec<Self>> {
+        let mut items = Vec::new();
+
+        while let Some(&prefix) = buf.first() {
+            if prefix == LONG_ITEM_PREFIX {
+                let len = 3 + *buf.get(1).unwrap_or(&0) as usize;
+                buf = buf
+                    .get(len..)
+                    .ok_or(Error::InvalidDescriptor("truncated HID long item".into()))?;
+                continue;
+            }
+
+            let size = [0, 1, 2, 4][(prefix & 0x3) as usize];
+            let data = buf
+                .get(1..1 + size)
+                .ok_or(Error::InvalidDescriptor(format!(
+                    "truncated HID item: 0x{prefix:02x}"
+                )))?;
+
+            items.push(Self {
+                item_type: HidItemType::create(prefix >> 2),
+                tag: prefix >> 4,
+                size: size as u8,
+                data: data
+                    .iter()
+                    .rev()
+                    .fold(0u32, |acc, &b| (acc << 8) | b as u32),
+            });
+            buf = &buf[1 + size..];
+        }
+
+        Ok(items)
+    }
+
+    /// Gets the [HidItemType].
+    pub const fn item_type(&self) -> HidItemType {
+        self.item_type
+    }
+
+    /// Gets the item tag.
+    pub const fn tag(&self) -> u8 {
+        self.tag
+    }
+
+    /// Gets the data size, in bytes.
+    pub const fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Gets the data, as an unsigned value.
+    pub const fn data(&self) -> u32 {
+        self.data
+    }
+
+    /// Gets the data, sign-extended from its size.
+    pub const fn signed_data(&self) -> i32 {
+        match self.size {
+            1 => self.data as u8 as i8 as i32,
+            2 => self.data as u16 as i16 as i32,
+            _ => self.data as i32,
+        }
+    }
+}
+
+impl fmt::Display for HidItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"type": {}, "tag": {}, "size": {}, "data": {}}}"#,
+            self.item_type, self.tag, self.size, self.data
+        )
+    }
+}
+
+/// Represents a field of a report, declared by an Input, Output or Feature item.
+///
+/// Usages are extended usages: the usage page in the upper 16 bits, and the usage ID in the
+/// lower 16 bits.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportField {
+    report_type: ReportType,
+    report_id: u8,
+    bit_offset: u32,
+    report_size: u32,
+    report_count: u32,
+    flags: u32,
+    usages: Vec<u32>,
+    usage_range: Option<(u32, u32)>,
+    logical_min: i32,
+    logical_max: i32,
+    physical_min: i32,
+    physical_max: i32,
+}
+
+impl ReportField {
+    /// Gets the [ReportType].
+    pub const fn report_type(&self) -> ReportType {
+        self.report_type
+    }
+
+    /// Gets the report ID, `0` for devices without report IDs.
+    pub const fn report_id(&self) -> u8 {
+        self.report_id
+    }
+
+    /// Gets the offset of the field in the report, in bits, after the report ID.
+    pub const fn bit_offset(&self) -> u32 {
+        self.bit_offset
+    }
+
+    /// Gets the size of each value, in bits.
+    pub const fn report_size(&self) -> u32 {
+        self.report_size
+    }
+
+    /// Gets the number of values.
+    pub const fn report_count(&self) -> u32 {
+        self.report_count
+    }
+
+    /// Gets the main item flags.
+    pub const fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Gets whether the field is constant padding.
+    pub const fn is_constant(&self) -> bool {
+        self.flags & 0x1 != 0
+    }
+
+    /// Gets whether the field holds one value per usage, rather than an array of usage indices.
+    pub const fn is_variable(&self) -> bool {
+        self.flags & 0x2 != 0
+    }
+
+    /// Gets whether the values are relative to the previous report.
+    pub const fn is_relative(&self) -> bool {
+        self.flags & 0x4 != 0
+    }
+
+    /// Gets the list of explicit usages.
+    pub fn usages(&self) -> &[u32] {
+        self.usages.as_ref()
+    }
+
+    /// Gets the usage range, declared with Usage Minimum and Usage Maximum.
+    pub const fn usage_range(&self) -> Option<(u32, u32)> {
+        self.usage_range
+    }
+
+    /// Gets the usage of the value at the provided index.
+    ///
+    /// For array fields, the index is the logical value minus the logical minimum.
+    pub fn usage(&self, index: usize) -> Option<u32> {
+        if let Some(&usage) = self.usages.get(index) {
+            return Some(usage);
+        }
+        match self.usage_range {
+            Some((min, max)) => {
+                let usage = min.checked_add((index - self.usages.len()) as u32)?;
+                (usage <= max).then_some(usage)
+            }
+            // extra values reuse the last usage
+            None if self.is_variable() => self.usages.last().copied(),
+            None => None,
+        }
+    }
+
+    /// Gets the logical minimum.
+    pub const fn logical_min(&self) -> i32 {
+        self.logical_min
+    }
+
+    /// Gets the logical maximum.
+    pub const fn logical_max(&self) -> i32 {
+        self.logical_max
+    }
+
+    /// Gets the physical minimum.
+    pub const fn physical_min(&self) -> i32 {
+        self.physical_min
+    }
+
+    /// Gets the physical maximum.
+    pub const fn physical_max(&self) -> i32 {
+        self.physical_max
+    }
+
+    /// Extracts the value at the provided index from the report data, after the report ID.
+    pub fn value(&self, data: &[u8], index: usize) -> Option<i32> {
+        let size = self.report_size as usize;
+        if index >= self.report_count as usize || size == 0 || size > 32 {
+            return None;
+        }
+
+        let offset = self.bit_offset as usize + index * size;
+        if offset + size > data.len() * 8 {
+            return None;
+        }
+
+        let raw = (0..size).fold(0u32, |acc, i| {
+            let bit = offset + i;
+            acc | (((data[bit / 8] >> (bit % 8)) as u32 & 1) << i)
+        });
+
+        if self.logical_min < 0 && size < 32 {
+            let shift = 32 - size;
+            Some(((raw << shift) as i32) >> shift)
+        } else {
+            Some(raw as i32)
+        }
+    }
+
+    /// Decodes the field values from the report data, after the report ID.
+    ///
+    /// Variable fields yield one value per usage. Array fields yield the usages present in the
+    /// report, with a value of `1`. Constant fields yield nothing.
+    pub fn decode(&self, data: &[u8]) -> Vec<HidValue> {
+        if self.is_constant() {
+            return Vec::new();
+        }
+
+        // no more values than the report data holds
+        let size = self.report_size.max(1) as usize;
+        let count = (self.report_count as usize)
+            .min((data.len() * 8).saturating_sub(self.bit_offset as usize) / size);
+
+        (0..count)
+            .filter_map(|i| {
+                let value = self.value(data, i)?;
+                if self.is_variable() {
+                    Some(HidValue::create(self.usage(i)?, value))
+                } else {
+                    let index = value.checked_sub(self.logical_min)?;
+                    let usage = self.usage(usize::try_from(index).ok()?)?;
+                    // usage ID 0 reports no control
+                    (usage & 0xffff != 0).then_some(HidValue::create(usage, 1))
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ReportField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""report_type": {}, "#, self.report_type)?;
+        write!(f, r#""report_id": {}, "#, self.report_id)?;
+        write!(f, r#""bit_offset": {}, "#, self.bit_offset)?;
+        write!(f, r#""report_size": {}, "#, self.report_size)?;
+        write!(f, r#""report_count": {}, "#, self.report_count)?;
+        write!(f, r#""flags": {}, "#, self.flags)?;
+        write!(f, r#""usages": {:?}, "#, self.usages)?;
+        match self.usage_range {
+            Some((min, max)) => write!(f, r#""usage_range": [{min}, {max}], "#)?,
+            None => write!(f, r#""usage_range": null, "#)?,
+        }
+        write!(f, r#""logical_min": {}, "#, self.logical_min)?;
+        write!(f, r#""logical_max": {}"#, self.logical_max)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a collection of a report descriptor.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidCollection {
+    kind: u8,
+    usage: u32,
+    children: Vec<HidNode>,
+}
+
+impl HidCollection {
+    /// Gets the collection type: `0` for physical, `1` for application, `2` for logical, etc.
+    pub const fn kind(&self) -> u8 {
+        self.kind
+    }
+
+    /// Gets the extended usage of the collection.
+    pub const fn usage(&self) -> u32 {
+        self.usage
+    }
+
+    /// Gets the nested collections and fields.
+    pub fn children(&self) -> &[HidNode] {
+        self.children.as_ref()
+    }
+}
+
+impl fmt::Display for HidCollection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""kind": {}, "#, self.kind)?;
+        write!(f, r#""usage": "{}", "#, usage_name(self.usage))?;
+        write!(f, r#""children": ["#)?;
+        for (i, child) in self.children.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{child}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents a node of the report descriptor tree.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HidNode {
+    Collection(HidCollection),
+    Field(ReportField),
+}
+
+impl fmt::Display for HidNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Collection(c) => write!(f, r#"{{"collection": {c}}}"#),
+            Self::Field(field) => write!(f, r#"{{"field": {field}}}"#),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct GlobalState {
+    usage_page: u32,
+    logical_min: i32,
+    logical_max: i32,
+    physical_min: i32,
+    physical_max: i32,
+    report_size: u32,
+    report_id: u8,
+    report_count: u32,
+}
+
+#[derive(Default)]
+struct LocalState {
+    // usage, and whether it carries its own usage page
+    usages: Vec<(u32, bool)>,
+    usage_min: Option<(u32, bool)>,
+    usage_max: Option<(u32, bool)>,
+}
+
+impl LocalState {
+    fn extended(usage: (u32, bool), page: u32) -> u32 {
+        match usage {
+            (usage, true) => usage,
+            (usage, false) => (page << 16) | (usage & 0xffff),
+        }
+    }
+}
+
+/// Represents a parsed HID report descriptor.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReportDescriptor {
+    nodes: Vec<HidNode>,
+    report_ids: bool,
+}
+
+impl ReportDescriptor {
+    /// Creates a new [ReportDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            report_ids: false,
+        }
+    }
+
+    /// Parses a [ReportDescriptor] from its raw bytes.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let mut global = GlobalState::default();
+        let mut global_stack = Vec::new();
+        let mut local = LocalState::default();
+        let mut offsets = BTreeMap::new();
+        let mut stack: Vec<HidCollection> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut report_ids = false;
+
+        for item in HidItem::parse_all(buf)? {
+            // usages of 4 bytes carry their own usage page
+            let usage = (item.data, item.size == 4);
+
+            match (item.item_type, item.tag) {
+                (HidItemType::Main, MAIN_COLLECTION) => {
+                    let usage = local
+                        .usages
+                        .first()
+                        .map_or(0, |&u| LocalState::extended(u, global.usage_page));
+                    stack.push(HidCollection {
+                        kind: item.data as u8,
+                        usage,
+                        children: Vec::new(),
+                    });
+                }
+                (HidItemType::Main, MAIN_END_COLLECTION) => {
+                    let collection = stack.pop().ok_or(Error::InvalidDescriptor(
+                        "unbalanced HID end collection".into(),
+                    ))?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(HidNode::Collection(collection)),
+                        None => nodes.push(HidNode::Collection(collection)),
+                    }
+                }
+                (HidItemType::Main, tag @ (MAIN_INPUT | MAIN_OUTPUT | MAIN_FEATURE)) => {
+                    let report_type = match tag {
+                        MAIN_INPUT => ReportType::Input,
+                        MAIN_OUTPUT => ReportType::Output,
+                        _ => ReportType::Feature,
+                    };
+                    let offset = offsets.entry((report_type, global.report_id)).or_insert(0);
+                    let end = global
+                        .report_size
+                        .checked_mul(global.report_count)
+                        .and_then(|bits| bits.checked_add(*offset))
+                        .filter(|end| {
+                            *end <= HID_MAX_REPORT_BITS
+                                && global.report_count <= HID_MAX_REPORT_BITS
+                        })
+                        .ok_or(Error::InvalidDescriptor(format!(
+                            "HID report too long, offset: {}, size: {}, count: {}",
+                            *offset, global.report_size, global.report_count
+                        )))?;
+                    let page = global.usage_page;
+                    let field = ReportField {
+                        report_type,
+                        report_id: global.report_id,
+                        bit_offset: *offset,
+                        report_size: global.report_size,
+                        report_count: global.report_count,
+                        flags: item.data,
+                        usages: local
+                            .usages
+                            .iter()
+                            .map(|&u| LocalState::extended(u, page))
+                            .collect(),
+                        usage_range: local.usage_min.zip(local.usage_max).map(|(min, max)| {
+                            (
+                                LocalState::extended(min, page),
+                                LocalState::extended(max, page),
+                            )
+                        }),
+                        logical_min: global.logical_min,
+                        logical_max: global.logical_max,
+                        physical_min: global.physical_min,
+                        physical_max: global.physical_max,
+                    };
+                    *offset = end;
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(HidNode::Field(field)),
+                        None => nodes.push(HidNode::Field(field)),
+                    }
+                }
+                (HidItemType::Global, GLOBAL_USAGE_PAGE) => global.usage_page = item.data & 0xffff,
+                (HidItemType::Global, GLOBAL_LOGICAL_MIN) => {
+                    global.logical_min = item.signed_data()
+                }
+                (HidItemType::Global, GLOBAL_LOGICAL_MAX) => {
+                    // unsigned ranges may set the sign bit of the maximum
+                    global.logical_max = if global.logical_min < 0 {
+                        item.signed_data()
+                    } else {
+                        item.data as i32
+                    };
+                }
+                (HidItemType::Global, GLOBAL_PHYSICAL_MIN) => {
+                    global.physical_min = item.signed_data()
+                }
+                (HidItemType::Global, GLOBAL_PHYSICAL_MAX) => {
+                    global.physical_max = item.signed_data()
+                }
+                (HidItemType::Global, GLOBAL_REPORT_SIZE) => global.report_size = item.data,
+                (HidItemType::Global, GLOBAL_REPORT_ID) => {
+                    global.report_id = item.data as u8;
+                    report_ids = true;
+                }
+                (HidItemType::Global, GLOBAL_REPORT_COUNT) => global.report_count = item.data,
+                (HidItemType::Global, GLOBAL_PUSH) => global_stack.push(global),
+                (HidItemType::Global, GLOBAL_POP) => {
+                    global = global_stack
+                        .pop()
+                        .ok_or(Error::InvalidDescriptor("unbalanced HID pop".into()))?;
+                }
+                (HidItemType::Local, LOCAL_USAGE) => local.usages.push(usage),
+                (HidItemType::Local, LOCAL_USAGE_MIN) => local.usage_min = Some(usage),
+                (HidItemType::Local, LOCAL_USAGE_MAX) => local.usage_max = Some(usage),
+                _ => (),
+            }
+
+            // local items only apply to the next main item
+            if item.item_type == HidItemType::Main {
+                local = LocalState::default();
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err(Error::InvalidDescriptor(
+                "unterminated HID collection".into(),
+            ));
+        }
+
+        Ok(Self { nodes, report_ids })
+    }
+
+    /// Gets the top-level nodes of the descriptor tree.
+    pub fn nodes(&self) -> &[HidNode] {
+        self.nodes.as_ref()
+    }
+
+    /// Gets whether reports start with a report ID byte.
+    pub const fn uses_report_ids(&self) -> bool {
+        self.report_ids
+    }
+
+    /// Gets every [ReportField] of the descriptor, in declaration order.
+    pub fn fields(&self) -> Vec<&ReportField> {
+        fn walk<'a>(nodes: &'a [HidNode], fields: &mut Vec<&'a ReportField>) {
+            for node in nodes {
+                match node {
+                    HidNode::Collection(c) => walk(c.children(), fields),
+                    HidNode::Field(field) => fields.push(field),
+                }
+            }
+        }
+
+        let mut fields = Vec::new();
+        walk(&self.nodes, &mut fields);
+        fields
+    }
+
+    /// Gets the length of a report in bytes, excluding the report ID.
+    pub fn report_len(&self, report_type: ReportType, report_id: u8) -> Result<usize> {
+        let mut bits = 0u32;
+        for f in self
+            .fields()
+            .iter()
+            .filter(|f| f.report_type == report_type && f.report_id == report_id)
+        {
+            let end = f
+                .report_size
+                .checked_mul(f.report_count)
+                .and_then(|len| len.checked_add(f.bit_offset))
+                .ok_or(Error::InvalidDescriptor(format!(
+                    "HID report {report_id} length overflow"
+                )))?;
+            bits = bits.max(end);
+        }
+        Ok(bits.div_ceil(8) as usize)
+    }
+
+    /// Decodes a report, including its report ID if the device uses them, into usage values.
+    pub fn decode(&self, report_type: ReportType, report: &[u8]) -> Result<Vec<HidValue>> {
+        let (report_id, data) = match (self.report_ids, report.split_first()) {
+            (true, Some((&id, data))) => (id, data),
+            (true, None) => return Err(Error::InvalidMessage("empty HID report".into())),
+            (false, _) => (0, report),
+        };
+
+        let fields = self
+            .fields()
+            .into_iter()
+            .filter(|f| f.report_type == report_type && f.report_id == report_id)
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            return Err(Error::NotFound(format!(
+                "HID {report_type} report ID {report_id}"
+            )));
+        }
+
+        Ok(fields.iter().flat_map(|f| f.decode(data)).collect())
+    }
+}
+
+impl fmt::Display for ReportDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#"{{"report_ids": {}, "nodes": ["#, self.report_ids)?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{node}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents a decoded report value.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HidValue {
+    usage: u32,
+    value: i32,
+}
+
+impl HidValue {
+    /// Creates a new [HidValue] from the provided parameters.
+    pub const fn create(usage: u32, value: i32) -> Self {
+        Self { usage, value }
+    }
+
+    /// Gets the extended usage.
+    pub const fn usage(&self) -> u32 {
+        self.usage
+    }
+
+    /// Gets the usage name, see [usage_name].
+    pub fn name(&self) -> String {
+        usage_name(self.usage)
+    }
+
+    /// Gets the logical value.
+    pub const fn value(&self) -> i32 {
+        self.value
+    }
+}
+
+impl fmt::Display for HidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"usage": {}, "name": "{}", "value": {}}}"#,
+            self.usage,
+            self.name(),
+            self.value
+        )
+    }
+}
+
+/// Gets a readable name for an extended usage.
+///
+/// Common Generic Desktop, keyboard, LED, button and consumer usages are named, others are
+/// formatted as `page:id`.
+pub fn usage_name(usage: u32) -> String {
+    let page = (usage >> 16) as u16;
+    let id = usage as u16;
+
+    let name = match (page, id) {
+        (0x01, 0x01) => "Pointer",
+        (0x01, 0x02) => "Mouse",
+        (0x01, 0x04) => "Joystick",
+        (0x01, 0x05) => "Gamepad",
+        (0x01, 0x06) => "Keyboard",
+        (0x01, 0x07) => "Keypad",
+        (0x01, 0x30) => "X",
+        (0x01, 0x31) => "Y",
+        (0x01, 0x32) => "Z",
+        (0x01, 0x33) => "Rx",
+        (0x01, 0x34) => "Ry",
+        (0x01, 0x35) => "Rz",
+        (0x01, 0x36) => "Slider",
+        (0x01, 0x37) => "Dial",
+        (0x01, 0x38) => "Wheel",
+        (0x01, 0x39) => "Hat Switch",
+        (0x07, id) => return format!("Key 0x{id:02x}"),
+        (0x08, 0x01) => "Num Lock",
+        (0x08, 0x02) => "Caps Lock",
+        (0x08, 0x03) => "Scroll Lock",
+        (0x09, id) => return format!("Button {id}"),
+        (0x0c, 0x01) => "Consumer Control",
+        (0x0c, 0xcd) => "Play/Pause",
+        (0x0c, 0xe2) => "Mute",
+        (0x0c, 0xe9) => "Volume Up",
+        (0x0c, 0xea) => "Volume Down",
+        (0xff00..=0xffff, _) => return format!("Vendor 0x{page:04x}:0x{id:04x}"),
+        _ => return format!("0x{page:04x}:0x{id:04x}"),
+    };
+
+    name.into()
+}
+
+/// HID interface driver over a [UsbBackend].
+pub struct Hid<B: UsbBackend> {
+    backend: B,
+    iface: u8,
+    in_ep: Option<u8>,
+    out_ep: Option<u8>,
+    max_packet_size: usize,
+    hid_descriptor: HidDescriptor,
+    raw_report_descriptor: Vec<u8>,
+    report_descriptor: ReportDescriptor,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Hid<B> {
+    /// Opens the first HID interface of the active configuration.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let iface = config
+            .interfaces()
+            .iter()
+            .find(|i| i.class() == HID_CLASS && i.alternate_setting() == 0)
+            .ok_or(Error::NotFound("HID interface".into()))?
+            .number();
+        Self::open_interface(backend, iface)
+    }
+
+    /// Opens a HID interface, detaching the `usbhid` kernel driver from it only.
+    ///
+    /// Fetches and parses the report descriptor.
+    pub fn open_interface(backend: B, iface: u8) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let desc = config
+            .interface(iface, 0)
+            .filter(|i| i.class() == HID_CLASS)
+            .ok_or(Error::NotFound(format!("HID interface {iface}")))?;
+        let hid_descriptor = HidDescriptor::parse(desc.extra())?;
+        let in_ep = super::find_endpoint(desc, TransferType::Interrupt, true);
+        let out_ep = super::find_endpoint(desc, TransferType::Interrupt, false);
+
+        super::claim_detaching(&backend, iface)?;
+
+        // the report descriptor can only be requested once the interface is claimed
+        let (raw, report_descriptor) =
+            match Self::fetch_report_descriptor(&backend, iface, hid_descriptor.report_len()) {
+                Ok(report) => report,
+                Err(err) => {
+                    backend.release_interface(iface as u32).ok();
+                    return Err(err);
+                }
+            };
+
+        Ok(Self {
+            iface,
+            in_ep: in_ep.map(|e| e.address()),
+            out_ep: out_ep.map(|e| e.address()),
+            max_packet_size: in_ep.map_or(64, |e| e.max_packet_size().max(1) as usize),
+            hid_descriptor,
+            report_descriptor,
+            raw_report_descriptor: raw,
+            timeout: super::DEFAULT_TIMEOUT,
+            backend,
+        })
+    }
+
+    fn fetch_report_descriptor(
+        backend: &B,
+        iface: u8,
+        len: u16,
+    ) -> Result<(Vec<u8>, ReportDescriptor)> {
+        let mut raw = vec![0u8; len as usize];
+        let len = class_request(
+            backend,
+            REQUEST_TYPE_STANDARD_INTERFACE_IN,
+            REQUEST_GET_DESCRIPTOR,
+            (DESCRIPTOR_TYPE_REPORT as u16) << 8,
+            iface as u16,
+            &mut raw,
+            super::DEFAULT_TIMEOUT,
+        )?;
+        raw.truncate(len);
+
+        let report_descriptor = ReportDescriptor::parse(&raw)?;
+        Ok((raw, report_descriptor))
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the Interrupt IN endpoint, if any.
+    pub const fn in_endpoint(&self) -> Option<u8> {
+        self.in_ep
+    }
+
+    /// Gets the Interrupt OUT endpoint, if any.
+    pub const fn out_endpoint(&self) -> Option<u8> {
+        self.out_ep
+    }
+
+    /// Gets the [HidDescriptor].
+    pub const fn hid_descriptor(&self) -> &HidDescriptor {
+        &self.hid_descriptor
+    }
+
+    /// Gets the raw report descriptor.
+    pub fn raw_report_descriptor(&self) -> &[u8] {
+        self.raw_report_descriptor.as_ref()
+    }
+
+    /// Gets the parsed [ReportDescriptor].
+    pub const fn report_descriptor(&self) -> &ReportDescriptor {
+        &self.report_descriptor
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Reads a report with `GET_REPORT`, of at most `len` bytes including the report ID.
+    pub fn get_report(
+        &self,
+        report_type: ReportType,
+        report_id: u8,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            HID_GET_REPORT,
+            ((report_type.inner() as u16) << 8) | report_id as u16,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Writes a report with `SET_REPORT`.
+    ///
+    /// The data starts with the report ID if the device uses them.
+    pub fn set_report(&self, report_type: ReportType, report_id: u8, data: &[u8]) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            HID_SET_REPORT,
+            ((report_type.inner() as u16) << 8) | report_id as u16,
+            self.iface as u16,
+            &mut data.to_vec(),
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Gets the idle rate of a report, in 4 ms units, with `GET_IDLE`.
+    pub fn get_idle(&self, report_id: u8) -> Result<u8> {
+        let mut buf = [0u8];
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            HID_GET_IDLE,
+            report_id as u16,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        Ok(buf[0])
+    }
+
+    /// Sets the idle rate of a report, in 4 ms units, with `SET_IDLE`.
+    ///
+    /// A rate of `0` only reports changes, report ID `0` applies to every report.
+    pub fn set_idle(&self, rate: u8, report_id: u8) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            HID_SET_IDLE,
+            ((rate as u16) << 8) | report_id as u16,
+            self.iface as u16,
+            &mut [],
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Gets the active [HidProtocol] with `GET_PROTOCOL`.
+    pub fn get_protocol(&self) -> Result<HidProtocol> {
+        let mut buf = [0u8];
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            HID_GET_PROTOCOL,
+            0,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        Ok(HidProtocol::create(buf[0]))
+    }
+
+    /// Selects the [HidProtocol] with `SET_PROTOCOL`.
+    pub fn set_protocol(&self, protocol: HidProtocol) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            HID_SET_PROTOCOL,
+            protocol.inner() as u16,
+            self.iface as u16,
+            &mut [],
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Waits for the next input report on the Interrupt IN endpoint.
+    pub fn read_input(&self) -> Result<Vec<u8>> {
+        let ep = self
+            .in_ep
+            .ok_or(Error::NotFound("HID Interrupt IN endpoint".into()))?;
+        let mut int =
+            UsbfsBulkTransfer::create(ep as u32, self.timeout, vec![0u8; self.max_packet_size]);
+        let len = self.backend.bulk(&mut int)?;
+
+        let mut report = int.into_data();
+        report.truncate(len);
+        Ok(report)
+    }
+
+    /// Waits for the next input report, and decodes it into usage values.
+    pub fn read_values(&self) -> Result<Vec<HidValue>> {
+        self.report_descriptor
+            .decode(ReportType::Input, &self.read_input()?)
+    }
+
+    /// Writes an output report on the Interrupt OUT endpoint, or with `SET_REPORT` without one.
+    ///
+    /// The data starts with the report ID if the device uses them.
+    pub fn write_output(&self, data: &[u8]) -> Result<()> {
+        match self.out_ep {
+            Some(ep) => {
+                let mut int =
+                    UsbfsBulkTransfer::create(ep as u32, self.timeout, data.iter().copied());
+                self.backend.bulk(&mut int).map(|_| ())
+            }
+            None => {
+                let report_id = match self.report_descriptor.uses_report_ids() {
+                    true => data.first().copied().unwrap_or(0),
+                    false => 0,
+                };
+                self.set_report(ReportType::Output, report_id, data)
+            }
+        }
+    }
+
+    /// Releases the interface, and converts the [Hid] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Hid<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hid")
+            .field("iface", &self.iface)
+            .field("in_ep", &self.in_ep)
+            .field("out_ep", &self.out_ep)
+            .field("hid_descriptor", &self.hid_descriptor)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // three-button mouse, with relative X, Y and wheel
+    const MOUSE_REPORT: [u8; 52] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, //
+        0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81,
+        0x02, //
+        0x95, 0x01, 0x75, 0x05, 0x81, 0x01, //
+        0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95,
+        0x03, 0x81, 0x06, //
+        0xc0, 0xc0,
+    ];
+
+    // HID interface 0, with its HID descriptor and an Interrupt IN endpoint
+    const DESCRIPTORS: [u8; 52] = [
+        0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x6d, 0x04, 0x77, 0xc0, 0x00, 0x01, 0x01,
+        0x02, 0x00, 0x01, //
+        0x09, 0x02, 0x22, 0x00, 0x01, 0x01, 0x00, 0xa0, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x03, 0x01, 0x02, 0x00, //
+        0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 0x34, 0x00, //
+        0x07, 0x05, 0x81, 0x03, 0x04, 0x00, 0x0a,
+    ];
+
+    #[test]
+    fn test_hid() -> Result<()> {
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "usbhid")
+            .with_control(
+                MockControl::create(0x81, REQUEST_GET_DESCRIPTOR, 0x2200, 0)
+                    .with_response(MockResponse::Data(MOUSE_REPORT.into())),
+            )
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                HID_SET_IDLE,
+                0,
+                0,
+            ))
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OUT,
+                HID_SET_PROTOCOL,
+                1,
+                0,
+            ))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, HID_GET_REPORT, 0x0100, 0)
+                    .with_response(MockResponse::Data(vec![0x00, 0x01, 0x00, 0x00])),
+            )
+            .with_data(0x81, [0x05, 0x0a, 0xfe, 0x01]);
+
+        let hid = Hid::open(dev)?;
+        assert_eq!(hid.hid_descriptor().report_len(), 52);
+        assert_eq!(hid.in_endpoint(), Some(0x81));
+
+        let desc = hid.report_descriptor();
+        assert!(!desc.uses_report_ids());
+        assert_eq!(desc.report_len(ReportType::Input, 0)?, 4);
+
+        // Report Size 0xffff_ffff, Report Count 2, Input
+        let overflow = [0x77, 0xff, 0xff, 0xff, 0xff, 0x95, 0x02, 0x81, 0x02];
+        assert!(ReportDescriptor::parse(&overflow).is_err());
+        // Report Size 0, Report Count 0xffff_ffff, Input
+        let too_many = [0x75, 0x00, 0x97, 0xff, 0xff, 0xff, 0xff, 0x81, 0x02];
+        assert!(ReportDescriptor::parse(&too_many).is_err());
+
+        let HidNode::Collection(app) = &desc.nodes()[0] else {
+            panic!("expected the application collection");
+        };
+        assert_eq!((app.kind(), usage_name(app.usage())), (1, "Mouse".into()));
+
+        let fields = desc.fields();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].usage_range(), Some((0x0009_0001, 0x0009_0003)));
+        assert!(fields[1].is_constant());
+        assert_eq!(fields[2].bit_offset(), 8);
+        assert_eq!(
+            (fields[2].logical_min(), fields[2].logical_max()),
+            (-127, 127)
+        );
+        assert!(fields[2].is_relative());
+
+        hid.set_idle(0, 0)?;
+        hid.set_protocol(HidProtocol::Report)?;
+        assert_eq!(hid.get_report(ReportType::Input, 0, 4)?, [0, 1, 0, 0]);
+
+        let values = hid
+            .read_values()?
+            .iter()
+            .map(|v| (v.name(), v.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                ("Button 1".into(), 1),
+                ("Button 2".into(), 0),
+                ("Button 3".into(), 1),
+                ("X".into(), 10),
+                ("Y".into(), -2),
+                ("Wheel".into(), 1),
+            ]
+        );
+
+        let dev = hid.close()?;
+        dev.verify()
+    }
+}
@@ -0,0 +1,1116 @@
+//! Mass Storage Class Bulk-Only Transport (BOT), and a minimal SCSI command set.
+//!
+//! [BulkOnly] frames commands in Command Block Wrappers, moves the data over the Bulk pair,
+//! and checks the Command Status Wrapper, running the reset recovery sequence on phase errors.
+//! [Scsi] issues SCSI block commands over it, and reports `CHECK CONDITION` failures with the
+//! typed [SenseData] returned by `REQUEST SENSE`.
+
+use std::fmt;
+
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::{is_stall, Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer};
+
+pub const MSC_CLASS: u8 = 0x08;
+pub const MSC_SUBCLASS_SCSI: u8 = 0x06;
+pub const MSC_PROTOCOL_BOT: u8 = 0x50;
+
+pub const BOT_RESET: u8 = 0xff;
+pub const BOT_GET_MAX_LUN: u8 = 0xfe;
+
+pub const CBW_SIGNATURE: u32 = 0x4342_5355;
+pub const CSW_SIGNATURE: u32 = 0x5342_5355;
+pub const CBW_LEN: usize = 31;
+/// Longest command block of a [CommandBlockWrapper].
+pub const CBW_MAX_COMMAND_LEN: usize = 16;
+pub const CSW_LEN: usize = 13;
+
+pub const SCSI_TEST_UNIT_READY: u8 = 0x00;
+pub const SCSI_REQUEST_SENSE: u8 = 0x03;
+pub const SCSI_INQUIRY: u8 = 0x12;
+pub const SCSI_READ_CAPACITY_10: u8 = 0x25;
+pub const SCSI_READ_10: u8 = 0x28;
+pub const SCSI_WRITE_10: u8 = 0x2a;
+pub const SCSI_READ_16: u8 = 0x88;
+pub const SCSI_WRITE_16: u8 = 0x8a;
+pub const SCSI_SERVICE_ACTION_IN_16: u8 = 0x9e;
+
+const CBW_FLAG_DATA_IN: u8 = 0x80;
+const SERVICE_ACTION_READ_CAPACITY_16: u8 = 0x10;
+const INQUIRY_LEN: usize = 36;
+const SENSE_LEN: usize = 18;
+// largest Bulk transfer of a data phase
+const MAX_TRANSFER_LEN: usize = 64 * 1024;
+
+/// Represents a Command Block Wrapper.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandBlockWrapper {
+    tag: u32,
+    transfer_len: u32,
+    data_in: bool,
+    lun: u8,
+    command: Vec<u8>,
+}
+
+impl CommandBlockWrapper {
+    /// Creates a new [CommandBlockWrapper] from the provided parameters.
+    ///
+    /// Returns an [Error::InvalidArgument] error if the command block is empty, or longer
+    /// than [CBW_MAX_COMMAND_LEN].
+    pub fn create(
+        tag: u32,
+        transfer_len: u32,
+        data_in: bool,
+        lun: u8,
+        command: &[u8],
+    ) -> Result<Self> {
+        if command.is_empty() || command.len() > CBW_MAX_COMMAND_LEN {
+            return Err(Error::InvalidArgument(format!(
+                "CBW command block length: {}",
+                command.len()
+            )));
+        }
+
+        Ok(Self {
+            tag,
+            transfer_len,
+            data_in,
+            lun,
+            command: command.into(),
+        })
+    }
+
+    /// Gets the tag, echoed by the matching [CommandStatusWrapper].
+    pub const fn tag(&self) -> u32 {
+        self.tag
+    }
+
+    /// Gets the expected length of the data phase.
+    pub const fn transfer_len(&self) -> u32 {
+        self.transfer_len
+    }
+
+    /// Gets whether the data phase is from the device to the host.
+    pub const fn data_in(&self) -> bool {
+        self.data_in
+    }
+
+    /// Gets the logical unit number.
+    pub const fn lun(&self) -> u8 {
+        self.lun
+    }
+
+    /// Gets the command block.
+    pub fn command(&self) -> &[u8] {
+        self.command.as_ref()
+    }
+
+    /// Gets the wire representation of the [CommandBlockWrapper].
+    pub fn to_bytes(&self) -> [u8; CBW_LEN] {
+        let mut buf = [0u8; CBW_LEN];
+        let len = self.command.len().min(CBW_MAX_COMMAND_LEN);
+
+        buf[..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.transfer_len.to_le_bytes());
+        buf[12] = if self.data_in { CBW_FLAG_DATA_IN } else { 0 };
+        buf[13] = self.lun & 0xf;
+        buf[14] = len as u8;
+        buf[15..15 + len].copy_from_slice(&self.command[..len]);
+
+        buf
+    }
+}
+
+impl fmt::Display for CommandBlockWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""tag": {}, "#, self.tag)?;
+        write!(f, r#""transfer_len": {}, "#, self.transfer_len)?;
+        write!(f, r#""data_in": {}, "#, self.data_in)?;
+        write!(f, r#""lun": {}, "#, self.lun)?;
+        write!(f, r#""command": {:?}"#, self.command)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the status of a [CommandStatusWrapper].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CswStatus {
+    #[default]
+    Passed = 0,
+    Failed = 1,
+    PhaseError = 2,
+}
+
+impl CswStatus {
+    /// Creates a new [CswStatus].
+    pub const fn new() -> Self {
+        Self::Passed
+    }
+
+    /// Creates a new [CswStatus] from its `bCSWStatus` value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0 => Some(Self::Passed),
+            1 => Some(Self::Failed),
+            2 => Some(Self::PhaseError),
+            _ => None,
+        }
+    }
+}
+
+impl From<&CswStatus> for &'static str {
+    fn from(val: &CswStatus) -> Self {
+        match val {
+            CswStatus::Passed => "passed",
+            CswStatus::Failed => "failed",
+            CswStatus::PhaseError => "phase error",
+        }
+    }
+}
+
+impl fmt::Display for CswStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a Command Status Wrapper.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandStatusWrapper {
+    tag: u32,
+    residue: u32,
+    status: CswStatus,
+}
+
+impl CommandStatusWrapper {
+    /// Creates a new [CommandStatusWrapper] from the provided parameters.
+    pub const fn create(tag: u32, residue: u32, status: CswStatus) -> Self {
+        Self {
+            tag,
+            residue,
+            status,
+        }
+    }
+
+    /// Parses a [CommandStatusWrapper] from its wire representation.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() != CSW_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "invalid CSW length: {}",
+                buf.len()
+            )));
+        }
+
+        let read_u32 =
+            |off: usize| u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+        let signature = read_u32(0);
+        if signature != CSW_SIGNATURE {
+            return Err(Error::InvalidMessage(format!(
+                "invalid CSW signature: 0x{signature:08x}"
+            )));
+        }
+
+        Ok(Self {
+            tag: read_u32(4),
+            residue: read_u32(8),
+            status: CswStatus::create(buf[12]).ok_or(Error::InvalidMessage(format!(
+                "invalid CSW status: {}",
+                buf[12]
+            )))?,
+        })
+    }
+
+    /// Gets the wire representation of the [CommandStatusWrapper].
+    pub fn to_bytes(&self) -> [u8; CSW_LEN] {
+        let mut buf = [0u8; CSW_LEN];
+        buf[..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.residue.to_le_bytes());
+        buf[12] = self.status as u8;
+        buf
+    }
+
+    /// Gets the tag of the matching [CommandBlockWrapper].
+    pub const fn tag(&self) -> u32 {
+        self.tag
+    }
+
+    /// Gets the difference between the expected and the processed data length.
+    pub const fn residue(&self) -> u32 {
+        self.residue
+    }
+
+    /// Gets the [CswStatus].
+    pub const fn status(&self) -> CswStatus {
+        self.status
+    }
+}
+
+impl fmt::Display for CommandStatusWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"tag": {}, "residue": {}, "status": {}}}"#,
+            self.tag, self.residue, self.status
+        )
+    }
+}
+
+/// Represents the data phase of a command.
+#[derive(Debug)]
+pub enum DataPhase<'a> {
+    None,
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+impl DataPhase<'_> {
+    /// Gets the length of the data phase.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::In(buf) => buf.len(),
+            Self::Out(buf) => buf.len(),
+        }
+    }
+
+    /// Gets whether the data phase is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Bulk-Only Transport over a [UsbBackend].
+pub struct BulkOnly<B: UsbBackend> {
+    backend: B,
+    iface: u8,
+    bulk_in: u8,
+    bulk_out: u8,
+    tag: u32,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> BulkOnly<B> {
+    /// Opens the first Bulk-Only mass storage interface of the active configuration.
+    ///
+    /// Claims the interface, detaching the `usb-storage` kernel driver.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let desc = config
+            .interfaces()
+            .iter()
+            .find(|i| i.class() == MSC_CLASS && i.protocol() == MSC_PROTOCOL_BOT)
+            .ok_or(Error::NotFound("Bulk-Only mass storage interface".into()))?;
+        let bulk_in = super::find_endpoint(desc, TransferType::Bulk, true)
+            .ok_or(Error::NotFound("mass storage Bulk IN endpoint".into()))?;
+        let bulk_out = super::find_endpoint(desc, TransferType::Bulk, false)
+            .ok_or(Error::NotFound("mass storage Bulk OUT endpoint".into()))?;
+
+        let bot = Self {
+            iface: desc.number(),
+            bulk_in: bulk_in.address(),
+            bulk_out: bulk_out.address(),
+            tag: 0,
+            timeout: super::DEFAULT_TIMEOUT * 5,
+            backend,
+        };
+        super::claim_detaching(&bot.backend, bot.iface)?;
+
+        Ok(bot)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Gets the highest logical unit number with `GET_MAX_LUN`.
+    ///
+    /// Devices with a single LUN may stall the request, which reports LUN `0`.
+    pub fn max_lun(&self) -> Result<u8> {
+        let mut buf = [0u8];
+        match self.class_request(REQUEST_TYPE_CLASS_IN, BOT_GET_MAX_LUN, &mut buf) {
+            Ok(_) => Ok(buf[0]),
+            Err(err) if is_stall(&err) => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Runs the reset recovery sequence: Bulk-Only Mass Storage Reset, then clears the halt of
+    /// both Bulk endpoints.
+    pub fn reset_recovery(&self) -> Result<()> {
+        self.class_request(REQUEST_TYPE_CLASS_OUT, BOT_RESET, &mut [])?;
+        self.backend.clear_halt(self.bulk_in as u32)?;
+        self.backend.clear_halt(self.bulk_out as u32)
+    }
+
+    /// Runs a command, and returns its [CommandStatusWrapper].
+    ///
+    /// Stalled data phases are cleared before reading the status. Invalid or mismatched status
+    /// wrappers, and phase errors, run the reset recovery sequence and return an error.
+    pub fn command(
+        &mut self,
+        lun: u8,
+        command: &[u8],
+        data: DataPhase<'_>,
+    ) -> Result<CommandStatusWrapper> {
+        self.tag = self.tag.wrapping_add(1);
+        let data_in = matches!(data, DataPhase::In(_));
+        let cbw = CommandBlockWrapper::create(self.tag, data.len() as u32, data_in, lun, command)?;
+
+        if let Err(err) = self.bulk(self.bulk_out, &mut cbw.to_bytes()) {
+            if is_stall(&err) {
+                self.reset_recovery()?;
+            }
+            return Err(err);
+        }
+
+        let res = match data {
+            DataPhase::None => Ok(()),
+            DataPhase::In(buf) => self.data_phase(self.bulk_in, buf),
+            DataPhase::Out(buf) => self.data_phase(self.bulk_out, &mut buf.to_vec()),
+        };
+        match res {
+            Err(err) if is_stall(&err) => {
+                let ep = if data_in { self.bulk_in } else { self.bulk_out };
+                self.backend.clear_halt(ep as u32)?;
+            }
+            res => res?,
+        }
+
+        let csw = match self.read_csw() {
+            Err(err) if is_stall(&err) => {
+                self.backend.clear_halt(self.bulk_in as u32)?;
+                self.read_csw()
+            }
+            csw => csw,
+        };
+
+        match csw {
+            Ok(csw) if csw.tag() != cbw.tag() => {
+                self.reset_recovery()?;
+                Err(Error::InvalidMessage(format!(
+                    "CSW tag mismatch, have: {}, expected: {}",
+                    csw.tag(),
+                    cbw.tag()
+                )))
+            }
+            Ok(csw) if csw.status() == CswStatus::PhaseError => {
+                self.reset_recovery()?;
+                Err(Error::InvalidMessage("CSW phase error".into()))
+            }
+            Ok(csw) => Ok(csw),
+            Err(err) => {
+                self.reset_recovery()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Releases the interface, and converts the [BulkOnly] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn data_phase(&self, ep: u8, buf: &mut [u8]) -> Result<()> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let end = buf.len().min(pos + MAX_TRANSFER_LEN);
+            let len = self.bulk(ep, &mut buf[pos..end])?;
+            // short packets end the data phase
+            let short = len < end - pos;
+            pos += len;
+            if short {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_csw(&self) -> Result<CommandStatusWrapper> {
+        let mut buf = [0u8; CSW_LEN];
+        let len = self.bulk(self.bulk_in, &mut buf)?;
+        CommandStatusWrapper::parse(&buf[..len])
+    }
+
+    fn bulk(&self, ep: u8, buf: &mut [u8]) -> Result<usize> {
+        let mut bulk = UsbfsBulkTransfer::create(ep as u32, self.timeout, buf.iter().copied());
+        let len = self.backend.bulk(&mut bulk)?.min(buf.len());
+        if ep & crate::descriptor::ENDPOINT_DIR_IN != 0 {
+            buf[..len].copy_from_slice(&bulk.data()[..len]);
+        }
+        Ok(len)
+    }
+
+    fn class_request(&self, request_type: u8, request: u8, data: &mut [u8]) -> Result<usize> {
+        class_request(
+            &self.backend,
+            request_type,
+            request,
+            0,
+            self.iface as u16,
+            data,
+            super::DEFAULT_TIMEOUT,
+        )
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for BulkOnly<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkOnly")
+            .field("iface", &self.iface)
+            .field("bulk_in", &self.bulk_in)
+            .field("bulk_out", &self.bulk_out)
+            .field("tag", &self.tag)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Represents the SCSI sense key.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SenseKey {
+    #[default]
+    NoSense = 0x0,
+    RecoveredError = 0x1,
+    NotReady = 0x2,
+    MediumError = 0x3,
+    HardwareError = 0x4,
+    IllegalRequest = 0x5,
+    UnitAttention = 0x6,
+    DataProtect = 0x7,
+    BlankCheck = 0x8,
+    VendorSpecific = 0x9,
+    CopyAborted = 0xa,
+    AbortedCommand = 0xb,
+    Reserved = 0xc,
+    VolumeOverflow = 0xd,
+    Miscompare = 0xe,
+    Completed = 0xf,
+}
+
+impl SenseKey {
+    /// Creates a new [SenseKey].
+    pub const fn new() -> Self {
+        Self::NoSense
+    }
+
+    /// Creates a new [SenseKey] from its 4-bit value.
+    pub const fn create(val: u8) -> Self {
+        match val & 0xf {
+            0x0 => Self::NoSense,
+            0x1 => Self::RecoveredError,
+            0x2 => Self::NotReady,
+            0x3 => Self::MediumError,
+            0x4 => Self::HardwareError,
+            0x5 => Self::IllegalRequest,
+            0x6 => Self::UnitAttention,
+            0x7 => Self::DataProtect,
+            0x8 => Self::BlankCheck,
+            0x9 => Self::VendorSpecific,
+            0xa => Self::CopyAborted,
+            0xb => Self::AbortedCommand,
+            0xc => Self::Reserved,
+            0xd => Self::VolumeOverflow,
+            0xe => Self::Miscompare,
+            _ => Self::Completed,
+        }
+    }
+}
+
+impl From<&SenseKey> for &'static str {
+    fn from(val: &SenseKey) -> Self {
+        match val {
+            SenseKey::NoSense => "no sense",
+            SenseKey::RecoveredError => "recovered error",
+            SenseKey::NotReady => "not ready",
+            SenseKey::MediumError => "medium error",
+            SenseKey::HardwareError => "hardware error",
+            SenseKey::IllegalRequest => "illegal request",
+            SenseKey::UnitAttention => "unit attention",
+            SenseKey::DataProtect => "data protect",
+            SenseKey::BlankCheck => "blank check",
+            SenseKey::VendorSpecific => "vendor specific",
+            SenseKey::CopyAborted => "copy aborted",
+            SenseKey::AbortedCommand => "aborted command",
+            SenseKey::Reserved => "reserved",
+            SenseKey::VolumeOverflow => "volume overflow",
+            SenseKey::Miscompare => "miscompare",
+            SenseKey::Completed => "completed",
+        }
+    }
+}
+
+impl fmt::Display for SenseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the sense data returned by `REQUEST SENSE`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SenseData {
+    response_code: u8,
+    sense_key: SenseKey,
+    asc: u8,
+    ascq: u8,
+    information: Option<u64>,
+}
+
+impl SenseData {
+    /// Creates a new [SenseData] from the provided parameters.
+    pub const fn create(sense_key: SenseKey, asc: u8, ascq: u8) -> Self {
+        Self {
+            response_code: 0x70,
+            sense_key,
+            asc,
+            ascq,
+            information: None,
+        }
+    }
+
+    /// Parses [SenseData] in the fixed or descriptor format.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let response_code = buf.first().map_or(0, |c| c & 0x7f);
+
+        match response_code {
+            0x70 | 0x71 if buf.len() >= 14 => Ok(Self {
+                response_code,
+                sense_key: SenseKey::create(buf[2]),
+                asc: buf[12],
+                ascq: buf[13],
+                // the information field is only valid with the VALID bit
+                information: (buf[0] & 0x80 != 0)
+                    .then(|| u32::from_be_bytes([buf[3], buf[4], buf[5], buf[6]]) as u64),
+            }),
+            0x72 | 0x73 if buf.len() >= 8 => Ok(Self {
+                response_code,
+                sense_key: SenseKey::create(buf[1]),
+                asc: buf[2],
+                ascq: buf[3],
+                information: buf
+                    .get(8..)
+                    .and_then(|d| d.get(..12))
+                    .filter(|d| d[0] == 0x00 && d[1] >= 0x0a)
+                    .map(|d| u64::from_be_bytes(d[4..12].try_into().unwrap_or_default())),
+            }),
+            _ => Err(Error::InvalidMessage(format!(
+                "invalid sense data, response code: 0x{response_code:02x}, length: {}",
+                buf.len()
+            ))),
+        }
+    }
+
+    /// Gets the response code: `0x70`-`0x71` for fixed, `0x72`-`0x73` for descriptor format.
+    pub const fn response_code(&self) -> u8 {
+        self.response_code
+    }
+
+    /// Gets the [SenseKey].
+    pub const fn sense_key(&self) -> SenseKey {
+        self.sense_key
+    }
+
+    /// Gets the Additional Sense Code.
+    pub const fn asc(&self) -> u8 {
+        self.asc
+    }
+
+    /// Gets the Additional Sense Code Qualifier.
+    pub const fn ascq(&self) -> u8 {
+        self.ascq
+    }
+
+    /// Gets the information field, usually the failing LBA, if valid.
+    pub const fn information(&self) -> Option<u64> {
+        self.information
+    }
+}
+
+impl fmt::Display for SenseData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""sense_key": {}, "#, self.sense_key)?;
+        write!(f, r#""asc": {}, "#, self.asc)?;
+        write!(f, r#""ascq": {}, "#, self.ascq)?;
+        match self.information {
+            Some(info) => write!(f, r#""information": {info}"#)?,
+            None => write!(f, r#""information": null"#)?,
+        }
+        write!(f, "}}")
+    }
+}
+
+impl std::error::Error for SenseData {}
+
+/// Represents the standard `INQUIRY` data.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InquiryData {
+    peripheral_type: u8,
+    removable: bool,
+    version: u8,
+    vendor: String,
+    product: String,
+    revision: String,
+}
+
+impl InquiryData {
+    /// Parses the [InquiryData] from the `INQUIRY` response.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < INQUIRY_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "INQUIRY data too short: {}",
+                buf.len()
+            )));
+        }
+
+        let text = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&buf[range]).trim_end().to_string()
+        };
+
+        Ok(Self {
+            peripheral_type: buf[0] & 0x1f,
+            removable: buf[1] & 0x80 != 0,
+            version: buf[2],
+            vendor: text(8..16),
+            product: text(16..32),
+            revision: text(32..36),
+        })
+    }
+
+    /// Gets the peripheral device type, `0` for direct access block devices.
+    pub const fn peripheral_type(&self) -> u8 {
+        self.peripheral_type
+    }
+
+    /// Gets whether the medium is removable.
+    pub const fn removable(&self) -> bool {
+        self.removable
+    }
+
+    /// Gets the SCSI version.
+    pub const fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Gets the vendor identification.
+    pub fn vendor(&self) -> &str {
+        self.vendor.as_str()
+    }
+
+    /// Gets the product identification.
+    pub fn product(&self) -> &str {
+        self.product.as_str()
+    }
+
+    /// Gets the product revision level.
+    pub fn revision(&self) -> &str {
+        self.revision.as_str()
+    }
+}
+
+impl fmt::Display for InquiryData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""peripheral_type": {}, "#, self.peripheral_type)?;
+        write!(f, r#""removable": {}, "#, self.removable)?;
+        write!(f, r#""version": {}, "#, self.version)?;
+        write!(f, r#""vendor": "{}", "#, self.vendor)?;
+        write!(f, r#""product": "{}", "#, self.product)?;
+        write!(f, r#""revision": "{}""#, self.revision)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the capacity returned by `READ CAPACITY`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capacity {
+    last_lba: u64,
+    block_len: u32,
+}
+
+impl Capacity {
+    /// Creates a new [Capacity] from the provided parameters.
+    pub const fn create(last_lba: u64, block_len: u32) -> Self {
+        Self {
+            last_lba,
+            block_len,
+        }
+    }
+
+    /// Gets the address of the last logical block.
+    pub const fn last_lba(&self) -> u64 {
+        self.last_lba
+    }
+
+    /// Gets the logical block length, in bytes.
+    pub const fn block_len(&self) -> u32 {
+        self.block_len
+    }
+
+    /// Gets the number of logical blocks, saturating at [u64::MAX].
+    pub const fn blocks(&self) -> u64 {
+        self.last_lba.saturating_add(1)
+    }
+
+    /// Gets the capacity, in bytes, saturating at [u64::MAX].
+    pub const fn bytes(&self) -> u64 {
+        self.blocks().saturating_mul(self.block_len as u64)
+    }
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"last_lba": {}, "block_len": {}}}"#,
+            self.last_lba, self.block_len
+        )
+    }
+}
+
+/// SCSI block commands over a [BulkOnly] transport.
+///
+/// Commands failing with `CHECK CONDITION` return [Error::Class], with the [SenseData]
+/// fetched by `REQUEST SENSE`.
+#[derive(Debug)]
+pub struct Scsi<B: UsbBackend> {
+    transport: BulkOnly<B>,
+    lun: u8,
+}
+
+impl<B: UsbBackend> Scsi<B> {
+    /// Creates a new [Scsi] addressing a logical unit of the transport.
+    pub const fn new(transport: BulkOnly<B>, lun: u8) -> Self {
+        Self { transport, lun }
+    }
+
+    /// Opens the first Bulk-Only interface of the device, addressing LUN `0`.
+    pub fn open(backend: B) -> Result<Self> {
+        Ok(Self::new(BulkOnly::open(backend)?, 0))
+    }
+
+    /// Gets a reference to the [BulkOnly] transport.
+    pub const fn transport(&self) -> &BulkOnly<B> {
+        &self.transport
+    }
+
+    /// Gets a mutable reference to the [BulkOnly] transport.
+    pub fn transport_mut(&mut self) -> &mut BulkOnly<B> {
+        &mut self.transport
+    }
+
+    /// Converts the [Scsi] into its [BulkOnly] transport.
+    pub fn into_transport(self) -> BulkOnly<B> {
+        self.transport
+    }
+
+    /// Gets the logical unit number.
+    pub const fn lun(&self) -> u8 {
+        self.lun
+    }
+
+    /// Sets the logical unit number.
+    pub fn set_lun(&mut self, lun: u8) {
+        self.lun = lun;
+    }
+
+    /// Builder function that sets the logical unit number.
+    pub fn with_lun(mut self, lun: u8) -> Self {
+        self.set_lun(lun);
+        self
+    }
+
+    /// Runs a command, fetching the [SenseData] if it fails.
+    ///
+    /// Returns the number of bytes transferred in the data phase.
+    pub fn command(&mut self, command: &[u8], data: DataPhase<'_>) -> Result<usize> {
+        let len = data.len();
+        let csw = self.transport.command(self.lun, command, data)?;
+
+        match csw.status() {
+            CswStatus::Passed => Ok(len.saturating_sub(csw.residue() as usize)),
+            _ => Err(Error::class("SCSI", self.request_sense()?)),
+        }
+    }
+
+    /// Checks whether the logical unit is ready with `TEST UNIT READY`.
+    pub fn test_unit_ready(&mut self) -> Result<()> {
+        self.command(&[SCSI_TEST_UNIT_READY, 0, 0, 0, 0, 0], DataPhase::None)
+            .map(|_| ())
+    }
+
+    /// Gets the [SenseData] of the last failed command with `REQUEST SENSE`.
+    pub fn request_sense(&mut self) -> Result<SenseData> {
+        let mut buf = [0u8; SENSE_LEN];
+        let cb = [SCSI_REQUEST_SENSE, 0, 0, 0, SENSE_LEN as u8, 0];
+        let csw = self
+            .transport
+            .command(self.lun, &cb, DataPhase::In(&mut buf))?;
+        if csw.status() != CswStatus::Passed {
+            return Err(Error::InvalidMessage("REQUEST SENSE failed".into()));
+        }
+
+        let len = SENSE_LEN.saturating_sub(csw.residue() as usize);
+        SenseData::parse(&buf[..len])
+    }
+
+    /// Gets the [InquiryData] with `INQUIRY`.
+    pub fn inquiry(&mut self) -> Result<InquiryData> {
+        let mut buf = [0u8; INQUIRY_LEN];
+        let len = self.command(
+            &[SCSI_INQUIRY, 0, 0, 0, INQUIRY_LEN as u8, 0],
+            DataPhase::In(&mut buf),
+        )?;
+        InquiryData::parse(&buf[..len])
+    }
+
+    /// Gets the [Capacity] with `READ CAPACITY (10)`.
+    pub fn read_capacity_10(&mut self) -> Result<Capacity> {
+        let mut buf = [0u8; 8];
+        let mut cb = [0u8; 10];
+        cb[0] = SCSI_READ_CAPACITY_10;
+        self.command(&cb, DataPhase::In(&mut buf))?;
+
+        Ok(Capacity::create(
+            u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64,
+            u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        ))
+    }
+
+    /// Gets the [Capacity] with `READ CAPACITY (16)`.
+    pub fn read_capacity_16(&mut self) -> Result<Capacity> {
+        let mut buf = [0u8; 32];
+        let mut cb = [0u8; 16];
+        cb[0] = SCSI_SERVICE_ACTION_IN_16;
+        cb[1] = SERVICE_ACTION_READ_CAPACITY_16;
+        cb[10..14].copy_from_slice(&(buf.len() as u32).to_be_bytes());
+        self.command(&cb, DataPhase::In(&mut buf))?;
+
+        Ok(Capacity::create(
+            u64::from_be_bytes(buf[..8].try_into().unwrap_or_default()),
+            u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        ))
+    }
+
+    /// Gets the [Capacity], with `READ CAPACITY (16)` for devices above 2 TiB blocks.
+    pub fn read_capacity(&mut self) -> Result<Capacity> {
+        match self.read_capacity_10()? {
+            cap if cap.last_lba() == u32::MAX as u64 => self.read_capacity_16(),
+            cap => Ok(cap),
+        }
+    }
+
+    /// Reads blocks with `READ (10)`, filling the buffer.
+    ///
+    /// Fails with [InvalidArgument](Error::InvalidArgument) above 65535 blocks.
+    pub fn read_10(&mut self, lba: u32, buf: &mut [u8], block_len: u32) -> Result<usize> {
+        let cb = rw_10(SCSI_READ_10, lba, blocks(buf.len(), block_len)?)?;
+        self.command(&cb, DataPhase::In(buf))
+    }
+
+    /// Reads blocks with `READ (16)`, filling the buffer.
+    pub fn read_16(&mut self, lba: u64, buf: &mut [u8], block_len: u32) -> Result<usize> {
+        let cb = rw_16(SCSI_READ_16, lba, blocks(buf.len(), block_len)?);
+        self.command(&cb, DataPhase::In(buf))
+    }
+
+    /// Writes blocks with `WRITE (10)`.
+    ///
+    /// Fails with [InvalidArgument](Error::InvalidArgument) above 65535 blocks.
+    pub fn write_10(&mut self, lba: u32, data: &[u8], block_len: u32) -> Result<usize> {
+        let cb = rw_10(SCSI_WRITE_10, lba, blocks(data.len(), block_len)?)?;
+        self.command(&cb, DataPhase::Out(data))
+    }
+
+    /// Writes blocks with `WRITE (16)`.
+    pub fn write_16(&mut self, lba: u64, data: &[u8], block_len: u32) -> Result<usize> {
+        let cb = rw_16(SCSI_WRITE_16, lba, blocks(data.len(), block_len)?);
+        self.command(&cb, DataPhase::Out(data))
+    }
+
+    /// Reads blocks, with `READ (16)` when `READ (10)` cannot address them.
+    pub fn read(&mut self, lba: u64, buf: &mut [u8], block_len: u32) -> Result<usize> {
+        match u32::try_from(lba) {
+            Ok(lba) if blocks(buf.len(), block_len)? <= u16::MAX as u32 => {
+                self.read_10(lba, buf, block_len)
+            }
+            _ => self.read_16(lba, buf, block_len),
+        }
+    }
+
+    /// Writes blocks, with `WRITE (16)` when `WRITE (10)` cannot address them.
+    pub fn write(&mut self, lba: u64, data: &[u8], block_len: u32) -> Result<usize> {
+        match u32::try_from(lba) {
+            Ok(lba) if blocks(data.len(), block_len)? <= u16::MAX as u32 => {
+                self.write_10(lba, data, block_len)
+            }
+            _ => self.write_16(lba, data, block_len),
+        }
+    }
+}
+
+fn blocks(len: usize, block_len: u32) -> Result<u32> {
+    if block_len == 0 || len % block_len as usize != 0 {
+        return Err(Error::InvalidArgument(format!(
+            "length {len} is not a multiple of the block length {block_len}"
+        )));
+    }
+    u32::try_from(len / block_len as usize)
+        .map_err(|_| Error::InvalidArgument(format!("too many blocks: {len}")))
+}
+
+fn rw_10(opcode: u8, lba: u32, blocks: u32) -> Result<[u8; 10]> {
+    let blocks = u16::try_from(blocks).map_err(|_| {
+        Error::InvalidArgument(format!("too many blocks for a 10-byte CDB: {blocks}"))
+    })?;
+
+    let mut cb = [0u8; 10];
+    cb[0] = opcode;
+    cb[2..6].copy_from_slice(&lba.to_be_bytes());
+    cb[7..9].copy_from_slice(&blocks.to_be_bytes());
+    Ok(cb)
+}
+
+fn rw_16(opcode: u8, lba: u64, blocks: u32) -> [u8; 16] {
+    let mut cb = [0u8; 16];
+    cb[0] = opcode;
+    cb[2..10].copy_from_slice(&lba.to_be_bytes());
+    cb[10..14].copy_from_slice(&blocks.to_be_bytes());
+    cb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // Bulk-Only SCSI interface 0, with a pair of Bulk endpoints
+    const DESCRIPTORS: [u8; 50] = [
+        0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x81, 0x07, 0x81, 0x55, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x20, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x02, 0x08, 0x06, 0x50, 0x00, //
+        0x07, 0x05, 0x81, 0x02, 0x00, 0x02, 0x00, //
+        0x07, 0x05, 0x02, 0x02, 0x00, 0x02, 0x00,
+    ];
+
+    fn csw(tag: u32, residue: u32, status: CswStatus) -> Vec<u8> {
+        CommandStatusWrapper::create(tag, residue, status)
+            .to_bytes()
+            .into()
+    }
+
+    #[test]
+    fn test_bulk_only_scsi() -> Result<()> {
+        let mut inquiry = [0x20u8; INQUIRY_LEN];
+        inquiry[..8].copy_from_slice(&[0x00, 0x80, 0x06, 0x02, 0x1f, 0, 0, 0]);
+        inquiry[8..12].copy_from_slice(b"USB ");
+        inquiry[16..21].copy_from_slice(b"Flash");
+        inquiry[32..36].copy_from_slice(b"1.00");
+
+        let mut sense = [0u8; SENSE_LEN];
+        sense[..3].copy_from_slice(&[0x70, 0x00, 0x02]);
+        sense[12..14].copy_from_slice(&[0x3a, 0x00]);
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "usb-storage")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, BOT_GET_MAX_LUN, 0, 0)
+                    .with_response(MockResponse::Data(vec![0])),
+            )
+            // INQUIRY
+            .with_data(0x81, inquiry)
+            .with_data(0x81, csw(1, 0, CswStatus::Passed))
+            // TEST UNIT READY fails, REQUEST SENSE reports the missing medium
+            .with_data(0x81, csw(2, 0, CswStatus::Failed))
+            .with_data(0x81, sense)
+            .with_data(0x81, csw(3, 0, CswStatus::Passed))
+            // READ CAPACITY (10): 2048 blocks of 512 bytes
+            .with_data(0x81, [0x00, 0x00, 0x07, 0xff, 0x00, 0x00, 0x02, 0x00])
+            .with_data(0x81, csw(4, 0, CswStatus::Passed))
+            // READ (10)
+            .with_data(0x81, [0xa5u8; 512])
+            .with_data(0x81, csw(5, 0, CswStatus::Passed))
+            // WRITE (10)
+            .with_data(0x81, csw(6, 0, CswStatus::Passed));
+
+        let mut scsi = Scsi::open(dev)?;
+        assert_eq!(scsi.transport().max_lun()?, 0);
+
+        let inquiry = scsi.inquiry()?;
+        assert_eq!((inquiry.vendor(), inquiry.product()), ("USB", "Flash"));
+        assert!(inquiry.removable());
+
+        let err = scsi.test_unit_ready().unwrap_err();
+        let sense = err
+            .class_status::<SenseData>()
+            .unwrap_or_else(|| panic!("unexpected TEST UNIT READY error: {err}"));
+        assert_eq!(sense.sense_key(), SenseKey::NotReady);
+        assert_eq!((sense.asc(), sense.ascq()), (0x3a, 0x00));
+
+        let cap = scsi.read_capacity()?;
+        assert_eq!((cap.blocks(), cap.block_len()), (2048, 512));
+        assert_eq!(Capacity::create(u64::MAX, 512).bytes(), u64::MAX);
+        assert!(CommandBlockWrapper::create(1, 0, false, 0, &[0u8; 17]).is_err());
+
+        let mut block = [0u8; 512];
+        assert_eq!(scsi.read(16, &mut block, cap.block_len())?, 512);
+        assert!(block.iter().all(|&b| b == 0xa5));
+        assert_eq!(scsi.write(17, &block, cap.block_len())?, 512);
+
+        // the 10-byte CDBs cannot hold the transfer length, nothing is sent
+        let mut large = vec![0u8; 0x10000];
+        assert!(matches!(
+            scsi.read_10(0, &mut large, 1),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            scsi.write_10(0, &large, 1),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let dev = scsi.into_transport().close()?;
+        let written = dev.take_written(0x02);
+        // INQUIRY, TEST UNIT READY, REQUEST SENSE, READ CAPACITY, READ, and WRITE with its data
+        assert_eq!(written.len(), 7);
+        assert_eq!(&written[4][15..25], &rw_10(SCSI_READ_10, 16, 1)?);
+        assert_eq!(written[6], block);
+
+        dev.verify()
+    }
+}
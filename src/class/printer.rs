@@ -0,0 +1,471 @@
+//! USB printer class driver.
+//!
+//! Drives label and receipt printers in place of the `usblp` kernel driver. Print jobs go out
+//! through [Write] on the Bulk OUT endpoint, and bidirectional printers report status on the
+//! Bulk IN back channel, read through [Read].
+
+use std::io::{self, Read, Write};
+use std::{cmp, fmt};
+
+use super::{class_request, BulkReader, REQUEST_TYPE_CLASS_IN};
+use crate::descriptor::Descriptors;
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsSetInterface};
+
+pub const PRINTER_CLASS: u8 = 0x07;
+pub const PRINTER_SUBCLASS: u8 = 0x01;
+pub const PRINTER_PROTOCOL_UNIDIRECTIONAL: u8 = 0x01;
+pub const PRINTER_PROTOCOL_BIDIRECTIONAL: u8 = 0x02;
+pub const PRINTER_PROTOCOL_IEEE_1284_4: u8 = 0x03;
+
+pub const PRINTER_GET_DEVICE_ID: u8 = 0x00;
+pub const PRINTER_GET_PORT_STATUS: u8 = 0x01;
+pub const PRINTER_SOFT_RESET: u8 = 0x02;
+
+// SOFT_RESET is addressed to the "other" recipient, as implemented by printers and `usblp`
+const REQUEST_TYPE_CLASS_OTHER_OUT: u8 = 0x23;
+// GET_DEVICE_ID length prefix, and largest response
+const DEVICE_ID_HEADER_LEN: usize = 2;
+const MAX_DEVICE_ID_LEN: usize = 1024;
+
+const PORT_STATUS_NOT_ERROR: u8 = 0x08;
+const PORT_STATUS_SELECTED: u8 = 0x10;
+const PORT_STATUS_PAPER_EMPTY: u8 = 0x20;
+
+/// Represents an IEEE 1284 device ID: `KEY:value;` pairs, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId {
+    fields: Vec<(String, String)>,
+}
+
+impl DeviceId {
+    /// Creates a new [DeviceId].
+    pub const fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    /// Parses the [DeviceId] from its string representation, e.g. `MFG:ACME;MDL:Label 1;`.
+    ///
+    /// Keys and values are trimmed, entries without a `:` separator are skipped.
+    pub fn parse(id: &str) -> Self {
+        Self {
+            fields: id
+                .split(';')
+                .filter_map(|f| f.split_once(':'))
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .filter(|(k, _)| !k.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Gets the list of key/value pairs.
+    pub fn fields(&self) -> &[(String, String)] {
+        self.fields.as_ref()
+    }
+
+    /// Gets the value of a key, compared case-insensitively.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Gets the manufacturer, from the `MANUFACTURER` or `MFG` key.
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.get("MANUFACTURER").or_else(|| self.get("MFG"))
+    }
+
+    /// Gets the model, from the `MODEL` or `MDL` key.
+    pub fn model(&self) -> Option<&str> {
+        self.get("MODEL").or_else(|| self.get("MDL"))
+    }
+
+    /// Gets the supported command sets, from the `COMMAND SET` or `CMD` key.
+    pub fn command_set(&self) -> Vec<&str> {
+        self.get("COMMAND SET")
+            .or_else(|| self.get("CMD"))
+            .map(|c| {
+                c.split(',')
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Gets the device class, from the `CLASS` or `CLS` key.
+    pub fn class(&self) -> Option<&str> {
+        self.get("CLASS").or_else(|| self.get("CLS"))
+    }
+
+    /// Gets the description, from the `DESCRIPTION` or `DES` key.
+    pub fn description(&self) -> Option<&str> {
+        self.get("DESCRIPTION").or_else(|| self.get("DES"))
+    }
+
+    /// Gets the serial number, from the `SERIALNUMBER` or `SN` key.
+    pub fn serial_number(&self) -> Option<&str> {
+        self.get("SERIALNUMBER").or_else(|| self.get("SN"))
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, val)) in self.fields.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, r#""{key}": "{val}""#)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Represents the printer port status, in the Centronics status register layout.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortStatus(u8);
+
+impl PortStatus {
+    /// Creates a new [PortStatus].
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [PortStatus] from its raw value.
+    pub const fn create(val: u8) -> Self {
+        Self(val)
+    }
+
+    /// Gets the raw status bits.
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Gets whether the printer is out of paper.
+    pub const fn paper_empty(&self) -> bool {
+        self.0 & PORT_STATUS_PAPER_EMPTY != 0
+    }
+
+    /// Gets whether the printer is selected (online).
+    pub const fn selected(&self) -> bool {
+        self.0 & PORT_STATUS_SELECTED != 0
+    }
+
+    /// Gets whether the printer reports an error.
+    pub const fn error(&self) -> bool {
+        self.0 & PORT_STATUS_NOT_ERROR == 0
+    }
+}
+
+impl fmt::Display for PortStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""paper_empty": {}, "#, self.paper_empty())?;
+        write!(f, r#""selected": {}, "#, self.selected())?;
+        write!(f, r#""error": {}"#, self.error())?;
+        write!(f, "}}")
+    }
+}
+
+/// USB printer over a [UsbBackend].
+///
+/// Implements [Write] for print jobs, and [Read] for the back channel of bidirectional
+/// printers.
+pub struct Printer<B: UsbBackend> {
+    backend: B,
+    config_index: u8,
+    iface: u8,
+    alt_setting: u8,
+    protocol: u8,
+    reader: Option<BulkReader>,
+    bulk_out: u8,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Printer<B> {
+    /// Opens the first printer interface of the active configuration.
+    ///
+    /// Selects the bidirectional alternate setting when the printer has one, and claims the
+    /// interface, detaching the `usblp` kernel driver.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let descriptors = Descriptors::parse(&backend.descriptors()?)?;
+        let config_index = descriptors
+            .configs()
+            .iter()
+            .position(|c| c.configuration_value() == config.configuration_value())
+            .unwrap_or_default() as u8;
+
+        let desc = config
+            .interfaces()
+            .iter()
+            .filter(|i| i.class() == PRINTER_CLASS && i.subclass() == PRINTER_SUBCLASS)
+            .filter(|i| super::find_endpoint(i, TransferType::Bulk, false).is_some())
+            .max_by_key(|i| {
+                // prefer the first interface, then its bidirectional setting
+                (
+                    cmp::Reverse(i.number()),
+                    i.protocol() == PRINTER_PROTOCOL_BIDIRECTIONAL,
+                )
+            })
+            .ok_or(Error::NotFound("printer interface".into()))?;
+
+        let bulk_out = super::find_endpoint(desc, TransferType::Bulk, false)
+            .ok_or(Error::NotFound("printer Bulk OUT endpoint".into()))?;
+        let bulk_in = super::find_endpoint(desc, TransferType::Bulk, true);
+
+        let printer = Self {
+            config_index,
+            iface: desc.number(),
+            alt_setting: desc.alternate_setting(),
+            protocol: desc.protocol(),
+            reader: bulk_in.map(BulkReader::new),
+            bulk_out: bulk_out.address(),
+            timeout: super::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        super::claim_detaching(&printer.backend, printer.iface)?;
+        if printer.alt_setting != 0 {
+            printer.backend.set_interface(&UsbfsSetInterface::create(
+                printer.iface as u32,
+                printer.alt_setting as u32,
+            ))?;
+        }
+
+        Ok(printer)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the selected alternate setting.
+    pub const fn alt_setting(&self) -> u8 {
+        self.alt_setting
+    }
+
+    /// Gets the interface protocol: unidirectional, bidirectional, or IEEE 1284.4.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets whether the printer has a back channel.
+    pub const fn is_bidirectional(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    /// Gets the Bulk IN (back channel) endpoint address, if any.
+    pub fn bulk_in(&self) -> Option<u8> {
+        self.reader.as_ref().map(|r| r.endpoint())
+    }
+
+    /// Gets the Bulk OUT endpoint address.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Gets the raw IEEE 1284 device ID string with `GET_DEVICE_ID`.
+    pub fn device_id_string(&self) -> Result<String> {
+        let mut buf = vec![0u8; MAX_DEVICE_ID_LEN];
+        let index = ((self.iface as u16) << 8) | self.alt_setting as u16;
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            PRINTER_GET_DEVICE_ID,
+            self.config_index as u16,
+            index,
+            &mut buf,
+            self.timeout,
+        )?;
+
+        if len < DEVICE_ID_HEADER_LEN {
+            return Err(Error::InvalidMessage(format!("device ID too short: {len}")));
+        }
+
+        // the big-endian length includes itself
+        let id_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        let end = id_len.clamp(DEVICE_ID_HEADER_LEN, len);
+        Ok(String::from_utf8_lossy(&buf[DEVICE_ID_HEADER_LEN..end]).into_owned())
+    }
+
+    /// Gets the [DeviceId] with `GET_DEVICE_ID`.
+    pub fn device_id(&self) -> Result<DeviceId> {
+        self.device_id_string().map(|id| DeviceId::parse(&id))
+    }
+
+    /// Gets the [PortStatus] with `GET_PORT_STATUS`.
+    pub fn port_status(&self) -> Result<PortStatus> {
+        let mut buf = [0u8];
+        match class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            PRINTER_GET_PORT_STATUS,
+            0,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )? {
+            1 => Ok(PortStatus::create(buf[0])),
+            _ => Err(Error::InvalidMessage("empty port status".into())),
+        }
+    }
+
+    /// Flushes the printer buffers, and resets the Bulk endpoints with `SOFT_RESET`.
+    pub fn soft_reset(&mut self) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OTHER_OUT,
+            PRINTER_SOFT_RESET,
+            0,
+            self.iface as u16,
+            &mut [],
+            self.timeout,
+        )?;
+
+        if let Some(reader) = self.reader.as_mut() {
+            reader.clear();
+        }
+        Ok(())
+    }
+
+    /// Releases the interface, and converts the [Printer] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+}
+
+impl<B: UsbBackend> Read for Printer<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let reader = self.reader.as_mut().ok_or(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "printer has no back channel",
+        ))?;
+        Ok(reader.read(&self.backend, buf, self.timeout)?)
+    }
+}
+
+impl<B: UsbBackend> Write for Printer<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(super::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Printer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Printer")
+            .field("iface", &self.iface)
+            .field("alt_setting", &self.alt_setting)
+            .field("protocol", &self.protocol)
+            .field("bulk_in", &self.bulk_in())
+            .field("bulk_out", &self.bulk_out)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // printer interface 0: unidirectional setting 0, bidirectional setting 1
+    const DESCRIPTORS: [u8; 66] = [
+        0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x28, 0x0a, 0x01, 0x00, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x30, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x07, 0x01, 0x01, 0x00, //
+        0x07, 0x05, 0x01, 0x02, 0x40, 0x00, 0x00, //
+        0x09, 0x04, 0x00, 0x01, 0x02, 0x07, 0x01, 0x02, 0x00, //
+        0x07, 0x05, 0x01, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x82, 0x02, 0x40, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_printer() -> Result<()> {
+        let id = b"MFG:ACME;MDL:Label 42;CMD:ZPL, EPL;CLS:PRINTER;";
+        let mut id_response = ((id.len() + 2) as u16).to_be_bytes().to_vec();
+        id_response.extend_from_slice(id);
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "usblp")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, PRINTER_GET_DEVICE_ID, 0, 0x0001)
+                    .with_response(MockResponse::Data(id_response)),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, PRINTER_GET_PORT_STATUS, 0, 0)
+                    .with_response(MockResponse::Data(vec![0x38])),
+            )
+            .with_control(MockControl::create(
+                REQUEST_TYPE_CLASS_OTHER_OUT,
+                PRINTER_SOFT_RESET,
+                0,
+                0,
+            ))
+            .with_data(0x82, *b"OK\n");
+
+        let mut printer = Printer::open(dev)?;
+        assert_eq!(printer.alt_setting(), 1);
+        assert!(printer.is_bidirectional());
+        assert_eq!(printer.backend().alt_setting(0), 1);
+
+        let id = printer.device_id()?;
+        assert_eq!(id.manufacturer(), Some("ACME"));
+        assert_eq!(id.model(), Some("Label 42"));
+        assert_eq!(id.command_set(), ["ZPL", "EPL"]);
+        assert_eq!(id.get("cls"), Some("PRINTER"));
+
+        let status = printer.port_status()?;
+        assert!(status.paper_empty() && status.selected() && !status.error());
+        printer.soft_reset()?;
+
+        printer.write_all(b"^XA^FDHello^FS^XZ")?;
+        let mut buf = [0u8; 3];
+        printer.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"OK\n");
+
+        let dev = printer.close()?;
+        assert_eq!(dev.take_written(0x01), [b"^XA^FDHello^FS^XZ".to_vec()]);
+
+        dev.verify()
+    }
+}
@@ -0,0 +1,178 @@
+//! Drivers for USB to UART bridge chips.
+//!
+//! Bridge chips are vendor-specific devices: line settings and modem control lines are set with
+//! vendor Control requests, and data flows over a pair of Bulk endpoints. Each driver implements
+//! the common [SerialPort] trait, and [open] picks the driver from the vendor and product IDs.
+//!
+//! The [ftdi], [cp210x], [ch34x] and [pl2303] modules implement the FTDI, Silicon Labs, WCH and
+//! Prolific protocols.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use super::cdc_acm::{ControlLineState, LineCoding};
+use super::BulkReader;
+use crate::descriptor::{Descriptors, DeviceDescriptor, InterfaceDescriptor};
+use crate::{Error, Result, TransferType, UsbBackend};
+
+pub mod ch34x;
+pub mod cp210x;
+pub mod ftdi;
+pub mod pl2303;
+
+pub use ch34x::Ch34x;
+pub use cp210x::Cp210x;
+pub use ftdi::{BitMode, Ftdi, FtdiChip};
+pub use pl2303::{Pl2303, Pl2303Type};
+
+/// Represents the modem status lines driven by the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModemStatus {
+    cts: bool,
+    dsr: bool,
+    ri: bool,
+    dcd: bool,
+}
+
+impl ModemStatus {
+    // upper nibble of the 16550 Modem Status Register, used by FTDI and CP210x
+    const MSR_CTS: u8 = 0x10;
+    const MSR_DSR: u8 = 0x20;
+    const MSR_RI: u8 = 0x40;
+    const MSR_DCD: u8 = 0x80;
+
+    /// Creates a new [ModemStatus], with all lines deasserted.
+    pub const fn new() -> Self {
+        Self::create(false, false, false, false)
+    }
+
+    /// Creates a new [ModemStatus] from the provided parameters.
+    pub const fn create(cts: bool, dsr: bool, ri: bool, dcd: bool) -> Self {
+        Self { cts, dsr, ri, dcd }
+    }
+
+    /// Creates a new [ModemStatus] from a 16550 Modem Status Register value.
+    pub const fn from_msr(msr: u8) -> Self {
+        Self::create(
+            msr & Self::MSR_CTS != 0,
+            msr & Self::MSR_DSR != 0,
+            msr & Self::MSR_RI != 0,
+            msr & Self::MSR_DCD != 0,
+        )
+    }
+
+    /// Gets whether Clear To Send is asserted.
+    pub const fn cts(&self) -> bool {
+        self.cts
+    }
+
+    /// Gets whether Data Set Ready is asserted.
+    pub const fn dsr(&self) -> bool {
+        self.dsr
+    }
+
+    /// Gets whether the Ring Indicator is asserted.
+    pub const fn ri(&self) -> bool {
+        self.ri
+    }
+
+    /// Gets whether Data Carrier Detect is asserted.
+    pub const fn dcd(&self) -> bool {
+        self.dcd
+    }
+}
+
+impl fmt::Display for ModemStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""cts": {}, "#, self.cts)?;
+        write!(f, r#""dsr": {}, "#, self.dsr)?;
+        write!(f, r#""ri": {}, "#, self.ri)?;
+        write!(f, r#""dcd": {}"#, self.dcd)?;
+        write!(f, "}}")
+    }
+}
+
+/// Common interface of USB serial ports.
+///
+/// Data is read and written with [Read] and [Write], reads fail with
+/// [TimedOut](std::io::ErrorKind::TimedOut) when no data arrives before the timeout.
+pub trait SerialPort: Read + Write {
+    /// Sets the baud rate and character framing.
+    fn set_line_coding(&mut self, coding: &LineCoding) -> Result<()>;
+
+    /// Gets the [LineCoding] last set.
+    fn line_coding(&self) -> LineCoding;
+
+    /// Sets the DTR and RTS modem control lines.
+    fn set_control_lines(&mut self, state: ControlLineState) -> Result<()>;
+
+    /// Gets the [ModemStatus] lines.
+    fn modem_status(&mut self) -> Result<ModemStatus>;
+
+    /// Starts or stops sending a break.
+    fn set_break(&mut self, on: bool) -> Result<()>;
+
+    /// Gets the transfer timeout, in milliseconds.
+    fn timeout(&self) -> u32;
+
+    /// Sets the transfer timeout, in milliseconds.
+    fn set_timeout(&mut self, timeout: u32);
+
+    /// Sets the baud rate, keeping the character framing.
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        let coding = self.line_coding().with_baud_rate(baud_rate);
+        self.set_line_coding(&coding)
+    }
+}
+
+/// Opens the serial port of a supported bridge chip, by vendor and product ID.
+///
+/// Multi-port chips are opened on their first port.
+pub fn open<B: UsbBackend + 'static>(backend: B) -> Result<Box<dyn SerialPort>> {
+    let device = *Descriptors::parse(&backend.descriptors()?)?.device();
+    let ids = (device.vendor_id(), device.product_id());
+
+    if ftdi::FTDI_IDS.contains(&ids) {
+        Ok(Box::new(Ftdi::open(backend)?))
+    } else if cp210x::CP210X_IDS.contains(&ids) {
+        Ok(Box::new(Cp210x::open(backend)?))
+    } else if ch34x::CH34X_IDS.contains(&ids) {
+        Ok(Box::new(Ch34x::open(backend)?))
+    } else if pl2303::PL2303_IDS.contains(&ids) {
+        Ok(Box::new(Pl2303::open(backend)?))
+    } else {
+        Err(Error::NotFound(format!(
+            "serial driver for {:04x}:{:04x}",
+            ids.0, ids.1
+        )))
+    }
+}
+
+/// Finds the Bulk endpoints of a serial interface: a reader for the IN one, and the OUT address.
+pub(crate) fn find_bulk_endpoints(iface: &InterfaceDescriptor) -> Result<(BulkReader, u8)> {
+    let bulk_in = super::find_endpoint(iface, TransferType::Bulk, true)
+        .ok_or(Error::NotFound("serial Bulk IN endpoint".into()))?;
+    let bulk_out = super::find_endpoint(iface, TransferType::Bulk, false)
+        .ok_or(Error::NotFound("serial Bulk OUT endpoint".into()))?;
+    Ok((BulkReader::new(bulk_in), bulk_out.address()))
+}
+
+/// Claims an interface, and gets the device descriptor and the interface descriptor.
+pub(crate) fn claim_port<B: UsbBackend>(
+    backend: &B,
+    iface: u8,
+) -> Result<(DeviceDescriptor, InterfaceDescriptor)> {
+    let device = *Descriptors::parse(&backend.descriptors()?)?.device();
+    let config = super::active_config(backend)?;
+    let desc = config
+        .alt_settings(iface)
+        .next()
+        .cloned()
+        .ok_or(Error::NotFound(format!("serial interface {iface}")))?;
+
+    super::claim_detaching(backend, iface)?;
+
+    Ok((device, desc))
+}
@@ -0,0 +1,429 @@
+//! WCH CH340 and CH341 driver.
+//!
+//! The chip is configured through registers, read and written in pairs with vendor requests.
+//! The baud rate is a prescaler and an 8-bit divisor of a 48 MHz clock, and the modem control
+//! lines are set inverted with `MODEM_CTRL`.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{ModemStatus, SerialPort};
+use crate::class::cdc_acm::{ControlLineState, LineCoding, Parity, StopBits};
+use crate::class::BulkReader;
+use crate::class::{class_request, REQUEST_TYPE_VENDOR_IN, REQUEST_TYPE_VENDOR_OUT};
+use crate::{Error, Result, UsbBackend};
+
+/// Vendor and product IDs of the CH340 and CH341 chips.
+pub const CH34X_IDS: [(u16, u16); 3] = [(0x1a86, 0x7523), (0x1a86, 0x7522), (0x1a86, 0x5523)];
+
+pub const CH34X_READ_VERSION: u8 = 0x5f;
+pub const CH34X_READ_REG: u8 = 0x95;
+pub const CH34X_WRITE_REG: u8 = 0x9a;
+pub const CH34X_SERIAL_INIT: u8 = 0xa1;
+pub const CH34X_MODEM_CTRL: u8 = 0xa4;
+
+pub const CH34X_REG_BREAK: u8 = 0x05;
+pub const CH34X_REG_PRESCALER: u8 = 0x12;
+pub const CH34X_REG_DIVISOR: u8 = 0x13;
+pub const CH34X_REG_LCR: u8 = 0x18;
+pub const CH34X_REG_LCR2: u8 = 0x25;
+pub const CH34X_REG_STATUS: u8 = 0x06;
+pub const CH34X_REG_STATUS2: u8 = 0x07;
+
+// LCR bits
+const CH34X_LCR_ENABLE_RX: u8 = 0x80;
+const CH34X_LCR_ENABLE_TX: u8 = 0x40;
+const CH34X_LCR_MARK_SPACE: u8 = 0x20;
+const CH34X_LCR_PAR_EVEN: u8 = 0x10;
+const CH34X_LCR_ENABLE_PAR: u8 = 0x08;
+const CH34X_LCR_STOP_BITS_2: u8 = 0x04;
+
+// break register bit, cleared while sending a break
+const CH34X_NBREAK: u8 = 0x01;
+
+// MODEM_CTRL bits, inverted on the wire
+const CH34X_MCR_DTR: u16 = 0x20;
+const CH34X_MCR_RTS: u16 = 0x40;
+
+// modem status bits, inverted on the wire
+const CH34X_MSR_CTS: u8 = 0x01;
+const CH34X_MSR_DSR: u8 = 0x02;
+const CH34X_MSR_RI: u8 = 0x04;
+const CH34X_MSR_DCD: u8 = 0x08;
+
+// divisor register bit sending partial packets without waiting for a full one
+const CH34X_DIVISOR_NO_BUFFERING: u16 = 0x80;
+
+// chip versions from 0x30 use a single LCR register
+const CH34X_VERSION_LCR: u8 = 0x30;
+
+const CH34X_CLOCK: u32 = 48_000_000;
+const CH34X_MIN_BAUD: u32 = 46;
+const CH34X_MAX_BAUD: u32 = 3_000_000;
+
+// clock divider of a prescaler and factor
+const fn clock_divider(prescaler: u32, factor: u32) -> u32 {
+    1 << (12 - 3 * prescaler - factor)
+}
+
+/// Computes the prescaler and divisor register pair of a baud rate.
+///
+/// Picks the fastest clock giving a divisor below 256, and rounds the divisor to the closest
+/// rate.
+pub fn baud_rate_divisor(baud_rate: u32) -> Result<u16> {
+    if !(CH34X_MIN_BAUD..=CH34X_MAX_BAUD).contains(&baud_rate) {
+        return Err(Error::InvalidArgument(format!(
+            "CH34x baud rate out of range: {baud_rate}"
+        )));
+    }
+
+    let prescaler = (0..4u32)
+        .rev()
+        .find(|ps| baud_rate > CH34X_CLOCK / (clock_divider(*ps, 1) * 512))
+        .unwrap_or(0);
+
+    let mut factor = 1;
+    let mut divider = clock_divider(prescaler, factor);
+    let mut div = CH34X_CLOCK / (divider * baud_rate);
+    if !(9..=255).contains(&div) {
+        div /= 2;
+        divider *= 2;
+        factor = 0;
+    }
+    if div < 2 {
+        return Err(Error::InvalidArgument(format!(
+            "CH34x baud rate unsupported: {baud_rate}"
+        )));
+    }
+
+    // pick the next divisor if closer, in sixteenths to avoid rounding errors at low rates
+    let rate = |div: u32| 16 * CH34X_CLOCK / (divider * div);
+    if rate(div) - 16 * baud_rate >= 16 * baud_rate - rate(div + 1) {
+        div += 1;
+    }
+    // the slower clock is more tolerant to errors
+    if factor == 1 && div % 2 == 0 {
+        div /= 2;
+        factor = 0;
+    }
+
+    Ok((((0x100 - div) << 8) | (factor << 2) | prescaler) as u16)
+}
+
+/// WCH CH340 and CH341 serial port over a [UsbBackend].
+pub struct Ch34x<B: UsbBackend> {
+    backend: B,
+    version: u8,
+    reader: BulkReader,
+    bulk_out: u8,
+    coding: LineCoding,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Ch34x<B> {
+    /// Opens a CH340 or CH341 chip.
+    ///
+    /// Claims the interface, detaching the `ch341` kernel driver, reads the chip version, and
+    /// initializes the port with the default [LineCoding] and the control lines deasserted.
+    pub fn open(backend: B) -> Result<Self> {
+        let (_, desc) = super::claim_port(&backend, 0)?;
+        let (reader, bulk_out) = super::find_bulk_endpoints(&desc)?;
+
+        let mut ch = Self {
+            version: 0,
+            reader,
+            bulk_out,
+            coding: LineCoding::new(),
+            timeout: crate::class::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        let mut buf = [0u8; 2];
+        ch.request_in(CH34X_READ_VERSION, 0, &mut buf)?;
+        ch.version = buf[0];
+        ch.request_out(CH34X_SERIAL_INIT, 0, 0)?;
+        ch.set_line_coding(&LineCoding::new())?;
+        ch.set_control_lines(ControlLineState::new())?;
+
+        Ok(ch)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the chip version.
+    pub const fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Gets the Bulk IN endpoint.
+    pub const fn bulk_in(&self) -> u8 {
+        self.reader.endpoint()
+    }
+
+    /// Gets the Bulk OUT endpoint.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reads a pair of registers.
+    pub fn read_registers(&self, first: u8, second: u8) -> Result<[u8; 2]> {
+        let mut buf = [0u8; 2];
+        self.request_in(
+            CH34X_READ_REG,
+            u16::from_le_bytes([first, second]),
+            &mut buf,
+        )?;
+        Ok(buf)
+    }
+
+    /// Writes a pair of registers.
+    pub fn write_registers(&self, first: u8, second: u8, values: [u8; 2]) -> Result<()> {
+        self.request_out(
+            CH34X_WRITE_REG,
+            u16::from_le_bytes([first, second]),
+            u16::from_le_bytes(values),
+        )
+    }
+
+    /// Releases the interface, and converts the [Ch34x] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(0)?;
+        Ok(self.backend)
+    }
+
+    fn request_out(&self, request: u8, value: u16, index: u16) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_OUT,
+            request,
+            value,
+            index,
+            &mut [],
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    fn request_in(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IN,
+            request,
+            value,
+            0,
+            data,
+            self.timeout,
+        )?;
+        if len < data.len() {
+            return Err(Error::InvalidMessage(format!(
+                "CH34x request {request:#04x} response too short: {len}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> SerialPort for Ch34x<B> {
+    fn set_line_coding(&mut self, coding: &LineCoding) -> Result<()> {
+        if !(5..=8).contains(&coding.data_bits()) {
+            return Err(Error::InvalidArgument(format!(
+                "CH34x data bits: {}",
+                coding.data_bits()
+            )));
+        }
+
+        let mut lcr = CH34X_LCR_ENABLE_RX | CH34X_LCR_ENABLE_TX | (coding.data_bits() - 5);
+        lcr |= match coding.parity() {
+            Parity::None => 0,
+            Parity::Odd => CH34X_LCR_ENABLE_PAR,
+            Parity::Even => CH34X_LCR_ENABLE_PAR | CH34X_LCR_PAR_EVEN,
+            Parity::Mark => CH34X_LCR_ENABLE_PAR | CH34X_LCR_MARK_SPACE,
+            Parity::Space => CH34X_LCR_ENABLE_PAR | CH34X_LCR_MARK_SPACE | CH34X_LCR_PAR_EVEN,
+        };
+        lcr |= match coding.stop_bits() {
+            StopBits::One => 0,
+            StopBits::Two => CH34X_LCR_STOP_BITS_2,
+            StopBits::OnePointFive => {
+                return Err(Error::InvalidArgument("CH34x 1.5 stop bits".into()))
+            }
+        };
+
+        let divisor = baud_rate_divisor(coding.baud_rate())? | CH34X_DIVISOR_NO_BUFFERING;
+        self.write_registers(
+            CH34X_REG_PRESCALER,
+            CH34X_REG_DIVISOR,
+            divisor.to_le_bytes(),
+        )?;
+        // older chips keep their power-on framing
+        if self.version >= CH34X_VERSION_LCR {
+            self.write_registers(CH34X_REG_LCR, CH34X_REG_LCR2, [lcr, 0])?;
+        }
+
+        self.coding = *coding;
+        Ok(())
+    }
+
+    fn line_coding(&self) -> LineCoding {
+        self.coding
+    }
+
+    fn set_control_lines(&mut self, state: ControlLineState) -> Result<()> {
+        let mut mcr = 0;
+        if state.dtr() {
+            mcr |= CH34X_MCR_DTR;
+        }
+        if state.rts() {
+            mcr |= CH34X_MCR_RTS;
+        }
+        self.request_out(CH34X_MODEM_CTRL, !mcr, 0)
+    }
+
+    fn modem_status(&mut self) -> Result<ModemStatus> {
+        let status = !self.read_registers(CH34X_REG_STATUS, CH34X_REG_STATUS2)?[0];
+        Ok(ModemStatus::create(
+            status & CH34X_MSR_CTS != 0,
+            status & CH34X_MSR_DSR != 0,
+            status & CH34X_MSR_RI != 0,
+            status & CH34X_MSR_DCD != 0,
+        ))
+    }
+
+    fn set_break(&mut self, on: bool) -> Result<()> {
+        let [mut brk, mut lcr] = self.read_registers(CH34X_REG_BREAK, CH34X_REG_LCR)?;
+        if on {
+            brk &= !CH34X_NBREAK;
+            lcr &= !CH34X_LCR_ENABLE_TX;
+        } else {
+            brk |= CH34X_NBREAK;
+            lcr |= CH34X_LCR_ENABLE_TX;
+        }
+        self.write_registers(CH34X_REG_BREAK, CH34X_REG_LCR, [brk, lcr])
+    }
+
+    fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+}
+
+impl<B: UsbBackend> Read for Ch34x<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.reader.read(&self.backend, buf, self.timeout)?)
+    }
+}
+
+impl<B: UsbBackend> Write for Ch34x<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(crate::class::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Ch34x<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ch34x")
+            .field("version", &self.version)
+            .field("bulk_in", &self.reader.endpoint())
+            .field("bulk_out", &self.bulk_out)
+            .field("coding", &self.coding)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // CH340: one vendor interface with a pair of Bulk endpoints and an Interrupt endpoint
+    const DESCRIPTORS: [u8; 57] = [
+        0x12, 0x01, 0x10, 0x01, 0xff, 0x00, 0x00, 0x08, 0x86, 0x1a, 0x23, 0x75, 0x64, 0x02, 0x00,
+        0x02, 0x00, 0x01, //
+        0x09, 0x02, 0x27, 0x00, 0x01, 0x01, 0x00, 0x80, 0x31, //
+        0x09, 0x04, 0x00, 0x00, 0x03, 0xff, 0x01, 0x02, 0x00, //
+        0x07, 0x05, 0x82, 0x02, 0x20, 0x00, 0x00, //
+        0x07, 0x05, 0x02, 0x02, 0x20, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x03, 0x08, 0x00, 0x01,
+    ];
+
+    fn vendor_out(request: u8, value: u16, index: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_OUT, request, value, index)
+    }
+
+    fn vendor_in(request: u8, value: u16, data: [u8; 2]) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_IN, request, value, 0)
+            .with_response(MockResponse::Data(data.into()))
+    }
+
+    #[test]
+    fn test_ch34x() -> Result<()> {
+        assert_eq!(baud_rate_divisor(9600)?, 0xb202);
+        assert_eq!(baud_rate_divisor(115_200)?, 0xcc03);
+        assert_eq!(baud_rate_divisor(3_000_000)?, 0xfe03);
+        assert!(baud_rate_divisor(45).is_err());
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "ch341")
+            .with_control(vendor_in(CH34X_READ_VERSION, 0, [0x31, 0x00]))
+            .with_control(vendor_out(CH34X_SERIAL_INIT, 0, 0))
+            .with_control(vendor_out(CH34X_WRITE_REG, 0x1312, 0xb282))
+            .with_control(vendor_out(CH34X_WRITE_REG, 0x2518, 0x00c3))
+            .with_control(vendor_out(CH34X_MODEM_CTRL, 0xffff, 0))
+            .with_control(vendor_out(CH34X_WRITE_REG, 0x1312, 0xcc83))
+            .with_control(vendor_out(CH34X_WRITE_REG, 0x2518, 0x00da))
+            .with_control(vendor_out(CH34X_MODEM_CTRL, 0xff9f, 0))
+            .with_control(vendor_in(CH34X_READ_REG, 0x0706, [0xfc, 0xee]))
+            .with_control(vendor_in(CH34X_READ_REG, 0x1805, [0x9f, 0xda]))
+            .with_control(vendor_out(CH34X_WRITE_REG, 0x1805, 0x9a9e))
+            .with_data(0x82, *b"ready");
+
+        let mut ch = Ch34x::open(dev)?;
+        assert_eq!(ch.version(), 0x31);
+        assert_eq!(ch.backend().claimed_interfaces(), [0]);
+
+        let coding = LineCoding::create(115_200, StopBits::One, Parity::Even, 7);
+        ch.set_line_coding(&coding)?;
+        assert!(ch
+            .set_line_coding(&coding.with_stop_bits(StopBits::OnePointFive))
+            .is_err());
+        assert_eq!(ch.line_coding(), coding);
+
+        ch.set_control_lines(ControlLineState::create(true, true))?;
+        let status = ch.modem_status()?;
+        assert!(status.cts() && status.dsr() && !status.ri() && !status.dcd());
+        ch.set_break(true)?;
+
+        let mut buf = [0u8; 8];
+        assert_eq!(ch.read(&mut buf)?, 5);
+        assert_eq!(&buf[..5], b"ready");
+        ch.write_all(b"go")?;
+        assert_eq!(ch.backend().take_written(0x02), [b"go".to_vec()]);
+
+        let dev = ch.close()?;
+        assert!(dev.claimed_interfaces().is_empty());
+        dev.verify()
+    }
+}
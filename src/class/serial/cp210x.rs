@@ -0,0 +1,337 @@
+//! Silicon Labs CP210x driver.
+//!
+//! Each port is a vendor interface, enabled with `IFC_ENABLE` before use. Requests are
+//! addressed to the interface, and the baud rate is set directly in bits per second.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{ModemStatus, SerialPort};
+use crate::class::cdc_acm::{ControlLineState, LineCoding};
+use crate::class::BulkReader;
+use crate::class::{class_request, REQUEST_TYPE_VENDOR_IFACE_IN, REQUEST_TYPE_VENDOR_IFACE_OUT};
+use crate::{Error, Result, UsbBackend};
+
+/// Vendor and product IDs of the CP210x chips.
+pub const CP210X_IDS: [(u16, u16); 3] = [(0x10c4, 0xea60), (0x10c4, 0xea70), (0x10c4, 0xea71)];
+
+pub const CP210X_IFC_ENABLE: u8 = 0x00;
+pub const CP210X_SET_LINE_CTL: u8 = 0x03;
+pub const CP210X_GET_LINE_CTL: u8 = 0x04;
+pub const CP210X_SET_BREAK: u8 = 0x05;
+pub const CP210X_SET_MHS: u8 = 0x07;
+pub const CP210X_GET_MDMSTS: u8 = 0x08;
+pub const CP210X_PURGE: u8 = 0x12;
+pub const CP210X_GET_BAUDRATE: u8 = 0x1d;
+pub const CP210X_SET_BAUDRATE: u8 = 0x1e;
+
+// wValue bits of SET_MHS: line states, and the mask of lines to change
+const CP210X_MHS_DTR: u16 = 0x0001;
+const CP210X_MHS_RTS: u16 = 0x0002;
+const CP210X_MHS_MASK: u16 = 0x0300;
+
+// wValue of PURGE: both receive and transmit queues
+const CP210X_PURGE_ALL: u16 = 0x000f;
+
+/// Silicon Labs CP210x serial port over a [UsbBackend].
+pub struct Cp210x<B: UsbBackend> {
+    backend: B,
+    iface: u8,
+    reader: BulkReader,
+    bulk_out: u8,
+    coding: LineCoding,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Cp210x<B> {
+    /// Opens the first port of a CP210x chip.
+    pub fn open(backend: B) -> Result<Self> {
+        Self::open_port(backend, 0)
+    }
+
+    /// Opens a port of a CP210x chip, by interface number.
+    ///
+    /// Claims the interface, detaching the `cp210x` kernel driver, enables it, and sets the
+    /// default [LineCoding].
+    pub fn open_port(backend: B, port: u8) -> Result<Self> {
+        let (_, desc) = super::claim_port(&backend, port)?;
+        let (reader, bulk_out) = super::find_bulk_endpoints(&desc)?;
+
+        let mut cp = Self {
+            iface: port,
+            reader,
+            bulk_out,
+            coding: LineCoding::new(),
+            timeout: crate::class::DEFAULT_TIMEOUT,
+            backend,
+        };
+        cp.request_out(CP210X_IFC_ENABLE, 1, &mut [])?;
+        cp.set_line_coding(&LineCoding::new())?;
+
+        Ok(cp)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number of the port.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the Bulk IN endpoint.
+    pub const fn bulk_in(&self) -> u8 {
+        self.reader.endpoint()
+    }
+
+    /// Gets the Bulk OUT endpoint.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets the baud rate the chip actually runs at.
+    pub fn baud_rate(&self) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IFACE_IN,
+            CP210X_GET_BAUDRATE,
+            0,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        if len < buf.len() {
+            return Err(Error::InvalidMessage(format!(
+                "CP210x baud rate too short: {len}"
+            )));
+        }
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Discards the data in the receive and transmit queues of the chip.
+    pub fn purge(&mut self) -> Result<()> {
+        self.request_out(CP210X_PURGE, CP210X_PURGE_ALL, &mut [])?;
+        self.reader.clear();
+        Ok(())
+    }
+
+    /// Disables and releases the interface, and converts the [Cp210x] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.request_out(CP210X_IFC_ENABLE, 0, &mut [])?;
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn request_out(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IFACE_OUT,
+            request,
+            value,
+            self.iface as u16,
+            data,
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+}
+
+impl<B: UsbBackend> SerialPort for Cp210x<B> {
+    fn set_line_coding(&mut self, coding: &LineCoding) -> Result<()> {
+        if !(5..=8).contains(&coding.data_bits()) || coding.baud_rate() == 0 {
+            return Err(Error::InvalidArgument(format!(
+                "CP210x line coding: {coding}"
+            )));
+        }
+
+        // stop bits, parity and data bits by nibbles, in the CDC encodings
+        let line_ctl = coding.stop_bits().inner() as u16
+            | ((coding.parity().inner() as u16) << 4)
+            | ((coding.data_bits() as u16) << 8);
+        self.request_out(
+            CP210X_SET_BAUDRATE,
+            0,
+            &mut coding.baud_rate().to_le_bytes(),
+        )?;
+        self.request_out(CP210X_SET_LINE_CTL, line_ctl, &mut [])?;
+
+        self.coding = *coding;
+        Ok(())
+    }
+
+    fn line_coding(&self) -> LineCoding {
+        self.coding
+    }
+
+    fn set_control_lines(&mut self, state: ControlLineState) -> Result<()> {
+        let mut value = CP210X_MHS_MASK;
+        if state.dtr() {
+            value |= CP210X_MHS_DTR;
+        }
+        if state.rts() {
+            value |= CP210X_MHS_RTS;
+        }
+        self.request_out(CP210X_SET_MHS, value, &mut [])
+    }
+
+    fn modem_status(&mut self) -> Result<ModemStatus> {
+        let mut buf = [0u8; 1];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IFACE_IN,
+            CP210X_GET_MDMSTS,
+            0,
+            self.iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        if len == 0 {
+            return Err(Error::InvalidMessage("empty CP210x modem status".into()));
+        }
+        Ok(ModemStatus::from_msr(buf[0]))
+    }
+
+    fn set_break(&mut self, on: bool) -> Result<()> {
+        self.request_out(CP210X_SET_BREAK, on as u16, &mut [])
+    }
+
+    fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+}
+
+impl<B: UsbBackend> Read for Cp210x<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.reader.read(&self.backend, buf, self.timeout)?)
+    }
+}
+
+impl<B: UsbBackend> Write for Cp210x<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(crate::class::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Cp210x<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cp210x")
+            .field("iface", &self.iface)
+            .field("bulk_in", &self.reader.endpoint())
+            .field("bulk_out", &self.bulk_out)
+            .field("coding", &self.coding)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::{serial, Parity, StopBits};
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // CP2102: one vendor interface with a pair of Bulk endpoints
+    const DESCRIPTORS: [u8; 50] = [
+        0x12, 0x01, 0x10, 0x01, 0x00, 0x00, 0x00, 0x40, 0xc4, 0x10, 0x60, 0xea, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x20, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x02, 0xff, 0x00, 0x00, 0x02, //
+        0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x01, 0x02, 0x40, 0x00, 0x00,
+    ];
+
+    fn vendor_out(request: u8, value: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_IFACE_OUT, request, value, 0)
+    }
+
+    #[test]
+    fn test_cp210x() -> Result<()> {
+        let open = [
+            vendor_out(CP210X_IFC_ENABLE, 1),
+            vendor_out(CP210X_SET_BAUDRATE, 0).with_data(9600u32.to_le_bytes()),
+            vendor_out(CP210X_SET_LINE_CTL, 0x0800),
+        ];
+        let dev = open
+            .iter()
+            .cloned()
+            .fold(MockDevice::new(), |dev, ctrl| dev.with_control(ctrl))
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "cp210x")
+            .with_control(vendor_out(CP210X_SET_BAUDRATE, 0).with_data(115_200u32.to_le_bytes()))
+            .with_control(vendor_out(CP210X_SET_LINE_CTL, 0x0722))
+            .with_control(vendor_out(CP210X_SET_MHS, 0x0303))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_VENDOR_IFACE_IN, CP210X_GET_MDMSTS, 0, 0)
+                    .with_response(MockResponse::Data(vec![0xb3])),
+            )
+            .with_control(vendor_out(CP210X_SET_BREAK, 1))
+            .with_control(vendor_out(CP210X_SET_BREAK, 0))
+            .with_control(vendor_out(CP210X_IFC_ENABLE, 0))
+            .with_data(0x81, *b"OK\r\n");
+
+        let mut cp = Cp210x::open(dev)?;
+        assert_eq!(cp.backend().claimed_interfaces(), [0]);
+        assert_eq!(cp.line_coding(), LineCoding::new());
+
+        let coding = LineCoding::create(115_200, StopBits::Two, Parity::Even, 7);
+        cp.set_line_coding(&coding)?;
+        assert_eq!(cp.line_coding(), coding);
+        assert!(cp.set_line_coding(&coding.with_data_bits(9)).is_err());
+        cp.set_control_lines(ControlLineState::create(true, true))?;
+        let status = cp.modem_status()?;
+        assert!(status.cts() && status.dsr() && !status.ri() && status.dcd());
+        cp.set_break(true)?;
+        cp.set_break(false)?;
+
+        let mut buf = [0u8; 2];
+        cp.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"OK");
+        cp.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"\r\n");
+        cp.write_all(b"AT\r")?;
+        assert_eq!(cp.backend().take_written(0x01), [b"AT\r".to_vec()]);
+
+        let dev = cp.close()?;
+        assert!(dev.claimed_interfaces().is_empty());
+        dev.verify()?;
+
+        // the driver is picked by vendor and product ID
+        let dev = open
+            .into_iter()
+            .fold(MockDevice::new(), |dev, ctrl| dev.with_control(ctrl))
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1);
+        let mut port = serial::open(dev)?;
+        port.set_timeout(100);
+        assert_eq!(port.timeout(), 100);
+
+        let mut other = DESCRIPTORS;
+        other[8] = 0x00;
+        assert!(serial::open(MockDevice::new().with_descriptors(other)).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,790 @@
+//! FTDI FT232, FT2232, FT4232 and FT-X driver.
+//!
+//! Every Bulk IN packet starts with two status bytes, the modem status and the line status,
+//! sent even without data at each latency timer expiry. They are stripped from the data, and
+//! kept as the last known status.
+//!
+//! Baud rates are set as a divisor of a 3 MHz base clock, or 12 MHz on the hi-speed (H) chips,
+//! with a fractional part in eighths. Multi-port chips expose one interface per port, addressed
+//! by the `wIndex` of every request.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+
+use super::{ModemStatus, SerialPort};
+use crate::class::cdc_acm::{ControlLineState, LineCoding};
+use crate::class::BulkReader;
+use crate::class::{class_request, REQUEST_TYPE_VENDOR_IN, REQUEST_TYPE_VENDOR_OUT};
+use crate::{Error, Result, UsbBackend};
+
+/// Vendor and product IDs of the FTDI chips.
+pub const FTDI_IDS: [(u16, u16); 5] = [
+    (0x0403, 0x6001),
+    (0x0403, 0x6010),
+    (0x0403, 0x6011),
+    (0x0403, 0x6014),
+    (0x0403, 0x6015),
+];
+
+pub const FTDI_SIO_RESET: u8 = 0x00;
+pub const FTDI_SIO_SET_MODEM_CTRL: u8 = 0x01;
+pub const FTDI_SIO_SET_FLOW_CTRL: u8 = 0x02;
+pub const FTDI_SIO_SET_BAUD_RATE: u8 = 0x03;
+pub const FTDI_SIO_SET_DATA: u8 = 0x04;
+pub const FTDI_SIO_GET_MODEM_STATUS: u8 = 0x05;
+pub const FTDI_SIO_SET_LATENCY_TIMER: u8 = 0x09;
+pub const FTDI_SIO_GET_LATENCY_TIMER: u8 = 0x0a;
+pub const FTDI_SIO_SET_BITMODE: u8 = 0x0b;
+pub const FTDI_SIO_READ_PINS: u8 = 0x0c;
+
+// wValue of SIO_RESET
+const FTDI_RESET_SIO: u16 = 0;
+const FTDI_RESET_PURGE_RX: u16 = 1;
+const FTDI_RESET_PURGE_TX: u16 = 2;
+
+// wValue bits of SIO_SET_MODEM_CTRL: line states, and the mask of lines to change
+const FTDI_MODEM_CTRL_DTR: u16 = 0x0001;
+const FTDI_MODEM_CTRL_RTS: u16 = 0x0002;
+const FTDI_MODEM_CTRL_MASK: u16 = 0x0300;
+
+// wValue bits of SIO_SET_DATA above the data bits
+const FTDI_DATA_PARITY_SHIFT: u16 = 8;
+const FTDI_DATA_STOP_BITS_SHIFT: u16 = 11;
+const FTDI_DATA_BREAK: u16 = 1 << 14;
+
+/// Line status bit of the second status byte: receive overrun.
+pub const FTDI_LINE_OVERRUN: u8 = 0x02;
+/// Line status bit of the second status byte: parity error.
+pub const FTDI_LINE_PARITY: u8 = 0x04;
+/// Line status bit of the second status byte: framing error.
+pub const FTDI_LINE_FRAMING: u8 = 0x08;
+/// Line status bit of the second status byte: break received.
+pub const FTDI_LINE_BREAK: u8 = 0x10;
+
+/// Length of the status header of Bulk IN packets.
+pub const FTDI_STATUS_LEN: usize = 2;
+
+// fractional divisor codes, by eighths
+const FTDI_FRAC_CODE: [u32; 8] = [0, 3, 2, 4, 1, 5, 6, 7];
+// divisor flag selecting the 12 MHz base clock of H chips
+const FTDI_DIVISOR_HI_SPEED: u32 = 0x2_0000;
+// lowest baud rate of the 12 MHz base clock, with the 14-bit divisor
+const FTDI_HI_SPEED_MIN_BAUD: u32 = 12_000_000 / 0x3fff;
+
+pub const MPSSE_SET_BITS_LOW: u8 = 0x80;
+pub const MPSSE_GET_BITS_LOW: u8 = 0x81;
+pub const MPSSE_SET_BITS_HIGH: u8 = 0x82;
+pub const MPSSE_GET_BITS_HIGH: u8 = 0x83;
+pub const MPSSE_LOOPBACK_START: u8 = 0x84;
+pub const MPSSE_LOOPBACK_END: u8 = 0x85;
+pub const MPSSE_TCK_DIVISOR: u8 = 0x86;
+pub const MPSSE_SEND_IMMEDIATE: u8 = 0x87;
+pub const MPSSE_DISABLE_DIV_5: u8 = 0x8a;
+/// Response of the MPSSE engine to an invalid command, followed by the command.
+pub const MPSSE_BAD_COMMAND: u8 = 0xfa;
+
+/// Represents the FTDI chip type, from the `bcdDevice` of the device descriptor.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FtdiChip {
+    Am = 0x0200,
+    #[default]
+    Bm = 0x0400,
+    Ft2232C = 0x0500,
+    Ft232R = 0x0600,
+    Ft2232H = 0x0700,
+    Ft4232H = 0x0800,
+    Ft232H = 0x0900,
+    FtX = 0x1000,
+}
+
+impl FtdiChip {
+    /// Creates a new [FtdiChip].
+    pub const fn new() -> Self {
+        Self::Bm
+    }
+
+    /// Creates a new [FtdiChip] from the `bcdDevice`, unknown versions are treated as BM.
+    pub const fn create(version: u16) -> Self {
+        match version {
+            0x0200 => Self::Am,
+            0x0500 => Self::Ft2232C,
+            0x0600 => Self::Ft232R,
+            0x0700 => Self::Ft2232H,
+            0x0800 => Self::Ft4232H,
+            0x0900 => Self::Ft232H,
+            0x1000 => Self::FtX,
+            _ => Self::Bm,
+        }
+    }
+
+    /// Gets whether the chip is a hi-speed (H) chip, with the 12 MHz baud rate clock.
+    pub const fn is_hi_speed(&self) -> bool {
+        matches!(self, Self::Ft2232H | Self::Ft4232H | Self::Ft232H)
+    }
+
+    /// Gets the number of ports of the chip.
+    pub const fn ports(&self) -> u8 {
+        match self {
+            Self::Ft2232C | Self::Ft2232H => 2,
+            Self::Ft4232H => 4,
+            _ => 1,
+        }
+    }
+
+    /// Gets the maximum baud rate of the chip.
+    pub const fn max_baud_rate(&self) -> u32 {
+        if self.is_hi_speed() {
+            12_000_000
+        } else {
+            3_000_000
+        }
+    }
+
+    /// Gets whether the chip has an MPSSE engine, on its first ports.
+    pub const fn has_mpsse(&self) -> bool {
+        matches!(
+            self,
+            Self::Ft2232C | Self::Ft2232H | Self::Ft4232H | Self::Ft232H
+        )
+    }
+}
+
+impl From<&FtdiChip> for &'static str {
+    fn from(val: &FtdiChip) -> Self {
+        match val {
+            FtdiChip::Am => "FT8U232AM",
+            FtdiChip::Bm => "FT232BM",
+            FtdiChip::Ft2232C => "FT2232C",
+            FtdiChip::Ft232R => "FT232R",
+            FtdiChip::Ft2232H => "FT2232H",
+            FtdiChip::Ft4232H => "FT4232H",
+            FtdiChip::Ft232H => "FT232H",
+            FtdiChip::FtX => "FT-X",
+        }
+    }
+}
+
+impl fmt::Display for FtdiChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the bit mode of an FTDI port, set with `SIO_SET_BITMODE`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BitMode {
+    /// UART mode.
+    #[default]
+    Reset = 0x00,
+    Bitbang = 0x01,
+    /// Multi-Protocol Synchronous Serial Engine, for SPI, I2C and JTAG.
+    Mpsse = 0x02,
+    SyncBitbang = 0x04,
+    Mcu = 0x08,
+    Opto = 0x10,
+    Cbus = 0x20,
+    SyncFifo = 0x40,
+}
+
+impl BitMode {
+    /// Creates a new [BitMode].
+    pub const fn new() -> Self {
+        Self::Reset
+    }
+
+    /// Creates a new [BitMode] from its value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0x00 => Some(Self::Reset),
+            0x01 => Some(Self::Bitbang),
+            0x02 => Some(Self::Mpsse),
+            0x04 => Some(Self::SyncBitbang),
+            0x08 => Some(Self::Mcu),
+            0x10 => Some(Self::Opto),
+            0x20 => Some(Self::Cbus),
+            0x40 => Some(Self::SyncFifo),
+            _ => None,
+        }
+    }
+
+    /// Gets the inner value of the [BitMode].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&BitMode> for &'static str {
+    fn from(val: &BitMode) -> Self {
+        match val {
+            BitMode::Reset => "reset",
+            BitMode::Bitbang => "bitbang",
+            BitMode::Mpsse => "MPSSE",
+            BitMode::SyncBitbang => "synchronous bitbang",
+            BitMode::Mcu => "MCU host bus",
+            BitMode::Opto => "fast opto-isolated serial",
+            BitMode::Cbus => "CBUS bitbang",
+            BitMode::SyncFifo => "synchronous FIFO",
+        }
+    }
+}
+
+impl fmt::Display for BitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Computes the encoded baud rate divisor of a chip, and the actual baud rate.
+///
+/// Fails if the actual rate is off by more than 3%, the tolerance of most UARTs.
+pub fn baud_rate_divisor(chip: FtdiChip, baud_rate: u32) -> Result<(u32, u32)> {
+    if baud_rate == 0 || baud_rate > chip.max_baud_rate() {
+        return Err(Error::InvalidArgument(format!(
+            "{} baud rate out of range: {baud_rate}",
+            <&str>::from(&chip)
+        )));
+    }
+
+    let (base, flags) = if chip.is_hi_speed() && baud_rate > FTDI_HI_SPEED_MIN_BAUD {
+        (12_000_000u32, FTDI_DIVISOR_HI_SPEED)
+    } else {
+        (3_000_000, 0)
+    };
+
+    // divisors 0 and 1 are special cases of 1 and 1.5
+    let (encoded, actual) = if baud_rate >= base {
+        (0, base)
+    } else if baud_rate >= base * 2 / 3 {
+        (1, base * 2 / 3)
+    } else if baud_rate >= base / 2 {
+        (2, base / 2)
+    } else {
+        // divisor in sixteenths, rounded to eighths
+        let sixteenths = base as u64 * 16 / baud_rate as u64;
+        let eighths = sixteenths.div_ceil(2).min(0x1_ffff) as u32;
+        let actual = (base as u64 * 16 / eighths as u64).div_ceil(2) as u32;
+        let encoded = (eighths >> 3) | (FTDI_FRAC_CODE[(eighths & 7) as usize] << 14);
+        (encoded, actual)
+    };
+
+    if actual.abs_diff(baud_rate) as u64 * 100 > baud_rate as u64 * 3 {
+        return Err(Error::InvalidArgument(format!(
+            "{} baud rate unsupported: {baud_rate}, closest: {actual}",
+            <&str>::from(&chip)
+        )));
+    }
+
+    Ok((encoded | flags, actual))
+}
+
+/// Strips the status bytes of every packet of a Bulk IN transfer.
+///
+/// Returns the data, and the status bytes of the last packet.
+pub fn strip_status(buf: &[u8], max_packet_size: usize) -> (Vec<u8>, Option<[u8; 2]>) {
+    let mut data = Vec::with_capacity(buf.len());
+    let mut status = None;
+
+    for packet in buf.chunks(max_packet_size.max(FTDI_STATUS_LEN + 1)) {
+        if let [modem, line, rest @ ..] = packet {
+            status = Some([*modem, *line]);
+            data.extend_from_slice(rest);
+        }
+    }
+
+    (data, status)
+}
+
+/// FTDI serial port over a [UsbBackend].
+pub struct Ftdi<B: UsbBackend> {
+    backend: B,
+    chip: FtdiChip,
+    iface: u8,
+    reader: BulkReader,
+    bulk_out: u8,
+    coding: LineCoding,
+    bitmode: BitMode,
+    status: [u8; 2],
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Ftdi<B> {
+    /// Opens the first port of an FTDI chip.
+    pub fn open(backend: B) -> Result<Self> {
+        Self::open_port(backend, 0)
+    }
+
+    /// Opens a port of an FTDI chip, by interface number.
+    ///
+    /// Claims the interface, detaching the `ftdi_sio` kernel driver, resets the port, and sets
+    /// the default [LineCoding].
+    pub fn open_port(backend: B, port: u8) -> Result<Self> {
+        let (device, desc) = super::claim_port(&backend, port)?;
+        let (reader, bulk_out) = super::find_bulk_endpoints(&desc)?;
+
+        let mut ftdi = Self {
+            chip: FtdiChip::create(device.device_version()),
+            iface: port,
+            reader,
+            bulk_out,
+            coding: LineCoding::new(),
+            bitmode: BitMode::Reset,
+            status: [0; 2],
+            timeout: crate::class::DEFAULT_TIMEOUT,
+            backend,
+        };
+        ftdi.request_out(FTDI_SIO_RESET, FTDI_RESET_SIO, ftdi.index())?;
+        ftdi.set_line_coding(&LineCoding::new())?;
+
+        Ok(ftdi)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the [FtdiChip] type.
+    pub const fn chip(&self) -> FtdiChip {
+        self.chip
+    }
+
+    /// Gets the interface number of the port.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets the Bulk IN endpoint.
+    pub const fn bulk_in(&self) -> u8 {
+        self.reader.endpoint()
+    }
+
+    /// Gets the Bulk OUT endpoint.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Gets the line status byte of the last Bulk IN packet, see the `FTDI_LINE_*` bits.
+    pub const fn line_status(&self) -> u8 {
+        self.status[1]
+    }
+
+    /// Gets the [ModemStatus] of the last Bulk IN packet, without a Control request.
+    pub const fn last_modem_status(&self) -> ModemStatus {
+        ModemStatus::from_msr(self.status[0])
+    }
+
+    /// Sets the latency timer, in milliseconds: how long the chip waits before sending a
+    /// partially filled packet.
+    pub fn set_latency_timer(&self, latency: u8) -> Result<()> {
+        if latency == 0 {
+            return Err(Error::InvalidArgument("FTDI latency timer 0".into()));
+        }
+        self.request_out(FTDI_SIO_SET_LATENCY_TIMER, latency as u16, self.index())
+    }
+
+    /// Gets the latency timer, in milliseconds.
+    pub fn latency_timer(&self) -> Result<u8> {
+        let mut buf = [0u8; 1];
+        self.request_in(FTDI_SIO_GET_LATENCY_TIMER, 0, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Discards the data in the receive and transmit FIFOs of the chip.
+    pub fn purge(&mut self, rx: bool, tx: bool) -> Result<()> {
+        if rx {
+            self.request_out(FTDI_SIO_RESET, FTDI_RESET_PURGE_RX, self.index())?;
+            self.reader.clear();
+        }
+        if tx {
+            self.request_out(FTDI_SIO_RESET, FTDI_RESET_PURGE_TX, self.index())?;
+        }
+        Ok(())
+    }
+
+    /// Gets the current [BitMode].
+    pub const fn bitmode(&self) -> BitMode {
+        self.bitmode
+    }
+
+    /// Sets the [BitMode], with the direction of the pins: `1` for outputs.
+    pub fn set_bitmode(&mut self, mode: BitMode, mask: u8) -> Result<()> {
+        if mode == BitMode::Mpsse && !self.chip.has_mpsse() {
+            return Err(Error::InvalidArgument(format!(
+                "{} has no MPSSE engine",
+                <&str>::from(&self.chip)
+            )));
+        }
+
+        let value = ((mode.inner() as u16) << 8) | mask as u16;
+        self.request_out(FTDI_SIO_SET_BITMODE, value, self.index())?;
+        self.bitmode = mode;
+        Ok(())
+    }
+
+    /// Reads the instantaneous state of the data pins.
+    pub fn read_pins(&self) -> Result<u8> {
+        let mut buf = [0u8; 1];
+        self.request_in(FTDI_SIO_READ_PINS, 0, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Sets the MPSSE clock frequency, in Hz, and returns the actual frequency.
+    ///
+    /// H chips run from a 60 MHz clock with the divide by 5 disabled, others from 12 MHz. The
+    /// clock is `base / ((1 + divisor) * 2)`.
+    pub fn set_mpsse_clock(&mut self, frequency: u32) -> Result<u32> {
+        if self.bitmode != BitMode::Mpsse || frequency == 0 {
+            return Err(Error::InvalidArgument(format!(
+                "MPSSE clock: {frequency}, mode: {}",
+                <&str>::from(&self.bitmode)
+            )));
+        }
+
+        let base: u32 = if self.chip.is_hi_speed() {
+            30_000_000
+        } else {
+            6_000_000
+        };
+        let divisor = base.div_ceil(frequency).clamp(1, 0x1_0000) - 1;
+        let [lo, hi] = (divisor as u16).to_le_bytes();
+
+        let mut cmd = Vec::with_capacity(4);
+        if self.chip.is_hi_speed() {
+            cmd.push(MPSSE_DISABLE_DIV_5);
+        }
+        cmd.extend_from_slice(&[MPSSE_TCK_DIVISOR, lo, hi]);
+        self.mpsse(&cmd, 0)?;
+
+        Ok(base / (divisor + 1))
+    }
+
+    /// Sends MPSSE commands, and reads `response_len` bytes of response.
+    ///
+    /// Responses are flushed with `SEND_IMMEDIATE`, and a bad command response fails.
+    pub fn mpsse(&mut self, cmd: &[u8], response_len: usize) -> Result<Vec<u8>> {
+        if self.bitmode != BitMode::Mpsse {
+            return Err(Error::InvalidArgument(format!(
+                "MPSSE command in {} mode",
+                <&str>::from(&self.bitmode)
+            )));
+        }
+
+        let mut buf = cmd.to_vec();
+        if response_len > 0 {
+            buf.push(MPSSE_SEND_IMMEDIATE);
+        }
+        self.write_all(&buf)?;
+
+        let mut response = vec![0u8; response_len];
+        self.read_exact(&mut response)?;
+        match response.as_slice() {
+            [MPSSE_BAD_COMMAND, bad, ..] => Err(Error::InvalidMessage(format!(
+                "MPSSE bad command: {bad:#04x}"
+            ))),
+            _ => Ok(response),
+        }
+    }
+
+    /// Releases the interface, and converts the [Ftdi] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+
+    // port number of multi-port chips, in the low byte of wIndex
+    fn index(&self) -> u16 {
+        if self.chip.ports() > 1 {
+            self.iface as u16 + 1
+        } else {
+            0
+        }
+    }
+
+    fn data_value(&self, coding: &LineCoding) -> Result<u16> {
+        if !matches!(coding.data_bits(), 7 | 8) {
+            return Err(Error::InvalidArgument(format!(
+                "FTDI data bits: {}",
+                coding.data_bits()
+            )));
+        }
+
+        Ok(coding.data_bits() as u16
+            | ((coding.parity().inner() as u16) << FTDI_DATA_PARITY_SHIFT)
+            | ((coding.stop_bits().inner() as u16) << FTDI_DATA_STOP_BITS_SHIFT))
+    }
+
+    fn request_out(&self, request: u8, value: u16, index: u16) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_OUT,
+            request,
+            value,
+            index,
+            &mut [],
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    fn request_in(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IN,
+            request,
+            value,
+            self.index(),
+            data,
+            self.timeout,
+        )?;
+        if len < data.len() {
+            return Err(Error::InvalidMessage(format!(
+                "FTDI request {request:#04x} response too short: {len}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> SerialPort for Ftdi<B> {
+    fn set_line_coding(&mut self, coding: &LineCoding) -> Result<()> {
+        let data = self.data_value(coding)?;
+        let (divisor, _) = baud_rate_divisor(self.chip, coding.baud_rate())?;
+
+        // multi-port and H chips move the divisor high bits above the port number
+        let index = if self.chip.ports() > 1 || self.chip.is_hi_speed() {
+            (((divisor >> 16) as u16) << 8) | self.index()
+        } else {
+            (divisor >> 16) as u16
+        };
+        self.request_out(FTDI_SIO_SET_BAUD_RATE, divisor as u16, index)?;
+        self.request_out(FTDI_SIO_SET_DATA, data, self.index())?;
+
+        self.coding = *coding;
+        Ok(())
+    }
+
+    fn line_coding(&self) -> LineCoding {
+        self.coding
+    }
+
+    fn set_control_lines(&mut self, state: ControlLineState) -> Result<()> {
+        let mut value = FTDI_MODEM_CTRL_MASK;
+        if state.dtr() {
+            value |= FTDI_MODEM_CTRL_DTR;
+        }
+        if state.rts() {
+            value |= FTDI_MODEM_CTRL_RTS;
+        }
+        self.request_out(FTDI_SIO_SET_MODEM_CTRL, value, self.index())
+    }
+
+    fn modem_status(&mut self) -> Result<ModemStatus> {
+        let mut buf = [0u8; 2];
+        self.request_in(FTDI_SIO_GET_MODEM_STATUS, 0, &mut buf)?;
+        self.status[0] = buf[0];
+        Ok(ModemStatus::from_msr(buf[0]))
+    }
+
+    fn set_break(&mut self, on: bool) -> Result<()> {
+        let mut value = self.data_value(&self.coding)?;
+        if on {
+            value |= FTDI_DATA_BREAK;
+        }
+        self.request_out(FTDI_SIO_SET_DATA, value, self.index())
+    }
+
+    fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+}
+
+impl<B: UsbBackend> Read for Ftdi<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // status-only packets arrive at every latency timer expiry, until data does
+        let deadline = Instant::now() + Duration::from_millis(self.timeout as u64);
+        while self.reader.is_empty() {
+            let packets = self
+                .reader
+                .read_packets(&self.backend, buf.len(), self.timeout)?;
+            let (data, status) = strip_status(&packets, self.reader.max_packet_size());
+            if let Some(status) = status {
+                self.status = status;
+            }
+            self.reader.fill(data);
+
+            if self.reader.is_empty() && Instant::now() >= deadline {
+                return Err(Error::from(Errno::ETIMEDOUT).into());
+            }
+        }
+
+        Ok(self.reader.drain(buf))
+    }
+}
+
+impl<B: UsbBackend> Write for Ftdi<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(crate::class::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Ftdi<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ftdi")
+            .field("chip", &self.chip)
+            .field("iface", &self.iface)
+            .field("bulk_in", &self.reader.endpoint())
+            .field("bulk_out", &self.bulk_out)
+            .field("coding", &self.coding)
+            .field("bitmode", &self.bitmode)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::Parity;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // FT2232H: two ports, each a vendor interface with a pair of Bulk endpoints
+    const DESCRIPTORS: [u8; 73] = [
+        0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x03, 0x04, 0x10, 0x60, 0x00, 0x07, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x37, 0x00, 0x02, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x02, 0xff, 0xff, 0xff, 0x02, //
+        0x07, 0x05, 0x81, 0x02, 0x00, 0x02, 0x00, //
+        0x07, 0x05, 0x02, 0x02, 0x00, 0x02, 0x00, //
+        0x09, 0x04, 0x01, 0x00, 0x02, 0xff, 0xff, 0xff, 0x02, //
+        0x07, 0x05, 0x83, 0x02, 0x00, 0x02, 0x00, //
+        0x07, 0x05, 0x04, 0x02, 0x00, 0x02, 0x00,
+    ];
+
+    fn vendor_out(request: u8, value: u16, index: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_OUT, request, value, index)
+    }
+
+    #[test]
+    fn test_ftdi() -> Result<()> {
+        // FT232R: 3 MHz base clock, with eighths of divisor
+        assert_eq!(baud_rate_divisor(FtdiChip::Ft232R, 9600)?, (0x4138, 9600));
+        assert_eq!(
+            baud_rate_divisor(FtdiChip::Ft232R, 115_200)?,
+            (0x1a, 115_385)
+        );
+        assert_eq!(
+            baud_rate_divisor(FtdiChip::Ft232R, 2_000_000)?,
+            (1, 2_000_000)
+        );
+        assert!(baud_rate_divisor(FtdiChip::Ft232R, 2_600_000).is_err());
+        // H chips: 12 MHz base clock, flagged in bit 17
+        assert_eq!(
+            baud_rate_divisor(FtdiChip::Ft2232H, 115_200)?,
+            (0x2_c068, 115_246)
+        );
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(1, "ftdi_sio")
+            .with_control(vendor_out(FTDI_SIO_RESET, 0, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_BAUD_RATE, 0x04e2, 0x0202))
+            .with_control(vendor_out(FTDI_SIO_SET_DATA, 0x0008, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_BAUD_RATE, 0xc068, 0x0202))
+            .with_control(vendor_out(FTDI_SIO_SET_DATA, 0x0008, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_BAUD_RATE, 0xc068, 0x0202))
+            .with_control(vendor_out(FTDI_SIO_SET_DATA, 0x0108, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_MODEM_CTRL, 0x0301, 2))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_VENDOR_IN, FTDI_SIO_GET_MODEM_STATUS, 0, 2)
+                    .with_response(MockResponse::Data(vec![0x31, 0x60])),
+            )
+            .with_control(vendor_out(FTDI_SIO_SET_DATA, 0x4108, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_LATENCY_TIMER, 2, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_BITMODE, 0x020b, 2))
+            .with_control(vendor_out(FTDI_SIO_SET_BITMODE, 0x0000, 2))
+            // status-only packet, then data split across packets
+            .with_data(0x83, [0x01, 0x60])
+            .with_data(
+                0x83,
+                [[0x11, 0x60].as_slice(), &[b'a'; 510], &[0x11, 0x62, b'b']].concat(),
+            );
+
+        let mut ftdi = Ftdi::open_port(dev, 1)?;
+        assert_eq!(ftdi.chip(), FtdiChip::Ft2232H);
+        assert_eq!(ftdi.backend().claimed_interfaces(), [1]);
+
+        ftdi.set_baud_rate(115_200)?;
+        assert_eq!(ftdi.line_coding().baud_rate(), 115_200);
+        assert!(ftdi
+            .set_line_coding(&LineCoding::new().with_data_bits(5))
+            .is_err());
+
+        ftdi.set_line_coding(&ftdi.line_coding().with_parity(Parity::Odd))?;
+        ftdi.set_control_lines(ControlLineState::create(true, false))?;
+        let status = ftdi.modem_status()?;
+        assert!(status.cts() && status.dsr() && !status.dcd());
+        ftdi.set_break(true)?;
+
+        let mut buf = [0u8; 1024];
+        assert_eq!(ftdi.read(&mut buf)?, 511);
+        assert_eq!(buf[510], b'b');
+        assert_eq!(ftdi.line_status() & FTDI_LINE_OVERRUN, FTDI_LINE_OVERRUN);
+        assert_eq!(
+            ftdi.read(&mut buf).map_err(|e| e.kind()),
+            Err(io::ErrorKind::TimedOut)
+        );
+
+        ftdi.set_latency_timer(2)?;
+        assert!(ftdi.mpsse(&[MPSSE_GET_BITS_LOW], 1).is_err());
+        ftdi.set_bitmode(BitMode::Mpsse, 0x0b)?;
+        assert_eq!(ftdi.set_mpsse_clock(1_000_000)?, 1_000_000);
+        ftdi.backend().push_data(0x83, [0x31, 0x60, 0xa5]);
+        assert_eq!(ftdi.mpsse(&[MPSSE_GET_BITS_LOW], 1)?, [0xa5]);
+        assert_eq!(
+            ftdi.backend().take_written(0x04),
+            [
+                vec![MPSSE_DISABLE_DIV_5, MPSSE_TCK_DIVISOR, 29, 0],
+                vec![MPSSE_GET_BITS_LOW, MPSSE_SEND_IMMEDIATE],
+            ]
+        );
+        ftdi.set_bitmode(BitMode::Reset, 0)?;
+
+        ftdi.write_all(b"hello")?;
+        assert_eq!(ftdi.backend().take_written(0x04), [b"hello".to_vec()]);
+
+        let dev = ftdi.close()?;
+        assert!(dev.claimed_interfaces().is_empty());
+        dev.verify()
+    }
+}
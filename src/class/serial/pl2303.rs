@@ -0,0 +1,536 @@
+//! Prolific PL2303 driver.
+//!
+//! Line coding and control lines use the CDC-ACM class requests on the vendor interface, after
+//! a vendor initialization sequence. The modem status arrives on the Interrupt endpoint, as a
+//! `SERIAL_STATE`-like notification.
+//!
+//! Standard baud rates are sent directly, others as a mantissa and exponent divisor of a
+//! 384 MHz reference, except on the HXN (G) series, which only supports the standard rates.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use nix::errno::Errno;
+
+use super::{ModemStatus, SerialPort};
+use crate::class::cdc_acm::{ControlLineState, LineCoding};
+use crate::class::BulkReader;
+use crate::class::{
+    class_request, find_endpoint, REQUEST_TYPE_CLASS_OUT, REQUEST_TYPE_VENDOR_IN,
+    REQUEST_TYPE_VENDOR_OUT,
+};
+use crate::descriptor::DeviceDescriptor;
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer};
+
+/// Vendor and product IDs of the PL2303 chips.
+pub const PL2303_IDS: [(u16, u16); 7] = [
+    (0x067b, 0x2303),
+    (0x067b, 0x23a3),
+    (0x067b, 0x23b3),
+    (0x067b, 0x23c3),
+    (0x067b, 0x23d3),
+    (0x067b, 0x23e3),
+    (0x067b, 0x23f3),
+];
+
+pub const PL2303_VENDOR_READ: u8 = 0x01;
+pub const PL2303_VENDOR_WRITE: u8 = 0x01;
+pub const PL2303_HXN_VENDOR_READ: u8 = 0x81;
+pub const PL2303_HXN_VENDOR_WRITE: u8 = 0x80;
+
+pub const PL2303_SET_LINE_REQUEST: u8 = 0x20;
+pub const PL2303_SET_CONTROL_REQUEST: u8 = 0x22;
+pub const PL2303_BREAK_REQUEST: u8 = 0x23;
+
+const PL2303_BREAK_ON: u16 = 0xffff;
+const PL2303_BREAK_OFF: u16 = 0x0000;
+
+// HXN register resetting the upstream and downstream pipes
+const PL2303_HXN_RESET_REG: u16 = 0x07;
+const PL2303_HXN_RESET_PIPES: u16 = 0x03;
+
+// product ID of the original PL2303, the G series have their own
+const PL2303_PRODUCT_ID: u16 = 0x2303;
+// bcdDevice of HXN chips reusing the original product ID
+const PL2303_HXN_VERSIONS: [u16; 12] = [
+    0x0100, 0x0105, 0x0305, 0x0400, 0x0405, 0x0505, 0x0600, 0x0605, 0x0700, 0x0705, 0x0905, 0x1005,
+];
+
+const PL2303_BAUD_RATES: [u32; 25] = [
+    75, 150, 300, 600, 1200, 1800, 2400, 3600, 4800, 7200, 9600, 14400, 19200, 28800, 38400, 57600,
+    115200, 230400, 460800, 614400, 921600, 1228800, 2457600, 3000000, 6000000,
+];
+// reference of divisor encoded baud rates
+const PL2303_DIVISOR_BASE: u32 = 12_000_000 * 32;
+
+// offset of the UART state in Interrupt notifications, and its bits
+const PL2303_UART_STATE_INDEX: usize = 8;
+const PL2303_UART_DCD: u8 = 0x01;
+const PL2303_UART_DSR: u8 = 0x02;
+const PL2303_UART_RING: u8 = 0x08;
+const PL2303_UART_CTS: u8 = 0x80;
+
+// timeout of Interrupt endpoint polls, notifications only arrive on changes
+const PL2303_STATUS_POLL_TIMEOUT: u32 = 10;
+
+/// Represents the PL2303 chip type.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pl2303Type {
+    /// Original PL2303H.
+    H,
+    /// PL2303HX, and the HX compatible TA and TB revisions.
+    #[default]
+    Hx,
+    /// PL2303HXN and the G series.
+    Hxn,
+}
+
+impl Pl2303Type {
+    /// Creates a new [Pl2303Type].
+    pub const fn new() -> Self {
+        Self::Hx
+    }
+
+    /// Detects the [Pl2303Type] from the device descriptor.
+    pub fn detect(device: &DeviceDescriptor) -> Self {
+        if device.product_id() != PL2303_PRODUCT_ID {
+            Self::Hxn
+        } else if device.class() == 0x02 || device.max_packet_size0() != 0x40 {
+            Self::H
+        } else if device.usb_version() == 0x0200
+            && PL2303_HXN_VERSIONS.contains(&device.device_version())
+        {
+            Self::Hxn
+        } else {
+            Self::Hx
+        }
+    }
+
+    /// Gets the maximum baud rate of the chip.
+    pub const fn max_baud_rate(&self) -> u32 {
+        match self {
+            Self::H => 1_228_800,
+            Self::Hx => 6_000_000,
+            Self::Hxn => 12_000_000,
+        }
+    }
+}
+
+impl From<&Pl2303Type> for &'static str {
+    fn from(val: &Pl2303Type) -> Self {
+        match val {
+            Pl2303Type::H => "H",
+            Pl2303Type::Hx => "HX",
+            Pl2303Type::Hxn => "HXN",
+        }
+    }
+}
+
+impl fmt::Display for Pl2303Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Encodes the baud rate field of the line coding, and returns it with the actual baud rate.
+///
+/// Rates are capped to the maximum of the chip.
+pub fn encode_baud_rate(chip: Pl2303Type, baud_rate: u32) -> Result<([u8; 4], u32)> {
+    if baud_rate == 0 {
+        return Err(Error::InvalidArgument("PL2303 baud rate 0".into()));
+    }
+    let baud_rate = baud_rate.min(chip.max_baud_rate());
+
+    let closest = PL2303_BAUD_RATES
+        .into_iter()
+        .min_by_key(|r| r.abs_diff(baud_rate))
+        .unwrap_or(baud_rate);
+    if closest == baud_rate || chip == Pl2303Type::Hxn {
+        return Ok((closest.to_le_bytes(), closest));
+    }
+
+    // mantissa and base 4 exponent: baud = 384 MHz / mantissa / 4^exponent
+    let mut mantissa = (PL2303_DIVISOR_BASE / baud_rate).max(1);
+    let mut exponent = 0;
+    while mantissa >= 512 {
+        if exponent < 7 {
+            mantissa >>= 2;
+            exponent += 1;
+        } else {
+            mantissa = 511;
+            break;
+        }
+    }
+
+    let encoded = [
+        mantissa as u8,
+        ((exponent << 1) | (mantissa >> 8)) as u8,
+        0x00,
+        0x80,
+    ];
+    Ok((encoded, (PL2303_DIVISOR_BASE / mantissa) >> (exponent << 1)))
+}
+
+/// Prolific PL2303 serial port over a [UsbBackend].
+pub struct Pl2303<B: UsbBackend> {
+    backend: B,
+    chip: Pl2303Type,
+    reader: BulkReader,
+    bulk_out: u8,
+    notify_ep: Option<u8>,
+    coding: LineCoding,
+    status: ModemStatus,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Pl2303<B> {
+    /// Opens a PL2303 chip.
+    ///
+    /// Claims the interface, detaching the `pl2303` kernel driver, runs the vendor
+    /// initialization sequence, and sets the default [LineCoding] with the control lines
+    /// deasserted.
+    pub fn open(backend: B) -> Result<Self> {
+        let (device, desc) = super::claim_port(&backend, 0)?;
+        let (reader, bulk_out) = super::find_bulk_endpoints(&desc)?;
+
+        let mut pl = Self {
+            chip: Pl2303Type::detect(&device),
+            reader,
+            bulk_out,
+            notify_ep: find_endpoint(&desc, TransferType::Interrupt, true).map(|e| e.address()),
+            coding: LineCoding::new(),
+            status: ModemStatus::new(),
+            timeout: crate::class::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        if pl.chip == Pl2303Type::Hxn {
+            pl.vendor_write(PL2303_HXN_RESET_REG, PL2303_HXN_RESET_PIPES)?;
+        } else {
+            pl.initialize()?;
+            // reset the upstream and downstream pipes
+            pl.vendor_write(8, 0)?;
+            pl.vendor_write(9, 0)?;
+        }
+        pl.set_line_coding(&LineCoding::new())?;
+        pl.set_control_lines(ControlLineState::new())?;
+
+        Ok(pl)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the [Pl2303Type].
+    pub const fn chip(&self) -> Pl2303Type {
+        self.chip
+    }
+
+    /// Gets the Bulk IN endpoint.
+    pub const fn bulk_in(&self) -> u8 {
+        self.reader.endpoint()
+    }
+
+    /// Gets the Bulk OUT endpoint.
+    pub const fn bulk_out(&self) -> u8 {
+        self.bulk_out
+    }
+
+    /// Gets the Interrupt IN status endpoint, if any.
+    pub const fn notify_endpoint(&self) -> Option<u8> {
+        self.notify_ep
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reads a vendor register.
+    pub fn vendor_read(&self, value: u16) -> Result<u8> {
+        let request = match self.chip {
+            Pl2303Type::Hxn => PL2303_HXN_VENDOR_READ,
+            _ => PL2303_VENDOR_READ,
+        };
+        let mut buf = [0u8; 1];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_IN,
+            request,
+            value,
+            0,
+            &mut buf,
+            self.timeout,
+        )?;
+        if len == 0 {
+            return Err(Error::InvalidMessage(format!(
+                "empty PL2303 vendor register {value:#06x}"
+            )));
+        }
+        Ok(buf[0])
+    }
+
+    /// Writes a vendor register.
+    pub fn vendor_write(&self, value: u16, index: u16) -> Result<()> {
+        let request = match self.chip {
+            Pl2303Type::Hxn => PL2303_HXN_VENDOR_WRITE,
+            _ => PL2303_VENDOR_WRITE,
+        };
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_VENDOR_OUT,
+            request,
+            value,
+            index,
+            &mut [],
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Releases the interface, and converts the [Pl2303] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(0)?;
+        Ok(self.backend)
+    }
+
+    // magic sequence of the vendor driver, undocumented
+    fn initialize(&self) -> Result<()> {
+        self.vendor_read(0x8484)?;
+        self.vendor_write(0x0404, 0)?;
+        self.vendor_read(0x8484)?;
+        self.vendor_read(0x8383)?;
+        self.vendor_read(0x8484)?;
+        self.vendor_write(0x0404, 1)?;
+        self.vendor_read(0x8484)?;
+        self.vendor_read(0x8383)?;
+        self.vendor_write(0, 1)?;
+        self.vendor_write(1, 0)?;
+        match self.chip {
+            Pl2303Type::H => self.vendor_write(2, 0x24),
+            _ => self.vendor_write(2, 0x44),
+        }
+    }
+
+    fn request_out(&self, request: u8, value: u16, data: &mut [u8]) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            request,
+            value,
+            0,
+            data,
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+}
+
+impl<B: UsbBackend> SerialPort for Pl2303<B> {
+    fn set_line_coding(&mut self, coding: &LineCoding) -> Result<()> {
+        if !(5..=8).contains(&coding.data_bits()) {
+            return Err(Error::InvalidArgument(format!(
+                "PL2303 data bits: {}",
+                coding.data_bits()
+            )));
+        }
+
+        let (encoded, _) = encode_baud_rate(self.chip, coding.baud_rate())?;
+        let mut buf = coding.to_bytes();
+        buf[..4].copy_from_slice(&encoded);
+        self.request_out(PL2303_SET_LINE_REQUEST, 0, &mut buf)?;
+
+        self.coding = *coding;
+        Ok(())
+    }
+
+    fn line_coding(&self) -> LineCoding {
+        self.coding
+    }
+
+    fn set_control_lines(&mut self, state: ControlLineState) -> Result<()> {
+        self.request_out(PL2303_SET_CONTROL_REQUEST, state.bits(), &mut [])
+    }
+
+    /// Gets the [ModemStatus], from the notifications received since the last call.
+    fn modem_status(&mut self) -> Result<ModemStatus> {
+        let Some(ep) = self.notify_ep else {
+            return Ok(self.status);
+        };
+
+        loop {
+            let mut int =
+                UsbfsBulkTransfer::create(ep as u32, PL2303_STATUS_POLL_TIMEOUT, [0u8; 16]);
+            match self.backend.bulk(&mut int) {
+                Ok(len) if len > PL2303_UART_STATE_INDEX => {
+                    let state = int.data()[PL2303_UART_STATE_INDEX];
+                    self.status = ModemStatus::create(
+                        state & PL2303_UART_CTS != 0,
+                        state & PL2303_UART_DSR != 0,
+                        state & PL2303_UART_RING != 0,
+                        state & PL2303_UART_DCD != 0,
+                    );
+                }
+                Ok(_) => (),
+                Err(err) if err.errno() == Some(Errno::ETIMEDOUT as i32) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(self.status)
+    }
+
+    fn set_break(&mut self, on: bool) -> Result<()> {
+        let value = if on {
+            PL2303_BREAK_ON
+        } else {
+            PL2303_BREAK_OFF
+        };
+        self.request_out(PL2303_BREAK_REQUEST, value, &mut [])
+    }
+
+    fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+}
+
+impl<B: UsbBackend> Read for Pl2303<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.reader.read(&self.backend, buf, self.timeout)?)
+    }
+}
+
+impl<B: UsbBackend> Write for Pl2303<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(crate::class::bulk_write(
+            &self.backend,
+            self.bulk_out,
+            buf,
+            self.timeout,
+        )?)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Pl2303<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pl2303")
+            .field("chip", &self.chip)
+            .field("bulk_in", &self.reader.endpoint())
+            .field("bulk_out", &self.bulk_out)
+            .field("notify_ep", &self.notify_ep)
+            .field("coding", &self.coding)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::{Parity, StopBits};
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // PL2303HX: one vendor interface with an Interrupt endpoint and a pair of Bulk endpoints
+    const DESCRIPTORS: [u8; 57] = [
+        0x12, 0x01, 0x10, 0x01, 0x00, 0x00, 0x00, 0x40, 0x7b, 0x06, 0x03, 0x23, 0x00, 0x03, 0x01,
+        0x02, 0x00, 0x01, //
+        0x09, 0x02, 0x27, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x03, 0xff, 0x00, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x03, 0x0a, 0x00, 0x01, //
+        0x07, 0x05, 0x02, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x83, 0x02, 0x40, 0x00, 0x00,
+    ];
+
+    fn vendor_read(value: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_IN, PL2303_VENDOR_READ, value, 0)
+            .with_response(MockResponse::Data(vec![0x00]))
+    }
+
+    fn vendor_write(value: u16, index: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_VENDOR_OUT, PL2303_VENDOR_WRITE, value, index)
+    }
+
+    fn class_out(request: u8, value: u16) -> MockControl {
+        MockControl::create(REQUEST_TYPE_CLASS_OUT, request, value, 0)
+    }
+
+    #[test]
+    fn test_pl2303() -> Result<()> {
+        let (rate, actual) = encode_baud_rate(Pl2303Type::Hx, 500_000)?;
+        assert_eq!((rate, actual), ([0xc0, 0x02, 0x00, 0x80], 500_000));
+        assert_eq!(
+            encode_baud_rate(Pl2303Type::Hx, 115_200)?,
+            (115_200u32.to_le_bytes(), 115_200)
+        );
+        assert_eq!(encode_baud_rate(Pl2303Type::Hxn, 500_000)?.1, 460_800);
+        assert_eq!(encode_baud_rate(Pl2303Type::H, 2_000_000)?.1, 1_228_800);
+
+        let coding = LineCoding::create(500_000, StopBits::One, Parity::Odd, 8);
+        let mut line = coding.to_bytes();
+        line[..4].copy_from_slice(&rate);
+
+        let dev = [
+            vendor_read(0x8484),
+            vendor_write(0x0404, 0),
+            vendor_read(0x8484),
+            vendor_read(0x8383),
+            vendor_read(0x8484),
+            vendor_write(0x0404, 1),
+            vendor_read(0x8484),
+            vendor_read(0x8383),
+            vendor_write(0, 1),
+            vendor_write(1, 0),
+            vendor_write(2, 0x44),
+            vendor_write(8, 0),
+            vendor_write(9, 0),
+            class_out(PL2303_SET_LINE_REQUEST, 0).with_data(LineCoding::new().to_bytes()),
+            class_out(PL2303_SET_CONTROL_REQUEST, 0),
+            class_out(PL2303_SET_LINE_REQUEST, 0).with_data(line),
+            class_out(PL2303_SET_CONTROL_REQUEST, 0x3),
+            class_out(PL2303_BREAK_REQUEST, 0xffff),
+        ]
+        .into_iter()
+        .fold(MockDevice::new(), |dev, ctrl| dev.with_control(ctrl))
+        .with_descriptors(DESCRIPTORS)
+        .with_configuration(1)
+        .with_driver(0, "pl2303")
+        .with_data(0x81, [0xa1, 0x20, 0, 0, 0, 0, 2, 0, 0x83, 0])
+        .with_data(0x83, *b"hello");
+
+        let mut pl = Pl2303::open(dev)?;
+        assert_eq!(pl.chip(), Pl2303Type::Hx);
+        assert_eq!(pl.notify_endpoint(), Some(0x81));
+
+        pl.set_line_coding(&coding)?;
+        assert!(pl.set_line_coding(&coding.with_data_bits(16)).is_err());
+        pl.set_control_lines(ControlLineState::create(true, true))?;
+        pl.set_break(true)?;
+
+        let status = pl.modem_status()?;
+        assert!(status.cts() && status.dsr() && status.dcd() && !status.ri());
+        // no new notification, the last status is kept
+        assert_eq!(pl.modem_status()?, status);
+
+        let mut buf = [0u8; 5];
+        pl.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"hello");
+        pl.write_all(b"bye")?;
+        assert_eq!(pl.backend().take_written(0x02), [b"bye".to_vec()]);
+
+        let dev = pl.close()?;
+        assert!(dev.claimed_interfaces().is_empty());
+        dev.verify()
+    }
+}
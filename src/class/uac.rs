@@ -0,0 +1,906 @@
+//! USB Audio Class (UAC1 and UAC2) driver.
+//!
+//! An audio function pairs an AudioControl interface, exposing the terminals, feature units and
+//! clocks, with AudioStreaming interfaces, each alternate setting streaming one sample format.
+//! Playback and capture run on Isochronous URBs, with playback packet sizes following the
+//! feedback endpoint of asynchronous devices.
+//!
+//! The [descriptors] module parses the class-specific descriptors, and the [feedback] module
+//! implements the rate matching.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::{Error, Result, Urb, UrbId, UsbBackend, UsbfsSetInterface, UsbfsSpeed};
+
+pub mod descriptors;
+pub mod feedback;
+
+pub use descriptors::{
+    AudioControl, AudioFormat, AudioTerminal, ClockEntity, FeatureUnit, SampleRates, SyncType,
+    UacVersion,
+};
+pub use feedback::RateMatcher;
+
+pub const UAC_CLASS: u8 = 0x01;
+pub const UAC_SUBCLASS_AUDIOCONTROL: u8 = 0x01;
+pub const UAC_SUBCLASS_AUDIOSTREAMING: u8 = 0x02;
+
+pub const UAC_SET_CUR: u8 = 0x01;
+pub const UAC_GET_CUR: u8 = 0x81;
+pub const UAC_GET_MIN: u8 = 0x82;
+pub const UAC_GET_MAX: u8 = 0x83;
+pub const UAC_GET_RES: u8 = 0x84;
+pub const UAC2_CUR: u8 = 0x01;
+pub const UAC2_RANGE: u8 = 0x02;
+
+pub const FU_MUTE_CONTROL: u8 = 0x01;
+pub const FU_VOLUME_CONTROL: u8 = 0x02;
+/// Sampling frequency control of UAC1 endpoints and UAC2 clock sources.
+pub const SAMPLING_FREQ_CONTROL: u8 = 0x01;
+pub const CLOCK_SELECTOR_CONTROL: u8 = 0x01;
+
+const REQUEST_TYPE_CLASS_ENDPOINT_IN: u8 = 0xa2;
+const REQUEST_TYPE_CLASS_ENDPOINT_OUT: u8 = 0x22;
+
+// URBs kept in flight per stream, and Isochronous packets per URB
+const ISO_URBS: usize = 4;
+const ISO_PACKETS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UrbKind {
+    Playback,
+    Capture,
+    Feedback,
+}
+
+/// Playback stream, with the frames not yet submitted.
+#[derive(Debug)]
+struct Playback {
+    format: AudioFormat,
+    matcher: RateMatcher,
+    pending: VecDeque<u8>,
+}
+
+/// USB audio interface over a [UsbBackend].
+pub struct Uac<B: UsbBackend> {
+    backend: B,
+    ac_iface: u8,
+    control: AudioControl,
+    as_ifaces: Vec<u8>,
+    formats: Vec<AudioFormat>,
+    high_speed: bool,
+    playback: Option<Playback>,
+    capture: Option<AudioFormat>,
+    urbs: Vec<(UrbId, UrbKind)>,
+    captured: VecDeque<Vec<u8>>,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Uac<B> {
+    /// Opens the first audio function of the active configuration.
+    ///
+    /// Claims the AudioControl and AudioStreaming interfaces, detaching the `snd-usb-audio`
+    /// kernel driver, and selects the zero-bandwidth setting of the streaming interfaces.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let ac = config
+            .interfaces()
+            .iter()
+            .find(|i| i.class() == UAC_CLASS && i.subclass() == UAC_SUBCLASS_AUDIOCONTROL)
+            .ok_or(Error::NotFound("UAC AudioControl interface".into()))?;
+        let version = UacVersion::create(ac.protocol()).ok_or(Error::InvalidDescriptor(
+            format!("unsupported UAC protocol: {:#04x}", ac.protocol()),
+        ))?;
+        let control = AudioControl::parse(ac.extra(), version)?;
+
+        let mut as_ifaces: Vec<u8> = if control.streaming_interfaces().is_empty() {
+            config
+                .interfaces()
+                .iter()
+                .filter(|i| i.class() == UAC_CLASS && i.subclass() == UAC_SUBCLASS_AUDIOSTREAMING)
+                .map(|i| i.number())
+                .collect()
+        } else {
+            control.streaming_interfaces().to_vec()
+        };
+        as_ifaces.dedup();
+
+        let mut formats = Vec::new();
+        for iface in as_ifaces.iter() {
+            for alt in config.alt_settings(*iface) {
+                formats.extend(AudioFormat::parse(alt, version)?);
+            }
+        }
+        if formats.is_empty() {
+            return Err(Error::NotFound("UAC Type I format".into()));
+        }
+
+        let high_speed = matches!(
+            backend.get_speed()?,
+            UsbfsSpeed::High | UsbfsSpeed::Super | UsbfsSpeed::SuperPlus
+        );
+
+        let uac = Self {
+            ac_iface: ac.number(),
+            control,
+            as_ifaces,
+            formats,
+            high_speed,
+            playback: None,
+            capture: None,
+            urbs: Vec::new(),
+            captured: VecDeque::new(),
+            timeout: super::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        super::claim_detaching(&uac.backend, uac.ac_iface)?;
+        for iface in uac.as_ifaces.iter() {
+            super::claim_detaching(&uac.backend, *iface)?;
+            uac.set_alt_setting(*iface, 0)?;
+        }
+
+        Ok(uac)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the [UacVersion] of the function.
+    pub const fn version(&self) -> UacVersion {
+        self.control.version()
+    }
+
+    /// Gets the AudioControl interface number.
+    pub const fn control_interface(&self) -> u8 {
+        self.ac_iface
+    }
+
+    /// Gets the [AudioControl] descriptors: terminals, feature units and clocks.
+    pub const fn audio_control(&self) -> &AudioControl {
+        &self.control
+    }
+
+    /// Gets the list of [AudioFormat]s of all AudioStreaming interfaces.
+    pub fn formats(&self) -> &[AudioFormat] {
+        self.formats.as_ref()
+    }
+
+    /// Finds the playback or capture [AudioFormat] with the provided channels and resolution.
+    pub fn find_format(
+        &self,
+        capture: bool,
+        channels: u8,
+        bit_resolution: u8,
+    ) -> Option<&AudioFormat> {
+        self.formats.iter().find(|f| {
+            f.is_capture() == capture
+                && f.channels() == channels
+                && f.bit_resolution() == bit_resolution
+        })
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Gets the supported [SampleRates] of a format.
+    ///
+    /// UAC1 rates come from the format descriptor, UAC2 rates from a `RANGE` request to the
+    /// clock source of the format.
+    pub fn sample_rates(&self, format: &AudioFormat) -> Result<SampleRates> {
+        match self.version() {
+            UacVersion::Uac1 => Ok(format.sample_rates().clone()),
+            UacVersion::Uac2 => {
+                let index = self.entity_index(self.clock_source(format)?);
+                let value = (SAMPLING_FREQ_CONTROL as u16) << 8;
+                // the number of subranges first, then all of them
+                let buf = self.request_in(REQUEST_TYPE_CLASS_IN, UAC2_RANGE, value, index, 2)?;
+                let count = match buf[..] {
+                    [lo, hi] => u16::from_le_bytes([lo, hi]) as usize,
+                    _ => return SampleRates::parse_range(&buf),
+                };
+                let buf = self.request_in(
+                    REQUEST_TYPE_CLASS_IN,
+                    UAC2_RANGE,
+                    value,
+                    index,
+                    2 + count * 12,
+                )?;
+                SampleRates::parse_range(&buf)
+            }
+        }
+    }
+
+    /// Gets the current sample rate of a format, in Hz.
+    pub fn sample_rate(&self, format: &AudioFormat) -> Result<u32> {
+        let value = (SAMPLING_FREQ_CONTROL as u16) << 8;
+        let buf = match self.version() {
+            UacVersion::Uac1 => self.request_in(
+                REQUEST_TYPE_CLASS_ENDPOINT_IN,
+                UAC_GET_CUR,
+                value,
+                format.endpoint() as u16,
+                3,
+            )?,
+            UacVersion::Uac2 => self.request_in(
+                REQUEST_TYPE_CLASS_IN,
+                UAC2_CUR,
+                value,
+                self.entity_index(self.clock_source(format)?),
+                4,
+            )?,
+        };
+
+        match buf[..] {
+            [a, b, c] => Ok(u32::from_le_bytes([a, b, c, 0])),
+            [a, b, c, d] => Ok(u32::from_le_bytes([a, b, c, d])),
+            _ => Err(Error::InvalidMessage(format!(
+                "UAC sample rate too short: {}",
+                buf.len()
+            ))),
+        }
+    }
+
+    /// Sets the sample rate of a format, in Hz.
+    ///
+    /// Formats with a fixed rate, without a sampling frequency control or programmable clock,
+    /// only accept their rate.
+    pub fn set_sample_rate(&self, format: &AudioFormat, rate: u32) -> Result<()> {
+        let value = (SAMPLING_FREQ_CONTROL as u16) << 8;
+        match self.version() {
+            UacVersion::Uac1 if format.sample_rate_control() => self.request_out(
+                REQUEST_TYPE_CLASS_ENDPOINT_OUT,
+                UAC_SET_CUR,
+                value,
+                format.endpoint() as u16,
+                &rate.to_le_bytes()[..3],
+            ),
+            UacVersion::Uac1 if format.sample_rates().supports(rate) => Ok(()),
+            UacVersion::Uac1 => Err(Error::InvalidArgument(format!("UAC sample rate: {rate}"))),
+            UacVersion::Uac2 => {
+                let clock = self.clock_source(format)?;
+                if self
+                    .control
+                    .clock(clock)
+                    .is_some_and(|c| c.is_programmable())
+                {
+                    self.request_out(
+                        REQUEST_TYPE_CLASS_OUT,
+                        UAC2_CUR,
+                        value,
+                        self.entity_index(clock),
+                        &rate.to_le_bytes(),
+                    )
+                } else if self.sample_rate(format)? == rate {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidArgument(format!("UAC sample rate: {rate}")))
+                }
+            }
+        }
+    }
+
+    /// Gets the volume of a feature unit channel, in 1/256 dB, channel `0` being the master.
+    pub fn volume(&self, unit: u8, channel: u8) -> Result<i16> {
+        let buf = self.feature_in(self.cur_request(), FU_VOLUME_CONTROL, unit, channel, 2)?;
+        Self::read_i16(&buf, 0)
+    }
+
+    /// Sets the volume of a feature unit channel, in 1/256 dB.
+    pub fn set_volume(&self, unit: u8, channel: u8, volume: i16) -> Result<()> {
+        self.feature_out(FU_VOLUME_CONTROL, unit, channel, &volume.to_le_bytes())
+    }
+
+    /// Gets the `(min, max, resolution)` volume range of a feature unit channel, in 1/256 dB.
+    pub fn volume_range(&self, unit: u8, channel: u8) -> Result<(i16, i16, i16)> {
+        match self.version() {
+            UacVersion::Uac1 => {
+                let mut range = [0i16; 3];
+                for (val, request) in range
+                    .iter_mut()
+                    .zip([UAC_GET_MIN, UAC_GET_MAX, UAC_GET_RES])
+                {
+                    let buf = self.feature_in(request, FU_VOLUME_CONTROL, unit, channel, 2)?;
+                    *val = Self::read_i16(&buf, 0)?;
+                }
+                Ok((range[0], range[1], range[2]))
+            }
+            // the first subrange
+            UacVersion::Uac2 => {
+                let buf = self.feature_in(UAC2_RANGE, FU_VOLUME_CONTROL, unit, channel, 8)?;
+                Ok((
+                    Self::read_i16(&buf, 2)?,
+                    Self::read_i16(&buf, 4)?,
+                    Self::read_i16(&buf, 6)?,
+                ))
+            }
+        }
+    }
+
+    /// Gets whether a feature unit channel is muted.
+    pub fn mute(&self, unit: u8, channel: u8) -> Result<bool> {
+        self.feature_in(self.cur_request(), FU_MUTE_CONTROL, unit, channel, 1)?
+            .first()
+            .map(|m| *m != 0)
+            .ok_or(Error::InvalidMessage("empty UAC mute control".into()))
+    }
+
+    /// Mutes or unmutes a feature unit channel.
+    pub fn set_mute(&self, unit: u8, channel: u8, mute: bool) -> Result<()> {
+        self.feature_out(FU_MUTE_CONTROL, unit, channel, &[mute as u8])
+    }
+
+    /// Starts playback of a format at a sample rate.
+    ///
+    /// Frames are queued with [write](Self::write), and their packet sizes follow the
+    /// feedback endpoint of the format, if any.
+    pub fn start_playback(&mut self, format: &AudioFormat, rate: u32) -> Result<()> {
+        if format.is_capture() {
+            return Err(Error::InvalidArgument("UAC capture format".into()));
+        }
+        if self.playback.is_some() {
+            self.stop_playback()?;
+        }
+
+        self.set_alt_setting(format.interface(), format.alt_setting())?;
+        self.set_sample_rate(format, rate)?;
+
+        self.playback = Some(Playback {
+            format: format.clone(),
+            matcher: RateMatcher::create(rate, self.high_speed, format.interval()),
+            pending: VecDeque::new(),
+        });
+        if let Some(ep) = format.feedback_endpoint() {
+            self.submit_feedback_urb(ep, format.feedback_packet_size())?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets the [RateMatcher] of the playback stream, if started.
+    pub fn playback_rate(&self) -> Option<&RateMatcher> {
+        self.playback.as_ref().map(|p| &p.matcher)
+    }
+
+    /// Queues interleaved frames for playback.
+    ///
+    /// Submits URBs as long as enough frames are queued, blocking while the URBs in flight
+    /// complete. Frames short of a full URB stay queued until the next call, or
+    /// [drain](Self::drain).
+    pub fn write(&mut self, frames: &[u8]) -> Result<()> {
+        let playback = self
+            .playback
+            .as_mut()
+            .ok_or(Error::InvalidArgument("UAC playback is not started".into()))?;
+        playback.pending.extend(frames);
+        self.pump_playback(false)
+    }
+
+    /// Submits the queued frames, and waits for the playback URBs to complete.
+    pub fn drain(&mut self) -> Result<()> {
+        self.pump_playback(true)?;
+        while self.in_flight(UrbKind::Playback) != 0 {
+            self.reap()?;
+        }
+        Ok(())
+    }
+
+    /// Stops playback, dropping the queued frames, and selects the zero-bandwidth setting.
+    pub fn stop_playback(&mut self) -> Result<()> {
+        let Some(playback) = self.playback.take() else {
+            return Ok(());
+        };
+        self.cancel(&[UrbKind::Playback, UrbKind::Feedback])?;
+        self.set_alt_setting(playback.format.interface(), 0)
+    }
+
+    /// Starts capture of a format at a sample rate.
+    pub fn start_capture(&mut self, format: &AudioFormat, rate: u32) -> Result<()> {
+        if !format.is_capture() {
+            return Err(Error::InvalidArgument("UAC playback format".into()));
+        }
+        if self.capture.is_some() {
+            self.stop_capture()?;
+        }
+
+        self.set_alt_setting(format.interface(), format.alt_setting())?;
+        self.set_sample_rate(format, rate)?;
+
+        self.capture = Some(format.clone());
+        for _ in 0..ISO_URBS {
+            self.submit_capture_urb()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the interleaved frames of the next capture URB.
+    ///
+    /// Packets lost by the device or the host are skipped, and the frames may be empty.
+    pub fn read(&mut self) -> Result<Vec<u8>> {
+        if self.capture.is_none() {
+            return Err(Error::InvalidArgument("UAC capture is not started".into()));
+        }
+        loop {
+            if let Some(frames) = self.captured.pop_front() {
+                return Ok(frames);
+            }
+            self.reap()?;
+        }
+    }
+
+    /// Stops capture, dropping the unread frames, and selects the zero-bandwidth setting.
+    pub fn stop_capture(&mut self) -> Result<()> {
+        let Some(format) = self.capture.take() else {
+            return Ok(());
+        };
+        self.cancel(&[UrbKind::Capture])?;
+        self.captured.clear();
+        self.set_alt_setting(format.interface(), 0)
+    }
+
+    /// Stops the streams, releases the interfaces, and converts the [Uac] into its
+    /// [UsbBackend].
+    pub fn close(mut self) -> Result<B> {
+        self.stop_playback()?;
+        self.stop_capture()?;
+        for iface in self.as_ifaces.iter() {
+            self.backend.release_interface(*iface as u32)?;
+        }
+        self.backend.release_interface(self.ac_iface as u32)?;
+        Ok(self.backend)
+    }
+
+    /// Follows the clock selectors and multipliers of a UAC2 terminal to its clock source.
+    fn clock_source(&self, format: &AudioFormat) -> Result<u8> {
+        let terminal = self
+            .control
+            .terminal(format.terminal_link())
+            .ok_or(Error::NotFound(format!(
+                "UAC terminal {}",
+                format.terminal_link()
+            )))?;
+
+        let mut id = terminal.clock_source();
+        for _ in 0..self.control.clocks().len() {
+            match self.control.clock(id) {
+                Some(ClockEntity::Source { .. }) => return Ok(id),
+                Some(ClockEntity::Selector { sources, .. }) => {
+                    let value = (CLOCK_SELECTOR_CONTROL as u16) << 8;
+                    let buf = self.request_in(
+                        REQUEST_TYPE_CLASS_IN,
+                        UAC2_CUR,
+                        value,
+                        self.entity_index(id),
+                        1,
+                    )?;
+                    // selector pins are numbered from 1
+                    id = buf
+                        .first()
+                        .and_then(|pin| sources.get((*pin as usize).wrapping_sub(1)))
+                        .copied()
+                        .ok_or(Error::InvalidMessage(format!(
+                            "invalid UAC clock selector {id} pin: {buf:?}"
+                        )))?;
+                }
+                Some(ClockEntity::Multiplier { source, .. }) => id = *source,
+                None => break,
+            }
+        }
+
+        Err(Error::NotFound(format!(
+            "UAC clock source of terminal {}",
+            terminal.id()
+        )))
+    }
+
+    fn cur_request(&self) -> u8 {
+        match self.version() {
+            UacVersion::Uac1 => UAC_GET_CUR,
+            UacVersion::Uac2 => UAC2_CUR,
+        }
+    }
+
+    fn entity_index(&self, id: u8) -> u16 {
+        ((id as u16) << 8) | self.ac_iface as u16
+    }
+
+    fn read_i16(buf: &[u8], off: usize) -> Result<i16> {
+        buf.get(off..off + 2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .ok_or(Error::InvalidMessage(format!(
+                "UAC control too short: {}",
+                buf.len()
+            )))
+    }
+
+    fn feature_in(
+        &self,
+        request: u8,
+        selector: u8,
+        unit: u8,
+        channel: u8,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let value = ((selector as u16) << 8) | channel as u16;
+        self.request_in(
+            REQUEST_TYPE_CLASS_IN,
+            request,
+            value,
+            self.entity_index(unit),
+            len,
+        )
+    }
+
+    fn feature_out(&self, selector: u8, unit: u8, channel: u8, data: &[u8]) -> Result<()> {
+        let request = match self.version() {
+            UacVersion::Uac1 => UAC_SET_CUR,
+            UacVersion::Uac2 => UAC2_CUR,
+        };
+        let value = ((selector as u16) << 8) | channel as u16;
+        self.request_out(
+            REQUEST_TYPE_CLASS_OUT,
+            request,
+            value,
+            self.entity_index(unit),
+            data,
+        )
+    }
+
+    fn request_in(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let len = class_request(
+            &self.backend,
+            request_type,
+            request,
+            value,
+            index,
+            &mut buf,
+            self.timeout,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn request_out(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> Result<()> {
+        class_request(
+            &self.backend,
+            request_type,
+            request,
+            value,
+            index,
+            &mut data.to_vec(),
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    fn set_alt_setting(&self, iface: u8, alt: u8) -> Result<()> {
+        self.backend
+            .set_interface(&UsbfsSetInterface::create(iface as u32, alt as u32))
+    }
+
+    fn in_flight(&self, kind: UrbKind) -> usize {
+        self.urbs.iter().filter(|(_, k)| *k == kind).count()
+    }
+
+    fn submit(&mut self, kind: UrbKind, ep: u8, buffer: Vec<u8>, lens: &[usize]) -> Result<()> {
+        let id = self.backend.submit_urb(super::iso_urb(ep, buffer, lens))?;
+        self.urbs.push((id, kind));
+        Ok(())
+    }
+
+    fn submit_feedback_urb(&mut self, ep: u8, packet_size: usize) -> Result<()> {
+        self.submit(
+            UrbKind::Feedback,
+            ep,
+            vec![0u8; packet_size],
+            &[packet_size],
+        )
+    }
+
+    fn submit_capture_urb(&mut self) -> Result<()> {
+        let Some((ep, packet_size)) = self
+            .capture
+            .as_ref()
+            .map(|f| (f.endpoint(), f.max_packet_size()))
+        else {
+            return Ok(());
+        };
+        self.submit(
+            UrbKind::Capture,
+            ep,
+            vec![0u8; packet_size * ISO_PACKETS],
+            &[packet_size; ISO_PACKETS],
+        )
+    }
+
+    /// Submits playback URBs while enough frames are queued, or all of them when flushing.
+    fn pump_playback(&mut self, flush: bool) -> Result<()> {
+        loop {
+            let Some(playback) = self.playback.as_ref() else {
+                return Ok(());
+            };
+            let frame_size = playback.format.frame_size();
+            let queued = playback.pending.len();
+
+            let mut matcher = playback.matcher;
+            let mut lens = Vec::with_capacity(ISO_PACKETS);
+            let mut total = 0;
+            for _ in 0..ISO_PACKETS {
+                let len =
+                    (matcher.next_packet() * frame_size).min(playback.format.max_packet_size());
+                if total + len > queued {
+                    if !flush {
+                        return Ok(());
+                    }
+                    let rest = (queued - total) / frame_size * frame_size;
+                    if rest != 0 {
+                        lens.push(rest);
+                        total += rest;
+                    }
+                    break;
+                }
+                lens.push(len);
+                total += len;
+            }
+
+            if lens.is_empty() {
+                // less than a frame left
+                if let Some(playback) = self.playback.as_mut() {
+                    playback.pending.clear();
+                }
+                return Ok(());
+            }
+            if self.in_flight(UrbKind::Playback) >= ISO_URBS {
+                self.reap()?;
+                continue;
+            }
+
+            let Some(playback) = self.playback.as_mut() else {
+                return Ok(());
+            };
+            playback.matcher = matcher;
+            let buffer: Vec<u8> = playback.pending.drain(..total).collect();
+            let ep = playback.format.endpoint();
+            self.submit(UrbKind::Playback, ep, buffer, &lens)?;
+        }
+    }
+
+    fn reap(&mut self) -> Result<()> {
+        let timeout = Duration::from_millis(self.timeout as u64);
+        let (id, urb) = self.backend.reap_urb(Some(timeout))?;
+        self.complete(id, &urb)
+    }
+
+    fn complete(&mut self, id: UrbId, urb: &Urb<'_>) -> Result<()> {
+        let Some(pos) = self.urbs.iter().position(|(u, _)| *u == id) else {
+            return Ok(());
+        };
+        let (_, kind) = self.urbs.remove(pos);
+
+        let packets = super::iso_packets(urb)?;
+        match kind {
+            UrbKind::Playback => Ok(()),
+            UrbKind::Capture => {
+                // lost packets are skipped
+                let frames: Vec<&[u8]> = packets.into_iter().flatten().collect();
+                self.captured.push_back(frames.concat());
+                self.submit_capture_urb()
+            }
+            UrbKind::Feedback => {
+                let Some(playback) = self.playback.as_mut() else {
+                    return Ok(());
+                };
+                if let Some(Some(data)) = packets.first() {
+                    // implausible values keep the current rate
+                    let _ = playback.matcher.update(data);
+                }
+                let ep = urb.endpoint();
+                let packet_size = playback.format.feedback_packet_size();
+                self.submit_feedback_urb(ep, packet_size)
+            }
+        }
+    }
+
+    /// Discards the URBs of the provided kinds, and waits for them to complete.
+    fn cancel(&mut self, kinds: &[UrbKind]) -> Result<()> {
+        let ids: Vec<UrbId> = self
+            .urbs
+            .iter()
+            .filter(|(_, k)| kinds.contains(k))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids.iter() {
+            // URBs may complete while being discarded
+            let _ = self.backend.discard_urb(*id);
+        }
+
+        let timeout = Duration::from_millis(self.timeout as u64);
+        while self.urbs.iter().any(|(id, _)| ids.contains(id)) {
+            match self.backend.reap_urb(Some(timeout)) {
+                Ok((id, _)) if ids.contains(&id) => self.urbs.retain(|(u, _)| *u != id),
+                // the other stream keeps running
+                Ok((id, urb)) => self.complete(id, &urb)?,
+                Err(Error::Disconnected) => return Err(Error::Disconnected),
+                Err(_) => break,
+            }
+        }
+        self.urbs.retain(|(id, _)| !ids.contains(id));
+
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Uac<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Uac")
+            .field("ac_iface", &self.ac_iface)
+            .field("as_ifaces", &self.as_ifaces)
+            .field("version", &self.version())
+            .field("high_speed", &self.high_speed)
+            .field(
+                "playback",
+                &self.playback.as_ref().map(|p| p.format.alt_setting()),
+            )
+            .field("capture", &self.capture.as_ref().map(|f| f.alt_setting()))
+            .field("urbs", &self.urbs.len())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+    use nix::errno::Errno;
+
+    // UAC2 function: AudioControl interface 0 with clock source 0x10, a stereo playback path
+    // through feature unit 2, and a mono capture path; asynchronous playback on interface 1
+    // with feedback endpoint 0x81, and capture on interface 2
+    const DESCRIPTORS: [u8; 246] = [
+        0x12, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01, 0x00,
+        0x00, 0x00, 0x01, //
+        0x09, 0x02, 0xe4, 0x00, 0x03, 0x01, 0x00, 0x80, 0xfa, //
+        0x09, 0x04, 0x00, 0x00, 0x00, 0x01, 0x01, 0x20, 0x00, //
+        0x09, 0x24, 0x01, 0x00, 0x02, 0x08, 0x5d, 0x00, 0x00, //
+        0x08, 0x24, 0x0a, 0x10, 0x03, 0x07, 0x00, 0x00, //
+        0x11, 0x24, 0x02, 0x01, 0x01, 0x01, 0x00, 0x10, 0x02, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, //
+        0x12, 0x24, 0x06, 0x02, 0x01, 0x0f, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x0c, 0x00,
+        0x00, 0x00, 0x00, //
+        0x0c, 0x24, 0x03, 0x03, 0x01, 0x03, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00, //
+        0x11, 0x24, 0x02, 0x04, 0x01, 0x02, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, //
+        0x0c, 0x24, 0x03, 0x05, 0x01, 0x01, 0x00, 0x04, 0x10, 0x00, 0x00, 0x00, //
+        0x09, 0x04, 0x01, 0x00, 0x00, 0x01, 0x02, 0x20, 0x00, //
+        0x09, 0x04, 0x01, 0x01, 0x02, 0x01, 0x02, 0x20, 0x00, //
+        0x10, 0x24, 0x01, 0x01, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x02, 0x03, 0x00, 0x00, 0x00,
+        0x00, //
+        0x06, 0x24, 0x02, 0x01, 0x02, 0x10, //
+        0x07, 0x05, 0x01, 0x05, 0xc8, 0x00, 0x01, //
+        0x08, 0x25, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x11, 0x04, 0x00, 0x04, //
+        0x09, 0x04, 0x02, 0x00, 0x00, 0x01, 0x02, 0x20, 0x00, //
+        0x09, 0x04, 0x02, 0x01, 0x01, 0x01, 0x02, 0x20, 0x00, //
+        0x10, 0x24, 0x01, 0x05, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, //
+        0x06, 0x24, 0x02, 0x01, 0x02, 0x10, //
+        0x07, 0x05, 0x82, 0x05, 0x64, 0x00, 0x01, //
+        0x08, 0x25, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_uac() -> Result<()> {
+        // 44.1 and 48 kHz
+        let ranges = vec![
+            0x02, 0x00, 0x44, 0xac, 0x00, 0x00, 0x44, 0xac, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x80, 0xbb, 0x00, 0x00, 0x80, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let set_rate = MockControl::create(REQUEST_TYPE_CLASS_OUT, UAC2_CUR, 0x0100, 0x1000)
+            .with_data(48_000u32.to_le_bytes());
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_speed(UsbfsSpeed::High)
+            .with_driver(0, "snd-usb-audio")
+            .with_driver(1, "snd-usb-audio")
+            .with_driver(2, "snd-usb-audio")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, UAC2_RANGE, 0x0100, 0x1000)
+                    .with_response(MockResponse::Data(ranges.clone())),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, UAC2_RANGE, 0x0100, 0x1000)
+                    .with_response(MockResponse::Data(ranges)),
+            )
+            .with_control(set_rate.clone())
+            .with_control(set_rate)
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, UAC2_CUR, 0x0201, 0x0200)
+                    .with_response(MockResponse::Data(vec![0x00, 0xf6])),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, UAC2_CUR, 0x0201, 0x0200)
+                    .with_data([0x00, 0xfb]),
+            )
+            // 6.5 frames per microframe, in 16.16
+            .with_data(0x81, [0x00, 0x80, 0x06, 0x00]);
+        // captured frames around a lost packet
+        dev.push_data(0x82, [1, 2, 3, 4]);
+        dev.push_response(0x82, MockResponse::Errno(Errno::EPROTO as i32));
+        dev.push_data(0x82, [5, 6]);
+
+        let mut uac = Uac::open(dev)?;
+        assert_eq!(uac.version(), UacVersion::Uac2);
+        let playback = uac.find_format(false, 2, 16).unwrap().clone();
+        let capture = uac.find_format(true, 1, 16).unwrap().clone();
+        assert_eq!(playback.sync_type(), SyncType::Async);
+        assert_eq!(playback.feedback_endpoint(), Some(0x81));
+
+        let rates = uac.sample_rates(&playback)?;
+        assert!(rates.supports(44_100) && rates.supports(48_000));
+
+        // 6 frames of 4 bytes per microframe, 8 packets per URB
+        uac.start_playback(&playback, 48_000)?;
+        uac.write(&[0u8; 768])?;
+        assert_eq!(uac.backend().alt_setting(1), 1);
+
+        // the fifth URB follows the feedback: 6 and 7 frames in turn
+        uac.write(&[0u8; 208])?;
+        assert_eq!(uac.playback_rate().map(|r| r.rate()), Some(52_000));
+        uac.drain()?;
+        let written = uac.backend().take_written(0x01);
+        assert_eq!(written.len(), 5);
+        assert_eq!(written[4].len(), 208);
+        uac.stop_playback()?;
+        assert_eq!(uac.backend().alt_setting(1), 0);
+
+        uac.start_capture(&capture, 48_000)?;
+        assert_eq!(uac.read()?, [1, 2, 3, 4, 5, 6]);
+
+        let unit = uac
+            .audio_control()
+            .stream_feature_unit(playback.terminal_link())
+            .map(|u| u.id())
+            .unwrap();
+        assert_eq!(uac.volume(unit, 1)?, -10 * 256);
+        uac.set_volume(unit, 1, -5 * 256)?;
+
+        uac.close()?.verify()
+    }
+}
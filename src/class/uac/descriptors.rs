@@ -0,0 +1,1032 @@
+//! UAC1 and UAC2 class-specific AudioControl and AudioStreaming descriptors.
+//!
+//! The AudioControl interface describes the function topology: terminals, where audio enters
+//! or leaves the function, feature units with the volume and mute controls, and on UAC2 the
+//! clock entities. Each AudioStreaming alternate setting describes one sample format, with its
+//! data endpoint, and for asynchronous playback its feedback endpoint.
+
+use std::fmt;
+
+use crate::class::cdc::DESCRIPTOR_TYPE_CS_INTERFACE;
+use crate::descriptor::{read_u16, DescriptorIter, EndpointDescriptor, InterfaceDescriptor};
+use crate::{Error, Result, TransferType};
+
+/// Interface protocol of UAC1 functions.
+pub const UAC_PROTOCOL_1: u8 = 0x00;
+/// Interface protocol of UAC2 functions.
+pub const UAC_PROTOCOL_2: u8 = 0x20;
+
+pub const DESCRIPTOR_TYPE_CS_ENDPOINT: u8 = 0x25;
+
+pub const AC_HEADER: u8 = 0x01;
+pub const AC_INPUT_TERMINAL: u8 = 0x02;
+pub const AC_OUTPUT_TERMINAL: u8 = 0x03;
+pub const AC_FEATURE_UNIT: u8 = 0x06;
+pub const AC_CLOCK_SOURCE: u8 = 0x0a;
+pub const AC_CLOCK_SELECTOR: u8 = 0x0b;
+pub const AC_CLOCK_MULTIPLIER: u8 = 0x0c;
+
+pub const AS_GENERAL: u8 = 0x01;
+pub const AS_FORMAT_TYPE: u8 = 0x02;
+pub const EP_GENERAL: u8 = 0x01;
+
+pub const FORMAT_TYPE_I: u8 = 0x01;
+
+/// `bmFormats` bits of Type I formats, UAC1 format tags are converted to the same bits.
+pub const FORMAT_PCM: u32 = 0x01;
+pub const FORMAT_PCM8: u32 = 0x02;
+pub const FORMAT_IEEE_FLOAT: u32 = 0x04;
+
+pub const TERMINAL_USB_STREAMING: u16 = 0x0101;
+pub const TERMINAL_MICROPHONE: u16 = 0x0201;
+pub const TERMINAL_SPEAKER: u16 = 0x0301;
+pub const TERMINAL_HEADPHONES: u16 = 0x0302;
+
+// endpoint usage type of explicit feedback endpoints, in bmAttributes
+const USAGE_FEEDBACK: u8 = 0x10;
+const USAGE_MASK: u8 = 0x30;
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+fn u24_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], 0])
+}
+
+fn class_descriptors(extra: &[u8], desc_type: u8) -> impl Iterator<Item = &[u8]> {
+    DescriptorIter::new(extra).filter(move |d| d.len() >= 3 && d[1] == desc_type)
+}
+
+fn too_short(name: &str, desc: &[u8]) -> Error {
+    Error::InvalidDescriptor(format!("UAC {name} too short: {}", desc.len()))
+}
+
+/// Represents the USB Audio Class release of a function.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UacVersion {
+    #[default]
+    Uac1 = UAC_PROTOCOL_1,
+    Uac2 = UAC_PROTOCOL_2,
+}
+
+impl UacVersion {
+    /// Creates a new [UacVersion].
+    pub const fn new() -> Self {
+        Self::Uac1
+    }
+
+    /// Creates a new [UacVersion] from the interface protocol.
+    pub const fn create(protocol: u8) -> Option<Self> {
+        match protocol {
+            UAC_PROTOCOL_1 => Some(Self::Uac1),
+            UAC_PROTOCOL_2 => Some(Self::Uac2),
+            _ => None,
+        }
+    }
+}
+
+impl From<&UacVersion> for &'static str {
+    fn from(val: &UacVersion) -> Self {
+        match val {
+            UacVersion::Uac1 => "UAC1",
+            UacVersion::Uac2 => "UAC2",
+        }
+    }
+}
+
+impl fmt::Display for UacVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents an AudioControl input or output terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioTerminal {
+    id: u8,
+    terminal_type: u16,
+    input: bool,
+    assoc_terminal: u8,
+    source_id: u8,
+    clock_source: u8,
+    channels: u8,
+}
+
+impl AudioTerminal {
+    /// Creates a new [AudioTerminal].
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            terminal_type: 0,
+            input: false,
+            assoc_terminal: 0,
+            source_id: 0,
+            clock_source: 0,
+            channels: 0,
+        }
+    }
+
+    fn parse(desc: &[u8], version: UacVersion) -> Result<Self> {
+        let input = desc[2] == AC_INPUT_TERMINAL;
+        let min_len = match (version, input) {
+            (UacVersion::Uac1, true) => 12,
+            (UacVersion::Uac1, false) => 9,
+            (UacVersion::Uac2, true) => 17,
+            (UacVersion::Uac2, false) => 12,
+        };
+        if desc.len() < min_len {
+            return Err(too_short("terminal", desc));
+        }
+
+        let mut terminal = Self {
+            id: desc[3],
+            terminal_type: read_u16(desc, 4),
+            input,
+            assoc_terminal: desc[6],
+            ..Self::new()
+        };
+        match (version, input) {
+            (UacVersion::Uac1, true) => terminal.channels = desc[7],
+            (UacVersion::Uac1, false) => terminal.source_id = desc[7],
+            (UacVersion::Uac2, true) => {
+                terminal.clock_source = desc[7];
+                terminal.channels = desc[8];
+            }
+            (UacVersion::Uac2, false) => {
+                terminal.source_id = desc[7];
+                terminal.clock_source = desc[8];
+            }
+        }
+
+        Ok(terminal)
+    }
+
+    /// Gets the terminal ID.
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Gets the terminal type, e.g. [TERMINAL_USB_STREAMING].
+    pub const fn terminal_type(&self) -> u16 {
+        self.terminal_type
+    }
+
+    /// Gets whether audio enters the function through the terminal.
+    pub const fn is_input(&self) -> bool {
+        self.input
+    }
+
+    /// Gets the ID of the associated terminal, `0` for none.
+    pub const fn assoc_terminal(&self) -> u8 {
+        self.assoc_terminal
+    }
+
+    /// Gets the ID of the unit or terminal feeding an output terminal.
+    pub const fn source_id(&self) -> u8 {
+        self.source_id
+    }
+
+    /// Gets the ID of the clock entity of UAC2 terminals.
+    pub const fn clock_source(&self) -> u8 {
+        self.clock_source
+    }
+
+    /// Gets the number of channels of input terminals.
+    pub const fn channels(&self) -> u8 {
+        self.channels
+    }
+}
+
+impl fmt::Display for AudioTerminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""id": {}, "#, self.id)?;
+        write!(f, r#""terminal_type": {}, "#, self.terminal_type)?;
+        write!(f, r#""input": {}, "#, self.input)?;
+        write!(f, r#""assoc_terminal": {}, "#, self.assoc_terminal)?;
+        write!(f, r#""source_id": {}, "#, self.source_id)?;
+        write!(f, r#""clock_source": {}, "#, self.clock_source)?;
+        write!(f, r#""channels": {}"#, self.channels)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a feature unit, with the volume and mute controls of each channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeatureUnit {
+    id: u8,
+    source_id: u8,
+    controls: Vec<u32>,
+}
+
+impl FeatureUnit {
+    /// Creates a new [FeatureUnit].
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            source_id: 0,
+            controls: Vec::new(),
+        }
+    }
+
+    fn parse(desc: &[u8], version: UacVersion) -> Result<Self> {
+        let controls = match version {
+            UacVersion::Uac1 => {
+                let size = *desc.get(5).ok_or(too_short("feature unit", desc))? as usize;
+                desc.get(6..desc.len() - 1)
+                    .filter(|_| size != 0)
+                    .ok_or(too_short("feature unit", desc))?
+                    .chunks_exact(size)
+                    .map(|c| c.iter().rev().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+                    .collect()
+            }
+            // two bits per control, normalized to the UAC1 single bit
+            UacVersion::Uac2 => desc
+                .get(5..desc.len() - 1)
+                .ok_or(too_short("feature unit", desc))?
+                .chunks_exact(4)
+                .map(|c| {
+                    let bits = u32_at(c, 0);
+                    (0..16).fold(0u32, |acc, i| match (bits >> (i * 2)) & 0x3 {
+                        0 => acc,
+                        _ => acc | (1 << i),
+                    })
+                })
+                .collect(),
+        };
+
+        Ok(Self {
+            id: desc[3],
+            source_id: *desc.get(4).ok_or(too_short("feature unit", desc))?,
+            controls,
+        })
+    }
+
+    /// Gets the unit ID.
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Gets the ID of the unit or terminal feeding the unit.
+    pub const fn source_id(&self) -> u8 {
+        self.source_id
+    }
+
+    /// Gets the number of logical channels, without the master channel `0`.
+    pub fn channels(&self) -> usize {
+        self.controls.len().saturating_sub(1)
+    }
+
+    /// Gets the control bitmaps by channel, bit `n` set if control selector `n + 1` is present.
+    pub fn controls(&self) -> &[u32] {
+        self.controls.as_ref()
+    }
+
+    /// Gets whether the channel has the control selector, channel `0` being the master.
+    pub fn has_control(&self, channel: u8, selector: u8) -> bool {
+        let bit = selector.wrapping_sub(1) as u32;
+        bit < 32
+            && self
+                .controls
+                .get(channel as usize)
+                .is_some_and(|c| c & (1 << bit) != 0)
+    }
+}
+
+impl fmt::Display for FeatureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""id": {}, "#, self.id)?;
+        write!(f, r#""source_id": {}, "#, self.source_id)?;
+        write!(f, r#""controls": {:?}"#, self.controls)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a UAC2 clock entity.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClockEntity {
+    /// A clock source, with its `bmAttributes` and `bmControls`.
+    Source {
+        id: u8,
+        attributes: u8,
+        controls: u8,
+    },
+    /// A clock selector, choosing one of its sources with its `CUR` value.
+    Selector { id: u8, sources: Vec<u8> },
+    /// A clock multiplier, deriving a clock from its source.
+    Multiplier { id: u8, source: u8 },
+}
+
+impl ClockEntity {
+    /// Creates a new [ClockEntity].
+    pub const fn new() -> Self {
+        Self::Source {
+            id: 0,
+            attributes: 0,
+            controls: 0,
+        }
+    }
+
+    fn parse(desc: &[u8]) -> Result<Self> {
+        let at = |off: usize| {
+            desc.get(off)
+                .copied()
+                .ok_or(too_short("clock entity", desc))
+        };
+
+        match desc[2] {
+            AC_CLOCK_SOURCE => Ok(Self::Source {
+                id: at(3)?,
+                attributes: at(4)?,
+                controls: at(5)?,
+            }),
+            AC_CLOCK_SELECTOR => {
+                let pins = at(4)? as usize;
+                Ok(Self::Selector {
+                    id: at(3)?,
+                    sources: desc
+                        .get(5..5 + pins)
+                        .ok_or(too_short("clock selector", desc))?
+                        .to_vec(),
+                })
+            }
+            _ => Ok(Self::Multiplier {
+                id: at(3)?,
+                source: at(4)?,
+            }),
+        }
+    }
+
+    /// Gets the entity ID.
+    pub const fn id(&self) -> u8 {
+        match self {
+            Self::Source { id, .. } | Self::Selector { id, .. } | Self::Multiplier { id, .. } => {
+                *id
+            }
+        }
+    }
+
+    /// Gets whether the host can set the frequency of a clock source.
+    pub const fn is_programmable(&self) -> bool {
+        matches!(self, Self::Source { controls, .. } if *controls & 0x3 == 0x3)
+    }
+}
+
+impl Default for ClockEntity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ClockEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Source {
+                id,
+                attributes,
+                controls,
+            } => write!(
+                f,
+                r#"{{"source": {id}, "attributes": {attributes}, "controls": {controls}}}"#
+            ),
+            Self::Selector { id, sources } => {
+                write!(f, r#"{{"selector": {id}, "sources": {sources:?}}}"#)
+            }
+            Self::Multiplier { id, source } => {
+                write!(f, r#"{{"multiplier": {id}, "source": {source}}}"#)
+            }
+        }
+    }
+}
+
+/// Represents the class-specific descriptors of the AudioControl interface.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioControl {
+    version: UacVersion,
+    adc_version: u16,
+    streaming_interfaces: Vec<u8>,
+    terminals: Vec<AudioTerminal>,
+    feature_units: Vec<FeatureUnit>,
+    clocks: Vec<ClockEntity>,
+}
+
+impl AudioControl {
+    /// Creates a new [AudioControl].
+    pub const fn new() -> Self {
+        Self {
+            version: UacVersion::new(),
+            adc_version: 0,
+            streaming_interfaces: Vec::new(),
+            terminals: Vec::new(),
+            feature_units: Vec::new(),
+            clocks: Vec::new(),
+        }
+    }
+
+    /// Parses the [AudioControl] from the class-specific descriptors of the interface.
+    pub fn parse(extra: &[u8], version: UacVersion) -> Result<Self> {
+        let mut ac = Self {
+            version,
+            ..Self::new()
+        };
+        let mut header = false;
+
+        for desc in class_descriptors(extra, DESCRIPTOR_TYPE_CS_INTERFACE) {
+            match desc[2] {
+                AC_HEADER => {
+                    if desc.len() < 8 {
+                        return Err(too_short("AudioControl header", desc));
+                    }
+                    ac.adc_version = read_u16(desc, 3);
+                    // UAC2 functions are grouped by their interface association instead
+                    if version == UacVersion::Uac1 {
+                        let count = desc[7] as usize;
+                        ac.streaming_interfaces = desc
+                            .get(8..8 + count)
+                            .ok_or(too_short("AudioControl header", desc))?
+                            .to_vec();
+                    }
+                    header = true;
+                }
+                AC_INPUT_TERMINAL | AC_OUTPUT_TERMINAL => {
+                    ac.terminals.push(AudioTerminal::parse(desc, version)?);
+                }
+                AC_FEATURE_UNIT => ac.feature_units.push(FeatureUnit::parse(desc, version)?),
+                AC_CLOCK_SOURCE | AC_CLOCK_SELECTOR | AC_CLOCK_MULTIPLIER
+                    if version == UacVersion::Uac2 =>
+                {
+                    ac.clocks.push(ClockEntity::parse(desc)?);
+                }
+                _ => (),
+            }
+        }
+
+        if !header {
+            return Err(Error::InvalidDescriptor(
+                "missing UAC AudioControl header".into(),
+            ));
+        }
+
+        Ok(ac)
+    }
+
+    /// Gets the [UacVersion].
+    pub const fn version(&self) -> UacVersion {
+        self.version
+    }
+
+    /// Gets the `bcdADC` release of the specification, in BCD.
+    pub const fn adc_version(&self) -> u16 {
+        self.adc_version
+    }
+
+    /// Gets the numbers of the AudioStreaming interfaces of UAC1 functions.
+    pub fn streaming_interfaces(&self) -> &[u8] {
+        self.streaming_interfaces.as_ref()
+    }
+
+    /// Gets the list of [AudioTerminal]s.
+    pub fn terminals(&self) -> &[AudioTerminal] {
+        self.terminals.as_ref()
+    }
+
+    /// Gets the [AudioTerminal] with the provided ID.
+    pub fn terminal(&self, id: u8) -> Option<&AudioTerminal> {
+        self.terminals.iter().find(|t| t.id() == id)
+    }
+
+    /// Gets the list of [FeatureUnit]s.
+    pub fn feature_units(&self) -> &[FeatureUnit] {
+        self.feature_units.as_ref()
+    }
+
+    /// Gets the [FeatureUnit] with the provided ID.
+    pub fn feature_unit(&self, id: u8) -> Option<&FeatureUnit> {
+        self.feature_units.iter().find(|u| u.id() == id)
+    }
+
+    /// Gets the [FeatureUnit] directly connected to a streaming terminal: the one fed by a
+    /// playback input terminal, or feeding a capture output terminal.
+    pub fn stream_feature_unit(&self, terminal_link: u8) -> Option<&FeatureUnit> {
+        let terminal = self.terminal(terminal_link)?;
+        if terminal.is_input() {
+            self.feature_units
+                .iter()
+                .find(|u| u.source_id() == terminal.id())
+        } else {
+            self.feature_unit(terminal.source_id())
+        }
+    }
+
+    /// Gets the list of UAC2 [ClockEntity]s.
+    pub fn clocks(&self) -> &[ClockEntity] {
+        self.clocks.as_ref()
+    }
+
+    /// Gets the [ClockEntity] with the provided ID.
+    pub fn clock(&self, id: u8) -> Option<&ClockEntity> {
+        self.clocks.iter().find(|c| c.id() == id)
+    }
+}
+
+impl fmt::Display for AudioControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""version": {}, "#, self.version)?;
+        write!(f, r#""adc_version": {}, "#, self.adc_version)?;
+        write!(
+            f,
+            r#""streaming_interfaces": {:?}, "#,
+            self.streaming_interfaces
+        )?;
+        write!(f, r#""terminals": ["#)?;
+        for (i, terminal) in self.terminals.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{terminal}")?;
+        }
+        write!(f, r#"], "feature_units": ["#)?;
+        for (i, unit) in self.feature_units.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{unit}")?;
+        }
+        write!(f, r#"], "clocks": ["#)?;
+        for (i, clock) in self.clocks.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{clock}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents the sample rates of a format or clock, as `(min, max)` ranges.
+///
+/// Discrete rates are ranges with equal bounds.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SampleRates {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl SampleRates {
+    /// Creates a new [SampleRates].
+    pub const fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    /// Creates a new [SampleRates] from a list of discrete rates.
+    pub fn create(rates: &[u32]) -> Self {
+        Self {
+            ranges: rates.iter().map(|r| (*r, *r)).collect(),
+        }
+    }
+
+    /// Parses the [SampleRates] of a UAC2 sampling frequency `RANGE` request.
+    pub fn parse_range(buf: &[u8]) -> Result<Self> {
+        let count = match buf {
+            [lo, hi, ..] => u16::from_le_bytes([*lo, *hi]) as usize,
+            _ => {
+                return Err(Error::InvalidMessage(format!(
+                    "UAC sample rate range too short: {}",
+                    buf.len()
+                )))
+            }
+        };
+        let ranges = buf[2..]
+            .chunks_exact(12)
+            .take(count)
+            .map(|c| (u32_at(c, 0), u32_at(c, 4)))
+            .collect();
+
+        Ok(Self { ranges })
+    }
+
+    /// Gets the `(min, max)` ranges.
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        self.ranges.as_ref()
+    }
+
+    /// Gets whether the sample rate is supported.
+    pub fn supports(&self, rate: u32) -> bool {
+        self.ranges
+            .iter()
+            .any(|(min, max)| (*min..=*max).contains(&rate))
+    }
+
+    /// Gets the highest supported sample rate.
+    pub fn max(&self) -> Option<u32> {
+        self.ranges.iter().map(|(_, max)| *max).max()
+    }
+}
+
+impl fmt::Display for SampleRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, (min, max)) in self.ranges.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "[{min}, {max}]")?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Represents the synchronization type of an Isochronous endpoint.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SyncType {
+    #[default]
+    None = 0x00,
+    /// The device clock is free-running, playback rate follows the feedback endpoint.
+    Async = 0x04,
+    /// The device locks to the rate of the data.
+    Adaptive = 0x08,
+    /// The device clock is locked to the USB frames.
+    Sync = 0x0c,
+}
+
+impl SyncType {
+    /// Creates a new [SyncType].
+    pub const fn new() -> Self {
+        Self::None
+    }
+
+    /// Creates a new [SyncType] from the endpoint `bmAttributes`.
+    pub const fn create(attributes: u8) -> Self {
+        match attributes & 0x0c {
+            0x04 => Self::Async,
+            0x08 => Self::Adaptive,
+            0x0c => Self::Sync,
+            _ => Self::None,
+        }
+    }
+}
+
+impl From<&SyncType> for &'static str {
+    fn from(val: &SyncType) -> Self {
+        match val {
+            SyncType::None => "none",
+            SyncType::Async => "async",
+            SyncType::Adaptive => "adaptive",
+            SyncType::Sync => "sync",
+        }
+    }
+}
+
+impl fmt::Display for SyncType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a Type I PCM format: an AudioStreaming alternate setting with its endpoints.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioFormat {
+    interface: u8,
+    alt_setting: u8,
+    terminal_link: u8,
+    formats: u32,
+    channels: u8,
+    subslot_size: u8,
+    bit_resolution: u8,
+    sample_rates: SampleRates,
+    endpoint: u8,
+    sync_type: SyncType,
+    max_packet_size: usize,
+    interval: u8,
+    feedback_endpoint: Option<u8>,
+    feedback_packet_size: usize,
+    sample_rate_control: bool,
+}
+
+impl AudioFormat {
+    /// Creates a new [AudioFormat].
+    pub const fn new() -> Self {
+        Self {
+            interface: 0,
+            alt_setting: 0,
+            terminal_link: 0,
+            formats: 0,
+            channels: 0,
+            subslot_size: 0,
+            bit_resolution: 0,
+            sample_rates: SampleRates::new(),
+            endpoint: 0,
+            sync_type: SyncType::new(),
+            max_packet_size: 0,
+            interval: 0,
+            feedback_endpoint: None,
+            feedback_packet_size: 0,
+            sample_rate_control: false,
+        }
+    }
+
+    /// Parses the [AudioFormat] of an AudioStreaming alternate setting.
+    ///
+    /// Returns `None` for zero-bandwidth settings, and formats other than Type I.
+    pub fn parse(iface: &InterfaceDescriptor, version: UacVersion) -> Result<Option<Self>> {
+        let is_iso = |e: &&EndpointDescriptor| e.transfer_type() == TransferType::Isochronous;
+        let Some(data) = iface
+            .endpoints()
+            .iter()
+            .filter(is_iso)
+            .find(|e| e.attributes() & USAGE_MASK != USAGE_FEEDBACK)
+        else {
+            return Ok(None);
+        };
+
+        let mut format = Self {
+            interface: iface.number(),
+            alt_setting: iface.alternate_setting(),
+            endpoint: data.address(),
+            sync_type: SyncType::create(data.attributes()),
+            max_packet_size: data.packet_size() as usize * data.transactions() as usize,
+            interval: data.interval(),
+            ..Self::new()
+        };
+
+        let mut general = false;
+        for desc in class_descriptors(iface.extra(), DESCRIPTOR_TYPE_CS_INTERFACE) {
+            match (desc[2], version) {
+                (AS_GENERAL, UacVersion::Uac1) => {
+                    if desc.len() < 7 {
+                        return Err(too_short("AudioStreaming header", desc));
+                    }
+                    format.terminal_link = desc[3];
+                    format.formats = match read_u16(desc, 5) {
+                        tag @ 1..=32 => 1 << (tag - 1),
+                        _ => 0,
+                    };
+                    general = true;
+                }
+                (AS_GENERAL, UacVersion::Uac2) => {
+                    if desc.len() < 16 {
+                        return Err(too_short("AudioStreaming header", desc));
+                    }
+                    format.terminal_link = desc[3];
+                    if desc[5] != FORMAT_TYPE_I {
+                        return Ok(None);
+                    }
+                    format.formats = u32_at(desc, 6);
+                    format.channels = desc[10];
+                    general = true;
+                }
+                (AS_FORMAT_TYPE, UacVersion::Uac1) => {
+                    if desc.len() < 8 {
+                        return Err(too_short("format type", desc));
+                    }
+                    if desc[3] != FORMAT_TYPE_I {
+                        return Ok(None);
+                    }
+                    format.channels = desc[4];
+                    format.subslot_size = desc[5];
+                    format.bit_resolution = desc[6];
+                    format.sample_rates = match desc[7] {
+                        // a continuous range
+                        0 if desc.len() >= 14 => SampleRates {
+                            ranges: vec![(u24_at(desc, 8), u24_at(desc, 11))],
+                        },
+                        n => SampleRates::create(
+                            &desc
+                                .get(8..8 + n as usize * 3)
+                                .ok_or(too_short("format type", desc))?
+                                .chunks_exact(3)
+                                .map(|c| u24_at(c, 0))
+                                .collect::<Vec<u32>>(),
+                        ),
+                    };
+                }
+                (AS_FORMAT_TYPE, UacVersion::Uac2) => {
+                    if desc.len() < 6 {
+                        return Err(too_short("format type", desc));
+                    }
+                    if desc[3] != FORMAT_TYPE_I {
+                        return Ok(None);
+                    }
+                    format.subslot_size = desc[4];
+                    format.bit_resolution = desc[5];
+                }
+                _ => (),
+            }
+        }
+
+        if !general || format.subslot_size == 0 || format.channels == 0 {
+            return Err(Error::InvalidDescriptor(format!(
+                "incomplete UAC AudioStreaming interface {}, alternate setting {}",
+                format.interface, format.alt_setting
+            )));
+        }
+
+        // UAC1 endpoints report the sampling frequency control in their class descriptor
+        format.sample_rate_control = class_descriptors(data.extra(), DESCRIPTOR_TYPE_CS_ENDPOINT)
+            .find(|d| d[2] == EP_GENERAL && d.len() >= 4)
+            .is_some_and(|d| d[3] & 0x01 != 0);
+
+        // explicit feedback, named by UAC1 data endpoints, and flagged by UAC2 endpoints
+        let feedback = iface.endpoints().iter().filter(is_iso).find(|e| {
+            e.address() != data.address()
+                && e.is_in() != data.is_in()
+                && (e.address() == data.synch_address()
+                    || e.attributes() & USAGE_MASK == USAGE_FEEDBACK)
+        });
+        if let Some(ep) = feedback {
+            format.feedback_endpoint = Some(ep.address());
+            format.feedback_packet_size = ep.packet_size() as usize;
+        }
+
+        Ok(Some(format))
+    }
+
+    /// Gets the AudioStreaming interface number.
+    pub const fn interface(&self) -> u8 {
+        self.interface
+    }
+
+    /// Gets the alternate setting of the format.
+    pub const fn alt_setting(&self) -> u8 {
+        self.alt_setting
+    }
+
+    /// Gets the ID of the USB streaming terminal of the interface.
+    pub const fn terminal_link(&self) -> u8 {
+        self.terminal_link
+    }
+
+    /// Gets the format bitmap, e.g. [FORMAT_PCM].
+    pub const fn formats(&self) -> u32 {
+        self.formats
+    }
+
+    /// Gets the number of channels.
+    pub const fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    /// Gets the number of bytes per sample.
+    pub const fn subslot_size(&self) -> u8 {
+        self.subslot_size
+    }
+
+    /// Gets the number of significant bits per sample.
+    pub const fn bit_resolution(&self) -> u8 {
+        self.bit_resolution
+    }
+
+    /// Gets the number of bytes per audio frame: one sample for each channel.
+    pub const fn frame_size(&self) -> usize {
+        self.channels as usize * self.subslot_size as usize
+    }
+
+    /// Gets the [SampleRates] of UAC1 formats, UAC2 rates are held by the clock source.
+    pub const fn sample_rates(&self) -> &SampleRates {
+        &self.sample_rates
+    }
+
+    /// Gets the data endpoint address.
+    pub const fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    /// Gets whether the format captures audio.
+    pub const fn is_capture(&self) -> bool {
+        self.endpoint & 0x80 != 0
+    }
+
+    /// Gets the [SyncType] of the data endpoint.
+    pub const fn sync_type(&self) -> SyncType {
+        self.sync_type
+    }
+
+    /// Gets the largest packet of the data endpoint, including high-bandwidth transactions.
+    pub const fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// Gets the `bInterval` of the data endpoint.
+    pub const fn interval(&self) -> u8 {
+        self.interval
+    }
+
+    /// Gets the explicit feedback endpoint address, if any.
+    pub const fn feedback_endpoint(&self) -> Option<u8> {
+        self.feedback_endpoint
+    }
+
+    /// Gets the packet size of the feedback endpoint.
+    pub const fn feedback_packet_size(&self) -> usize {
+        self.feedback_packet_size
+    }
+
+    /// Gets whether a UAC1 data endpoint has the sampling frequency control.
+    pub const fn sample_rate_control(&self) -> bool {
+        self.sample_rate_control
+    }
+}
+
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""interface": {}, "#, self.interface)?;
+        write!(f, r#""alt_setting": {}, "#, self.alt_setting)?;
+        write!(f, r#""terminal_link": {}, "#, self.terminal_link)?;
+        write!(f, r#""formats": {}, "#, self.formats)?;
+        write!(f, r#""channels": {}, "#, self.channels)?;
+        write!(f, r#""subslot_size": {}, "#, self.subslot_size)?;
+        write!(f, r#""bit_resolution": {}, "#, self.bit_resolution)?;
+        write!(f, r#""sample_rates": {}, "#, self.sample_rates)?;
+        write!(f, r#""endpoint": {}, "#, self.endpoint)?;
+        write!(f, r#""sync_type": {}, "#, self.sync_type)?;
+        write!(f, r#""max_packet_size": {}, "#, self.max_packet_size)?;
+        match self.feedback_endpoint {
+            Some(ep) => write!(f, r#""feedback_endpoint": {ep}"#)?,
+            None => write!(f, r#""feedback_endpoint": null"#)?,
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uac1_descriptors() -> Result<()> {
+        let ac = [
+            0x0a, 0x24, 0x01, 0x00, 0x01, 0x27, 0x00, 0x02, 0x01, 0x02, //
+            0x0c, 0x24, 0x02, 0x01, 0x01, 0x01, 0x00, 0x02, 0x03, 0x00, 0x00, 0x00, //
+            0x0a, 0x24, 0x06, 0x02, 0x01, 0x01, 0x01, 0x02, 0x02, 0x00, //
+            0x09, 0x24, 0x03, 0x03, 0x01, 0x03, 0x00, 0x02, 0x00,
+        ];
+        let ac = AudioControl::parse(&ac, UacVersion::Uac1)?;
+        assert_eq!(ac.streaming_interfaces(), [1, 2]);
+        assert_eq!(ac.terminal(1).map(|t| t.channels()), Some(2));
+        let unit = ac.stream_feature_unit(1).unwrap();
+        assert_eq!(unit.id(), 2);
+        assert_eq!(unit.channels(), 2);
+        assert!(unit.has_control(0, 1) && !unit.has_control(0, 2));
+        assert!(unit.has_control(1, 2) && !unit.has_control(1, 1));
+
+        // AudioStreaming alternate setting with a Type I format, and a feedback endpoint
+        let mut iface =
+            InterfaceDescriptor::parse(&[0x09, 0x04, 0x01, 0x01, 0x02, 0x01, 0x02, 0x00, 0x00])?;
+        iface.extend_extra(&[
+            0x07, 0x24, 0x01, 0x01, 0x01, 0x01, 0x00, //
+            0x11, 0x24, 0x02, 0x01, 0x02, 0x02, 0x10, 0x03, 0x40, 0x1f, 0x00, 0x44, 0xac, 0x00,
+            0x80, 0xbb, 0x00,
+        ]);
+        let mut data =
+            EndpointDescriptor::parse(&[0x09, 0x05, 0x01, 0x05, 0xc8, 0x00, 0x01, 0x00, 0x81])?;
+        data.extend_extra(&[0x07, 0x25, 0x01, 0x01, 0x00, 0x00, 0x00]);
+        iface.push_endpoint(data);
+        iface.push_endpoint(EndpointDescriptor::parse(&[
+            0x09, 0x05, 0x81, 0x01, 0x03, 0x00, 0x01, 0x05, 0x00,
+        ])?);
+
+        let format = AudioFormat::parse(&iface, UacVersion::Uac1)?.unwrap();
+        assert_eq!(format.formats(), FORMAT_PCM);
+        assert_eq!(format.frame_size(), 4);
+        assert!(format.sample_rates().supports(44_100));
+        assert!(!format.sample_rates().supports(96_000));
+        assert_eq!(format.sync_type(), SyncType::Async);
+        assert_eq!(format.feedback_endpoint(), Some(0x81));
+        assert!(format.sample_rate_control() && !format.is_capture());
+
+        // a format without channels has no frame size
+        let mut iface =
+            InterfaceDescriptor::parse(&[0x09, 0x04, 0x01, 0x01, 0x01, 0x01, 0x02, 0x00, 0x00])?;
+        iface.extend_extra(&[
+            0x07, 0x24, 0x01, 0x01, 0x01, 0x01, 0x00, //
+            0x0b, 0x24, 0x02, 0x01, 0x00, 0x02, 0x10, 0x01, 0x80, 0xbb, 0x00,
+        ]);
+        iface.push_endpoint(EndpointDescriptor::parse(&[
+            0x09, 0x05, 0x01, 0x09, 0xc8, 0x00, 0x01, 0x00, 0x00,
+        ])?);
+        assert!(AudioFormat::parse(&iface, UacVersion::Uac1).is_err());
+
+        assert_eq!(
+            SampleRates::parse_range(&[
+                0x01, 0x00, 0x44, 0xac, 0x00, 0x00, 0x00, 0x77, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00
+            ])?
+            .ranges(),
+            [(44_100, 96_000)]
+        );
+
+        Ok(())
+    }
+}
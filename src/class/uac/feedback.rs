@@ -0,0 +1,160 @@
+//! Rate matching of Isochronous audio playback.
+//!
+//! Audio frames rarely divide evenly into packets, e.g. 44.1 frames per millisecond, so packet
+//! sizes vary around the nominal rate. Asynchronous devices run their own clock, and report
+//! the rate they consume through a feedback endpoint: on Full speed, frames per frame in 10.14
+//! fixed point, and on High speed, frames per microframe in 16.16 fixed point.
+
+use std::fmt;
+
+use crate::{Error, Result};
+
+/// Computes playback packet sizes, in audio frames, from the nominal or feedback rate.
+///
+/// Rates are kept in 16.16 fixed point frames per packet, and the fractional frames carried
+/// over to the next packets.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateMatcher {
+    nominal: u32,
+    current: u32,
+    remainder: u32,
+    packets_per_second: u32,
+    frames_per_packet: u32,
+}
+
+impl RateMatcher {
+    /// Creates a new [RateMatcher].
+    pub const fn new() -> Self {
+        Self {
+            nominal: 0,
+            current: 0,
+            remainder: 0,
+            packets_per_second: 1000,
+            frames_per_packet: 1,
+        }
+    }
+
+    /// Creates a new [RateMatcher] for a sample rate, and the data endpoint `bInterval`.
+    ///
+    /// High speed endpoints send a packet every `2^(bInterval - 1)` microframes, Full speed
+    /// endpoints every frame.
+    pub fn create(rate: u32, high_speed: bool, interval: u8) -> Self {
+        let (packets_per_second, frames_per_packet) = if high_speed {
+            let microframes = 1u32 << (interval.clamp(1, 4) - 1);
+            (8000 / microframes, microframes)
+        } else {
+            (1000, 1)
+        };
+        let pps = packets_per_second as u64;
+        let nominal = ((((rate as u64) << 16) + pps / 2) / pps) as u32;
+
+        Self {
+            nominal,
+            current: nominal,
+            remainder: 0,
+            packets_per_second,
+            frames_per_packet,
+        }
+    }
+
+    /// Gets the nominal rate, in 16.16 fixed point frames per packet.
+    pub const fn nominal(&self) -> u32 {
+        self.nominal
+    }
+
+    /// Gets the current rate, in 16.16 fixed point frames per packet.
+    pub const fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Gets the current rate, in frames per second.
+    pub const fn rate(&self) -> u32 {
+        ((self.current as u64 * self.packets_per_second as u64) >> 16) as u32
+    }
+
+    /// Updates the current rate from a feedback packet.
+    ///
+    /// The format is guessed from the packet length, and checked against the nominal rate,
+    /// as many devices send 10.14 values on High speed or 16.16 values on Full speed.
+    /// Values off by more than 25% are rejected.
+    pub fn update(&mut self, feedback: &[u8]) -> Result<()> {
+        let value = match feedback {
+            [a, b, c] => u32::from_le_bytes([*a, *b, *c, 0]) << 2,
+            [a, b, c, d, ..] => u32::from_le_bytes([*a, *b, *c, *d]),
+            _ => {
+                return Err(Error::InvalidMessage(format!(
+                    "UAC feedback too short: {}",
+                    feedback.len()
+                )))
+            }
+        };
+
+        // the value is by (micro)frame, the nominal rate by packet
+        let nominal = self.nominal / self.frames_per_packet;
+        let value = [value, value << 2, value >> 2]
+            .into_iter()
+            .find(|v| v.abs_diff(nominal) <= nominal / 4)
+            .ok_or(Error::InvalidMessage(format!(
+                "UAC feedback out of range: {value:#x}, nominal: {nominal:#x}"
+            )))?;
+
+        self.current = value.saturating_mul(self.frames_per_packet);
+        Ok(())
+    }
+
+    /// Gets the number of frames of the next packet.
+    pub fn next_packet(&mut self) -> usize {
+        let total = self.remainder + self.current;
+        self.remainder = total & 0xffff;
+        (total >> 16) as usize
+    }
+
+    /// Resets the current rate to the nominal rate.
+    pub fn reset(&mut self) {
+        self.current = self.nominal;
+        self.remainder = 0;
+    }
+}
+
+impl fmt::Display for RateMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""nominal": {}, "#, self.nominal)?;
+        write!(f, r#""current": {}, "#, self.current)?;
+        write!(f, r#""rate": {}"#, self.rate())?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_matcher() -> Result<()> {
+        // 44.1 frames per Full speed frame
+        let mut matcher = RateMatcher::create(44_100, false, 1);
+        let frames: Vec<usize> = (0..10).map(|_| matcher.next_packet()).collect();
+        assert_eq!(frames.iter().sum::<usize>(), 441);
+        assert!(frames.iter().all(|f| *f == 44 || *f == 45));
+
+        // 44.2 frames per frame, in 10.14
+        matcher.update(&[0xcd, 0x0c, 0x0b])?;
+        assert_eq!(matcher.rate(), 44_200);
+        assert!(matcher.update(&[0x00, 0x00, 0x80, 0x00]).is_err());
+
+        // 6.5 frames per microframe, one packet every two microframes
+        let mut matcher = RateMatcher::create(48_000, true, 2);
+        assert_eq!(matcher.next_packet(), 12);
+        matcher.update(&[0x00, 0x80, 0x06, 0x00])?;
+        assert_eq!(matcher.next_packet(), 13);
+        assert_eq!(matcher.rate(), 52_000);
+
+        // 10.14 value sent in four bytes
+        matcher.update(&[0x00, 0x80, 0x01, 0x00])?;
+        assert_eq!(matcher.rate(), 48_000);
+
+        Ok(())
+    }
+}
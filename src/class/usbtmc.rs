@@ -0,0 +1,921 @@
+//! USB Test and Measurement Class (USBTMC) driver, with the USB488 subclass.
+//!
+//! Messages to the instrument travel in `DEV_DEP_MSG_OUT` Bulk transfers, and responses are
+//! requested with `REQUEST_DEV_DEP_MSG_IN`, both framed by a 12-byte header carrying a rolling
+//! `bTag`. Timed out transfers are recovered with the abort sequences, and USB488 instruments
+//! report service requests on the Interrupt IN endpoint.
+
+use std::time::Duration;
+use std::{fmt, thread};
+
+use nix::errno::Errno;
+
+use super::{class_request, DEFAULT_TIMEOUT, REQUEST_TYPE_CLASS_IN};
+use crate::{Error, Result, TransferType, UsbBackend, UsbfsBulkTransfer};
+
+pub const USBTMC_CLASS: u8 = 0xfe;
+pub const USBTMC_SUBCLASS: u8 = 0x03;
+pub const USBTMC_PROTOCOL: u8 = 0x00;
+pub const USBTMC_PROTOCOL_USB488: u8 = 0x01;
+
+pub const USBTMC_MSG_DEV_DEP_MSG_OUT: u8 = 1;
+pub const USBTMC_MSG_REQUEST_DEV_DEP_MSG_IN: u8 = 2;
+pub const USBTMC_MSG_DEV_DEP_MSG_IN: u8 = 2;
+pub const USB488_MSG_TRIGGER: u8 = 128;
+
+pub const USBTMC_INITIATE_ABORT_BULK_OUT: u8 = 1;
+pub const USBTMC_CHECK_ABORT_BULK_OUT_STATUS: u8 = 2;
+pub const USBTMC_INITIATE_ABORT_BULK_IN: u8 = 3;
+pub const USBTMC_CHECK_ABORT_BULK_IN_STATUS: u8 = 4;
+pub const USBTMC_INITIATE_CLEAR: u8 = 5;
+pub const USBTMC_CHECK_CLEAR_STATUS: u8 = 6;
+pub const USBTMC_GET_CAPABILITIES: u8 = 7;
+pub const USBTMC_INDICATOR_PULSE: u8 = 64;
+pub const USB488_READ_STATUS_BYTE: u8 = 128;
+pub const USB488_REN_CONTROL: u8 = 160;
+pub const USB488_GO_TO_LOCAL: u8 = 161;
+pub const USB488_LOCAL_LOCKOUT: u8 = 162;
+
+/// Length of the Bulk message header.
+pub const USBTMC_HEADER_LEN: usize = 12;
+pub const USBTMC_CAPABILITIES_LEN: usize = 0x18;
+
+// bmRequestType of class requests to an endpoint
+const REQUEST_TYPE_CLASS_ENDPOINT_IN: u8 = 0xa2;
+// largest message payload per Bulk transfer
+const MAX_TRANSFER_LEN: usize = 16 * 1024;
+// interval between abort and clear status checks
+const CHECK_STATUS_INTERVAL: Duration = Duration::from_millis(50);
+
+const ATTR_EOM: u8 = 0x01;
+const ATTR_TERM_CHAR: u8 = 0x02;
+// bNotify1 of service requests, and of READ_STATUS_BYTE responses
+const NOTIFY_SRQ: u8 = 0x81;
+const NOTIFY_STATUS_BYTE: u8 = 0x80;
+
+/// Represents the `USBTMC_status` of a control response.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbtmcStatus {
+    #[default]
+    Success = 0x01,
+    Pending = 0x02,
+    InterruptInBusy = 0x20,
+    Failed = 0x80,
+    TransferNotInProgress = 0x81,
+    SplitNotInProgress = 0x82,
+    SplitInProgress = 0x83,
+}
+
+impl UsbtmcStatus {
+    /// Creates a new [UsbtmcStatus].
+    pub const fn new() -> Self {
+        Self::Success
+    }
+
+    /// Creates a new [UsbtmcStatus] from its raw value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            0x01 => Some(Self::Success),
+            0x02 => Some(Self::Pending),
+            0x20 => Some(Self::InterruptInBusy),
+            0x80 => Some(Self::Failed),
+            0x81 => Some(Self::TransferNotInProgress),
+            0x82 => Some(Self::SplitNotInProgress),
+            0x83 => Some(Self::SplitInProgress),
+            _ => None,
+        }
+    }
+}
+
+impl From<&UsbtmcStatus> for &'static str {
+    fn from(val: &UsbtmcStatus) -> Self {
+        match val {
+            UsbtmcStatus::Success => "success",
+            UsbtmcStatus::Pending => "pending",
+            UsbtmcStatus::InterruptInBusy => "interrupt in busy",
+            UsbtmcStatus::Failed => "failed",
+            UsbtmcStatus::TransferNotInProgress => "transfer not in progress",
+            UsbtmcStatus::SplitNotInProgress => "split not in progress",
+            UsbtmcStatus::SplitInProgress => "split in progress",
+        }
+    }
+}
+
+impl fmt::Display for UsbtmcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+impl std::error::Error for UsbtmcStatus {}
+
+/// Represents the `GET_CAPABILITIES` response, with the USB488 fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbtmcCapabilities {
+    usbtmc_version: u16,
+    interface: u8,
+    device: u8,
+    usb488_version: u16,
+    usb488_interface: u8,
+    usb488_device: u8,
+}
+
+impl UsbtmcCapabilities {
+    /// Creates a new [UsbtmcCapabilities].
+    pub const fn new() -> Self {
+        Self {
+            usbtmc_version: 0,
+            interface: 0,
+            device: 0,
+            usb488_version: 0,
+            usb488_interface: 0,
+            usb488_device: 0,
+        }
+    }
+
+    /// Parses the [UsbtmcCapabilities] from the `GET_CAPABILITIES` response.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < USBTMC_CAPABILITIES_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "USBTMC capabilities too short: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            usbtmc_version: u16::from_le_bytes([buf[2], buf[3]]),
+            interface: buf[4],
+            device: buf[5],
+            usb488_version: u16::from_le_bytes([buf[12], buf[13]]),
+            usb488_interface: buf[14],
+            usb488_device: buf[15],
+        })
+    }
+
+    /// Gets the USBTMC specification release, in BCD.
+    pub const fn usbtmc_version(&self) -> u16 {
+        self.usbtmc_version
+    }
+
+    /// Gets whether the interface accepts `INDICATOR_PULSE`.
+    pub const fn indicator_pulse(&self) -> bool {
+        self.interface & 0x04 != 0
+    }
+
+    /// Gets whether the interface only sends messages.
+    pub const fn talk_only(&self) -> bool {
+        self.interface & 0x02 != 0
+    }
+
+    /// Gets whether the interface only receives messages.
+    pub const fn listen_only(&self) -> bool {
+        self.interface & 0x01 != 0
+    }
+
+    /// Gets whether the device ends responses on a termination character.
+    pub const fn term_char(&self) -> bool {
+        self.device & 0x01 != 0
+    }
+
+    /// Gets the USB488 specification release in BCD, `0` for plain USBTMC interfaces.
+    pub const fn usb488_version(&self) -> u16 {
+        self.usb488_version
+    }
+
+    /// Gets whether the interface is IEEE 488.2 compliant.
+    pub const fn is_488_2(&self) -> bool {
+        self.usb488_interface & 0x04 != 0
+    }
+
+    /// Gets whether the interface accepts `REN_CONTROL`, `GO_TO_LOCAL` and `LOCAL_LOCKOUT`.
+    pub const fn ren_control(&self) -> bool {
+        self.usb488_interface & 0x02 != 0
+    }
+
+    /// Gets whether the interface accepts the `TRIGGER` message.
+    pub const fn trigger(&self) -> bool {
+        self.usb488_interface & 0x01 != 0
+    }
+
+    /// Gets whether the device understands SCPI.
+    pub const fn scpi(&self) -> bool {
+        self.usb488_device & 0x08 != 0
+    }
+
+    /// Gets whether the device implements service requests (SR1).
+    pub const fn sr1(&self) -> bool {
+        self.usb488_device & 0x04 != 0
+    }
+
+    /// Gets whether the device implements remote/local control (RL1).
+    pub const fn rl1(&self) -> bool {
+        self.usb488_device & 0x02 != 0
+    }
+
+    /// Gets whether the device implements device triggers (DT1).
+    pub const fn dt1(&self) -> bool {
+        self.usb488_device & 0x01 != 0
+    }
+}
+
+impl fmt::Display for UsbtmcCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""usbtmc_version": {}, "#, self.usbtmc_version)?;
+        write!(f, r#""interface": {}, "#, self.interface)?;
+        write!(f, r#""device": {}, "#, self.device)?;
+        write!(f, r#""usb488_version": {}, "#, self.usb488_version)?;
+        write!(f, r#""usb488_interface": {}, "#, self.usb488_interface)?;
+        write!(f, r#""usb488_device": {}"#, self.usb488_device)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a notification from the USB488 Interrupt IN endpoint.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbtmcNotification {
+    /// A service request, with the status byte.
+    Srq(u8),
+    /// The status byte requested by `READ_STATUS_BYTE`.
+    StatusByte { tag: u8, status: u8 },
+    /// A notification not handled by the crate.
+    Other(Vec<u8>),
+}
+
+impl UsbtmcNotification {
+    /// Parses a [UsbtmcNotification] from the bytes read on the Interrupt IN endpoint.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        match buf {
+            [NOTIFY_SRQ, status, ..] => Ok(Self::Srq(*status)),
+            [notify, status, ..] if notify & NOTIFY_STATUS_BYTE != 0 => Ok(Self::StatusByte {
+                tag: notify & 0x7f,
+                status: *status,
+            }),
+            [_, _, ..] => Ok(Self::Other(buf.into())),
+            _ => Err(Error::InvalidMessage(format!(
+                "USBTMC notification too short: {}",
+                buf.len()
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for UsbtmcNotification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Srq(status) => write!(f, r#"{{"srq": {status}}}"#),
+            Self::StatusByte { tag, status } => {
+                write!(
+                    f,
+                    r#"{{"status_byte": {{"tag": {tag}, "status": {status}}}}}"#
+                )
+            }
+            Self::Other(data) => write!(f, r#"{{"data": {data:?}}}"#),
+        }
+    }
+}
+
+/// USBTMC instrument over a [UsbBackend].
+pub struct Usbtmc<B: UsbBackend> {
+    backend: B,
+    iface: u8,
+    protocol: u8,
+    bulk_in: u8,
+    bulk_out: u8,
+    interrupt_in: Option<u8>,
+    max_packet_size: usize,
+    capabilities: UsbtmcCapabilities,
+    tag: u8,
+    status_tag: u8,
+    term_char: Option<u8>,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Usbtmc<B> {
+    /// Opens the first USBTMC interface of the active configuration.
+    ///
+    /// Claims the interface, detaching the `usbtmc` kernel driver, and reads its capabilities.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let desc = config
+            .interfaces()
+            .iter()
+            .find(|i| i.class() == USBTMC_CLASS && i.subclass() == USBTMC_SUBCLASS)
+            .ok_or(Error::NotFound("USBTMC interface".into()))?;
+        let bulk_in = super::find_endpoint(desc, TransferType::Bulk, true)
+            .ok_or(Error::NotFound("USBTMC Bulk IN endpoint".into()))?;
+        let bulk_out = super::find_endpoint(desc, TransferType::Bulk, false)
+            .ok_or(Error::NotFound("USBTMC Bulk OUT endpoint".into()))?;
+        let interrupt_in = super::find_endpoint(desc, TransferType::Interrupt, true);
+
+        let mut tmc = Self {
+            iface: desc.number(),
+            protocol: desc.protocol(),
+            bulk_in: bulk_in.address(),
+            bulk_out: bulk_out.address(),
+            interrupt_in: interrupt_in.map(|e| e.address()),
+            max_packet_size: (bulk_in.max_packet_size() as usize).max(USBTMC_HEADER_LEN),
+            capabilities: UsbtmcCapabilities::new(),
+            tag: 0,
+            status_tag: 1,
+            term_char: None,
+            timeout: DEFAULT_TIMEOUT * 5,
+            backend,
+        };
+
+        super::claim_detaching(&tmc.backend, tmc.iface)?;
+        tmc.capabilities = tmc.get_capabilities()?;
+
+        Ok(tmc)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u8 {
+        self.iface
+    }
+
+    /// Gets whether the interface implements the USB488 subclass.
+    pub const fn is_usb488(&self) -> bool {
+        self.protocol == USBTMC_PROTOCOL_USB488
+    }
+
+    /// Gets the [UsbtmcCapabilities] read when opening the interface.
+    pub const fn capabilities(&self) -> &UsbtmcCapabilities {
+        &self.capabilities
+    }
+
+    /// Gets the Interrupt IN endpoint address, if any.
+    pub const fn interrupt_in(&self) -> Option<u8> {
+        self.interrupt_in
+    }
+
+    /// Gets the termination character ending responses, if enabled.
+    pub const fn term_char(&self) -> Option<u8> {
+        self.term_char
+    }
+
+    /// Sets the termination character ending responses.
+    ///
+    /// Only devices reporting [term_char](UsbtmcCapabilities::term_char) support it.
+    pub fn set_term_char(&mut self, term_char: Option<u8>) {
+        self.term_char = term_char;
+    }
+
+    /// Builder function that sets the termination character ending responses.
+    pub fn with_term_char(mut self, term_char: Option<u8>) -> Self {
+        self.set_term_char(term_char);
+        self
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Sends a device dependent message, e.g. a SCPI command.
+    ///
+    /// Long messages are split across transfers, the last one carrying the end of message flag.
+    /// A timed out transfer is aborted with `INITIATE_ABORT_BULK_OUT`.
+    pub fn write(&mut self, msg: &[u8]) -> Result<()> {
+        if msg.is_empty() {
+            return Err(Error::InvalidArgument("empty USBTMC message".into()));
+        }
+
+        let mut chunks = msg.chunks(MAX_TRANSFER_LEN).peekable();
+
+        while let Some(chunk) = chunks.next() {
+            let eom = chunks.peek().is_none();
+            let tag = self.next_tag();
+
+            let mut buf = self.header(USBTMC_MSG_DEV_DEP_MSG_OUT, tag, chunk.len(), eom as u8, 0);
+            buf.extend_from_slice(chunk);
+            // messages are padded to a multiple of 4 bytes
+            buf.resize(buf.len().next_multiple_of(4), 0);
+
+            if let Err(err) = self.bulk(self.bulk_out, buf) {
+                if is_timeout(&err) {
+                    self.abort_bulk_out(tag)?;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a device dependent message, up to `max_len` bytes.
+    ///
+    /// Requests transfers until the device sets the end of message flag, or sends the
+    /// termination character. A timed out transfer is aborted with `INITIATE_ABORT_BULK_IN`.
+    pub fn read(&mut self, max_len: usize) -> Result<Vec<u8>> {
+        let mut msg = Vec::new();
+
+        while msg.len() < max_len {
+            let len = (max_len - msg.len()).min(MAX_TRANSFER_LEN);
+            let tag = self.next_tag();
+            let (attributes, term_char) = match self.term_char {
+                Some(c) => (ATTR_TERM_CHAR, c),
+                None => (0, 0),
+            };
+
+            let request = self.header(
+                USBTMC_MSG_REQUEST_DEV_DEP_MSG_IN,
+                tag,
+                len,
+                attributes,
+                term_char,
+            );
+            let buf_len = USBTMC_HEADER_LEN
+                .saturating_add(len)
+                .next_multiple_of(self.max_packet_size);
+            let res = self
+                .bulk(self.bulk_out, request)
+                .and_then(|_| self.bulk(self.bulk_in, vec![0u8; buf_len]));
+
+            let buf = match res {
+                Ok(buf) => buf,
+                Err(err) => {
+                    if is_timeout(&err) {
+                        self.abort_bulk_in(tag)?;
+                    }
+                    return Err(err);
+                }
+            };
+
+            let (data, attributes) = parse_msg_in(&buf, tag)?;
+            msg.extend_from_slice(data);
+            if attributes & (ATTR_EOM | ATTR_TERM_CHAR) != 0 || data.is_empty() {
+                break;
+            }
+        }
+
+        Ok(msg)
+    }
+
+    /// Sends a SCPI command, and reads its response as a string, without the trailing newline.
+    pub fn query(&mut self, cmd: &str) -> Result<String> {
+        self.command(cmd)?;
+        let resp = self.read(MAX_TRANSFER_LEN)?;
+        Ok(String::from_utf8_lossy(&resp)
+            .trim_end_matches(['\r', '\n'])
+            .to_string())
+    }
+
+    /// Sends a SCPI command, terminated by a newline.
+    pub fn command(&mut self, cmd: &str) -> Result<()> {
+        if cmd.ends_with('\n') {
+            self.write(cmd.as_bytes())
+        } else {
+            self.write(format!("{cmd}\n").as_bytes())
+        }
+    }
+
+    /// Sends the USB488 `TRIGGER` message, the equivalent of the IEEE 488 GET.
+    pub fn trigger(&mut self) -> Result<()> {
+        let tag = self.next_tag();
+        let buf = self.header(USB488_MSG_TRIGGER, tag, 0, 0, 0);
+        self.bulk(self.bulk_out, buf).map(|_| ())
+    }
+
+    /// Gets the [UsbtmcCapabilities] with `GET_CAPABILITIES`.
+    pub fn get_capabilities(&self) -> Result<UsbtmcCapabilities> {
+        let buf = self.request(
+            REQUEST_TYPE_CLASS_IN,
+            USBTMC_GET_CAPABILITIES,
+            0,
+            self.iface as u16,
+            USBTMC_CAPABILITIES_LEN,
+        )?;
+        check_status(&buf)?;
+        UsbtmcCapabilities::parse(&buf)
+    }
+
+    /// Blinks the instrument activity indicator with `INDICATOR_PULSE`.
+    pub fn indicator_pulse(&self) -> Result<()> {
+        self.interface_request(USBTMC_INDICATOR_PULSE, 0)
+            .map(|_| ())
+    }
+
+    /// Aborts the Bulk OUT transfer with the provided tag, and clears the endpoint halt.
+    pub fn abort_bulk_out(&self, tag: u8) -> Result<()> {
+        let ep = self.bulk_out as u16;
+        let buf = self.request(
+            REQUEST_TYPE_CLASS_ENDPOINT_IN,
+            USBTMC_INITIATE_ABORT_BULK_OUT,
+            tag as u16,
+            ep,
+            2,
+        )?;
+        match status(&buf)? {
+            UsbtmcStatus::Success => (),
+            // the transfer already completed
+            UsbtmcStatus::Failed | UsbtmcStatus::TransferNotInProgress => return Ok(()),
+            status => return Err(Error::class("USBTMC", status)),
+        }
+
+        loop {
+            let buf = self.request(
+                REQUEST_TYPE_CLASS_ENDPOINT_IN,
+                USBTMC_CHECK_ABORT_BULK_OUT_STATUS,
+                0,
+                ep,
+                8,
+            )?;
+            match status(&buf)? {
+                UsbtmcStatus::Pending => thread::sleep(CHECK_STATUS_INTERVAL),
+                UsbtmcStatus::Success => break,
+                status => return Err(Error::class("USBTMC", status)),
+            }
+        }
+
+        self.backend.clear_halt(self.bulk_out as u32)
+    }
+
+    /// Aborts the Bulk IN transfer with the provided tag, draining the pending data.
+    pub fn abort_bulk_in(&self, tag: u8) -> Result<()> {
+        let ep = self.bulk_in as u16;
+        let buf = self.request(
+            REQUEST_TYPE_CLASS_ENDPOINT_IN,
+            USBTMC_INITIATE_ABORT_BULK_IN,
+            tag as u16,
+            ep,
+            2,
+        )?;
+        match status(&buf)? {
+            UsbtmcStatus::Success => (),
+            UsbtmcStatus::Failed | UsbtmcStatus::TransferNotInProgress => return Ok(()),
+            status => return Err(Error::class("USBTMC", status)),
+        }
+
+        loop {
+            // the device keeps the abort pending until the FIFO is read out
+            self.drain_bulk_in();
+            let buf = self.request(
+                REQUEST_TYPE_CLASS_ENDPOINT_IN,
+                USBTMC_CHECK_ABORT_BULK_IN_STATUS,
+                0,
+                ep,
+                8,
+            )?;
+            match status(&buf)? {
+                UsbtmcStatus::Pending => thread::sleep(CHECK_STATUS_INTERVAL),
+                UsbtmcStatus::Success => return Ok(()),
+                status => return Err(Error::class("USBTMC", status)),
+            }
+        }
+    }
+
+    /// Clears the instrument input and output buffers with `INITIATE_CLEAR`.
+    pub fn clear(&self) -> Result<()> {
+        check_status(&self.interface_request(USBTMC_INITIATE_CLEAR, 0)?)?;
+
+        loop {
+            let buf = self.request(
+                REQUEST_TYPE_CLASS_IN,
+                USBTMC_CHECK_CLEAR_STATUS,
+                0,
+                self.iface as u16,
+                2,
+            )?;
+            match status(&buf)? {
+                UsbtmcStatus::Pending => {
+                    if buf.get(1).is_some_and(|b| b & 0x01 != 0) {
+                        self.drain_bulk_in();
+                    } else {
+                        thread::sleep(CHECK_STATUS_INTERVAL);
+                    }
+                }
+                UsbtmcStatus::Success => break,
+                status => return Err(Error::class("USBTMC", status)),
+            }
+        }
+
+        self.backend.clear_halt(self.bulk_out as u32)
+    }
+
+    /// Reads the IEEE 488 status byte with `READ_STATUS_BYTE`.
+    ///
+    /// Devices with an Interrupt IN endpoint return the status byte there.
+    pub fn read_status_byte(&mut self) -> Result<u8> {
+        // tags 2 to 127, 1 is reserved for service requests
+        self.status_tag = self.status_tag % 127 + 1;
+        self.status_tag = self.status_tag.max(2);
+        let tag = self.status_tag;
+
+        let buf = self.request(
+            REQUEST_TYPE_CLASS_IN,
+            USB488_READ_STATUS_BYTE,
+            tag as u16,
+            self.iface as u16,
+            3,
+        )?;
+        check_status(&buf)?;
+
+        if self.interrupt_in.is_none() {
+            return buf
+                .get(2)
+                .copied()
+                .ok_or(Error::InvalidMessage("missing USB488 status byte".into()));
+        }
+
+        loop {
+            match self.read_notification(self.timeout)? {
+                UsbtmcNotification::StatusByte { tag: t, status } if t == tag => return Ok(status),
+                // service requests and stale responses are skipped
+                _ => (),
+            }
+        }
+    }
+
+    /// Waits for the next [UsbtmcNotification] on the Interrupt IN endpoint.
+    pub fn read_notification(&self, timeout: u32) -> Result<UsbtmcNotification> {
+        let ep = self
+            .interrupt_in
+            .ok_or(Error::NotFound("USBTMC Interrupt IN endpoint".into()))?;
+        let mut int = UsbfsBulkTransfer::create(ep as u32, timeout, [0u8; 2]);
+        let len = self.backend.bulk(&mut int)?;
+        UsbtmcNotification::parse(&int.data()[..len])
+    }
+
+    /// Waits for a service request, and returns its status byte.
+    pub fn wait_srq(&self, timeout: u32) -> Result<u8> {
+        loop {
+            if let UsbtmcNotification::Srq(status) = self.read_notification(timeout)? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Asserts or releases Remote Enable with `REN_CONTROL`.
+    pub fn remote_enable(&self, enable: bool) -> Result<()> {
+        check_status(&self.interface_request(USB488_REN_CONTROL, enable as u16)?)
+    }
+
+    /// Returns the instrument to local control with `GO_TO_LOCAL`.
+    pub fn go_to_local(&self) -> Result<()> {
+        check_status(&self.interface_request(USB488_GO_TO_LOCAL, 0)?)
+    }
+
+    /// Disables the instrument front panel with `LOCAL_LOCKOUT`.
+    pub fn local_lockout(&self) -> Result<()> {
+        check_status(&self.interface_request(USB488_LOCAL_LOCKOUT, 0)?)
+    }
+
+    /// Releases the interface, and converts the [Usbtmc] into its [UsbBackend].
+    pub fn close(self) -> Result<B> {
+        self.backend.release_interface(self.iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn next_tag(&mut self) -> u8 {
+        // bTag cycles through 1 to 255, never 0
+        self.tag = self.tag % 255 + 1;
+        self.tag
+    }
+
+    fn header(&self, msg_id: u8, tag: u8, len: usize, attributes: u8, term_char: u8) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(USBTMC_HEADER_LEN + len.next_multiple_of(4));
+        buf.extend_from_slice(&[msg_id, tag, !tag, 0]);
+        buf.extend_from_slice(&(len as u32).to_le_bytes());
+        buf.extend_from_slice(&[attributes, term_char, 0, 0]);
+        buf
+    }
+
+    fn bulk(&self, ep: u8, data: Vec<u8>) -> Result<Vec<u8>> {
+        let mut bulk = UsbfsBulkTransfer::create(ep as u32, self.timeout, data);
+        let len = self.backend.bulk(&mut bulk)?;
+        let mut data = bulk.into_data();
+        data.truncate(len);
+        Ok(data)
+    }
+
+    fn drain_bulk_in(&self) {
+        let mut bulk = UsbfsBulkTransfer::create(
+            self.bulk_in as u32,
+            DEFAULT_TIMEOUT,
+            vec![0u8; self.max_packet_size],
+        );
+        while let Ok(len) = self.backend.bulk(&mut bulk) {
+            if len < self.max_packet_size {
+                break;
+            }
+        }
+    }
+
+    fn interface_request(&self, request: u8, value: u16) -> Result<Vec<u8>> {
+        self.request(REQUEST_TYPE_CLASS_IN, request, value, self.iface as u16, 1)
+    }
+
+    fn request(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let len = class_request(
+            &self.backend,
+            request_type,
+            request,
+            value,
+            index,
+            &mut buf,
+            DEFAULT_TIMEOUT,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Usbtmc<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Usbtmc")
+            .field("iface", &self.iface)
+            .field("protocol", &self.protocol)
+            .field("bulk_in", &self.bulk_in)
+            .field("bulk_out", &self.bulk_out)
+            .field("interrupt_in", &self.interrupt_in)
+            .field("capabilities", &self.capabilities)
+            .field("tag", &self.tag)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+fn is_timeout(err: &Error) -> bool {
+    err.errno() == Some(Errno::ETIMEDOUT as i32)
+}
+
+fn status(buf: &[u8]) -> Result<UsbtmcStatus> {
+    let val = *buf
+        .first()
+        .ok_or(Error::InvalidMessage("empty USBTMC response".into()))?;
+    UsbtmcStatus::create(val).ok_or(Error::InvalidMessage(format!(
+        "invalid USBTMC status: 0x{val:02x}"
+    )))
+}
+
+fn check_status(buf: &[u8]) -> Result<()> {
+    match status(buf)? {
+        UsbtmcStatus::Success => Ok(()),
+        status => Err(Error::class("USBTMC", status)),
+    }
+}
+
+/// Parses a `DEV_DEP_MSG_IN` transfer, and returns its payload and `bmTransferAttributes`.
+fn parse_msg_in(buf: &[u8], tag: u8) -> Result<(&[u8], u8)> {
+    if buf.len() < USBTMC_HEADER_LEN {
+        return Err(Error::InvalidMessage(format!(
+            "USBTMC message too short: {}",
+            buf.len()
+        )));
+    }
+    if buf[0] != USBTMC_MSG_DEV_DEP_MSG_IN || buf[1] != tag || buf[2] != !tag {
+        return Err(Error::InvalidMessage(format!(
+            "unexpected USBTMC message {}, tag: {}, expected tag: {tag}",
+            buf[0], buf[1]
+        )));
+    }
+
+    let len = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+    let end = USBTMC_HEADER_LEN.saturating_add(len).min(buf.len());
+    Ok((&buf[USBTMC_HEADER_LEN..end], buf[8]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+
+    // USB488 interface 0, with Bulk OUT, Bulk IN and Interrupt IN endpoints
+    const DESCRIPTORS: [u8; 57] = [
+        0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x57, 0x09, 0x07, 0x04, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        0x09, 0x02, 0x27, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+        0x09, 0x04, 0x00, 0x00, 0x03, 0xfe, 0x03, 0x01, 0x00, //
+        0x07, 0x05, 0x01, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x82, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x83, 0x03, 0x02, 0x00, 0x01,
+    ];
+
+    fn capabilities() -> Vec<u8> {
+        let mut caps = vec![0u8; USBTMC_CAPABILITIES_LEN];
+        caps[..6].copy_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x04, 0x00]);
+        caps[12..16].copy_from_slice(&[0x00, 0x01, 0x07, 0x0f]);
+        caps
+    }
+
+    fn msg_in(tag: u8, data: &[u8]) -> Vec<u8> {
+        let mut buf = vec![USBTMC_MSG_DEV_DEP_MSG_IN, tag, !tag, 0];
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&[ATTR_EOM, 0, 0, 0]);
+        buf.extend_from_slice(data);
+        buf.resize(buf.len().next_multiple_of(4), 0);
+        buf
+    }
+
+    #[test]
+    fn test_usbtmc() -> Result<()> {
+        let idn = b"ACME,DMM-1,1234,1.0\n";
+
+        // a transfer size past the end of the buffer is cut to the received data
+        let mut long = msg_in(1, b"OK");
+        long[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(parse_msg_in(&long, 1)?, (&b"OK\0\0"[..], ATTR_EOM));
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "usbtmc")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, USBTMC_GET_CAPABILITIES, 0, 0)
+                    .with_response(MockResponse::Data(capabilities())),
+            )
+            .with_data(0x82, msg_in(2, idn))
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, USB488_READ_STATUS_BYTE, 2, 0)
+                    .with_response(MockResponse::Data(vec![0x01, 0x02, 0x00])),
+            )
+            .with_data(0x83, [NOTIFY_SRQ, 0x50])
+            .with_data(0x83, [NOTIFY_STATUS_BYTE | 2, 0x10])
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, USB488_REN_CONTROL, 1, 0)
+                    .with_response(MockResponse::Data(vec![0x01])),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, USB488_GO_TO_LOCAL, 0, 0)
+                    .with_response(MockResponse::Data(vec![0x80])),
+            );
+
+        let mut tmc = Usbtmc::open(dev)?;
+        assert!(tmc.is_usb488());
+        assert!(tmc.capabilities().scpi() && tmc.capabilities().ren_control());
+
+        assert_eq!(tmc.query("*IDN?")?, "ACME,DMM-1,1234,1.0");
+
+        // the pending service request is skipped
+        assert_eq!(tmc.read_status_byte()?, 0x10);
+
+        tmc.remote_enable(true)?;
+        assert_eq!(
+            tmc.go_to_local(),
+            Err(Error::class("USBTMC", UsbtmcStatus::Failed))
+        );
+
+        let dev = tmc.close()?;
+        let written = dev.take_written(0x01);
+        assert_eq!(
+            written[0],
+            [
+                USBTMC_MSG_DEV_DEP_MSG_OUT,
+                1,
+                0xfe,
+                0,
+                6,
+                0,
+                0,
+                0,
+                ATTR_EOM,
+                0,
+                0,
+                0,
+                b'*',
+                b'I',
+                b'D',
+                b'N',
+                b'?',
+                b'\n',
+                0,
+                0
+            ]
+        );
+        assert_eq!(
+            &written[1][..4],
+            [USBTMC_MSG_REQUEST_DEV_DEP_MSG_IN, 2, 0xfd, 0]
+        );
+
+        dev.verify()
+    }
+}
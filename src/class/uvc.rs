@@ -0,0 +1,957 @@
+//! USB Video Class (UVC) camera driver.
+//!
+//! A UVC function pairs a VideoControl interface, exposing the camera, processing and vendor
+//! extension unit controls, with VideoStreaming interfaces. Streaming parameters are agreed
+//! with the device through `PROBE`/`COMMIT` negotiation, after which the driver selects the
+//! Isochronous alternate setting with enough bandwidth, and reassembles frames from the
+//! payloads of its URBs.
+//!
+//! The [descriptors] module parses the class-specific descriptors, and the [payload] module
+//! the payload headers.
+
+use std::fmt;
+use std::time::Duration;
+
+use super::{class_request, REQUEST_TYPE_CLASS_IN, REQUEST_TYPE_CLASS_OUT};
+use crate::{Error, Result, TransferType, UrbId, UsbBackend, UsbfsBulkTransfer, UsbfsSetInterface};
+
+pub mod descriptors;
+pub mod payload;
+
+pub use descriptors::{
+    FrameIntervals, UvcFormat, UvcFormatType, UvcFrame, UvcTerminal, UvcUnit, UvcUnitType,
+    VideoControl, VideoStreaming, GUID_LEN,
+};
+pub use payload::{FrameAssembler, PayloadHeader, VideoFrame};
+
+pub const UVC_CLASS: u8 = 0x0e;
+pub const UVC_SUBCLASS_VIDEOCONTROL: u8 = 0x01;
+pub const UVC_SUBCLASS_VIDEOSTREAMING: u8 = 0x02;
+
+pub const VS_PROBE_CONTROL: u8 = 0x01;
+pub const VS_COMMIT_CONTROL: u8 = 0x02;
+pub const VC_REQUEST_ERROR_CODE_CONTROL: u8 = 0x02;
+
+pub const PU_BACKLIGHT_COMPENSATION_CONTROL: u8 = 0x01;
+pub const PU_BRIGHTNESS_CONTROL: u8 = 0x02;
+pub const PU_CONTRAST_CONTROL: u8 = 0x03;
+pub const PU_GAIN_CONTROL: u8 = 0x04;
+pub const PU_POWER_LINE_FREQUENCY_CONTROL: u8 = 0x05;
+pub const PU_HUE_CONTROL: u8 = 0x06;
+pub const PU_SATURATION_CONTROL: u8 = 0x07;
+pub const PU_SHARPNESS_CONTROL: u8 = 0x08;
+pub const PU_GAMMA_CONTROL: u8 = 0x09;
+pub const PU_WHITE_BALANCE_TEMPERATURE_CONTROL: u8 = 0x0a;
+pub const PU_WHITE_BALANCE_TEMPERATURE_AUTO_CONTROL: u8 = 0x0b;
+pub const PU_WHITE_BALANCE_COMPONENT_CONTROL: u8 = 0x0c;
+pub const PU_WHITE_BALANCE_COMPONENT_AUTO_CONTROL: u8 = 0x0d;
+pub const PU_DIGITAL_MULTIPLIER_CONTROL: u8 = 0x0e;
+pub const PU_DIGITAL_MULTIPLIER_LIMIT_CONTROL: u8 = 0x0f;
+pub const PU_HUE_AUTO_CONTROL: u8 = 0x10;
+pub const PU_ANALOG_VIDEO_STANDARD_CONTROL: u8 = 0x11;
+pub const PU_ANALOG_LOCK_STATUS_CONTROL: u8 = 0x12;
+pub const PU_CONTRAST_AUTO_CONTROL: u8 = 0x13;
+
+/// Length of the probe and commit controls, by UVC release.
+pub const PROBE_COMMIT_LEN_1_0: usize = 26;
+pub const PROBE_COMMIT_LEN_1_1: usize = 34;
+pub const PROBE_COMMIT_LEN_1_5: usize = 48;
+
+// URBs kept in flight while streaming, and Isochronous packets per URB
+const ISO_URBS: usize = 4;
+const ISO_PACKETS: usize = 32;
+
+/// Represents a UVC control request.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UvcRequest {
+    SetCur = 0x01,
+    #[default]
+    GetCur = 0x81,
+    GetMin = 0x82,
+    GetMax = 0x83,
+    GetRes = 0x84,
+    GetLen = 0x85,
+    GetInfo = 0x86,
+    GetDef = 0x87,
+}
+
+impl UvcRequest {
+    /// Creates a new [UvcRequest].
+    pub const fn new() -> Self {
+        Self::GetCur
+    }
+}
+
+impl From<&UvcRequest> for &'static str {
+    fn from(val: &UvcRequest) -> Self {
+        match val {
+            UvcRequest::SetCur => "SET_CUR",
+            UvcRequest::GetCur => "GET_CUR",
+            UvcRequest::GetMin => "GET_MIN",
+            UvcRequest::GetMax => "GET_MAX",
+            UvcRequest::GetRes => "GET_RES",
+            UvcRequest::GetLen => "GET_LEN",
+            UvcRequest::GetInfo => "GET_INFO",
+            UvcRequest::GetDef => "GET_DEF",
+        }
+    }
+}
+
+impl fmt::Display for UvcRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Gets the length of a processing unit control, by selector.
+pub const fn processing_control_len(selector: u8) -> usize {
+    match selector {
+        PU_POWER_LINE_FREQUENCY_CONTROL
+        | PU_WHITE_BALANCE_TEMPERATURE_AUTO_CONTROL
+        | PU_WHITE_BALANCE_COMPONENT_AUTO_CONTROL
+        | PU_HUE_AUTO_CONTROL
+        | PU_ANALOG_VIDEO_STANDARD_CONTROL
+        | PU_ANALOG_LOCK_STATUS_CONTROL
+        | PU_CONTRAST_AUTO_CONTROL => 1,
+        PU_WHITE_BALANCE_COMPONENT_CONTROL => 4,
+        _ => 2,
+    }
+}
+
+/// Represents the video probe and commit controls: the streaming parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeCommit {
+    hint: u16,
+    format_index: u8,
+    frame_index: u8,
+    frame_interval: u32,
+    key_frame_rate: u16,
+    p_frame_rate: u16,
+    comp_quality: u16,
+    comp_window_size: u16,
+    delay: u16,
+    max_video_frame_size: u32,
+    max_payload_transfer_size: u32,
+    clock_frequency: u32,
+    framing_info: u8,
+    preferred_version: u8,
+    min_version: u8,
+    max_version: u8,
+}
+
+impl ProbeCommit {
+    /// Creates a new [ProbeCommit].
+    pub const fn new() -> Self {
+        Self {
+            hint: 0,
+            format_index: 0,
+            frame_index: 0,
+            frame_interval: 0,
+            key_frame_rate: 0,
+            p_frame_rate: 0,
+            comp_quality: 0,
+            comp_window_size: 0,
+            delay: 0,
+            max_video_frame_size: 0,
+            max_payload_transfer_size: 0,
+            clock_frequency: 0,
+            framing_info: 0,
+            preferred_version: 0,
+            min_version: 0,
+            max_version: 0,
+        }
+    }
+
+    /// Creates a new [ProbeCommit] requesting a format, frame size and interval.
+    ///
+    /// The frame interval is hinted as fixed, so the device adjusts the other parameters.
+    pub const fn create(format_index: u8, frame_index: u8, frame_interval: u32) -> Self {
+        let mut probe = Self::new();
+        probe.hint = 0x0001;
+        probe.format_index = format_index;
+        probe.frame_index = frame_index;
+        probe.frame_interval = frame_interval;
+        probe
+    }
+
+    /// Parses a [ProbeCommit], of any UVC release.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < PROBE_COMMIT_LEN_1_0 {
+            return Err(Error::InvalidMessage(format!(
+                "UVC probe control too short: {}",
+                buf.len()
+            )));
+        }
+
+        let u16_at = |off: usize| u16::from_le_bytes([buf[off], buf[off + 1]]);
+        let u32_at =
+            |off: usize| u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+        let ext = buf.len() >= PROBE_COMMIT_LEN_1_1;
+
+        Ok(Self {
+            hint: u16_at(0),
+            format_index: buf[2],
+            frame_index: buf[3],
+            frame_interval: u32_at(4),
+            key_frame_rate: u16_at(8),
+            p_frame_rate: u16_at(10),
+            comp_quality: u16_at(12),
+            comp_window_size: u16_at(14),
+            delay: u16_at(16),
+            max_video_frame_size: u32_at(18),
+            max_payload_transfer_size: u32_at(22),
+            clock_frequency: if ext { u32_at(26) } else { 0 },
+            framing_info: if ext { buf[30] } else { 0 },
+            preferred_version: if ext { buf[31] } else { 0 },
+            min_version: if ext { buf[32] } else { 0 },
+            max_version: if ext { buf[33] } else { 0 },
+        })
+    }
+
+    /// Gets the wire representation of the [ProbeCommit], with the length of the UVC release.
+    pub fn to_bytes(&self, len: usize) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PROBE_COMMIT_LEN_1_5);
+        buf.extend_from_slice(&self.hint.to_le_bytes());
+        buf.extend_from_slice(&[self.format_index, self.frame_index]);
+        buf.extend_from_slice(&self.frame_interval.to_le_bytes());
+        buf.extend_from_slice(&self.key_frame_rate.to_le_bytes());
+        buf.extend_from_slice(&self.p_frame_rate.to_le_bytes());
+        buf.extend_from_slice(&self.comp_quality.to_le_bytes());
+        buf.extend_from_slice(&self.comp_window_size.to_le_bytes());
+        buf.extend_from_slice(&self.delay.to_le_bytes());
+        buf.extend_from_slice(&self.max_video_frame_size.to_le_bytes());
+        buf.extend_from_slice(&self.max_payload_transfer_size.to_le_bytes());
+        buf.extend_from_slice(&self.clock_frequency.to_le_bytes());
+        buf.extend_from_slice(&[
+            self.framing_info,
+            self.preferred_version,
+            self.min_version,
+            self.max_version,
+        ]);
+        // UVC 1.5 fields, left for the device to fill
+        buf.resize(len, 0);
+        buf
+    }
+
+    /// Gets the `bmHint` bitmap of the parameters to keep fixed.
+    pub const fn hint(&self) -> u16 {
+        self.hint
+    }
+
+    /// Builder function that sets the `bmHint` bitmap.
+    pub fn with_hint(mut self, hint: u16) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    /// Gets the format index.
+    pub const fn format_index(&self) -> u8 {
+        self.format_index
+    }
+
+    /// Gets the frame index.
+    pub const fn frame_index(&self) -> u8 {
+        self.frame_index
+    }
+
+    /// Gets the frame interval, in 100 ns units.
+    pub const fn frame_interval(&self) -> u32 {
+        self.frame_interval
+    }
+
+    /// Gets the key frame rate of compressed formats.
+    pub const fn key_frame_rate(&self) -> u16 {
+        self.key_frame_rate
+    }
+
+    /// Gets the compression quality, from 0 to 10000.
+    pub const fn comp_quality(&self) -> u16 {
+        self.comp_quality
+    }
+
+    /// Builder function that sets the compression quality.
+    pub fn with_comp_quality(mut self, comp_quality: u16) -> Self {
+        self.comp_quality = comp_quality;
+        self
+    }
+
+    /// Gets the internal latency of the device, in milliseconds.
+    pub const fn delay(&self) -> u16 {
+        self.delay
+    }
+
+    /// Gets the largest video frame, in bytes.
+    pub const fn max_video_frame_size(&self) -> u32 {
+        self.max_video_frame_size
+    }
+
+    /// Gets the largest payload per transfer, selecting the bandwidth to reserve.
+    pub const fn max_payload_transfer_size(&self) -> u32 {
+        self.max_payload_transfer_size
+    }
+
+    /// Gets the device clock frequency, in Hz.
+    pub const fn clock_frequency(&self) -> u32 {
+        self.clock_frequency
+    }
+
+    /// Gets the `bmFramingInfo` bitmap.
+    pub const fn framing_info(&self) -> u8 {
+        self.framing_info
+    }
+}
+
+impl fmt::Display for ProbeCommit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""hint": {}, "#, self.hint)?;
+        write!(f, r#""format_index": {}, "#, self.format_index)?;
+        write!(f, r#""frame_index": {}, "#, self.frame_index)?;
+        write!(f, r#""frame_interval": {}, "#, self.frame_interval)?;
+        write!(f, r#""comp_quality": {}, "#, self.comp_quality)?;
+        write!(
+            f,
+            r#""max_video_frame_size": {}, "#,
+            self.max_video_frame_size
+        )?;
+        write!(
+            f,
+            r#""max_payload_transfer_size": {}"#,
+            self.max_payload_transfer_size
+        )?;
+        write!(f, "}}")
+    }
+}
+
+/// Streaming alternate setting of the VideoStreaming interface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StreamingAlt {
+    alt: u8,
+    ep: u8,
+    packet_size: usize,
+}
+
+/// UVC camera over a [UsbBackend].
+pub struct Uvc<B: UsbBackend> {
+    backend: B,
+    vc_iface: u8,
+    vs_iface: u8,
+    control: VideoControl,
+    streaming: VideoStreaming,
+    alts: Vec<StreamingAlt>,
+    bulk_ep: Option<u8>,
+    probe_len: usize,
+    committed: Option<ProbeCommit>,
+    active: Option<StreamingAlt>,
+    urbs: Vec<UrbId>,
+    assembler: FrameAssembler,
+    timeout: u32,
+}
+
+impl<B: UsbBackend> Uvc<B> {
+    /// Opens the first video function of the active configuration, streaming on its first
+    /// VideoStreaming interface.
+    ///
+    /// Claims the VideoControl and VideoStreaming interfaces, detaching the `uvcvideo` kernel
+    /// driver.
+    pub fn open(backend: B) -> Result<Self> {
+        let config = super::active_config(&backend)?;
+        let vc = config
+            .interfaces()
+            .iter()
+            .find(|i| i.class() == UVC_CLASS && i.subclass() == UVC_SUBCLASS_VIDEOCONTROL)
+            .ok_or(Error::NotFound("UVC VideoControl interface".into()))?;
+        let control = VideoControl::parse(vc.extra())?;
+
+        let vs_iface = *control
+            .streaming_interfaces()
+            .first()
+            .ok_or(Error::NotFound("UVC VideoStreaming interface".into()))?;
+        let vs = config
+            .alt_settings(vs_iface)
+            .find(|i| i.alternate_setting() == 0)
+            .ok_or(Error::NotFound(format!(
+                "UVC VideoStreaming interface {vs_iface}"
+            )))?;
+        let streaming = VideoStreaming::parse(vs.extra())?;
+
+        // Bulk devices stream on the default setting, Isochronous ones on the others
+        let bulk_ep = super::find_endpoint(vs, TransferType::Bulk, true).map(|e| e.address());
+        let mut alts: Vec<StreamingAlt> = config
+            .alt_settings(vs_iface)
+            .filter_map(|i| {
+                let ep = super::find_endpoint(i, TransferType::Isochronous, true)?;
+                let packet_size = ep.packet_size() as usize * ep.transactions() as usize;
+                Some(StreamingAlt {
+                    alt: i.alternate_setting(),
+                    ep: ep.address(),
+                    packet_size,
+                })
+            })
+            .collect();
+        alts.sort_by_key(|a| a.packet_size);
+
+        if bulk_ep.is_none() && alts.is_empty() {
+            return Err(Error::NotFound("UVC streaming endpoint".into()));
+        }
+
+        let probe_len = match control.version() {
+            v if v < 0x0110 => PROBE_COMMIT_LEN_1_0,
+            v if v < 0x0150 => PROBE_COMMIT_LEN_1_1,
+            _ => PROBE_COMMIT_LEN_1_5,
+        };
+
+        let uvc = Self {
+            vc_iface: vc.number(),
+            vs_iface,
+            control,
+            streaming,
+            alts,
+            bulk_ep,
+            probe_len,
+            committed: None,
+            active: None,
+            urbs: Vec::new(),
+            assembler: FrameAssembler::new(),
+            timeout: super::DEFAULT_TIMEOUT,
+            backend,
+        };
+
+        super::claim_detaching(&uvc.backend, uvc.vc_iface)?;
+        super::claim_detaching(&uvc.backend, uvc.vs_iface)?;
+        uvc.set_alt_setting(0)?;
+
+        Ok(uvc)
+    }
+
+    /// Gets a reference to the [UsbBackend].
+    pub const fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Gets the VideoControl interface number.
+    pub const fn control_interface(&self) -> u8 {
+        self.vc_iface
+    }
+
+    /// Gets the VideoStreaming interface number.
+    pub const fn streaming_interface(&self) -> u8 {
+        self.vs_iface
+    }
+
+    /// Gets the [VideoControl] descriptors: terminals and units.
+    pub const fn video_control(&self) -> &VideoControl {
+        &self.control
+    }
+
+    /// Gets the [VideoStreaming] descriptors: formats and frames.
+    pub const fn video_streaming(&self) -> &VideoStreaming {
+        &self.streaming
+    }
+
+    /// Gets the committed [ProbeCommit], if streaming parameters were negotiated.
+    pub const fn committed(&self) -> Option<&ProbeCommit> {
+        self.committed.as_ref()
+    }
+
+    /// Gets the active streaming alternate setting, if streaming.
+    pub fn alt_setting(&self) -> Option<u8> {
+        self.active.map(|a| a.alt)
+    }
+
+    /// Gets whether the device is streaming.
+    pub fn is_streaming(&self) -> bool {
+        self.active.is_some() || (self.bulk_ep.is_some() && self.committed.is_some())
+    }
+
+    /// Gets the transfer timeout, in milliseconds.
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the transfer timeout, in milliseconds.
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the transfer timeout, in milliseconds.
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Sends a `PROBE` request, and returns the parameters the device settled on.
+    pub fn probe(&self, probe: &ProbeCommit) -> Result<ProbeCommit> {
+        let mut buf = probe.to_bytes(self.probe_len);
+        self.streaming_request(
+            REQUEST_TYPE_CLASS_OUT,
+            UvcRequest::SetCur,
+            VS_PROBE_CONTROL,
+            &mut buf,
+        )?;
+
+        let mut buf = vec![0u8; self.probe_len];
+        let len = self.streaming_request(
+            REQUEST_TYPE_CLASS_IN,
+            UvcRequest::GetCur,
+            VS_PROBE_CONTROL,
+            &mut buf,
+        )?;
+        ProbeCommit::parse(&buf[..len])
+    }
+
+    /// Commits the streaming parameters returned by [probe](Self::probe).
+    pub fn commit(&mut self, probe: &ProbeCommit) -> Result<()> {
+        let mut buf = probe.to_bytes(self.probe_len);
+        self.streaming_request(
+            REQUEST_TYPE_CLASS_OUT,
+            UvcRequest::SetCur,
+            VS_COMMIT_CONTROL,
+            &mut buf,
+        )?;
+        self.committed = Some(*probe);
+        Ok(())
+    }
+
+    /// Negotiates a format, frame size and interval, with `PROBE` and `COMMIT`.
+    ///
+    /// A frame interval of `0` selects the default interval of the frame.
+    pub fn negotiate(
+        &mut self,
+        format_index: u8,
+        frame_index: u8,
+        frame_interval: u32,
+    ) -> Result<ProbeCommit> {
+        let frame = self
+            .streaming
+            .format(format_index)
+            .and_then(|f| f.frame(frame_index))
+            .ok_or(Error::InvalidArgument(format!(
+                "UVC format {format_index}, frame {frame_index}"
+            )))?;
+        let frame_interval = match frame_interval {
+            0 => frame.default_interval(),
+            i if frame.intervals().supports(i) => i,
+            i => return Err(Error::InvalidArgument(format!("UVC frame interval: {i}"))),
+        };
+
+        let probe = self.probe(&ProbeCommit::create(
+            format_index,
+            frame_index,
+            frame_interval,
+        ))?;
+        if probe.format_index() != format_index || probe.frame_index() != frame_index {
+            return Err(Error::InvalidMessage(format!(
+                "UVC probe returned format {}, frame {}",
+                probe.format_index(),
+                probe.frame_index()
+            )));
+        }
+        self.commit(&probe)?;
+
+        Ok(probe)
+    }
+
+    /// Negotiates the streaming parameters, and starts streaming.
+    ///
+    /// Isochronous devices stream on the alternate setting with the least bandwidth covering
+    /// the payload size, with URBs kept in flight until [stop_streaming](Self::stop_streaming).
+    pub fn start_streaming(
+        &mut self,
+        format_index: u8,
+        frame_index: u8,
+        frame_interval: u32,
+    ) -> Result<ProbeCommit> {
+        if self.is_streaming() {
+            self.stop_streaming()?;
+        }
+
+        let probe = self.negotiate(format_index, frame_index, frame_interval)?;
+        self.assembler =
+            FrameAssembler::new().with_max_frame_size(probe.max_video_frame_size() as usize);
+
+        if self.bulk_ep.is_some() {
+            return Ok(probe);
+        }
+
+        let payload = probe.max_payload_transfer_size() as usize;
+        let alt = *self
+            .alts
+            .iter()
+            .find(|a| a.packet_size >= payload)
+            .ok_or(Error::NotFound(format!(
+                "UVC alternate setting for {payload} bytes per interval"
+            )))?;
+
+        self.set_alt_setting(alt.alt)?;
+        self.active = Some(alt);
+        for _ in 0..ISO_URBS {
+            self.submit_iso_urb(&alt)?;
+        }
+
+        Ok(probe)
+    }
+
+    /// Reads the next complete [VideoFrame], skipping frames with errors.
+    pub fn read_frame(&mut self) -> Result<VideoFrame> {
+        if !self.is_streaming() {
+            return Err(Error::InvalidArgument("UVC device is not streaming".into()));
+        }
+
+        loop {
+            while let Some(frame) = self.assembler.next_frame() {
+                if !frame.error() {
+                    return Ok(frame);
+                }
+            }
+
+            match (self.active, self.bulk_ep) {
+                (Some(alt), _) => self.reap_iso_urb(&alt)?,
+                (None, Some(ep)) => self.read_bulk_payload(ep)?,
+                (None, None) => unreachable!("streaming without an endpoint"),
+            }
+        }
+    }
+
+    /// Stops streaming: cancels the pending URBs, and selects the zero-bandwidth setting.
+    pub fn stop_streaming(&mut self) -> Result<()> {
+        for id in self.urbs.iter() {
+            // URBs may complete while being discarded
+            let _ = self.backend.discard_urb(*id);
+        }
+        while !self.urbs.is_empty() {
+            match self
+                .backend
+                .reap_urb(Some(Duration::from_millis(self.timeout as u64)))
+            {
+                Ok((id, _)) => self.urbs.retain(|u| *u != id),
+                Err(Error::Disconnected) => return Err(Error::Disconnected),
+                Err(_) => break,
+            }
+        }
+        self.urbs.clear();
+
+        self.active = None;
+        self.committed = None;
+        self.assembler.reset();
+        self.set_alt_setting(0)
+    }
+
+    /// Sends a control request to a unit or terminal, and returns the read data.
+    pub fn get_control(
+        &self,
+        unit: u8,
+        selector: u8,
+        request: UvcRequest,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let len = class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_IN,
+            request as u8,
+            (selector as u16) << 8,
+            ((unit as u16) << 8) | self.vc_iface as u16,
+            &mut buf,
+            self.timeout,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Sets the current value of a unit or terminal control, with `SET_CUR`.
+    pub fn set_control(&self, unit: u8, selector: u8, data: &[u8]) -> Result<()> {
+        class_request(
+            &self.backend,
+            REQUEST_TYPE_CLASS_OUT,
+            UvcRequest::SetCur as u8,
+            (selector as u16) << 8,
+            ((unit as u16) << 8) | self.vc_iface as u16,
+            &mut data.to_vec(),
+            self.timeout,
+        )
+        .map(|_| ())
+    }
+
+    /// Gets the length of a control, with `GET_LEN`.
+    pub fn control_len(&self, unit: u8, selector: u8) -> Result<u16> {
+        let buf = self.get_control(unit, selector, UvcRequest::GetLen, 2)?;
+        match buf[..] {
+            [lo, hi] => Ok(u16::from_le_bytes([lo, hi])),
+            _ => Err(Error::InvalidMessage(format!(
+                "UVC control length too short: {}",
+                buf.len()
+            ))),
+        }
+    }
+
+    /// Gets the capabilities of a control, with `GET_INFO`: bit 0 for `GET`, bit 1 for `SET`.
+    pub fn control_info(&self, unit: u8, selector: u8) -> Result<u8> {
+        self.get_control(unit, selector, UvcRequest::GetInfo, 1)?
+            .first()
+            .copied()
+            .ok_or(Error::InvalidMessage("empty UVC control info".into()))
+    }
+
+    /// Gets the error code of the last failed control request, with `VC_REQUEST_ERROR_CODE`.
+    pub fn request_error_code(&self) -> Result<u8> {
+        self.get_control(0, VC_REQUEST_ERROR_CODE_CONTROL, UvcRequest::GetCur, 1)?
+            .first()
+            .copied()
+            .ok_or(Error::InvalidMessage("empty UVC request error code".into()))
+    }
+
+    /// Sends a request for a processing unit control, e.g. [PU_BRIGHTNESS_CONTROL].
+    pub fn processing_control(&self, selector: u8, request: UvcRequest) -> Result<Vec<u8>> {
+        let unit = self.processing_unit_id()?;
+        self.get_control(unit, selector, request, processing_control_len(selector))
+    }
+
+    /// Sets the current value of a processing unit control.
+    pub fn set_processing_control(&self, selector: u8, data: &[u8]) -> Result<()> {
+        self.set_control(self.processing_unit_id()?, selector, data)
+    }
+
+    /// Sends a request for a control of the extension unit with the provided vendor GUID.
+    ///
+    /// The control length is read with `GET_LEN`, as only the vendor knows it.
+    pub fn extension_control(
+        &self,
+        guid: &[u8; GUID_LEN],
+        selector: u8,
+        request: UvcRequest,
+    ) -> Result<Vec<u8>> {
+        let unit = self.extension_unit_id(guid)?;
+        let len = match request {
+            UvcRequest::GetLen => 2,
+            UvcRequest::GetInfo => 1,
+            _ => self.control_len(unit, selector)? as usize,
+        };
+        self.get_control(unit, selector, request, len)
+    }
+
+    /// Sets the current value of an extension unit control.
+    pub fn set_extension_control(
+        &self,
+        guid: &[u8; GUID_LEN],
+        selector: u8,
+        data: &[u8],
+    ) -> Result<()> {
+        self.set_control(self.extension_unit_id(guid)?, selector, data)
+    }
+
+    /// Stops streaming, releases the interfaces, and converts the [Uvc] into its [UsbBackend].
+    pub fn close(mut self) -> Result<B> {
+        if self.is_streaming() {
+            self.stop_streaming()?;
+        }
+        self.backend.release_interface(self.vs_iface as u32)?;
+        self.backend.release_interface(self.vc_iface as u32)?;
+        Ok(self.backend)
+    }
+
+    fn processing_unit_id(&self) -> Result<u8> {
+        self.control
+            .processing_unit()
+            .map(|u| u.id())
+            .ok_or(Error::NotFound("UVC processing unit".into()))
+    }
+
+    fn extension_unit_id(&self, guid: &[u8; GUID_LEN]) -> Result<u8> {
+        self.control
+            .extension_unit(guid)
+            .map(|u| u.id())
+            .ok_or(Error::NotFound(format!("UVC extension unit {guid:02x?}")))
+    }
+
+    fn set_alt_setting(&self, alt: u8) -> Result<()> {
+        self.backend
+            .set_interface(&UsbfsSetInterface::create(self.vs_iface as u32, alt as u32))
+    }
+
+    fn streaming_request(
+        &self,
+        request_type: u8,
+        request: UvcRequest,
+        selector: u8,
+        data: &mut [u8],
+    ) -> Result<usize> {
+        class_request(
+            &self.backend,
+            request_type,
+            request as u8,
+            (selector as u16) << 8,
+            self.vs_iface as u16,
+            data,
+            self.timeout,
+        )
+    }
+
+    fn submit_iso_urb(&mut self, alt: &StreamingAlt) -> Result<()> {
+        let urb = super::iso_urb(
+            alt.ep,
+            vec![0u8; alt.packet_size * ISO_PACKETS],
+            &[alt.packet_size; ISO_PACKETS],
+        );
+        self.urbs.push(self.backend.submit_urb(urb)?);
+        Ok(())
+    }
+
+    fn reap_iso_urb(&mut self, alt: &StreamingAlt) -> Result<()> {
+        let timeout = Duration::from_millis(self.timeout as u64);
+        let (id, urb) = self.backend.reap_urb(Some(timeout))?;
+        self.urbs.retain(|u| *u != id);
+
+        for packet in super::iso_packets(&urb)? {
+            match packet {
+                Some(data) => self.assembler.push(data),
+                None => self.assembler.set_error(),
+            }
+        }
+
+        self.submit_iso_urb(alt)
+    }
+
+    fn read_bulk_payload(&mut self, ep: u8) -> Result<()> {
+        let len = self
+            .committed
+            .map_or(0, |p| p.max_payload_transfer_size() as usize)
+            .max(512);
+        let mut bulk = UsbfsBulkTransfer::create(ep as u32, self.timeout, vec![0u8; len]);
+        let len = self.backend.bulk(&mut bulk)?;
+        self.assembler.push(&bulk.data()[..len]);
+        Ok(())
+    }
+}
+
+impl<B: UsbBackend> fmt::Debug for Uvc<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Uvc")
+            .field("vc_iface", &self.vc_iface)
+            .field("vs_iface", &self.vs_iface)
+            .field("alts", &self.alts)
+            .field("bulk_ep", &self.bulk_ep)
+            .field("committed", &self.committed)
+            .field("active", &self.active)
+            .field("urbs", &self.urbs.len())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MockControl, MockDevice, MockResponse};
+    use nix::errno::Errno;
+
+    // VideoControl interface 0, with a camera, processing unit 2 and extension unit 3, and
+    // VideoStreaming interface 1 with a YUY2 640x480 frame, and two Isochronous settings
+    const DESCRIPTORS: [u8; 230] = [
+        0x12, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x6d, 0x04, 0x25, 0x08, 0x00, 0x01, 0x00,
+        0x02, 0x00, 0x01, //
+        0x09, 0x02, 0xd4, 0x00, 0x02, 0x01, 0x00, 0x80, 0xfa, //
+        0x09, 0x04, 0x00, 0x00, 0x00, 0x0e, 0x01, 0x00, 0x00, //
+        0x0d, 0x24, 0x01, 0x10, 0x01, 0x4d, 0x00, 0x80, 0xc3, 0xc9, 0x01, 0x01, 0x01, //
+        0x12, 0x24, 0x02, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03,
+        0x0a, 0x00, 0x00, //
+        0x0b, 0x24, 0x05, 0x02, 0x01, 0x00, 0x40, 0x02, 0x7f, 0x15, 0x00, //
+        0x1b, 0x24, 0x06, 0x03, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,
+        0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x02, 0x01, 0x02, 0x02, 0x03, 0x00, 0x00, //
+        0x09, 0x24, 0x03, 0x04, 0x01, 0x01, 0x00, 0x03, 0x00, //
+        0x09, 0x04, 0x01, 0x00, 0x00, 0x0e, 0x02, 0x00, 0x00, //
+        0x0e, 0x24, 0x01, 0x01, 0x4b, 0x00, 0x81, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00,
+        //
+        0x1b, 0x24, 0x04, 0x01, 0x01, b'Y', b'U', b'Y', b'2', 0x00, 0x00, 0x10, 0x00, 0x80, 0x00,
+        0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, //
+        0x22, 0x24, 0x05, 0x01, 0x00, 0x80, 0x02, 0xe0, 0x01, 0x00, 0x00, 0x77, 0x01, 0x00, 0x00,
+        0xca, 0x08, 0x00, 0x60, 0x09, 0x00, 0x15, 0x16, 0x05, 0x00, 0x02, 0x15, 0x16, 0x05, 0x00,
+        0x2a, 0x2c, 0x0a, 0x00, //
+        0x09, 0x04, 0x01, 0x01, 0x01, 0x0e, 0x02, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x05, 0x00, 0x04, 0x01, //
+        0x09, 0x04, 0x01, 0x02, 0x01, 0x0e, 0x02, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x05, 0x00, 0x14, 0x01,
+    ];
+
+    fn probe(max_payload: u32) -> Vec<u8> {
+        let mut probe = ProbeCommit::create(1, 1, 333_333).to_bytes(PROBE_COMMIT_LEN_1_1);
+        probe[18..22].copy_from_slice(&614_400u32.to_le_bytes());
+        probe[22..26].copy_from_slice(&max_payload.to_le_bytes());
+        probe
+    }
+
+    #[test]
+    fn test_uvc() -> Result<()> {
+        let guid: [u8; GUID_LEN] = std::array::from_fn(|i| i as u8 + 1);
+
+        let dev = MockDevice::new()
+            .with_descriptors(DESCRIPTORS)
+            .with_configuration(1)
+            .with_driver(0, "uvcvideo")
+            .with_driver(1, "uvcvideo")
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, 0x01, 0x0100, 1)
+                    .with_data(ProbeCommit::create(1, 1, 333_333).to_bytes(PROBE_COMMIT_LEN_1_1)),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, 0x81, 0x0100, 1)
+                    .with_response(MockResponse::Data(probe(2048))),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, 0x01, 0x0200, 1).with_data(probe(2048)),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, 0x81, 0x0200, 0x0200)
+                    .with_response(MockResponse::Data(vec![0x80, 0x00])),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, 0x85, 0x0100, 0x0300)
+                    .with_response(MockResponse::Data(vec![0x04, 0x00])),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_IN, 0x81, 0x0100, 0x0300)
+                    .with_response(MockResponse::Data(vec![1, 2, 3, 4])),
+            )
+            .with_control(
+                MockControl::create(REQUEST_TYPE_CLASS_OUT, 0x01, 0x0100, 0x0300)
+                    .with_data([4, 3, 2, 1]),
+            );
+
+        // a frame with a lost packet, one frame across two packets, then the start of the next
+        dev.push_data(0x81, [0x02, 0x80, 0xaa]);
+        dev.push_response(0x81, MockResponse::Errno(Errno::EPROTO as i32));
+        dev.push_data(0x81, [0x02, 0x82, 0xbb]);
+        dev.push_data(0x81, [0x02, 0x81, 0x10, 0x20]);
+        dev.push_data(0x81, []);
+        dev.push_data(0x81, [0x02, 0x83, 0x30]);
+        dev.push_data(0x81, [0x02, 0x80, 0x40]);
+
+        let mut uvc = Uvc::open(dev)?;
+        assert_eq!(uvc.streaming_interface(), 1);
+        let (format, frame) = uvc.video_streaming().find_frame("YUY2", 640, 480).unwrap();
+        let (format, frame) = (format.index(), frame.index());
+
+        // 2048 bytes per interval only fit the high-bandwidth setting
+        let probe = uvc.start_streaming(format, frame, 0)?;
+        assert_eq!(probe.max_video_frame_size(), 614_400);
+        assert_eq!(uvc.alt_setting(), Some(2));
+        assert_eq!(uvc.backend().alt_setting(1), 2);
+
+        assert_eq!(uvc.read_frame()?.data(), [0x10, 0x20, 0x30]);
+        uvc.stop_streaming()?;
+        assert_eq!(uvc.backend().alt_setting(1), 0);
+
+        let brightness = uvc.processing_control(PU_BRIGHTNESS_CONTROL, UvcRequest::GetCur)?;
+        assert_eq!(i16::from_le_bytes([brightness[0], brightness[1]]), 128);
+        assert_eq!(
+            uvc.extension_control(&guid, 1, UvcRequest::GetCur)?,
+            [1, 2, 3, 4]
+        );
+        uvc.set_extension_control(&guid, 1, &[4, 3, 2, 1])?;
+
+        uvc.close()?.verify()
+    }
+}
@@ -0,0 +1,965 @@
+//! UVC class-specific VideoControl and VideoStreaming descriptors.
+//!
+//! The VideoControl interface describes the function topology: terminals, where video enters
+//! or leaves the function, and units processing it in between. Each VideoStreaming interface
+//! lists its formats, each followed by the frame sizes and intervals it supports.
+
+use std::fmt;
+
+use crate::class::cdc::DESCRIPTOR_TYPE_CS_INTERFACE;
+use crate::descriptor::{read_u16, DescriptorIter};
+use crate::{Error, Result};
+
+pub const VC_HEADER: u8 = 0x01;
+pub const VC_INPUT_TERMINAL: u8 = 0x02;
+pub const VC_OUTPUT_TERMINAL: u8 = 0x03;
+pub const VC_SELECTOR_UNIT: u8 = 0x04;
+pub const VC_PROCESSING_UNIT: u8 = 0x05;
+pub const VC_EXTENSION_UNIT: u8 = 0x06;
+pub const VC_ENCODING_UNIT: u8 = 0x07;
+
+pub const VS_INPUT_HEADER: u8 = 0x01;
+pub const VS_FORMAT_UNCOMPRESSED: u8 = 0x04;
+pub const VS_FRAME_UNCOMPRESSED: u8 = 0x05;
+pub const VS_FORMAT_MJPEG: u8 = 0x06;
+pub const VS_FRAME_MJPEG: u8 = 0x07;
+pub const VS_FORMAT_FRAME_BASED: u8 = 0x10;
+pub const VS_FRAME_FRAME_BASED: u8 = 0x11;
+
+/// Terminal type of camera sensors.
+pub const ITT_CAMERA: u16 = 0x0201;
+
+/// Length of a format GUID.
+pub const GUID_LEN: usize = 16;
+
+fn u32_at(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+fn class_descriptors(extra: &[u8]) -> impl Iterator<Item = &[u8]> {
+    DescriptorIter::new(extra).filter(|d| d.len() >= 3 && d[1] == DESCRIPTOR_TYPE_CS_INTERFACE)
+}
+
+fn too_short(name: &str, desc: &[u8]) -> Error {
+    Error::InvalidDescriptor(format!("UVC {name} too short: {}", desc.len()))
+}
+
+/// Represents a VideoControl input or output terminal.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvcTerminal {
+    id: u8,
+    terminal_type: u16,
+    input: bool,
+    assoc_terminal: u8,
+    source_id: u8,
+    controls: Vec<u8>,
+}
+
+impl UvcTerminal {
+    /// Creates a new [UvcTerminal].
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            terminal_type: 0,
+            input: false,
+            assoc_terminal: 0,
+            source_id: 0,
+            controls: Vec::new(),
+        }
+    }
+
+    fn parse(desc: &[u8]) -> Result<Self> {
+        let input = desc[2] == VC_INPUT_TERMINAL;
+        let min_len = if input { 8 } else { 9 };
+        if desc.len() < min_len {
+            return Err(too_short("terminal", desc));
+        }
+
+        let terminal_type = read_u16(desc, 4);
+        let controls = match desc.get(14) {
+            // camera terminals list their controls after the focal lengths
+            Some(&size) if input && terminal_type == ITT_CAMERA => desc
+                .get(15..15 + size as usize)
+                .ok_or(too_short("camera terminal", desc))?
+                .to_vec(),
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            id: desc[3],
+            terminal_type,
+            input,
+            assoc_terminal: desc[6],
+            source_id: if input { 0 } else { desc[7] },
+            controls,
+        })
+    }
+
+    /// Gets the terminal ID.
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Gets the terminal type, e.g. [ITT_CAMERA].
+    pub const fn terminal_type(&self) -> u16 {
+        self.terminal_type
+    }
+
+    /// Gets whether video enters the function through the terminal.
+    pub const fn is_input(&self) -> bool {
+        self.input
+    }
+
+    /// Gets the ID of the associated terminal, `0` for none.
+    pub const fn assoc_terminal(&self) -> u8 {
+        self.assoc_terminal
+    }
+
+    /// Gets the ID of the unit or terminal feeding an output terminal.
+    pub const fn source_id(&self) -> u8 {
+        self.source_id
+    }
+
+    /// Gets the `bmControls` bitmap of camera terminals.
+    pub fn controls(&self) -> &[u8] {
+        self.controls.as_ref()
+    }
+}
+
+impl fmt::Display for UvcTerminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""id": {}, "#, self.id)?;
+        write!(f, r#""terminal_type": {}, "#, self.terminal_type)?;
+        write!(f, r#""input": {}, "#, self.input)?;
+        write!(f, r#""assoc_terminal": {}, "#, self.assoc_terminal)?;
+        write!(f, r#""source_id": {}, "#, self.source_id)?;
+        write!(f, r#""controls": {:?}"#, self.controls)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the kind of a VideoControl unit.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UvcUnitType {
+    #[default]
+    Selector = VC_SELECTOR_UNIT,
+    Processing = VC_PROCESSING_UNIT,
+    Extension = VC_EXTENSION_UNIT,
+    Encoding = VC_ENCODING_UNIT,
+}
+
+impl UvcUnitType {
+    /// Creates a new [UvcUnitType].
+    pub const fn new() -> Self {
+        Self::Selector
+    }
+
+    /// Creates a new [UvcUnitType] from its descriptor subtype.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            VC_SELECTOR_UNIT => Some(Self::Selector),
+            VC_PROCESSING_UNIT => Some(Self::Processing),
+            VC_EXTENSION_UNIT => Some(Self::Extension),
+            VC_ENCODING_UNIT => Some(Self::Encoding),
+            _ => None,
+        }
+    }
+}
+
+impl From<&UvcUnitType> for &'static str {
+    fn from(val: &UvcUnitType) -> Self {
+        match val {
+            UvcUnitType::Selector => "selector",
+            UvcUnitType::Processing => "processing",
+            UvcUnitType::Extension => "extension",
+            UvcUnitType::Encoding => "encoding",
+        }
+    }
+}
+
+impl fmt::Display for UvcUnitType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a VideoControl unit.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvcUnit {
+    id: u8,
+    unit_type: UvcUnitType,
+    sources: Vec<u8>,
+    controls: Vec<u8>,
+    guid: [u8; GUID_LEN],
+    num_controls: u8,
+}
+
+impl UvcUnit {
+    /// Creates a new [UvcUnit].
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            unit_type: UvcUnitType::new(),
+            sources: Vec::new(),
+            controls: Vec::new(),
+            guid: [0u8; GUID_LEN],
+            num_controls: 0,
+        }
+    }
+
+    fn parse(desc: &[u8], unit_type: UvcUnitType) -> Result<Self> {
+        let field = |range: std::ops::Range<usize>| {
+            desc.get(range)
+                .map(|d| d.to_vec())
+                .ok_or(too_short("unit", desc))
+        };
+        let at = |off: usize| desc.get(off).copied().ok_or(too_short("unit", desc));
+
+        let mut unit = Self {
+            id: at(3)?,
+            unit_type,
+            ..Self::new()
+        };
+
+        match unit_type {
+            UvcUnitType::Selector => {
+                let pins = at(4)? as usize;
+                unit.sources = field(5..5 + pins)?;
+            }
+            UvcUnitType::Processing => {
+                unit.sources = vec![at(4)?];
+                let size = at(7)? as usize;
+                unit.controls = field(8..8 + size)?;
+            }
+            UvcUnitType::Extension => {
+                unit.guid.copy_from_slice(&field(4..20)?);
+                unit.num_controls = at(20)?;
+                let pins = at(21)? as usize;
+                unit.sources = field(22..22 + pins)?;
+                let size = at(22 + pins)? as usize;
+                unit.controls = field(23 + pins..23 + pins + size)?;
+            }
+            UvcUnitType::Encoding => {
+                unit.sources = vec![at(4)?];
+                let size = at(6)? as usize;
+                unit.controls = field(7..7 + size)?;
+            }
+        }
+
+        Ok(unit)
+    }
+
+    /// Gets the unit ID.
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Gets the [UvcUnitType].
+    pub const fn unit_type(&self) -> UvcUnitType {
+        self.unit_type
+    }
+
+    /// Gets the IDs of the units or terminals feeding the unit.
+    pub fn sources(&self) -> &[u8] {
+        self.sources.as_ref()
+    }
+
+    /// Gets the `bmControls` bitmap, bit `n` set if control selector `n + 1` is supported.
+    pub fn controls(&self) -> &[u8] {
+        self.controls.as_ref()
+    }
+
+    /// Gets whether the control selector is supported.
+    pub fn has_control(&self, selector: u8) -> bool {
+        let bit = selector.wrapping_sub(1) as usize;
+        self.controls
+            .get(bit / 8)
+            .is_some_and(|b| b & (1 << (bit % 8)) != 0)
+    }
+
+    /// Gets the vendor GUID of extension units.
+    pub const fn guid(&self) -> &[u8; GUID_LEN] {
+        &self.guid
+    }
+
+    /// Gets the number of controls of extension units.
+    pub const fn num_controls(&self) -> u8 {
+        self.num_controls
+    }
+}
+
+impl fmt::Display for UvcUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""id": {}, "#, self.id)?;
+        write!(f, r#""unit_type": {}, "#, self.unit_type)?;
+        write!(f, r#""sources": {:?}, "#, self.sources)?;
+        write!(f, r#""controls": {:?}, "#, self.controls)?;
+        write!(f, r#""guid": {:?}, "#, self.guid)?;
+        write!(f, r#""num_controls": {}"#, self.num_controls)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the class-specific descriptors of the VideoControl interface.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VideoControl {
+    version: u16,
+    clock_frequency: u32,
+    streaming_interfaces: Vec<u8>,
+    terminals: Vec<UvcTerminal>,
+    units: Vec<UvcUnit>,
+}
+
+impl VideoControl {
+    /// Creates a new [VideoControl].
+    pub const fn new() -> Self {
+        Self {
+            version: 0,
+            clock_frequency: 0,
+            streaming_interfaces: Vec::new(),
+            terminals: Vec::new(),
+            units: Vec::new(),
+        }
+    }
+
+    /// Parses the [VideoControl] from the class-specific descriptors of the interface.
+    pub fn parse(extra: &[u8]) -> Result<Self> {
+        let mut vc = Self::new();
+        let mut header = false;
+
+        for desc in class_descriptors(extra) {
+            match desc[2] {
+                VC_HEADER => {
+                    if desc.len() < 12 {
+                        return Err(too_short("VideoControl header", desc));
+                    }
+                    let count = desc[11] as usize;
+                    vc.version = read_u16(desc, 3);
+                    vc.clock_frequency = u32_at(desc, 7);
+                    vc.streaming_interfaces = desc
+                        .get(12..12 + count)
+                        .ok_or(too_short("VideoControl header", desc))?
+                        .to_vec();
+                    header = true;
+                }
+                VC_INPUT_TERMINAL | VC_OUTPUT_TERMINAL => {
+                    vc.terminals.push(UvcTerminal::parse(desc)?);
+                }
+                subtype => {
+                    if let Some(unit_type) = UvcUnitType::create(subtype) {
+                        vc.units.push(UvcUnit::parse(desc, unit_type)?);
+                    }
+                }
+            }
+        }
+
+        if !header {
+            return Err(Error::InvalidDescriptor(
+                "missing UVC VideoControl header".into(),
+            ));
+        }
+
+        Ok(vc)
+    }
+
+    /// Gets the UVC specification release, in BCD.
+    pub const fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Gets the device clock frequency, in Hz (deprecated in UVC 1.5).
+    pub const fn clock_frequency(&self) -> u32 {
+        self.clock_frequency
+    }
+
+    /// Gets the numbers of the VideoStreaming interfaces of the function.
+    pub fn streaming_interfaces(&self) -> &[u8] {
+        self.streaming_interfaces.as_ref()
+    }
+
+    /// Gets the list of [UvcTerminal]s.
+    pub fn terminals(&self) -> &[UvcTerminal] {
+        self.terminals.as_ref()
+    }
+
+    /// Gets the list of [UvcUnit]s.
+    pub fn units(&self) -> &[UvcUnit] {
+        self.units.as_ref()
+    }
+
+    /// Gets the [UvcUnit] with the provided ID.
+    pub fn unit(&self, id: u8) -> Option<&UvcUnit> {
+        self.units.iter().find(|u| u.id() == id)
+    }
+
+    /// Gets the first processing [UvcUnit].
+    pub fn processing_unit(&self) -> Option<&UvcUnit> {
+        self.units
+            .iter()
+            .find(|u| u.unit_type() == UvcUnitType::Processing)
+    }
+
+    /// Gets the extension [UvcUnit] with the provided vendor GUID.
+    pub fn extension_unit(&self, guid: &[u8; GUID_LEN]) -> Option<&UvcUnit> {
+        self.units
+            .iter()
+            .find(|u| u.unit_type() == UvcUnitType::Extension && u.guid() == guid)
+    }
+
+    /// Gets the camera [UvcTerminal], if any.
+    pub fn camera_terminal(&self) -> Option<&UvcTerminal> {
+        self.terminals
+            .iter()
+            .find(|t| t.is_input() && t.terminal_type() == ITT_CAMERA)
+    }
+}
+
+impl fmt::Display for VideoControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""version": {}, "#, self.version)?;
+        write!(f, r#""clock_frequency": {}, "#, self.clock_frequency)?;
+        write!(
+            f,
+            r#""streaming_interfaces": {:?}, "#,
+            self.streaming_interfaces
+        )?;
+        write!(f, r#""terminals": ["#)?;
+        for (i, terminal) in self.terminals.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{terminal}")?;
+        }
+        write!(f, r#"], "units": ["#)?;
+        for (i, unit) in self.units.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{unit}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents the frame intervals of a [UvcFrame], in 100 ns units.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameIntervals {
+    /// A list of supported intervals.
+    Discrete(Vec<u32>),
+    /// A range of supported intervals.
+    Continuous { min: u32, max: u32, step: u32 },
+}
+
+impl FrameIntervals {
+    /// Creates a new [FrameIntervals].
+    pub const fn new() -> Self {
+        Self::Discrete(Vec::new())
+    }
+
+    fn parse(desc: &[u8], off: usize, count: u8) -> Result<Self> {
+        let len = if count == 0 { 3 } else { count as usize };
+        let buf = desc
+            .get(off..off + len * 4)
+            .ok_or(too_short("frame", desc))?;
+        let vals: Vec<u32> = buf.chunks_exact(4).map(|c| u32_at(c, 0)).collect();
+
+        if count == 0 {
+            Ok(Self::Continuous {
+                min: vals[0],
+                max: vals[1],
+                step: vals[2],
+            })
+        } else {
+            Ok(Self::Discrete(vals))
+        }
+    }
+
+    /// Gets whether the interval is supported.
+    pub fn supports(&self, interval: u32) -> bool {
+        match self {
+            Self::Discrete(vals) => vals.contains(&interval),
+            Self::Continuous { min, max, step } => {
+                (*min..=*max).contains(&interval) && (*step == 0 || (interval - min) % *step == 0)
+            }
+        }
+    }
+}
+
+impl Default for FrameIntervals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for FrameIntervals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Discrete(vals) => write!(f, "{vals:?}"),
+            Self::Continuous { min, max, step } => {
+                write!(f, r#"{{"min": {min}, "max": {max}, "step": {step}}}"#)
+            }
+        }
+    }
+}
+
+/// Represents a frame descriptor: a frame size of a [UvcFormat].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvcFrame {
+    index: u8,
+    capabilities: u8,
+    width: u16,
+    height: u16,
+    min_bit_rate: u32,
+    max_bit_rate: u32,
+    max_frame_size: u32,
+    default_interval: u32,
+    intervals: FrameIntervals,
+}
+
+impl UvcFrame {
+    /// Creates a new [UvcFrame].
+    pub const fn new() -> Self {
+        Self {
+            index: 0,
+            capabilities: 0,
+            width: 0,
+            height: 0,
+            min_bit_rate: 0,
+            max_bit_rate: 0,
+            max_frame_size: 0,
+            default_interval: 0,
+            intervals: FrameIntervals::new(),
+        }
+    }
+
+    fn parse(desc: &[u8]) -> Result<Self> {
+        if desc.len() < 26 {
+            return Err(too_short("frame", desc));
+        }
+
+        // frame-based descriptors have no buffer size, and shift the interval fields
+        let frame_based = desc[2] == VS_FRAME_FRAME_BASED;
+        let (max_frame_size, default_interval, count) = if frame_based {
+            (0, u32_at(desc, 17), desc[21])
+        } else {
+            (u32_at(desc, 17), u32_at(desc, 21), desc[25])
+        };
+
+        Ok(Self {
+            index: desc[3],
+            capabilities: desc[4],
+            width: read_u16(desc, 5),
+            height: read_u16(desc, 7),
+            min_bit_rate: u32_at(desc, 9),
+            max_bit_rate: u32_at(desc, 13),
+            max_frame_size,
+            default_interval,
+            intervals: FrameIntervals::parse(desc, 26, count)?,
+        })
+    }
+
+    /// Gets the frame index, referenced by [ProbeCommit](super::ProbeCommit).
+    pub const fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Gets the `bmCapabilities` bitmap.
+    pub const fn capabilities(&self) -> u8 {
+        self.capabilities
+    }
+
+    /// Gets the width, in pixels.
+    pub const fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Gets the height, in pixels.
+    pub const fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Gets the minimum bit rate, in bits per second.
+    pub const fn min_bit_rate(&self) -> u32 {
+        self.min_bit_rate
+    }
+
+    /// Gets the maximum bit rate, in bits per second.
+    pub const fn max_bit_rate(&self) -> u32 {
+        self.max_bit_rate
+    }
+
+    /// Gets the largest frame size in bytes, `0` for frame-based formats.
+    pub const fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Gets the default frame interval, in 100 ns units.
+    pub const fn default_interval(&self) -> u32 {
+        self.default_interval
+    }
+
+    /// Gets the supported [FrameIntervals].
+    pub const fn intervals(&self) -> &FrameIntervals {
+        &self.intervals
+    }
+}
+
+impl fmt::Display for UvcFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""index": {}, "#, self.index)?;
+        write!(f, r#""capabilities": {}, "#, self.capabilities)?;
+        write!(f, r#""width": {}, "#, self.width)?;
+        write!(f, r#""height": {}, "#, self.height)?;
+        write!(f, r#""min_bit_rate": {}, "#, self.min_bit_rate)?;
+        write!(f, r#""max_bit_rate": {}, "#, self.max_bit_rate)?;
+        write!(f, r#""max_frame_size": {}, "#, self.max_frame_size)?;
+        write!(f, r#""default_interval": {}, "#, self.default_interval)?;
+        write!(f, r#""intervals": {}"#, self.intervals)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents the payload format of a [UvcFormat].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UvcFormatType {
+    #[default]
+    Uncompressed = VS_FORMAT_UNCOMPRESSED,
+    Mjpeg = VS_FORMAT_MJPEG,
+    FrameBased = VS_FORMAT_FRAME_BASED,
+}
+
+impl UvcFormatType {
+    /// Creates a new [UvcFormatType].
+    pub const fn new() -> Self {
+        Self::Uncompressed
+    }
+
+    /// Creates a new [UvcFormatType] from its descriptor subtype.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            VS_FORMAT_UNCOMPRESSED => Some(Self::Uncompressed),
+            VS_FORMAT_MJPEG => Some(Self::Mjpeg),
+            VS_FORMAT_FRAME_BASED => Some(Self::FrameBased),
+            _ => None,
+        }
+    }
+}
+
+impl From<&UvcFormatType> for &'static str {
+    fn from(val: &UvcFormatType) -> Self {
+        match val {
+            UvcFormatType::Uncompressed => "uncompressed",
+            UvcFormatType::Mjpeg => "mjpeg",
+            UvcFormatType::FrameBased => "frame-based",
+        }
+    }
+}
+
+impl fmt::Display for UvcFormatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a format descriptor, with its [UvcFrame]s.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvcFormat {
+    index: u8,
+    format_type: UvcFormatType,
+    guid: [u8; GUID_LEN],
+    bits_per_pixel: u8,
+    default_frame_index: u8,
+    frames: Vec<UvcFrame>,
+}
+
+impl UvcFormat {
+    /// Creates a new [UvcFormat].
+    pub const fn new() -> Self {
+        Self {
+            index: 0,
+            format_type: UvcFormatType::new(),
+            guid: [0u8; GUID_LEN],
+            bits_per_pixel: 0,
+            default_frame_index: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    fn parse(desc: &[u8], format_type: UvcFormatType) -> Result<Self> {
+        let mut format = Self {
+            format_type,
+            ..Self::new()
+        };
+
+        if format_type == UvcFormatType::Mjpeg {
+            if desc.len() < 7 {
+                return Err(too_short("format", desc));
+            }
+            format.default_frame_index = desc[6];
+        } else {
+            if desc.len() < 23 {
+                return Err(too_short("format", desc));
+            }
+            format.guid.copy_from_slice(&desc[5..21]);
+            format.bits_per_pixel = desc[21];
+            format.default_frame_index = desc[22];
+        }
+        format.index = desc[3];
+
+        Ok(format)
+    }
+
+    /// Gets the format index, referenced by [ProbeCommit](super::ProbeCommit).
+    pub const fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Gets the [UvcFormatType].
+    pub const fn format_type(&self) -> UvcFormatType {
+        self.format_type
+    }
+
+    /// Gets the format GUID of uncompressed and frame-based formats.
+    pub const fn guid(&self) -> &[u8; GUID_LEN] {
+        &self.guid
+    }
+
+    /// Gets the FourCC of the format, e.g. `YUY2` or `MJPG`.
+    pub fn fourcc(&self) -> String {
+        match self.format_type {
+            UvcFormatType::Mjpeg => "MJPG".into(),
+            _ => String::from_utf8_lossy(&self.guid[..4]).into_owned(),
+        }
+    }
+
+    /// Gets the number of bits per pixel, `0` for MJPEG.
+    pub const fn bits_per_pixel(&self) -> u8 {
+        self.bits_per_pixel
+    }
+
+    /// Gets the index of the default [UvcFrame].
+    pub const fn default_frame_index(&self) -> u8 {
+        self.default_frame_index
+    }
+
+    /// Gets the list of [UvcFrame]s.
+    pub fn frames(&self) -> &[UvcFrame] {
+        self.frames.as_ref()
+    }
+
+    /// Gets the [UvcFrame] with the provided index.
+    pub fn frame(&self, index: u8) -> Option<&UvcFrame> {
+        self.frames.iter().find(|f| f.index() == index)
+    }
+}
+
+impl fmt::Display for UvcFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""index": {}, "#, self.index)?;
+        write!(f, r#""format_type": {}, "#, self.format_type)?;
+        write!(f, r#""fourcc": "{}", "#, self.fourcc().escape_default())?;
+        write!(f, r#""bits_per_pixel": {}, "#, self.bits_per_pixel)?;
+        write!(
+            f,
+            r#""default_frame_index": {}, "#,
+            self.default_frame_index
+        )?;
+        write!(f, r#""frames": ["#)?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{frame}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents the class-specific descriptors of a VideoStreaming interface.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VideoStreaming {
+    endpoint: u8,
+    terminal_link: u8,
+    formats: Vec<UvcFormat>,
+}
+
+impl VideoStreaming {
+    /// Creates a new [VideoStreaming].
+    pub const fn new() -> Self {
+        Self {
+            endpoint: 0,
+            terminal_link: 0,
+            formats: Vec::new(),
+        }
+    }
+
+    /// Parses the [VideoStreaming] from the class-specific descriptors of the interface.
+    ///
+    /// Formats not handled by the crate, e.g. MPEG-2 TS or H.264, are skipped along with their
+    /// frames.
+    pub fn parse(extra: &[u8]) -> Result<Self> {
+        let mut vs = Self::new();
+        let mut header = false;
+
+        for desc in class_descriptors(extra) {
+            match desc[2] {
+                VS_INPUT_HEADER => {
+                    if desc.len() < 9 {
+                        return Err(too_short("VideoStreaming input header", desc));
+                    }
+                    vs.endpoint = desc[6];
+                    vs.terminal_link = desc[8];
+                    header = true;
+                }
+                // frames follow their format descriptor
+                VS_FRAME_UNCOMPRESSED | VS_FRAME_MJPEG | VS_FRAME_FRAME_BASED => {
+                    if let Some(format) = vs.formats.last_mut() {
+                        format.frames.push(UvcFrame::parse(desc)?);
+                    }
+                }
+                subtype => {
+                    if let Some(format_type) = UvcFormatType::create(subtype) {
+                        vs.formats.push(UvcFormat::parse(desc, format_type)?);
+                    }
+                }
+            }
+        }
+
+        if !header {
+            return Err(Error::InvalidDescriptor(
+                "missing UVC VideoStreaming input header".into(),
+            ));
+        }
+
+        Ok(vs)
+    }
+
+    /// Gets the address of the streaming endpoint.
+    pub const fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    /// Gets the ID of the output terminal the interface streams.
+    pub const fn terminal_link(&self) -> u8 {
+        self.terminal_link
+    }
+
+    /// Gets the list of [UvcFormat]s.
+    pub fn formats(&self) -> &[UvcFormat] {
+        self.formats.as_ref()
+    }
+
+    /// Gets the [UvcFormat] with the provided index.
+    pub fn format(&self, index: u8) -> Option<&UvcFormat> {
+        self.formats.iter().find(|f| f.index() == index)
+    }
+
+    /// Finds the [UvcFormat] and [UvcFrame] with the provided FourCC and size.
+    pub fn find_frame(
+        &self,
+        fourcc: &str,
+        width: u16,
+        height: u16,
+    ) -> Option<(&UvcFormat, &UvcFrame)> {
+        self.formats
+            .iter()
+            .filter(|f| f.fourcc() == fourcc)
+            .find_map(|format| {
+                format
+                    .frames()
+                    .iter()
+                    .find(|f| f.width() == width && f.height() == height)
+                    .map(|frame| (format, frame))
+            })
+    }
+}
+
+impl fmt::Display for VideoStreaming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""endpoint": {}, "#, self.endpoint)?;
+        write!(f, r#""terminal_link": {}, "#, self.terminal_link)?;
+        write!(f, r#""formats": ["#)?;
+        for (i, format) in self.formats.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{format}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uvc_descriptors() -> Result<()> {
+        let vc = [
+            0x0d, 0x24, 0x01, 0x10, 0x01, 0x4d, 0x00, 0x80, 0xc3, 0xc9, 0x01, 0x01, 0x01, //
+            0x12, 0x24, 0x02, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x0a, 0x00, 0x00, //
+            0x0b, 0x24, 0x05, 0x02, 0x01, 0x00, 0x40, 0x02, 0x7f, 0x15, 0x00, //
+            0x1b, 0x24, 0x06, 0x03, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a,
+            0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x02, 0x01, 0x02, 0x02, 0x03, 0x00, 0x00, //
+            0x09, 0x24, 0x03, 0x04, 0x01, 0x01, 0x00, 0x03, 0x00,
+        ];
+        let vc = VideoControl::parse(&vc)?;
+        assert_eq!(vc.version(), 0x0110);
+        assert_eq!(vc.streaming_interfaces(), [1]);
+        assert_eq!(vc.camera_terminal().map(|t| t.id()), Some(1));
+        let pu = vc.processing_unit().unwrap();
+        assert!(pu.has_control(2) && !pu.has_control(8));
+        let guid: [u8; GUID_LEN] = std::array::from_fn(|i| i as u8 + 1);
+        assert_eq!(
+            vc.extension_unit(&guid).map(|u| u.sources()),
+            Some(&[2u8][..])
+        );
+
+        let vs = [
+            0x0e, 0x24, 0x01, 0x02, 0x00, 0x00, 0x81, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00,
+            0x1b, 0x24, 0x04, 0x01, 0x01, b'Y', b'U', b'Y', b'2', 0x00, 0x00, 0x10, 0x00, 0x80,
+            0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, //
+            0x22, 0x24, 0x05, 0x01, 0x00, 0x80, 0x02, 0xe0, 0x01, 0x00, 0x00, 0x77, 0x01, 0x00,
+            0x00, 0xca, 0x08, 0x00, 0x60, 0x09, 0x00, 0x15, 0x16, 0x05, 0x00, 0x02, 0x15, 0x16,
+            0x05, 0x00, 0x2a, 0x2c, 0x0a, 0x00, //
+            0x0b, 0x24, 0x06, 0x02, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, //
+            0x26, 0x24, 0x07, 0x01, 0x00, 0x00, 0x05, 0xd0, 0x02, 0x00, 0x00, 0x77, 0x01, 0x00,
+            0x00, 0xca, 0x08, 0x00, 0x20, 0x1c, 0x00, 0x15, 0x16, 0x05, 0x00, 0x00, 0x15, 0x16,
+            0x05, 0x00, 0x2a, 0x2c, 0x0a, 0x00, 0x15, 0x16, 0x05, 0x00,
+        ];
+        let vs = VideoStreaming::parse(&vs)?;
+        assert_eq!(vs.endpoint(), 0x81);
+        assert_eq!(vs.formats().len(), 2);
+
+        let (format, frame) = vs.find_frame("YUY2", 640, 480).unwrap();
+        assert_eq!(format.bits_per_pixel(), 16);
+        assert_eq!(frame.max_frame_size(), 614_400);
+        assert!(frame.intervals().supports(666_666));
+        assert!(!frame.intervals().supports(500_000));
+
+        let (format, frame) = vs.find_frame("MJPG", 1280, 720).unwrap();
+        assert_eq!(format.index(), 2);
+        assert!(frame.intervals().supports(333_333 * 2));
+        assert!(!frame.intervals().supports(333_334));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! UVC payload headers, and reassembly of payloads into video frames.
+//!
+//! Every payload, an Isochronous packet or a Bulk transfer, starts with a header carrying the
+//! frame ID bit (FID), toggled at each new frame, and an end of frame bit (EOF). Frames are
+//! complete on EOF, or when FID toggles for devices that never set EOF.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::{Error, Result};
+
+pub const UVC_HEADER_FID: u8 = 0x01;
+pub const UVC_HEADER_EOF: u8 = 0x02;
+pub const UVC_HEADER_PTS: u8 = 0x04;
+pub const UVC_HEADER_SCR: u8 = 0x08;
+pub const UVC_HEADER_STI: u8 = 0x20;
+pub const UVC_HEADER_ERR: u8 = 0x40;
+pub const UVC_HEADER_EOH: u8 = 0x80;
+
+/// Represents the header of a UVC payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PayloadHeader {
+    len: u8,
+    info: u8,
+    pts: Option<u32>,
+    scr: Option<(u32, u16)>,
+}
+
+impl PayloadHeader {
+    /// Creates a new [PayloadHeader].
+    pub const fn new() -> Self {
+        Self {
+            len: 2,
+            info: UVC_HEADER_EOH,
+            pts: None,
+            scr: None,
+        }
+    }
+
+    /// Parses the [PayloadHeader] at the start of a payload.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let len = *buf
+            .first()
+            .ok_or(Error::InvalidMessage("empty UVC payload".into()))?;
+        if len < 2 || len as usize > buf.len() {
+            return Err(Error::InvalidMessage(format!(
+                "invalid UVC payload header length: {len}, payload: {}",
+                buf.len()
+            )));
+        }
+
+        let info = buf[1];
+        let mut off = 2;
+        let pts = if info & UVC_HEADER_PTS != 0 && off + 4 <= len as usize {
+            off += 4;
+            Some(u32::from_le_bytes([buf[2], buf[3], buf[4], buf[5]]))
+        } else {
+            None
+        };
+        let scr = buf
+            .get(off..off + 6)
+            .filter(|_| info & UVC_HEADER_SCR != 0 && off + 6 <= len as usize)
+            .map(|s| {
+                (
+                    u32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+                    u16::from_le_bytes([s[4], s[5]]),
+                )
+            });
+
+        Ok(Self {
+            len,
+            info,
+            pts,
+            scr,
+        })
+    }
+
+    /// Gets the header length, the offset of the payload data.
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Gets whether the header is empty, always `false` for a valid header.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the `bmHeaderInfo` bitmap.
+    pub const fn info(&self) -> u8 {
+        self.info
+    }
+
+    /// Gets the frame ID bit.
+    pub const fn fid(&self) -> bool {
+        self.info & UVC_HEADER_FID != 0
+    }
+
+    /// Gets whether the payload ends the frame.
+    pub const fn eof(&self) -> bool {
+        self.info & UVC_HEADER_EOF != 0
+    }
+
+    /// Gets whether the payload belongs to a still image.
+    pub const fn still_image(&self) -> bool {
+        self.info & UVC_HEADER_STI != 0
+    }
+
+    /// Gets whether the device reported a streaming error.
+    pub const fn error(&self) -> bool {
+        self.info & UVC_HEADER_ERR != 0
+    }
+
+    /// Gets the Presentation Time Stamp, in device clock units.
+    pub const fn pts(&self) -> Option<u32> {
+        self.pts
+    }
+
+    /// Gets the Source Clock Reference: the device clock, and the USB frame number.
+    pub const fn scr(&self) -> Option<(u32, u16)> {
+        self.scr
+    }
+}
+
+impl fmt::Display for PayloadHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""len": {}, "#, self.len)?;
+        write!(f, r#""info": {}, "#, self.info)?;
+        match self.pts {
+            Some(pts) => write!(f, r#""pts": {pts}"#)?,
+            None => write!(f, r#""pts": null"#)?,
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Represents a complete video frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VideoFrame {
+    data: Vec<u8>,
+    pts: Option<u32>,
+    fid: bool,
+    error: bool,
+}
+
+impl VideoFrame {
+    /// Creates a new [VideoFrame].
+    pub const fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            pts: None,
+            fid: false,
+            error: false,
+        }
+    }
+
+    /// Gets the frame data, e.g. a JPEG image or packed pixels.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Converts the [VideoFrame] into its data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Gets the Presentation Time Stamp of the first payload.
+    pub const fn pts(&self) -> Option<u32> {
+        self.pts
+    }
+
+    /// Gets the frame ID bit.
+    pub const fn fid(&self) -> bool {
+        self.fid
+    }
+
+    /// Gets whether a payload of the frame was lost or reported an error.
+    pub const fn error(&self) -> bool {
+        self.error
+    }
+}
+
+impl fmt::Display for VideoFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""len": {}, "#, self.data.len())?;
+        match self.pts {
+            Some(pts) => write!(f, r#""pts": {pts}, "#)?,
+            None => write!(f, r#""pts": null, "#)?,
+        }
+        write!(f, r#""fid": {}, "#, self.fid)?;
+        write!(f, r#""error": {}"#, self.error)?;
+        write!(f, "}}")
+    }
+}
+
+/// Reassembles UVC payloads into [VideoFrame]s.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameAssembler {
+    frame: VideoFrame,
+    started: bool,
+    max_frame_size: usize,
+    frames: VecDeque<VideoFrame>,
+}
+
+impl FrameAssembler {
+    /// Creates a new [FrameAssembler], without a frame size limit.
+    pub const fn new() -> Self {
+        Self {
+            frame: VideoFrame::new(),
+            started: false,
+            max_frame_size: 0,
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Gets the frame size limit, `0` for none.
+    pub const fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Sets the frame size limit, frames growing past it are flagged as errors.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Builder function that sets the frame size limit.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.set_max_frame_size(max_frame_size);
+        self
+    }
+
+    /// Adds a payload, completing frames on EOF or on FID toggles.
+    ///
+    /// Empty payloads are skipped, and invalid headers flag the current frame as an error.
+    pub fn push(&mut self, payload: &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+        let Ok(header) = PayloadHeader::parse(payload) else {
+            self.set_error();
+            return;
+        };
+
+        if self.started && header.fid() != self.frame.fid {
+            self.finish();
+        }
+        if !self.started {
+            self.started = true;
+            self.frame.fid = header.fid();
+            self.frame.pts = header.pts();
+        }
+
+        let data = &payload[header.len()..];
+        if self.max_frame_size != 0 && self.frame.data.len() + data.len() > self.max_frame_size {
+            self.frame.error = true;
+        } else {
+            self.frame.data.extend_from_slice(data);
+        }
+        self.frame.error |= header.error();
+
+        if header.eof() {
+            self.finish();
+        }
+    }
+
+    /// Flags the current frame as an error, e.g. after a lost packet.
+    pub fn set_error(&mut self) {
+        self.frame.error = true;
+    }
+
+    /// Gets the next complete [VideoFrame], if any.
+    pub fn next_frame(&mut self) -> Option<VideoFrame> {
+        self.frames.pop_front()
+    }
+
+    /// Drops the current frame, and the complete ones not yet read.
+    pub fn reset(&mut self) {
+        self.frame = VideoFrame::new();
+        self.started = false;
+        self.frames.clear();
+    }
+
+    fn finish(&mut self) {
+        let fid = self.frame.fid;
+        let frame = std::mem::take(&mut self.frame);
+        self.started = false;
+        // the FID of the next frame toggles, even if its first payload is lost
+        self.frame.fid = !fid;
+        if !frame.data.is_empty() {
+            self.frames.push_back(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_assembler() -> Result<()> {
+        let header = PayloadHeader::parse(&[
+            0x0c, 0x8d, 0x10, 0x20, 0x30, 0x40, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00,
+        ])?;
+        assert!(header.fid() && !header.eof());
+        assert_eq!(header.pts(), Some(0x4030_2010));
+        assert_eq!(header.scr(), Some((1, 5)));
+        assert!(PayloadHeader::parse(&[0x04, 0x80]).is_err());
+
+        let mut asm = FrameAssembler::new().with_max_frame_size(8);
+
+        // frame ended by EOF, then one ended by the FID toggle
+        asm.push(&[0x02, 0x80, 1, 2]);
+        asm.push(&[]);
+        asm.push(&[0x02, 0x82, 3]);
+        asm.push(&[0x02, 0x81, 4, 5]);
+        asm.push(&[0x02, 0x80, 6]);
+
+        let frame = asm.next_frame().unwrap();
+        assert_eq!(frame.data(), [1, 2, 3]);
+        assert!(!frame.error());
+        let frame = asm.next_frame().unwrap();
+        assert_eq!(frame.data(), [4, 5]);
+        assert!(frame.fid());
+        assert!(asm.next_frame().is_none());
+
+        // errors reported by the device, and oversized frames
+        asm.push(&[0x02, 0xc2, 7]);
+        asm.push(&[0x02, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        asm.push(&[0x02, 0x82, 8]);
+        assert!(asm.next_frame().unwrap().error());
+        assert!(asm.next_frame().unwrap().error());
+
+        Ok(())
+    }
+}
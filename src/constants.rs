@@ -1,8 +1,16 @@
 pub const SYSFS_MOUNT_PATH: &str = "/sys";
 pub const SYSFS_DEVICE_PATH: &str = "/sys/bus/usb/devices";
+pub const USBFS_DEVICE_PATH: &str = "/dev/bus/usb";
 
+/// Name of the kernel driver bound to interfaces claimed through USBFS.
+pub const USBFS_DRIVER_NAME: &str = "usbfs";
 pub const USBFS_MAX_DRIVER_NAME: usize = 255;
 pub const USBFS_MAX_DRIVER_NAME_FFI: usize = 256;
 pub const MAX_BULK_BUFFER_LENGTH: usize = 16384;
 pub const MAX_CTRL_BUFFER_LENGTH: usize = 4096;
 pub const MAX_ISO_PACKETS_PER_URB: usize = 128;
+
+/// `USBDEVFS_DISCONNECT` request code, passed as a [UsbfsIoctl](crate::UsbfsIoctl) code.
+pub const USBFS_IOCTL_DISCONNECT: i32 = request_code_none!(b'U', 22) as i32;
+/// `USBDEVFS_CONNECT` request code, passed as a [UsbfsIoctl](crate::UsbfsIoctl) code.
+pub const USBFS_IOCTL_CONNECT: i32 = request_code_none!(b'U', 23) as i32;
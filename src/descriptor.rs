@@ -0,0 +1,265 @@
+//! Standard USB descriptor parsing.
+//!
+//! Reading a USBFS device node returns the device descriptor, followed by every configuration
+//! descriptor with its interface, endpoint and class-specific descriptors.
+
+use std::fmt;
+
+use crate::{Error, Result};
+
+mod config;
+mod device;
+mod endpoint;
+mod interface;
+
+pub use config::*;
+pub use device::*;
+pub use endpoint::*;
+pub use interface::*;
+
+pub const DESCRIPTOR_TYPE_DEVICE: u8 = 0x01;
+pub const DESCRIPTOR_TYPE_CONFIG: u8 = 0x02;
+pub const DESCRIPTOR_TYPE_STRING: u8 = 0x03;
+pub const DESCRIPTOR_TYPE_INTERFACE: u8 = 0x04;
+pub const DESCRIPTOR_TYPE_ENDPOINT: u8 = 0x05;
+pub const DESCRIPTOR_TYPE_INTERFACE_ASSOCIATION: u8 = 0x0b;
+pub const DESCRIPTOR_TYPE_BOS: u8 = 0x0f;
+pub const DESCRIPTOR_TYPE_SS_ENDPOINT_COMPANION: u8 = 0x30;
+
+/// Reads a little-endian `u16` at the provided offset.
+pub(crate) fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+/// Iterator over the raw descriptors in a buffer.
+///
+/// Each item is a full descriptor, starting with its `bLength` and `bDescriptorType` fields.
+/// Iteration stops at the first malformed descriptor.
+#[derive(Clone, Debug)]
+pub struct DescriptorIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DescriptorIter<'a> {
+    /// Creates a new [DescriptorIter].
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Gets the remaining unparsed bytes.
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+
+impl<'a> Iterator for DescriptorIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = *self.buf.first()? as usize;
+
+        if len < 2 || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+
+        let (desc, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        Some(desc)
+    }
+}
+
+/// Represents the parsed descriptors of a USB device.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Descriptors {
+    device: DeviceDescriptor,
+    configs: Vec<ConfigDescriptor>,
+}
+
+impl Descriptors {
+    /// Creates a new [Descriptors].
+    pub const fn new() -> Self {
+        Self {
+            device: DeviceDescriptor::new(),
+            configs: Vec::new(),
+        }
+    }
+
+    /// Parses the [Descriptors] from the raw bytes read from a USBFS device node.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        let mut iter = DescriptorIter::new(buf);
+        let device = DeviceDescriptor::parse(
+            iter.next()
+                .ok_or(Error::InvalidDescriptor("missing device descriptor".into()))?,
+        )?;
+
+        let mut configs = Vec::with_capacity(device.num_configurations() as usize);
+        let mut rest = iter.remaining();
+
+        while !rest.is_empty() {
+            let config = ConfigDescriptor::parse(rest)?;
+            let total = (config.total_length() as usize).clamp(ConfigDescriptor::LEN, rest.len());
+
+            configs.push(Self::parse_config(
+                config,
+                &rest[ConfigDescriptor::LEN..total],
+            )?);
+            rest = &rest[total..];
+        }
+
+        Ok(Self { device, configs })
+    }
+
+    fn parse_config(mut config: ConfigDescriptor, buf: &[u8]) -> Result<ConfigDescriptor> {
+        for desc in DescriptorIter::new(buf) {
+            match desc[1] {
+                DESCRIPTOR_TYPE_INTERFACE => {
+                    config.push_interface(InterfaceDescriptor::parse(desc)?);
+                }
+                DESCRIPTOR_TYPE_ENDPOINT => {
+                    let ep = EndpointDescriptor::parse(desc)?;
+                    config
+                        .last_interface_mut()
+                        .ok_or(Error::InvalidDescriptor(
+                            "endpoint descriptor outside of an interface".into(),
+                        ))?
+                        .push_endpoint(ep);
+                }
+                _ => match config.last_interface_mut() {
+                    Some(iface) => match iface.endpoints_mut().last_mut() {
+                        Some(ep) => ep.extend_extra(desc),
+                        None => iface.extend_extra(desc),
+                    },
+                    None => config.extend_extra(desc),
+                },
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Gets the [DeviceDescriptor].
+    pub const fn device(&self) -> &DeviceDescriptor {
+        &self.device
+    }
+
+    /// Gets the list of [ConfigDescriptor]s.
+    pub fn configs(&self) -> &[ConfigDescriptor] {
+        self.configs.as_ref()
+    }
+
+    /// Gets the [ConfigDescriptor] with the provided configuration value.
+    pub fn config(&self, configuration_value: u8) -> Option<&ConfigDescriptor> {
+        self.configs
+            .iter()
+            .find(|c| c.configuration_value() == configuration_value)
+    }
+}
+
+impl fmt::Display for Descriptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""device": {}, "#, self.device)?;
+        write!(f, r#""configs": ["#)?;
+        for (i, config) in self.configs.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{config}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Descriptors of a CDC-ACM device, with a vendor interface using two alternate settings.
+    pub(crate) const TEST_DESCRIPTORS: [u8; 118] = [
+        // device
+        0x12, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x83, 0x04, 0x40, 0x57, 0x00, 0x01, 0x01,
+        0x02, 0x03, 0x01, //
+        // configuration, total length 100
+        0x09, 0x02, 0x64, 0x00, 0x03, 0x01, 0x00, 0x80, 0x32, //
+        // interface association
+        0x08, 0x0b, 0x00, 0x02, 0x02, 0x02, 0x01, 0x00, //
+        // interface 0: CDC communication
+        0x09, 0x04, 0x00, 0x00, 0x01, 0x02, 0x02, 0x01, 0x00, //
+        // CDC header, call management, ACM, union
+        0x05, 0x24, 0x00, 0x10, 0x01, //
+        0x05, 0x24, 0x01, 0x00, 0x01, //
+        0x04, 0x24, 0x02, 0x02, //
+        0x05, 0x24, 0x06, 0x00, 0x01, //
+        // interrupt IN
+        0x07, 0x05, 0x82, 0x03, 0x08, 0x00, 0x10, //
+        // interface 1: CDC data
+        0x09, 0x04, 0x01, 0x00, 0x02, 0x0a, 0x00, 0x00, 0x00, //
+        0x07, 0x05, 0x01, 0x02, 0x40, 0x00, 0x00, //
+        0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00, //
+        // interface 2, alt 0: no endpoints
+        0x09, 0x04, 0x02, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, //
+        // interface 2, alt 1: isochronous IN
+        0x09, 0x04, 0x02, 0x01, 0x01, 0xff, 0x00, 0x00, 0x00, //
+        0x07, 0x05, 0x83, 0x05, 0x00, 0x02, 0x01,
+    ];
+
+    #[test]
+    fn test_descriptor_iter() {
+        let descs: Vec<&[u8]> = DescriptorIter::new(&[0x02, 0x01, 0x03, 0x24, 0x00]).collect();
+
+        assert_eq!(
+            descs,
+            [[0x02u8, 0x01].as_ref(), [0x03, 0x24, 0x00].as_ref()]
+        );
+
+        // zero length descriptors stop the iteration
+        assert_eq!(DescriptorIter::new(&[0x00, 0x01, 0x02]).count(), 0);
+        // truncated descriptors stop the iteration
+        assert_eq!(DescriptorIter::new(&[0x02, 0x01, 0x05, 0x04]).count(), 1);
+    }
+
+    #[test]
+    fn test_descriptors() -> Result<()> {
+        let descs = Descriptors::parse(TEST_DESCRIPTORS.as_ref())?;
+
+        assert_eq!(descs.device().vendor_id(), 0x0483);
+        assert_eq!(descs.configs().len(), 1);
+
+        let config = descs.config(1).unwrap();
+
+        assert_eq!(config.num_interfaces(), 3);
+        assert_eq!(config.interfaces().len(), 4);
+        assert_eq!(
+            config.extra(),
+            &[0x08, 0x0b, 0x00, 0x02, 0x02, 0x02, 0x01, 0x00]
+        );
+
+        let comm = config.interface(0, 0).unwrap();
+
+        assert_eq!(comm.class(), 0x02);
+        assert_eq!(comm.extra().len(), 19);
+        assert_eq!(comm.endpoints().len(), 1);
+        assert_eq!(comm.endpoints()[0].transfer_type(), TransferType::Interrupt);
+
+        let data = config.interface(1, 0).unwrap();
+
+        assert_eq!(data.endpoints().len(), 2);
+        assert!(data.endpoint(0x01).is_some());
+        assert!(data.endpoint(0x81).is_some());
+
+        assert_eq!(config.alt_settings(2).count(), 2);
+        assert!(config.interface(2, 0).unwrap().endpoints().is_empty());
+        assert_eq!(
+            config.interface(2, 1).unwrap().endpoints()[0].transfer_type(),
+            TransferType::Isochronous
+        );
+
+        assert!(descs.config(2).is_none());
+        assert!(Descriptors::parse(&[]).is_err());
+
+        Ok(())
+    }
+}
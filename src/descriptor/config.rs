@@ -0,0 +1,184 @@
+use std::fmt;
+
+use super::{read_u16, InterfaceDescriptor, DESCRIPTOR_TYPE_CONFIG};
+use crate::{Error, Result};
+
+pub const CONFIG_ATTR_SELF_POWERED: u8 = 0x40;
+pub const CONFIG_ATTR_REMOTE_WAKEUP: u8 = 0x20;
+
+/// Represents a USB configuration descriptor, including all its interfaces.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConfigDescriptor {
+    total_length: u16,
+    num_interfaces: u8,
+    configuration_value: u8,
+    configuration_index: u8,
+    attributes: u8,
+    max_power: u8,
+    interfaces: Vec<InterfaceDescriptor>,
+    extra: Vec<u8>,
+}
+
+impl ConfigDescriptor {
+    /// Length of a standard configuration descriptor.
+    pub const LEN: usize = 9;
+
+    /// Creates a new [ConfigDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            total_length: 0,
+            num_interfaces: 0,
+            configuration_value: 0,
+            configuration_index: 0,
+            attributes: 0,
+            max_power: 0,
+            interfaces: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// Parses a [ConfigDescriptor] header from a raw descriptor.
+    ///
+    /// **NOTE** interfaces are added while parsing the full configuration, see
+    /// [Descriptors](super::Descriptors).
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || buf[1] != DESCRIPTOR_TYPE_CONFIG {
+            return Err(Error::InvalidDescriptor(format!(
+                "invalid configuration descriptor, length: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            total_length: read_u16(buf, 2),
+            num_interfaces: buf[4],
+            configuration_value: buf[5],
+            configuration_index: buf[6],
+            attributes: buf[7],
+            max_power: buf[8],
+            interfaces: Vec::new(),
+            extra: Vec::new(),
+        })
+    }
+
+    /// Gets the total length of the configuration, including all sub-descriptors.
+    pub const fn total_length(&self) -> u16 {
+        self.total_length
+    }
+
+    /// Gets the number of interfaces.
+    pub const fn num_interfaces(&self) -> u8 {
+        self.num_interfaces
+    }
+
+    /// Gets the configuration value, used as the argument to set the configuration.
+    pub const fn configuration_value(&self) -> u8 {
+        self.configuration_value
+    }
+
+    /// Gets the configuration string descriptor index.
+    pub const fn configuration_index(&self) -> u8 {
+        self.configuration_index
+    }
+
+    /// Gets the configuration attributes.
+    pub const fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Gets whether the device is self-powered in this configuration.
+    pub const fn self_powered(&self) -> bool {
+        self.attributes & CONFIG_ATTR_SELF_POWERED != 0
+    }
+
+    /// Gets whether the device supports remote wakeup in this configuration.
+    pub const fn remote_wakeup(&self) -> bool {
+        self.attributes & CONFIG_ATTR_REMOTE_WAKEUP != 0
+    }
+
+    /// Gets the raw maximum power field (in 2 mA units for USB 2.0, 8 mA for SuperSpeed).
+    pub const fn max_power(&self) -> u8 {
+        self.max_power
+    }
+
+    /// Gets the list of [InterfaceDescriptor]s, one entry per alternate setting.
+    pub fn interfaces(&self) -> &[InterfaceDescriptor] {
+        self.interfaces.as_ref()
+    }
+
+    /// Gets the [InterfaceDescriptor] for the interface number and alternate setting.
+    pub fn interface(&self, number: u8, alternate_setting: u8) -> Option<&InterfaceDescriptor> {
+        self.interfaces
+            .iter()
+            .find(|i| i.number() == number && i.alternate_setting() == alternate_setting)
+    }
+
+    /// Gets an iterator over the alternate settings of the interface number.
+    pub fn alt_settings(&self, number: u8) -> impl Iterator<Item = &InterfaceDescriptor> {
+        self.interfaces.iter().filter(move |i| i.number() == number)
+    }
+
+    /// Gets the extra (class-specific) descriptors preceding the first interface.
+    pub fn extra(&self) -> &[u8] {
+        self.extra.as_ref()
+    }
+
+    pub(crate) fn push_interface(&mut self, interface: InterfaceDescriptor) {
+        self.interfaces.push(interface);
+    }
+
+    pub(crate) fn last_interface_mut(&mut self) -> Option<&mut InterfaceDescriptor> {
+        self.interfaces.last_mut()
+    }
+
+    pub(crate) fn extend_extra(&mut self, extra: &[u8]) {
+        self.extra.extend_from_slice(extra);
+    }
+}
+
+impl fmt::Display for ConfigDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""total_length": {}, "#, self.total_length)?;
+        write!(f, r#""num_interfaces": {}, "#, self.num_interfaces)?;
+        write!(
+            f,
+            r#""configuration_value": {}, "#,
+            self.configuration_value
+        )?;
+        write!(f, r#""attributes": {}, "#, self.attributes)?;
+        write!(f, r#""max_power": {}, "#, self.max_power)?;
+        write!(f, r#""interfaces": ["#)?;
+        for (i, iface) in self.interfaces.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{iface}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_descriptor() -> Result<()> {
+        let config =
+            ConfigDescriptor::parse(&[0x09, 0x02, 0x20, 0x00, 0x01, 0x01, 0x00, 0xc0, 0x32])?;
+
+        assert_eq!(config.total_length(), 0x20);
+        assert_eq!(config.num_interfaces(), 1);
+        assert_eq!(config.configuration_value(), 1);
+        assert_eq!(config.configuration_index(), 0);
+        assert!(config.self_powered());
+        assert!(!config.remote_wakeup());
+        assert_eq!(config.max_power(), 0x32);
+
+        assert!(ConfigDescriptor::parse(&[0x09, 0x02, 0x20, 0x00]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use super::{read_u16, DESCRIPTOR_TYPE_DEVICE};
+use crate::{Error, Result};
+
+/// Represents a USB device descriptor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceDescriptor {
+    usb_version: u16,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    max_packet_size0: u8,
+    vendor_id: u16,
+    product_id: u16,
+    device_version: u16,
+    manufacturer_index: u8,
+    product_index: u8,
+    serial_number_index: u8,
+    num_configurations: u8,
+}
+
+impl DeviceDescriptor {
+    /// Length of a device descriptor.
+    pub const LEN: usize = 18;
+
+    /// Creates a new [DeviceDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            usb_version: 0,
+            class: 0,
+            subclass: 0,
+            protocol: 0,
+            max_packet_size0: 0,
+            vendor_id: 0,
+            product_id: 0,
+            device_version: 0,
+            manufacturer_index: 0,
+            product_index: 0,
+            serial_number_index: 0,
+            num_configurations: 0,
+        }
+    }
+
+    /// Parses a [DeviceDescriptor] from a raw descriptor.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || buf[1] != DESCRIPTOR_TYPE_DEVICE {
+            return Err(Error::InvalidDescriptor(format!(
+                "invalid device descriptor, length: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            usb_version: read_u16(buf, 2),
+            class: buf[4],
+            subclass: buf[5],
+            protocol: buf[6],
+            max_packet_size0: buf[7],
+            vendor_id: read_u16(buf, 8),
+            product_id: read_u16(buf, 10),
+            device_version: read_u16(buf, 12),
+            manufacturer_index: buf[14],
+            product_index: buf[15],
+            serial_number_index: buf[16],
+            num_configurations: buf[17],
+        })
+    }
+
+    /// Gets the USB specification version, in BCD.
+    pub const fn usb_version(&self) -> u16 {
+        self.usb_version
+    }
+
+    /// Gets the device class.
+    pub const fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Gets the device subclass.
+    pub const fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Gets the device protocol.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets the maximum packet size of the default control endpoint.
+    pub const fn max_packet_size0(&self) -> u8 {
+        self.max_packet_size0
+    }
+
+    /// Gets the vendor ID.
+    pub const fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// Gets the product ID.
+    pub const fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Gets the device release number, in BCD.
+    pub const fn device_version(&self) -> u16 {
+        self.device_version
+    }
+
+    /// Gets the manufacturer string descriptor index.
+    pub const fn manufacturer_index(&self) -> u8 {
+        self.manufacturer_index
+    }
+
+    /// Gets the product string descriptor index.
+    pub const fn product_index(&self) -> u8 {
+        self.product_index
+    }
+
+    /// Gets the serial number string descriptor index.
+    pub const fn serial_number_index(&self) -> u8 {
+        self.serial_number_index
+    }
+
+    /// Gets the number of configurations.
+    pub const fn num_configurations(&self) -> u8 {
+        self.num_configurations
+    }
+}
+
+impl fmt::Display for DeviceDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""usb_version": {}, "#, self.usb_version)?;
+        write!(f, r#""class": {}, "#, self.class)?;
+        write!(f, r#""subclass": {}, "#, self.subclass)?;
+        write!(f, r#""protocol": {}, "#, self.protocol)?;
+        write!(f, r#""max_packet_size0": {}, "#, self.max_packet_size0)?;
+        write!(f, r#""vendor_id": {}, "#, self.vendor_id)?;
+        write!(f, r#""product_id": {}, "#, self.product_id)?;
+        write!(f, r#""device_version": {}, "#, self.device_version)?;
+        write!(f, r#""num_configurations": {}"#, self.num_configurations)?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_descriptor() -> Result<()> {
+        let dev = DeviceDescriptor::parse(&[
+            0x12, 0x01, 0x00, 0x02, 0xef, 0x02, 0x01, 0x40, 0x83, 0x04, 0x40, 0x57, 0x00, 0x01,
+            0x01, 0x02, 0x03, 0x01,
+        ])?;
+
+        assert_eq!(dev.usb_version(), 0x0200);
+        assert_eq!(dev.class(), 0xef);
+        assert_eq!(dev.subclass(), 0x02);
+        assert_eq!(dev.protocol(), 0x01);
+        assert_eq!(dev.max_packet_size0(), 64);
+        assert_eq!(dev.vendor_id(), 0x0483);
+        assert_eq!(dev.product_id(), 0x5740);
+        assert_eq!(dev.device_version(), 0x0100);
+        assert_eq!(dev.manufacturer_index(), 1);
+        assert_eq!(dev.product_index(), 2);
+        assert_eq!(dev.serial_number_index(), 3);
+        assert_eq!(dev.num_configurations(), 1);
+
+        assert!(DeviceDescriptor::parse(&[0x12, 0x01, 0x00]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+
+use super::{read_u16, DESCRIPTOR_TYPE_ENDPOINT};
+use crate::{Endpoint, Error, Result};
+
+pub const ENDPOINT_DIR_IN: u8 = 0x80;
+pub const ENDPOINT_NUMBER_MASK: u8 = 0x0f;
+pub const TRANSFER_TYPE_MASK: u8 = 0x03;
+
+pub const TRANSFER_TYPE_CONTROL: u8 = 0x00;
+pub const TRANSFER_TYPE_ISOCHRONOUS: u8 = 0x01;
+pub const TRANSFER_TYPE_BULK: u8 = 0x02;
+pub const TRANSFER_TYPE_INTERRUPT: u8 = 0x03;
+
+/// Represents the transfer type of a USB endpoint.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferType {
+    #[default]
+    Control = TRANSFER_TYPE_CONTROL,
+    Isochronous = TRANSFER_TYPE_ISOCHRONOUS,
+    Bulk = TRANSFER_TYPE_BULK,
+    Interrupt = TRANSFER_TYPE_INTERRUPT,
+}
+
+impl TransferType {
+    /// Creates a new [TransferType].
+    pub const fn new() -> Self {
+        Self::Control
+    }
+
+    /// Creates a new [TransferType] from the provided endpoint attributes.
+    pub const fn create(val: u8) -> Self {
+        match val & TRANSFER_TYPE_MASK {
+            TRANSFER_TYPE_ISOCHRONOUS => Self::Isochronous,
+            TRANSFER_TYPE_BULK => Self::Bulk,
+            TRANSFER_TYPE_INTERRUPT => Self::Interrupt,
+            _ => Self::Control,
+        }
+    }
+
+    /// Gets the inner representation of the [TransferType].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&TransferType> for &'static str {
+    fn from(val: &TransferType) -> Self {
+        match val {
+            TransferType::Control => "control",
+            TransferType::Isochronous => "isochronous",
+            TransferType::Bulk => "bulk",
+            TransferType::Interrupt => "interrupt",
+        }
+    }
+}
+
+impl From<TransferType> for &'static str {
+    fn from(val: TransferType) -> Self {
+        (&val).into()
+    }
+}
+
+impl From<u8> for TransferType {
+    fn from(val: u8) -> Self {
+        Self::create(val)
+    }
+}
+
+impl fmt::Display for TransferType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a USB endpoint descriptor.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndpointDescriptor {
+    address: u8,
+    attributes: u8,
+    max_packet_size: u16,
+    interval: u8,
+    refresh: u8,
+    synch_address: u8,
+    extra: Vec<u8>,
+}
+
+impl EndpointDescriptor {
+    /// Length of a standard endpoint descriptor.
+    pub const LEN: usize = 7;
+    /// Length of an audio endpoint descriptor.
+    pub const AUDIO_LEN: usize = 9;
+
+    /// Creates a new [EndpointDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            address: 0,
+            attributes: 0,
+            max_packet_size: 0,
+            interval: 0,
+            refresh: 0,
+            synch_address: 0,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Parses an [EndpointDescriptor] from a raw descriptor.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || buf[1] != DESCRIPTOR_TYPE_ENDPOINT {
+            return Err(Error::InvalidDescriptor(format!(
+                "invalid endpoint descriptor, length: {}",
+                buf.len()
+            )));
+        }
+
+        let audio = buf.len() >= Self::AUDIO_LEN;
+
+        Ok(Self {
+            address: buf[2],
+            attributes: buf[3],
+            max_packet_size: read_u16(buf, 4),
+            interval: buf[6],
+            refresh: if audio { buf[7] } else { 0 },
+            synch_address: if audio { buf[8] } else { 0 },
+            extra: Vec::new(),
+        })
+    }
+
+    /// Gets the endpoint address.
+    pub const fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Gets the typed [Endpoint] address.
+    pub const fn endpoint(&self) -> Endpoint {
+        Endpoint::from_address(self.address)
+    }
+
+    /// Gets the endpoint number.
+    pub const fn number(&self) -> u8 {
+        self.address & ENDPOINT_NUMBER_MASK
+    }
+
+    /// Gets whether the endpoint direction is `IN` (device-to-host).
+    pub const fn is_in(&self) -> bool {
+        self.address & ENDPOINT_DIR_IN != 0
+    }
+
+    /// Gets the endpoint attributes.
+    pub const fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Gets the endpoint [TransferType].
+    pub const fn transfer_type(&self) -> TransferType {
+        TransferType::create(self.attributes)
+    }
+
+    /// Gets the raw `wMaxPacketSize` field.
+    pub const fn max_packet_size(&self) -> u16 {
+        self.max_packet_size
+    }
+
+    /// Gets the maximum packet size, without the high-bandwidth multiplier bits.
+    pub const fn packet_size(&self) -> u16 {
+        self.max_packet_size & 0x7ff
+    }
+
+    /// Gets the number of transactions per microframe for high-bandwidth endpoints.
+    pub const fn transactions(&self) -> u16 {
+        ((self.max_packet_size >> 11) & 0x3) + 1
+    }
+
+    /// Gets the polling interval.
+    pub const fn interval(&self) -> u8 {
+        self.interval
+    }
+
+    /// Gets the audio refresh rate.
+    pub const fn refresh(&self) -> u8 {
+        self.refresh
+    }
+
+    /// Gets the audio synchronization endpoint address.
+    pub const fn synch_address(&self) -> u8 {
+        self.synch_address
+    }
+
+    /// Gets the extra (class-specific and companion) descriptors.
+    pub fn extra(&self) -> &[u8] {
+        self.extra.as_ref()
+    }
+
+    pub(crate) fn extend_extra(&mut self, extra: &[u8]) {
+        self.extra.extend_from_slice(extra);
+    }
+}
+
+impl fmt::Display for EndpointDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""address": {}, "#, self.address)?;
+        write!(f, r#""attributes": {}, "#, self.attributes)?;
+        write!(f, r#""transfer_type": {}, "#, self.transfer_type())?;
+        write!(f, r#""max_packet_size": {}, "#, self.max_packet_size)?;
+        write!(f, r#""interval": {}"#, self.interval)?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_descriptor() -> Result<()> {
+        let ep = EndpointDescriptor::parse(&[0x07, 0x05, 0x81, 0x02, 0x00, 0x02, 0x00])?;
+
+        assert_eq!(ep.address(), 0x81);
+        assert_eq!(ep.number(), 1);
+        assert!(ep.is_in());
+        assert_eq!(ep.transfer_type(), TransferType::Bulk);
+        assert_eq!(ep.max_packet_size(), 512);
+        assert_eq!(ep.interval(), 0);
+
+        let ep =
+            EndpointDescriptor::parse(&[0x09, 0x05, 0x02, 0x05, 0x00, 0x14, 0x01, 0x00, 0x82])?;
+
+        assert!(!ep.is_in());
+        assert_eq!(ep.transfer_type(), TransferType::Isochronous);
+        assert_eq!(ep.packet_size(), 1024);
+        assert_eq!(ep.transactions(), 3);
+        assert_eq!(ep.synch_address(), 0x82);
+
+        assert!(EndpointDescriptor::parse(&[0x07, 0x04, 0x81, 0x02, 0x00, 0x02, 0x00]).is_err());
+        assert!(EndpointDescriptor::parse(&[0x05, 0x05, 0x81, 0x02, 0x00]).is_err());
+
+        Ok(())
+    }
+}
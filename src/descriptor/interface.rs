@@ -0,0 +1,175 @@
+use std::fmt;
+
+use super::{EndpointDescriptor, DESCRIPTOR_TYPE_INTERFACE};
+use crate::{Error, Result};
+
+/// Represents a USB interface descriptor, for a single alternate setting.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceDescriptor {
+    number: u8,
+    alternate_setting: u8,
+    num_endpoints: u8,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    interface_index: u8,
+    endpoints: Vec<EndpointDescriptor>,
+    extra: Vec<u8>,
+}
+
+impl InterfaceDescriptor {
+    /// Length of a standard interface descriptor.
+    pub const LEN: usize = 9;
+
+    /// Creates a new [InterfaceDescriptor].
+    pub const fn new() -> Self {
+        Self {
+            number: 0,
+            alternate_setting: 0,
+            num_endpoints: 0,
+            class: 0,
+            subclass: 0,
+            protocol: 0,
+            interface_index: 0,
+            endpoints: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    /// Parses an [InterfaceDescriptor] from a raw descriptor.
+    ///
+    /// **NOTE** endpoints are added while parsing the full configuration.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::LEN || buf[1] != DESCRIPTOR_TYPE_INTERFACE {
+            return Err(Error::InvalidDescriptor(format!(
+                "invalid interface descriptor, length: {}",
+                buf.len()
+            )));
+        }
+
+        Ok(Self {
+            number: buf[2],
+            alternate_setting: buf[3],
+            num_endpoints: buf[4],
+            class: buf[5],
+            subclass: buf[6],
+            protocol: buf[7],
+            interface_index: buf[8],
+            endpoints: Vec::new(),
+            extra: Vec::new(),
+        })
+    }
+
+    /// Gets the interface number.
+    pub const fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Gets the alternate setting.
+    pub const fn alternate_setting(&self) -> u8 {
+        self.alternate_setting
+    }
+
+    /// Gets the number of endpoints, as reported by the descriptor.
+    pub const fn num_endpoints(&self) -> u8 {
+        self.num_endpoints
+    }
+
+    /// Gets the interface class.
+    pub const fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Gets the interface subclass.
+    pub const fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Gets the interface protocol.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets the interface string descriptor index.
+    pub const fn interface_index(&self) -> u8 {
+        self.interface_index
+    }
+
+    /// Gets the list of [EndpointDescriptor]s.
+    pub fn endpoints(&self) -> &[EndpointDescriptor] {
+        self.endpoints.as_ref()
+    }
+
+    /// Gets the [EndpointDescriptor] with the provided address.
+    pub fn endpoint(&self, address: u8) -> Option<&EndpointDescriptor> {
+        self.endpoints.iter().find(|ep| ep.address() == address)
+    }
+
+    /// Gets the extra (class-specific) descriptors.
+    pub fn extra(&self) -> &[u8] {
+        self.extra.as_ref()
+    }
+
+    pub(crate) fn push_endpoint(&mut self, endpoint: EndpointDescriptor) {
+        self.endpoints.push(endpoint);
+    }
+
+    pub(crate) fn endpoints_mut(&mut self) -> &mut [EndpointDescriptor] {
+        self.endpoints.as_mut()
+    }
+
+    pub(crate) fn extend_extra(&mut self, extra: &[u8]) {
+        self.extra.extend_from_slice(extra);
+    }
+}
+
+impl fmt::Display for InterfaceDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""number": {}, "#, self.number)?;
+        write!(f, r#""alternate_setting": {}, "#, self.alternate_setting)?;
+        write!(f, r#""class": {}, "#, self.class)?;
+        write!(f, r#""subclass": {}, "#, self.subclass)?;
+        write!(f, r#""protocol": {}, "#, self.protocol)?;
+        write!(f, r#""endpoints": ["#)?;
+        for (i, ep) in self.endpoints.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{ep}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_descriptor() -> Result<()> {
+        let mut iface =
+            InterfaceDescriptor::parse(&[0x09, 0x04, 0x01, 0x02, 0x01, 0x0a, 0x00, 0x00, 0x05])?;
+
+        assert_eq!(iface.number(), 1);
+        assert_eq!(iface.alternate_setting(), 2);
+        assert_eq!(iface.num_endpoints(), 1);
+        assert_eq!(iface.class(), 0x0a);
+        assert_eq!(iface.subclass(), 0);
+        assert_eq!(iface.protocol(), 0);
+        assert_eq!(iface.interface_index(), 5);
+        assert!(iface.endpoints().is_empty());
+
+        iface.push_endpoint(EndpointDescriptor::parse(&[
+            0x07, 0x05, 0x81, 0x02, 0x40, 0x00, 0x00,
+        ])?);
+
+        assert!(iface.endpoint(0x81).is_some());
+        assert!(iface.endpoint(0x01).is_none());
+
+        assert!(InterfaceDescriptor::parse(&[0x09, 0x05, 0, 0, 0, 0, 0, 0, 0]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::{fmt, os::unix::fs::OpenOptionsExt};
+
+use crate::{
+    Descriptors, Result, UsbfsBulkTransfer, UsbfsCtrlTransfer, UsbfsSpeed, USBFS_DEVICE_PATH,
+};
+
+mod claim;
+mod driver;
+mod halt;
+mod passing;
+mod reset;
+mod urb;
+
+pub use claim::ClaimedInterface;
+pub use driver::DetachPolicy;
+pub use halt::{is_stall, StallPolicy};
+pub(crate) use reset::reopen_at_port;
+pub use reset::ResetStatus;
+pub use urb::{UrbId, UrbStatus};
+
+/// USB standard `GET_CONFIGURATION` request.
+const REQUEST_GET_CONFIGURATION: u8 = 0x08;
+/// Default timeout (in milliseconds) for standard control requests.
+const DEFAULT_CTRL_TIMEOUT: u32 = 1000;
+
+/// Interface and URB bookkeeping shared by a [UsbDevice] and its [ClaimedInterface]s.
+#[derive(Debug, Default)]
+struct DeviceState {
+    detached_interfaces: Vec<u32>,
+    // claimed interface number, and its active alternate setting
+    claimed_interfaces: Vec<(u32, u8)>,
+    urbs: urb::UrbQueue,
+    disconnected: bool,
+}
+
+/// Represents an opened USBFS device node.
+///
+/// The device owns the underlying file descriptor, and closes it on drop.
+#[derive(Debug)]
+pub struct UsbDevice {
+    // only taken when the device is consumed, see `into_fd`
+    fd: Option<OwnedFd>,
+    path: PathBuf,
+    bus_num: u8,
+    dev_num: u8,
+    descriptors: Vec<u8>,
+    allowed_interfaces: Option<u32>,
+    auto_reattach: bool,
+    stall_policy: StallPolicy,
+    state: Mutex<DeviceState>,
+    urb_cond: Condvar,
+}
+
+impl UsbDevice {
+    /// Opens a USBFS device node for reading and writing.
+    ///
+    /// The bus and device numbers are parsed from the path, when it follows the
+    /// `/dev/bus/usb/BBB/DDD` layout.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, OpenOptions::new().read(true).write(true))
+    }
+
+    /// Opens a USBFS device node read-only.
+    ///
+    /// Read-only nodes can still be used to read descriptors, and for most informational `ioctl`
+    /// calls.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, OpenOptions::new().read(true))
+    }
+
+    /// Opens the USBFS device node for the provided bus and device numbers.
+    pub fn open_bus_dev(bus_num: u8, dev_num: u8) -> Result<Self> {
+        Self::open(format!("{USBFS_DEVICE_PATH}/{bus_num:03}/{dev_num:03}"))
+    }
+
+    fn open_with<P: AsRef<Path>>(path: P, opts: &mut OpenOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = opts.custom_flags(nix::libc::O_CLOEXEC).open(path)?;
+
+        // usbfs nodes return the device descriptor, followed by all configuration descriptors
+        let mut descriptors = Vec::new();
+        file.read_to_end(&mut descriptors)?;
+
+        let (bus_num, dev_num) = parse_bus_dev(path).unwrap_or((0, 0));
+
+        Ok(Self::from_fd(file.into())
+            .with_path(path)
+            .with_bus_num(bus_num)
+            .with_dev_num(dev_num)
+            .with_descriptors(descriptors))
+    }
+
+    /// Creates a new [UsbDevice] from an already opened file descriptor.
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        Self {
+            fd: Some(fd),
+            path: PathBuf::new(),
+            bus_num: 0,
+            dev_num: 0,
+            descriptors: Vec::new(),
+            allowed_interfaces: None,
+            auto_reattach: false,
+            stall_policy: StallPolicy::new(),
+            state: Mutex::new(DeviceState::default()),
+            urb_cond: Condvar::new(),
+        }
+    }
+
+    /// Gets the raw file descriptor.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_ref().map_or(-1, |fd| fd.as_raw_fd())
+    }
+
+    /// Gets the path used to open the device.
+    ///
+    /// **NOTE** the path is empty for devices created from a bare file descriptor.
+    pub fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+
+    /// Sets the device path.
+    pub fn set_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.path = path.into();
+    }
+
+    /// Builder function that sets the device path.
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.set_path(path);
+        self
+    }
+
+    /// Gets the bus number.
+    pub const fn bus_num(&self) -> u8 {
+        self.bus_num
+    }
+
+    /// Sets the bus number.
+    pub fn set_bus_num(&mut self, bus_num: u8) {
+        self.bus_num = bus_num;
+    }
+
+    /// Builder function that sets the bus number.
+    pub fn with_bus_num(mut self, bus_num: u8) -> Self {
+        self.set_bus_num(bus_num);
+        self
+    }
+
+    /// Gets the device number.
+    pub const fn dev_num(&self) -> u8 {
+        self.dev_num
+    }
+
+    /// Sets the device number.
+    pub fn set_dev_num(&mut self, dev_num: u8) {
+        self.dev_num = dev_num;
+    }
+
+    /// Builder function that sets the device number.
+    pub fn with_dev_num(mut self, dev_num: u8) -> Self {
+        self.set_dev_num(dev_num);
+        self
+    }
+
+    /// Gets the raw descriptors read from the device node.
+    pub fn descriptors(&self) -> &[u8] {
+        self.descriptors.as_ref()
+    }
+
+    /// Sets the raw descriptors.
+    pub fn set_descriptors<D: IntoIterator<Item = u8>>(&mut self, descriptors: D) {
+        self.descriptors = descriptors.into_iter().collect();
+    }
+
+    /// Builder function that sets the raw descriptors.
+    pub fn with_descriptors<D: IntoIterator<Item = u8>>(mut self, descriptors: D) -> Self {
+        self.set_descriptors(descriptors);
+        self
+    }
+
+    /// Parses the raw descriptors.
+    pub fn parsed_descriptors(&self) -> Result<Descriptors> {
+        Descriptors::parse(self.descriptors.as_ref())
+    }
+
+    /// Gets the interface mask set by [drop_privileges](Self::drop_privileges), if any.
+    pub const fn allowed_interfaces(&self) -> Option<u32> {
+        self.allowed_interfaces
+    }
+
+    /// Drops the privileges of the device file descriptor.
+    ///
+    /// After dropping privileges, only interfaces set in the `allowed_interfaces` bitmask can be
+    /// claimed, and `ioctl` calls affecting the whole device are rejected by the kernel.
+    ///
+    /// **NOTE** privileges are attached to the open file description, so they also apply to
+    /// every duplicate of the file descriptor. Dropped privileges cannot be regained.
+    pub fn drop_privileges(&mut self, allowed_interfaces: u32) -> Result<()> {
+        self.io(|fd| crate::usbfs_drop_privileges(fd, allowed_interfaces as u64))?;
+        self.allowed_interfaces = Some(
+            self.allowed_interfaces
+                .map_or(allowed_interfaces, |a| a & allowed_interfaces),
+        );
+        Ok(())
+    }
+
+    /// Performs a synchronous control transfer.
+    ///
+    /// Returns the number of bytes transferred during the data stage.
+    pub fn control_transfer(&self, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
+        self.io(|fd| crate::usbfs_control(fd, ctrl))
+    }
+
+    /// Performs a synchronous read from a Bulk or Interrupt `IN` endpoint.
+    ///
+    /// Returns the number of bytes read into the buffer. Stalls are handled according to the
+    /// [StallPolicy].
+    ///
+    /// **NOTE** reads at most [`MAX_BULK_BUFFER_LENGTH`](crate::MAX_BULK_BUFFER_LENGTH) bytes.
+    pub fn read_endpoint(&self, ep: u8, buf: &mut [u8], timeout: u32) -> Result<usize> {
+        let mut bulk = UsbfsBulkTransfer::create(ep as u32, timeout, vec![0u8; buf.len()]);
+        let len = halt::recover_stall(
+            self.stall_policy,
+            || self.io(|fd| crate::usbfs_bulk(fd, &mut bulk)),
+            || self.clear_halt(ep.into()),
+        )?
+        .min(bulk.data().len());
+
+        buf[..len].copy_from_slice(&bulk.data()[..len]);
+
+        Ok(len)
+    }
+
+    /// Performs a synchronous write to a Bulk or Interrupt `OUT` endpoint.
+    ///
+    /// Returns the number of bytes written. Stalls are handled according to the
+    /// [StallPolicy].
+    ///
+    /// **NOTE** writes at most [`MAX_BULK_BUFFER_LENGTH`](crate::MAX_BULK_BUFFER_LENGTH) bytes.
+    pub fn write_endpoint(&self, ep: u8, data: &[u8], timeout: u32) -> Result<usize> {
+        let mut bulk = UsbfsBulkTransfer::create(ep as u32, timeout, data.iter().copied());
+        halt::recover_stall(
+            self.stall_policy,
+            || self.io(|fd| crate::usbfs_bulk(fd, &mut bulk)),
+            || self.clear_halt(ep.into()),
+        )
+    }
+
+    /// Gets the active configuration value, using a standard `GET_CONFIGURATION` request.
+    pub fn active_configuration(&self) -> Result<u8> {
+        let mut ctrl = UsbfsCtrlTransfer::new()
+            .with_request_type(crate::descriptor::ENDPOINT_DIR_IN)
+            .with_request(REQUEST_GET_CONFIGURATION)
+            .with_timeout(DEFAULT_CTRL_TIMEOUT)
+            .with_data([0u8]);
+
+        self.control_transfer(&mut ctrl)?;
+
+        Ok(ctrl.data()[0])
+    }
+
+    /// Selects the active configuration.
+    ///
+    /// **NOTE** the kernel refuses the change while interfaces are claimed by other users.
+    pub fn set_configuration(&self, configuration_value: u8) -> Result<()> {
+        let mut config = configuration_value as u32;
+        self.io(|fd| crate::usbfs_set_configuration(fd, &mut config))
+    }
+
+    /// Gets the [UsbfsSpeed] the device is operating at.
+    pub fn speed(&self) -> Result<UsbfsSpeed> {
+        self.io(crate::usbfs_get_speed)
+    }
+
+    fn state(&self) -> MutexGuard<'_, DeviceState> {
+        // bookkeeping stays consistent even if a holder panicked
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Converts the [UsbDevice] into a [File] handle.
+    ///
+    /// In-flight URBs are cancelled first, as their buffers are freed with the device.
+    pub fn into_file(self) -> File {
+        self.into_fd().into()
+    }
+
+    fn into_fd(mut self) -> OwnedFd {
+        self.cancel_all();
+        self.fd
+            .take()
+            .expect("the file descriptor is only taken when the device is consumed")
+    }
+}
+
+impl Drop for UsbDevice {
+    fn drop(&mut self) {
+        // URB buffers are owned by `state`: the kernel must be done with them before they are
+        // freed, so in-flight URBs are cancelled, and the file descriptor is closed first
+        self.cancel_all();
+        self.fd.take();
+    }
+}
+
+impl AsFd for UsbDevice {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd
+            .as_ref()
+            .expect("the file descriptor is only taken when the device is consumed")
+            .as_fd()
+    }
+}
+
+impl AsRawFd for UsbDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
+impl From<UsbDevice> for OwnedFd {
+    fn from(val: UsbDevice) -> Self {
+        val.into_fd()
+    }
+}
+
+impl fmt::Display for UsbDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""path": "{}", "#, self.path.display())?;
+        write!(f, r#""bus_num": {}, "#, self.bus_num)?;
+        write!(f, r#""dev_num": {}, "#, self.dev_num)?;
+        write!(f, r#""descriptors_len": {}"#, self.descriptors.len())?;
+        write!(f, "}}")
+    }
+}
+
+/// Parses the bus and device numbers from a `/dev/bus/usb/BBB/DDD` style path.
+fn parse_bus_dev(path: &Path) -> Option<(u8, u8)> {
+    let mut components = path.iter().rev();
+    let dev_num = components.next()?.to_str()?.parse::<u8>().ok()?;
+    let bus_num = components.next()?.to_str()?.parse::<u8>().ok()?;
+
+    Some((bus_num, dev_num))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bus_dev() {
+        assert_eq!(
+            parse_bus_dev(Path::new("/dev/bus/usb/001/042")),
+            Some((1, 42))
+        );
+        assert_eq!(parse_bus_dev(Path::new("003/004")), Some((3, 4)));
+        assert_eq!(parse_bus_dev(Path::new("/dev/null")), None);
+        assert_eq!(parse_bus_dev(Path::new("/dev/bus/usb/001/256")), None);
+    }
+}
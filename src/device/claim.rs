@@ -0,0 +1,344 @@
+//! RAII guard for claimed [UsbDevice] interfaces.
+
+use std::fmt;
+
+use super::UsbDevice;
+use crate::{
+    DetachPolicy, EndpointDescriptor, Error, InterfaceDescriptor, Result, TransferType,
+    UsbfsSetInterface,
+};
+
+/// Represents a claimed interface of a [UsbDevice].
+///
+/// The interface is released when the guard is dropped. Endpoint I/O is only allowed on
+/// endpoints of the active alternate setting, according to the device descriptors.
+pub struct ClaimedInterface<'a> {
+    device: &'a UsbDevice,
+    interface: u32,
+    alt_settings: Vec<InterfaceDescriptor>,
+    reattach: bool,
+    released: bool,
+}
+
+impl UsbDevice {
+    /// Claims the interface, returning a [ClaimedInterface] guard.
+    ///
+    /// Returns an [Error::AlreadyClaimed] error if the interface is claimed through this
+    /// [UsbDevice].
+    pub fn claim(&self, iface: u32) -> Result<ClaimedInterface<'_>> {
+        self.check_unclaimed(iface)?;
+        let alt_settings = self.interface_alt_settings(iface);
+
+        self.claim_interface(iface)?;
+
+        Ok(ClaimedInterface::new(self, iface, alt_settings))
+    }
+
+    /// Claims the interface, detaching the bound kernel driver according to the
+    /// [DetachPolicy], and returns a [ClaimedInterface] guard.
+    pub fn claim_detaching(
+        &self,
+        iface: u32,
+        policy: &DetachPolicy,
+    ) -> Result<ClaimedInterface<'_>> {
+        self.check_unclaimed(iface)?;
+        let alt_settings = self.interface_alt_settings(iface);
+
+        self.claim_interface_detaching(iface, policy)?;
+
+        Ok(ClaimedInterface::new(self, iface, alt_settings))
+    }
+
+    /// Gets the active alternate setting of a claimed interface.
+    pub fn alt_setting(&self, iface: u32) -> Option<u8> {
+        self.state()
+            .claimed_interfaces
+            .iter()
+            .find(|&&(i, _)| i == iface)
+            .map(|&(_, alt)| alt)
+    }
+
+    /// Sets the alternate setting of the interface.
+    pub fn set_alt_setting(&self, iface: u32, alt_setting: u8) -> Result<()> {
+        let mut set_iface = UsbfsSetInterface::create(iface, alt_setting as u32);
+        self.io(|fd| crate::usbfs_set_interface(fd, &mut set_iface))?;
+
+        if let Some(claim) = self
+            .state()
+            .claimed_interfaces
+            .iter_mut()
+            .find(|(i, _)| *i == iface)
+        {
+            claim.1 = alt_setting;
+        }
+
+        Ok(())
+    }
+
+    fn check_unclaimed(&self, iface: u32) -> Result<()> {
+        if self.claimed_interfaces().contains(&iface) {
+            Err(Error::AlreadyClaimed(iface))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Gets the alternate settings of the interface in the active configuration.
+    ///
+    /// Falls back to the first configuration if the active one cannot be queried.
+    fn interface_alt_settings(&self, iface: u32) -> Vec<InterfaceDescriptor> {
+        let Ok(descriptors) = self.parsed_descriptors() else {
+            return Vec::new();
+        };
+
+        let config = self
+            .active_configuration()
+            .ok()
+            .and_then(|value| descriptors.config(value))
+            .or(descriptors.configs().first());
+
+        config
+            .map(|c| {
+                c.interfaces()
+                    .iter()
+                    .filter(|i| i.number() as u32 == iface)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl<'a> ClaimedInterface<'a> {
+    fn new(device: &'a UsbDevice, interface: u32, alt_settings: Vec<InterfaceDescriptor>) -> Self {
+        Self {
+            device,
+            interface,
+            alt_settings,
+            reattach: device.auto_reattach(),
+            released: false,
+        }
+    }
+
+    /// Gets the [UsbDevice] owning the interface.
+    pub const fn device(&self) -> &'a UsbDevice {
+        self.device
+    }
+
+    /// Gets the interface number.
+    pub const fn interface(&self) -> u32 {
+        self.interface
+    }
+
+    /// Gets the active alternate setting.
+    ///
+    /// Read from the owning [UsbDevice], so changes made through
+    /// [UsbDevice::set_alt_setting] are reflected.
+    pub fn alt_setting(&self) -> u8 {
+        self.device.alt_setting(self.interface).unwrap_or(0)
+    }
+
+    /// Gets the [InterfaceDescriptor]s for every alternate setting of the interface.
+    pub fn alt_settings(&self) -> &[InterfaceDescriptor] {
+        self.alt_settings.as_ref()
+    }
+
+    /// Gets the [InterfaceDescriptor] of the active alternate setting.
+    pub fn descriptor(&self) -> Option<&InterfaceDescriptor> {
+        self.alt_settings
+            .iter()
+            .find(|i| i.alternate_setting() == self.alt_setting())
+    }
+
+    /// Gets the [EndpointDescriptor]s of the active alternate setting.
+    pub fn endpoints(&self) -> &[EndpointDescriptor] {
+        self.descriptor().map(|d| d.endpoints()).unwrap_or(&[])
+    }
+
+    /// Selects the alternate setting of the interface.
+    pub fn set_alt_setting(&mut self, alt_setting: u8) -> Result<()> {
+        if !self
+            .alt_settings
+            .iter()
+            .any(|i| i.alternate_setting() == alt_setting)
+        {
+            return Err(Error::InvalidAltSetting(alt_setting));
+        }
+
+        self.device.set_alt_setting(self.interface, alt_setting)
+    }
+
+    /// Gets whether the kernel driver is attached again on release.
+    pub const fn reattach(&self) -> bool {
+        self.reattach
+    }
+
+    /// Sets whether the kernel driver is attached again on release.
+    ///
+    /// Only applies if the kernel driver was detached through the owning [UsbDevice].
+    pub fn set_reattach(&mut self, reattach: bool) {
+        self.reattach = reattach;
+    }
+
+    /// Builder function that sets whether the kernel driver is attached again on release.
+    pub fn with_reattach(mut self, reattach: bool) -> Self {
+        self.set_reattach(reattach);
+        self
+    }
+
+    /// Gets the [EndpointDescriptor] for the endpoint address in the active alternate setting,
+    /// checking the transfer type and direction.
+    pub fn check_endpoint(
+        &self,
+        ep: u8,
+        transfer_type: TransferType,
+        is_in: bool,
+    ) -> Result<&EndpointDescriptor> {
+        self.endpoints()
+            .iter()
+            .find(|e| e.address() == ep)
+            .filter(|e| e.transfer_type() == transfer_type && e.is_in() == is_in)
+            .ok_or(Error::InvalidEndpoint(ep))
+    }
+
+    /// Reads from a Bulk `IN` endpoint of the active alternate setting.
+    pub fn read_bulk(&self, ep: u8, buf: &mut [u8], timeout: u32) -> Result<usize> {
+        self.check_endpoint(ep, TransferType::Bulk, true)?;
+        self.device.read_endpoint(ep, buf, timeout)
+    }
+
+    /// Writes to a Bulk `OUT` endpoint of the active alternate setting.
+    pub fn write_bulk(&self, ep: u8, data: &[u8], timeout: u32) -> Result<usize> {
+        self.check_endpoint(ep, TransferType::Bulk, false)?;
+        self.device.write_endpoint(ep, data, timeout)
+    }
+
+    /// Reads from an Interrupt `IN` endpoint of the active alternate setting.
+    pub fn read_interrupt(&self, ep: u8, buf: &mut [u8], timeout: u32) -> Result<usize> {
+        self.check_endpoint(ep, TransferType::Interrupt, true)?;
+        self.device.read_endpoint(ep, buf, timeout)
+    }
+
+    /// Writes to an Interrupt `OUT` endpoint of the active alternate setting.
+    pub fn write_interrupt(&self, ep: u8, data: &[u8], timeout: u32) -> Result<usize> {
+        self.check_endpoint(ep, TransferType::Interrupt, false)?;
+        self.device.write_endpoint(ep, data, timeout)
+    }
+
+    /// Releases the interface, reporting any error.
+    ///
+    /// Dropping the guard also releases the interface, but ignores errors.
+    pub fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.device
+            .release_interface_reattaching(self.interface, self.reattach)
+    }
+}
+
+impl Drop for ClaimedInterface<'_> {
+    fn drop(&mut self) {
+        if !self.released {
+            self.device
+                .release_interface_reattaching(self.interface, self.reattach)
+                .ok();
+        }
+    }
+}
+
+impl fmt::Debug for ClaimedInterface<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClaimedInterface")
+            .field("interface", &self.interface)
+            .field("alt_setting", &self.alt_setting())
+            .field("reattach", &self.reattach)
+            .finish()
+    }
+}
+
+impl fmt::Display for ClaimedInterface<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""interface": {}, "#, self.interface)?;
+        write!(f, r#""alt_setting": {}"#, self.alt_setting())?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::tests::TEST_DESCRIPTORS;
+    use crate::Descriptors;
+
+    fn test_device() -> UsbDevice {
+        let file = std::fs::File::open("/dev/null").unwrap();
+        UsbDevice::from_fd(file.into()).with_descriptors(TEST_DESCRIPTORS)
+    }
+
+    #[test]
+    fn test_claimed_interface_endpoints() -> Result<()> {
+        let dev = test_device();
+        let config = Descriptors::parse(dev.descriptors())?.configs()[0].clone();
+        let alt_settings: Vec<InterfaceDescriptor> = config.alt_settings(2).cloned().collect();
+
+        let mut claim = ClaimedInterface::new(&dev, 2, alt_settings);
+        // nothing was claimed on the kernel side
+        claim.released = true;
+
+        assert_eq!(claim.alt_setting(), 0);
+        assert!(claim.endpoints().is_empty());
+        assert_eq!(
+            claim.check_endpoint(0x83, TransferType::Isochronous, true),
+            Err(Error::InvalidEndpoint(0x83))
+        );
+
+        // switching the alt setting fails on a non-USBFS file, state is unchanged
+        assert!(claim.set_alt_setting(1).is_err());
+        assert_eq!(claim.alt_setting(), 0);
+        assert_eq!(claim.set_alt_setting(2), Err(Error::InvalidAltSetting(2)));
+
+        // the active setting is shared with the device
+        dev.state().claimed_interfaces.push((2, 1));
+
+        assert_eq!(claim.endpoints().len(), 1);
+        assert!(claim
+            .check_endpoint(0x83, TransferType::Isochronous, true)
+            .is_ok());
+        assert_eq!(
+            claim.check_endpoint(0x83, TransferType::Bulk, true),
+            Err(Error::InvalidEndpoint(0x83))
+        );
+        assert_eq!(
+            claim.read_bulk(0x81, &mut [0u8; 8], 100),
+            Err(Error::InvalidEndpoint(0x81))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_claimed_interface_data() -> Result<()> {
+        let dev = test_device();
+        let config = Descriptors::parse(dev.descriptors())?.configs()[0].clone();
+        let alt_settings: Vec<InterfaceDescriptor> = config.alt_settings(1).cloned().collect();
+
+        let mut claim = ClaimedInterface::new(&dev, 1, alt_settings);
+        claim.released = true;
+
+        assert!(claim.check_endpoint(0x81, TransferType::Bulk, true).is_ok());
+        assert!(claim
+            .check_endpoint(0x01, TransferType::Bulk, false)
+            .is_ok());
+        // wrong direction
+        assert_eq!(
+            claim.write_bulk(0x81, &[0u8], 100),
+            Err(Error::InvalidEndpoint(0x81))
+        );
+        assert_eq!(
+            claim.write_interrupt(0x01, &[0u8], 100),
+            Err(Error::InvalidEndpoint(0x01))
+        );
+
+        Ok(())
+    }
+}
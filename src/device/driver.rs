@@ -0,0 +1,250 @@
+//! Kernel driver management for [UsbDevice] interfaces.
+
+use std::fmt;
+
+use nix::errno::Errno;
+
+use super::UsbDevice;
+use crate::{
+    Error, Result, UsbfsDisconnectClaim, UsbfsDisconnectClaimFlag, UsbfsGetDriver, UsbfsIoctl,
+    USBFS_DRIVER_NAME, USBFS_IOCTL_CONNECT, USBFS_IOCTL_DISCONNECT,
+};
+
+/// Represents the policy for detaching a kernel driver when claiming an interface.
+///
+/// Maps onto the [UsbfsDisconnectClaimFlag] semantics of `USBDEVFS_DISCONNECT_CLAIM`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DetachPolicy {
+    /// Detach any bound kernel driver.
+    #[default]
+    Always,
+    /// Only detach the bound kernel driver if its name matches.
+    IfDriver(String),
+    /// Detach the bound kernel driver unless its name matches.
+    ExceptDriver(String),
+}
+
+impl DetachPolicy {
+    /// Creates a new [DetachPolicy].
+    pub const fn new() -> Self {
+        Self::Always
+    }
+
+    /// Gets the [UsbfsDisconnectClaimFlag] for the [DetachPolicy].
+    pub const fn flag(&self) -> UsbfsDisconnectClaimFlag {
+        match self {
+            Self::Always => UsbfsDisconnectClaimFlag::None,
+            Self::IfDriver(_) => UsbfsDisconnectClaimFlag::IfDriver,
+            Self::ExceptDriver(_) => UsbfsDisconnectClaimFlag::ExceptDriver,
+        }
+    }
+
+    /// Gets the driver name the [DetachPolicy] matches against.
+    pub fn driver(&self) -> &str {
+        match self {
+            Self::Always => "",
+            Self::IfDriver(name) | Self::ExceptDriver(name) => name.as_str(),
+        }
+    }
+
+    /// Gets whether the policy allows detaching the provided driver.
+    pub fn detaches(&self, driver: &str) -> bool {
+        match self {
+            Self::Always => true,
+            Self::IfDriver(name) => name == driver,
+            Self::ExceptDriver(name) => name != driver,
+        }
+    }
+
+    /// Creates the [UsbfsDisconnectClaim] argument for the provided interface.
+    pub fn disconnect_claim(&self, iface: u32) -> UsbfsDisconnectClaim {
+        UsbfsDisconnectClaim::create(iface, self.flag(), self.driver())
+    }
+}
+
+impl fmt::Display for DetachPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""flag": {}, "#, self.flag())?;
+        write!(f, r#""driver": "{}""#, self.driver())?;
+        write!(f, "}}")
+    }
+}
+
+impl UsbDevice {
+    /// Gets the name of the kernel driver bound to the interface, if any.
+    pub fn kernel_driver(&self, iface: u32) -> Result<Option<String>> {
+        let mut get_driver = UsbfsGetDriver::new().with_interface(iface);
+
+        match self.io(|fd| crate::usbfs_get_driver(fd, &mut get_driver)) {
+            Ok(()) => Ok(Some(get_driver.driver().into())),
+            Err(Error::Errno(errno)) if errno == Errno::ENODATA as i32 => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Gets whether a kernel driver, other than USBFS, is bound to the interface.
+    pub fn kernel_driver_active(&self, iface: u32) -> Result<bool> {
+        Ok(self
+            .kernel_driver(iface)?
+            .is_some_and(|d| d != USBFS_DRIVER_NAME))
+    }
+
+    /// Detaches the kernel driver bound to the interface.
+    ///
+    /// Returns an `ENODATA` error if no driver is bound.
+    pub fn detach_kernel_driver(&self, iface: u32) -> Result<()> {
+        let mut ioctl = UsbfsIoctl::new()
+            .with_ifno(iface as i32)
+            .with_ioctl_code(USBFS_IOCTL_DISCONNECT);
+
+        self.io(|fd| crate::usbfs_ioctl(fd, &mut ioctl))?;
+        self.mark_detached(iface);
+
+        Ok(())
+    }
+
+    /// Attaches the kernel driver matching the interface.
+    ///
+    /// Returns an `EBUSY` error if a driver is already bound.
+    pub fn attach_kernel_driver(&self, iface: u32) -> Result<()> {
+        let mut ioctl = UsbfsIoctl::new()
+            .with_ifno(iface as i32)
+            .with_ioctl_code(USBFS_IOCTL_CONNECT);
+
+        self.io(|fd| crate::usbfs_ioctl(fd, &mut ioctl))?;
+        self.state().detached_interfaces.retain(|&i| i != iface);
+
+        Ok(())
+    }
+
+    /// Claims the interface.
+    pub fn claim_interface(&self, iface: u32) -> Result<()> {
+        let mut ifno = iface;
+        self.io(|fd| crate::usbfs_claim_interface(fd, &mut ifno))?;
+        self.mark_claimed(iface);
+
+        Ok(())
+    }
+
+    /// Claims the interface, detaching the bound kernel driver according to the
+    /// [DetachPolicy].
+    ///
+    /// The kernel performs the detach and claim atomically, so no other process can grab the
+    /// interface in between.
+    pub fn claim_interface_detaching(&self, iface: u32, policy: &DetachPolicy) -> Result<()> {
+        let driver = self.kernel_driver(iface)?;
+        let mut claim = policy.disconnect_claim(iface);
+
+        self.io(|fd| crate::usbfs_disconnect_claim(fd, &mut claim))?;
+        self.mark_claimed(iface);
+
+        if driver.is_some_and(|d| d != USBFS_DRIVER_NAME) {
+            self.mark_detached(iface);
+        }
+
+        Ok(())
+    }
+
+    /// Releases the interface.
+    ///
+    /// If [auto_reattach](Self::auto_reattach) is enabled, and the kernel driver was detached
+    /// through this [UsbDevice], the driver is attached again.
+    pub fn release_interface(&self, iface: u32) -> Result<()> {
+        self.release_interface_reattaching(iface, self.auto_reattach)
+    }
+
+    pub(crate) fn release_interface_reattaching(&self, iface: u32, reattach: bool) -> Result<()> {
+        let mut ifno = iface;
+        self.io(|fd| crate::usbfs_release_interface(fd, &mut ifno))?;
+        self.state().claimed_interfaces.retain(|&(i, _)| i != iface);
+
+        if reattach && self.state().detached_interfaces.contains(&iface) {
+            self.attach_kernel_driver(iface)?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets whether kernel drivers are attached again when releasing an interface.
+    pub const fn auto_reattach(&self) -> bool {
+        self.auto_reattach
+    }
+
+    /// Sets whether kernel drivers are attached again when releasing an interface.
+    pub fn set_auto_reattach(&mut self, auto_reattach: bool) {
+        self.auto_reattach = auto_reattach;
+    }
+
+    /// Builder function that sets whether kernel drivers are attached again when releasing an
+    /// interface.
+    pub fn with_auto_reattach(mut self, auto_reattach: bool) -> Self {
+        self.set_auto_reattach(auto_reattach);
+        self
+    }
+
+    /// Gets the list of interfaces with a kernel driver detached through this [UsbDevice].
+    pub fn detached_interfaces(&self) -> Vec<u32> {
+        self.state().detached_interfaces.clone()
+    }
+
+    /// Gets the list of interfaces claimed through this [UsbDevice].
+    pub fn claimed_interfaces(&self) -> Vec<u32> {
+        self.state()
+            .claimed_interfaces
+            .iter()
+            .map(|&(i, _)| i)
+            .collect()
+    }
+
+    fn mark_detached(&self, iface: u32) {
+        let mut state = self.state();
+        if !state.detached_interfaces.contains(&iface) {
+            state.detached_interfaces.push(iface);
+        }
+    }
+
+    fn mark_claimed(&self, iface: u32) {
+        let mut state = self.state();
+        if !state.claimed_interfaces.iter().any(|&(i, _)| i == iface) {
+            state.claimed_interfaces.push((iface, 0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detach_policy() {
+        let exp_driver = "cdc_acm";
+
+        let always = DetachPolicy::new();
+        let if_driver = DetachPolicy::IfDriver(exp_driver.into());
+        let except_driver = DetachPolicy::ExceptDriver(exp_driver.into());
+
+        assert_eq!(always.flag(), UsbfsDisconnectClaimFlag::None);
+        assert_eq!(if_driver.flag(), UsbfsDisconnectClaimFlag::IfDriver);
+        assert_eq!(except_driver.flag(), UsbfsDisconnectClaimFlag::ExceptDriver);
+
+        assert!(always.detaches(exp_driver));
+        assert!(always.detaches("usbhid"));
+        assert!(if_driver.detaches(exp_driver));
+        assert!(!if_driver.detaches("usbhid"));
+        assert!(!except_driver.detaches(exp_driver));
+        assert!(except_driver.detaches("usbhid"));
+
+        let claim = if_driver.disconnect_claim(2);
+
+        assert_eq!(claim.interface(), 2);
+        assert_eq!(claim.flags(), UsbfsDisconnectClaimFlag::IfDriver);
+        assert_eq!(claim.driver(), exp_driver);
+
+        let claim = always.disconnect_claim(1);
+
+        assert_eq!(claim.flags(), UsbfsDisconnectClaimFlag::None);
+        assert_eq!(claim.driver(), "");
+    }
+}
@@ -0,0 +1,176 @@
+//! Endpoint halt (stall) recovery for [UsbDevice] transfers.
+
+use std::fmt;
+
+use nix::errno::Errno;
+
+use super::UsbDevice;
+use crate::{Endpoint, Error, Result};
+
+/// Represents the policy applied when a synchronous transfer fails with a stall (`EPIPE`).
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StallPolicy {
+    /// Return the stall error to the caller.
+    #[default]
+    Fail,
+    /// Clear the halt condition on the endpoint, and retry the transfer once.
+    ClearHaltAndRetry,
+}
+
+impl StallPolicy {
+    /// Creates a new [StallPolicy].
+    pub const fn new() -> Self {
+        Self::Fail
+    }
+}
+
+impl From<&StallPolicy> for &'static str {
+    fn from(val: &StallPolicy) -> Self {
+        match val {
+            StallPolicy::Fail => "fail",
+            StallPolicy::ClearHaltAndRetry => "clear halt and retry",
+        }
+    }
+}
+
+impl From<StallPolicy> for &'static str {
+    fn from(val: StallPolicy) -> Self {
+        (&val).into()
+    }
+}
+
+impl fmt::Display for StallPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Gets whether the error is an endpoint stall.
+pub fn is_stall(err: &Error) -> bool {
+    err.errno() == Some(Errno::EPIPE as i32)
+}
+
+/// Runs the transfer, applying the [StallPolicy] if it fails with a stall.
+pub(crate) fn recover_stall<T, X, C>(policy: StallPolicy, mut transfer: X, clear: C) -> Result<T>
+where
+    X: FnMut() -> Result<T>,
+    C: FnOnce() -> Result<()>,
+{
+    match transfer() {
+        Err(err) if is_stall(&err) && policy == StallPolicy::ClearHaltAndRetry => {
+            clear()?;
+            transfer()
+        }
+        res => res,
+    }
+}
+
+impl UsbDevice {
+    /// Clears the halt (stall) condition on the endpoint.
+    ///
+    /// Sends a `CLEAR_FEATURE(ENDPOINT_HALT)` request to the device, and resets the host-side
+    /// data toggle.
+    pub fn clear_halt(&self, ep: Endpoint) -> Result<()> {
+        let mut ep = ep.address() as u32;
+        self.io(|fd| crate::usbfs_clear_halt(fd, &mut ep))
+    }
+
+    /// Resets the host-side state of the endpoint.
+    ///
+    /// Unlike [clear_halt](Self::clear_halt), no request is sent to the device.
+    pub fn reset_endpoint(&self, ep: Endpoint) -> Result<()> {
+        let mut ep = ep.address() as u32;
+        self.io(|fd| crate::usbfs_reset_ep(fd, &mut ep))
+    }
+
+    /// Gets the [StallPolicy] applied to synchronous endpoint transfers.
+    pub const fn stall_policy(&self) -> StallPolicy {
+        self.stall_policy
+    }
+
+    /// Sets the [StallPolicy] applied to synchronous endpoint transfers.
+    pub fn set_stall_policy(&mut self, policy: StallPolicy) {
+        self.stall_policy = policy;
+    }
+
+    /// Builder function that sets the [StallPolicy] applied to synchronous endpoint transfers.
+    pub fn with_stall_policy(mut self, policy: StallPolicy) -> Self {
+        self.set_stall_policy(policy);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover_stall() {
+        let stall = || Err::<usize, _>(Error::from(Errno::EPIPE));
+
+        // default policy reports the stall without clearing
+        let mut cleared = false;
+        let res = recover_stall(StallPolicy::Fail, stall, || {
+            cleared = true;
+            Ok(())
+        });
+        assert!(res.as_ref().is_err_and(is_stall));
+        assert!(!cleared);
+
+        // retry once after clearing the halt
+        let mut attempts = 0;
+        let mut cleared = false;
+        let res = recover_stall(
+            StallPolicy::ClearHaltAndRetry,
+            || {
+                attempts += 1;
+                if attempts == 1 {
+                    stall()
+                } else {
+                    Ok(42)
+                }
+            },
+            || {
+                cleared = true;
+                Ok(())
+            },
+        );
+        assert_eq!(res, Ok(42));
+        assert_eq!(attempts, 2);
+        assert!(cleared);
+
+        // only a single retry
+        let mut attempts = 0;
+        let res = recover_stall(
+            StallPolicy::ClearHaltAndRetry,
+            || {
+                attempts += 1;
+                stall()
+            },
+            || Ok(()),
+        );
+        assert!(res.as_ref().is_err_and(is_stall));
+        assert_eq!(attempts, 2);
+
+        // other errors are not retried
+        let mut attempts = 0;
+        let res = recover_stall(
+            StallPolicy::ClearHaltAndRetry,
+            || {
+                attempts += 1;
+                Err::<usize, _>(Error::from(Errno::ETIMEDOUT))
+            },
+            || Ok(()),
+        );
+        assert_eq!(res, Err(Error::from(Errno::ETIMEDOUT)));
+        assert_eq!(attempts, 1);
+
+        // failing to clear the halt reports the clear error
+        let res = recover_stall(StallPolicy::ClearHaltAndRetry, stall, || {
+            Err(Error::from(Errno::ENODEV))
+        });
+        assert_eq!(res, Err(Error::from(Errno::ENODEV)));
+    }
+}
@@ -0,0 +1,292 @@
+//! Passing opened [UsbDevice] handles between processes over Unix sockets.
+//!
+//! A privileged process (broker) opens the USBFS node, and sends the file descriptor to an
+//! unprivileged process using `SCM_RIGHTS`. The device metadata is sent in the same message,
+//! so the receiver does not need access to `/dev/bus/usb` or sysfs.
+//!
+//! Wire format (little-endian):
+//!
+//! | Offset | Size | Field                              |
+//! |--------|------|------------------------------------|
+//! | 0      | 4    | magic (`USBF`)                     |
+//! | 4      | 1    | version                            |
+//! | 5      | 1    | bus number                         |
+//! | 6      | 1    | device number                      |
+//! | 7      | 1    | flags                              |
+//! | 8      | 4    | allowed interfaces mask            |
+//! | 12     | 4    | path length                        |
+//! | 16     | 4    | descriptors length                 |
+//! | 20     | ...  | path, followed by descriptors      |
+
+use std::io::{IoSlice, IoSliceMut, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::{ffi::OsStrExt, net::UnixStream};
+use std::path::PathBuf;
+
+use nix::sys::socket::{self, ControlMessage, ControlMessageOwned, MsgFlags};
+
+use super::UsbDevice;
+use crate::{Error, Result};
+
+const MSG_MAGIC: [u8; 4] = *b"USBF";
+const MSG_VERSION: u8 = 1;
+const MSG_HEADER_LEN: usize = 20;
+const MSG_FLAG_PRIVILEGES_DROPPED: u8 = 0x01;
+const MSG_MAX_PATH_LEN: usize = 4096;
+const MSG_MAX_DESCRIPTORS_LEN: usize = 1 << 20;
+
+impl UsbDevice {
+    /// Sends the device file descriptor, and its metadata, over a Unix socket.
+    ///
+    /// The local handle stays open, and refers to the same open file description as the
+    /// handle received on the other end.
+    pub fn send_over(&self, sock: &UnixStream) -> Result<()> {
+        let header = self.message_header()?;
+        let path = self.path.as_os_str().as_bytes();
+        let iov = [
+            IoSlice::new(header.as_ref()),
+            IoSlice::new(path),
+            IoSlice::new(self.descriptors.as_ref()),
+        ];
+        let fds = [self.fd()];
+        let cmsg = [ControlMessage::ScmRights(fds.as_ref())];
+
+        let total = MSG_HEADER_LEN + path.len() + self.descriptors.len();
+        let sent = socket::sendmsg::<()>(sock.as_raw_fd(), &iov, &cmsg, MsgFlags::empty(), None)?;
+
+        // stream sockets may accept a partial write, the descriptor is always sent with the
+        // first byte, so just push the rest of the payload
+        if sent < total {
+            let mut rest = Vec::with_capacity(total);
+            rest.extend_from_slice(header.as_ref());
+            rest.extend_from_slice(path);
+            rest.extend_from_slice(self.descriptors.as_ref());
+            (&*sock).write_all(&rest[sent..])?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops privileges on the device file descriptor, then sends it over a Unix socket.
+    ///
+    /// See [drop_privileges](Self::drop_privileges) for the `allowed_interfaces` semantics.
+    pub fn send_over_with_privileges(
+        &mut self,
+        sock: &UnixStream,
+        allowed_interfaces: u32,
+    ) -> Result<()> {
+        self.drop_privileges(allowed_interfaces)?;
+        self.send_over(sock)
+    }
+
+    /// Receives a device file descriptor, and its metadata, from a Unix socket.
+    pub fn recv_from(sock: &UnixStream) -> Result<Self> {
+        let mut header = [0u8; MSG_HEADER_LEN];
+        let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+
+        let (read, fd) = {
+            let mut iov = [IoSliceMut::new(header.as_mut())];
+            let msg = socket::recvmsg::<()>(
+                sock.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg_buf),
+                MsgFlags::MSG_CMSG_CLOEXEC,
+            )?;
+
+            let mut fd = None;
+            for cmsg in msg.cmsgs() {
+                if let ControlMessageOwned::ScmRights(fds) = cmsg {
+                    for raw in fds {
+                        // SAFETY: the kernel just installed the descriptor in our table, we are
+                        // the only owner.
+                        let owned = unsafe { OwnedFd::from_raw_fd(raw) };
+                        // keep the first descriptor, extra descriptors get closed on drop
+                        if fd.is_none() {
+                            fd = Some(owned);
+                        }
+                    }
+                }
+            }
+
+            (msg.bytes, fd)
+        };
+
+        if read == 0 {
+            return Err(Error::InvalidMessage("connection closed".into()));
+        }
+        let fd = fd.ok_or(Error::InvalidMessage("missing file descriptor".into()))?;
+
+        if read < MSG_HEADER_LEN {
+            (&*sock).read_exact(&mut header[read..])?;
+        }
+
+        let (mut dev, path_len, desc_len) = Self::parse_message_header(header.as_ref(), fd)?;
+
+        let mut path = vec![0u8; path_len];
+        (&*sock).read_exact(path.as_mut())?;
+        dev.path = PathBuf::from(std::ffi::OsStr::from_bytes(path.as_ref()));
+
+        dev.descriptors.resize(desc_len, 0);
+        (&*sock).read_exact(dev.descriptors.as_mut())?;
+
+        Ok(dev)
+    }
+
+    fn message_header(&self) -> Result<[u8; MSG_HEADER_LEN]> {
+        let path_len = self.path.as_os_str().len();
+        let desc_len = self.descriptors.len();
+
+        if path_len > MSG_MAX_PATH_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "path length {path_len} exceeds maximum {MSG_MAX_PATH_LEN}"
+            )));
+        }
+        if desc_len > MSG_MAX_DESCRIPTORS_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "descriptors length {desc_len} exceeds maximum {MSG_MAX_DESCRIPTORS_LEN}"
+            )));
+        }
+
+        let mut header = [0u8; MSG_HEADER_LEN];
+        header[..4].copy_from_slice(MSG_MAGIC.as_ref());
+        header[4] = MSG_VERSION;
+        header[5] = self.bus_num;
+        header[6] = self.dev_num;
+        header[7] = if self.allowed_interfaces.is_some() {
+            MSG_FLAG_PRIVILEGES_DROPPED
+        } else {
+            0
+        };
+        header[8..12].copy_from_slice(
+            self.allowed_interfaces
+                .unwrap_or(u32::MAX)
+                .to_le_bytes()
+                .as_ref(),
+        );
+        header[12..16].copy_from_slice((path_len as u32).to_le_bytes().as_ref());
+        header[16..20].copy_from_slice((desc_len as u32).to_le_bytes().as_ref());
+
+        Ok(header)
+    }
+
+    fn parse_message_header(header: &[u8], fd: OwnedFd) -> Result<(Self, usize, usize)> {
+        if header[..4] != MSG_MAGIC {
+            return Err(Error::InvalidMessage("invalid magic".into()));
+        }
+        if header[4] != MSG_VERSION {
+            return Err(Error::InvalidMessage(format!(
+                "unsupported version: {}",
+                header[4]
+            )));
+        }
+
+        let read_u32 = |off: usize| {
+            u32::from_le_bytes([
+                header[off],
+                header[off + 1],
+                header[off + 2],
+                header[off + 3],
+            ])
+        };
+
+        let path_len = read_u32(12) as usize;
+        let desc_len = read_u32(16) as usize;
+
+        if path_len > MSG_MAX_PATH_LEN || desc_len > MSG_MAX_DESCRIPTORS_LEN {
+            return Err(Error::InvalidMessage(format!(
+                "invalid payload lengths, path: {path_len}, descriptors: {desc_len}"
+            )));
+        }
+
+        let mut dev = Self::from_fd(fd)
+            .with_bus_num(header[5])
+            .with_dev_num(header[6]);
+
+        if header[7] & MSG_FLAG_PRIVILEGES_DROPPED != 0 {
+            dev.allowed_interfaces = Some(read_u32(8));
+        }
+
+        Ok((dev, path_len, desc_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+
+    use super::*;
+
+    fn fixture(name: &str, data: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("usbfs-passing-{}-{name}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_send_recv_device() -> Result<()> {
+        let exp_desc = [0x12u8, 0x01, 0x00, 0x02, 0xff, 0x00, 0x00, 0x40];
+        let path = fixture("send-recv", exp_desc.as_ref());
+
+        let dev = UsbDevice::open_read_only(&path)?
+            .with_bus_num(1)
+            .with_dev_num(42);
+
+        let (tx, rx) = UnixStream::pair()?;
+
+        dev.send_over(&tx)?;
+        let recv_dev = UsbDevice::recv_from(&rx)?;
+
+        assert_eq!(recv_dev.bus_num(), dev.bus_num());
+        assert_eq!(recv_dev.dev_num(), dev.dev_num());
+        assert_eq!(recv_dev.path(), dev.path());
+        assert_eq!(recv_dev.descriptors(), exp_desc.as_ref());
+        assert_eq!(recv_dev.allowed_interfaces(), None);
+        assert_ne!(recv_dev.fd(), dev.fd());
+
+        // the received descriptor refers to the same file
+        let mut file = recv_dev.into_file();
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+        assert_eq!(data, exp_desc);
+
+        std::fs::remove_file(path).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recv_invalid_message() -> Result<()> {
+        let path = fixture("invalid", &[]);
+        let file = File::open(&path)?;
+        let (tx, rx) = UnixStream::pair()?;
+
+        let header = [0u8; MSG_HEADER_LEN];
+        let fds = [file.as_raw_fd()];
+        socket::sendmsg::<()>(
+            tx.as_raw_fd(),
+            &[IoSlice::new(header.as_ref())],
+            &[ControlMessage::ScmRights(fds.as_ref())],
+            MsgFlags::empty(),
+            None,
+        )?;
+
+        assert!(matches!(
+            UsbDevice::recv_from(&rx),
+            Err(Error::InvalidMessage(_))
+        ));
+
+        // no file descriptor attached
+        (&tx).write_all(header.as_ref())?;
+        assert!(matches!(
+            UsbDevice::recv_from(&rx),
+            Err(Error::InvalidMessage(_))
+        ));
+
+        std::fs::remove_file(path).ok();
+
+        Ok(())
+    }
+}
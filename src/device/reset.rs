@@ -0,0 +1,268 @@
+//! Device reset, and re-enumeration handling.
+
+use std::time::{Duration, Instant};
+use std::{fmt, mem, thread};
+
+use nix::errno::Errno;
+
+use super::UsbDevice;
+use crate::{DetachPolicy, Error, Result, Sysfs, SysfsDevice};
+
+/// Default time to wait for a device to re-enumerate after a reset.
+const DEFAULT_REENUMERATION_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval between `sysfs` lookups while waiting for re-enumeration.
+const REENUMERATION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Represents the outcome of a [UsbDevice] reset.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResetStatus {
+    /// The device kept its identity, and the file descriptor is still valid.
+    #[default]
+    Kept,
+    /// The device re-enumerated (e.g. its descriptors changed), and was reopened at the same
+    /// port path.
+    Reopened,
+}
+
+impl ResetStatus {
+    /// Creates a new [ResetStatus].
+    pub const fn new() -> Self {
+        Self::Kept
+    }
+}
+
+impl From<&ResetStatus> for &'static str {
+    fn from(val: &ResetStatus) -> Self {
+        match val {
+            ResetStatus::Kept => "kept",
+            ResetStatus::Reopened => "reopened",
+        }
+    }
+}
+
+impl From<ResetStatus> for &'static str {
+    fn from(val: ResetStatus) -> Self {
+        (&val).into()
+    }
+}
+
+impl fmt::Display for ResetStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+impl UsbDevice {
+    /// Resets the device, reopening it if it re-enumerates.
+    ///
+    /// Uses the default `sysfs` locations, and waits up to five seconds for re-enumeration.
+    /// See [reset_with](Self::reset_with).
+    pub fn reset(&mut self) -> Result<ResetStatus> {
+        self.reset_with(&Sysfs::new(), DEFAULT_REENUMERATION_TIMEOUT)
+    }
+
+    /// Resets the device, reopening it if it re-enumerates.
+    ///
+    /// If the descriptors change during the reset, the kernel re-enumerates the device with a
+    /// new device number, and the old file descriptor becomes unusable. The new device is
+    /// located by its port path through `sysfs`, and reopened.
+    ///
+    /// In both cases, claimed interfaces and their alternate settings are restored when
+    /// possible. Interfaces that cannot be claimed again are dropped from
+    /// [claimed_interfaces](Self::claimed_interfaces).
+    ///
+    /// [ClaimedInterface](crate::ClaimedInterface) guards borrow the device, so they are
+    /// dropped before a reset, releasing their interfaces: claim them again afterwards. URBs in
+    /// flight on a reopened device are discarded, and complete with a
+    /// [Cancelled](crate::UrbStatus::Cancelled) or [Disconnected](crate::UrbStatus::Disconnected)
+    /// status.
+    pub fn reset_with(&mut self, sysfs: &Sysfs, timeout: Duration) -> Result<ResetStatus> {
+        if self.is_disconnected() {
+            return Err(Error::Disconnected);
+        }
+
+        // the port path must be resolved before the old device disappears from sysfs
+        let port_path = self
+            .sysfs_device(sysfs)
+            .ok()
+            .flatten()
+            .map(|d| d.port_path().to_owned());
+
+        match crate::usbfs_reset(self.fd()) {
+            Ok(()) => {
+                self.restore_claims(false);
+                Ok(ResetStatus::Kept)
+            }
+            Err(err) if err.errno() == Some(Errno::ENODEV as i32) => {
+                let port_path = port_path.ok_or(Error::NotFound(format!(
+                    "port path for bus {} device {}",
+                    self.bus_num, self.dev_num
+                )))?;
+                let device = match reopen_at_port(
+                    sysfs,
+                    &port_path,
+                    (self.bus_num, self.dev_num),
+                    timeout,
+                ) {
+                    Ok(device) => device,
+                    Err(err) => {
+                        // the old device is gone for good
+                        self.disconnect_locked(&mut self.state());
+                        return Err(err);
+                    }
+                };
+
+                self.replace(device)?;
+                self.restore_claims(true);
+
+                Ok(ResetStatus::Reopened)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Gets the `sysfs` entry of the device, using its bus and device numbers.
+    ///
+    /// Returns `None` if the bus and device numbers are unknown.
+    pub fn sysfs_device(&self, sysfs: &Sysfs) -> Result<Option<SysfsDevice>> {
+        if self.bus_num == 0 || self.dev_num == 0 {
+            Ok(None)
+        } else {
+            sysfs.device_by_bus_dev(self.bus_num, self.dev_num)
+        }
+    }
+
+    /// Takes over the file descriptor and identity of a reopened device.
+    fn replace(&mut self, mut device: UsbDevice) -> Result<()> {
+        // dropped privileges belong to the old open file description
+        if let Some(allowed) = self.allowed_interfaces {
+            crate::usbfs_drop_privileges(device.fd(), allowed as u64)?;
+        }
+
+        // URBs in flight on the old file descriptor are cancelled before it is closed
+        self.discard_all_locked(&mut self.state());
+
+        // the old file descriptor is closed with `device`, once the URBs are abandoned
+        mem::swap(&mut self.fd, &mut device.fd);
+        self.path = mem::take(&mut device.path);
+        self.bus_num = device.bus_num;
+        self.dev_num = device.dev_num;
+        self.descriptors = mem::take(&mut device.descriptors);
+
+        // the remaining URBs can no longer be reaped
+        let mut state = self.state();
+        state.urbs.abandon();
+        state.urbs.caps = None;
+        self.urb_cond.notify_all();
+
+        Ok(())
+    }
+
+    /// Claims the previously claimed interfaces again, and selects their alternate settings.
+    fn restore_claims(&self, reenumerated: bool) {
+        let (claims, detached) = {
+            let mut state = self.state();
+            let detached = if reenumerated {
+                mem::take(&mut state.detached_interfaces)
+            } else {
+                state.detached_interfaces.clone()
+            };
+
+            (mem::take(&mut state.claimed_interfaces), detached)
+        };
+
+        for (iface, alt_setting) in claims {
+            let claimed = if detached.contains(&iface) {
+                self.claim_interface_detaching(iface, &DetachPolicy::Always)
+            } else {
+                self.claim_interface(iface)
+            };
+
+            // the interface stays claimed on the default setting if selection fails
+            if claimed.is_ok() && alt_setting != 0 {
+                self.set_alt_setting(iface, alt_setting).ok();
+            }
+        }
+    }
+}
+
+/// Waits for a device to re-enumerate at the port path, and opens it.
+///
+/// The device is considered re-enumerated once its bus and device numbers differ from the old
+/// ones, and its device node can be opened.
+pub(crate) fn reopen_at_port(
+    sysfs: &Sysfs,
+    port_path: &str,
+    old_bus_dev: (u8, u8),
+    timeout: Duration,
+) -> Result<UsbDevice> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Ok(Some(dev)) = sysfs.device(port_path) {
+            if (dev.bus_num(), dev.dev_num()) != old_bus_dev {
+                // the device node may appear after the sysfs entry
+                if let Ok(device) = UsbDevice::open(sysfs.devnode(&dev)) {
+                    return Ok(device
+                        .with_bus_num(dev.bus_num())
+                        .with_dev_num(dev.dev_num()));
+                }
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(Error::NotFound(format!(
+                "re-enumerated device at port {port_path}"
+            )));
+        }
+
+        thread::sleep(REENUMERATION_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::tests::TEST_DESCRIPTORS;
+    use crate::sysfs::tests::{add_device, fixture_tree, remove_device};
+
+    #[test]
+    fn test_reopen_at_port() -> Result<()> {
+        let sysfs = fixture_tree("reset");
+        let timeout = Duration::from_millis(100);
+
+        // the device has not re-enumerated yet
+        add_device(&sysfs, "1-4", 1, 7, &[], TEST_DESCRIPTORS.as_ref());
+        assert!(matches!(
+            reopen_at_port(&sysfs, "1-4", (1, 7), timeout),
+            Err(Error::NotFound(_))
+        ));
+
+        remove_device(&sysfs, "1-4", 1, 7);
+        add_device(&sysfs, "1-4", 1, 8, &[], TEST_DESCRIPTORS.as_ref());
+
+        let mut dev = UsbDevice::from_fd(std::fs::File::open("/dev/null")?.into())
+            .with_bus_num(1)
+            .with_dev_num(7);
+
+        assert_eq!(dev.sysfs_device(&sysfs)?, None);
+
+        dev.replace(reopen_at_port(&sysfs, "1-4", (1, 7), timeout)?)?;
+
+        assert_eq!((dev.bus_num(), dev.dev_num()), (1, 8));
+        assert_eq!(dev.path(), sysfs.dev_root().join("001").join("008"));
+        assert_eq!(dev.descriptors(), TEST_DESCRIPTORS.as_ref());
+        assert_eq!(
+            dev.sysfs_device(&sysfs)?.map(|d| d.port_path().to_owned()),
+            Some("1-4".into())
+        );
+
+        // resetting a non-USBFS file reports the error, without reopening
+        assert!(dev.reset_with(&sysfs, timeout).is_err());
+        assert_eq!(dev.dev_num(), 8);
+
+        Ok(())
+    }
+}
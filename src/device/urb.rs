@@ -0,0 +1,639 @@
+//! Asynchronous URB submission, and disconnect handling.
+//!
+//! Submitted URBs are owned by the [UsbDevice] until they are reaped, so the kernel never writes
+//! transfer results into freed memory. When the device is unplugged, every in-flight URB is
+//! completed with a [Disconnected](UrbStatus::Disconnected) status, and the device enters a
+//! terminal state where every operation returns [Error::Disconnected].
+
+use std::collections::HashMap;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+use std::{fmt, mem, ptr, slice};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+
+use super::{DeviceState, UsbDevice};
+use crate::types::cap::CAP_REAP_AFTER_DISCONNECT;
+use crate::{Error, Result, TransferInfo, Urb, UrbFfi, UsbfsIsoPacketDesc};
+
+/// Identifies a URB submitted through a [UsbDevice], or a [UsbBackend](crate::UsbBackend).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UrbId(u64);
+
+impl UrbId {
+    /// Creates a new [UrbId].
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [UrbId] from its inner representation.
+    pub const fn create(id: u64) -> Self {
+        Self(id)
+    }
+
+    /// Gets the inner representation of the [UrbId].
+    pub const fn inner(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for UrbId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Represents the completion status of a reaped [Urb].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UrbStatus {
+    /// The transfer completed.
+    #[default]
+    Completed,
+    /// The transfer was cancelled.
+    Cancelled,
+    /// The endpoint stalled.
+    Stall,
+    /// The device was disconnected.
+    Disconnected,
+    /// The transfer failed with the provided error number.
+    Error(i32),
+}
+
+impl UrbStatus {
+    /// Creates a new [UrbStatus].
+    pub const fn new() -> Self {
+        Self::Completed
+    }
+
+    /// Creates a new [UrbStatus] from a (negative) kernel URB status.
+    pub fn create(status: i32) -> Self {
+        match Errno::from_i32(-status) {
+            _ if status == 0 => Self::Completed,
+            Errno::ENOENT | Errno::ECONNRESET => Self::Cancelled,
+            Errno::EPIPE => Self::Stall,
+            Errno::ENODEV | Errno::ESHUTDOWN => Self::Disconnected,
+            _ => Self::Error(-status),
+        }
+    }
+
+    /// Gets whether the transfer completed.
+    pub const fn is_completed(&self) -> bool {
+        matches!(self, Self::Completed)
+    }
+}
+
+impl From<&Urb<'_>> for UrbStatus {
+    fn from(val: &Urb<'_>) -> Self {
+        Self::create(val.status())
+    }
+}
+
+impl fmt::Display for UrbStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Completed => write!(f, r#""completed""#),
+            Self::Cancelled => write!(f, r#""cancelled""#),
+            Self::Stall => write!(f, r#""stall""#),
+            Self::Disconnected => write!(f, r#""disconnected""#),
+            Self::Error(errno) => write!(f, r#""{}""#, Errno::from_i32(*errno)),
+        }
+    }
+}
+
+/// A submitted URB, laid out as `struct usbdevfs_urb` followed by its packet descriptors.
+#[derive(Debug)]
+pub(super) struct PendingUrb {
+    id: UrbId,
+    // 8-byte aligned storage for the record, the heap allocation never moves
+    raw: Vec<u64>,
+    buffer: Vec<u8>,
+    iso_packets: usize,
+    status: Option<i32>,
+}
+
+impl PendingUrb {
+    fn new(id: UrbId, mut urb: Urb<'_>) -> Self {
+        let header = UrbFfi::from(&mut urb);
+        let iso = urb.iso_frame_desc().to_vec();
+        // moving the buffer keeps its heap allocation, so the record pointer stays valid
+        let buffer = urb.into_buffer();
+
+        let len = mem::size_of::<UrbFfi>() + iso.len() * mem::size_of::<UsbfsIsoPacketDesc>();
+        let mut raw = vec![0u64; len.div_ceil(mem::size_of::<u64>())];
+
+        // SAFETY: the storage is large enough, and aligned for the record and descriptors
+        unsafe {
+            let urb_ptr = raw.as_mut_ptr() as *mut UrbFfi;
+            ptr::write(urb_ptr, header);
+            ptr::copy_nonoverlapping(
+                iso.as_ptr(),
+                urb_ptr.add(1) as *mut UsbfsIsoPacketDesc,
+                iso.len(),
+            );
+        }
+
+        Self {
+            id,
+            raw,
+            buffer,
+            iso_packets: iso.len(),
+            status: None,
+        }
+    }
+
+    fn as_ptr(&self) -> *const UrbFfi {
+        self.raw.as_ptr() as *const UrbFfi
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut UrbFfi {
+        self.raw.as_mut_ptr() as *mut UrbFfi
+    }
+
+    fn key(&self) -> usize {
+        self.as_ptr() as usize
+    }
+
+    fn header(&self) -> &UrbFfi {
+        // SAFETY: the storage always starts with an initialized record
+        unsafe { &*self.as_ptr() }
+    }
+
+    /// Marks the URB as completed by a disconnection.
+    fn disconnect(&mut self) {
+        self.status = Some(-(Errno::ENODEV as i32));
+    }
+
+    fn into_urb(mut self) -> Urb<'static> {
+        let buffer = mem::take(&mut self.buffer);
+        let header = self.header();
+        // SAFETY: the descriptors directly follow the record, and were initialized on creation
+        let iso = unsafe {
+            slice::from_raw_parts(
+                self.as_ptr().add(1) as *const UsbfsIsoPacketDesc,
+                self.iso_packets,
+            )
+        }
+        .to_vec();
+        let info = if self.iso_packets != 0 {
+            TransferInfo::create_isoc(header.number_of_packets())
+        } else {
+            TransferInfo::create_bulk(header.number_of_packets() as u32)
+        };
+
+        Urb::new()
+            .with_urb_type(header.urb_type())
+            .with_endpoint(header.endpoint())
+            .with_status(self.status.unwrap_or(header.status()))
+            .with_flags(header.flags())
+            .with_start_frame(header.start_frame())
+            .with_info(info)
+            .with_error_count(header.error_count())
+            .with_signr(header.signr())
+            .with_iso_frame_desc(iso)
+            .with_buffer(buffer)
+            .with_actual_length(header.actual_length().max(0) as usize)
+    }
+}
+
+/// URB bookkeeping of a [UsbDevice].
+#[derive(Debug, Default)]
+pub(super) struct UrbQueue {
+    next_id: u64,
+    pub(super) caps: Option<u32>,
+    reaping: bool,
+    pending: HashMap<usize, PendingUrb>,
+    completed: Vec<PendingUrb>,
+}
+
+impl UrbQueue {
+    fn is_pending(&self, id: UrbId) -> bool {
+        self.pending.values().any(|p| p.id == id)
+    }
+
+    fn complete(&mut self, key: usize) {
+        if let Some(urb) = self.pending.remove(&key) {
+            self.completed.push(urb);
+        }
+    }
+
+    /// Completes every in-flight URB with a disconnected status.
+    pub(super) fn abandon(&mut self) {
+        for (_, mut urb) in self.pending.drain() {
+            urb.disconnect();
+            self.completed.push(urb);
+        }
+    }
+}
+
+impl UsbDevice {
+    /// Gets the USBFS capabilities bitmask of the device file descriptor.
+    pub fn capabilities(&self) -> Result<u32> {
+        let mut caps = 0u32;
+        self.io(|fd| crate::usbfs_get_capabilities(fd, &mut caps))?;
+        Ok(caps)
+    }
+
+    /// Gets whether the device was disconnected.
+    ///
+    /// Once disconnected, every operation returns an [Error::Disconnected] error.
+    pub fn is_disconnected(&self) -> bool {
+        self.state().disconnected
+    }
+
+    /// Submits an asynchronous [Urb], returning its [UrbId].
+    ///
+    /// The device owns the URB until it is returned by [wait_urb](Self::wait_urb) or
+    /// [reap_urb](Self::reap_urb).
+    ///
+    /// **NOTE** the [UrbUserContext](crate::UrbUserContext) is not kept, use the [UrbId] to
+    /// track the URB instead.
+    pub fn submit_urb(&self, urb: Urb<'_>) -> Result<UrbId> {
+        let caps = self.capabilities().unwrap_or(0);
+        let mut state = self.state();
+
+        if state.disconnected {
+            return Err(Error::Disconnected);
+        }
+        state.urbs.caps.get_or_insert(caps);
+
+        let id = UrbId(state.urbs.next_id);
+        state.urbs.next_id += 1;
+
+        let mut urb = PendingUrb::new(id, urb);
+
+        // SAFETY: the record is owned by the queue until reaped, or the device is closed
+        match unsafe { crate::usbfs_submit_urb(self.fd(), urb.as_mut_ptr()) } {
+            Ok(()) => {
+                state.urbs.pending.insert(urb.key(), urb);
+                Ok(id)
+            }
+            Err(err) if err.errno() == Some(Errno::ENODEV as i32) => {
+                self.disconnect_locked(&mut state);
+                Err(Error::Disconnected)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Cancels a submitted URB.
+    ///
+    /// The URB still has to be reaped, and completes with a
+    /// [Cancelled](UrbStatus::Cancelled) status unless it already completed.
+    pub fn discard_urb(&self, id: UrbId) -> Result<()> {
+        let mut state = self.state();
+
+        if state.disconnected {
+            return Err(Error::Disconnected);
+        }
+
+        let urb = state
+            .urbs
+            .pending
+            .values_mut()
+            .find(|p| p.id == id)
+            .ok_or(Error::NotFound(format!("pending URB {id}")))?;
+
+        // SAFETY: the URB is still owned by the queue
+        match unsafe { crate::usbfs_discard_urb(self.fd(), urb.as_mut_ptr()) } {
+            // the URB already completed, and is waiting to be reaped
+            Err(err) if err.errno() == Some(Errno::EINVAL as i32) => Ok(()),
+            Err(err) if err.errno() == Some(Errno::ENODEV as i32) => {
+                self.disconnect_locked(&mut state);
+                Err(Error::Disconnected)
+            }
+            res => res,
+        }
+    }
+
+    /// Gets the number of submitted URBs that were not reaped yet.
+    pub fn pending_urbs(&self) -> usize {
+        self.state().urbs.pending.len()
+    }
+
+    /// Waits for the URB to complete, and returns it.
+    ///
+    /// Waits forever if `timeout` is `None`, otherwise returns an `ETIMEDOUT` error when the
+    /// timeout expires. The URB stays pending on timeout.
+    ///
+    /// URBs in flight when the device is disconnected are returned with a
+    /// [Disconnected](UrbStatus::Disconnected) status.
+    pub fn wait_urb(&self, id: UrbId, timeout: Option<Duration>) -> Result<Urb<'static>> {
+        self.wait_completed(Some(id), timeout).map(|(_, urb)| urb)
+    }
+
+    /// Waits for any URB to complete, and returns it with its [UrbId].
+    ///
+    /// See [wait_urb](Self::wait_urb).
+    pub fn reap_urb(&self, timeout: Option<Duration>) -> Result<(UrbId, Urb<'static>)> {
+        self.wait_completed(None, timeout)
+    }
+
+    fn wait_completed(
+        &self,
+        id: Option<UrbId>,
+        timeout: Option<Duration>,
+    ) -> Result<(UrbId, Urb<'static>)> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.state();
+
+        loop {
+            if let Some(pos) = state
+                .urbs
+                .completed
+                .iter()
+                .position(|p| id.map_or(true, |id| p.id == id))
+            {
+                let urb = state.urbs.completed.remove(pos);
+                return Ok((urb.id, urb.into_urb()));
+            }
+
+            if state.disconnected {
+                return Err(Error::Disconnected);
+            }
+
+            match id {
+                Some(id) if !state.urbs.is_pending(id) => {
+                    return Err(Error::NotFound(format!("pending URB {id}")));
+                }
+                None if state.urbs.pending.is_empty() => {
+                    return Err(Error::NotFound("pending URB".into()));
+                }
+                _ => (),
+            }
+
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Errno::ETIMEDOUT.into());
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+
+            // only one thread reaps at a time, others wait for it to hand over completions
+            if state.urbs.reaping {
+                state = match remaining {
+                    Some(remaining) => {
+                        self.urb_cond
+                            .wait_timeout(state, remaining)
+                            .unwrap_or_else(|err| err.into_inner())
+                            .0
+                    }
+                    None => self
+                        .urb_cond
+                        .wait(state)
+                        .unwrap_or_else(|err| err.into_inner()),
+                };
+                continue;
+            }
+
+            state.urbs.reaping = true;
+            drop(state);
+
+            let events = poll_completion(self.fd(), remaining);
+
+            state = self.state();
+            state.urbs.reaping = false;
+
+            let res = match events {
+                Ok(events) if events.intersects(PollFlags::POLLHUP | PollFlags::POLLERR) => {
+                    self.disconnect_locked(&mut state);
+                    Ok(())
+                }
+                Ok(events) if events.contains(PollFlags::POLLOUT) => {
+                    self.reap_completed(&mut state)
+                }
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            };
+
+            self.urb_cond.notify_all();
+            res?;
+        }
+    }
+
+    /// Reaps every completed URB without blocking.
+    fn reap_completed(&self, state: &mut DeviceState) -> Result<()> {
+        loop {
+            // SAFETY: every submitted URB is owned by the queue
+            match unsafe { crate::usbfs_reap_urb_ndelay(self.fd()) } {
+                Ok(urb) => state.urbs.complete(urb as usize),
+                Err(err) if err.errno() == Some(Errno::EAGAIN as i32) => return Ok(()),
+                Err(err) if err.errno() == Some(Errno::ENODEV as i32) => {
+                    self.disconnect_locked(state);
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Discards every in-flight URB, and reaps the ones the kernel gives back.
+    ///
+    /// Reaped URBs complete with their own status, the others stay pending.
+    pub(super) fn discard_all_locked(&self, state: &mut DeviceState) {
+        for urb in state.urbs.pending.values_mut() {
+            // SAFETY: the URB is still owned by the queue
+            unsafe { crate::usbfs_discard_urb(self.fd(), urb.as_mut_ptr()) }.ok();
+        }
+
+        // SAFETY: every submitted URB is owned by the queue
+        while let Ok(urb) = unsafe { crate::usbfs_reap_urb_ndelay(self.fd()) } {
+            state.urbs.complete(urb as usize);
+        }
+        self.urb_cond.notify_all();
+    }
+
+    /// Cancels every in-flight URB, and waits for the kernel to give them back.
+    ///
+    /// Used before the URB buffers are freed, or the file descriptor is handed out.
+    pub(super) fn cancel_all(&self) {
+        let mut state = self.state();
+        if state.disconnected || state.urbs.pending.is_empty() {
+            return;
+        }
+
+        self.discard_all_locked(&mut state);
+        while !state.urbs.pending.is_empty() {
+            // SAFETY: every submitted URB is owned by the queue
+            match unsafe { crate::usbfs_reap_urb(self.fd()) } {
+                Ok(urb) => state.urbs.complete(urb as usize),
+                // the kernel kills the remaining URBs when the file descriptor is closed
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Moves the device into the disconnected state, completing every in-flight URB.
+    pub(super) fn disconnect_locked(&self, state: &mut DeviceState) {
+        if state.disconnected {
+            return;
+        }
+        state.disconnected = true;
+
+        if state.urbs.caps.unwrap_or(0) & CAP_REAP_AFTER_DISCONNECT != 0 {
+            // the kernel cancels in-flight URBs on disconnect, and keeps them for reaping
+            // SAFETY: every submitted URB is owned by the queue
+            while let Ok(urb) = unsafe { crate::usbfs_reap_urb_ndelay(self.fd()) } {
+                if let Some(mut urb) = state.urbs.pending.remove(&(urb as usize)) {
+                    // keep the results of transfers that completed before the disconnection
+                    if urb.header().status() != 0 {
+                        urb.disconnect();
+                    }
+                    state.urbs.completed.push(urb);
+                }
+            }
+        }
+
+        // without the capability, the kernel never writes to the remaining URBs again
+        state.urbs.abandon();
+        self.urb_cond.notify_all();
+    }
+
+    /// Runs an `ioctl` on the device file descriptor, detecting disconnection.
+    pub(crate) fn io<T, F: FnOnce(RawFd) -> Result<T>>(&self, f: F) -> Result<T> {
+        if self.is_disconnected() {
+            return Err(Error::Disconnected);
+        }
+
+        match f(self.fd()) {
+            Err(err) if err.errno() == Some(Errno::ENODEV as i32) => {
+                self.disconnect_locked(&mut self.state());
+                Err(Error::Disconnected)
+            }
+            res => res,
+        }
+    }
+}
+
+/// Waits for the file descriptor to have completed URBs, or to be disconnected.
+fn poll_completion(fd: RawFd, timeout: Option<Duration>) -> Result<PollFlags> {
+    // SAFETY: the file descriptor is owned by the device for the duration of the call
+    let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    let mut fds = [PollFd::new(&fd, PollFlags::POLLOUT)];
+    let timeout = timeout.map_or(-1, |t| {
+        t.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+    });
+
+    match poll(&mut fds, timeout) {
+        Ok(_) => Ok(fds[0].revents().unwrap_or(PollFlags::empty())),
+        Err(Errno::EINTR) => Ok(PollFlags::empty()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{UsbfsCtrlTransfer, URB_TYPE_BULK};
+
+    #[test]
+    fn test_urb_disconnect() -> Result<()> {
+        let dev = UsbDevice::from_fd(std::fs::File::open("/dev/null")?.into());
+
+        // submitting on a non-USBFS file fails, without queueing the URB
+        assert!(dev
+            .submit_urb(Urb::new().with_urb_type(URB_TYPE_BULK))
+            .is_err());
+        assert_eq!(dev.pending_urbs(), 0);
+        assert!(matches!(dev.reap_urb(None), Err(Error::NotFound(_))));
+
+        // simulate an in-flight URB, and one reaped before the disconnection
+        let (done, in_flight) = {
+            let mut state = dev.state();
+            let mut urbs = Vec::new();
+
+            for i in 0..2 {
+                let id = UrbId(state.urbs.next_id);
+                state.urbs.next_id += 1;
+
+                let urb = PendingUrb::new(
+                    id,
+                    Urb::new()
+                        .with_urb_type(URB_TYPE_BULK)
+                        .with_endpoint(0x81)
+                        .with_buffer([i as u8; 4]),
+                );
+                if i == 0 {
+                    state.urbs.completed.push(urb);
+                } else {
+                    state.urbs.pending.insert(urb.key(), urb);
+                }
+                urbs.push(id);
+            }
+
+            (urbs[0], urbs[1])
+        };
+
+        assert_eq!(dev.pending_urbs(), 1);
+        assert!(!dev.is_disconnected());
+
+        // a waiter on another thread is woken by the disconnection
+        std::thread::scope(|s| -> Result<()> {
+            // hold the reaper role, so the waiter blocks on the condition variable
+            dev.state().urbs.reaping = true;
+            let waiter = s.spawn(|| {
+                dev.wait_urb(in_flight, None)
+                    .map(|urb| (UrbStatus::from(&urb), urb.endpoint(), urb.into_buffer()))
+            });
+            std::thread::sleep(Duration::from_millis(20));
+            {
+                let mut state = dev.state();
+                state.urbs.reaping = false;
+                dev.disconnect_locked(&mut state);
+            }
+
+            let (status, endpoint, buffer) = waiter.join().unwrap()?;
+
+            assert_eq!(status, UrbStatus::Disconnected);
+            assert_eq!(endpoint, 0x81);
+            assert_eq!(buffer, [1u8; 4]);
+
+            Ok(())
+        })?;
+
+        assert!(dev.is_disconnected());
+        assert_eq!(dev.pending_urbs(), 0);
+
+        let urb = dev.wait_urb(done, Some(Duration::ZERO))?;
+
+        assert_eq!(UrbStatus::from(&urb), UrbStatus::Completed);
+        assert_eq!(urb.buffer(), [0u8; 4]);
+
+        // terminal state
+        assert_eq!(dev.reap_urb(None).err(), Some(Error::Disconnected));
+        assert_eq!(dev.submit_urb(Urb::new()).err(), Some(Error::Disconnected));
+        assert_eq!(
+            dev.control_transfer(&mut UsbfsCtrlTransfer::new()).err(),
+            Some(Error::Disconnected)
+        );
+        assert_eq!(dev.claim_interface(0).err(), Some(Error::Disconnected));
+        assert_eq!(
+            dev.read_endpoint(0x81, &mut [0u8; 4], 100).err(),
+            Some(Error::Disconnected)
+        );
+
+        assert_eq!(UrbStatus::create(0), UrbStatus::Completed);
+        assert_eq!(UrbStatus::create(-(Errno::EPIPE as i32)), UrbStatus::Stall);
+        assert_eq!(
+            UrbStatus::create(-(Errno::ENOENT as i32)),
+            UrbStatus::Cancelled
+        );
+        assert_eq!(
+            UrbStatus::create(-(Errno::ESHUTDOWN as i32)),
+            UrbStatus::Disconnected
+        );
+        assert_eq!(
+            UrbStatus::create(-(Errno::EOVERFLOW as i32)),
+            UrbStatus::Error(Errno::EOVERFLOW as i32)
+        );
+
+        Ok(())
+    }
+}
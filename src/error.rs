@@ -1,4 +1,8 @@
+use std::error::Error as StdError;
 use std::fmt;
+use std::sync::Arc;
+
+use nix::errno::Errno;
 
 /// Convenience alias for the library `Result` type.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -7,19 +11,128 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
+    /// No longer constructed: failed `ioctl` calls are reported as [Error::Errno].
+    #[deprecated(note = "failed `ioctl` calls are reported as `Error::Errno`")]
     Ioctl(String),
+    /// OS error number of a failed system call, e.g. an `ioctl`.
+    Errno(i32),
+    Io(String),
+    InvalidMessage(String),
+    InvalidDescriptor(String),
+    AlreadyClaimed(u32),
+    InvalidEndpoint(u8),
+    InvalidAltSetting(u8),
+    NotFound(String),
+    InvalidArgument(String),
+    Disconnected,
+    Class(ClassError),
+}
+
+impl Error {
+    /// Creates a new [Error::Class] from a status reported by a class protocol.
+    pub fn class<E: StdError + Send + Sync + 'static>(class: &'static str, status: E) -> Self {
+        Self::Class(ClassError::new(class, status))
+    }
+
+    /// Gets the class protocol status of an [Error::Class], if it has the requested type.
+    pub fn class_status<E: StdError + 'static>(&self) -> Option<&E> {
+        match self {
+            Self::Class(err) => err.status(),
+            _ => None,
+        }
+    }
+
+    /// Gets the OS error number, if the error originates from a system call.
+    pub const fn errno(&self) -> Option<i32> {
+        match self {
+            Self::Errno(errno) => Some(*errno),
+            Self::Disconnected => Some(Errno::ENODEV as i32),
+            _ => None,
+        }
+    }
+}
+
+impl From<Errno> for Error {
+    fn from(err: Errno) -> Self {
+        Self::Errno(err as i32)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(format!("{err}"))
+    }
 }
 
-impl From<nix::errno::Errno> for Error {
-    fn from(err: nix::errno::Errno) -> Self {
-        Self::Ioctl(format!("{err}"))
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err.errno() {
+            Some(errno) => Self::from_raw_os_error(errno),
+            None => Self::other(format!("{err}")),
+        }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            #[allow(deprecated)]
             Self::Ioctl(err) => write!(f, "IOCTL error: {err}"),
+            Self::Errno(err) => write!(f, "IOCTL error: {}", Errno::from_i32(*err)),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::InvalidMessage(err) => write!(f, "invalid message: {err}"),
+            Self::InvalidDescriptor(err) => write!(f, "invalid descriptor: {err}"),
+            Self::AlreadyClaimed(iface) => write!(f, "interface {iface} is already claimed"),
+            Self::InvalidEndpoint(ep) => {
+                write!(f, "endpoint 0x{ep:02x} is not valid for the active setting")
+            }
+            Self::InvalidAltSetting(alt) => write!(f, "invalid alternate setting: {alt}"),
+            Self::NotFound(err) => write!(f, "not found: {err}"),
+            Self::InvalidArgument(err) => write!(f, "invalid argument: {err}"),
+            Self::Disconnected => write!(f, "device disconnected"),
+            Self::Class(err) => write!(f, "{err}"),
         }
     }
 }
+
+/// Represents a failure status reported by a class protocol, e.g. SCSI sense data.
+///
+/// The status keeps its driver type, see [status](Self::status).
+#[derive(Clone, Debug)]
+pub struct ClassError {
+    class: &'static str,
+    status: Arc<dyn StdError + Send + Sync>,
+}
+
+impl ClassError {
+    /// Creates a new [ClassError] from the provided parameters.
+    pub fn new<E: StdError + Send + Sync + 'static>(class: &'static str, status: E) -> Self {
+        Self {
+            class,
+            status: Arc::new(status),
+        }
+    }
+
+    /// Gets the name of the class protocol.
+    pub const fn class(&self) -> &'static str {
+        self.class
+    }
+
+    /// Gets the status, if it has the requested type.
+    pub fn status<E: StdError + 'static>(&self) -> Option<&E> {
+        self.status.downcast_ref()
+    }
+}
+
+impl PartialEq for ClassError {
+    fn eq(&self, other: &Self) -> bool {
+        // the status type is erased, compare what it reports
+        self.class == other.class && self.status.to_string() == other.status.to_string()
+    }
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {}", self.class, self.status)
+    }
+}
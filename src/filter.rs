@@ -0,0 +1,487 @@
+//! Declarative USB device matching.
+
+use std::fmt;
+
+use crate::{Error, HotplugAction, HotplugEvent, Result, Sysfs, SysfsDevice, UsbDevice};
+
+/// Represents a set of criteria to match USB devices against.
+///
+/// Unset criteria match any device. String criteria match exactly.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceFilter {
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    class: Option<u8>,
+    subclass: Option<u8>,
+    protocol: Option<u8>,
+    serial: Option<String>,
+    bus_num: Option<u8>,
+    port_path: Option<String>,
+    manufacturer: Option<String>,
+    product: Option<String>,
+}
+
+impl DeviceFilter {
+    /// Creates a new [DeviceFilter] matching any device.
+    pub const fn new() -> Self {
+        Self {
+            vendor_id: None,
+            product_id: None,
+            class: None,
+            subclass: None,
+            protocol: None,
+            serial: None,
+            bus_num: None,
+            port_path: None,
+            manufacturer: None,
+            product: None,
+        }
+    }
+
+    /// Parses a [DeviceFilter] from a `VID:PID` string of hexadecimal IDs.
+    ///
+    /// Either ID can be `*`, or empty, to match any value (e.g. `0483:*`).
+    pub fn parse_id(id: &str) -> Result<Self> {
+        let parse = |s: &str| match s {
+            "" | "*" => Ok(None),
+            _ => u16::from_str_radix(s, 16)
+                .map(Some)
+                .map_err(|_| Error::InvalidArgument(format!("invalid USB ID: {id}"))),
+        };
+
+        let (vid, pid) = id.split_once(':').unwrap_or((id, ""));
+
+        Ok(Self {
+            vendor_id: parse(vid)?,
+            product_id: parse(pid)?,
+            ..Self::new()
+        })
+    }
+
+    /// Gets the vendor ID criteria.
+    pub const fn vendor_id(&self) -> Option<u16> {
+        self.vendor_id
+    }
+
+    /// Sets the vendor ID criteria.
+    pub fn set_vendor_id(&mut self, vendor_id: u16) {
+        self.vendor_id = Some(vendor_id);
+    }
+
+    /// Builder function that sets the vendor ID criteria.
+    pub fn with_vendor_id(mut self, vendor_id: u16) -> Self {
+        self.set_vendor_id(vendor_id);
+        self
+    }
+
+    /// Gets the product ID criteria.
+    pub const fn product_id(&self) -> Option<u16> {
+        self.product_id
+    }
+
+    /// Sets the product ID criteria.
+    pub fn set_product_id(&mut self, product_id: u16) {
+        self.product_id = Some(product_id);
+    }
+
+    /// Builder function that sets the product ID criteria.
+    pub fn with_product_id(mut self, product_id: u16) -> Self {
+        self.set_product_id(product_id);
+        self
+    }
+
+    /// Gets the class criteria.
+    pub const fn class(&self) -> Option<u8> {
+        self.class
+    }
+
+    /// Sets the class criteria.
+    ///
+    /// The class, subclass and protocol criteria match either the device, or one of its
+    /// interfaces.
+    pub fn set_class(&mut self, class: u8) {
+        self.class = Some(class);
+    }
+
+    /// Builder function that sets the class criteria.
+    pub fn with_class(mut self, class: u8) -> Self {
+        self.set_class(class);
+        self
+    }
+
+    /// Gets the subclass criteria.
+    pub const fn subclass(&self) -> Option<u8> {
+        self.subclass
+    }
+
+    /// Sets the subclass criteria.
+    pub fn set_subclass(&mut self, subclass: u8) {
+        self.subclass = Some(subclass);
+    }
+
+    /// Builder function that sets the subclass criteria.
+    pub fn with_subclass(mut self, subclass: u8) -> Self {
+        self.set_subclass(subclass);
+        self
+    }
+
+    /// Gets the protocol criteria.
+    pub const fn protocol(&self) -> Option<u8> {
+        self.protocol
+    }
+
+    /// Sets the protocol criteria.
+    pub fn set_protocol(&mut self, protocol: u8) {
+        self.protocol = Some(protocol);
+    }
+
+    /// Builder function that sets the protocol criteria.
+    pub fn with_protocol(mut self, protocol: u8) -> Self {
+        self.set_protocol(protocol);
+        self
+    }
+
+    /// Gets the serial number criteria.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Sets the serial number criteria.
+    pub fn set_serial<S: Into<String>>(&mut self, serial: S) {
+        self.serial = Some(serial.into());
+    }
+
+    /// Builder function that sets the serial number criteria.
+    pub fn with_serial<S: Into<String>>(mut self, serial: S) -> Self {
+        self.set_serial(serial);
+        self
+    }
+
+    /// Gets the bus number criteria.
+    pub const fn bus_num(&self) -> Option<u8> {
+        self.bus_num
+    }
+
+    /// Sets the bus number criteria.
+    pub fn set_bus_num(&mut self, bus_num: u8) {
+        self.bus_num = Some(bus_num);
+    }
+
+    /// Builder function that sets the bus number criteria.
+    pub fn with_bus_num(mut self, bus_num: u8) -> Self {
+        self.set_bus_num(bus_num);
+        self
+    }
+
+    /// Gets the port path criteria.
+    pub fn port_path(&self) -> Option<&str> {
+        self.port_path.as_deref()
+    }
+
+    /// Sets the port path criteria (e.g. `1-1.2`).
+    pub fn set_port_path<S: Into<String>>(&mut self, port_path: S) {
+        self.port_path = Some(port_path.into());
+    }
+
+    /// Builder function that sets the port path criteria.
+    pub fn with_port_path<S: Into<String>>(mut self, port_path: S) -> Self {
+        self.set_port_path(port_path);
+        self
+    }
+
+    /// Gets the manufacturer string criteria.
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.manufacturer.as_deref()
+    }
+
+    /// Sets the manufacturer string criteria.
+    pub fn set_manufacturer<S: Into<String>>(&mut self, manufacturer: S) {
+        self.manufacturer = Some(manufacturer.into());
+    }
+
+    /// Builder function that sets the manufacturer string criteria.
+    pub fn with_manufacturer<S: Into<String>>(mut self, manufacturer: S) -> Self {
+        self.set_manufacturer(manufacturer);
+        self
+    }
+
+    /// Gets the product string criteria.
+    pub fn product(&self) -> Option<&str> {
+        self.product.as_deref()
+    }
+
+    /// Sets the product string criteria.
+    pub fn set_product<S: Into<String>>(&mut self, product: S) {
+        self.product = Some(product.into());
+    }
+
+    /// Builder function that sets the product string criteria.
+    pub fn with_product<S: Into<String>>(mut self, product: S) -> Self {
+        self.set_product(product);
+        self
+    }
+
+    /// Gets whether the `sysfs` device matches every criteria.
+    pub fn matches(&self, device: &SysfsDevice) -> bool {
+        self.matches_ids(
+            device.vendor_id(),
+            device.product_id(),
+            device.bus_num(),
+            device.port_path(),
+        ) && self.matches_strings(device)
+            && (self.matches_class(device.class(), device.subclass(), device.protocol())
+                || device
+                    .interfaces()
+                    .iter()
+                    .any(|i| self.matches_class(i.class(), i.subclass(), i.protocol())))
+    }
+
+    /// Gets whether the hotplug event matches every criteria.
+    ///
+    /// Criteria not carried by the event (strings, and interface classes) are checked against
+    /// the `sysfs` entry of the device. These criteria never match [Remove](HotplugAction::Remove)
+    /// events, since the device is already gone.
+    pub fn matches_event(&self, event: &HotplugEvent, sysfs: &Sysfs) -> bool {
+        if !self.matches_ids(
+            event.vendor_id(),
+            event.product_id(),
+            event.bus_num(),
+            event.port_path(),
+        ) {
+            return false;
+        }
+
+        let device_class = self.matches_class(event.class(), event.subclass(), event.protocol());
+
+        if device_class && !self.needs_strings() {
+            return true;
+        }
+
+        if event.action() == HotplugAction::Remove {
+            return false;
+        }
+
+        match event.sysfs_device(sysfs) {
+            Ok(Some(device)) => {
+                device.dev_num() == event.dev_num()
+                    && self.matches_strings(&device)
+                    && (device_class
+                        || device
+                            .interfaces()
+                            .iter()
+                            .any(|i| self.matches_class(i.class(), i.subclass(), i.protocol())))
+            }
+            _ => false,
+        }
+    }
+
+    /// Lists the `sysfs` devices matching every criteria.
+    pub fn find(&self, sysfs: &Sysfs) -> Result<Vec<SysfsDevice>> {
+        Ok(sysfs
+            .devices()?
+            .into_iter()
+            .filter(|d| self.matches(d))
+            .collect())
+    }
+
+    /// Opens the first device matching every criteria.
+    pub fn open(&self, sysfs: &Sysfs) -> Result<UsbDevice> {
+        let device = self
+            .find(sysfs)?
+            .into_iter()
+            .next()
+            .ok_or(Error::NotFound(format!("device matching {self}")))?;
+
+        UsbDevice::open(sysfs.devnode(&device))
+    }
+
+    fn matches_ids(&self, vendor_id: u16, product_id: u16, bus_num: u8, port_path: &str) -> bool {
+        self.vendor_id.map_or(true, |v| v == vendor_id)
+            && self.product_id.map_or(true, |p| p == product_id)
+            && self.bus_num.map_or(true, |b| b == bus_num)
+            && self.port_path.as_deref().map_or(true, |p| p == port_path)
+    }
+
+    fn matches_class(&self, class: u8, subclass: u8, protocol: u8) -> bool {
+        self.class.map_or(true, |c| c == class)
+            && self.subclass.map_or(true, |s| s == subclass)
+            && self.protocol.map_or(true, |p| p == protocol)
+    }
+
+    fn matches_strings(&self, device: &SysfsDevice) -> bool {
+        let matches = |exp: &Option<String>, val: Option<&str>| {
+            exp.as_deref().map_or(true, |e| Some(e) == val)
+        };
+
+        matches(&self.serial, device.serial())
+            && matches(&self.manufacturer, device.manufacturer())
+            && matches(&self.product, device.product())
+    }
+
+    fn needs_strings(&self) -> bool {
+        self.serial.is_some() || self.manufacturer.is_some() || self.product.is_some()
+    }
+}
+
+/// Lists the devices matching the [DeviceFilter], using the default `sysfs` locations.
+pub fn find_devices(filter: &DeviceFilter) -> Result<Vec<SysfsDevice>> {
+    filter.find(&Sysfs::new())
+}
+
+impl fmt::Display for DeviceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut field = |f: &mut fmt::Formatter<'_>, name: &str, val: Option<String>| {
+            if let Some(val) = val {
+                let sep = if first { "" } else { ", " };
+                first = false;
+                write!(f, r#"{sep}"{name}": {val}"#)
+            } else {
+                Ok(())
+            }
+        };
+
+        write!(f, "{{")?;
+        field(f, "vendor_id", self.vendor_id.map(|v| v.to_string()))?;
+        field(f, "product_id", self.product_id.map(|v| v.to_string()))?;
+        field(f, "class", self.class.map(|v| v.to_string()))?;
+        field(f, "subclass", self.subclass.map(|v| v.to_string()))?;
+        field(f, "protocol", self.protocol.map(|v| v.to_string()))?;
+        field(
+            f,
+            "serial",
+            self.serial.as_ref().map(|v| format!(r#""{v}""#)),
+        )?;
+        field(f, "bus_num", self.bus_num.map(|v| v.to_string()))?;
+        field(
+            f,
+            "port_path",
+            self.port_path.as_ref().map(|v| format!(r#""{v}""#)),
+        )?;
+        field(
+            f,
+            "manufacturer",
+            self.manufacturer.as_ref().map(|v| format!(r#""{v}""#)),
+        )?;
+        field(
+            f,
+            "product",
+            self.product.as_ref().map(|v| format!(r#""{v}""#)),
+        )?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysfs::tests::{add_device, fixture_tree};
+
+    #[test]
+    fn test_device_filter() -> Result<()> {
+        let sysfs = fixture_tree("filter");
+
+        add_device(
+            &sysfs,
+            "1-1.2",
+            1,
+            5,
+            &[
+                ("idVendor", "0483"),
+                ("idProduct", "5740"),
+                ("bDeviceClass", "ef"),
+                ("serial", "0123456789"),
+                ("product", "Virtual COM Port"),
+                ("1-1.2:1.0/bInterfaceNumber", "00"),
+                ("1-1.2:1.0/bInterfaceClass", "02"),
+                ("1-1.2:1.0/bInterfaceSubClass", "02"),
+                ("1-1.2:1.1/bInterfaceNumber", "01"),
+                ("1-1.2:1.1/bInterfaceClass", "0a"),
+            ],
+            &[],
+        );
+        add_device(
+            &sysfs,
+            "2-3",
+            2,
+            9,
+            &[
+                ("idVendor", "0483"),
+                ("idProduct", "df11"),
+                ("manufacturer", "STMicroelectronics"),
+            ],
+            &[],
+        );
+
+        let ids = |f: &DeviceFilter| -> Result<Vec<String>> {
+            Ok(f.find(&sysfs)?
+                .iter()
+                .map(|d| d.port_path().to_owned())
+                .collect())
+        };
+
+        assert_eq!(ids(&DeviceFilter::new())?, ["1-1.2", "2-3"]);
+        assert_eq!(ids(&DeviceFilter::parse_id("0483:*")?)?, ["1-1.2", "2-3"]);
+        assert_eq!(ids(&DeviceFilter::parse_id("0483:df11")?)?, ["2-3"]);
+        assert_eq!(ids(&DeviceFilter::parse_id(":5740")?)?, ["1-1.2"]);
+        assert!(ids(&DeviceFilter::parse_id("1234")?)?.is_empty());
+        assert!(DeviceFilter::parse_id("xyz:1").is_err());
+
+        // device class, or interface class
+        assert_eq!(ids(&DeviceFilter::new().with_class(0xef))?, ["1-1.2"]);
+        assert_eq!(
+            ids(&DeviceFilter::new().with_class(0x02).with_subclass(0x02))?,
+            ["1-1.2"]
+        );
+        assert!(ids(&DeviceFilter::new().with_class(0x0a).with_subclass(0x02))?.is_empty());
+
+        assert_eq!(
+            ids(&DeviceFilter::new().with_serial("0123456789"))?,
+            ["1-1.2"]
+        );
+        assert_eq!(
+            ids(&DeviceFilter::new().with_manufacturer("STMicroelectronics"))?,
+            ["2-3"]
+        );
+        assert_eq!(
+            ids(&DeviceFilter::new().with_product("Virtual COM Port"))?,
+            ["1-1.2"]
+        );
+        assert_eq!(ids(&DeviceFilter::new().with_bus_num(2))?, ["2-3"]);
+        assert_eq!(
+            ids(&DeviceFilter::new().with_port_path("1-1.2"))?,
+            ["1-1.2"]
+        );
+
+        let add = HotplugEvent::parse(
+            b"add@/devices/usb1/1-1/1-1.2\0ACTION=add\0DEVPATH=/devices/usb1/1-1/1-1.2\0\
+              SUBSYSTEM=usb\0DEVTYPE=usb_device\0PRODUCT=483/5740/200\0TYPE=239/2/1\0\
+              BUSNUM=001\0DEVNUM=005\0",
+        )?
+        .unwrap();
+        let remove = HotplugEvent::parse(
+            b"remove@/devices/usb1/1-1/1-1.2\0ACTION=remove\0DEVPATH=/devices/usb1/1-1/1-1.2\0\
+              SUBSYSTEM=usb\0DEVTYPE=usb_device\0PRODUCT=483/5740/200\0TYPE=239/2/1\0\
+              BUSNUM=001\0DEVNUM=005\0",
+        )?
+        .unwrap();
+
+        assert!(DeviceFilter::parse_id("0483:5740")?.matches_event(&add, &sysfs));
+        assert!(DeviceFilter::parse_id("0483:5740")?.matches_event(&remove, &sysfs));
+        assert!(!DeviceFilter::parse_id("0483:df11")?.matches_event(&add, &sysfs));
+
+        // criteria checked through sysfs
+        let serial = DeviceFilter::new().with_serial("0123456789");
+        let iface_class = DeviceFilter::new().with_class(0x0a);
+
+        assert!(serial.matches_event(&add, &sysfs));
+        assert!(iface_class.matches_event(&add, &sysfs));
+        assert!(!serial.matches_event(&remove, &sysfs));
+        assert!(!DeviceFilter::new()
+            .with_serial("other")
+            .matches_event(&add, &sysfs));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,540 @@
+//! Hotplug notifications for USB devices.
+//!
+//! The kernel broadcasts `uevent` messages over a `NETLINK_KOBJECT_UEVENT` socket. Each message
+//! is a `ACTION@DEVPATH` header, followed by NUL-separated `KEY=VALUE` pairs.
+
+use std::fmt;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::socket::{
+    self, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+
+use crate::{Error, Result, Sysfs, SysfsDevice, UsbDevice};
+
+/// Multicast group of `uevent` messages sent by the kernel.
+const UEVENT_KERNEL_GROUP: u32 = 1;
+/// Maximum length of a received `uevent` message.
+const UEVENT_BUFFER_LEN: usize = 8192;
+/// Prefix of `uevent` messages re-broadcast by `udev`.
+const UDEV_MESSAGE_PREFIX: &[u8] = b"libudev\0";
+
+const SUBSYSTEM_USB: &str = "usb";
+const DEVTYPE_USB_DEVICE: &str = "usb_device";
+
+/// Represents the kind of hotplug event.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HotplugAction {
+    /// A device arrived.
+    #[default]
+    Add,
+    /// A device left.
+    Remove,
+    /// A driver was bound to a device.
+    Bind,
+    /// A driver was unbound from a device.
+    Unbind,
+}
+
+impl HotplugAction {
+    /// Creates a new [HotplugAction].
+    pub const fn new() -> Self {
+        Self::Add
+    }
+
+    /// Parses the [HotplugAction] from a `uevent` action, ignoring unsupported actions.
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "add" => Some(Self::Add),
+            "remove" => Some(Self::Remove),
+            "bind" => Some(Self::Bind),
+            "unbind" => Some(Self::Unbind),
+            _ => None,
+        }
+    }
+}
+
+impl From<&HotplugAction> for &'static str {
+    fn from(val: &HotplugAction) -> Self {
+        match val {
+            HotplugAction::Add => "add",
+            HotplugAction::Remove => "remove",
+            HotplugAction::Bind => "bind",
+            HotplugAction::Unbind => "unbind",
+        }
+    }
+}
+
+impl From<HotplugAction> for &'static str {
+    fn from(val: HotplugAction) -> Self {
+        (&val).into()
+    }
+}
+
+impl fmt::Display for HotplugAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a hotplug event for a USB device.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HotplugEvent {
+    action: HotplugAction,
+    devpath: String,
+    bus_num: u8,
+    dev_num: u8,
+    vendor_id: u16,
+    product_id: u16,
+    device_version: u16,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    driver: Option<String>,
+    seqnum: u64,
+}
+
+impl HotplugEvent {
+    /// Creates a new [HotplugEvent].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a [HotplugEvent] from a raw `uevent` message.
+    ///
+    /// Returns `None` for messages about anything other than USB devices (e.g. interfaces), for
+    /// unsupported actions, and for messages re-broadcast by `udev`.
+    pub fn parse(buf: &[u8]) -> Result<Option<Self>> {
+        if buf.starts_with(UDEV_MESSAGE_PREFIX) {
+            return Ok(None);
+        }
+
+        let mut fields = buf.split(|&b| b == 0).filter(|f| !f.is_empty());
+
+        let header = fields
+            .next()
+            .ok_or(Error::InvalidMessage("empty uevent".into()))?;
+        let header = utf8(header)?;
+        if !header.contains('@') {
+            return Err(Error::InvalidMessage(format!(
+                "invalid uevent header: {header}"
+            )));
+        }
+
+        let pairs: Vec<(&[u8], &[u8])> = fields
+            .filter_map(|f| {
+                let pos = f.iter().position(|&b| b == b'=')?;
+                Some((&f[..pos], &f[pos + 1..]))
+            })
+            .collect();
+        let value = |key: &[u8]| pairs.iter().find(|(k, _)| *k == key).map(|&(_, v)| v);
+
+        // other subsystems are filtered out before their values are parsed
+        if value(b"SUBSYSTEM") != Some(SUBSYSTEM_USB.as_bytes())
+            || value(b"DEVTYPE") != Some(DEVTYPE_USB_DEVICE.as_bytes())
+        {
+            return Ok(None);
+        }
+
+        let Some(action) = value(b"ACTION")
+            .and_then(|a| std::str::from_utf8(a).ok())
+            .and_then(HotplugAction::parse)
+        else {
+            return Ok(None);
+        };
+
+        let mut event = Self::new();
+        event.action = action;
+
+        for &(key, val) in pairs.iter() {
+            let (key, val) = (utf8(key)?, utf8(val)?);
+
+            match key {
+                "DEVPATH" => event.devpath = val.into(),
+                "BUSNUM" => event.bus_num = parse_num(key, val)?,
+                "DEVNUM" => event.dev_num = parse_num(key, val)?,
+                "SEQNUM" => event.seqnum = parse_num(key, val)?,
+                "DRIVER" => event.driver = Some(val.into()),
+                "PRODUCT" => {
+                    [event.vendor_id, event.product_id, event.device_version] =
+                        parse_triple(key, val, 16)?;
+                }
+                "TYPE" => {
+                    [event.class, event.subclass, event.protocol] = parse_triple(key, val, 10)?;
+                }
+                _ => (),
+            }
+        }
+
+        if event.devpath.is_empty() || event.bus_num == 0 || event.dev_num == 0 {
+            return Err(Error::InvalidMessage(format!(
+                "incomplete USB device uevent: {header}"
+            )));
+        }
+
+        Ok(Some(event))
+    }
+
+    /// Gets the [HotplugAction].
+    pub const fn action(&self) -> HotplugAction {
+        self.action
+    }
+
+    /// Gets the kernel device path, relative to the `sysfs` mount point.
+    pub fn devpath(&self) -> &str {
+        self.devpath.as_str()
+    }
+
+    /// Gets the port path (e.g. `1-1.2`), the last component of the device path.
+    pub fn port_path(&self) -> &str {
+        self.devpath.rsplit('/').next().unwrap_or_default()
+    }
+
+    /// Gets the bus number.
+    pub const fn bus_num(&self) -> u8 {
+        self.bus_num
+    }
+
+    /// Gets the device number.
+    pub const fn dev_num(&self) -> u8 {
+        self.dev_num
+    }
+
+    /// Gets the vendor ID.
+    pub const fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// Gets the product ID.
+    pub const fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Gets the device release number (BCD).
+    pub const fn device_version(&self) -> u16 {
+        self.device_version
+    }
+
+    /// Gets the device class.
+    pub const fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Gets the device subclass.
+    pub const fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Gets the device protocol.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets the bound driver name, for [Bind](HotplugAction::Bind) events.
+    pub fn driver(&self) -> Option<&str> {
+        self.driver.as_deref()
+    }
+
+    /// Gets the kernel event sequence number.
+    pub const fn seqnum(&self) -> u64 {
+        self.seqnum
+    }
+
+    /// Gets the USBFS device node path.
+    pub fn devnode(&self, sysfs: &Sysfs) -> PathBuf {
+        sysfs
+            .dev_root()
+            .join(format!("{:03}", self.bus_num))
+            .join(format!("{:03}", self.dev_num))
+    }
+
+    /// Gets the `sysfs` entry of the device, if it is still present.
+    pub fn sysfs_device(&self, sysfs: &Sysfs) -> Result<Option<SysfsDevice>> {
+        sysfs.device(self.port_path())
+    }
+
+    /// Opens the USBFS device node of the device.
+    pub fn open(&self) -> Result<UsbDevice> {
+        UsbDevice::open_bus_dev(self.bus_num, self.dev_num)
+    }
+}
+
+impl fmt::Display for HotplugEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""action": {}, "#, self.action)?;
+        write!(f, r#""devpath": "{}", "#, self.devpath)?;
+        write!(f, r#""bus_num": {}, "#, self.bus_num)?;
+        write!(f, r#""dev_num": {}, "#, self.dev_num)?;
+        write!(f, r#""vendor_id": {}, "#, self.vendor_id)?;
+        write!(f, r#""product_id": {}, "#, self.product_id)?;
+        write!(f, r#""class": {}, "#, self.class)?;
+        write!(f, r#""seqnum": {}"#, self.seqnum)?;
+        write!(f, "}}")
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(key: &str, val: &str) -> Result<T> {
+    val.parse()
+        .map_err(|_| Error::InvalidMessage(format!("invalid {key}: {val}")))
+}
+
+fn utf8(buf: &[u8]) -> Result<&str> {
+    std::str::from_utf8(buf).map_err(|err| Error::InvalidMessage(format!("{err}")))
+}
+
+/// Parses a `A/B/C` uevent value, e.g. `PRODUCT=483/5740/200`.
+///
+/// Values that do not fit the integer type are rejected.
+fn parse_triple<T: TryFrom<u32> + Copy + Default>(
+    key: &str,
+    val: &str,
+    radix: u32,
+) -> Result<[T; 3]> {
+    let mut res = [T::default(); 3];
+    let mut parts = val.split('/');
+
+    for r in res.iter_mut() {
+        *r = parts
+            .next()
+            .and_then(|p| u32::from_str_radix(p, radix).ok())
+            .and_then(|p| T::try_from(p).ok())
+            .ok_or(Error::InvalidMessage(format!("invalid {key}: {val}")))?;
+    }
+
+    Ok(res)
+}
+
+/// Listens for USB device hotplug events on a `NETLINK_KOBJECT_UEVENT` socket.
+///
+/// The socket file descriptor can be registered with `poll`/`epoll` based event loops, and
+/// switched to non-blocking mode with [set_nonblocking](Self::set_nonblocking).
+#[derive(Debug)]
+pub struct HotplugMonitor {
+    fd: OwnedFd,
+}
+
+impl HotplugMonitor {
+    /// Creates a new [HotplugMonitor], subscribed to kernel `uevent` messages.
+    pub fn new() -> Result<Self> {
+        let fd = socket::socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkKObjectUEvent,
+        )?;
+
+        socket::bind(fd.as_raw_fd(), &NetlinkAddr::new(0, UEVENT_KERNEL_GROUP))?;
+
+        Ok(Self { fd })
+    }
+
+    /// Gets the raw socket file descriptor.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Sets whether receiving events blocks when none are pending.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        let mut flags = OFlag::from_bits_truncate(fcntl(self.fd(), FcntlArg::F_GETFL)?);
+        flags.set(OFlag::O_NONBLOCK, nonblocking);
+        fcntl(self.fd(), FcntlArg::F_SETFL(flags))?;
+
+        Ok(())
+    }
+
+    /// Receives a single `uevent` message.
+    ///
+    /// Returns `None` if the message is not a USB device event, or was not sent by the kernel.
+    /// In non-blocking mode, returns an `EAGAIN` error if no message is pending.
+    pub fn recv_event(&self) -> Result<Option<HotplugEvent>> {
+        let mut buf = [0u8; UEVENT_BUFFER_LEN];
+        let (len, addr) =
+            socket::recvfrom::<NetlinkAddr>(self.fd(), &mut buf).map_err(Error::from)?;
+
+        // only trust messages from the kernel, user space processes may send to the group
+        if addr.map_or(true, |a| a.pid() != 0) {
+            return Ok(None);
+        }
+
+        HotplugEvent::parse(&buf[..len])
+    }
+
+    /// Waits for the next USB device event, skipping unrelated messages.
+    pub fn next_event(&self) -> Result<HotplugEvent> {
+        loop {
+            if let Some(event) = self.recv_event()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Discards every pending message, without blocking.
+    pub fn drain(&self) -> Result<()> {
+        let mut buf = [0u8; UEVENT_BUFFER_LEN];
+
+        while socket::recv(self.fd(), &mut buf, MsgFlags::MSG_DONTWAIT).is_ok() {}
+
+        Ok(())
+    }
+}
+
+impl AsFd for HotplugMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for HotplugMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uevent(header: &str, fields: &[&str]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for f in [header].iter().chain(fields) {
+            buf.extend_from_slice(f.as_bytes());
+            buf.push(0);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_parse_hotplug_event() -> Result<()> {
+        let devpath = "/devices/pci0000:00/0000:00:14.0/usb1/1-1/1-1.2";
+        let add = uevent(
+            format!("add@{devpath}").as_str(),
+            &[
+                "ACTION=add",
+                format!("DEVPATH={devpath}").as_str(),
+                "SUBSYSTEM=usb",
+                "MAJOR=189",
+                "MINOR=4",
+                "DEVNAME=bus/usb/001/005",
+                "DEVTYPE=usb_device",
+                "PRODUCT=483/5740/200",
+                "TYPE=239/2/1",
+                "BUSNUM=001",
+                "DEVNUM=005",
+                "SEQNUM=4242",
+            ],
+        );
+
+        let event = HotplugEvent::parse(&add)?.unwrap();
+
+        assert_eq!(event.action(), HotplugAction::Add);
+        assert_eq!(event.devpath(), devpath);
+        assert_eq!(event.port_path(), "1-1.2");
+        assert_eq!((event.bus_num(), event.dev_num()), (1, 5));
+        assert_eq!((event.vendor_id(), event.product_id()), (0x0483, 0x5740));
+        assert_eq!(event.device_version(), 0x0200);
+        assert_eq!(
+            (event.class(), event.subclass(), event.protocol()),
+            (0xef, 2, 1)
+        );
+        assert_eq!(event.seqnum(), 4242);
+        assert_eq!(
+            event.devnode(&Sysfs::new()),
+            PathBuf::from("/dev/bus/usb/001/005")
+        );
+
+        let bind = uevent(
+            format!("bind@{devpath}").as_str(),
+            &[
+                "ACTION=bind",
+                format!("DEVPATH={devpath}").as_str(),
+                "SUBSYSTEM=usb",
+                "DEVTYPE=usb_device",
+                "DRIVER=usb",
+                "BUSNUM=001",
+                "DEVNUM=005",
+            ],
+        );
+        let event = HotplugEvent::parse(&bind)?.unwrap();
+
+        assert_eq!(event.action(), HotplugAction::Bind);
+        assert_eq!(event.driver(), Some("usb"));
+
+        // interface events are filtered out
+        let iface = uevent(
+            format!("add@{devpath}/1-1.2:1.0").as_str(),
+            &[
+                "ACTION=add",
+                "SUBSYSTEM=usb",
+                "DEVTYPE=usb_interface",
+                "INTERFACE=2/2/1",
+            ],
+        );
+        assert_eq!(HotplugEvent::parse(&iface)?, None);
+
+        // unsupported actions are filtered out
+        let change = uevent(
+            format!("change@{devpath}").as_str(),
+            &["ACTION=change", "SUBSYSTEM=usb", "DEVTYPE=usb_device"],
+        );
+        assert_eq!(HotplugEvent::parse(&change)?, None);
+
+        // udev messages are filtered out
+        assert_eq!(HotplugEvent::parse(b"libudev\0\xfe\xed\xca\xfe")?, None);
+
+        // malformed messages
+        assert!(HotplugEvent::parse(&[]).is_err());
+        assert!(HotplugEvent::parse(&uevent("garbage", &[])).is_err());
+        assert!(HotplugEvent::parse(&uevent(
+            "remove@/devices/usb1/1-1",
+            &[
+                "ACTION=remove",
+                "DEVPATH=/devices/usb1/1-1",
+                "SUBSYSTEM=usb",
+                "DEVTYPE=usb_device",
+                "BUSNUM=001",
+            ],
+        ))
+        .is_err());
+        assert!(HotplugEvent::parse(&uevent(
+            "add@/devices/usb1/1-1",
+            &[
+                "ACTION=add",
+                "SUBSYSTEM=usb",
+                "DEVTYPE=usb_device",
+                "PRODUCT=483/zz/200",
+            ],
+        ))
+        .is_err());
+        assert!(HotplugEvent::parse(&uevent(
+            "add@/devices/usb1/1-1",
+            &[
+                "ACTION=add",
+                "SUBSYSTEM=usb",
+                "DEVTYPE=usb_device",
+                "TYPE=256/0/0",
+            ],
+        ))
+        .is_err());
+
+        // values of other subsystems are not parsed
+        let input = uevent(
+            "add@/devices/virtual/input/input7",
+            &[
+                "ACTION=add",
+                "SUBSYSTEM=input",
+                "PRODUCT=3/abcd/xyz/110",
+                "TYPE=keyboard",
+                "SEQNUM=-1",
+            ],
+        );
+        assert_eq!(HotplugEvent::parse(&input)?, None);
+
+        Ok(())
+    }
+}
@@ -1,23 +1,94 @@
+//! `USBDEVFS` request codes, and their `ioctl` wrappers.
+//!
+//! Request codes mirror `linux/usbdevice_fs.h`, including the header's directions. Several
+//! requests have directions that do not match their data flow (e.g. `SETINTERFACE` is `_IOR`, but
+//! only passes data to the kernel, and `GETDRIVER` is `_IOW`, but returns the driver name). The
+//! direction is part of the request code, so the codes follow the header, while the wrappers
+//! take the pointer mutability the kernel actually needs.
+
+use std::ffi::c_void;
+use std::mem::size_of;
+
+use nix::sys::ioctl::ioctl_num_type;
+
 use super::*;
 
-ioctl_readwrite!(usbfs_control, b'U', 0, UsbfsCtrlTransferFfi);
-ioctl_read!(usbfs_setinterface, b'U', 4, UsbfsSetInterface);
-ioctl_read!(usbfs_setconfiguration, b'U', 5, u32);
-ioctl_write_ptr!(usbfs_getdriver, b'U', 8, UsbfsGetDriver);
-ioctl_read!(usbfs_submiturb, b'U', 10, UrbFfi);
-ioctl_none!(usbfs_discardurb, b'U', 11);
-ioctl_write_ptr!(usbfs_reapurbndelay, b'U', 13, UrbFfi);
-ioctl_read!(usbfs_claiminterface, b'U', 15, u32);
-ioctl_read!(usbfs_releaseinterface, b'U', 16, u32);
-ioctl_write_ptr!(usbfs_connectinfo, b'U', 17, UsbfsConnectInfo);
-ioctl_readwrite!(usbfs_ioctl, b'U', 18, UsbfsIoctlFfi);
-ioctl_none!(usbfs_reset, b'U', 20);
-ioctl_read!(usbfs_clear_halt, b'U', 21, u32);
-ioctl_none!(usbfs_disconnect, b'U', 22);
-ioctl_none!(usbfs_connect, b'U', 23);
-ioctl_read!(usbfs_get_capabilities, b'U', 26, u32);
-ioctl_read!(usbfs_disconnect_claim, b'U', 27, UsbfsDisconnectClaim);
-ioctl_read!(usbfs_alloc_streams, b'U', 28, UsbfsStreamsFfi);
-ioctl_read!(usbfs_free_streams, b'U', 29, UsbfsStreamsFfi);
-ioctl_write_int!(usbfs_drop_privileges, b'U', 30);
-ioctl_none!(usbfs_get_speed, b'U', 31);
+const USBDEVFS_MAGIC: u8 = b'U';
+
+pub(crate) const USBDEVFS_CONTROL: ioctl_num_type =
+    request_code_readwrite!(USBDEVFS_MAGIC, 0, size_of::<UsbfsCtrlTransferFfi>());
+pub(crate) const USBDEVFS_BULK: ioctl_num_type =
+    request_code_readwrite!(USBDEVFS_MAGIC, 2, size_of::<UsbfsBulkTransferFfi>());
+pub(crate) const USBDEVFS_RESETEP: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 3, size_of::<u32>());
+pub(crate) const USBDEVFS_SETINTERFACE: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 4, size_of::<UsbfsSetInterface>());
+pub(crate) const USBDEVFS_SETCONFIGURATION: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 5, size_of::<u32>());
+pub(crate) const USBDEVFS_GETDRIVER: ioctl_num_type =
+    request_code_write!(USBDEVFS_MAGIC, 8, size_of::<UsbfsGetDriver>());
+pub(crate) const USBDEVFS_SUBMITURB: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 10, size_of::<UrbFfi>());
+pub(crate) const USBDEVFS_DISCARDURB: ioctl_num_type = request_code_none!(USBDEVFS_MAGIC, 11);
+pub(crate) const USBDEVFS_REAPURB: ioctl_num_type =
+    request_code_write!(USBDEVFS_MAGIC, 12, size_of::<*mut c_void>());
+pub(crate) const USBDEVFS_REAPURBNDELAY: ioctl_num_type =
+    request_code_write!(USBDEVFS_MAGIC, 13, size_of::<*mut c_void>());
+pub(crate) const USBDEVFS_CLAIMINTERFACE: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 15, size_of::<u32>());
+pub(crate) const USBDEVFS_RELEASEINTERFACE: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 16, size_of::<u32>());
+pub(crate) const USBDEVFS_CONNECTINFO: ioctl_num_type =
+    request_code_write!(USBDEVFS_MAGIC, 17, size_of::<UsbfsConnectInfo>());
+pub(crate) const USBDEVFS_IOCTL: ioctl_num_type =
+    request_code_readwrite!(USBDEVFS_MAGIC, 18, size_of::<UsbfsIoctlFfi>());
+pub(crate) const USBDEVFS_RESET: ioctl_num_type = request_code_none!(USBDEVFS_MAGIC, 20);
+pub(crate) const USBDEVFS_CLEAR_HALT: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 21, size_of::<u32>());
+pub(crate) const USBDEVFS_DISCONNECT: ioctl_num_type = request_code_none!(USBDEVFS_MAGIC, 22);
+pub(crate) const USBDEVFS_CONNECT: ioctl_num_type = request_code_none!(USBDEVFS_MAGIC, 23);
+pub(crate) const USBDEVFS_GET_CAPABILITIES: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 26, size_of::<u32>());
+pub(crate) const USBDEVFS_DISCONNECT_CLAIM: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 27, size_of::<UsbfsDisconnectClaim>());
+pub(crate) const USBDEVFS_ALLOC_STREAMS: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 28, size_of::<UsbfsStreamsFfi>());
+pub(crate) const USBDEVFS_FREE_STREAMS: ioctl_num_type =
+    request_code_read!(USBDEVFS_MAGIC, 29, size_of::<UsbfsStreamsFfi>());
+pub(crate) const USBDEVFS_DROP_PRIVILEGES: ioctl_num_type =
+    request_code_write!(USBDEVFS_MAGIC, 30, size_of::<u32>());
+pub(crate) const USBDEVFS_GET_SPEED: ioctl_num_type = request_code_none!(USBDEVFS_MAGIC, 31);
+
+ioctl_readwrite_bad!(usbfs_control, USBDEVFS_CONTROL, UsbfsCtrlTransferFfi);
+ioctl_readwrite_bad!(usbfs_bulk, USBDEVFS_BULK, UsbfsBulkTransferFfi);
+ioctl_read_bad!(usbfs_resetep, USBDEVFS_RESETEP, u32);
+ioctl_read_bad!(usbfs_setinterface, USBDEVFS_SETINTERFACE, UsbfsSetInterface);
+ioctl_read_bad!(usbfs_setconfiguration, USBDEVFS_SETCONFIGURATION, u32);
+// `_IOW`, but the kernel writes the driver name back
+ioctl_read_bad!(usbfs_getdriver, USBDEVFS_GETDRIVER, UsbfsGetDriver);
+ioctl_read_bad!(usbfs_submiturb, USBDEVFS_SUBMITURB, UrbFfi);
+// the URB pointer is passed as the argument of a `_IO` request
+ioctl_write_ptr_bad!(usbfs_discardurb, USBDEVFS_DISCARDURB, UrbFfi);
+// `_IOW`, but the kernel writes the reaped URB pointer through a `void **` argument
+ioctl_read_bad!(usbfs_reapurb, USBDEVFS_REAPURB, *mut c_void);
+ioctl_read_bad!(usbfs_reapurbndelay, USBDEVFS_REAPURBNDELAY, *mut c_void);
+ioctl_read_bad!(usbfs_claiminterface, USBDEVFS_CLAIMINTERFACE, u32);
+ioctl_read_bad!(usbfs_releaseinterface, USBDEVFS_RELEASEINTERFACE, u32);
+// `_IOW`, but the kernel writes the connection information back
+ioctl_read_bad!(usbfs_connectinfo, USBDEVFS_CONNECTINFO, UsbfsConnectInfo);
+ioctl_readwrite_bad!(usbfs_ioctl, USBDEVFS_IOCTL, UsbfsIoctlFfi);
+ioctl_none_bad!(usbfs_reset, USBDEVFS_RESET);
+ioctl_read_bad!(usbfs_clear_halt, USBDEVFS_CLEAR_HALT, u32);
+ioctl_none_bad!(usbfs_disconnect, USBDEVFS_DISCONNECT);
+ioctl_none_bad!(usbfs_connect, USBDEVFS_CONNECT);
+ioctl_read_bad!(usbfs_get_capabilities, USBDEVFS_GET_CAPABILITIES, u32);
+ioctl_read_bad!(
+    usbfs_disconnect_claim,
+    USBDEVFS_DISCONNECT_CLAIM,
+    UsbfsDisconnectClaim
+);
+// the endpoint addresses directly follow the record in memory
+ioctl_read_bad!(usbfs_alloc_streams, USBDEVFS_ALLOC_STREAMS, UsbfsStreamsFfi);
+ioctl_read_bad!(usbfs_free_streams, USBDEVFS_FREE_STREAMS, UsbfsStreamsFfi);
+ioctl_write_ptr_bad!(usbfs_drop_privileges, USBDEVFS_DROP_PRIVILEGES, u32);
+ioctl_none_bad!(usbfs_get_speed, USBDEVFS_GET_SPEED);
@@ -1,37 +1,91 @@
 #[macro_use]
 extern crate nix;
 
+mod abi;
+pub mod backend;
+pub mod class;
 mod constants;
+pub mod descriptor;
+mod device;
 mod error;
+pub mod filter;
+pub mod hotplug;
 mod ioctl;
+pub mod sysfs;
 mod types;
+pub mod usbmon;
 
+pub use backend::{
+    IoctlBackend, MockControl, MockDevice, MockResponse, Recorder, Replay, Session, SessionOp,
+    SessionRecord, UsbBackend,
+};
+pub use class::{
+    BulkOnly, CdcAcm, CdcEcm, CdcNcm, CdcNotification, Ch34x, ControlLineState, Cp210x, Dfu,
+    DfuFile, Ftdi, Hid, HidValue, LineCoding, ModemStatus, Pl2303, Printer, ReportDescriptor,
+    ReportType, Scsi, SenseData, SenseKey, SerialPort, SerialState, Uac, Usbtmc, Uvc,
+};
 pub use constants::*;
+pub use descriptor::{
+    ConfigDescriptor, Descriptors, DeviceDescriptor, EndpointDescriptor, InterfaceDescriptor,
+    TransferType,
+};
+pub use device::{
+    is_stall, ClaimedInterface, DetachPolicy, ResetStatus, StallPolicy, UrbId, UrbStatus, UsbDevice,
+};
 pub use error::*;
+pub use filter::{find_devices, DeviceFilter};
+pub use hotplug::{HotplugAction, HotplugEvent, HotplugMonitor};
+pub use sysfs::{Sysfs, SysfsDevice, SysfsInterface};
+pub use usbmon::{PcapWriter, PcapngWriter, Usbmon, UsbmonEvent};
 
+pub use types::bulk_transfer::UsbfsBulkTransfer;
 pub use types::cap::UsbfsCap;
 pub use types::connect_info::UsbfsConnectInfo;
 pub use types::ctrl_transfer::UsbfsCtrlTransfer;
 pub use types::disconnect_claim::{UsbfsDisconnectClaim, UsbfsDisconnectClaimFlag};
 pub use types::driver::{DriverName, UsbfsGetDriver};
+pub use types::endpoint::{Direction, Endpoint};
 pub use types::interface::UsbfsSetInterface;
 pub use types::ioctl::{UsbfsIoctl, UsbfsIoctlData};
 pub use types::iso_packet_desc::UsbfsIsoPacketDesc;
 pub use types::speed::UsbfsSpeed;
 pub use types::streams::UsbfsStreams;
-pub use types::urb::{TransferInfo, Urb, UrbUserContext};
+pub use types::urb::{
+    TransferInfo, Urb, UrbUserContext, URB_BULK_CONTINUATION, URB_ISO_ASAP, URB_NO_INTERRUPT,
+    URB_SHORT_NOT_OK, URB_TYPE_BULK, URB_TYPE_CONTROL, URB_TYPE_INTERRUPT, URB_TYPE_ISO,
+    URB_ZERO_PACKET,
+};
 
-use types::{UrbFfi, UsbfsCtrlTransferFfi, UsbfsIoctlFfi, UsbfsStreamsFfi};
+use std::ffi::c_void;
+
+pub use types::UrbFfi;
+
+use types::{
+    UsbfsBulkTransferFfi, UsbfsCtrlTransferFfi, UsbfsIoctlFfi, UsbfsStreamsBuffer, UsbfsStreamsFfi,
+};
 
 /// USBFS Control transfer.
 ///
 /// The user is responsible for setting all the relevant [UsbfsCtrlTransfer] fields.
-pub fn usbfs_control(fd: i32, ctrl: &mut UsbfsCtrlTransfer) -> Result<()> {
+///
+/// Returns the number of bytes transferred during the data stage.
+pub fn usbfs_control(fd: i32, ctrl: &mut UsbfsCtrlTransfer) -> Result<usize> {
     let mut ctrl = UsbfsCtrlTransferFfi::from(ctrl);
-    unsafe {
-        ioctl::usbfs_control(fd, &mut ctrl)?;
-    }
-    Ok(())
+    let len = unsafe { ioctl::usbfs_control(fd, &mut ctrl)? };
+    Ok(len as usize)
+}
+
+/// USBFS Bulk transfer.
+///
+/// Synchronously transfers data on a Bulk or Interrupt endpoint.
+///
+/// The user is responsible for setting all the relevant [UsbfsBulkTransfer] fields.
+///
+/// Returns the number of bytes transferred.
+pub fn usbfs_bulk(fd: i32, bulk: &mut UsbfsBulkTransfer) -> Result<usize> {
+    let mut bulk = UsbfsBulkTransferFfi::from(bulk);
+    let len = unsafe { ioctl::usbfs_bulk(fd, &mut bulk)? };
+    Ok(len as usize)
 }
 
 /// USBFS Set Interface
@@ -64,32 +118,58 @@ pub fn usbfs_get_driver(fd: i32, get_driver: &mut UsbfsGetDriver) -> Result<()>
 
 /// USBFS Submit URB
 ///
-/// The user is responsible for setting all the relevant [Urb] fields.
-pub fn usbfs_submit_urb(fd: i32, urb: &mut Urb) -> Result<()> {
-    let mut urb_ffi = UrbFfi::from(urb);
-    unsafe {
-        ioctl::usbfs_submiturb(fd, &mut urb_ffi)?;
-    }
+/// Queues the URB for asynchronous processing. For Isochronous transfers, the
+/// [UsbfsIsoPacketDesc] list must directly follow the [UrbFfi] record in memory.
+///
+/// Prefer [UsbDevice::submit_urb], which keeps the URB memory alive until it is reaped.
+///
+/// # Safety
+///
+/// The URB record, its buffer and packet descriptors must stay valid, and must not move, until
+/// the URB is reaped, or the file descriptor is closed.
+pub unsafe fn usbfs_submit_urb(fd: i32, urb: *mut UrbFfi) -> Result<()> {
+    ioctl::usbfs_submiturb(fd, urb)?;
     Ok(())
 }
 
 /// USBFS Discard URB
-pub fn usbfs_discard_urb(fd: i32) -> Result<()> {
-    unsafe {
-        ioctl::usbfs_discardurb(fd)?;
-    }
+///
+/// Cancels a submitted URB. The URB still needs to be reaped.
+///
+/// # Safety
+///
+/// The URB pointer must be one previously passed to [usbfs_submit_urb].
+pub unsafe fn usbfs_discard_urb(fd: i32, urb: *mut UrbFfi) -> Result<()> {
+    ioctl::usbfs_discardurb(fd, urb)?;
     Ok(())
 }
 
+/// USBFS Reap URB
+///
+/// Waits for a submitted URB to complete, and returns its pointer.
+///
+/// # Safety
+///
+/// The kernel writes the transfer results into the memory of the reaped URB, so every submitted
+/// URB must still be valid.
+pub unsafe fn usbfs_reap_urb(fd: i32) -> Result<*mut UrbFfi> {
+    let mut urb: *mut c_void = std::ptr::null_mut();
+    ioctl::usbfs_reapurb(fd, &mut urb)?;
+    Ok(urb as *mut UrbFfi)
+}
+
 /// USBFS Reap URB N_Delay
 ///
-/// The user is responsible for setting all the relevant [Urb] fields.
-pub fn usbfs_reap_urb_ndelay(fd: i32, urb: &mut Urb) -> Result<()> {
-    let urb_ffi = UrbFfi::from(urb);
-    unsafe {
-        ioctl::usbfs_reapurbndelay(fd, &urb_ffi)?;
-    }
-    Ok(())
+/// Returns the pointer of a completed URB, or an `EAGAIN` error if none completed.
+///
+/// # Safety
+///
+/// The kernel writes the transfer results into the memory of the reaped URB, so every submitted
+/// URB must still be valid.
+pub unsafe fn usbfs_reap_urb_ndelay(fd: i32) -> Result<*mut UrbFfi> {
+    let mut urb: *mut c_void = std::ptr::null_mut();
+    ioctl::usbfs_reapurbndelay(fd, &mut urb)?;
+    Ok(urb as *mut UrbFfi)
 }
 
 /// USBFS Claim Interface
@@ -138,9 +218,23 @@ pub fn usbfs_reset(fd: i32) -> Result<()> {
 }
 
 /// USBFS Clear Halt
-pub fn usbfs_clear_halt(fd: i32, iface: &mut u32) -> Result<()> {
+///
+/// Clears the halt (stall) condition on the endpoint, the `ep` argument is the endpoint
+/// address, including the direction bit.
+pub fn usbfs_clear_halt(fd: i32, ep: &mut u32) -> Result<()> {
+    unsafe {
+        ioctl::usbfs_clear_halt(fd, ep)?;
+    }
+    Ok(())
+}
+
+/// USBFS Reset Endpoint
+///
+/// Resets the host-side data toggle of the endpoint, without sending a request to the device.
+/// The `ep` argument is the endpoint address, including the direction bit.
+pub fn usbfs_reset_ep(fd: i32, ep: &mut u32) -> Result<()> {
     unsafe {
-        ioctl::usbfs_clear_halt(fd, iface)?;
+        ioctl::usbfs_resetep(fd, ep)?;
     }
     Ok(())
 }
@@ -185,9 +279,9 @@ pub fn usbfs_disconnect_claim(fd: i32, claim: &mut UsbfsDisconnectClaim) -> Resu
 ///
 /// The user is responsible for setting all the relevant [UsbfsStreams] fields.
 pub fn usbfs_alloc_streams(fd: i32, streams: &mut UsbfsStreams) -> Result<()> {
-    let mut streams_ffi = UsbfsStreamsFfi::from(streams);
+    let mut streams_ffi = UsbfsStreamsBuffer::from(&*streams);
     unsafe {
-        ioctl::usbfs_alloc_streams(fd, &mut streams_ffi)?;
+        ioctl::usbfs_alloc_streams(fd, streams_ffi.as_mut_ptr())?;
     }
     Ok(())
 }
@@ -198,25 +292,30 @@ pub fn usbfs_alloc_streams(fd: i32, streams: &mut UsbfsStreams) -> Result<()> {
 ///
 /// The user is responsible for setting all the relevant [UsbfsStreams] fields.
 pub fn usbfs_free_streams(fd: i32, streams: &mut UsbfsStreams) -> Result<()> {
-    let mut streams_ffi = UsbfsStreamsFfi::from(streams);
+    let mut streams_ffi = UsbfsStreamsBuffer::from(&*streams);
     unsafe {
-        ioctl::usbfs_free_streams(fd, &mut streams_ffi)?;
+        ioctl::usbfs_free_streams(fd, streams_ffi.as_mut_ptr())?;
     }
     Ok(())
 }
 
 /// USBFS Drop Privileges
+///
+/// The `privileges` argument is a bitmask of interfaces that can still be claimed.
+///
+/// **NOTE** the kernel mask is 32 bits wide, upper bits are ignored.
 pub fn usbfs_drop_privileges(fd: i32, privileges: u64) -> Result<()> {
+    let privileges = privileges as u32;
     unsafe {
-        ioctl::usbfs_drop_privileges(fd, privileges)?;
+        ioctl::usbfs_drop_privileges(fd, &privileges)?;
     }
     Ok(())
 }
 
 /// USBFS Get Speed
-pub fn usbfs_get_speed(fd: i32) -> Result<()> {
-    unsafe {
-        ioctl::usbfs_get_speed(fd)?;
-    }
-    Ok(())
+///
+/// Returns the [UsbfsSpeed] the device is operating at.
+pub fn usbfs_get_speed(fd: i32) -> Result<UsbfsSpeed> {
+    let speed = unsafe { ioctl::usbfs_get_speed(fd)? };
+    Ok(UsbfsSpeed::create(speed as u32))
 }
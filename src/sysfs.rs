@@ -0,0 +1,557 @@
+//! USB device enumeration through `sysfs`.
+//!
+//! Every USB device is listed under `/sys/bus/usb/devices`, named after its port path (e.g.
+//! `1-1.2`). Root hubs are named after their bus (e.g. `usb1`), and interfaces are named after
+//! their device, configuration and interface number (e.g. `1-1.2:1.0`).
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result, UsbfsSpeed, SYSFS_MOUNT_PATH, USBFS_DEVICE_PATH};
+
+/// Path of the USB devices directory, relative to the `sysfs` mount point.
+const DEVICES_DIR: &str = "bus/usb/devices";
+
+/// Reads a trimmed attribute from a `sysfs` directory.
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|s| s.trim().to_owned())
+}
+
+/// Reads a hexadecimal attribute from a `sysfs` directory.
+fn read_hex<T: TryFrom<u32>>(dir: &Path, name: &str) -> Option<T> {
+    u32::from_str_radix(read_attr(dir, name)?.as_str(), 16)
+        .ok()
+        .and_then(|v| T::try_from(v).ok())
+}
+
+/// Reads a decimal attribute from a `sysfs` directory.
+fn read_dec<T: std::str::FromStr>(dir: &Path, name: &str) -> Option<T> {
+    read_attr(dir, name)?.parse().ok()
+}
+
+/// Parses the `speed` attribute, reported in Mbit/s.
+fn parse_speed(speed: &str) -> UsbfsSpeed {
+    match speed {
+        "1.5" => UsbfsSpeed::Low,
+        "12" => UsbfsSpeed::Full,
+        "480" => UsbfsSpeed::High,
+        "53.3" => UsbfsSpeed::Wireless,
+        "5000" => UsbfsSpeed::Super,
+        "10000" | "20000" => UsbfsSpeed::SuperPlus,
+        _ => UsbfsSpeed::Unknown,
+    }
+}
+
+/// Writes an optional string as a JSON value.
+fn fmt_opt_str(f: &mut fmt::Formatter<'_>, val: Option<&str>) -> fmt::Result {
+    match val {
+        Some(s) => write!(f, r#""{s}""#),
+        None => write!(f, "null"),
+    }
+}
+
+/// Represents the `sysfs` and device node locations used for enumeration.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sysfs {
+    root: PathBuf,
+    dev_root: PathBuf,
+}
+
+impl Sysfs {
+    /// Creates a new [Sysfs] using the default `/sys` and `/dev/bus/usb` locations.
+    pub fn new() -> Self {
+        Self::create(SYSFS_MOUNT_PATH, USBFS_DEVICE_PATH)
+    }
+
+    /// Creates a new [Sysfs] from the provided `sysfs` mount point, and USBFS device node
+    /// directory.
+    pub fn create<R: Into<PathBuf>, D: Into<PathBuf>>(root: R, dev_root: D) -> Self {
+        Self {
+            root: root.into(),
+            dev_root: dev_root.into(),
+        }
+    }
+
+    /// Gets the `sysfs` mount point.
+    pub fn root(&self) -> &Path {
+        self.root.as_ref()
+    }
+
+    /// Sets the `sysfs` mount point.
+    pub fn set_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.root = root.into();
+    }
+
+    /// Builder function that sets the `sysfs` mount point.
+    pub fn with_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.set_root(root);
+        self
+    }
+
+    /// Gets the USBFS device node directory.
+    pub fn dev_root(&self) -> &Path {
+        self.dev_root.as_ref()
+    }
+
+    /// Sets the USBFS device node directory.
+    pub fn set_dev_root<P: Into<PathBuf>>(&mut self, dev_root: P) {
+        self.dev_root = dev_root.into();
+    }
+
+    /// Builder function that sets the USBFS device node directory.
+    pub fn with_dev_root<P: Into<PathBuf>>(mut self, dev_root: P) -> Self {
+        self.set_dev_root(dev_root);
+        self
+    }
+
+    /// Gets the directory listing every USB device and interface.
+    pub fn devices_path(&self) -> PathBuf {
+        self.root.join(DEVICES_DIR)
+    }
+
+    /// Lists every USB device, sorted by bus and device number.
+    pub fn devices(&self) -> Result<Vec<SysfsDevice>> {
+        let mut devices: Vec<SysfsDevice> = fs::read_dir(self.devices_path())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().contains(':'))
+            .filter_map(|entry| SysfsDevice::read(entry.path()).ok())
+            .collect();
+
+        devices.sort_by_key(|d| (d.bus_num, d.dev_num));
+
+        Ok(devices)
+    }
+
+    /// Gets the USB device at the provided port path (e.g. `1-1.2`), if present.
+    pub fn device(&self, port_path: &str) -> Result<Option<SysfsDevice>> {
+        let path = self.devices_path().join(port_path);
+
+        if port_path.contains(['/', ':']) || !path.exists() {
+            Ok(None)
+        } else {
+            SysfsDevice::read(path).map(Some)
+        }
+    }
+
+    /// Gets the USB device with the provided bus and device numbers, if present.
+    pub fn device_by_bus_dev(&self, bus_num: u8, dev_num: u8) -> Result<Option<SysfsDevice>> {
+        Ok(self
+            .devices()?
+            .into_iter()
+            .find(|d| d.bus_num == bus_num && d.dev_num == dev_num))
+    }
+
+    /// Gets the USBFS device node path for the provided device.
+    pub fn devnode(&self, device: &SysfsDevice) -> PathBuf {
+        self.dev_root
+            .join(format!("{:03}", device.bus_num))
+            .join(format!("{:03}", device.dev_num))
+    }
+}
+
+impl Default for Sysfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents a USB device listed in `sysfs`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SysfsDevice {
+    path: PathBuf,
+    port_path: String,
+    bus_num: u8,
+    dev_num: u8,
+    vendor_id: u16,
+    product_id: u16,
+    device_version: u16,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    configuration_value: Option<u8>,
+    speed: UsbfsSpeed,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    serial: Option<String>,
+    interfaces: Vec<SysfsInterface>,
+}
+
+impl SysfsDevice {
+    /// Creates a new [SysfsDevice].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the [SysfsDevice] attributes from its `sysfs` directory.
+    pub fn read<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let port_path = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let bus_num = read_dec(&path, "busnum")
+            .ok_or(Error::NotFound(format!("busnum for {}", path.display())))?;
+        let dev_num = read_dec(&path, "devnum")
+            .ok_or(Error::NotFound(format!("devnum for {}", path.display())))?;
+
+        let mut interfaces: Vec<SysfsInterface> = fs::read_dir(&path)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(format!("{port_path}:").as_str())
+            })
+            .map(|entry| SysfsInterface::read(entry.path()))
+            .collect();
+
+        interfaces.sort_by_key(|i| i.number);
+
+        Ok(Self {
+            bus_num,
+            dev_num,
+            vendor_id: read_hex(&path, "idVendor").unwrap_or(0),
+            product_id: read_hex(&path, "idProduct").unwrap_or(0),
+            device_version: read_hex(&path, "bcdDevice").unwrap_or(0),
+            class: read_hex(&path, "bDeviceClass").unwrap_or(0),
+            subclass: read_hex(&path, "bDeviceSubClass").unwrap_or(0),
+            protocol: read_hex(&path, "bDeviceProtocol").unwrap_or(0),
+            configuration_value: read_dec(&path, "bConfigurationValue"),
+            speed: read_attr(&path, "speed")
+                .map(|s| parse_speed(s.as_str()))
+                .unwrap_or_default(),
+            manufacturer: read_attr(&path, "manufacturer"),
+            product: read_attr(&path, "product"),
+            serial: read_attr(&path, "serial"),
+            interfaces,
+            port_path,
+            path,
+        })
+    }
+
+    /// Gets the `sysfs` directory of the device.
+    pub fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+
+    /// Gets the port path (e.g. `1-1.2`), which stays the same across re-enumeration.
+    ///
+    /// Root hubs use their bus name (e.g. `usb1`).
+    pub fn port_path(&self) -> &str {
+        self.port_path.as_str()
+    }
+
+    /// Gets the chain of hub port numbers leading to the device.
+    ///
+    /// Empty for root hubs.
+    pub fn port_numbers(&self) -> Vec<u8> {
+        self.port_path
+            .split_once('-')
+            .map(|(_, ports)| ports.split('.').filter_map(|p| p.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the bus number.
+    pub const fn bus_num(&self) -> u8 {
+        self.bus_num
+    }
+
+    /// Gets the device number.
+    pub const fn dev_num(&self) -> u8 {
+        self.dev_num
+    }
+
+    /// Gets the vendor ID.
+    pub const fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// Gets the product ID.
+    pub const fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Gets the device release number (BCD).
+    pub const fn device_version(&self) -> u16 {
+        self.device_version
+    }
+
+    /// Gets the device class.
+    pub const fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Gets the device subclass.
+    pub const fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Gets the device protocol.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets the active configuration value, if the device is configured.
+    pub const fn configuration_value(&self) -> Option<u8> {
+        self.configuration_value
+    }
+
+    /// Gets the connection speed.
+    pub const fn speed(&self) -> UsbfsSpeed {
+        self.speed
+    }
+
+    /// Gets the manufacturer string, if any.
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.manufacturer.as_deref()
+    }
+
+    /// Gets the product string, if any.
+    pub fn product(&self) -> Option<&str> {
+        self.product.as_deref()
+    }
+
+    /// Gets the serial number string, if any.
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Gets the interfaces of the active configuration.
+    pub fn interfaces(&self) -> &[SysfsInterface] {
+        self.interfaces.as_ref()
+    }
+}
+
+impl fmt::Display for SysfsDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""port_path": "{}", "#, self.port_path)?;
+        write!(f, r#""bus_num": {}, "#, self.bus_num)?;
+        write!(f, r#""dev_num": {}, "#, self.dev_num)?;
+        write!(f, r#""vendor_id": {}, "#, self.vendor_id)?;
+        write!(f, r#""product_id": {}, "#, self.product_id)?;
+        write!(f, r#""class": {}, "#, self.class)?;
+        write!(f, r#""speed": "{}", "#, <&str>::from(self.speed))?;
+        write!(f, r#""manufacturer": "#)?;
+        fmt_opt_str(f, self.manufacturer())?;
+        write!(f, r#", "product": "#)?;
+        fmt_opt_str(f, self.product())?;
+        write!(f, r#", "serial": "#)?;
+        fmt_opt_str(f, self.serial())?;
+        write!(f, r#", "interfaces": ["#)?;
+        for (i, iface) in self.interfaces.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{iface}")?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+/// Represents a USB interface listed in `sysfs`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SysfsInterface {
+    number: u8,
+    alt_setting: u8,
+    class: u8,
+    subclass: u8,
+    protocol: u8,
+    driver: Option<String>,
+}
+
+impl SysfsInterface {
+    /// Creates a new [SysfsInterface].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the [SysfsInterface] attributes from its `sysfs` directory.
+    pub fn read<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+
+        Self {
+            number: read_hex(path, "bInterfaceNumber").unwrap_or(0),
+            alt_setting: read_dec(path, "bAlternateSetting").unwrap_or(0),
+            class: read_hex(path, "bInterfaceClass").unwrap_or(0),
+            subclass: read_hex(path, "bInterfaceSubClass").unwrap_or(0),
+            protocol: read_hex(path, "bInterfaceProtocol").unwrap_or(0),
+            driver: fs::read_link(path.join("driver"))
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned())),
+        }
+    }
+
+    /// Gets the interface number.
+    pub const fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Gets the active alternate setting.
+    pub const fn alt_setting(&self) -> u8 {
+        self.alt_setting
+    }
+
+    /// Gets the interface class.
+    pub const fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Gets the interface subclass.
+    pub const fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Gets the interface protocol.
+    pub const fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Gets the name of the bound kernel driver, if any.
+    pub fn driver(&self) -> Option<&str> {
+        self.driver.as_deref()
+    }
+}
+
+impl fmt::Display for SysfsInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""number": {}, "#, self.number)?;
+        write!(f, r#""alt_setting": {}, "#, self.alt_setting)?;
+        write!(f, r#""class": {}, "#, self.class)?;
+        write!(f, r#""subclass": {}, "#, self.subclass)?;
+        write!(f, r#""protocol": {}, "#, self.protocol)?;
+        write!(f, r#""driver": "#)?;
+        fmt_opt_str(f, self.driver())?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Creates an empty `sysfs` and device node fixture tree.
+    pub(crate) fn fixture_tree(name: &str) -> Sysfs {
+        let base = std::env::temp_dir().join(format!("usbfs-sysfs-{}-{name}", std::process::id()));
+        fs::remove_dir_all(&base).ok();
+
+        let sysfs = Sysfs::create(base.join("sys"), base.join("dev"));
+        fs::create_dir_all(sysfs.devices_path()).unwrap();
+        fs::create_dir_all(sysfs.dev_root()).unwrap();
+
+        sysfs
+    }
+
+    /// Adds a device to a fixture tree, with the provided `sysfs` attributes and device node
+    /// contents.
+    pub(crate) fn add_device(
+        sysfs: &Sysfs,
+        port_path: &str,
+        bus_num: u8,
+        dev_num: u8,
+        attrs: &[(&str, &str)],
+        devnode: &[u8],
+    ) {
+        let dir = sysfs.devices_path().join(port_path);
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("busnum"), format!("{bus_num}\n")).unwrap();
+        fs::write(dir.join("devnum"), format!("{dev_num}\n")).unwrap();
+        for (name, val) in attrs {
+            let attr = dir.join(name);
+            fs::create_dir_all(attr.parent().unwrap()).unwrap();
+            fs::write(attr, format!("{val}\n")).unwrap();
+        }
+
+        let node = sysfs
+            .dev_root()
+            .join(format!("{bus_num:03}"))
+            .join(format!("{dev_num:03}"));
+        fs::create_dir_all(node.parent().unwrap()).unwrap();
+        fs::write(node, devnode).unwrap();
+    }
+
+    /// Removes a device from a fixture tree.
+    pub(crate) fn remove_device(sysfs: &Sysfs, port_path: &str, bus_num: u8, dev_num: u8) {
+        fs::remove_dir_all(sysfs.devices_path().join(port_path)).ok();
+        fs::remove_file(
+            sysfs
+                .dev_root()
+                .join(format!("{bus_num:03}"))
+                .join(format!("{dev_num:03}")),
+        )
+        .ok();
+    }
+
+    #[test]
+    fn test_sysfs_devices() -> Result<()> {
+        let sysfs = fixture_tree("devices");
+
+        add_device(&sysfs, "usb1", 1, 1, &[("idVendor", "1d6b")], &[]);
+        add_device(
+            &sysfs,
+            "1-1.2",
+            1,
+            5,
+            &[
+                ("idVendor", "0483"),
+                ("idProduct", "5740"),
+                ("bDeviceClass", "ef"),
+                ("bConfigurationValue", "1"),
+                ("speed", "480"),
+                ("product", "Virtual COM Port"),
+                ("serial", "0123456789"),
+                ("1-1.2:1.1/bInterfaceNumber", "01"),
+                ("1-1.2:1.1/bInterfaceClass", "0a"),
+                ("1-1.2:1.0/bInterfaceNumber", "00"),
+                ("1-1.2:1.0/bInterfaceClass", "02"),
+                ("1-1.2:1.0/bAlternateSetting", " 0"),
+            ],
+            &[],
+        );
+        // interface directories at the top level are not devices
+        fs::create_dir_all(sysfs.devices_path().join("1-1.2:1.0"))?;
+
+        let devices = sysfs.devices()?;
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].port_path(), "usb1");
+        assert!(devices[0].port_numbers().is_empty());
+
+        let dev = &devices[1];
+
+        assert_eq!(dev.port_path(), "1-1.2");
+        assert_eq!(dev.port_numbers(), [1, 2]);
+        assert_eq!((dev.bus_num(), dev.dev_num()), (1, 5));
+        assert_eq!((dev.vendor_id(), dev.product_id()), (0x0483, 0x5740));
+        assert_eq!(dev.class(), 0xef);
+        assert_eq!(dev.configuration_value(), Some(1));
+        assert_eq!(dev.speed(), UsbfsSpeed::High);
+        assert_eq!(dev.product(), Some("Virtual COM Port"));
+        assert_eq!(dev.manufacturer(), None);
+        assert_eq!(dev.serial(), Some("0123456789"));
+
+        assert_eq!(dev.interfaces().len(), 2);
+        assert_eq!(dev.interfaces()[0].number(), 0);
+        assert_eq!(dev.interfaces()[0].class(), 0x02);
+        assert_eq!(dev.interfaces()[1].class(), 0x0a);
+        assert_eq!(dev.interfaces()[1].driver(), None);
+
+        assert_eq!(sysfs.device("1-1.2")?.as_ref(), Some(dev));
+        assert_eq!(sysfs.device("1-1.3")?, None);
+        assert_eq!(sysfs.device("../1-1.2")?, None);
+        assert_eq!(sysfs.device_by_bus_dev(1, 5)?.as_ref(), Some(dev));
+        assert_eq!(sysfs.device_by_bus_dev(2, 5)?, None);
+        assert_eq!(sysfs.devnode(dev), sysfs.dev_root().join("001").join("005"));
+
+        Ok(())
+    }
+}
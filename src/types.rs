@@ -1,8 +1,10 @@
+pub mod bulk_transfer;
 pub mod cap;
 pub mod connect_info;
 pub mod ctrl_transfer;
 pub mod disconnect_claim;
 pub mod driver;
+pub mod endpoint;
 pub mod interface;
 pub mod ioctl;
 pub mod iso_packet_desc;
@@ -10,14 +12,10 @@ pub mod speed;
 pub mod streams;
 pub mod urb;
 
-pub use cap::*;
-pub use connect_info::*;
+pub use bulk_transfer::*;
 pub use ctrl_transfer::*;
-pub use disconnect_claim::*;
 pub use driver::*;
-pub use interface::*;
 pub use ioctl::*;
 pub use iso_packet_desc::*;
-pub use speed::*;
 pub use streams::*;
 pub use urb::*;
@@ -0,0 +1,204 @@
+use std::{ffi::c_void, fmt};
+
+use crate::MAX_BULK_BUFFER_LENGTH;
+
+/// Represents a USBFS synchronous Bulk (or Interrupt) transfer.
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbfsBulkTransfer {
+    ep: u32,
+    timeout: u32,
+    data: Vec<u8>,
+}
+
+impl UsbfsBulkTransfer {
+    /// Creates a new [UsbfsBulkTransfer].
+    pub const fn new() -> Self {
+        Self {
+            ep: 0,
+            timeout: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Creates a new [UsbfsBulkTransfer] from the provided parameters.
+    pub fn create<D: IntoIterator<Item = u8>>(ep: u32, timeout: u32, data: D) -> Self {
+        Self::new()
+            .with_ep(ep)
+            .with_timeout(timeout)
+            .with_data(data)
+    }
+
+    /// Gets the endpoint address.
+    pub const fn ep(&self) -> u32 {
+        self.ep
+    }
+
+    /// Sets the endpoint address.
+    pub fn set_ep(&mut self, ep: u32) {
+        self.ep = ep;
+    }
+
+    /// Builder function that sets the endpoint address.
+    pub fn with_ep(mut self, ep: u32) -> Self {
+        self.set_ep(ep);
+        self
+    }
+
+    /// Gets the length.
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    /// Gets the timeout (in milliseconds).
+    pub const fn timeout(&self) -> u32 {
+        self.timeout
+    }
+
+    /// Sets the timeout (in milliseconds).
+    pub fn set_timeout(&mut self, timeout: u32) {
+        self.timeout = timeout;
+    }
+
+    /// Builder function that sets the timeout (in milliseconds).
+    pub fn with_timeout(mut self, timeout: u32) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Gets a reference to the data buffer.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Gets a mutable reference to the data buffer.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.data.as_mut()
+    }
+
+    /// Sets the data buffer.
+    ///
+    /// For `IN` transfers, the buffer length is the maximum number of bytes to read.
+    ///
+    /// **NOTE** Sets at most [`MAX_BULK_BUFFER_LENGTH`] bytes.
+    pub fn set_data<D: IntoIterator<Item = u8>>(&mut self, data: D) {
+        self.data = data.into_iter().take(MAX_BULK_BUFFER_LENGTH).collect();
+    }
+
+    /// Builder function that sets the data buffer.
+    ///
+    /// **NOTE** Sets at most [`MAX_BULK_BUFFER_LENGTH`] bytes.
+    pub fn with_data<D: IntoIterator<Item = u8>>(mut self, data: D) -> Self {
+        self.set_data(data);
+        self
+    }
+
+    /// Converts the [UsbfsBulkTransfer] into its data buffer.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl fmt::Display for UsbfsBulkTransfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""ep": {}, "#, self.ep)?;
+        write!(f, r#""len": {}, "#, self.data.len())?;
+        write!(f, r#""timeout": {}"#, self.timeout)?;
+        write!(f, "}}")
+    }
+}
+
+/// Represents a USBFS Bulk transfer passed to `ioctl` FFI.
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub struct UsbfsBulkTransferFfi {
+    ep: u32,
+    len: u32,
+    timeout: u32,
+    data: *mut c_void,
+}
+
+crate::abi::assert_layout!(UsbfsBulkTransferFfi, size: (24, 16), align: (8, 4), {
+    ep: (0, 0),
+    len: (4, 4),
+    timeout: (8, 8),
+    data: (16, 12),
+});
+
+impl UsbfsBulkTransferFfi {
+    /// Creates a new [UsbfsBulkTransferFfi].
+    pub const fn new() -> Self {
+        Self {
+            ep: 0,
+            len: 0,
+            timeout: 0,
+            data: std::ptr::null_mut(),
+        }
+    }
+}
+
+impl Default for UsbfsBulkTransferFfi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&mut UsbfsBulkTransfer> for UsbfsBulkTransferFfi {
+    fn from(val: &mut UsbfsBulkTransfer) -> Self {
+        Self {
+            ep: val.ep,
+            len: val.data.len() as u32,
+            timeout: val.timeout,
+            data: if val.data.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                val.data.as_mut_ptr() as *mut _
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usbfs_bulk_transfer() {
+        let exp_ep = 0x81;
+        let exp_timeout = 1000;
+        let exp_data = [1u8, 2, 3];
+
+        let mut exp_xfer = UsbfsBulkTransfer::create(exp_ep, exp_timeout, exp_data);
+        let mut null_xfer = UsbfsBulkTransfer::new();
+
+        assert_eq!(exp_xfer.ep(), exp_ep);
+        assert_eq!(exp_xfer.timeout(), exp_timeout);
+        assert_eq!(exp_xfer.data(), exp_data.as_ref());
+        assert_eq!(exp_xfer.length(), exp_data.len() as u32);
+
+        assert_eq!(null_xfer.ep(), 0);
+        assert_eq!(null_xfer.timeout(), 0);
+        assert_eq!(null_xfer.data(), &[]);
+
+        null_xfer.set_ep(exp_ep);
+        null_xfer.set_timeout(exp_timeout);
+        null_xfer.set_data(exp_data);
+
+        assert_eq!(null_xfer, exp_xfer);
+
+        let ffi = UsbfsBulkTransferFfi::from(&mut exp_xfer);
+
+        assert_eq!(ffi.ep, exp_ep);
+        assert_eq!(ffi.len, exp_data.len() as u32);
+        assert_eq!(ffi.data as usize, exp_xfer.data().as_ptr() as usize);
+
+        assert_eq!(
+            UsbfsBulkTransfer::new()
+                .with_data([0u8; MAX_BULK_BUFFER_LENGTH + 1])
+                .length(),
+            MAX_BULK_BUFFER_LENGTH as u32
+        );
+    }
+}
@@ -9,6 +9,7 @@ pub const CAP_REAP_AFTER_DISCONNECT: u32 = 0x10;
 /// Represents USBFS capabilities.
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UsbfsCap {
     None = 0x00,
     #[default]
@@ -37,6 +38,20 @@ impl UsbfsCap {
         }
     }
 
+    /// Gets the list of [UsbfsCap]s set in a capabilities bitmask.
+    pub fn from_mask(mask: u32) -> Vec<Self> {
+        [
+            Self::ZeroPacket,
+            Self::BulkContinuation,
+            Self::NoPacketSizeLim,
+            Self::BulkScatterGather,
+            Self::ReapAfterDisconnect,
+        ]
+        .into_iter()
+        .filter(|c| mask & c.inner() != 0)
+        .collect()
+    }
+
     /// Gets the inner representation of the [UsbfsCap].
     pub const fn inner(&self) -> u32 {
         *self as u32
@@ -125,5 +140,11 @@ mod tests {
             UsbfsCap::ReapAfterDisconnect
         );
         assert_eq!(UsbfsCap::from(0), UsbfsCap::None);
+
+        assert_eq!(
+            UsbfsCap::from_mask(CAP_ZERO_PACKET | CAP_REAP_AFTER_DISCONNECT),
+            [UsbfsCap::ZeroPacket, UsbfsCap::ReapAfterDisconnect]
+        );
+        assert!(UsbfsCap::from_mask(0).is_empty());
     }
 }
@@ -3,11 +3,17 @@ use std::fmt;
 /// Represents USBFS connection information.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsConnectInfo {
     devnum: u32,
     slow: u8,
 }
 
+crate::abi::assert_layout!(UsbfsConnectInfo, size: (8, 8), align: (4, 4), {
+    devnum: (0, 0),
+    slow: (4, 4),
+});
+
 impl UsbfsConnectInfo {
     /// Creates a new [UsbfsConnectInfo].
     pub const fn new() -> Self {
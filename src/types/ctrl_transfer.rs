@@ -6,6 +6,7 @@ pub const MAX_CTRL_DATA: usize = u16::MAX as usize;
 /// Represents a USBFS Control transfer
 #[repr(C)]
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsCtrlTransfer {
     bm_request_type: u8,
     b_request: u8,
@@ -180,6 +181,16 @@ pub struct UsbfsCtrlTransferFfi {
     data: *mut c_void,
 }
 
+crate::abi::assert_layout!(UsbfsCtrlTransferFfi, size: (24, 16), align: (8, 4), {
+    bm_request_type: (0, 0),
+    b_request: (1, 1),
+    w_value: (2, 2),
+    w_index: (4, 4),
+    w_length: (6, 6),
+    timeout: (8, 8),
+    data: (16, 12),
+});
+
 impl UsbfsCtrlTransferFfi {
     /// Creates a new [UsbfsCtrlTransferFfi].
     pub const fn new() -> Self {
@@ -9,6 +9,7 @@ pub const CLAIM_FLAG_EXCEPT_DRIVER: u8 = 0x02;
 /// Represents USBFS disconnect claim flags.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UsbfsDisconnectClaimFlag {
     None = CLAIM_FLAG_NONE,
     #[default]
@@ -77,20 +78,30 @@ impl fmt::Display for UsbfsDisconnectClaimFlag {
 }
 
 /// Represents USBFS disconnect claim.
+///
+/// **NOTE** the kernel `flags` field is 32 bits wide, so the [UsbfsDisconnectClaimFlag] is
+/// stored in its `u32` representation.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsDisconnectClaim {
     interface: u32,
-    flags: UsbfsDisconnectClaimFlag,
+    flags: u32,
     driver: DriverName,
 }
 
+crate::abi::assert_layout!(UsbfsDisconnectClaim, size: (264, 264), align: (4, 4), {
+    interface: (0, 0),
+    flags: (4, 4),
+    driver: (8, 8),
+});
+
 impl UsbfsDisconnectClaim {
     /// Creates a new [UsbfsDisconnectClaim].
     pub const fn new() -> Self {
         Self {
             interface: 0,
-            flags: UsbfsDisconnectClaimFlag::new(),
+            flags: UsbfsDisconnectClaimFlag::new().inner() as u32,
             driver: DriverName::new(),
         }
     }
@@ -99,7 +110,7 @@ impl UsbfsDisconnectClaim {
     pub fn create(interface: u32, flags: UsbfsDisconnectClaimFlag, driver: &str) -> Self {
         Self {
             interface,
-            flags,
+            flags: flags.inner() as u32,
             driver: driver.into(),
         }
     }
@@ -122,12 +133,12 @@ impl UsbfsDisconnectClaim {
 
     /// Gets the [UsbfsDisconnectClaimFlag].
     pub const fn flags(&self) -> UsbfsDisconnectClaimFlag {
-        self.flags
+        UsbfsDisconnectClaimFlag::create(self.flags as u8)
     }
 
     /// Sets the [UsbfsDisconnectClaimFlags].
     pub fn set_flags(&mut self, flags: UsbfsDisconnectClaimFlag) {
-        self.flags = flags;
+        self.flags = flags.inner() as u32;
     }
 
     /// Builder function that sets the [UsbfsDisconnectClaimFlags].
@@ -163,7 +174,7 @@ impl fmt::Display for UsbfsDisconnectClaim {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{{")?;
         write!(f, r#""interface": {}, "#, self.interface)?;
-        write!(f, r#""flags": {}, "#, self.flags)?;
+        write!(f, r#""flags": {}, "#, self.flags())?;
         write!(f, r#""driver": "{}""#, self.driver())?;
         write!(f, "}}")
     }
@@ -58,14 +58,35 @@ impl fmt::Display for DriverName {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for DriverName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DriverName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let driver = String::deserialize(deserializer)?;
+        Ok(driver.as_str().into())
+    }
+}
+
 /// Represents an argument to get a USBFS driver from an `ioctl`.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsGetDriver {
     interface: u32,
     driver: DriverName,
 }
 
+crate::abi::assert_layout!(UsbfsGetDriver, size: (260, 260), align: (4, 4), {
+    interface: (0, 0),
+    driver: (4, 4),
+});
+
 impl UsbfsGetDriver {
     /// Creates a new [UbsfGetDriver].
     pub const fn new() -> Self {
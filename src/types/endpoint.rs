@@ -0,0 +1,186 @@
+use std::fmt;
+
+pub const ENDPOINT_OUT: u8 = 0x00;
+pub const ENDPOINT_IN: u8 = 0x80;
+pub const ENDPOINT_DIR_MASK: u8 = 0x80;
+pub const ENDPOINT_NUM_MASK: u8 = 0x0f;
+
+/// Represents the direction of a USB endpoint.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// Host-to-device.
+    #[default]
+    Out = ENDPOINT_OUT,
+    /// Device-to-host.
+    In = ENDPOINT_IN,
+}
+
+impl Direction {
+    /// Creates a new [Direction].
+    pub const fn new() -> Self {
+        Self::Out
+    }
+
+    /// Creates a new [Direction] from the provided endpoint address.
+    pub const fn create(val: u8) -> Self {
+        match val & ENDPOINT_DIR_MASK {
+            ENDPOINT_IN => Self::In,
+            _ => Self::Out,
+        }
+    }
+
+    /// Gets the inner representation of the [Direction].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Converts into the inner representation of the [Direction].
+    pub fn into_inner(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<&Direction> for &'static str {
+    fn from(val: &Direction) -> Self {
+        match val {
+            Direction::Out => "out",
+            Direction::In => "in",
+        }
+    }
+}
+
+impl From<Direction> for &'static str {
+    fn from(val: Direction) -> Self {
+        (&val).into()
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents a USB endpoint address.
+///
+/// The address combines the endpoint number (bits 0..3), and the [Direction] (bit 7).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Endpoint(u8);
+
+impl Endpoint {
+    /// Creates a new [Endpoint].
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [Endpoint] from the provided number and [Direction].
+    ///
+    /// **NOTE** the number is masked to its lower four bits.
+    pub const fn create(number: u8, direction: Direction) -> Self {
+        Self((number & ENDPOINT_NUM_MASK) | direction.inner())
+    }
+
+    /// Creates a new `IN` [Endpoint] from the provided number.
+    pub const fn input(number: u8) -> Self {
+        Self::create(number, Direction::In)
+    }
+
+    /// Creates a new `OUT` [Endpoint] from the provided number.
+    pub const fn output(number: u8) -> Self {
+        Self::create(number, Direction::Out)
+    }
+
+    /// Creates a new [Endpoint] from the provided address.
+    ///
+    /// **NOTE** reserved bits (4..6) are cleared.
+    pub const fn from_address(address: u8) -> Self {
+        Self(address & (ENDPOINT_NUM_MASK | ENDPOINT_DIR_MASK))
+    }
+
+    /// Gets the endpoint address.
+    pub const fn address(&self) -> u8 {
+        self.0
+    }
+
+    /// Gets the endpoint number.
+    pub const fn number(&self) -> u8 {
+        self.0 & ENDPOINT_NUM_MASK
+    }
+
+    /// Gets the endpoint [Direction].
+    pub const fn direction(&self) -> Direction {
+        Direction::create(self.0)
+    }
+
+    /// Gets whether the endpoint direction is `IN` (device-to-host).
+    pub const fn is_in(&self) -> bool {
+        self.0 & ENDPOINT_DIR_MASK == ENDPOINT_IN
+    }
+
+    /// Gets whether the endpoint direction is `OUT` (host-to-device).
+    pub const fn is_out(&self) -> bool {
+        !self.is_in()
+    }
+}
+
+impl From<u8> for Endpoint {
+    fn from(val: u8) -> Self {
+        Self::from_address(val)
+    }
+}
+
+impl From<&Endpoint> for u8 {
+    fn from(val: &Endpoint) -> Self {
+        val.address()
+    }
+}
+
+impl From<Endpoint> for u8 {
+    fn from(val: Endpoint) -> Self {
+        val.address()
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""number": {}, "#, self.number())?;
+        write!(f, r#""direction": {}"#, self.direction())?;
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        let ep_in = Endpoint::input(1);
+        let ep_out = Endpoint::output(2);
+
+        assert_eq!(ep_in.address(), 0x81);
+        assert_eq!(ep_in.number(), 1);
+        assert_eq!(ep_in.direction(), Direction::In);
+        assert!(ep_in.is_in());
+        assert!(!ep_in.is_out());
+
+        assert_eq!(ep_out.address(), 0x02);
+        assert_eq!(ep_out.number(), 2);
+        assert_eq!(ep_out.direction(), Direction::Out);
+        assert!(ep_out.is_out());
+
+        assert_eq!(Endpoint::from(0x81), ep_in);
+        assert_eq!(u8::from(ep_out), 0x02);
+        assert_eq!(Endpoint::create(0x1f, Direction::In).address(), 0x8f);
+        assert_eq!(Endpoint::from_address(0xf3).address(), 0x83);
+        assert_eq!(Endpoint::new(), Endpoint::output(0));
+
+        assert_eq!(Direction::create(0x81), Direction::In);
+        assert_eq!(Direction::create(0x01), Direction::Out);
+    }
+}
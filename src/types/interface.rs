@@ -3,11 +3,17 @@ use std::fmt;
 /// Represents a USBFS interface setting.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsSetInterface {
     interface: u32,
     altsetting: u32,
 }
 
+crate::abi::assert_layout!(UsbfsSetInterface, size: (8, 8), align: (4, 4), {
+    interface: (0, 0),
+    altsetting: (4, 4),
+});
+
 impl UsbfsSetInterface {
     /// Creates a new [UsbfsSetInterface].
     pub const fn new() -> Self {
@@ -51,9 +51,11 @@ impl fmt::Display for dyn UsbfsIoctlData {
 /// Represents USBFS `ioctl` information.
 #[repr(C)]
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsIoctl<'a> {
     ifno: i32,
     ioctl_code: i32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     data: Option<&'a mut dyn UsbfsIoctlData>,
 }
 
@@ -170,6 +172,12 @@ pub struct UsbfsIoctlFfi {
     data: *mut c_void,
 }
 
+crate::abi::assert_layout!(UsbfsIoctlFfi, size: (16, 12), align: (8, 4), {
+    ifno: (0, 0),
+    ioctl_code: (4, 4),
+    data: (8, 8),
+});
+
 impl UsbfsIoctlFfi {
     pub const fn new() -> Self {
         Self {
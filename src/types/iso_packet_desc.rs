@@ -3,12 +3,19 @@ use std::fmt;
 /// Represents a USBFS Isochronous packet description.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsIsoPacketDesc {
     length: u32,
     actual_length: u32,
     status: u32,
 }
 
+crate::abi::assert_layout!(UsbfsIsoPacketDesc, size: (12, 12), align: (4, 4), {
+    length: (0, 0),
+    actual_length: (4, 4),
+    status: (8, 8),
+});
+
 impl UsbfsIsoPacketDesc {
     /// Creates a new [UsbfsIsoPacketDesc].
     pub const fn new() -> Self {
@@ -11,6 +11,7 @@ pub const SPEED_SUPER_PLUS: u32 = 6;
 /// Represents USBFS transfer speeds.
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UsbfsSpeed {
     #[default]
     Unknown = SPEED_UNKNOWN,
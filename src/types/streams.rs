@@ -1,8 +1,9 @@
-use std::fmt;
+use std::{fmt, mem};
 
 /// Represents USBFS stream information.
 #[repr(C)]
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UsbfsStreams {
     num_streams: u32,
     eps: Vec<u8>,
@@ -50,26 +51,24 @@ impl UsbfsStreams {
     }
 }
 
-impl From<&UsbfsStreamsFfi> for UsbfsStreams {
-    fn from(val: &UsbfsStreamsFfi) -> Self {
+impl From<&UsbfsStreamsBuffer> for UsbfsStreams {
+    fn from(val: &UsbfsStreamsBuffer) -> Self {
+        let header = val.header();
+        // SAFETY: the endpoint addresses were written directly after the record on creation
+        let eps = unsafe {
+            std::slice::from_raw_parts(
+                (val.0.as_ptr() as *const u8).add(mem::size_of::<UsbfsStreamsFfi>()),
+                header.num_eps as usize,
+            )
+        };
+
         Self {
-            num_streams: val.num_streams,
-            eps: if val.eps.is_null() {
-                Vec::new()
-            } else {
-                // SAFETY: memory returned by the kernel should be valid, if non-null.
-                unsafe { std::slice::from_raw_parts(val.eps, val.num_eps as usize) }.into()
-            },
+            num_streams: header.num_streams,
+            eps: eps.into(),
         }
     }
 }
 
-impl From<UsbfsStreamsFfi> for UsbfsStreams {
-    fn from(val: UsbfsStreamsFfi) -> Self {
-        (&val).into()
-    }
-}
-
 impl fmt::Display for UsbfsStreams {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{{")?;
@@ -86,42 +85,74 @@ impl fmt::Display for UsbfsStreams {
 }
 
 /// Represents USBFS stream information for `ioctl` FFI.
+///
+/// Mirrors `struct usbdevfs_streams`, the endpoint addresses directly follow the record in
+/// memory. See [UsbfsStreamsBuffer].
 #[repr(C)]
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct UsbfsStreamsFfi {
     num_streams: u32,
     num_eps: u32,
-    eps: *mut u8,
 }
 
+crate::abi::assert_layout!(UsbfsStreamsFfi, size: (8, 8), align: (4, 4), {
+    num_streams: (0, 0),
+    num_eps: (4, 4),
+});
+
 impl UsbfsStreamsFfi {
     /// Creates a new [UsbfsStreamsFfi].
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             num_streams: 0,
             num_eps: 0,
-            eps: std::ptr::null_mut(),
         }
     }
 }
 
-impl From<&mut UsbfsStreams> for UsbfsStreamsFfi {
-    fn from(val: &mut UsbfsStreams) -> Self {
+impl From<&UsbfsStreams> for UsbfsStreamsFfi {
+    fn from(val: &UsbfsStreams) -> Self {
         Self {
             num_streams: val.num_streams,
             num_eps: val.eps.len() as u32,
-            eps: if val.eps.is_empty() {
-                std::ptr::null_mut()
-            } else {
-                val.eps.as_mut_ptr()
-            },
         }
     }
 }
 
-impl Default for UsbfsStreamsFfi {
-    fn default() -> Self {
-        Self::new()
+/// Storage for a [UsbfsStreamsFfi] record, followed by its endpoint addresses.
+#[derive(Debug)]
+pub struct UsbfsStreamsBuffer(Vec<u32>);
+
+impl UsbfsStreamsBuffer {
+    fn header(&self) -> UsbfsStreamsFfi {
+        // SAFETY: the storage always starts with an initialized record
+        unsafe { *(self.0.as_ptr() as *const UsbfsStreamsFfi) }
+    }
+
+    /// Gets a pointer to the record, for `ioctl` FFI.
+    pub fn as_mut_ptr(&mut self) -> *mut UsbfsStreamsFfi {
+        self.0.as_mut_ptr() as *mut UsbfsStreamsFfi
+    }
+}
+
+impl From<&UsbfsStreams> for UsbfsStreamsBuffer {
+    fn from(val: &UsbfsStreams) -> Self {
+        let header = mem::size_of::<UsbfsStreamsFfi>();
+        let len = header + val.eps.len();
+        let mut buf = vec![0u32; len.div_ceil(mem::size_of::<u32>())];
+
+        // SAFETY: the storage is large enough, and aligned for the record
+        unsafe {
+            let ptr = buf.as_mut_ptr() as *mut UsbfsStreamsFfi;
+            ptr.write(UsbfsStreamsFfi::from(val));
+            std::ptr::copy_nonoverlapping(
+                val.eps.as_ptr(),
+                (ptr as *mut u8).add(header),
+                val.eps.len(),
+            );
+        }
+
+        Self(buf)
     }
 }
 
@@ -152,5 +183,10 @@ mod tests {
         assert_eq!(null_streams.eps(), exp_eps.as_ref());
 
         assert_eq!(null_streams, exp_streams);
+
+        let buf = UsbfsStreamsBuffer::from(&exp_streams);
+
+        assert_eq!(buf.header(), UsbfsStreamsFfi::from(&exp_streams));
+        assert_eq!(UsbfsStreams::from(&buf), exp_streams);
     }
 }
@@ -2,6 +2,17 @@ use std::{cmp, ffi::c_void, fmt};
 
 use super::UsbfsIsoPacketDesc;
 
+pub const URB_TYPE_ISO: u8 = 0;
+pub const URB_TYPE_INTERRUPT: u8 = 1;
+pub const URB_TYPE_CONTROL: u8 = 2;
+pub const URB_TYPE_BULK: u8 = 3;
+
+pub const URB_SHORT_NOT_OK: u32 = 0x01;
+pub const URB_ISO_ASAP: u32 = 0x02;
+pub const URB_BULK_CONTINUATION: u32 = 0x04;
+pub const URB_ZERO_PACKET: u32 = 0x40;
+pub const URB_NO_INTERRUPT: u32 = 0x80;
+
 /// Convenience alias for types used as a `usercontext` argument in [`Urb`].
 ///
 /// The `usercontext` is an argument to USB callback completion functions.
@@ -41,6 +52,7 @@ impl fmt::Debug for dyn UrbUserContext {
 /// Represents USBFS transfer information.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransferInfo(u32);
 
 impl TransferInfo {
@@ -78,6 +90,7 @@ impl fmt::Display for TransferInfo {
 
 /// Represents a URB record on Linux.
 #[repr(C)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Urb<'a> {
     urb_type: u8,
     endpoint: u8,
@@ -89,6 +102,7 @@ pub struct Urb<'a> {
     info: TransferInfo,
     error_count: i32,
     signr: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     usercontext: Option<&'a mut dyn UrbUserContext>,
     iso_frame_desc: Vec<UsbfsIsoPacketDesc>,
 }
@@ -193,6 +207,11 @@ impl<'a> Urb<'a> {
         self
     }
 
+    /// Converts the [Urb] into its buffer.
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+
     /// Gets the URB buffer length.
     pub fn buffer_length(&self) -> usize {
         self.buffer.len()
@@ -337,6 +356,26 @@ impl<'a> Urb<'a> {
         self.set_iso_frame_desc(iso_frame_desc);
         self
     }
+
+    /// Converts into an [Urb] not borrowing a user context.
+    ///
+    /// **NOTE** the [UrbUserContext] is dropped.
+    pub fn into_owned(self) -> Urb<'static> {
+        Urb {
+            urb_type: self.urb_type,
+            endpoint: self.endpoint,
+            status: self.status,
+            flags: self.flags,
+            buffer: self.buffer,
+            actual_length: self.actual_length,
+            start_frame: self.start_frame,
+            info: self.info,
+            error_count: self.error_count,
+            signr: self.signr,
+            usercontext: None,
+            iso_frame_desc: self.iso_frame_desc,
+        }
+    }
 }
 
 impl<'a> PartialEq for Urb<'a> {
@@ -363,7 +402,7 @@ impl<'a> fmt::Display for Urb<'a> {
         write!(f, r#""status": {}, "#, self.status)?;
         write!(f, r#""flags": {}, "#, self.flags)?;
 
-        write!(f, r#""buffer: ["#)?;
+        write!(f, r#""buffer": ["#)?;
         for (i, b) in self.buffer.iter().enumerate() {
             if i != 0 {
                 write!(f, ", ")?;
@@ -478,6 +517,9 @@ impl PartialEq for TransferInfoFfi {
 }
 
 /// Represents a URB record on Linux passed to an `ioctl` FFI.
+///
+/// For Isochronous transfers, the kernel expects the [UsbfsIsoPacketDesc] list to directly
+/// follow the record in memory.
 #[repr(C)]
 #[derive(PartialEq)]
 pub struct UrbFfi {
@@ -493,9 +535,23 @@ pub struct UrbFfi {
     error_count: i32,
     signr: u32,
     usercontext: *mut c_void,
-    iso_frame_desc: *mut UsbfsIsoPacketDesc,
 }
 
+crate::abi::assert_layout!(UrbFfi, size: (56, 44), align: (8, 4), {
+    urb_type: (0, 0),
+    endpoint: (1, 1),
+    status: (4, 4),
+    flags: (8, 8),
+    buffer: (16, 12),
+    buffer_length: (24, 16),
+    actual_length: (28, 20),
+    start_frame: (32, 24),
+    info: (36, 28),
+    error_count: (40, 32),
+    signr: (44, 36),
+    usercontext: (48, 40),
+});
+
 impl UrbFfi {
     /// Creates a new [UrbFfi].
     pub const fn new() -> Self {
@@ -512,9 +568,68 @@ impl UrbFfi {
             error_count: 0,
             signr: 0,
             usercontext: std::ptr::null_mut(),
-            iso_frame_desc: std::ptr::null_mut(),
         }
     }
+
+    /// Gets the URB type.
+    pub const fn urb_type(&self) -> u8 {
+        self.urb_type
+    }
+
+    /// Gets the URB endpoint.
+    pub const fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    /// Gets the URB status.
+    pub const fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Gets the URB flags.
+    pub const fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Gets the URB buffer pointer.
+    pub const fn buffer(&self) -> *mut c_void {
+        self.buffer
+    }
+
+    /// Gets the URB buffer length.
+    pub const fn buffer_length(&self) -> i32 {
+        self.buffer_length
+    }
+
+    /// Gets the URB actual length.
+    pub const fn actual_length(&self) -> i32 {
+        self.actual_length
+    }
+
+    /// Gets the URB start frame.
+    pub const fn start_frame(&self) -> i32 {
+        self.start_frame
+    }
+
+    /// Gets the number of packets of an Isochronous transfer.
+    pub const fn number_of_packets(&self) -> i32 {
+        unsafe { self.info.number_of_packets }
+    }
+
+    /// Gets the URB error count.
+    pub const fn error_count(&self) -> i32 {
+        self.error_count
+    }
+
+    /// Gets the URB signr.
+    pub const fn signr(&self) -> u32 {
+        self.signr
+    }
+
+    /// Gets the URB usercontext pointer.
+    pub const fn usercontext(&self) -> *mut c_void {
+        self.usercontext
+    }
 }
 
 impl<'a> From<&mut Urb<'a>> for UrbFfi {
@@ -548,11 +663,6 @@ impl<'a> From<&mut Urb<'a>> for UrbFfi {
             } else {
                 std::ptr::null_mut()
             },
-            iso_frame_desc: if val.iso_frame_desc.is_empty() {
-                std::ptr::null_mut()
-            } else {
-                val.iso_frame_desc.as_mut_ptr() as *mut _
-            },
         }
     }
 }
@@ -0,0 +1,970 @@
+//! Reader for the binary `usbmon` interface, and capture file export.
+//!
+//! Each `/dev/usbmonN` node reports the URBs of bus `N` (or of every bus for `N = 0`), as the
+//! kernel sees them. Events are read one at a time with `MON_IOCX_GETX`, or in batches from the
+//! memory-mapped ring with `MON_IOCX_MFETCH`.
+//!
+//! Events use the `LINKTYPE_USB_LINUX_MMAPPED` layout: a 64-byte header, followed by the
+//! Isochronous descriptors and the captured data, in host byte order. The same layout is used in
+//! [pcap and pcapng](PcapngWriter) capture files, so Wireshark can open them.
+
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::num::NonZeroUsize;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::Duration;
+use std::{fmt, ptr, slice};
+
+use nix::errno::Errno;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+
+use crate::backend::{unzigzag, Session, SessionOp};
+use crate::descriptor::ENDPOINT_DIR_IN;
+use crate::{Error, Result};
+
+mod pcap;
+
+pub use pcap::{PcapWriter, PcapngWriter, LINKTYPE_USB_LINUX_MMAPPED};
+
+/// Path prefix of the binary `usbmon` device nodes.
+pub const USBMON_DEVICE_PATH: &str = "/dev/usbmon";
+/// Length of the binary `usbmon` event header.
+pub const USBMON_HEADER_LEN: usize = 64;
+/// Length of a binary `usbmon` Isochronous descriptor.
+pub const USBMON_ISO_DESC_LEN: usize = 16;
+
+// flag values of the setup and data fields, when they were captured
+const FLAG_PRESENT: u8 = 0;
+const FLAG_SETUP_ABSENT: u8 = b'-';
+const FLAG_DATA_ABSENT: u8 = b'=';
+// status of submitted URBs that did not complete yet
+const STATUS_IN_PROGRESS: i32 = -(Errno::EINPROGRESS as i32);
+// type of the filler events padding the end of the mapped ring
+const EVENT_FILLER: u8 = b'@';
+
+const MON_IOC_MAGIC: u8 = 0x92;
+
+/// Represents `struct mon_bin_stats`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbmonStats {
+    queued: u32,
+    dropped: u32,
+}
+
+impl UsbmonStats {
+    /// Creates a new [UsbmonStats].
+    pub const fn new() -> Self {
+        Self {
+            queued: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Gets the number of events waiting to be read.
+    pub const fn queued(&self) -> u32 {
+        self.queued
+    }
+
+    /// Gets the number of events dropped since the last call.
+    pub const fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
+
+impl fmt::Display for UsbmonStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"queued": {}, "dropped": {}}}"#,
+            self.queued, self.dropped
+        )
+    }
+}
+
+/// Represents `struct mon_bin_get`.
+#[repr(C)]
+struct MonBinGet {
+    hdr: *mut c_void,
+    data: *mut c_void,
+    alloc: usize,
+}
+
+/// Represents `struct mon_bin_mfetch`.
+#[repr(C)]
+struct MonBinMfetch {
+    offvec: *mut u32,
+    nfetch: u32,
+    nflush: u32,
+}
+
+ioctl_none!(mon_iocq_ring_size, MON_IOC_MAGIC, 5);
+ioctl_write_int_bad!(mon_ioct_ring_size, request_code_none!(MON_IOC_MAGIC, 4));
+ioctl_read!(mon_iocg_stats, MON_IOC_MAGIC, 3, UsbmonStats);
+// `_IOW`, but the kernel writes the event through the pointers of the record
+ioctl_write_ptr!(mon_iocx_getx, MON_IOC_MAGIC, 10, MonBinGet);
+ioctl_readwrite!(mon_iocx_mfetch, MON_IOC_MAGIC, 7, MonBinMfetch);
+ioctl_write_int_bad!(mon_ioch_mflush, request_code_none!(MON_IOC_MAGIC, 8));
+
+/// Represents the kind of a `usbmon` event.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbmonEventType {
+    /// The URB was submitted.
+    #[default]
+    Submit = b'S',
+    /// The URB completed.
+    Complete = b'C',
+    /// The URB submission failed.
+    Error = b'E',
+}
+
+impl UsbmonEventType {
+    /// Creates a new [UsbmonEventType].
+    pub const fn new() -> Self {
+        Self::Submit
+    }
+
+    /// Creates a new [UsbmonEventType] from its header value.
+    pub const fn create(val: u8) -> Option<Self> {
+        match val {
+            b'S' => Some(Self::Submit),
+            b'C' => Some(Self::Complete),
+            b'E' => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    /// Gets the inner representation of the [UsbmonEventType].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&UsbmonEventType> for &'static str {
+    fn from(val: &UsbmonEventType) -> Self {
+        match val {
+            UsbmonEventType::Submit => "submit",
+            UsbmonEventType::Complete => "complete",
+            UsbmonEventType::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for UsbmonEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents the transfer type of a `usbmon` event.
+///
+/// The values match the `URB_TYPE_*` constants.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbmonXferType {
+    Isochronous = 0,
+    Interrupt = 1,
+    #[default]
+    Control = 2,
+    Bulk = 3,
+}
+
+impl UsbmonXferType {
+    /// Creates a new [UsbmonXferType].
+    pub const fn new() -> Self {
+        Self::Control
+    }
+
+    /// Creates a new [UsbmonXferType] from its header value.
+    pub const fn create(val: u8) -> Self {
+        match val & 0x3 {
+            0 => Self::Isochronous,
+            1 => Self::Interrupt,
+            2 => Self::Control,
+            _ => Self::Bulk,
+        }
+    }
+
+    /// Gets the inner representation of the [UsbmonXferType].
+    pub const fn inner(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<&UsbmonXferType> for &'static str {
+    fn from(val: &UsbmonXferType) -> Self {
+        match val {
+            UsbmonXferType::Isochronous => "isochronous",
+            UsbmonXferType::Interrupt => "interrupt",
+            UsbmonXferType::Control => "control",
+            UsbmonXferType::Bulk => "bulk",
+        }
+    }
+}
+
+impl fmt::Display for UsbmonXferType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, r#""{}""#, <&str>::from(self))
+    }
+}
+
+/// Represents `struct mon_bin_isodesc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbmonIsoDesc {
+    status: i32,
+    offset: u32,
+    length: u32,
+}
+
+impl UsbmonIsoDesc {
+    /// Creates a new [UsbmonIsoDesc].
+    pub const fn new() -> Self {
+        Self {
+            status: 0,
+            offset: 0,
+            length: 0,
+        }
+    }
+
+    /// Creates a new [UsbmonIsoDesc] from the provided parameters.
+    pub const fn create(status: i32, offset: u32, length: u32) -> Self {
+        Self {
+            status,
+            offset,
+            length,
+        }
+    }
+
+    /// Gets the packet status.
+    pub const fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Gets the packet offset in the URB buffer.
+    pub const fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Gets the packet length.
+    pub const fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+impl fmt::Display for UsbmonIsoDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"status": {}, "offset": {}, "length": {}}}"#,
+            self.status, self.offset, self.length
+        )
+    }
+}
+
+/// Represents a binary `usbmon` event.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbmonEvent {
+    id: u64,
+    event_type: UsbmonEventType,
+    xfer_type: UsbmonXferType,
+    endpoint: u8,
+    devnum: u8,
+    busnum: u16,
+    flag_setup: u8,
+    flag_data: u8,
+    ts_sec: i64,
+    ts_usec: i32,
+    status: i32,
+    len_urb: u32,
+    setup: [u8; 8],
+    interval: i32,
+    start_frame: i32,
+    xfer_flags: u32,
+    iso_desc: Vec<UsbmonIsoDesc>,
+    data: Vec<u8>,
+}
+
+impl UsbmonEvent {
+    /// Creates a new [UsbmonEvent].
+    pub const fn new() -> Self {
+        Self {
+            id: 0,
+            event_type: UsbmonEventType::new(),
+            xfer_type: UsbmonXferType::new(),
+            endpoint: 0,
+            devnum: 0,
+            busnum: 0,
+            flag_setup: FLAG_SETUP_ABSENT,
+            flag_data: FLAG_DATA_ABSENT,
+            ts_sec: 0,
+            ts_usec: 0,
+            status: 0,
+            len_urb: 0,
+            setup: [0; 8],
+            interval: 0,
+            start_frame: 0,
+            xfer_flags: 0,
+            iso_desc: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Parses a [UsbmonEvent] from the header, followed by the captured bytes.
+    ///
+    /// Returns the event, and the number of bytes it used.
+    pub fn parse(buf: &[u8]) -> Result<(Self, usize)> {
+        let hdr = buf
+            .get(..USBMON_HEADER_LEN)
+            .ok_or(Error::InvalidMessage(format!(
+                "usbmon header too short: {}",
+                buf.len()
+            )))?;
+
+        let event_type = UsbmonEventType::create(hdr[8]).ok_or(Error::InvalidMessage(format!(
+            "invalid usbmon event type: 0x{:02x}",
+            hdr[8]
+        )))?;
+
+        let len_cap = read_u32(hdr, 36) as usize;
+        let ndesc = read_u32(hdr, 60) as usize;
+        let cap = buf
+            .get(USBMON_HEADER_LEN..USBMON_HEADER_LEN + len_cap)
+            .ok_or(Error::InvalidMessage(format!(
+                "usbmon capture too short: {}, expected: {}",
+                buf.len() - USBMON_HEADER_LEN,
+                len_cap
+            )))?;
+
+        // the descriptors are only captured for Isochronous transfers
+        let xfer_type = UsbmonXferType::create(hdr[9]);
+        let ndesc = match xfer_type {
+            UsbmonXferType::Isochronous => ndesc.min(len_cap / USBMON_ISO_DESC_LEN),
+            _ => 0,
+        };
+        let (desc, data) = cap.split_at(ndesc * USBMON_ISO_DESC_LEN);
+
+        let mut setup = [0u8; 8];
+        setup.copy_from_slice(&hdr[40..48]);
+
+        let event = Self {
+            id: u64::from_ne_bytes(hdr[..8].try_into().unwrap_or_default()),
+            event_type,
+            xfer_type,
+            endpoint: hdr[10],
+            devnum: hdr[11],
+            busnum: u16::from_ne_bytes([hdr[12], hdr[13]]),
+            flag_setup: hdr[14],
+            flag_data: hdr[15],
+            ts_sec: i64::from_ne_bytes(hdr[16..24].try_into().unwrap_or_default()),
+            ts_usec: read_u32(hdr, 24) as i32,
+            status: read_u32(hdr, 28) as i32,
+            len_urb: read_u32(hdr, 32),
+            setup,
+            interval: read_u32(hdr, 48) as i32,
+            start_frame: read_u32(hdr, 52) as i32,
+            xfer_flags: read_u32(hdr, 56),
+            iso_desc: desc
+                .chunks_exact(USBMON_ISO_DESC_LEN)
+                .map(|d| {
+                    UsbmonIsoDesc::create(read_u32(d, 0) as i32, read_u32(d, 4), read_u32(d, 8))
+                })
+                .collect(),
+            data: data.into(),
+        };
+
+        Ok((event, USBMON_HEADER_LEN + len_cap))
+    }
+
+    /// Parses every [UsbmonEvent] of a capture byte stream.
+    pub fn parse_all(mut buf: &[u8]) -> Result<Vec<Self>> {
+        let mut events = Vec::new();
+        while !buf.is_empty() {
+            let (event, len) = Self::parse(buf)?;
+            events.push(event);
+            buf = &buf[len..];
+        }
+        Ok(events)
+    }
+
+    /// Gets the [UsbmonEvent] as the header, followed by the captured bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(USBMON_HEADER_LEN + self.len_cap() as usize);
+
+        buf.extend_from_slice(&self.id.to_ne_bytes());
+        buf.extend_from_slice(&[
+            self.event_type.inner(),
+            self.xfer_type.inner(),
+            self.endpoint,
+            self.devnum,
+        ]);
+        buf.extend_from_slice(&self.busnum.to_ne_bytes());
+        buf.extend_from_slice(&[self.flag_setup, self.flag_data]);
+        buf.extend_from_slice(&self.ts_sec.to_ne_bytes());
+        buf.extend_from_slice(&self.ts_usec.to_ne_bytes());
+        buf.extend_from_slice(&self.status.to_ne_bytes());
+        buf.extend_from_slice(&self.len_urb.to_ne_bytes());
+        buf.extend_from_slice(&self.len_cap().to_ne_bytes());
+        buf.extend_from_slice(&self.setup);
+        buf.extend_from_slice(&self.interval.to_ne_bytes());
+        buf.extend_from_slice(&self.start_frame.to_ne_bytes());
+        buf.extend_from_slice(&self.xfer_flags.to_ne_bytes());
+        buf.extend_from_slice(&(self.iso_desc.len() as u32).to_ne_bytes());
+
+        for desc in self.iso_desc.iter() {
+            buf.extend_from_slice(&desc.status.to_ne_bytes());
+            buf.extend_from_slice(&desc.offset.to_ne_bytes());
+            buf.extend_from_slice(&desc.length.to_ne_bytes());
+            buf.extend_from_slice(&[0u8; 4]);
+        }
+        buf.extend_from_slice(&self.data);
+
+        buf
+    }
+
+    /// Gets the event ID, shared by the submission and completion of a URB.
+    pub const fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Builder function that sets the event ID.
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Gets the [UsbmonEventType].
+    pub const fn event_type(&self) -> UsbmonEventType {
+        self.event_type
+    }
+
+    /// Builder function that sets the [UsbmonEventType].
+    pub fn with_event_type(mut self, event_type: UsbmonEventType) -> Self {
+        self.event_type = event_type;
+        self
+    }
+
+    /// Gets the [UsbmonXferType].
+    pub const fn xfer_type(&self) -> UsbmonXferType {
+        self.xfer_type
+    }
+
+    /// Builder function that sets the [UsbmonXferType].
+    pub fn with_xfer_type(mut self, xfer_type: UsbmonXferType) -> Self {
+        self.xfer_type = xfer_type;
+        self
+    }
+
+    /// Gets the endpoint address, including the direction bit.
+    pub const fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    /// Builder function that sets the endpoint address.
+    pub fn with_endpoint(mut self, endpoint: u8) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Gets the device number.
+    pub const fn devnum(&self) -> u8 {
+        self.devnum
+    }
+
+    /// Gets the bus number.
+    pub const fn busnum(&self) -> u16 {
+        self.busnum
+    }
+
+    /// Builder function that sets the bus and device numbers.
+    pub fn with_address(mut self, busnum: u16, devnum: u8) -> Self {
+        self.busnum = busnum;
+        self.devnum = devnum;
+        self
+    }
+
+    /// Gets the event timestamp, since the Unix epoch for kernel events.
+    pub const fn timestamp(&self) -> Duration {
+        Duration::new(self.ts_sec as u64, self.ts_usec as u32 * 1000)
+    }
+
+    /// Builder function that sets the event timestamp.
+    pub fn with_timestamp(mut self, timestamp: Duration) -> Self {
+        self.ts_sec = timestamp.as_secs() as i64;
+        self.ts_usec = timestamp.subsec_micros() as i32;
+        self
+    }
+
+    /// Gets the URB status.
+    ///
+    /// Submissions have an `-EINPROGRESS` status.
+    pub const fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Builder function that sets the URB status.
+    pub fn with_status(mut self, status: i32) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Gets the URB length, for submissions, or the actual length, for completions.
+    pub const fn len_urb(&self) -> u32 {
+        self.len_urb
+    }
+
+    /// Builder function that sets the URB length.
+    pub fn with_len_urb(mut self, len_urb: u32) -> Self {
+        self.len_urb = len_urb;
+        self
+    }
+
+    /// Gets the captured length, including the Isochronous descriptors.
+    pub fn len_cap(&self) -> u32 {
+        (self.iso_desc.len() * USBMON_ISO_DESC_LEN + self.data.len()) as u32
+    }
+
+    /// Gets the Control setup packet, if it was captured.
+    pub fn setup(&self) -> Option<&[u8; 8]> {
+        (self.flag_setup == FLAG_PRESENT && self.xfer_type == UsbmonXferType::Control)
+            .then_some(&self.setup)
+    }
+
+    /// Builder function that sets the Control setup packet.
+    pub fn with_setup(mut self, setup: [u8; 8]) -> Self {
+        self.setup = setup;
+        self.flag_setup = FLAG_PRESENT;
+        self
+    }
+
+    /// Gets the Isochronous error count.
+    pub fn iso_error_count(&self) -> i32 {
+        read_u32(&self.setup, 0) as i32
+    }
+
+    /// Gets the polling interval of Interrupt and Isochronous transfers.
+    pub const fn interval(&self) -> i32 {
+        self.interval
+    }
+
+    /// Gets the start frame of Isochronous transfers.
+    pub const fn start_frame(&self) -> i32 {
+        self.start_frame
+    }
+
+    /// Gets the URB transfer flags.
+    pub const fn xfer_flags(&self) -> u32 {
+        self.xfer_flags
+    }
+
+    /// Gets the list of captured [UsbmonIsoDesc].
+    pub fn iso_desc(&self) -> &[UsbmonIsoDesc] {
+        self.iso_desc.as_ref()
+    }
+
+    /// Gets the captured data, if any.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Gets whether the data was captured.
+    pub const fn has_data(&self) -> bool {
+        self.flag_data == FLAG_PRESENT
+    }
+
+    /// Builder function that sets the captured data.
+    pub fn with_data<D: IntoIterator<Item = u8>>(mut self, data: D) -> Self {
+        self.data = data.into_iter().collect();
+        self.flag_data = FLAG_PRESENT;
+        self
+    }
+}
+
+impl Default for UsbmonEvent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for UsbmonEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        write!(f, r#""id": {}, "#, self.id)?;
+        write!(f, r#""type": {}, "#, self.event_type)?;
+        write!(f, r#""xfer_type": {}, "#, self.xfer_type)?;
+        write!(f, r#""endpoint": {}, "#, self.endpoint)?;
+        write!(f, r#""devnum": {}, "#, self.devnum)?;
+        write!(f, r#""busnum": {}, "#, self.busnum)?;
+        write!(f, r#""timestamp": {}, "#, self.timestamp().as_micros())?;
+        write!(f, r#""status": {}, "#, self.status)?;
+        write!(f, r#""len_urb": {}, "#, self.len_urb)?;
+        match self.setup() {
+            Some(setup) => write!(f, r#""setup": {setup:?}, "#)?,
+            None => write!(f, r#""setup": null, "#)?,
+        }
+        write!(f, r#""iso_desc": ["#)?;
+        for (i, desc) in self.iso_desc.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{desc}")?;
+        }
+        write!(f, r#"], "data": {:?}"#, self.data)?;
+        write!(f, "}}")
+    }
+}
+
+/// Reader of a binary `usbmon` device node.
+#[derive(Debug)]
+pub struct Usbmon {
+    file: File,
+    ring: Option<(*mut c_void, usize)>,
+}
+
+impl Usbmon {
+    /// Opens the `usbmon` node of a bus, or of every bus for bus `0`.
+    ///
+    /// **NOTE** requires the `usbmon` module, and usually root privileges.
+    pub fn open(busnum: u16) -> Result<Self> {
+        Self::open_path(format!("{USBMON_DEVICE_PATH}{busnum}"))
+    }
+
+    /// Opens a `usbmon` node from its path.
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(nix::libc::O_CLOEXEC)
+            .open(path)?;
+
+        Ok(Self { file, ring: None })
+    }
+
+    /// Gets the size of the kernel event ring.
+    pub fn ring_size(&self) -> Result<usize> {
+        let size = unsafe { mon_iocq_ring_size(self.fd())? };
+        Ok(size as usize)
+    }
+
+    /// Sets the size of the kernel event ring, dropping the queued events.
+    pub fn set_ring_size(&self, size: usize) -> Result<()> {
+        if self.ring.is_some() {
+            return Err(Error::InvalidArgument(
+                "the ring cannot be resized while mapped".into(),
+            ));
+        }
+        unsafe { mon_ioct_ring_size(self.fd(), size as i32)? };
+        Ok(())
+    }
+
+    /// Gets the event statistics, resetting the dropped events counter.
+    pub fn stats(&self) -> Result<UsbmonStats> {
+        let mut stats = UsbmonStats::new();
+        unsafe { mon_iocg_stats(self.fd(), &mut stats)? };
+        Ok(stats)
+    }
+
+    /// Waits for the next event, capturing at most `max_data` bytes.
+    pub fn next_event(&self, max_data: usize) -> Result<UsbmonEvent> {
+        let mut hdr = [0u8; USBMON_HEADER_LEN];
+        let mut data = vec![0u8; max_data];
+        let get = MonBinGet {
+            hdr: hdr.as_mut_ptr() as *mut c_void,
+            data: data.as_mut_ptr() as *mut c_void,
+            alloc: data.len(),
+        };
+
+        // SAFETY: the kernel writes at most `alloc` bytes of data, and a full header
+        unsafe { mon_iocx_getx(self.fd(), &get)? };
+
+        // the captured length is the full length, even when the data was truncated
+        let len_cap = (read_u32(&hdr, 36) as usize).min(max_data);
+        hdr[36..40].copy_from_slice(&(len_cap as u32).to_ne_bytes());
+
+        UsbmonEvent::parse(&[hdr.as_ref(), &data[..len_cap]].concat()).map(|(event, _)| event)
+    }
+
+    /// Maps the kernel event ring, for [fetch](Self::fetch).
+    pub fn map(&mut self) -> Result<()> {
+        if self.ring.is_some() {
+            return Ok(());
+        }
+
+        let size = self.ring_size()?;
+        let len = NonZeroUsize::new(size).ok_or(Error::InvalidArgument("empty ring".into()))?;
+        // SAFETY: the mapping is read-only, and unmapped before the file is closed
+        let ring = unsafe {
+            mmap(
+                None,
+                len,
+                ProtFlags::PROT_READ,
+                MapFlags::MAP_SHARED,
+                Some(self.file.as_fd()),
+                0,
+            )?
+        };
+        self.ring = Some((ring, size));
+
+        Ok(())
+    }
+
+    /// Waits for events in the mapped ring, and returns at most `max` of them.
+    ///
+    /// The events are copied out of the ring, and released from it before returning.
+    pub fn fetch(&mut self, max: usize) -> Result<Vec<UsbmonEvent>> {
+        self.map()?;
+        let (ring, size) = self.ring.unwrap_or((ptr::null_mut(), 0));
+        // SAFETY: the kernel keeps fetched events in the ring until they are flushed
+        let ring = unsafe { slice::from_raw_parts(ring as *const u8, size) };
+
+        let mut offsets = vec![0u32; max];
+        let mut mfetch = MonBinMfetch {
+            offvec: offsets.as_mut_ptr(),
+            nfetch: max as u32,
+            nflush: 0,
+        };
+        unsafe { mon_iocx_mfetch(self.fd(), &mut mfetch)? };
+
+        let mut events = Vec::new();
+        for &off in offsets.iter().take(mfetch.nfetch as usize) {
+            let buf = ring
+                .get(off as usize..)
+                .ok_or(Error::InvalidMessage(format!("invalid ring offset: {off}")))?;
+            if buf.get(8) == Some(&EVENT_FILLER) {
+                continue;
+            }
+            events.push(UsbmonEvent::parse(buf)?.0);
+        }
+
+        // release the events, they were copied out of the ring
+        unsafe { mon_ioch_mflush(self.fd(), mfetch.nfetch as i32)? };
+
+        Ok(events)
+    }
+
+    fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for Usbmon {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl Drop for Usbmon {
+    fn drop(&mut self) {
+        if let Some((ring, size)) = self.ring.take() {
+            // SAFETY: the ring was mapped with the same length
+            unsafe { munmap(ring, size) }.ok();
+        }
+    }
+}
+
+/// Converts a recorded [Session] into `usbmon` events, for capture file export.
+///
+/// Control and Bulk transfers, and URBs, produce a submission and a completion event. Other
+/// operations are not visible on the bus, and are skipped. Timestamps are relative to the start
+/// of the session.
+pub fn session_events(session: &Session, busnum: u16, devnum: u8) -> Vec<UsbmonEvent> {
+    // synchronous transfers have no URB ID, keep them apart from submitted URBs
+    const SYNC_ID: u64 = 1 << 63;
+
+    let mut urbs = BTreeMap::new();
+    let mut events = Vec::new();
+
+    for (i, record) in session.records().iter().enumerate() {
+        let args = record.args();
+        let arg = |n: usize| args.get(n).copied().unwrap_or(0);
+        let event = UsbmonEvent::new()
+            .with_address(busnum, devnum)
+            .with_timestamp(record.timestamp());
+        let status = match record.status() {
+            0 => 0,
+            errno if errno > 0 => -errno,
+            _ => -(Errno::ENODEV as i32),
+        };
+        let value = record.values().first().copied().unwrap_or(0) as u32;
+
+        match record.op() {
+            SessionOp::Control => {
+                let mut setup = [0u8; 8];
+                setup[0] = arg(0) as u8;
+                setup[1] = arg(1) as u8;
+                setup[2..4].copy_from_slice(&(arg(2) as u16).to_le_bytes());
+                setup[4..6].copy_from_slice(&(arg(3) as u16).to_le_bytes());
+                setup[6..8].copy_from_slice(&(arg(4) as u16).to_le_bytes());
+                let ep = setup[0] & ENDPOINT_DIR_IN;
+                let event = event
+                    .with_id(SYNC_ID | i as u64)
+                    .with_xfer_type(UsbmonXferType::Control)
+                    .with_endpoint(ep);
+
+                events.push(
+                    event
+                        .clone()
+                        .with_event_type(UsbmonEventType::Submit)
+                        .with_status(STATUS_IN_PROGRESS)
+                        .with_len_urb(arg(4) as u32)
+                        .with_setup(setup)
+                        .with_data(record.data().iter().copied()),
+                );
+                events.push(
+                    event
+                        .with_event_type(UsbmonEventType::Complete)
+                        .with_status(status)
+                        .with_len_urb(value)
+                        .with_data(record.response().iter().copied()),
+                );
+            }
+            SessionOp::Bulk => {
+                let event = event
+                    .with_id(SYNC_ID | i as u64)
+                    .with_xfer_type(UsbmonXferType::Bulk)
+                    .with_endpoint(arg(0) as u8);
+
+                events.push(
+                    event
+                        .clone()
+                        .with_event_type(UsbmonEventType::Submit)
+                        .with_status(STATUS_IN_PROGRESS)
+                        .with_len_urb(arg(1) as u32)
+                        .with_data(record.data().iter().copied()),
+                );
+                events.push(
+                    event
+                        .with_event_type(UsbmonEventType::Complete)
+                        .with_status(status)
+                        .with_len_urb(value)
+                        .with_data(record.response().iter().copied()),
+                );
+            }
+            SessionOp::SubmitUrb => {
+                let xfer_type = UsbmonXferType::create(arg(0) as u8);
+                let ep = arg(1) as u8;
+                let mut event = event
+                    .with_id(value as u64)
+                    .with_xfer_type(xfer_type)
+                    .with_endpoint(ep)
+                    .with_len_urb(arg(4) as u32);
+                let mut data = record.data();
+
+                if xfer_type == UsbmonXferType::Control && data.len() >= 8 {
+                    let mut setup = [0u8; 8];
+                    setup.copy_from_slice(&data[..8]);
+                    event = event
+                        .with_endpoint(setup[0] & ENDPOINT_DIR_IN)
+                        .with_setup(setup);
+                    event.len_urb = event.len_urb.saturating_sub(8);
+                    data = if setup[0] & ENDPOINT_DIR_IN != 0 {
+                        &[]
+                    } else {
+                        &data[8..]
+                    };
+                }
+
+                let event = event.with_event_type(UsbmonEventType::Submit);
+                if status == 0 {
+                    urbs.insert(value as u64, event.clone());
+                    events.push(
+                        event
+                            .with_status(STATUS_IN_PROGRESS)
+                            .with_data(data.iter().copied()),
+                    );
+                } else {
+                    events.push(
+                        event
+                            .with_event_type(UsbmonEventType::Error)
+                            .with_status(status),
+                    );
+                }
+            }
+            SessionOp::ReapUrb if status == 0 => {
+                let values = record.values();
+                let Some(submit) = values.first().and_then(|id| urbs.remove(id)) else {
+                    continue;
+                };
+                let urb_status = values.get(1).map_or(0, |&v| unzigzag(v) as i32);
+                let actual = values.get(2).copied().unwrap_or(0) as u32;
+                let mut data = record.response();
+                if submit.xfer_type == UsbmonXferType::Control {
+                    data = data.get(8..).unwrap_or_default();
+                }
+                let data = &data[..data.len().min(actual as usize)];
+
+                events.push(
+                    UsbmonEvent {
+                        flag_setup: FLAG_SETUP_ABSENT,
+                        ..submit
+                    }
+                    .with_event_type(UsbmonEventType::Complete)
+                    .with_timestamp(record.timestamp())
+                    .with_status(urb_status)
+                    .with_len_urb(actual)
+                    .with_data(data.iter().copied()),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    events
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_ne_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usbmon_event() -> Result<()> {
+        // GET_DESCRIPTOR submission on bus 1, device 5, followed by its completion
+        let submit = UsbmonEvent::new()
+            .with_id(0xffff_8800_1234_5600)
+            .with_xfer_type(UsbmonXferType::Control)
+            .with_endpoint(0x80)
+            .with_address(1, 5)
+            .with_timestamp(Duration::new(1_700_000_000, 250_000))
+            .with_status(STATUS_IN_PROGRESS)
+            .with_len_urb(18)
+            .with_setup([0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00]);
+        let complete = submit
+            .clone()
+            .with_event_type(UsbmonEventType::Complete)
+            .with_status(0)
+            .with_data([0x12, 0x01, 0x00, 0x02]);
+
+        let mut stream = submit.to_bytes();
+        assert_eq!(stream.len(), USBMON_HEADER_LEN);
+        assert_eq!(stream[8], b'S');
+        assert_eq!(stream[14], FLAG_PRESENT);
+        assert_eq!(stream[15], FLAG_DATA_ABSENT);
+        stream.extend(complete.to_bytes());
+
+        let events = UsbmonEvent::parse_all(&stream)?;
+        assert_eq!(events, [submit.clone(), complete.clone()]);
+        assert_eq!(
+            events[0].setup(),
+            Some(&[0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00])
+        );
+        assert_eq!(events[1].data(), [0x12, 0x01, 0x00, 0x02].as_ref());
+        assert_eq!(events[1].timestamp(), Duration::new(1_700_000_000, 250_000));
+
+        // truncated captures are rejected
+        assert!(UsbmonEvent::parse(&stream[..USBMON_HEADER_LEN * 2 + 2]).is_ok());
+        assert!(UsbmonEvent::parse(&stream[USBMON_HEADER_LEN..USBMON_HEADER_LEN + 66]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Writers for pcap and pcapng capture files.
+
+use std::io::Write;
+
+use super::{UsbmonEvent, USBMON_HEADER_LEN, USBMON_ISO_DESC_LEN};
+use crate::Result;
+
+/// Link type of `usbmon` events with the 64-byte header.
+pub const LINKTYPE_USB_LINUX_MMAPPED: u16 = 220;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION: (u16, u16) = (2, 4);
+
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_IDB: u32 = 0x0000_0001;
+const PCAPNG_EPB: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_VERSION: (u16, u16) = (1, 0);
+
+const DEFAULT_SNAPLEN: u32 = 0x4_0000;
+
+/// Writer of classic pcap capture files, in host byte order.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a new [PcapWriter], and writes the file header.
+    pub fn new(mut writer: W) -> Result<Self> {
+        let mut hdr = Vec::with_capacity(24);
+        hdr.extend_from_slice(&PCAP_MAGIC.to_ne_bytes());
+        hdr.extend_from_slice(&PCAP_VERSION.0.to_ne_bytes());
+        hdr.extend_from_slice(&PCAP_VERSION.1.to_ne_bytes());
+        // timezone offset, and timestamp accuracy
+        hdr.extend_from_slice(&[0u8; 8]);
+        hdr.extend_from_slice(&DEFAULT_SNAPLEN.to_ne_bytes());
+        hdr.extend_from_slice(&(LINKTYPE_USB_LINUX_MMAPPED as u32).to_ne_bytes());
+        writer.write_all(&hdr)?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes a [UsbmonEvent] record.
+    pub fn write_event(&mut self, event: &UsbmonEvent) -> Result<()> {
+        let packet = event.to_bytes();
+        let ts = event.timestamp();
+
+        let mut rec = Vec::with_capacity(16 + packet.len());
+        rec.extend_from_slice(&(ts.as_secs() as u32).to_ne_bytes());
+        rec.extend_from_slice(&ts.subsec_micros().to_ne_bytes());
+        rec.extend_from_slice(&(packet.len() as u32).to_ne_bytes());
+        rec.extend_from_slice(&original_len(event).to_ne_bytes());
+        rec.extend_from_slice(&packet);
+        self.writer.write_all(&rec)?;
+
+        Ok(())
+    }
+
+    /// Flushes, and converts the [PcapWriter] into its writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writer of pcapng capture files, in host byte order.
+///
+/// Files contain a single section, with a single interface using microsecond timestamps.
+#[derive(Debug)]
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Creates a new [PcapngWriter], and writes the section and interface blocks.
+    pub fn new(mut writer: W) -> Result<Self> {
+        let mut shb = Vec::with_capacity(16);
+        shb.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_ne_bytes());
+        shb.extend_from_slice(&PCAPNG_VERSION.0.to_ne_bytes());
+        shb.extend_from_slice(&PCAPNG_VERSION.1.to_ne_bytes());
+        // unspecified section length
+        shb.extend_from_slice(&(-1i64).to_ne_bytes());
+        write_block(&mut writer, PCAPNG_SHB, &shb)?;
+
+        let mut idb = Vec::with_capacity(8);
+        idb.extend_from_slice(&LINKTYPE_USB_LINUX_MMAPPED.to_ne_bytes());
+        idb.extend_from_slice(&[0u8; 2]);
+        idb.extend_from_slice(&DEFAULT_SNAPLEN.to_ne_bytes());
+        write_block(&mut writer, PCAPNG_IDB, &idb)?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes a [UsbmonEvent] as an enhanced packet block.
+    pub fn write_event(&mut self, event: &UsbmonEvent) -> Result<()> {
+        let packet = event.to_bytes();
+        let ts = event.timestamp().as_micros() as u64;
+
+        let mut epb = Vec::with_capacity(20 + packet.len() + 3);
+        // interface ID
+        epb.extend_from_slice(&0u32.to_ne_bytes());
+        epb.extend_from_slice(&((ts >> 32) as u32).to_ne_bytes());
+        epb.extend_from_slice(&(ts as u32).to_ne_bytes());
+        epb.extend_from_slice(&(packet.len() as u32).to_ne_bytes());
+        epb.extend_from_slice(&original_len(event).to_ne_bytes());
+        epb.extend_from_slice(&packet);
+        epb.resize(epb.len().next_multiple_of(4), 0);
+        write_block(&mut self.writer, PCAPNG_EPB, &epb)
+    }
+
+    /// Flushes, and converts the [PcapngWriter] into its writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// length of the packet, if the full URB buffer had been captured
+fn original_len(event: &UsbmonEvent) -> u32 {
+    let captured = event.to_bytes().len() as u32;
+    let full =
+        (USBMON_HEADER_LEN + event.iso_desc().len() * USBMON_ISO_DESC_LEN) as u32 + event.len_urb();
+    full.max(captured)
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<()> {
+    let len = (body.len() + 12) as u32;
+
+    let mut block = Vec::with_capacity(len as usize);
+    block.extend_from_slice(&block_type.to_ne_bytes());
+    block.extend_from_slice(&len.to_ne_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&len.to_ne_bytes());
+    writer.write_all(&block)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usbmon::{UsbmonEventType, UsbmonXferType};
+    use std::time::Duration;
+
+    #[test]
+    fn test_pcapng_writer() -> Result<()> {
+        let event = UsbmonEvent::new()
+            .with_id(7)
+            .with_event_type(UsbmonEventType::Complete)
+            .with_xfer_type(UsbmonXferType::Bulk)
+            .with_endpoint(0x81)
+            .with_address(2, 3)
+            .with_timestamp(Duration::new(5_000, 1_000))
+            .with_len_urb(64)
+            .with_data([1, 2, 3]);
+
+        let mut pcapng = PcapngWriter::new(Vec::new())?;
+        pcapng.write_event(&event)?;
+        let buf = pcapng.finish()?;
+
+        let u32_at = |off: usize| u32::from_ne_bytes(buf[off..off + 4].try_into().unwrap());
+
+        // section header block
+        assert_eq!(u32_at(0), PCAPNG_SHB);
+        assert_eq!(u32_at(4), 28);
+        assert_eq!(u32_at(8), PCAPNG_BYTE_ORDER_MAGIC);
+        assert_eq!(u32_at(24), 28);
+
+        // interface description block
+        assert_eq!(u32_at(28), PCAPNG_IDB);
+        assert_eq!(u32_at(32), 20);
+        assert_eq!(
+            u16::from_ne_bytes([buf[36], buf[37]]),
+            LINKTYPE_USB_LINUX_MMAPPED
+        );
+
+        // enhanced packet block, with the event padded to 4 bytes
+        let epb = 48;
+        let ts = event.timestamp().as_micros() as u64;
+        assert_eq!(u32_at(epb), PCAPNG_EPB);
+        assert_eq!(u32_at(epb + 4), 32 + 68);
+        assert_eq!(u32_at(epb + 12), (ts >> 32) as u32);
+        assert_eq!(u32_at(epb + 16), ts as u32);
+        assert_eq!(u32_at(epb + 20), 67);
+        assert_eq!(u32_at(epb + 24), 128);
+        assert_eq!(UsbmonEvent::parse(&buf[epb + 28..epb + 28 + 67])?.0, event);
+        assert_eq!(buf.len(), epb + 100);
+
+        Ok(())
+    }
+}
@@ -1,15 +1,29 @@
+use nix::errno::Errno;
 use usbfs::*;
 
-// FIXME: currently returns a constant, it should dynamically detect a valid USB file descriptor.
-//
-// Most relevant is the CI context, where a pseudo-device needs to be mocked for deterministic tests.
-fn get_usb_fd() -> i32 {
-    0
+// device with one configuration, and a CDC data interface with two bulk endpoints
+const DESCRIPTORS: [u8; 50] = [
+    0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40, 0x83, 0x04, 0x40, 0x57, 0x00, 0x01, 0x01, 0x02,
+    0x03, 0x01, //
+    0x09, 0x02, 0x20, 0x00, 0x01, 0x01, 0x00, 0x80, 0x32, //
+    0x09, 0x04, 0x00, 0x00, 0x02, 0x0a, 0x00, 0x00, 0x00, //
+    0x07, 0x05, 0x01, 0x02, 0x00, 0x02, 0x00, //
+    0x07, 0x05, 0x81, 0x02, 0x00, 0x02, 0x00,
+];
+
+/// Gets a scripted pseudo-device, for deterministic tests without hardware.
+fn get_usb_device() -> MockDevice {
+    MockDevice::new()
+        .with_descriptors(DESCRIPTORS)
+        .with_configuration(1)
+        .with_speed(UsbfsSpeed::High)
+        .with_connect_info(UsbfsConnectInfo::create(5, 0))
+        .with_driver(0, "cdc_acm")
 }
 
 #[test]
 fn test_control() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
     let mut control = UsbfsCtrlTransfer::new()
         // ENDPOINT_IN
         .with_request_type(0x80)
@@ -18,190 +32,525 @@ fn test_control() -> Result<()> {
         .with_timeout(1000)
         .with_data([0u8]);
 
-    usbfs_control(fd, &mut control).ok();
+    assert_eq!(dev.control(&mut control)?, 1);
+    assert_eq!(control.data(), [1].as_ref());
+
+    // USB_GET_DESCRIPTOR, configuration 0
+    let mut control = UsbfsCtrlTransfer::new()
+        .with_request_type(0x80)
+        .with_request(0x06)
+        .with_value(0x0200)
+        .with_data([0u8; 64]);
+
+    assert_eq!(dev.control(&mut control)?, 32);
+    assert_eq!(&control.data()[..32], &DESCRIPTORS[18..]);
+
+    // CDC SET_CONTROL_LINE_STATE, scripted
+    dev.expect_control(MockControl::create(0x21, 0x22, 3, 0));
+    let mut control = UsbfsCtrlTransfer::new()
+        .with_request_type(0x21)
+        .with_request(0x22)
+        .with_value(3);
+
+    assert_eq!(dev.control(&mut control)?, 0);
+    dev.verify()
+}
+
+#[test]
+fn test_bulk() -> Result<()> {
+    let dev = get_usb_device().with_data(0x81, *b"hello");
+    let mut bulk = UsbfsBulkTransfer::create(0x81, 1000, [0u8; 64]);
+
+    assert_eq!(dev.bulk(&mut bulk)?, 5);
+    assert_eq!(&bulk.data()[..5], b"hello");
+    assert_eq!(dev.bulk(&mut bulk), Err(Errno::ETIMEDOUT.into()));
+
+    let mut bulk = UsbfsBulkTransfer::create(0x01, 1000, *b"world");
+    assert_eq!(dev.bulk(&mut bulk)?, 5);
+    assert_eq!(dev.take_written(0x01), [b"world".to_vec()]);
 
     Ok(())
 }
 
 #[test]
 fn test_set_release_iface() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut set_iface = UsbfsSetInterface::create(1, 0);
-
-    usbfs_set_interface(fd, &mut set_iface).ok();
-    usbfs_claim_interface(fd, set_iface.interface_mut()).ok();
-    usbfs_release_interface(fd, set_iface.interface_mut()).ok();
+    let dev = get_usb_device();
+    let set_iface = UsbfsSetInterface::create(1, 1);
+
+    assert_eq!(dev.set_interface(&set_iface), Err(Errno::EINVAL.into()));
+    dev.claim_interface(set_iface.interface())?;
+    dev.set_interface(&set_iface)?;
+    assert_eq!(dev.alt_setting(1), 1);
+    dev.release_interface(set_iface.interface())?;
+    assert_eq!(
+        dev.release_interface(set_iface.interface()),
+        Err(Errno::EINVAL.into())
+    );
 
     Ok(())
 }
 
 #[test]
 fn test_set_configuration() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut config = 0u32;
+    let dev = get_usb_device();
+
+    dev.set_configuration(0)?;
+    assert_eq!(dev.configuration(), 0);
 
-    usbfs_set_configuration(fd, &mut config).ok();
+    dev.claim_interface(1)?;
+    assert_eq!(dev.set_configuration(1), Err(Errno::EBUSY.into()));
 
     Ok(())
 }
 
 #[test]
 fn test_get_driver() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
     let mut driver = UsbfsGetDriver::new().with_interface(0);
 
-    usbfs_get_driver(fd, &mut driver).ok();
+    dev.get_driver(&mut driver)?;
+    assert_eq!(driver.driver(), "cdc_acm");
+
+    driver.set_interface(1);
+    assert_eq!(dev.get_driver(&mut driver), Err(Errno::ENODATA.into()));
 
     Ok(())
 }
 
 #[test]
 fn test_submit_urb() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut urb = Urb::new()
-        // URB_TYPE_CONTROL
-        .with_urb_type(2)
-        .with_buffer([0; 4]);
+    let dev = get_usb_device();
+    let urb = Urb::new()
+        .with_urb_type(URB_TYPE_CONTROL)
+        // USB_GET_CONFIGURATION setup packet, followed by the data stage
+        .with_buffer([0x80, 0x08, 0, 0, 0, 0, 1, 0, 0]);
+
+    let id = dev.submit_urb(urb)?;
+    let (reaped, urb) = dev.reap_urb(None)?;
 
-    usbfs_submit_urb(fd, &mut urb).ok();
+    assert_eq!(reaped, id);
+    assert_eq!(urb.status(), 0);
+    assert_eq!(urb.actual_length(), 1);
+    assert_eq!(urb.buffer()[8], 1);
 
     Ok(())
 }
 
 #[test]
 fn test_discard_urb() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
+    let id = dev.submit_urb(
+        Urb::new()
+            .with_urb_type(URB_TYPE_BULK)
+            .with_endpoint(0x81)
+            .with_buffer([0; 4]),
+    )?;
 
-    usbfs_discard_urb(fd).ok();
+    dev.discard_urb(id)?;
+    let (reaped, urb) = dev.reap_urb(None)?;
+
+    assert_eq!(reaped, id);
+    assert_eq!(UrbStatus::from(&urb), UrbStatus::Cancelled);
 
     Ok(())
 }
 
 #[test]
 fn test_reap_urb_ndelay() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut urb = Urb::new()
-        // URB_TYPE_CONTROL
-        .with_urb_type(2)
-        .with_buffer([0; 4]);
+    let dev = get_usb_device();
 
-    usbfs_reap_urb_ndelay(fd, &mut urb).ok();
+    // nothing was submitted
+    assert!(matches!(dev.reap_urb(None), Err(Error::NotFound(_))));
 
     Ok(())
 }
 
 #[test]
 fn test_connect_info() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut info = UsbfsConnectInfo::create(1, 42);
+    let dev = get_usb_device();
 
-    usbfs_connect_info(fd, &mut info).ok();
+    assert_eq!(dev.connect_info()?, UsbfsConnectInfo::create(5, 0));
 
     Ok(())
 }
 
 #[test]
 fn test_ioctl() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut ioctl = UsbfsIoctl::new().with_ifno(1).with_ioctl_code(18);
+    let dev = get_usb_device();
+    let mut ioctl = UsbfsIoctl::new()
+        .with_ifno(0)
+        .with_ioctl_code(USBFS_IOCTL_DISCONNECT);
 
-    usbfs_ioctl(fd, &mut ioctl).ok();
+    dev.ioctl(&mut ioctl)?;
+    assert_eq!(dev.ioctl(&mut ioctl), Err(Errno::ENODATA.into()));
+
+    let mut ioctl = UsbfsIoctl::new().with_ifno(1).with_ioctl_code(18);
+    assert_eq!(dev.ioctl(&mut ioctl), Err(Errno::ENOTTY.into()));
 
     Ok(())
 }
 
 #[test]
 fn test_reset() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
 
-    usbfs_reset(fd).ok();
+    dev.stall(0x81);
+    dev.reset()?;
+    assert!(!dev.is_halted(0x81));
 
     Ok(())
 }
 
 #[test]
 fn test_clear_halt() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut iface = 1u32;
+    let dev = get_usb_device().with_data(0x81, [1]);
+    let mut bulk = UsbfsBulkTransfer::create(0x81, 1000, [0u8; 1]);
+
+    dev.stall(0x81);
+    let err = dev.bulk(&mut bulk).unwrap_err();
+    assert!(is_stall(&err));
+
+    dev.clear_halt(0x81)?;
+    assert_eq!(dev.bulk(&mut bulk)?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_reset_ep() -> Result<()> {
+    let dev = get_usb_device();
 
-    usbfs_clear_halt(fd, &mut iface).ok();
+    dev.reset_ep(0x81)?;
 
     Ok(())
 }
 
 #[test]
 fn test_disconnect() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
+
+    // the kernel only handles USBDEVFS_DISCONNECT through USBDEVFS_IOCTL
+    assert_eq!(dev.disconnect(), Err(Errno::ENOTTY.into()));
 
-    usbfs_disconnect(fd).ok();
+    dev.unplug();
+    assert_eq!(dev.get_speed(), Err(Error::Disconnected));
 
     Ok(())
 }
 
 #[test]
 fn test_connect() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
 
-    usbfs_connect(fd).ok();
+    assert_eq!(dev.connect(), Err(Errno::ENOTTY.into()));
 
     Ok(())
 }
 
 #[test]
 fn test_get_capabilities() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut iface = 1u32;
+    let dev = get_usb_device().with_capabilities(0x3);
 
-    usbfs_get_capabilities(fd, &mut iface).ok();
+    assert_eq!(dev.get_capabilities()?, 0x3);
 
     Ok(())
 }
 
 #[test]
 fn test_disconnect_claim() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut claim = UsbfsDisconnectClaim::new();
+    let dev = get_usb_device();
+    let claim = UsbfsDisconnectClaim::create(0, UsbfsDisconnectClaimFlag::ExceptDriver, "usbhid");
 
-    usbfs_disconnect_claim(fd, &mut claim).ok();
+    assert_eq!(dev.claim_interface(0), Err(Errno::EBUSY.into()));
+    dev.disconnect_claim(&claim)?;
+    assert_eq!(dev.claimed_interfaces(), [0]);
 
     Ok(())
 }
 
 #[test]
 fn test_alloc_streams() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut streams = UsbfsStreams::new();
+    let dev = get_usb_device();
+    let streams = UsbfsStreams::new().with_num_streams(4).with_eps([0x81]);
 
-    usbfs_alloc_streams(fd, &mut streams).ok();
+    dev.alloc_streams(&streams)?;
+    assert_eq!(
+        dev.alloc_streams(&UsbfsStreams::new()),
+        Err(Errno::EINVAL.into())
+    );
 
     Ok(())
 }
 
 #[test]
 fn test_free_streams() -> Result<()> {
-    let fd = get_usb_fd();
-    let mut streams = UsbfsStreams::new();
+    let dev = get_usb_device();
+    let streams = UsbfsStreams::new().with_eps([0x81]);
 
-    usbfs_free_streams(fd, &mut streams).ok();
+    dev.free_streams(&streams)?;
 
     Ok(())
 }
 
 #[test]
 fn test_drop_privileges() -> Result<()> {
-    let fd = get_usb_fd();
-    let privileges = 0u64;
+    let dev = get_usb_device();
 
-    usbfs_drop_privileges(fd, privileges).ok();
+    dev.drop_privileges(0b10)?;
+    assert_eq!(dev.claim_interface(0), Err(Errno::EACCES.into()));
+    dev.claim_interface(1)?;
+    assert_eq!(dev.reset(), Err(Errno::EACCES.into()));
 
     Ok(())
 }
 
 #[test]
 fn test_get_speed() -> Result<()> {
-    let fd = get_usb_fd();
+    let dev = get_usb_device();
+
+    assert_eq!(dev.get_speed()?, UsbfsSpeed::High);
+
+    Ok(())
+}
 
-    usbfs_get_speed(fd).ok();
+#[test]
+fn test_ioctl_backend() -> Result<()> {
+    // a non-USBFS file rejects every request, without being reported as disconnected
+    let dev = IoctlBackend::new(UsbDevice::from_fd(std::fs::File::open("/dev/null")?.into()));
+    let mut control = UsbfsCtrlTransfer::new()
+        .with_request_type(0x80)
+        .with_request(0x08);
+    let mut bulk = UsbfsBulkTransfer::create(0x81, 1000, [0u8; 4]);
+    let mut driver = UsbfsGetDriver::new();
+    let mut ioctl = UsbfsIoctl::new();
+    let streams = UsbfsStreams::new().with_eps([0x81]);
+
+    assert!(dev.control(&mut control).is_err());
+    assert!(dev.bulk(&mut bulk).is_err());
+    assert!(dev.set_interface(&UsbfsSetInterface::new()).is_err());
+    assert!(dev.set_configuration(1).is_err());
+    assert!(dev.get_driver(&mut driver).is_err());
+    assert!(dev
+        .submit_urb(Urb::new().with_urb_type(URB_TYPE_BULK))
+        .is_err());
+    assert!(dev.claim_interface(0).is_err());
+    assert!(dev.release_interface(0).is_err());
+    assert!(dev.connect_info().is_err());
+    assert!(dev.ioctl(&mut ioctl).is_err());
+    assert!(dev.reset().is_err());
+    assert!(dev.clear_halt(0x81).is_err());
+    assert!(dev.reset_ep(0x81).is_err());
+    assert!(dev.disconnect().is_err());
+    assert!(dev.connect().is_err());
+    assert!(dev.get_capabilities().is_err());
+    assert!(dev.disconnect_claim(&UsbfsDisconnectClaim::new()).is_err());
+    assert!(dev.alloc_streams(&streams).is_err());
+    assert!(dev.free_streams(&streams).is_err());
+    assert!(dev.drop_privileges(0).is_err());
+    assert!(dev.get_speed().is_err());
+    assert!(!dev.device().is_disconnected());
+
+    Ok(())
+}
+
+#[test]
+fn test_session_usbmon_export() -> Result<()> {
+    let recorder = Recorder::new(get_usb_device().with_data(0x81, *b"hello"), Vec::new())?;
+    let mut control = UsbfsCtrlTransfer::new()
+        .with_request_type(0x80)
+        .with_request(0x08)
+        .with_data([0u8]);
+    recorder.control(&mut control)?;
+    recorder.clear_halt(0x81)?;
+    recorder.submit_urb(
+        Urb::new()
+            .with_urb_type(URB_TYPE_BULK)
+            .with_endpoint(0x81)
+            .with_buffer([0u8; 64]),
+    )?;
+    recorder.reap_urb(None)?;
+
+    let (_, buf) = recorder.finish()?;
+    let session = Session::read(buf.as_slice())?;
+    let events = usbmon::session_events(&session, 1, 5);
+
+    // only transfers and URBs are exported
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0].setup(), Some(&[0x80, 0x08, 0, 0, 0, 0, 1, 0]));
+    assert_eq!(events[1].data(), [1].as_ref());
+    assert_eq!(events[2].id(), events[3].id());
+    assert_eq!(events[2].len_urb(), 64);
+    assert_eq!(events[3].endpoint(), 0x81);
+    assert_eq!(events[3].data(), b"hello".as_ref());
+
+    let mut pcapng = PcapngWriter::new(Vec::new())?;
+    for event in events.iter() {
+        pcapng.write_event(event)?;
+    }
+    assert!(pcapng.finish()?.len() > events.len() * 64);
+
+    Ok(())
+}
+
+#[test]
+fn test_hotplug_monitor() -> Result<()> {
+    // netlink sockets may be unavailable in sandboxed CI environments
+    if let Ok(monitor) = HotplugMonitor::new() {
+        monitor.set_nonblocking(true)?;
+        monitor.recv_event().ok();
+        monitor.drain()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_usbfs_ls() -> Result<()> {
+    use std::{fs, process::Command};
+
+    let base = std::env::temp_dir().join(format!("usbfs-ls-{}", std::process::id()));
+    fs::remove_dir_all(&base).ok();
+
+    let dev_dir = base.join("sys/bus/usb/devices/1-2");
+    let iface_dir = dev_dir.join("1-2:1.0");
+    fs::create_dir_all(&iface_dir)?;
+    fs::create_dir_all(base.join("dev/001"))?;
+
+    for (name, val) in [
+        ("busnum", "1"),
+        ("devnum", "5"),
+        ("idVendor", "0483"),
+        ("idProduct", "5740"),
+        ("speed", "480"),
+        ("manufacturer", "ACME \"Labs\""),
+        ("product", "Widget"),
+    ] {
+        fs::write(dev_dir.join(name), format!("{val}\n"))?;
+    }
+    for (name, val) in [("bInterfaceNumber", "00"), ("bInterfaceClass", "0a")] {
+        fs::write(iface_dir.join(name), format!("{val}\n"))?;
+    }
+    std::os::unix::fs::symlink(
+        "../../../../bus/usb/drivers/cdc_acm",
+        iface_dir.join("driver"),
+    )?;
+    // a regular file stands in for the device node, so ioctls fall back to sysfs
+    fs::write(base.join("dev/001/005"), DESCRIPTORS)?;
+
+    let usbfs_ls = |args: &[&str]| -> Result<(bool, String)> {
+        let out = Command::new(env!("CARGO_BIN_EXE_usbfs-ls"))
+            .arg("--sysfs-root")
+            .arg(base.join("sys"))
+            .arg("--dev-root")
+            .arg(base.join("dev"))
+            .args(args)
+            .output()?;
+        Ok((
+            out.status.success(),
+            String::from_utf8_lossy(&out.stdout).into_owned(),
+        ))
+    };
+
+    let (ok, out) = usbfs_ls(&[])?;
+    assert!(ok);
+    assert_eq!(
+        out,
+        "Bus 001 Device 005: ID 0483:5740 ACME \"Labs\" Widget\n"
+    );
+
+    let (ok, out) = usbfs_ls(&["-v"])?;
+    assert!(ok);
+    assert!(out.contains("  idVendor           0x0483 ACME \"Labs\""));
+    assert!(out.contains("      bInterfaceClass        10 CDC Data"));
+    assert!(out.contains("      Driver             cdc_acm"));
+    assert!(out.contains("        bEndpointAddress     0x81  EP 1 IN"));
+    assert!(out.contains("Speed: high"));
+
+    let (ok, out) = usbfs_ls(&["--json"])?;
+    assert!(ok);
+    assert!(out.starts_with(r#"[{"bus_num": 1, "dev_num": 5, "port_path": "1-2""#));
+    assert!(out.contains(r#""manufacturer": "ACME \"Labs\"""#));
+    assert!(out.contains(r#""speed": "high""#));
+    assert!(out.contains(r#""drivers": [{"interface": 0, "driver": "cdc_acm"}]"#));
+    assert!(out.contains(r#""descriptors": {"device": {"usb_version": 512"#));
+
+    // no matching device
+    let (ok, out) = usbfs_ls(&["-d", "1234:"])?;
+    assert!(!ok);
+    assert!(out.is_empty());
+
+    fs::remove_dir_all(&base).ok();
+
+    Ok(())
+}
+
+#[test]
+fn test_display_json() -> Result<()> {
+    fn assert_json<T: std::fmt::Display>(val: T) {
+        let json = val.to_string();
+        assert!(
+            serde_json::from_str::<serde_json::Value>(&json).is_ok(),
+            "invalid JSON: {json}"
+        );
+    }
+
+    assert_json(
+        Urb::new()
+            .with_buffer([1, 2, 3])
+            .with_iso_frame_desc([UsbfsIsoPacketDesc::new()]),
+    );
+    assert_json(Urb::new().with_buffer([1, 2, 3]));
+    assert_json(UsbfsIoctl::new());
+    assert_json(UsbfsStreams::new().with_eps([0x81, 0x02]));
+    assert_json(UsbfsConnectInfo::create(4, 1));
+    assert_json(UsbfsBulkTransfer::create(0x81, 1000, [1, 2]));
+    assert_json(UsbfsIsoPacketDesc::new());
+    assert_json(UsbfsSetInterface::create(1, 0));
+    assert_json(UsbfsGetDriver::new().with_interface(1));
+    assert_json(UsbfsSpeed::High);
+    assert_json(UsbfsCap::ZeroPacket);
+    assert_json(Descriptors::new());
+    assert_json(SysfsDevice::new());
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() -> Result<()> {
+    fn round_trip<T>(val: &T)
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_string(val).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), val);
+    }
+
+    round_trip(
+        &UsbfsCtrlTransfer::new()
+            .with_request_type(0x80)
+            .with_request(6)
+            .with_value(0x100)
+            .with_data([0u8; 18]),
+    );
+    round_trip(&UsbfsIsoPacketDesc::new());
+    round_trip(&UsbfsSpeed::SuperPlus);
+    round_trip(&UsbfsCap::ReapAfterDisconnect);
+    round_trip(&UsbfsGetDriver::new().with_interface(1).with_driver("usbfs"));
+    round_trip(&Descriptors::new());
+    round_trip(&SysfsDevice::new());
+    round_trip(&DeviceFilter::parse_id("0483:5740")?);
+    round_trip(&HotplugEvent::new());
+
+    let urb = Urb::new()
+        .with_urb_type(URB_TYPE_BULK)
+        .with_endpoint(0x81)
+        .with_buffer([1, 2, 3]);
+    let json = serde_json::to_string(&urb).unwrap();
+    let de: Urb = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(de.endpoint(), 0x81);
+    assert_eq!(de.buffer(), [1, 2, 3]);
 
     Ok(())
 }